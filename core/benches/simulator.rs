@@ -0,0 +1,108 @@
+//! latency of the simulator on a contract forked from malaga.
+//! Rpc responses are cached in ~/.cw-rpc-cache after the first run, so later runs measure the
+//! simulator rather than the network
+
+use cosmwasm_simulate::{Model, ModuleCache};
+use cosmwasm_std::Addr;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use test_contract::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ReadNumberResponse};
+
+const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+const PAIR_ADDRESS_MALAGA: &str = "wasm15le5evw4regnwf9lrjnpakr2075fcyp4n4yzpelvqcuevzkw2lss46hslz";
+const TEST_CODE_ID: u64 = 1337;
+
+fn test_contract() -> &'static [u8] {
+    include_bytes!(concat!(
+        env!("OUT_DIR"),
+        "/wasm32-unknown-unknown/release/test_contract.wasm"
+    ))
+}
+
+/// model with the test contract running at the address of the pair, and registered as TEST_CODE_ID
+fn forked_model() -> (Model, Addr) {
+    let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+    let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+    model.cheat_code(&pair_address, test_contract()).unwrap();
+    model
+        .add_custom_code(TEST_CODE_ID, test_contract())
+        .unwrap();
+    model
+        .execute_typed(&pair_address, &ExecuteMsg::TestQuerySelf {}, &[])
+        .unwrap();
+    (model, pair_address)
+}
+
+fn bench_calls(c: &mut Criterion) {
+    let (mut model, pair_address) = forked_model();
+    let mut group = c.benchmark_group("calls");
+    group.bench_function("instantiate", |b| {
+        b.iter(|| {
+            model
+                .instantiate_typed(TEST_CODE_ID, &InstantiateMsg {}, &[])
+                .unwrap()
+        })
+    });
+    group.bench_function("execute", |b| {
+        b.iter(|| {
+            model
+                .execute_typed(&pair_address, &ExecuteMsg::TestQuerySelf {}, &[])
+                .unwrap()
+        })
+    });
+    // fails after writing to storage, so the write is rolled back
+    group.bench_function("execute_reverted", |b| {
+        b.iter(|| {
+            model
+                .execute_typed(&pair_address, &ExecuteMsg::TestAtomic {}, &[])
+                .unwrap()
+        })
+    });
+    group.bench_function("query", |b| {
+        b.iter(|| {
+            let _: ReadNumberResponse = model
+                .query_typed(&pair_address, &QueryMsg::ReadNumber {})
+                .unwrap();
+        })
+    });
+    group.finish();
+}
+
+fn bench_module_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("module_cache");
+    group.bench_function("compile", |b| {
+        b.iter_batched(
+            || ModuleCache::new(1),
+            |cache| cache.get_or_compile(test_contract()).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    let cache = ModuleCache::new(1);
+    cache.get_or_compile(test_contract()).unwrap();
+    group.bench_function("hit", |b| {
+        b.iter(|| cache.get_or_compile(test_contract()).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_clone(c: &mut Criterion) {
+    let (model, pair_address) = forked_model();
+    let mut group = c.benchmark_group("clone");
+    group.bench_function("clone", |b| b.iter(|| model.clone()));
+    // the first write to a clone copies the storage of the contract
+    group.bench_function("execute_on_clone", |b| {
+        b.iter_batched(
+            || model.clone(),
+            |mut clone| {
+                clone
+                    .execute_typed(&pair_address, &ExecuteMsg::TestQuerySelf {}, &[])
+                    .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_calls, bench_module_cache, bench_clone);
+criterion_main!(benches);
@@ -8,8 +8,19 @@ fn main() -> Result<()> {
     compile_protos(
         &[
             "proto/cosmos/bank/v1beta1/query.proto",
+            "proto/cosmos/bank/v1beta1/tx.proto",
+            "proto/cosmos/distribution/v1beta1/query.proto",
+            "proto/cosmos/gov/v1/tx.proto",
+            "proto/cosmos/gov/v1beta1/query.proto",
+            "proto/cosmos/gov/v1beta1/tx.proto",
             "proto/cosmwasm/wasm/v1/query.proto",
             "proto/cosmwasm/wasm/v1/tx.proto",
+            "proto/cosmos/tx/v1beta1/tx.proto",
+            "proto/osmosis/gamm/pool-models/balancer/balancerPool.proto",
+            "proto/osmosis/gamm/v1beta1/query.proto",
+            "proto/osmosis/gamm/v1beta1/tx.proto",
+            "proto/osmosis/tokenfactory/v1beta1/query.proto",
+            "proto/osmosis/tokenfactory/v1beta1/tx.proto",
         ],
         &["proto"],
     )?;
@@ -1,8 +1,19 @@
 //analyzer for json schema file
 
-use std::collections::HashMap;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::Read;
+use wasmparser::{DataKind, ExternalKind, Operator, Parser, Payload};
+
+use crate::{ContractSchema, Error, MsgKind};
+
+/// custom section a contract can embed the schema written by cosmwasm_schema::write_api in
+pub const SCHEMA_SECTION: &str = "cosmwasm_schema";
+const INTERFACE_VERSION_PREFIX: &str = "interface_version_";
+// longest name taken for a serde variant or field name
+const MAX_NAME_LEN: usize = 64;
 
 //Todo: analyze more detail from json schema file
 pub struct StructType {
@@ -292,7 +303,7 @@ impl Analyzer {
             Some(idx) => idx,
         };
         let (parent_path, _) = file_path.split_at(seg);
-        println!("Auto loading json schema from {}/schema", parent_path);
+        tracing::info!("auto loading json schema from {}/schema", parent_path);
         self.try_load_json_schema(parent_path.to_string() + "/schema")
     }
 }
@@ -309,3 +320,322 @@ pub fn load_data_from_file(path: &str) -> Result<Vec<u8>, String> {
     };
     Ok(data)
 }
+
+/// what the wasm code of a contract tells about the messages it takes
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct WasmAnalysis {
+    /// exported functions, sorted
+    pub exports: Vec<String>,
+    /// the interface_version_* export, if any
+    pub interface_version: Option<String>,
+    /// the schema embedded in the SCHEMA_SECTION custom section, in the layout of write_api
+    pub schema: Option<Value>,
+    /// lists of names serde deserializes enum variants or struct fields from, found in the data of the code.
+    /// Contracts built without an embedded schema still keep these for the errors of unknown variants
+    pub name_lists: Vec<Vec<String>>,
+}
+
+/// a name serde may match with rename_all = "snake_case"
+fn is_serde_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && (name[0].is_ascii_lowercase() || name[0] == b'_')
+        && name
+            .iter()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'_')
+}
+
+/// the data segments of code placed at a constant offset, as (offset, bytes)
+fn active_data(code: &[u8]) -> Result<Vec<(usize, &[u8])>, Error> {
+    let mut segments = Vec::new();
+    for payload in Parser::new(0).parse_all(code) {
+        if let Payload::DataSection(reader) = payload.map_err(Error::format_error)? {
+            for data in reader {
+                let data = data.map_err(Error::format_error)?;
+                if let DataKind::Active { init_expr, .. } = data.kind {
+                    let mut operators = init_expr.get_operators_reader();
+                    if let Ok(Operator::I32Const { value }) = operators.read() {
+                        segments.push((value as u32 as usize, data.data));
+                    }
+                }
+            }
+        }
+    }
+    Ok(segments)
+}
+
+/// the &'static [&'static str] slices of names in data, e.g. the VARIANTS serde derives for an enum.
+/// On wasm32 they are laid out as consecutive (pointer, length) pairs of little endian u32
+fn name_lists(segments: &[(usize, &[u8])]) -> Vec<Vec<String>> {
+    let read = |address: usize, len: usize| {
+        segments.iter().find_map(|(offset, data)| {
+            let start = address.checked_sub(*offset)?;
+            data.get(start..start.checked_add(len)?)
+        })
+    };
+    let name_at = |data: &[u8], i: usize| {
+        let word = |i: usize| {
+            data.get(i..i + 4)
+                .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]) as usize)
+        };
+        let (address, len) = (word(i)?, word(i + 4)?);
+        read(address, len)
+            .filter(|name| is_serde_name(name))
+            .map(|name| String::from_utf8_lossy(name).to_string())
+    };
+    let mut lists = Vec::new();
+    for (offset, data) in segments.iter() {
+        // slices are 4 byte aligned in memory
+        let mut i = (4 - offset % 4) % 4;
+        while i + 8 <= data.len() {
+            let mut list = Vec::new();
+            while let Some(name) = name_at(data, i + 8 * list.len()) {
+                list.push(name);
+            }
+            // a single name is too likely to be a coincidence
+            if list.len() >= 2 {
+                i += 8 * list.len();
+                lists.push(list);
+            } else {
+                i += 4;
+            }
+        }
+    }
+    lists
+}
+
+/// a message matching schema, with the required fields set to placeholders
+fn template(schema: &Value, definitions: &Map<String, Value>, depth: usize) -> Value {
+    if depth > 8 {
+        return Value::Null;
+    }
+    if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        let name = reference.trim_start_matches("#/definitions/");
+        return match name {
+            "Uint64" | "Uint128" | "Uint256" | "Uint512" | "Timestamp" => json!("0"),
+            "Decimal" | "Decimal256" => json!("0.0"),
+            "Binary" | "Addr" => json!(""),
+            _ => match definitions.get(name) {
+                Some(definition) => template(definition, definitions, depth + 1),
+                None => Value::Null,
+            },
+        };
+    }
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(value) = schema.get("enum").and_then(|e| e.get(0)) {
+        return value.clone();
+    }
+    for key in ["oneOf", "anyOf", "allOf"] {
+        if let Some(first) = schema.get(key).and_then(|b| b.get(0)) {
+            return template(first, definitions, depth + 1);
+        }
+    }
+    let ty = match schema.get("type") {
+        Some(Value::Array(types)) => types.iter().find(|t| *t != "null"),
+        ty => ty,
+    };
+    match ty.and_then(|t| t.as_str()) {
+        Some("boolean") => json!(false),
+        Some("integer") | Some("number") => json!(0),
+        Some("string") => json!(""),
+        Some("array") => json!([]),
+        Some("object") => {
+            let mut object = Map::new();
+            let required = schema.get("required").and_then(|r| r.as_array());
+            for key in required.into_iter().flatten().filter_map(|k| k.as_str()) {
+                let property = schema
+                    .get("properties")
+                    .and_then(|p| p.get(key))
+                    .cloned()
+                    .unwrap_or_default();
+                object.insert(key.to_string(), template(&property, definitions, depth + 1));
+            }
+            Value::Object(object)
+        }
+        _ => Value::Null,
+    }
+}
+
+impl WasmAnalysis {
+    pub fn from_code(code: &[u8]) -> Result<Self, Error> {
+        let mut analysis = WasmAnalysis::default();
+        for payload in Parser::new(0).parse_all(code) {
+            match payload.map_err(Error::format_error)? {
+                Payload::ExportSection(mut reader) => {
+                    for _ in 0..reader.get_count() {
+                        let export = reader.read().map_err(Error::format_error)?;
+                        if let ExternalKind::Function = export.kind {
+                            analysis.exports.push(export.field.to_string());
+                        }
+                    }
+                }
+                Payload::CustomSection { name, data, .. } if name == SCHEMA_SECTION => {
+                    let schema = serde_json::from_slice(data).map_err(|e| {
+                        Error::format_error(format!("invalid {} section: {}", SCHEMA_SECTION, e))
+                    })?;
+                    analysis.schema = Some(schema);
+                }
+                _ => {}
+            }
+        }
+        analysis.exports.sort();
+        analysis.interface_version = analysis
+            .exports
+            .iter()
+            .find(|e| e.starts_with(INTERFACE_VERSION_PREFIX))
+            .cloned();
+        analysis.name_lists = name_lists(&active_data(code)?);
+        Ok(analysis)
+    }
+
+    pub fn from_path(path: &str) -> Result<Self, Error> {
+        let code = load_data_from_file(path).map_err(Error::io_error)?;
+        Self::from_code(&code)
+    }
+
+    /// the embedded schema, to validate messages with Model::register_schema
+    pub fn contract_schema(&self) -> Result<Option<ContractSchema>, Error> {
+        match &self.schema {
+            Some(schema) => {
+                let json = serde_json::to_vec(schema).map_err(Error::from)?;
+                ContractSchema::from_api_json(&json).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// every name found in name_lists, sorted
+    pub fn candidate_variants(&self) -> Vec<String> {
+        let names: BTreeSet<&String> = self.name_lists.iter().flatten().collect();
+        names.into_iter().cloned().collect()
+    }
+
+    /// the schema of kind, the embedded one if any.
+    /// Otherwise execute and query messages are guessed to be any of the candidate variants,
+    /// as the data of the code does not tell which enum a name belongs to
+    pub fn msg_schema(&self, kind: MsgKind) -> Option<Value> {
+        if let Some(schema) = &self.schema {
+            return schema
+                .get(kind.to_string())
+                .filter(|s| !s.is_null())
+                .cloned();
+        }
+        let variants = self.candidate_variants();
+        if !matches!(kind, MsgKind::Execute | MsgKind::Query) || variants.is_empty() {
+            return None;
+        }
+        let one_of: Vec<Value> = variants
+            .iter()
+            .map(|name| {
+                json!({
+                    "type": "object",
+                    "required": [name],
+                    "properties": { name: { "type": "object" } },
+                    "additionalProperties": false,
+                })
+            })
+            .collect();
+        Some(json!({ "oneOf": one_of }))
+    }
+
+    /// a message per variant of kind with the required fields set to placeholders, to start from
+    pub fn msg_templates(&self, kind: MsgKind) -> Vec<Value> {
+        let schema = match self.msg_schema(kind) {
+            Some(schema) => schema,
+            None => return Vec::new(),
+        };
+        let definitions = schema
+            .get("definitions")
+            .and_then(|d| d.as_object())
+            .cloned()
+            .unwrap_or_default();
+        match schema.get("oneOf").and_then(|o| o.as_array()) {
+            Some(variants) => variants
+                .iter()
+                .map(|variant| template(variant, &definitions, 0))
+                .collect(),
+            None => vec![template(&schema, &definitions, 0)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // VARIANTS of an enum at 16, pointing at its names at 8 and 12
+    const DATA: &[u8] = b"\x00\x00\x00\x00\x00\x00\x00\x00gas_mint\x08\x00\x00\x00\x03\x00\x00\x00\x0b\x00\x00\x00\x05\x00\x00\x00";
+
+    #[test]
+    fn test_name_lists() {
+        assert_eq!(
+            name_lists(&[(0, DATA)]),
+            vec![vec!["gas".to_string(), "_mint".to_string()]]
+        );
+        // placed elsewhere, the pointers no longer point at names
+        assert!(name_lists(&[(4, DATA)]).is_empty());
+    }
+
+    #[test]
+    fn test_wasm_analysis() {
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let analysis = WasmAnalysis::from_code(wasm_code).unwrap();
+        assert_eq!(
+            analysis.interface_version.as_deref(),
+            Some("interface_version_8")
+        );
+        assert!(analysis.exports.contains(&"execute".to_string()));
+        assert!(analysis.schema.is_none());
+        let variants = analysis.candidate_variants();
+        for variant in ["test_query_self", "test_atomic", "read_number", "recurse"] {
+            assert!(variants.contains(&variant.to_string()), "{}", variant);
+        }
+        assert!(analysis
+            .msg_templates(MsgKind::Execute)
+            .contains(&json!({"test_atomic": {}})));
+        assert!(analysis.msg_schema(MsgKind::Instantiate).is_none());
+    }
+
+    #[test]
+    fn test_msg_templates() {
+        let analysis = WasmAnalysis {
+            schema: Some(json!({
+                "contract_name": "test",
+                "execute": {
+                    "oneOf": [
+                        {
+                            "type": "object",
+                            "required": ["transfer"],
+                            "properties": {"transfer": {
+                                "type": "object",
+                                "required": ["amount", "recipient"],
+                                "properties": {
+                                    "amount": {"$ref": "#/definitions/Uint128"},
+                                    "recipient": {"type": "string"},
+                                    "memo": {"type": ["string", "null"]},
+                                },
+                            }},
+                        },
+                        {"type": "string", "enum": ["pause"]},
+                    ],
+                    "definitions": {"Uint128": {"type": "string"}},
+                },
+                "query": null,
+            })),
+            ..WasmAnalysis::default()
+        };
+        assert_eq!(
+            analysis.msg_templates(MsgKind::Execute),
+            vec![
+                json!({"transfer": {"amount": "0", "recipient": ""}}),
+                json!("pause")
+            ]
+        );
+        assert!(analysis.msg_templates(MsgKind::Query).is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use cosmwasm_simulate::repl::Repl;
+use cosmwasm_simulate::{ExecutionHooks, Model};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: cw-repl <rpc url> <height|latest> <bech32 prefix>
+       cw-repl --load <session file>";
+
+fn start(args: &[String]) -> Result<Repl, String> {
+    match args {
+        [flag, path] if flag == "--load" => Repl::load(Path::new(path)).map_err(|e| e.to_string()),
+        [url, height, prefix] => {
+            let height = match height.as_str() {
+                "latest" => None,
+                height => Some(height.parse().map_err(|_| USAGE.to_string())?),
+            };
+            let model = Model::new(url, height, prefix).map_err(|e| e.to_string())?;
+            Ok(Repl::new(model))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// waits for enter when a contract hits a breakpoint
+struct PauseOnBreakpoint;
+
+impl ExecutionHooks for PauseOnBreakpoint {
+    fn on_breakpoint(&self, label: &str, call: &str) {
+        print!("breakpoint {} in {}, press enter to continue", label, call);
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        let _ = io::stdin().lock().read_line(&mut line);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut repl = match start(&args) {
+        Ok(repl) => repl,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    repl.model().set_hooks(Arc::new(PauseOnBreakpoint));
+    println!(
+        "forked {} at height {}, type help for the commands",
+        repl.session().url,
+        repl.session().block_number
+    );
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        match line.trim() {
+            "exit" | "quit" => break,
+            line => match repl.run_line(line) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(e) => println!("error: {}", e.to_string().trim_end()),
+            },
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use cosmwasm_simulate::script::Script;
+use std::env;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: cw-script <script file>";
+
+fn run(args: &[String]) -> Result<bool, String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(USAGE.to_string()),
+    };
+    let script = Script::load(Path::new(path)).map_err(|e| e.to_string())?;
+    let mut model = script.fork().map_err(|e| e.to_string())?;
+    let report = script.run(&mut model).map_err(|e| e.to_string())?;
+    print!("{}", report);
+    Ok(report.passed())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+
+use super::profraw::FunctionCounters;
+
+/// number of entries of a coverage map, same as the default of AFL
+pub const COVERAGE_MAP_SIZE: usize = 1 << 16;
+
+/// classify execution counts into buckets, so that a loop running one more time
+/// does not count as new coverage but reaching a new order of magnitude does
+fn bucket(count: u64) -> u8 {
+    match count {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 4,
+        4..=7 => 8,
+        8..=15 => 16,
+        16..=31 => 32,
+        32..=127 => 64,
+        _ => 128,
+    }
+}
+
+/// the first 8 bytes of the sha256 digest, which unlike DefaultHasher is the same for every
+/// build, so that hashes can be compared across runs
+fn digest_u64(hasher: Sha256) -> u64 {
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+fn block_index(address: &str, function: &FunctionCounters, block: usize) -> usize {
+    let mut hasher = Sha256::new();
+    hasher.update(address.as_bytes());
+    hasher.update(function.name_hash.to_be_bytes());
+    hasher.update((block as u64).to_be_bytes());
+    (digest_u64(hasher) % COVERAGE_MAP_SIZE as u64) as usize
+}
+
+/// AFL style map of bucketed block hit counts of a single execution
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageMap {
+    map: Vec<u8>,
+}
+
+impl CoverageMap {
+    pub fn new() -> Self {
+        Self {
+            map: vec![0; COVERAGE_MAP_SIZE],
+        }
+    }
+
+    /// add the block counters of a contract to the map
+    pub fn add_profile(&mut self, address: &str, profile: &[FunctionCounters]) {
+        for function in profile.iter() {
+            for (block, count) in function.counters.iter().enumerate() {
+                if *count > 0 {
+                    let index = block_index(address, function, block);
+                    self.map[index] |= bucket(*count);
+                }
+            }
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// number of non-empty entries
+    pub fn count(&self) -> usize {
+        self.map.iter().filter(|b| **b != 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.iter().all(|b| *b == 0)
+    }
+
+    /// identifies the execution path, equal maps have equal hashes
+    pub fn hash(&self) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.map);
+        digest_u64(hasher)
+    }
+}
+
+impl Default for CoverageMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// accumulates coverage maps of executions to decide which inputs reached new code
+#[derive(Clone, Debug, Default)]
+pub struct CoverageFeedback {
+    seen: CoverageMap,
+}
+
+impl CoverageFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns true if map contains a block or a hit count bucket which was not observed before
+    pub fn observe(&mut self, map: &CoverageMap) -> bool {
+        let mut new_coverage = false;
+        for (seen, bits) in self.seen.map.iter_mut().zip(map.map.iter()) {
+            if *bits & !*seen != 0 {
+                *seen |= *bits;
+                new_coverage = true;
+            }
+        }
+        new_coverage
+    }
+
+    /// union of all observed maps
+    pub fn seen(&self) -> &CoverageMap {
+        &self.seen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CoverageFeedback, CoverageMap};
+    use crate::coverage::FunctionCounters;
+
+    fn profile(counters: Vec<u64>) -> Vec<FunctionCounters> {
+        vec![FunctionCounters {
+            name_hash: 1,
+            func_hash: 2,
+            name: None,
+            counters,
+        }]
+    }
+
+    #[test]
+    fn test_coverage_feedback() {
+        let mut feedback = CoverageFeedback::new();
+        // hashes are stable across builds
+        assert_eq!(CoverageMap::new().hash(), 0xde2f256064a0af79);
+        let mut first = CoverageMap::new();
+        first.add_profile("wasm1contract", &profile(vec![1, 0, 0]));
+        assert_eq!(first.count(), 1);
+        assert!(feedback.observe(&first));
+        assert!(!feedback.observe(&first));
+
+        // a new block is new coverage
+        let mut second = CoverageMap::new();
+        second.add_profile("wasm1contract", &profile(vec![1, 1, 0]));
+        assert_ne!(first.hash(), second.hash());
+        assert!(feedback.observe(&second));
+
+        // so is reaching the same block in a different contract
+        let mut other = CoverageMap::new();
+        other.add_profile("wasm1other", &profile(vec![1, 0, 0]));
+        assert!(feedback.observe(&other));
+
+        // hit counts in the same bucket are not
+        let mut loop_a = CoverageMap::new();
+        loop_a.add_profile("wasm1contract", &profile(vec![1, 5, 0]));
+        let mut loop_b = CoverageMap::new();
+        loop_b.add_profile("wasm1contract", &profile(vec![1, 6, 0]));
+        assert_eq!(loop_a.hash(), loop_b.hash());
+        assert!(feedback.observe(&loop_a));
+        assert!(!feedback.observe(&loop_b));
+        assert_eq!(feedback.seen().count(), 3);
+    }
+}
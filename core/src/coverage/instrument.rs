@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use wasmparser::{
+    BinaryReader, CodeSectionReader, ExportSectionReader, ExternalKind, FunctionBody,
+    ImportSectionEntryType, ImportSectionReader, Operator,
+};
+
+use super::profraw::{encode_profraw, name_hash, FunctionCounters};
+use super::report::wasm_function_indices;
+use crate::Error;
+
+pub const DUMP_COVERAGE_EXPORT: &str = "dump_coverage";
+
+/// counters inserted into a single function
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstrumentedFunction {
+    /// index of the function in the wasm module
+    pub index: u32,
+    pub name: String,
+    /// position of the function's first counter in the coverage dump
+    pub first_counter: usize,
+    pub num_counters: usize,
+}
+
+/// describes the counters of an instrumented module, needed to decode its coverage dumps
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageLayout {
+    pub functions: Vec<InstrumentedFunction>,
+}
+
+impl CoverageLayout {
+    pub fn num_counters(&self) -> usize {
+        self.functions.iter().map(|f| f.num_counters).sum()
+    }
+
+    /// convert the counters dumped by the synthesized dump_coverage export into a raw profile
+    pub fn to_profraw(&self, dump: &[u8]) -> Result<Vec<u8>, Error> {
+        if dump.len() != self.num_counters() * 8 {
+            return Err(Error::format_error(format!(
+                "coverage dump has {} bytes, expected {}",
+                dump.len(),
+                self.num_counters() * 8
+            )));
+        }
+        let counters: Vec<u64> = dump
+            .chunks(8)
+            .map(|c| {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(c);
+                u64::from_le_bytes(bytes)
+            })
+            .collect();
+        let functions: Vec<FunctionCounters> = self
+            .functions
+            .iter()
+            .map(|f| FunctionCounters {
+                name_hash: name_hash(&f.name),
+                func_hash: f.index as u64,
+                name: Some(f.name.clone()),
+                counters: counters[f.first_counter..f.first_counter + f.num_counters].to_vec(),
+            })
+            .collect();
+        Ok(encode_profraw(&functions))
+    }
+}
+
+const WASM_HEADER_LEN: usize = 8;
+const SECTION_TYPE: u8 = 1;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_FUNCTION: u8 = 3;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+// position of every known section id in the order sections must appear in
+const SECTION_ORDER: [u8; 12] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 12, 10, 11];
+
+fn section_order(id: u8) -> usize {
+    SECTION_ORDER
+        .iter()
+        .position(|i| *i == id)
+        .unwrap_or(usize::MAX)
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// top-level sections of a module, as (id, payload) pairs
+fn read_sections(code: &[u8]) -> Result<Vec<(u8, &[u8])>, Error> {
+    if code.len() < WASM_HEADER_LEN || &code[..4] != b"\0asm" {
+        return Err(Error::format_error("invalid wasm module"));
+    }
+    let mut sections = Vec::new();
+    let mut reader = BinaryReader::new_with_offset(&code[WASM_HEADER_LEN..], WASM_HEADER_LEN);
+    while !reader.eof() {
+        let id = reader.read_u8().map_err(Error::format_error)? as u8;
+        let len = reader.read_var_u32().map_err(Error::format_error)? as usize;
+        let payload = reader.read_bytes(len).map_err(Error::format_error)?;
+        sections.push((id, payload));
+    }
+    Ok(sections)
+}
+
+/// number of entries of a vector section, and the position of its first entry
+fn section_count(payload: &[u8]) -> Result<(u32, usize), Error> {
+    let mut reader = BinaryReader::new(payload);
+    let count = reader.read_var_u32().map_err(Error::format_error)?;
+    Ok((count, reader.original_position()))
+}
+
+/// append entries to a vector section, or create the section if the module lacks it
+fn append_entries(payload: Option<&[u8]>, count: u32, entries: &[u8]) -> Result<Vec<u8>, Error> {
+    let (old_count, entries_pos) = match payload {
+        Some(payload) => section_count(payload)?,
+        None => (0, 0),
+    };
+    let mut out = Vec::new();
+    write_uleb128(&mut out, (old_count + count) as u64);
+    if let Some(payload) = payload {
+        out.extend_from_slice(&payload[entries_pos..]);
+    }
+    out.extend_from_slice(entries);
+    Ok(out)
+}
+
+fn exported_functions(payload: &[u8]) -> Result<HashMap<String, u32>, Error> {
+    let mut reader = ExportSectionReader::new(payload, 0).map_err(Error::format_error)?;
+    let mut exports = HashMap::new();
+    for _ in 0..reader.get_count() {
+        let export = reader.read().map_err(Error::format_error)?;
+        if let ExternalKind::Function = export.kind {
+            exports.insert(export.field.to_string(), export.index);
+        }
+    }
+    Ok(exports)
+}
+
+/// returns true if the module already exports dump_coverage, e.g. because it was built with minicov
+pub fn exports_dump_coverage(code: &[u8]) -> Result<bool, Error> {
+    for (id, payload) in read_sections(code)? {
+        if id == SECTION_EXPORT {
+            return Ok(exported_functions(payload)?.contains_key(DUMP_COVERAGE_EXPORT));
+        }
+    }
+    Ok(false)
+}
+
+// global.get $counter; i64.const 1; i64.add; global.set $counter
+fn write_increment(out: &mut Vec<u8>, global: u32) {
+    out.push(0x23);
+    write_uleb128(out, global as u64);
+    out.extend_from_slice(&[0x42, 0x01, 0x7c, 0x24]);
+    write_uleb128(out, global as u64);
+}
+
+/// insert a counter at the function entry, at the beginning of every block body,
+/// after every block (where control flow joins) and after every conditional branch
+/// the original instructions are copied verbatim, so any instruction wasmparser can read is supported
+fn instrument_body(
+    body: &FunctionBody,
+    code: &[u8],
+    next_global: &mut u32,
+) -> Result<(Vec<u8>, usize), Error> {
+    let range = body.range();
+    let mut operators = body.get_operators_reader().map_err(Error::format_error)?;
+    let mut out = Vec::with_capacity(range.end - range.start);
+    let mut pos = operators.original_position();
+    // locals are kept as they are
+    out.extend_from_slice(&code[range.start..pos]);
+    write_increment(&mut out, *next_global);
+    *next_global += 1;
+    let mut num_counters = 1;
+    let mut depth = 0;
+    while !operators.eof() {
+        let operator = operators.read().map_err(Error::format_error)?;
+        let next = operators.original_position();
+        out.extend_from_slice(&code[pos..next]);
+        pos = next;
+        let counted = match operator {
+            Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                depth += 1;
+                true
+            }
+            Operator::Else | Operator::BrIf { .. } => true,
+            // the last end closes the function body
+            Operator::End if depth > 0 => {
+                depth -= 1;
+                true
+            }
+            _ => false,
+        };
+        if counted {
+            write_increment(&mut out, *next_global);
+            *next_global += 1;
+            num_counters += 1;
+        }
+    }
+    Ok((out, num_counters))
+}
+
+/// body of dump_coverage, which copies the counters into a region allocated by the contract
+fn dump_coverage_body(allocate: u32, counters: std::ops::Range<u32>) -> Vec<u8> {
+    let dump_len = (counters.len() * 8) as i64;
+    // local 0: result region, local 1: data pointer of the region
+    let mut out = vec![0x01, 0x02, 0x7f];
+    out.push(0x41);
+    write_sleb128(&mut out, dump_len);
+    out.push(0x10);
+    write_uleb128(&mut out, allocate as u64);
+    // local.tee 0; i32.load align=2 offset=0; local.set 1
+    out.extend_from_slice(&[0x22, 0x00, 0x28, 0x02, 0x00, 0x21, 0x01]);
+    for (i, global) in counters.enumerate() {
+        // local.get 1; global.get $counter; i64.store align=3 offset=i*8
+        out.extend_from_slice(&[0x20, 0x01, 0x23]);
+        write_uleb128(&mut out, global as u64);
+        out.extend_from_slice(&[0x37, 0x03]);
+        write_uleb128(&mut out, (i * 8) as u64);
+    }
+    // region.length = dump_len
+    out.extend_from_slice(&[0x20, 0x00, 0x41]);
+    write_sleb128(&mut out, dump_len);
+    out.extend_from_slice(&[0x36, 0x02, 0x08]);
+    // return the region
+    out.extend_from_slice(&[0x20, 0x00, 0x0b]);
+    out
+}
+
+/// rewrite a module so that it counts block executions in mutable globals and exports
+/// a dump_coverage function, which returns the counters as little-endian u64s through
+/// the contract's own allocator
+/// counter globals, the new type and dump_coverage are appended, so no existing index changes
+pub fn instrument_module(code: &[u8]) -> Result<(Vec<u8>, CoverageLayout), Error> {
+    let names: HashMap<u32, String> = wasm_function_indices(code)?
+        .into_iter()
+        .map(|(name, index)| (index, name))
+        .collect();
+    let sections = read_sections(code)?;
+    let section = |id: u8| sections.iter().find(|s| s.0 == id).map(|s| s.1);
+
+    let mut imported_functions = 0;
+    let mut imported_globals = 0;
+    if let Some(payload) = section(SECTION_IMPORT) {
+        let mut reader = ImportSectionReader::new(payload, 0).map_err(Error::format_error)?;
+        for _ in 0..reader.get_count() {
+            match reader.read().map_err(Error::format_error)?.ty {
+                ImportSectionEntryType::Function(_) => imported_functions += 1,
+                ImportSectionEntryType::Global(_) => imported_globals += 1,
+                _ => {}
+            }
+        }
+    }
+    let count = |id: u8| -> Result<u32, Error> {
+        match section(id) {
+            Some(payload) => Ok(section_count(payload)?.0),
+            None => Ok(0),
+        }
+    };
+    let first_global = imported_globals + count(SECTION_GLOBAL)?;
+    let num_types = count(SECTION_TYPE)?;
+    let dump_index = imported_functions + count(SECTION_FUNCTION)?;
+    let allocate = match section(SECTION_EXPORT) {
+        Some(payload) => exported_functions(payload)?.get("allocate").copied(),
+        None => None,
+    }
+    .ok_or_else(|| Error::format_error("module does not export allocate"))?;
+
+    // instrument function bodies
+    let mut next_global = first_global;
+    let mut layout = CoverageLayout::default();
+    let mut code_section = Vec::new();
+    if let Some(payload) = section(SECTION_CODE) {
+        let mut reader = CodeSectionReader::new(payload, 0).map_err(Error::format_error)?;
+        write_uleb128(&mut code_section, (reader.get_count() + 1) as u64);
+        for i in 0..reader.get_count() {
+            let body = reader.read().map_err(Error::format_error)?;
+            let index = imported_functions + i;
+            let first_counter = (next_global - first_global) as usize;
+            let (body, num_counters) = instrument_body(&body, payload, &mut next_global)?;
+            write_uleb128(&mut code_section, body.len() as u64);
+            code_section.extend_from_slice(&body);
+            layout.functions.push(InstrumentedFunction {
+                index,
+                name: names
+                    .get(&index)
+                    .cloned()
+                    .unwrap_or_else(|| format!("function_{}", index)),
+                first_counter,
+                num_counters,
+            });
+        }
+    } else {
+        write_uleb128(&mut code_section, 1);
+    }
+    let dump = dump_coverage_body(allocate, first_global..next_global);
+    write_uleb128(&mut code_section, dump.len() as u64);
+    code_section.extend_from_slice(&dump);
+
+    // mutable i64 globals initialized to 0
+    let mut globals = Vec::new();
+    for _ in first_global..next_global {
+        globals.extend_from_slice(&[0x7e, 0x01, 0x42, 0x00, 0x0b]);
+    }
+    let mut export = Vec::new();
+    write_uleb128(&mut export, DUMP_COVERAGE_EXPORT.len() as u64);
+    export.extend_from_slice(DUMP_COVERAGE_EXPORT.as_bytes());
+    export.push(0x00);
+    write_uleb128(&mut export, dump_index as u64);
+    let mut function = Vec::new();
+    write_uleb128(&mut function, num_types as u64);
+
+    // sections to replace, in section order
+    let replaced: Vec<(u8, Vec<u8>)> = vec![
+        (
+            SECTION_TYPE,
+            // () -> i32
+            append_entries(section(SECTION_TYPE), 1, &[0x60, 0x00, 0x01, 0x7f])?,
+        ),
+        (
+            SECTION_FUNCTION,
+            append_entries(section(SECTION_FUNCTION), 1, &function)?,
+        ),
+        (
+            SECTION_GLOBAL,
+            append_entries(
+                section(SECTION_GLOBAL),
+                next_global - first_global,
+                &globals,
+            )?,
+        ),
+        (
+            SECTION_EXPORT,
+            append_entries(section(SECTION_EXPORT), 1, &export)?,
+        ),
+        (SECTION_CODE, code_section),
+    ];
+
+    let mut out = code[..WASM_HEADER_LEN].to_vec();
+    let write_section = |out: &mut Vec<u8>, id: u8, payload: &[u8]| {
+        out.push(id);
+        write_uleb128(out, payload.len() as u64);
+        out.extend_from_slice(payload);
+    };
+    let mut replaced = replaced.into_iter().peekable();
+    for (id, payload) in sections.iter() {
+        // emit replacements of sections which are missing in the original module
+        while let Some((new_id, _)) = replaced.peek() {
+            if *id == 0 || section_order(*new_id) >= section_order(*id) {
+                break;
+            }
+            let (new_id, new_payload) = replaced.next().unwrap();
+            write_section(&mut out, new_id, &new_payload);
+        }
+        match replaced.peek() {
+            Some((new_id, _)) if new_id == id => {
+                let (new_id, new_payload) = replaced.next().unwrap();
+                write_section(&mut out, new_id, &new_payload);
+            }
+            _ => write_section(&mut out, *id, payload),
+        }
+    }
+    for (new_id, new_payload) in replaced {
+        write_section(&mut out, new_id, &new_payload);
+    }
+    Ok((out, layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmer::{imports, Instance, Module, Singlepass, Store, Universal, Value};
+
+    use super::{exports_dump_coverage, instrument_module};
+    use crate::coverage::parse_profraw;
+
+    // bump allocator returning cosmwasm regions {offset, capacity, length}
+    const WASM: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $heap (mut i32) (i32.const 1024))
+        (func $allocate (export "allocate") (param $size i32) (result i32)
+            (local $region i32)
+            global.get $heap
+            local.set $region
+            local.get $region
+            global.get $heap
+            i32.const 12
+            i32.add
+            i32.store
+            local.get $region
+            local.get $size
+            i32.store offset=4
+            local.get $region
+            i32.const 0
+            i32.store offset=8
+            global.get $heap
+            i32.const 12
+            i32.add
+            local.get $size
+            i32.add
+            global.set $heap
+            local.get $region)
+        (func $abs (export "abs") (param i32) (result i32)
+            local.get 0
+            i32.const 0
+            i32.lt_s
+            if (result i32)
+                i32.const 0
+                local.get 0
+                i32.sub
+            else
+                local.get 0
+            end))"#;
+
+    #[test]
+    fn test_instrument_module() {
+        let code = wat::parse_str(WASM).unwrap();
+        assert!(!exports_dump_coverage(&code).unwrap());
+        let (instrumented, layout) = instrument_module(&code).unwrap();
+        assert!(exports_dump_coverage(&instrumented).unwrap());
+        assert_eq!(layout.functions.len(), 2);
+        assert_eq!(layout.functions[1].name, "abs");
+        // entry, then, else, after the if
+        assert_eq!(layout.functions[1].num_counters, 4);
+
+        // plain store without gas metering
+        let store = Store::new(&Universal::new(Singlepass::default()).engine());
+        let module = Module::new(&store, &instrumented).unwrap();
+        let instance = Instance::new(&module, &imports! {}).unwrap();
+        let abs = instance.exports.get_function("abs").unwrap();
+        for x in [-3, -4, 5] {
+            let result = abs.call(&[Value::I32(x)]).unwrap();
+            assert_eq!(result[0], Value::I32(x.abs()));
+        }
+        let dump_coverage = instance.exports.get_function("dump_coverage").unwrap();
+        let region = match dump_coverage.call(&[]).unwrap()[0] {
+            Value::I32(region) => region as usize,
+            _ => panic!("unexpected return type"),
+        };
+        let memory = instance.exports.get_memory("memory").unwrap();
+        let read_u32 = |ptr: usize| {
+            let bytes: Vec<u8> = memory.view::<u8>()[ptr..ptr + 4]
+                .iter()
+                .map(|c| c.get())
+                .collect();
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+        };
+        let (offset, length) = (read_u32(region), read_u32(region + 8));
+        let dump: Vec<u8> = memory.view::<u8>()[offset..offset + length]
+            .iter()
+            .map(|c| c.get())
+            .collect();
+
+        let functions = parse_profraw(&layout.to_profraw(&dump).unwrap()).unwrap();
+        // dump_coverage itself called allocate once
+        assert_eq!(functions[0].counters[0], 1);
+        assert_eq!(functions[1].name.as_deref(), Some("abs"));
+        assert_eq!(functions[1].counters, vec![3, 2, 1, 3]);
+        assert!(layout.to_profraw(&dump[1..]).is_err());
+
+        // the global section is created if the module has none
+        let code = wat::parse_str(
+            r#"(module (memory 1) (func (export "allocate") (param i32) (result i32) i32.const 0))"#,
+        )
+        .unwrap();
+        let (instrumented, _) = instrument_module(&code).unwrap();
+        wasmparser::validate(&instrumented).unwrap();
+        assert!(instrument_module(&wat::parse_str("(module (func))").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_instrument_contract_code() {
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let (instrumented, layout) = instrument_module(code).unwrap();
+        assert!(layout.num_counters() > layout.functions.len());
+        assert!(exports_dump_coverage(&instrumented).unwrap());
+        wasmparser::validate(&instrumented).unwrap();
+    }
+}
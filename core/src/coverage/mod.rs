@@ -1,14 +1,49 @@
+mod feedback;
+mod instrument;
+mod profraw;
+mod report;
+mod snapshot;
+
+pub use feedback::{CoverageFeedback, CoverageMap, COVERAGE_MAP_SIZE};
+pub use instrument::{
+    exports_dump_coverage, instrument_module, CoverageLayout, InstrumentedFunction,
+};
+pub use profraw::{encode_profraw, merge_profiles, parse_profraw, FunctionCounters};
+pub use report::{wasm_function_indices, ContractCoverage, CoverageReport, FunctionCoverage};
+pub use snapshot::{ContractCounters, ContractDiff, CoverageDiff, CoverageSnapshot};
+
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use crate::{Error, Model, RpcContractInstance};
+use crate::{code_checksum, Addr, Error, Model, RpcContractInstance};
 use cosmwasm_vm::call_raw;
 
 static COVERAGE_MAX_LEN: usize = 0x200000;
 
+// instrumented code and its layout, None if the code already exports dump_coverage
+type InstrumentedCode = Option<(Vec<u8>, Arc<CoverageLayout>)>;
+
+#[derive(Default)]
+struct Instrumentation {
+    // by checksum of the original code
+    by_code: HashMap<Vec<u8>, InstrumentedCode>,
+    // layout of every contract running instrumented code
+    by_address: HashMap<String, Arc<CoverageLayout>>,
+}
+
 #[derive(Clone)]
 pub struct CoverageInfo {
     enabled: bool,
     coverage_data: HashMap<String, Vec<Vec<u8>>>,
+    // coverage collected since the beginning of the last top-level call
+    last_run: Vec<(String, Vec<u8>)>,
+    feedback: CoverageFeedback,
+    // coverage of other models merged by Model::merge_coverage
+    merged: CoverageSnapshot,
+    // instrument contracts loaded from the chain which do not export dump_coverage
+    instrument: bool,
+    // shared by clones, instrumenting the same code always gives the same result
+    instrumentation: Arc<Mutex<Instrumentation>>,
 }
 
 impl CoverageInfo {
@@ -16,6 +51,11 @@ impl CoverageInfo {
         Self {
             enabled: false,
             coverage_data: HashMap::new(),
+            last_run: Vec::new(),
+            feedback: CoverageFeedback::new(),
+            merged: CoverageSnapshot::new(),
+            instrument: false,
+            instrumentation: Arc::new(Mutex::new(Instrumentation::default())),
         }
     }
 
@@ -23,7 +63,21 @@ impl CoverageInfo {
         self.coverage_data.clone()
     }
 
+    pub(crate) fn begin_run(&mut self) {
+        self.last_run.clear();
+    }
+
+    /// the contract's code was replaced, so its dumps no longer match the layout
+    pub(crate) fn forget_layout(&self, address: &Addr) {
+        self.instrumentation
+            .lock()
+            .unwrap()
+            .by_address
+            .remove(address.as_str());
+    }
+
     fn add_coverage(&mut self, address: String, cov_data: Vec<u8>) {
+        self.last_run.push((address.clone(), cov_data.clone()));
         self.coverage_data
             .entry(address)
             .or_insert_with(Vec::new)
@@ -38,9 +92,122 @@ impl Model {
     pub fn disable_code_coverage(&mut self) {
         self.coverage_info.enabled = false;
     }
+    /// rewrite contracts without a dump_coverage export so that coverage can be collected
+    /// applies to contracts loaded or instantiated after this call
+    pub fn enable_coverage_instrumentation(&mut self) {
+        self.coverage_info.instrument = true;
+    }
+    pub fn disable_coverage_instrumentation(&mut self) {
+        self.coverage_info.instrument = false;
+    }
+
+    /// returns the code to run for contract_addr, instrumented if instrumentation is enabled
+    pub(crate) fn maybe_instrument(
+        &self,
+        contract_addr: &Addr,
+        code: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        if !self.coverage_info.instrument {
+            return Ok(code);
+        }
+        self.instrument_code(contract_addr, code)
+    }
+
+    fn instrument_code(&self, contract_addr: &Addr, code: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let checksum = code_checksum(&code);
+        let cached = self
+            .coverage_info
+            .instrumentation
+            .lock()
+            .unwrap()
+            .by_code
+            .get(&checksum)
+            .cloned();
+        let instrumented = match cached {
+            Some(instrumented) => instrumented,
+            None => {
+                let instrumented = if exports_dump_coverage(&code)? {
+                    None
+                } else {
+                    let (code, layout) = instrument_module(&code)?;
+                    Some((code, Arc::new(layout)))
+                };
+                self.coverage_info
+                    .instrumentation
+                    .lock()
+                    .unwrap()
+                    .by_code
+                    .insert(checksum, instrumented.clone());
+                instrumented
+            }
+        };
+        match instrumented {
+            Some((instrumented_code, layout)) => {
+                self.coverage_info
+                    .instrumentation
+                    .lock()
+                    .unwrap()
+                    .by_address
+                    .insert(contract_addr.to_string(), layout);
+                Ok(instrumented_code)
+            }
+            None => Ok(code),
+        }
+    }
+
+    /// instrument the code of an already loaded contract
+    /// does nothing if the contract is instrumented or exports dump_coverage
+    pub fn instrument_contract(&mut self, contract_addr: &Addr) -> Result<(), Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let code = self.contract_code(contract_addr).unwrap();
+        if self
+            .coverage_info
+            .instrumentation
+            .lock()
+            .unwrap()
+            .by_address
+            .contains_key(contract_addr.as_str())
+        {
+            return Ok(());
+        }
+        let instrumented = self.instrument_code(contract_addr, code.clone())?;
+        if instrumented != code {
+            let layout = self
+                .coverage_info
+                .instrumentation
+                .lock()
+                .unwrap()
+                .by_address
+                .get(contract_addr.as_str())
+                .cloned();
+            // cheat_code forgets the layout of the replaced code
+            self.cheat_code(contract_addr, &instrumented)?;
+            if let Some(layout) = layout {
+                self.coverage_info
+                    .instrumentation
+                    .lock()
+                    .unwrap()
+                    .by_address
+                    .insert(contract_addr.to_string(), layout);
+            }
+        }
+        Ok(())
+    }
+
     pub fn handle_coverage(&mut self, instance: &mut RpcContractInstance) -> Result<(), Error> {
         if self.coverage_info.enabled {
-            let cov = instance.dump_coverage()?;
+            let mut cov = instance.dump_coverage()?;
+            let layout = self
+                .coverage_info
+                .instrumentation
+                .lock()
+                .unwrap()
+                .by_address
+                .get(instance.address().as_str())
+                .cloned();
+            if let Some(layout) = layout {
+                cov = layout.to_profraw(&cov)?;
+            }
             self.coverage_info
                 .add_coverage(instance.address().to_string(), cov);
         }
@@ -49,6 +216,74 @@ impl Model {
     pub fn get_coverage(&self) -> HashMap<String, Vec<Vec<u8>>> {
         self.coverage_info.get_coverage()
     }
+
+    /// decode and merge the collected coverage of every contract, including the merged coverage
+    /// of other models
+    pub fn coverage_snapshot(&self) -> Result<CoverageSnapshot, Error> {
+        let mut snapshot = self.coverage_info.merged.clone();
+        for (address, runs) in self.coverage_info.coverage_data.iter() {
+            // contracts without the dump_coverage export produce empty dumps
+            for run in runs.iter().filter(|run| !run.is_empty()) {
+                snapshot.add_profile(address, parse_profraw(run)?);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// add the coverage of another model, e.g. of a parallel fuzzing shard, to the coverage
+    /// reported by this one. The contracts are matched by address
+    pub fn merge_coverage(&mut self, snapshot: &CoverageSnapshot) {
+        self.coverage_info.merged.merge(snapshot);
+    }
+
+    /// decode and merge the collected coverage of every contract
+    /// functions are mapped to their index in the contract's wasm module if it has a name section
+    pub fn coverage_report(&self) -> Result<CoverageReport, Error> {
+        let mut report = CoverageReport::default();
+        for (address, counters) in self.coverage_snapshot()?.contracts {
+            let code = self.contract_code(&Addr::unchecked(&address));
+            let coverage = ContractCoverage::new(
+                &address,
+                counters.runs as usize,
+                counters.functions,
+                code.as_deref(),
+            )?;
+            report.contracts.insert(address, coverage);
+        }
+        Ok(report)
+    }
+
+    /// bucketed block hit counts of the last instantiate or execute, including all of its submessages
+    /// equal maps mean the executions took the same path, up to the hit count buckets
+    pub fn last_coverage_map(&self) -> Result<CoverageMap, Error> {
+        let mut map = CoverageMap::new();
+        for (address, run) in self.coverage_info.last_run.iter() {
+            if !run.is_empty() {
+                map.add_profile(address, &parse_profraw(run)?);
+            }
+        }
+        Ok(map)
+    }
+
+    /// hash of the coverage map of the last call
+    pub fn coverage_hash(&self) -> Result<u64, Error> {
+        Ok(self.last_coverage_map()?.hash())
+    }
+
+    /// returns true if the last call reached a block, or a hit count bucket of a block,
+    /// which no call observed by this function before
+    pub fn has_new_coverage(&mut self) -> Result<bool, Error> {
+        let map = self.last_coverage_map()?;
+        Ok(self.coverage_info.feedback.observe(&map))
+    }
+
+    pub fn coverage_lcov(&self) -> Result<String, Error> {
+        Ok(self.coverage_report()?.to_lcov())
+    }
+
+    pub fn coverage_html(&self) -> Result<String, Error> {
+        Ok(self.coverage_report()?.to_html())
+    }
 }
 
 impl RpcContractInstance {
@@ -69,7 +304,8 @@ mod tests {
     use cosmwasm_std::to_binary;
     use test_contract_cov::msg::InstantiateMsg;
 
-    use crate::Model;
+    use crate::coverage::CoverageSnapshot;
+    use crate::{Addr, Model};
 
     const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
     const MALAGA_BLOCK_NUMBER: u64 = 2326474;
@@ -86,5 +322,63 @@ mod tests {
         let msg = to_binary(&InstantiateMsg {}).unwrap();
         let _ = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
         assert!(model.get_coverage().len() > 0);
+
+        let report = model.coverage_report().unwrap();
+        assert_eq!(report.contracts.len(), 1);
+        let coverage = report.contracts.values().next().unwrap();
+        assert!(coverage.covered_functions() > 0);
+        assert!(report.to_lcov().contains("end_of_record"));
+        assert!(!model.last_coverage_map().unwrap().is_empty());
+        assert!(model.has_new_coverage().unwrap());
+        assert!(!model.has_new_coverage().unwrap());
+
+        model.disable_code_coverage();
+        let _ = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
+        assert_eq!(model.coverage_report().unwrap().contracts.len(), 1);
+
+        // the coverage of a shard adds up with the coverage of another
+        let snapshot = model.coverage_snapshot().unwrap();
+        let snapshot = CoverageSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+        let mut other = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        assert!(!snapshot
+            .diff(&other.coverage_snapshot().unwrap())
+            .is_empty());
+        other.merge_coverage(&snapshot);
+        other.merge_coverage(&snapshot);
+        let report = other.coverage_report().unwrap();
+        assert_eq!(report.contracts.values().next().unwrap().runs, 2);
+        assert!(snapshot
+            .diff(&other.coverage_snapshot().unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_collect_coverage_instrumented() {
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.enable_code_coverage();
+        model.enable_coverage_instrumentation();
+        // test_contract is not built with minicov
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, wasm_code).unwrap();
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        let report = model.coverage_report().unwrap();
+        let coverage = report.contracts.values().next().unwrap();
+        assert!(coverage.covered_functions() > 0);
+
+        let contract_addr = Addr::unchecked(&coverage.address);
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model.execute(&contract_addr, msg.as_slice(), &[]).unwrap();
+        assert!(model.has_new_coverage().unwrap());
+        assert_eq!(
+            model.coverage_report().unwrap().contracts[coverage.address.as_str()].runs,
+            2
+        );
     }
 }
@@ -0,0 +1,422 @@
+use flate2::read::ZlibDecoder;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+
+use crate::Error;
+
+// LLVM raw profile (profraw) format, as written by minicov's capture_coverage()
+const RAW_MAGIC_64: u64 = u64::from_be_bytes([255, b'l', b'p', b'r', b'o', b'f', b'r', 129]);
+const RAW_MAGIC_32: u64 = u64::from_be_bytes([255, b'l', b'p', b'r', b'o', b'f', b'R', 129]);
+const RAW_VERSION: u64 = 8;
+const VARIANT_MASKS_ALL: u64 = 0xff00000000000000;
+const VARIANT_MASK_BYTE_COVERAGE: u64 = 1 << 60;
+const HEADER_LEN: usize = 11 * 8;
+// profile names are separated by this byte
+const NAME_SEPARATOR: u8 = 0x01;
+
+/// counters of a single instrumented function
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCounters {
+    /// md5 hash of the function's PGO name
+    pub name_hash: u64,
+    /// hash of the function's control flow, changes whenever the function is modified
+    pub func_hash: u64,
+    /// PGO name of the function, None if the profile does not contain it
+    pub name: Option<String>,
+    /// execution count of every instrumented block, the first one is the function entry
+    pub counters: Vec<u64>,
+}
+
+impl FunctionCounters {
+    pub fn covered_blocks(&self) -> usize {
+        self.counters.iter().filter(|c| **c > 0).count()
+    }
+}
+
+/// md5 based hash used to refer to function names in the profile
+pub fn name_hash(name: &str) -> u64 {
+    let digest = Md5::digest(name.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| Error::format_error("profraw: unexpected end of data"))?;
+        let rv = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(rv)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn pointer(&mut self, width: usize) -> Result<u64, Error> {
+        match width {
+            4 => self.u32().map(|v| v as u64),
+            _ => self.u64(),
+        }
+    }
+
+    fn uleb128(&mut self) -> Result<u64, Error> {
+        let mut rv = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.take(1)?[0];
+            if shift >= 64 {
+                return Err(Error::format_error("profraw: invalid uleb128"));
+            }
+            rv |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(rv);
+            }
+            shift += 7;
+        }
+    }
+}
+
+fn section_len(len: u64) -> Result<usize, Error> {
+    usize::try_from(len).map_err(|_| Error::format_error("profraw: section too large"))
+}
+
+/// decode the names section, which consists of possibly zlib compressed chunks of names
+fn parse_names(data: &[u8]) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    let mut cursor = Cursor { data, pos: 0 };
+    while cursor.pos < data.len() {
+        let uncompressed_len = section_len(cursor.uleb128()?)?;
+        let compressed_len = section_len(cursor.uleb128()?)?;
+        let chunk = if compressed_len == 0 {
+            cursor.take(uncompressed_len)?.to_vec()
+        } else {
+            let mut chunk = Vec::with_capacity(uncompressed_len);
+            ZlibDecoder::new(cursor.take(compressed_len)?)
+                .read_to_end(&mut chunk)
+                .map_err(|e| Error::format_error(format!("profraw: {}", e)))?;
+            chunk
+        };
+        for name in chunk.split(|b| *b == NAME_SEPARATOR) {
+            if !name.is_empty() {
+                names.push(String::from_utf8_lossy(name).to_string());
+            }
+        }
+        // names are padded to 8 bytes at the end of the section
+        if data[cursor.pos..].iter().all(|b| *b == 0) {
+            break;
+        }
+    }
+    Ok(names)
+}
+
+/// parse a raw profile dumped by an instrumented contract
+pub fn parse_profraw(data: &[u8]) -> Result<Vec<FunctionCounters>, Error> {
+    if data.len() < HEADER_LEN {
+        return Err(Error::format_error("profraw: missing header"));
+    }
+    let mut cursor = Cursor { data, pos: 0 };
+    let pointer_width = match cursor.u64()? {
+        RAW_MAGIC_64 => 8,
+        RAW_MAGIC_32 => 4,
+        _ => return Err(Error::format_error("profraw: invalid magic")),
+    };
+    let version = cursor.u64()?;
+    if version & !VARIANT_MASKS_ALL != RAW_VERSION {
+        return Err(Error::format_error(format!(
+            "profraw: unsupported version {}",
+            version & !VARIANT_MASKS_ALL
+        )));
+    }
+    let byte_coverage = version & VARIANT_MASK_BYTE_COVERAGE != 0;
+    let binary_ids_size = section_len(cursor.u64()?)?;
+    let num_data = section_len(cursor.u64()?)?;
+    let padding_before_counters = section_len(cursor.u64()?)?;
+    let num_counters = section_len(cursor.u64()?)?;
+    let padding_after_counters = section_len(cursor.u64()?)?;
+    let names_size = section_len(cursor.u64()?)?;
+    let counters_delta = cursor.u64()?;
+    let _names_delta = cursor.u64()?;
+    let value_kind_last = section_len(cursor.u64()?)?;
+
+    cursor.take(binary_ids_size)?;
+
+    // each data record is aligned to 8 bytes
+    let num_value_sites_len = 2 * (value_kind_last + 1);
+    let record_len = (16 + 3 * pointer_width + 4 + num_value_sites_len + 7) & !7;
+    let counter_len = if byte_coverage { 1 } else { 8 };
+    let pointer_mask = if pointer_width == 4 {
+        u32::MAX as u64
+    } else {
+        u64::MAX
+    };
+
+    let mut records = Vec::with_capacity(num_data);
+    for i in 0..num_data {
+        let mut record = Cursor {
+            data: cursor.take(record_len)?,
+            pos: 0,
+        };
+        let name_hash = record.u64()?;
+        let func_hash = record.u64()?;
+        let counter_ptr = record.pointer(pointer_width)?;
+        let _function_pointer = record.pointer(pointer_width)?;
+        let _values = record.pointer(pointer_width)?;
+        let num_func_counters = record.u32()? as usize;
+        // counter pointers are relative to the address of the record itself
+        let record_delta = counters_delta.wrapping_sub((i * record_len) as u64);
+        let offset = (counter_ptr.wrapping_sub(record_delta) & pointer_mask) as usize;
+        records.push((name_hash, func_hash, offset, num_func_counters));
+    }
+
+    cursor.take(padding_before_counters)?;
+    let counters = cursor.take(num_counters * counter_len)?;
+    cursor.take(padding_after_counters)?;
+    let names = parse_names(cursor.take(names_size)?)?;
+    let names: HashMap<u64, String> = names.into_iter().map(|n| (name_hash(&n), n)).collect();
+
+    records
+        .into_iter()
+        .map(|(name_hash, func_hash, offset, num_func_counters)| {
+            let end = offset + num_func_counters * counter_len;
+            if offset % counter_len != 0 || end > counters.len() {
+                return Err(Error::format_error("profraw: counters out of bounds"));
+            }
+            let counters = counters[offset..end]
+                .chunks(counter_len)
+                .map(|c| {
+                    if byte_coverage {
+                        // byte coverage counters are cleared once the block is executed
+                        (c[0] == 0) as u64
+                    } else {
+                        let mut bytes = [0u8; 8];
+                        bytes.copy_from_slice(c);
+                        u64::from_le_bytes(bytes)
+                    }
+                })
+                .collect();
+            Ok(FunctionCounters {
+                name_hash,
+                func_hash,
+                name: names.get(&name_hash).cloned(),
+                counters,
+            })
+        })
+        .collect()
+}
+
+/// whether a and b are counters of the same code of a function
+pub(crate) fn same_function(a: &FunctionCounters, b: &FunctionCounters) -> bool {
+    a.name_hash == b.name_hash && a.func_hash == b.func_hash && a.counters.len() == b.counters.len()
+}
+
+/// sum up counters of several profiles of the same code
+/// functions whose control flow hash differs are kept separately
+pub fn merge_profiles(profiles: &[Vec<FunctionCounters>]) -> Vec<FunctionCounters> {
+    let mut merged: Vec<FunctionCounters> = Vec::new();
+    for function in profiles.iter().flatten() {
+        match merged.iter_mut().find(|f| same_function(f, function)) {
+            Some(f) => {
+                for (a, b) in f.counters.iter_mut().zip(function.counters.iter()) {
+                    *a = a.saturating_add(*b);
+                }
+                if f.name.is_none() {
+                    f.name = function.name.clone();
+                }
+            }
+            None => merged.push(function.clone()),
+        }
+    }
+    merged
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// encode functions as a 64-bit raw profile, which parse_profraw reads back
+/// used for contracts whose counters are not collected by minicov
+pub fn encode_profraw(functions: &[FunctionCounters]) -> Vec<u8> {
+    let record_len = 48;
+    let names = functions
+        .iter()
+        .map(|f| f.name.clone().unwrap_or_default())
+        .collect::<Vec<String>>()
+        .join("\x01");
+    let mut names_section = Vec::new();
+    write_uleb128(&mut names_section, names.len() as u64);
+    write_uleb128(&mut names_section, 0);
+    names_section.extend_from_slice(names.as_bytes());
+    let names_size = names_section.len();
+    while names_section.len() % 8 != 0 {
+        names_section.push(0);
+    }
+    let num_counters: usize = functions.iter().map(|f| f.counters.len()).sum();
+    // counters directly follow the data records
+    let counters_delta = (functions.len() * record_len) as u64;
+
+    let mut out = Vec::new();
+    for v in [
+        RAW_MAGIC_64,
+        RAW_VERSION,
+        0,
+        functions.len() as u64,
+        0,
+        num_counters as u64,
+        0,
+        names_size as u64,
+        counters_delta,
+        0,
+        1,
+    ] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    let mut offset = 0;
+    for (i, f) in functions.iter().enumerate() {
+        let record_addr = (i * record_len) as u64;
+        out.extend_from_slice(&f.name_hash.to_le_bytes());
+        out.extend_from_slice(&f.func_hash.to_le_bytes());
+        out.extend_from_slice(&(counters_delta + offset - record_addr).to_le_bytes());
+        out.extend_from_slice(&[0u8; 16]);
+        out.extend_from_slice(&(f.counters.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0u8; 4]);
+        offset += (f.counters.len() * 8) as u64;
+    }
+    for f in functions.iter() {
+        for c in f.counters.iter() {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&names_section);
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{
+        encode_profraw, merge_profiles, name_hash, parse_profraw, FunctionCounters, RAW_MAGIC_32,
+        RAW_VERSION,
+    };
+
+    /// builds a wasm32 profile with a single chunk of uncompressed names
+    pub(crate) fn build_profraw(functions: &[(&str, Vec<u64>)]) -> Vec<u8> {
+        let record_len = 40;
+        let num_counters: usize = functions.iter().map(|(_, c)| c.len()).sum();
+        let names = functions
+            .iter()
+            .map(|(n, _)| n.to_string())
+            .collect::<Vec<String>>()
+            .join("\x01");
+        let mut names_section = vec![names.len() as u8, 0];
+        names_section.extend_from_slice(names.as_bytes());
+        while names_section.len() % 8 != 0 {
+            names_section.push(0);
+        }
+        // counters directly follow the data records
+        let counters_delta = (functions.len() * record_len) as u64;
+
+        let mut out = Vec::new();
+        for v in [
+            RAW_MAGIC_32,
+            RAW_VERSION,
+            0,
+            functions.len() as u64,
+            0,
+            num_counters as u64,
+            0,
+            names_section.len() as u64,
+            counters_delta,
+            0,
+            1,
+        ] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut offset = 0;
+        for (i, (name, counters)) in functions.iter().enumerate() {
+            let record_addr = (i * record_len) as u64;
+            out.extend_from_slice(&name_hash(name).to_le_bytes());
+            out.extend_from_slice(&(i as u64).to_le_bytes());
+            out.extend_from_slice(&((counters_delta + offset - record_addr) as u32).to_le_bytes());
+            out.extend_from_slice(&[0u8; 8]);
+            out.extend_from_slice(&(counters.len() as u32).to_le_bytes());
+            out.extend_from_slice(&[0u8; 8]);
+            offset += (counters.len() * 8) as u64;
+        }
+        for (_, counters) in functions.iter() {
+            for c in counters.iter() {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&names_section);
+        out
+    }
+
+    #[test]
+    fn test_parse_profraw() {
+        let data = build_profraw(&[("execute", vec![3, 0, 1]), ("query", vec![0])]);
+        let functions = parse_profraw(&data).unwrap();
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].name.as_deref(), Some("execute"));
+        assert_eq!(functions[0].counters, vec![3, 0, 1]);
+        assert_eq!(functions[0].covered_blocks(), 2);
+        assert_eq!(functions[1].name.as_deref(), Some("query"));
+        assert_eq!(functions[1].counters, vec![0]);
+
+        let merged = merge_profiles(&[functions.clone(), functions]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].counters, vec![6, 0, 2]);
+
+        assert!(parse_profraw(&data[..40]).is_err());
+        assert!(parse_profraw(&[0u8; 96]).is_err());
+    }
+
+    #[test]
+    fn test_encode_profraw() {
+        let functions = vec![
+            FunctionCounters {
+                name_hash: name_hash("instantiate"),
+                func_hash: 0,
+                name: Some("instantiate".to_string()),
+                counters: vec![1, 1],
+            },
+            FunctionCounters {
+                name_hash: name_hash("function_7"),
+                func_hash: 7,
+                name: Some("function_7".to_string()),
+                counters: vec![0, 0, 4],
+            },
+        ];
+        assert_eq!(
+            parse_profraw(&encode_profraw(&functions)).unwrap(),
+            functions
+        );
+    }
+}
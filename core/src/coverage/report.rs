@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use wasmparser::{Name, NameSectionReader, Parser, Payload};
+
+use super::profraw::FunctionCounters;
+use crate::Error;
+
+/// coverage of a single instrumented function
+#[derive(Clone, Debug)]
+pub struct FunctionCoverage {
+    pub name: String,
+    /// index of the function in the wasm module, None if the module has no matching name
+    pub wasm_index: Option<u32>,
+    /// execution count of every instrumented block
+    pub counters: Vec<u64>,
+}
+
+impl FunctionCoverage {
+    pub fn hits(&self) -> u64 {
+        self.counters.first().copied().unwrap_or(0)
+    }
+
+    pub fn covered_blocks(&self) -> usize {
+        self.counters.iter().filter(|c| **c > 0).count()
+    }
+
+    /// human readable function name
+    pub fn demangled_name(&self) -> String {
+        // local symbols are prefixed with the name of the source file
+        let symbol = match self.name.rsplit_once(';') {
+            Some((_, symbol)) => symbol,
+            None => &self.name,
+        };
+        format!("{:#}", rustc_demangle::demangle(symbol))
+    }
+}
+
+/// merged coverage of a single contract
+#[derive(Clone, Debug)]
+pub struct ContractCoverage {
+    pub address: String,
+    /// number of coverage dumps which were merged
+    pub runs: usize,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl ContractCoverage {
+    pub fn new(
+        address: &str,
+        runs: usize,
+        counters: Vec<FunctionCounters>,
+        wasm_code: Option<&[u8]>,
+    ) -> Result<Self, Error> {
+        let function_indices = match wasm_code {
+            Some(code) => wasm_function_indices(code)?,
+            None => HashMap::new(),
+        };
+        let mut functions: Vec<FunctionCoverage> = counters
+            .into_iter()
+            .map(|f| {
+                let name_hash = f.name_hash;
+                let name = f.name.unwrap_or_else(|| format!("{:016x}", name_hash));
+                let symbol = name.rsplit_once(';').map_or(name.as_str(), |(_, s)| s);
+                FunctionCoverage {
+                    // functions without a name are named after their index by instrument_module
+                    wasm_index: function_indices.get(symbol).copied().or_else(|| {
+                        symbol
+                            .strip_prefix("function_")
+                            .and_then(|i| i.parse().ok())
+                    }),
+                    name,
+                    counters: f.counters,
+                }
+            })
+            .collect();
+        functions.sort_by(|a, b| (a.wasm_index, &a.name).cmp(&(b.wasm_index, &b.name)));
+        Ok(Self {
+            address: address.to_string(),
+            runs,
+            functions,
+        })
+    }
+
+    pub fn covered_functions(&self) -> usize {
+        self.functions.iter().filter(|f| f.hits() > 0).count()
+    }
+
+    pub fn covered_blocks(&self) -> usize {
+        self.functions.iter().map(|f| f.covered_blocks()).sum()
+    }
+
+    pub fn total_blocks(&self) -> usize {
+        self.functions.iter().map(|f| f.counters.len()).sum()
+    }
+}
+
+/// coverage of every contract which ran with code coverage enabled
+#[derive(Clone, Debug, Default)]
+pub struct CoverageReport {
+    pub contracts: BTreeMap<String, ContractCoverage>,
+}
+
+impl CoverageReport {
+    /// lcov tracefile with one record per contract
+    /// profiles carry no source locations, so functions are keyed by their wasm index
+    /// and blocks are reported as branches of the function
+    pub fn to_lcov(&self) -> String {
+        let mut out = String::new();
+        for contract in self.contracts.values() {
+            writeln!(out, "TN:").unwrap();
+            writeln!(out, "SF:{}", contract.address).unwrap();
+            for f in contract.functions.iter() {
+                writeln!(out, "FN:{},{}", f.wasm_index.unwrap_or(0), f.name).unwrap();
+            }
+            for f in contract.functions.iter() {
+                writeln!(out, "FNDA:{},{}", f.hits(), f.name).unwrap();
+            }
+            writeln!(out, "FNF:{}", contract.functions.len()).unwrap();
+            writeln!(out, "FNH:{}", contract.covered_functions()).unwrap();
+            for f in contract.functions.iter() {
+                let line = f.wasm_index.unwrap_or(0);
+                for (block, count) in f.counters.iter().enumerate() {
+                    writeln!(out, "BRDA:{},0,{},{}", line, block, count).unwrap();
+                }
+            }
+            writeln!(out, "BRF:{}", contract.total_blocks()).unwrap();
+            writeln!(out, "BRH:{}", contract.covered_blocks()).unwrap();
+            writeln!(out, "end_of_record").unwrap();
+        }
+        out
+    }
+
+    /// standalone html page summarizing function and block coverage
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        out += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+        out += "<title>Coverage report</title>\n<style>\n";
+        out += "body { font-family: sans-serif; }\n";
+        out += "table { border-collapse: collapse; margin-bottom: 2em; }\n";
+        out += "td, th { border: 1px solid #ccc; padding: 2px 8px; text-align: left; }\n";
+        out += ".hit { background: #dfd; }\n.miss { background: #fdd; }\n";
+        out += "</style>\n</head>\n<body>\n<h1>Coverage report</h1>\n";
+        for contract in self.contracts.values() {
+            writeln!(
+                out,
+                "<h2>{}</h2>\n<p>{} runs, {}/{} functions, {}/{} blocks covered</p>",
+                html_escape(&contract.address),
+                contract.runs,
+                contract.covered_functions(),
+                contract.functions.len(),
+                contract.covered_blocks(),
+                contract.total_blocks()
+            )
+            .unwrap();
+            out +=
+                "<table>\n<tr><th>index</th><th>function</th><th>hits</th><th>blocks</th></tr>\n";
+            for f in contract.functions.iter() {
+                writeln!(
+                    out,
+                    "<tr class=\"{}\"><td>{}</td><td title=\"{}\">{}</td><td>{}</td><td>{}/{}</td></tr>",
+                    if f.hits() > 0 { "hit" } else { "miss" },
+                    f.wasm_index.map(|i| i.to_string()).unwrap_or_default(),
+                    html_escape(&f.name),
+                    html_escape(&f.demangled_name()),
+                    f.hits(),
+                    f.covered_blocks(),
+                    f.counters.len()
+                )
+                .unwrap();
+            }
+            out += "</table>\n";
+        }
+        out += "</body>\n</html>\n";
+        out
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// function indices by symbol name, read from the name section of the module
+pub fn wasm_function_indices(wasm_code: &[u8]) -> Result<HashMap<String, u32>, Error> {
+    let mut indices = HashMap::new();
+    for payload in Parser::new(0).parse_all(wasm_code) {
+        let payload = payload.map_err(Error::format_error)?;
+        if let Payload::CustomSection {
+            name: "name",
+            data,
+            data_offset,
+            ..
+        } = payload
+        {
+            let mut reader =
+                NameSectionReader::new(data, data_offset).map_err(Error::format_error)?;
+            while !reader.eof() {
+                if let Name::Function(map) = reader.read().map_err(Error::format_error)? {
+                    let mut map = map.get_map().map_err(Error::format_error)?;
+                    for _ in 0..map.get_count() {
+                        let naming = map.read().map_err(Error::format_error)?;
+                        indices.insert(naming.name.to_string(), naming.index);
+                    }
+                }
+            }
+        }
+    }
+    Ok(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wasm_function_indices, ContractCoverage, CoverageReport};
+    use crate::coverage::profraw::{parse_profraw, tests::build_profraw};
+
+    const WASM: &str = r#"(module
+        (func $execute)
+        (func $query)
+        (func $helper))"#;
+
+    #[test]
+    fn test_coverage_report() {
+        let wasm = wat::parse_str(WASM).unwrap();
+        let indices = wasm_function_indices(&wasm).unwrap();
+        assert_eq!(indices.get("query"), Some(&1));
+
+        let profile = build_profraw(&[
+            ("execute", vec![2, 1, 0]),
+            ("src/lib.rs;query", vec![0]),
+            ("missing", vec![1]),
+        ]);
+        let counters = parse_profraw(&profile).unwrap();
+        let coverage = ContractCoverage::new("wasm1contract", 1, counters, Some(&wasm)).unwrap();
+        assert_eq!(coverage.functions[0].name, "missing");
+        assert_eq!(coverage.functions[0].wasm_index, None);
+        assert_eq!(coverage.functions[1].wasm_index, Some(0));
+        assert_eq!(coverage.functions[2].wasm_index, Some(1));
+        assert_eq!(coverage.functions[2].demangled_name(), "query");
+        assert_eq!(coverage.covered_functions(), 2);
+        assert_eq!(coverage.covered_blocks(), 3);
+        assert_eq!(coverage.total_blocks(), 5);
+
+        let mut report = CoverageReport::default();
+        report
+            .contracts
+            .insert("wasm1contract".to_string(), coverage);
+        let lcov = report.to_lcov();
+        assert!(lcov.contains("SF:wasm1contract\n"));
+        assert!(lcov.contains("FNDA:2,execute\n"));
+        assert!(lcov.contains("BRDA:0,0,2,0\n"));
+        assert!(lcov.contains("FNH:2\nBRDA"));
+        assert!(lcov.ends_with("BRH:3\nend_of_record\n"));
+        let html = report.to_html();
+        assert!(html.contains("2/3 functions, 3/5 blocks covered"));
+    }
+}
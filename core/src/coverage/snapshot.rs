@@ -0,0 +1,205 @@
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use super::profraw::{merge_profiles, same_function, FunctionCounters};
+use crate::Error;
+
+// coverage files start with the magic and the format version, followed by the zlib compressed
+// bincode of the snapshot
+const SNAPSHOT_MAGIC: &[u8] = b"cwscov";
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// merged counters of a single contract
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractCounters {
+    /// number of coverage dumps which were merged
+    pub runs: u64,
+    pub functions: Vec<FunctionCounters>,
+}
+
+/// merged coverage of every contract by address. Snapshots of several models, e.g. the shards
+/// of a distributed fuzzing campaign, can be merged, compared and saved to compact files
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverageSnapshot {
+    pub contracts: BTreeMap<String, ContractCounters>,
+}
+
+/// coverage a contract reached in a run which the baseline did not reach
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContractDiff {
+    /// functions entered for the first time, named by the hex hash of their name if unnamed
+    pub new_functions: Vec<String>,
+    /// blocks executed for the first time, including those of new_functions
+    pub new_blocks: usize,
+}
+
+/// new coverage by contract address, contracts without new coverage are left out
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageDiff {
+    pub contracts: BTreeMap<String, ContractDiff>,
+}
+
+impl CoverageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.contracts.is_empty()
+    }
+}
+
+impl CoverageSnapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add the profile of a single coverage dump of the contract at address
+    pub fn add_profile(&mut self, address: &str, profile: Vec<FunctionCounters>) {
+        let contract = self.contracts.entry(address.to_string()).or_default();
+        contract.runs += 1;
+        contract.functions = merge_profiles(&[std::mem::take(&mut contract.functions), profile]);
+    }
+
+    /// sum up the counters of other into self
+    pub fn merge(&mut self, other: &CoverageSnapshot) {
+        for (address, counters) in other.contracts.iter() {
+            let contract = self.contracts.entry(address.clone()).or_default();
+            contract.runs += counters.runs;
+            contract.functions = merge_profiles(&[
+                std::mem::take(&mut contract.functions),
+                counters.functions.clone(),
+            ]);
+        }
+    }
+
+    /// the functions and blocks self covers which baseline does not
+    pub fn diff(&self, baseline: &CoverageSnapshot) -> CoverageDiff {
+        let mut diff = CoverageDiff::default();
+        for (address, counters) in self.contracts.iter() {
+            let old_functions = baseline
+                .contracts
+                .get(address)
+                .map_or(&[][..], |c| c.functions.as_slice());
+            let mut contract = ContractDiff::default();
+            for function in counters.functions.iter() {
+                // same_function implies that both have as many counters
+                let old = old_functions.iter().find(|f| same_function(f, function));
+                let covered_before = |block: usize| old.map_or(false, |f| f.counters[block] > 0);
+                contract.new_blocks += function
+                    .counters
+                    .iter()
+                    .enumerate()
+                    .filter(|(block, count)| **count > 0 && !covered_before(*block))
+                    .count();
+                // the first counter is the function entry
+                if function.counters.first().map_or(false, |c| *c > 0) && !covered_before(0) {
+                    let name = function
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("{:016x}", function.name_hash));
+                    contract.new_functions.push(name);
+                }
+            }
+            if contract.new_blocks > 0 {
+                diff.contracts.insert(address.clone(), contract);
+            }
+        }
+        diff
+    }
+
+    /// compact binary encoding, which from_bytes reads back
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let serialized = bincode::serialize(self).map_err(Error::format_error)?;
+        let mut out = SNAPSHOT_MAGIC.to_vec();
+        out.push(SNAPSHOT_VERSION);
+        let mut encoder = ZlibEncoder::new(out, Compression::best());
+        encoder.write_all(&serialized)?;
+        Ok(encoder.finish()?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let compressed = data
+            .strip_prefix(SNAPSHOT_MAGIC)
+            .ok_or_else(|| Error::format_error("not a coverage snapshot"))?;
+        match compressed.split_first() {
+            Some((&SNAPSHOT_VERSION, compressed)) => {
+                let mut serialized = Vec::new();
+                ZlibDecoder::new(compressed)
+                    .read_to_end(&mut serialized)
+                    .map_err(|e| Error::format_error(format!("coverage snapshot: {}", e)))?;
+                bincode::deserialize(&serialized).map_err(Error::format_error)
+            }
+            Some((version, _)) => Err(Error::format_error(format!(
+                "coverage snapshot of unsupported version {}, expected {}",
+                version, SNAPSHOT_VERSION
+            ))),
+            None => Err(Error::format_error("coverage snapshot: missing version")),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        fs::write(path, self.to_bytes()?).map_err(Error::from)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CoverageSnapshot, SNAPSHOT_MAGIC};
+    use crate::coverage::profraw::name_hash;
+    use crate::coverage::FunctionCounters;
+
+    fn function(name: &str, func_hash: u64, counters: Vec<u64>) -> FunctionCounters {
+        FunctionCounters {
+            name_hash: name_hash(name),
+            func_hash,
+            name: Some(name.to_string()),
+            counters,
+        }
+    }
+
+    #[test]
+    fn test_coverage_snapshot() {
+        let mut shard_a = CoverageSnapshot::new();
+        shard_a.add_profile("wasm1contract", vec![function("execute", 1, vec![1, 0, 0])]);
+        shard_a.add_profile("wasm1contract", vec![function("execute", 1, vec![1, 2, 0])]);
+        let mut shard_b = CoverageSnapshot::new();
+        shard_b.add_profile(
+            "wasm1contract",
+            vec![
+                function("execute", 1, vec![1, 0, 3]),
+                function("query", 2, vec![1]),
+            ],
+        );
+        shard_b.add_profile("wasm1other", vec![function("execute", 1, vec![0, 0])]);
+
+        let mut merged = shard_a.clone();
+        merged.merge(&shard_b);
+        let contract = &merged.contracts["wasm1contract"];
+        assert_eq!(contract.runs, 3);
+        assert_eq!(contract.functions[0].counters, vec![3, 2, 3]);
+        assert_eq!(contract.functions[1].counters, vec![1]);
+
+        let diff = merged.diff(&shard_a);
+        assert_eq!(diff.contracts.len(), 1);
+        assert_eq!(diff.contracts["wasm1contract"].new_functions, vec!["query"]);
+        assert_eq!(diff.contracts["wasm1contract"].new_blocks, 2);
+        assert!(shard_a.diff(&merged).is_empty());
+        assert!(merged.diff(&merged).is_empty());
+
+        let bytes = merged.to_bytes().unwrap();
+        assert!(bytes.starts_with(SNAPSHOT_MAGIC));
+        assert_eq!(CoverageSnapshot::from_bytes(&bytes).unwrap(), merged);
+        assert!(CoverageSnapshot::from_bytes(&bytes[1..]).is_err());
+        let mut newer = bytes.clone();
+        newer[SNAPSHOT_MAGIC.len()] += 1;
+        let err = CoverageSnapshot::from_bytes(&newer).unwrap_err();
+        assert!(err.to_string().contains("unsupported version"));
+    }
+}
@@ -1,31 +1,113 @@
-use std::fmt;
+use cosmwasm_std::{Addr, StdError};
+use cosmwasm_vm::VmError;
+use thiserror::Error;
 
-#[derive(Debug)]
+/// the error an Error was caused by, kept so that Error::source can be walked down to it
+pub type ErrorSource = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Errors of the simulator itself, i.e. failures of the host, carry a message and the error they
+/// were caused by if any. Contracts which returned an error or which the vm failed to run
+/// are told apart by ContractError and ContractVmError, with the contract they are about
+#[derive(Debug, Error)]
 pub enum Error {
-    TokioError(String),
-    RpcError(String),
-    HttpError(String),
+    #[error("tokio error: {0}")]
+    TokioError(String, #[source] Option<ErrorSource>),
+    #[error("RPC error: {0}")]
+    RpcError(String, #[source] Option<ErrorSource>),
+    #[error("HTTP error: {0}")]
+    HttpError(String, #[source] Option<ErrorSource>),
+    #[error("Invalid argument: {0}")]
     InvalidArg(String),
-    TendermintError(String),
-    FormatError(String),
-    VmError(String),
-    StdError(String),
-    IoError(String),
+    #[error("tendermint error: {0}")]
+    TendermintError(String, #[source] Option<ErrorSource>),
+    #[error("format error: {0}")]
+    FormatError(String, #[source] Option<ErrorSource>),
+    /// the vm failed outside of a contract call, e.g. to compile or instantiate a module
+    #[error("vm error: {0}")]
+    VmError(String, #[source] Option<ErrorSource>),
+    #[error("std error: {0}")]
+    StdError(String, #[source] Option<ErrorSource>),
+    #[error("I/O error: {0}")]
+    IoError(String, #[source] Option<ErrorSource>),
+    #[error("bank error: {0}")]
     BankError(String),
+    #[error("backend error: {0}")]
     BackendError(String),
+    #[error("out of gas: {0}")]
+    OutOfGas(String),
+    #[error("build error: {0}")]
+    BuildError(String),
+    /// a sandbox limit of the model was exceeded, see SandboxLimits
+    #[error("limit exceeded: {0}")]
+    LimitExceeded(String),
+    /// a top-level message failed where no existing contract is to blame, e.g. an instantiation or a bank send
+    #[error("message failed: {0}")]
+    MsgFailed(String),
+    /// an assertion on a DebugLog failed, the message describes how the log differs from what was expected
+    #[error("assertion failed: {0}")]
+    AssertionFailed(String),
+    /// the node pruned the state or block at height, earliest is the lowest height it still has if known
+    #[error("{}", pruned_message(.height, .earliest))]
+    HeightPruned { height: u64, earliest: Option<u64> },
+    /// contract returned msg as its error, the simulator itself worked fine
+    #[error("contract {contract}{} returned an error: {msg}", code_id_suffix(.code_id))]
+    ContractError {
+        contract: Addr,
+        code_id: Option<u64>,
+        msg: String,
+    },
+    /// the vm failed to run a call of contract, e.g. because the contract trapped.
+    /// wasm_backtrace holds the functions it trapped in if backtraces are enabled
+    #[error(
+        "vm error in contract {contract}{}: {source}{}",
+        code_id_suffix(.code_id),
+        backtrace_suffix(.wasm_backtrace)
+    )]
+    ContractVmError {
+        contract: Addr,
+        code_id: Option<u64>,
+        source: VmError,
+        wasm_backtrace: Option<String>,
+    },
+}
+
+fn pruned_message(height: &u64, earliest: &Option<u64>) -> String {
+    match earliest {
+        Some(earliest) => format!(
+            "height {} is pruned by the node, the earliest available height is {}",
+            height, earliest
+        ),
+        None => format!(
+            "height {} is pruned by the node, fork from a later height or use an archive node",
+            height
+        ),
+    }
+}
+
+fn code_id_suffix(code_id: &Option<u64>) -> String {
+    code_id
+        .map(|code_id| format!(" (code id {})", code_id))
+        .unwrap_or_default()
+}
+
+fn backtrace_suffix(wasm_backtrace: &Option<String>) -> String {
+    wasm_backtrace
+        .as_ref()
+        .map(|b| format!("\nwasm backtrace:\n{}", b))
+        .unwrap_or_default()
 }
 
 impl Error {
     pub fn tokio_error<T: ToString>(msg: T) -> Self {
-        Self::TokioError(msg.to_string())
+        Self::TokioError(msg.to_string(), None)
     }
 
     pub fn rpc_error<T: ToString>(msg: T) -> Self {
-        Self::RpcError(msg.to_string())
+        Self::RpcError(msg.to_string(), None)
     }
 
     pub fn http_error<T: ToString>(msg: T) -> Self {
-        Self::HttpError(msg.to_string())
+        Self::HttpError(msg.to_string(), None)
     }
 
     pub fn invalid_argument<T: ToString>(msg: T) -> Self {
@@ -33,23 +115,23 @@ impl Error {
     }
 
     pub fn tendermint_error<T: ToString>(msg: T) -> Self {
-        Self::TendermintError(msg.to_string())
+        Self::TendermintError(msg.to_string(), None)
     }
 
     pub fn format_error<T: ToString>(msg: T) -> Self {
-        Self::FormatError(msg.to_string())
+        Self::FormatError(msg.to_string(), None)
     }
 
     pub fn vm_error<T: ToString>(msg: T) -> Self {
-        Self::VmError(msg.to_string())
+        Self::VmError(msg.to_string(), None)
     }
 
     pub fn std_error<T: ToString>(msg: T) -> Self {
-        Self::StdError(msg.to_string())
+        Self::StdError(msg.to_string(), None)
     }
 
     pub fn io_error<T: ToString>(msg: T) -> Self {
-        Self::IoError(msg.to_string())
+        Self::IoError(msg.to_string(), None)
     }
 
     pub fn bank_error<T: ToString>(msg: T) -> Self {
@@ -59,45 +141,138 @@ impl Error {
     pub fn backend_error<T: ToString>(msg: T) -> Self {
         Self::BackendError(msg.to_string())
     }
-}
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    pub fn out_of_gas<T: ToString>(msg: T) -> Self {
+        Self::OutOfGas(msg.to_string())
+    }
+
+    pub fn build_error<T: ToString>(msg: T) -> Self {
+        Self::BuildError(msg.to_string())
+    }
+
+    pub fn limit_exceeded<T: ToString>(msg: T) -> Self {
+        Self::LimitExceeded(msg.to_string())
+    }
+
+    pub fn msg_failed<T: ToString>(msg: T) -> Self {
+        Self::MsgFailed(msg.to_string())
+    }
+
+    pub fn assertion_failed<T: ToString>(msg: T) -> Self {
+        Self::AssertionFailed(msg.to_string())
+    }
+
+    pub fn height_pruned(height: u64, earliest: Option<u64>) -> Self {
+        Self::HeightPruned { height, earliest }
+    }
+
+    pub fn contract_error<T: ToString>(contract: &Addr, code_id: Option<u64>, msg: T) -> Self {
+        Self::ContractError {
+            contract: contract.clone(),
+            code_id,
+            msg: msg.to_string(),
+        }
+    }
+
+    pub fn contract_vm_error(contract: &Addr, code_id: Option<u64>, source: VmError) -> Self {
+        Self::ContractVmError {
+            contract: contract.clone(),
+            code_id,
+            source,
+            wasm_backtrace: None,
+        }
+    }
+
+    /// the contract the error is about, if it is about a contract call
+    pub fn contract(&self) -> Option<&Addr> {
         match self {
-            Self::TokioError(s) => {
-                writeln!(f, "tokio error: {}", s)?;
-            }
-            Self::RpcError(s) => {
-                writeln!(f, "RPC error: {}", s)?;
-            }
-            Self::HttpError(s) => {
-                writeln!(f, "HTTP error: {}", s)?;
-            }
-            Self::InvalidArg(s) => {
-                writeln!(f, "Invalid argument: {}", s)?;
-            }
-            Self::TendermintError(s) => {
-                writeln!(f, "tendermint error: {}", s)?;
-            }
-            Self::FormatError(s) => {
-                writeln!(f, "format error: {}", s)?;
-            }
-            Self::VmError(s) => {
-                writeln!(f, "vm error: {}", s)?;
-            }
-            Self::StdError(s) => {
-                writeln!(f, "std error: {}", s)?;
-            }
-            Self::IoError(s) => {
-                writeln!(f, "I/O error: {}", s)?;
-            }
-            Self::BankError(s) => {
-                writeln!(f, "bank error: {}", s)?;
-            }
-            Self::BackendError(s) => {
-                writeln!(f, "backend error: {}", s)?;
+            Self::ContractError { contract, .. } | Self::ContractVmError { contract, .. } => {
+                Some(contract)
             }
+            _ => None,
         }
-        Ok(())
+    }
+
+    /// whether the error was returned by a contract, rather than caused by the simulator or the vm
+    pub fn is_contract_error(&self) -> bool {
+        matches!(self, Self::ContractError { .. })
+    }
+}
+
+impl From<VmError> for Error {
+    fn from(e: VmError) -> Self {
+        Self::VmError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+impl From<StdError> for Error {
+    fn from(e: StdError) -> Self {
+        Self::StdError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::FormatError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+impl From<tendermint_rpc::Error> for Error {
+    fn from(e: tendermint_rpc::Error) -> Self {
+        Self::RpcError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+impl From<tendermint::Error> for Error {
+    fn from(e: tendermint::Error) -> Self {
+        Self::TendermintError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Self::TokioError(e.to_string(), Some(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+    use cosmwasm_vm::{BackendError, VmError};
+    use std::error::Error as _;
+
+    use super::Error;
+
+    fn vm_error() -> VmError {
+        VmError::from(BackendError::Unknown {
+            msg: "boom".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_source_chain() {
+        let e = Error::from(vm_error());
+        assert!(e.source().unwrap().is::<VmError>());
+        assert!(Error::vm_error("no source").source().is_none());
+
+        let contract = Addr::unchecked("contract");
+        let e = Error::contract_vm_error(&contract, Some(5), vm_error());
+        assert_eq!(e.contract(), Some(&contract));
+        assert!(e.source().unwrap().is::<VmError>());
+        assert!(e.to_string().contains("contract contract (code id 5)"));
+        assert!(!e.is_contract_error());
+
+        let e = Error::contract_error(&contract, None, "unauthorized");
+        assert!(e.is_contract_error());
+        assert_eq!(
+            e.to_string(),
+            "contract contract returned an error: unauthorized"
+        );
     }
 }
@@ -1,38 +1,200 @@
 use bech32::{self, FromBase32, ToBase32, Variant};
+use cosmwasm_std::Addr;
 use cosmwasm_vm::{BackendApi, BackendError, BackendResult, GasInfo};
+use sha2::{Digest, Sha256};
 
 use crate::Error;
 
 const BECH32_PREFIX_MAX_LEN: usize = 10;
+/// bech32 prefixes an api accepts besides its own, see RpcMockApi::with_prefix
+pub const MAX_EXTRA_PREFIXES: usize = 8;
 
-//mock api
-#[derive(Copy, Clone)]
-pub struct RpcMockApi {
+/// a bech32 prefix and the longest canonical address it is used with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Bech32Prefix {
+    prefix: [u8; BECH32_PREFIX_MAX_LEN],
+    prefix_len: usize,
     canonical_length: usize,
-    bech32_prefix: [u8; BECH32_PREFIX_MAX_LEN],
-    bech32_prefix_len: usize,
+}
+
+impl Bech32Prefix {
+    fn new(prefix: &str, canonical_length: usize) -> Result<Self, Error> {
+        if prefix.is_empty() || prefix.len() > BECH32_PREFIX_MAX_LEN {
+            return Err(Error::invalid_argument(format!(
+                "bech32 prefix {} must be 1 to {} characters long",
+                prefix, BECH32_PREFIX_MAX_LEN
+            )));
+        }
+        // the human readable part of bech32 is lowercase ascii, see BIP-173
+        if !prefix
+            .bytes()
+            .all(|b| (33..=126).contains(&b) && !b.is_ascii_uppercase())
+        {
+            return Err(Error::invalid_argument(format!(
+                "bech32 prefix {} has invalid characters",
+                prefix
+            )));
+        }
+        if canonical_length == 0 || canonical_length > u8::MAX as usize {
+            return Err(Error::invalid_argument(format!(
+                "canonical address length {} of bech32 prefix {} is not in 1..=255",
+                canonical_length, prefix
+            )));
+        }
+        let mut bytes = [0; BECH32_PREFIX_MAX_LEN];
+        bytes[..prefix.len()].copy_from_slice(prefix.as_bytes());
+        Ok(Bech32Prefix {
+            prefix: bytes,
+            prefix_len: prefix.len(),
+            canonical_length,
+        })
+    }
+
+    fn as_str(&self) -> &str {
+        // only built from a str
+        std::str::from_utf8(&self.prefix[..self.prefix_len]).unwrap()
+    }
+}
+
+/// mock api of a chain whose addresses have bech32_prefix, which may also accept the addresses of
+/// other prefixes, e.g. for contracts checking the addresses of an ibc counterparty.
+/// The canonical addresses of other prefixes are the bytes of the address, followed by as many
+/// zeros as the canonical length of the chain and the index of the prefix. They are longer than the
+/// canonical addresses of the chain, so that they convert back to the same human address
+#[derive(Copy, Clone, Debug)]
+pub struct RpcMockApi {
+    prefix: Bech32Prefix,
+    extra_prefixes: [Option<Bech32Prefix>; MAX_EXTRA_PREFIXES],
 }
 
 impl RpcMockApi {
     pub fn new(canonical_length: usize, bech32_prefix_str: &str) -> Result<Self, Error> {
-        let bech32_prefix_len = bech32_prefix_str.len();
-        if bech32_prefix_len > BECH32_PREFIX_MAX_LEN {
-            Err(Error::invalid_argument(&format!(
-                "bech32 prefix {} is too long",
-                bech32_prefix_str
-            )))
-        } else {
-            let mut bech32_prefix = [0; BECH32_PREFIX_MAX_LEN];
-            let bech32_prefix_vec = Vec::from(bech32_prefix_str);
-            bech32_prefix[..bech32_prefix_len]
-                .copy_from_slice(&bech32_prefix_vec[..bech32_prefix_len]);
-            Ok(RpcMockApi {
-                canonical_length,
-                bech32_prefix,
-                bech32_prefix_len,
-            })
+        Ok(RpcMockApi {
+            prefix: Bech32Prefix::new(bech32_prefix_str, canonical_length)?,
+            extra_prefixes: [None; MAX_EXTRA_PREFIXES],
+        })
+    }
+
+    /// also accepts addresses with prefix, whose canonical addresses are at most canonical_length long
+    pub fn with_prefix(mut self, prefix: &str, canonical_length: usize) -> Result<Self, Error> {
+        let extra = Bech32Prefix::new(prefix, canonical_length)?;
+        if prefix == self.prefix.as_str() {
+            return Err(Error::invalid_argument(format!(
+                "{} is the bech32 prefix of the chain",
+                prefix
+            )));
+        }
+        let slot = self
+            .extra_prefixes
+            .iter_mut()
+            .find(|p| p.map_or(true, |p| p.as_str() == prefix))
+            .ok_or_else(|| {
+                Error::invalid_argument(format!(
+                    "at most {} other bech32 prefixes are supported",
+                    MAX_EXTRA_PREFIXES
+                ))
+            })?;
+        *slot = Some(extra);
+        Ok(self)
+    }
+
+    pub fn bech32_prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// the other prefixes accepted, with their canonical lengths
+    pub fn extra_prefixes(&self) -> Vec<(String, usize)> {
+        self.extra_prefixes
+            .iter()
+            .flatten()
+            .map(|p| (p.as_str().to_string(), p.canonical_length))
+            .collect()
+    }
+
+    fn canonicalize(&self, human: &str) -> Result<Vec<u8>, String> {
+        let (hrp, data) = decode_bech32(human)?;
+        if hrp == self.prefix.as_str() {
+            check_length(&data, self.prefix.canonical_length)?;
+            return Ok(data);
         }
+        let (index, extra) = self
+            .extra_prefixes
+            .iter()
+            .enumerate()
+            .find_map(|(i, p)| p.filter(|p| p.as_str() == hrp).map(|p| (i, p)))
+            .ok_or_else(|| {
+                format!(
+                    "Invalid input: human address has invalid bech32 prefix: {}",
+                    hrp
+                )
+            })?;
+        check_length(&data, extra.canonical_length)?;
+        let mut canonical = data;
+        canonical.resize(canonical.len() + self.prefix.canonical_length, 0);
+        canonical.push(index as u8);
+        Ok(canonical)
     }
+
+    fn humanize(&self, canonical: &[u8]) -> Result<String, String> {
+        if canonical.len() <= self.prefix.canonical_length {
+            return canonical_to_human(
+                canonical,
+                self.prefix.as_str(),
+                self.prefix.canonical_length,
+            );
+        }
+        let invalid = || "Invalid input: canonical address length not correct".to_string();
+        let (index, rest) = canonical.split_last().ok_or_else(invalid)?;
+        let extra = self
+            .extra_prefixes
+            .get(*index as usize)
+            .copied()
+            .flatten()
+            .ok_or_else(invalid)?;
+        let data_len = rest
+            .len()
+            .checked_sub(self.prefix.canonical_length)
+            .filter(|len| *len > 0)
+            .ok_or_else(invalid)?;
+        let (data, padding) = rest.split_at(data_len);
+        if padding.iter().any(|b| *b != 0) {
+            return Err(invalid());
+        }
+        canonical_to_human(data, extra.as_str(), extra.canonical_length)
+    }
+}
+
+fn decode_bech32(human: &str) -> Result<(String, Vec<u8>), String> {
+    let (hrp, base32_vec, variant) = bech32::decode(human).map_err(|e| {
+        format!(
+            "Invalid input: human address is not bech32 decodable: {}",
+            e
+        )
+    })?;
+    if variant != Variant::Bech32 {
+        return Err("Invalid input: human address is bech32m encoded".to_string());
+    }
+    let data = Vec::<u8>::from_base32(&base32_vec)
+        .map_err(|e| format!("Invalid input: human address has invalid data: {}", e))?;
+    Ok((hrp, data))
+}
+
+fn check_length(canonical: &[u8], canonical_length: usize) -> Result<(), String> {
+    if canonical.is_empty() || canonical.len() > canonical_length {
+        Err(format!(
+            "Invalid input: canonical address length {} is not in 1..={}",
+            canonical.len(),
+            canonical_length
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// the bech32 prefix of address and the length of its canonical address
+pub fn address_prefix(address: &str) -> Result<(String, usize), Error> {
+    let (hrp, data) = decode_bech32(address).map_err(Error::invalid_argument)?;
+    Ok((hrp, data.len()))
 }
 
 pub fn human_to_canonical(human: &str, bech32_prefix: &str) -> Result<Vec<u8>, String> {
@@ -80,24 +242,75 @@ pub fn canonical_to_human(
     }
 }
 
+/// address of the account of a cosmos-sdk module, e.g. distribution
+pub fn module_address(name: &str, bech32_prefix: &str) -> Addr {
+    let hash = Sha256::digest(name.as_bytes());
+    // module addresses are always 20 bytes long
+    Addr::unchecked(canonical_to_human(&hash[..20], bech32_prefix, 20).unwrap())
+}
+
 impl BackendApi for RpcMockApi {
     fn canonical_address(&self, human: &str) -> BackendResult<Vec<u8>> {
-        let bech32_prefix = unsafe {
-            String::from_utf8_unchecked(self.bech32_prefix[0..self.bech32_prefix_len].to_vec())
-        };
-        match human_to_canonical(human, bech32_prefix.as_str()) {
+        match self.canonicalize(human) {
             Ok(c) => (Ok(c), GasInfo::free()),
             Err(e) => (Err(BackendError::user_err(e)), GasInfo::free()),
         }
     }
 
     fn human_address(&self, canonical: &[u8]) -> BackendResult<String> {
-        let bech32_prefix = unsafe {
-            String::from_utf8_unchecked(self.bech32_prefix[0..self.bech32_prefix_len].to_vec())
-        };
-        match canonical_to_human(canonical, bech32_prefix.as_str(), self.canonical_length) {
+        match self.humanize(canonical) {
             Ok(h) => (Ok(h), GasInfo::free()),
             Err(e) => (Err(BackendError::user_err(e)), GasInfo::free()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_vm::BackendApi;
+
+    use super::{canonical_to_human, RpcMockApi, MAX_EXTRA_PREFIXES};
+
+    #[test]
+    fn test_extra_prefixes() {
+        let api = RpcMockApi::new(32, "wasm")
+            .unwrap()
+            .with_prefix("osmo", 20)
+            .unwrap();
+        let osmo = canonical_to_human(&[7; 20], "osmo", 20).unwrap();
+        let wasm = canonical_to_human(&[7; 20], "wasm", 32).unwrap();
+
+        let canonical = api.canonical_address(&wasm).0.unwrap();
+        assert_eq!(canonical, vec![7; 20]);
+        assert_eq!(api.human_address(&canonical).0.unwrap(), wasm);
+        // addresses of other prefixes convert back to themselves, as addr_validate requires
+        let canonical = api.canonical_address(&osmo).0.unwrap();
+        assert_eq!(canonical.len(), 20 + 32 + 1);
+        assert_eq!(&canonical[..20], &[7; 20][..]);
+        assert_eq!(api.human_address(&canonical).0.unwrap(), osmo);
+
+        // too long for the prefix
+        let long = canonical_to_human(&[7; 32], "osmo", 32).unwrap();
+        assert!(api.canonical_address(&long).0.is_err());
+        let juno = canonical_to_human(&[7; 20], "juno", 20).unwrap();
+        assert!(api.canonical_address(&juno).0.is_err());
+        assert!(api.human_address(&[1; 34]).0.is_err());
+        assert!(RpcMockApi::new(32, "wasm")
+            .unwrap()
+            .canonical_address(&osmo)
+            .0
+            .is_err());
+
+        assert!(api.with_prefix("wasm", 20).is_err());
+        assert!(api.with_prefix("Osmo", 20).is_err());
+        assert!(api.with_prefix("osmo", 0).is_err());
+        // prefixes given again replace their length
+        let api = api.with_prefix("osmo", 32).unwrap();
+        assert_eq!(api.extra_prefixes(), vec![("osmo".to_string(), 32)]);
+        let mut api = api;
+        for i in 1..MAX_EXTRA_PREFIXES {
+            api = api.with_prefix(&format!("p{}", i), 20).unwrap();
+        }
+        assert!(api.with_prefix("juno", 20).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use cosmwasm_std::{Addr, Event};
+
+use crate::{DebugLog, Error};
+
+// events shown when none matches, the closest ones first
+const MAX_CANDIDATES: usize = 5;
+
+fn attribute_values<'a>(event: &'a Event, key: &str) -> Vec<&'a str> {
+    event
+        .attributes
+        .iter()
+        .filter(|a| a.key == key)
+        .map(|a| a.value.as_str())
+        .collect()
+}
+
+// how event differs from the expected attributes, one line per attribute it lacks.
+// matches tells whether the value of an attribute of event is the expected one
+fn attribute_mismatches(
+    event: &Event,
+    expected: &[(&str, &str)],
+    matches: &dyn Fn(&str, &str, &str) -> bool,
+) -> Vec<String> {
+    expected
+        .iter()
+        .filter_map(|(key, value)| {
+            let values = attribute_values(event, key);
+            if values.iter().any(|v| matches(key, value, v)) {
+                None
+            } else if values.is_empty() {
+                Some(format!("{} is missing, expected {:?}", key, value))
+            } else {
+                Some(format!(
+                    "{}: expected {:?}, got {:?}",
+                    key,
+                    value,
+                    values.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+// whether the sdk formatted coins, e.g. 100uatom,5ucosm, hold coin
+fn coins_contain(coins: &str, coin: &str) -> bool {
+    coins.split(',').any(|c| c == coin)
+}
+
+impl DebugLog {
+    /// the first event of type ty with every attribute of attributes, which may be a subset of
+    /// its attributes. Fails with how the closest events of type ty differ if there is none
+    pub fn assert_event(&self, ty: &str, attributes: &[(&str, &str)]) -> Result<&Event, Error> {
+        self.find_event(ty, attributes, &|_, expected, value| expected == value)
+    }
+
+    /// the transfer event of the bank send of amount denom from from to to. A send of several
+    /// denoms matches if it includes amount denom
+    pub fn assert_transfer(
+        &self,
+        from: &Addr,
+        to: &Addr,
+        amount: u128,
+        denom: &str,
+    ) -> Result<&Event, Error> {
+        let coin = format!("{}{}", amount, denom);
+        let attributes = [
+            ("sender", from.as_str()),
+            ("recipient", to.as_str()),
+            ("amount", coin.as_str()),
+        ];
+        self.find_event("transfer", &attributes, &|key, expected, value| {
+            if key == "amount" {
+                coins_contain(value, expected)
+            } else {
+                expected == value
+            }
+        })
+    }
+
+    /// fails unless the transaction failed with an error containing msg
+    pub fn assert_error_contains(&self, msg: &str) -> Result<(), Error> {
+        match &self.err_msg {
+            Some(err_msg) if err_msg.contains(msg) => Ok(()),
+            Some(err_msg) => Err(Error::assertion_failed(format!(
+                "expected an error containing {:?}, got {:?}",
+                msg, err_msg
+            ))),
+            None => Err(Error::assertion_failed(format!(
+                "expected an error containing {:?}, but the transaction succeeded",
+                msg
+            ))),
+        }
+    }
+
+    /// the values of key among the attributes the contracts of the transaction returned, in order
+    pub fn wasm_attributes(&self, key: &str) -> Vec<&str> {
+        self.events_of_type("wasm")
+            .into_iter()
+            .flat_map(|e| attribute_values(e, key))
+            .collect()
+    }
+
+    /// the first value of key among the attributes the contracts of the transaction returned
+    pub fn wasm_attribute(&self, key: &str) -> Option<&str> {
+        self.wasm_attributes(key).into_iter().next()
+    }
+
+    /// the values of key among the attributes contract returned, in order
+    pub fn contract_attributes(&self, contract: &Addr, key: &str) -> Vec<&str> {
+        self.events_of_type("wasm")
+            .into_iter()
+            .filter(|e| attribute_values(e, "_contract_address").contains(&contract.as_str()))
+            .flat_map(|e| attribute_values(e, key))
+            .collect()
+    }
+
+    /// the first value of key among the attributes contract returned
+    pub fn contract_attribute(&self, contract: &Addr, key: &str) -> Option<&str> {
+        self.contract_attributes(contract, key).into_iter().next()
+    }
+
+    fn find_event(
+        &self,
+        ty: &str,
+        attributes: &[(&str, &str)],
+        matches: &dyn Fn(&str, &str, &str) -> bool,
+    ) -> Result<&Event, Error> {
+        let events = self.events_of_type(ty);
+        let mut candidates: Vec<(usize, Vec<String>)> = events
+            .iter()
+            .map(|e| attribute_mismatches(e, attributes, matches))
+            .enumerate()
+            .collect();
+        if let Some((i, _)) = candidates.iter().find(|(_, m)| m.is_empty()) {
+            return Ok(events[*i]);
+        }
+        let expected: Vec<String> = attributes
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        let mut msg = format!("no {} event with {}", ty, expected.join(" "));
+        if events.is_empty() {
+            msg += &self.emitted_types();
+        } else {
+            // sort_by_key is stable, events which differ as much stay in the order they were emitted
+            candidates.sort_by_key(|(_, m)| m.len());
+            msg += &format!(
+                "\n{} {} events were emitted, the closest ones differ in:",
+                events.len(),
+                ty
+            );
+            for (i, mismatches) in candidates.iter().take(MAX_CANDIDATES) {
+                msg += &format!("\n  {} event #{}:", ty, i);
+                for mismatch in mismatches.iter() {
+                    msg += &format!("\n    {}", mismatch);
+                }
+            }
+        }
+        Err(Error::assertion_failed(msg))
+    }
+
+    // the types of the events of the transaction, for failed assertions
+    fn emitted_types(&self) -> String {
+        if let Some(err_msg) = &self.err_msg {
+            return format!(
+                "\nthe transaction failed, so it emitted no events: {}",
+                err_msg
+            );
+        }
+        let mut types: Vec<&str> = Vec::new();
+        for e in self.events.iter() {
+            if !types.contains(&e.event.ty.as_str()) {
+                types.push(&e.event.ty);
+            }
+        }
+        if types.is_empty() {
+            "\nthe transaction emitted no events".to_string()
+        } else {
+            format!(
+                "\nthe transaction emitted events of type {}",
+                types.join(", ")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coin, Addr, Response};
+
+    use crate::fork::events::transfer_events;
+    use crate::{contract_events, DebugLog};
+
+    #[test]
+    fn test_assertions() {
+        let alice = Addr::unchecked("wasm1alice");
+        let bob = Addr::unchecked("wasm1bob");
+        let contract = Addr::unchecked("wasm1contract");
+        let mut log = DebugLog::new();
+        log.append_events(&transfer_events(
+            &alice,
+            &bob,
+            &[coin(5, "uatom"), coin(100, "umlg")],
+        ));
+        let response: Response = Response::new()
+            .add_attribute("action", "swap")
+            .add_attribute("amount", "42");
+        log.append_events(&contract_events(&contract, &response));
+
+        assert!(log.assert_transfer(&alice, &bob, 100, "umlg").is_ok());
+        let err = log
+            .assert_transfer(&bob, &alice, 100, "umlg")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("1 transfer events were emitted"));
+        assert!(err.contains("sender: expected \"wasm1bob\", got \"wasm1alice\""));
+        assert!(log.assert_transfer(&alice, &bob, 10, "umlg").is_err());
+
+        let event = log
+            .assert_event("wasm", &[("action", "swap"), ("amount", "42")])
+            .unwrap();
+        assert_eq!(event.attributes[0].value, contract.as_str());
+        let err = log
+            .assert_event("wasm", &[("action", "swap"), ("sender", "bob")])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("sender is missing"));
+        let err = log.assert_event("wasm-swap", &[]).unwrap_err().to_string();
+        assert!(err.contains("coin_spent, coin_received, transfer, message, wasm"));
+
+        assert_eq!(log.wasm_attribute("amount"), Some("42"));
+        assert_eq!(log.wasm_attributes("action"), vec!["swap"]);
+        assert_eq!(log.contract_attribute(&contract, "action"), Some("swap"));
+        assert_eq!(log.contract_attribute(&alice, "action"), None);
+
+        assert!(log.assert_error_contains("funds").is_err());
+        log.set_err_msg("insufficient funds");
+        assert!(log.assert_error_contains("funds").is_ok());
+        let err = log.assert_error_contains("gas").unwrap_err().to_string();
+        assert!(err.contains("got \"insufficient funds\""));
+    }
+}
@@ -0,0 +1,68 @@
+use prost::Message;
+
+use crate::fork::api::address_prefix;
+use crate::fork::staking::bonded_validators;
+use crate::{CwClientBackend, Error};
+
+// the bech32 prefix query of cosmos.auth.v1beta1, added in cosmos-sdk 0.46
+#[derive(Clone, PartialEq, Message)]
+struct Bech32PrefixRequest {}
+
+#[derive(Clone, PartialEq, Message)]
+struct Bech32PrefixResponse {
+    #[prost(string, tag = "1")]
+    bech32_prefix: String,
+}
+
+/// the account prefix of a validator operator address, which is the account prefix followed by valoper
+fn operator_account_prefix(operator_address: &str) -> Result<String, Error> {
+    let (hrp, _) = address_prefix(operator_address)?;
+    hrp.strip_suffix("valoper")
+        .filter(|prefix| !prefix.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| {
+            Error::format_error(format!(
+                "validator operator address {} does not have a valoper prefix",
+                operator_address
+            ))
+        })
+}
+
+/// the bech32 prefix of the accounts of the chain client serves. Chains before cosmos-sdk 0.46
+/// don't answer it directly, so it is taken from the operator address of a bonded validator
+pub fn detect_bech32_prefix(client: &mut dyn CwClientBackend) -> Result<String, Error> {
+    match client.query_grpc(
+        "/cosmos.auth.v1beta1.Query/Bech32Prefix",
+        &Bech32PrefixRequest {}.encode_to_vec(),
+    ) {
+        Ok(out) => {
+            let response =
+                Bech32PrefixResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+            if !response.bech32_prefix.is_empty() {
+                return Ok(response.bech32_prefix);
+            }
+        }
+        Err(Error::TendermintError(_, _)) => {}
+        Err(e) => return Err(e),
+    }
+    let validators = bonded_validators(client)?;
+    let validator = validators.first().ok_or_else(|| {
+        Error::backend_error("no bonded validator on chain to detect the bech32 prefix from")
+    })?;
+    operator_account_prefix(&validator.address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::operator_account_prefix;
+
+    #[test]
+    fn test_operator_account_prefix() {
+        let operator = "osmovaloper1qqqsyqcyq5rqwzqfpg9scrgwpugpzysn362fsv";
+        assert_eq!(operator_account_prefix(operator).unwrap(), "osmo");
+        let err =
+            operator_account_prefix("osmo1qqqsyqcyq5rqwzqfpg9scrgwpugpzysntdz28t").unwrap_err();
+        assert!(err.to_string().contains("valoper"));
+        assert!(operator_account_prefix("valoper1qqqsyqcyq5rqwzqfpg9scrgwpugpzysnpc56q2").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use cosmwasm_vm::internals::take_wasm_trace;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// a wasm function in the backtrace of a contract which trapped, e.g. because it panicked
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BacktraceFrame {
+    pub func_index: u32,
+    /// demangled name from the name section of the contract, None if it was stripped
+    pub name: Option<String>,
+    /// offset of the instruction in the wasm code
+    pub module_offset: usize,
+}
+
+impl fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "<wasm function {}>", self.func_index)?,
+        }
+        write!(f, " @ {:#x}", self.module_offset)
+    }
+}
+
+/// frames of the last trap of a contract on this thread, innermost first.
+/// Empty if no contract trapped since the last call
+pub fn take_backtrace() -> Vec<BacktraceFrame> {
+    take_wasm_trace()
+        .into_iter()
+        .map(|frame| BacktraceFrame {
+            func_index: frame.func_index,
+            name: frame
+                .function_name
+                .map(|name| format!("{:#}", rustc_demangle::demangle(&name))),
+            module_offset: frame.module_offset,
+        })
+        .collect()
+}
+
+/// one numbered frame per line, innermost first
+pub fn format_backtrace(frames: &[BacktraceFrame]) -> String {
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| format!("{:>4}: {}\n", i, frame))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_backtrace, BacktraceFrame};
+
+    #[test]
+    fn test_format_backtrace() {
+        let frames = vec![
+            BacktraceFrame {
+                func_index: 7,
+                name: Some("test_contract::contract::execute".to_string()),
+                module_offset: 0x1a2b,
+            },
+            BacktraceFrame {
+                func_index: 3,
+                name: None,
+                module_offset: 0x40,
+            },
+        ];
+        assert_eq!(
+            format_backtrace(&frames),
+            "   0: test_contract::contract::execute @ 0x1a2b\n   1: <wasm function 3> @ 0x40\n"
+        );
+    }
+}
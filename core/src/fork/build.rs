@@ -0,0 +1,146 @@
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::Error;
+
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// how build_wasm compiles a contract crate
+#[derive(Debug, Clone, Default)]
+pub struct WasmBuild {
+    /// cargo features enabled on the contract crate
+    pub features: Vec<String>,
+    /// target directory of the build, the one of the contract's workspace if None
+    pub target_dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadata {
+    packages: Vec<CargoPackage>,
+    target_directory: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct CargoPackage {
+    name: String,
+    manifest_path: PathBuf,
+    targets: Vec<CargoTarget>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    name: String,
+    kind: Vec<String>,
+}
+
+fn cargo() -> Command {
+    // cargo sets CARGO for build scripts and tests, so the same toolchain builds the contract
+    Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+
+fn run(command: &mut Command) -> Result<Vec<u8>, Error> {
+    let output = command
+        .output()
+        .map_err(|e| Error::io_error(format!("failed to run cargo: {}", e)))?;
+    if !output.status.success() {
+        return Err(Error::build_error(
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// checks that code looks like a wasm module before it is registered
+pub fn check_wasm(code: &[u8]) -> Result<(), Error> {
+    if !code.starts_with(WASM_MAGIC) {
+        return Err(Error::invalid_argument("code is not a wasm module"));
+    }
+    Ok(())
+}
+
+/// compiles the contract crate at project_dir, which may be a workspace member,
+/// like `cargo wasm` does and returns the path of the wasm file
+pub fn build_wasm(project_dir: &Path, config: &WasmBuild) -> Result<PathBuf, Error> {
+    let manifest_path = project_dir
+        .join("Cargo.toml")
+        .canonicalize()
+        .map_err(|e| Error::io_error(format!("{}: {}", project_dir.display(), e)))?;
+    let metadata = run(cargo()
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .arg("--manifest-path")
+        .arg(&manifest_path))?;
+    let metadata: CargoMetadata = serde_json::from_slice(&metadata).map_err(Error::from)?;
+    let package = metadata
+        .packages
+        .iter()
+        .find(|p| p.manifest_path == manifest_path)
+        .ok_or_else(|| {
+            Error::invalid_argument(format!("no package in {}", project_dir.display()))
+        })?;
+    let lib = package
+        .targets
+        .iter()
+        .find(|t| t.kind.iter().any(|k| k == "cdylib"))
+        .ok_or_else(|| Error::invalid_argument(format!("{} has no cdylib target", package.name)))?;
+
+    let target_dir = config
+        .target_dir
+        .clone()
+        .unwrap_or(metadata.target_directory);
+    let mut build = cargo();
+    build
+        .args(["build", "--release", "--lib", "--target", WASM_TARGET])
+        .arg("--manifest-path")
+        .arg(&manifest_path)
+        .args(["--package", &package.name])
+        .arg("--target-dir")
+        .arg(&target_dir);
+    if !config.features.is_empty() {
+        build.args(["--features", &config.features.join(",")]);
+    }
+    run(&mut build)?;
+
+    let wasm_path = target_dir
+        .join(WASM_TARGET)
+        .join("release")
+        .join(format!("{}.wasm", lib.name));
+    if !wasm_path.is_file() {
+        return Err(Error::build_error(format!(
+            "{} was not produced",
+            wasm_path.display()
+        )));
+    }
+    Ok(wasm_path)
+}
+
+/// reads the wasm file at path
+pub fn read_wasm(path: &Path) -> Result<Vec<u8>, Error> {
+    let code = fs::read(path).map_err(|e| Error::io_error(format!("{}: {}", path.display(), e)))?;
+    check_wasm(&code)?;
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_wasm() {
+        let project_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-contract");
+        // build into OUT_DIR like build.rs, the workspace target directory is locked by cargo test
+        let config = WasmBuild {
+            target_dir: Some(PathBuf::from(env!("OUT_DIR"))),
+            ..Default::default()
+        };
+        let wasm_path = build_wasm(&project_dir, &config).unwrap();
+        assert!(wasm_path.ends_with("wasm32-unknown-unknown/release/test_contract.wasm"));
+        read_wasm(&wasm_path).unwrap();
+
+        assert!(build_wasm(Path::new(env!("OUT_DIR")), &config).is_err());
+        assert!(read_wasm(&project_dir.join("Cargo.toml")).is_err());
+    }
+}
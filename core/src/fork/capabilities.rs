@@ -0,0 +1,97 @@
+use crate::Error;
+use cosmwasm_std::{BankQuery, QueryRequest};
+use cosmwasm_vm::internals::required_capabilities;
+use std::collections::BTreeSet;
+
+/// capabilities advertised by default, the same as wasmd 0.41.
+/// Messages and queries of cosmwasm 1.2 the simulator doesn't know still fail when they are used
+pub const DEFAULT_CAPABILITIES: &[&str] = &[
+    "iterator",
+    "staking",
+    "stargate",
+    "cosmwasm_1_1",
+    "cosmwasm_1_2",
+    "cosmwasm_1_3",
+];
+
+pub fn default_capabilities() -> BTreeSet<String> {
+    DEFAULT_CAPABILITIES.iter().map(|c| c.to_string()).collect()
+}
+
+/// capabilities code requires through its requires_* exports
+pub fn code_capabilities(code: &[u8]) -> Result<BTreeSet<String>, Error> {
+    let required = required_capabilities(code).map_err(Error::from)?;
+    Ok(required.into_iter().collect())
+}
+
+/// fails with the capabilities code requires which are not available, like wasmd on upload
+pub fn check_capabilities(code: &[u8], available: &BTreeSet<String>) -> Result<(), Error> {
+    let missing: Vec<String> = code_capabilities(code)?
+        .difference(available)
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let available: Vec<&str> = available.iter().map(|c| c.as_str()).collect();
+    Err(Error::invalid_argument(format!(
+        "contract requires capabilities the chain does not advertise: {} (advertised: {}), see Model::set_capabilities",
+        missing.join(", "),
+        available.join(", ")
+    )))
+}
+
+/// capability the chain must advertise for contracts to make request
+pub(crate) fn query_capability(request: &QueryRequest<()>) -> Option<&'static str> {
+    match request {
+        QueryRequest::Bank(BankQuery::Supply { .. }) => Some("cosmwasm_1_1"),
+        QueryRequest::Staking(_) => Some("staking"),
+        QueryRequest::Stargate { .. } | QueryRequest::Ibc(_) => Some("stargate"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{BankQuery, QueryRequest, StakingQuery};
+
+    use super::{check_capabilities, code_capabilities, default_capabilities, query_capability};
+
+    #[test]
+    fn test_check_capabilities() {
+        let code = wat::parse_str(
+            r#"(module
+            (func)
+            (export "requires_iterator" (func 0))
+            (export "requires_cosmwasm_1_2" (func 0)))"#,
+        )
+        .unwrap();
+        let required = code_capabilities(&code).unwrap();
+        assert_eq!(
+            required.into_iter().collect::<Vec<_>>(),
+            vec!["cosmwasm_1_2", "iterator"]
+        );
+        assert!(check_capabilities(&code, &default_capabilities()).is_ok());
+
+        let available = vec!["iterator".to_string()].into_iter().collect();
+        let err = check_capabilities(&code, &available).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("cosmwasm_1_2 (advertised: iterator)"));
+        assert!(code_capabilities(b"not wasm").is_err());
+    }
+
+    #[test]
+    fn test_query_capability() {
+        let supply = QueryRequest::Bank(BankQuery::Supply {
+            denom: "uluna".to_string(),
+        });
+        assert_eq!(query_capability(&supply), Some("cosmwasm_1_1"));
+        let balance = QueryRequest::Bank(BankQuery::AllBalances {
+            address: "addr".to_string(),
+        });
+        assert_eq!(query_capability(&balance), None);
+        let staking = QueryRequest::Staking(StakingQuery::BondedDenom {});
+        assert_eq!(query_capability(&staking), Some("staking"));
+    }
+}
@@ -1,18 +1,217 @@
-use crate::Error;
-use cosmwasm_std::Timestamp;
+use crate::rpc_items::cosmos::bank::v1beta1::{DenomUnit as ProtoDenomUnit, Metadata};
+use crate::{ContractStorage, Error};
+use cosmwasm_std::{Event, Timestamp};
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
 
-/// Full contract_info is much more verbose, and contains fields such as admin, creator, label, etc
-/// However, those fields are not used for simulations, and thus neglected for now
+/// Metadata of a contract, as registered by the wasm module.
+/// Fields such as the ibc port and the creation position are not used for simulations, and thus neglected
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ContractInfo {
     pub code_id: u64,
+    /// address that instantiated the contract
+    pub creator: String,
+    /// address allowed to migrate the contract and change its admin, if any
+    pub admin: Option<String>,
+    pub label: String,
 }
+
+/// Metadata of stored wasm code, as registered by the wasm module
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeInfo {
+    pub code_id: u64,
+    /// address that stored the code
+    pub creator: String,
+    /// sha256 checksum of the wasm code
+    pub checksum: Vec<u8>,
+}
+
+/// Metadata of a denom registered in the bank module, serialized the same way as
+/// cosmwasm_std::DenomMetadata of cosmwasm 1.3 so that it can be returned to contracts as is
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenomMetadata {
+    pub description: String,
+    pub denom_units: Vec<DenomUnit>,
+    pub base: String,
+    pub display: String,
+    pub name: String,
+    pub symbol: String,
+    /// uri and uri_hash are only set on chains running cosmos-sdk 0.46 or later
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub uri_hash: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenomUnit {
+    pub denom: String,
+    /// 1 denom = 10^exponent base denom
+    pub exponent: u32,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl From<Metadata> for DenomMetadata {
+    fn from(metadata: Metadata) -> Self {
+        Self {
+            description: metadata.description,
+            denom_units: metadata
+                .denom_units
+                .into_iter()
+                .map(|unit| DenomUnit {
+                    denom: unit.denom,
+                    exponent: unit.exponent,
+                    aliases: unit.aliases,
+                })
+                .collect(),
+            base: metadata.base,
+            display: metadata.display,
+            name: metadata.name,
+            symbol: metadata.symbol,
+            uri: String::new(),
+            uri_hash: String::new(),
+        }
+    }
+}
+
+impl From<DenomMetadata> for Metadata {
+    fn from(metadata: DenomMetadata) -> Self {
+        Self {
+            description: metadata.description,
+            denom_units: metadata
+                .denom_units
+                .into_iter()
+                .map(|unit| ProtoDenomUnit {
+                    denom: unit.denom,
+                    exponent: unit.exponent,
+                    aliases: unit.aliases,
+                })
+                .collect(),
+            base: metadata.base,
+            display: metadata.display,
+            name: metadata.name,
+            symbol: metadata.symbol,
+        }
+    }
+}
+
+/// A transaction included in a block, along with the result of its execution on chain
+pub struct TxInfo {
+    pub hash: String,
+    pub height: u64,
+    /// timestamp of the block that includes the transaction
+    pub timestamp: Timestamp,
+    pub success: bool,
+    pub events: Vec<Event>,
+    /// protobuf encoded cosmos.tx.v1beta1.Tx
+    pub tx: Vec<u8>,
+}
+
+const RPC_CACHE_DIRNAME: &str = ".cw-rpc-cache";
+
+/// where and how a client backend caches responses on disk
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// defaults to ~/.cw-rpc-cache
+    pub dir: PathBuf,
+    /// cached responses are used, but new ones are never written to disk
+    pub read_only: bool,
+    /// share responses which never change, i.e. contract code by code id,
+    /// with the caches of all heights of the chain
+    pub share_code: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        let homedir = env::var("HOME").unwrap_or_else(|_| "/tmp/".to_string());
+        Self {
+            dir: Path::new(&homedir).join(RPC_CACHE_DIRNAME),
+            read_only: false,
+            share_code: true,
+        }
+    }
+}
+
+// substrings of the errors of nodes asked for a height they pruned
+const PRUNED_MARKERS: &[&str] = &[
+    "version does not exist",
+    "failed to load state at height",
+    "could not find results for height",
+    "is not available, lowest height is",
+];
+
+/// whether msg is the error of a node which pruned the requested height
+pub(crate) fn is_pruned_message(msg: &str) -> bool {
+    let msg = msg.to_lowercase();
+    PRUNED_MARKERS.iter().any(|m| msg.contains(m))
+}
+
+/// the lowest height available according to msg, e.g. "height 1 is not available, lowest height is 42"
+pub(crate) fn lowest_height_in(msg: &str) -> Option<u64> {
+    let msg = msg.to_lowercase();
+    let (_, rest) = msg.split_once("lowest height is")?;
+    let digits: String = rest
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
 pub trait CwClientBackend: CwClientBackendClone + Send + Sync {
     fn block_number(&self) -> u64;
+    /// endpoint of the node queried by this client
+    fn url(&self) -> &str;
+    /// identifies the on-disk cache of responses, None if responses are not cached
+    fn cache_key(&self) -> Option<String>;
+    /// None if responses are not cached
+    fn cache_config(&self) -> Option<CacheConfig> {
+        None
+    }
+    /// reopens the cache with config, keeping the responses cached so far
+    fn set_cache_config(&mut self, _config: CacheConfig) -> Result<(), Error> {
+        Err(Error::backend_error(
+            "responses of this client are not cached",
+        ))
+    }
+    /// writes every cached response to path, to be imported by clients of other machines
+    fn export_cache(&self, _path: &Path) -> Result<(), Error> {
+        Err(Error::backend_error(
+            "responses of this client are not cached",
+        ))
+    }
+    /// adds the responses exported to path by a client of the same chain and height,
+    /// returning how many there were
+    fn import_cache(&mut self, _path: &Path) -> Result<usize, Error> {
+        Err(Error::backend_error(
+            "responses of this client are not cached",
+        ))
+    }
     fn chain_id(&mut self) -> Result<String, Error>;
     fn timestamp(&mut self) -> Result<Timestamp, Error>;
     fn block_height(&mut self) -> Result<u64, Error>;
+    /// lowest height of the blocks kept by the node. Pruning nodes may keep state for
+    /// fewer heights than blocks, so forks at this height can still fail with Error::HeightPruned
+    fn earliest_available_block(&mut self) -> Result<u64, Error>;
     fn query_bank_all_balances(&mut self, address: &str) -> Result<Vec<(String, u128)>, Error>;
+    /// total supply of denom, 0 if it does not exist
+    fn query_bank_supply(&mut self, denom: &str) -> Result<u128, Error>;
+    /// metadata of denom, fails if none is registered
+    fn query_bank_denom_metadata(&mut self, denom: &str) -> Result<DenomMetadata, Error>;
+    fn query_bank_all_denom_metadata(&mut self) -> Result<Vec<DenomMetadata>, Error>;
+    /// pending rewards of a delegation, truncated to whole coins
+    fn query_distribution_rewards(
+        &mut self,
+        delegator: &str,
+        validator: &str,
+    ) -> Result<Vec<(String, u128)>, Error>;
+    /// address receiving the rewards of delegator, the delegator itself unless changed
+    fn query_distribution_withdraw_address(&mut self, delegator: &str) -> Result<String, Error>;
+    /// id of the last submitted gov proposal, 0 if there is none
+    fn query_gov_latest_proposal_id(&mut self) -> Result<u64, Error>;
     fn query_wasm_contract_smart(
         &mut self,
         address: &str,
@@ -22,8 +221,47 @@ pub trait CwClientBackend: CwClientBackendClone + Send + Sync {
         &mut self,
         address: &str,
     ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, Error>;
+    /// value of a single key in the storage of a contract, None if it is not set
+    fn query_wasm_contract_state_raw(
+        &mut self,
+        address: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error>;
     fn query_wasm_contract_info(&mut self, address: &str) -> Result<ContractInfo, Error>;
     fn query_wasm_contract_code(&mut self, code_id: u64) -> Result<Vec<u8>, Error>;
+    /// creator and checksum of code_id
+    fn query_wasm_code_info(&mut self, code_id: u64) -> Result<CodeInfo, Error>;
+    /// states of several contracts, backends may fetch them concurrently
+    fn query_wasm_contracts_state_all(
+        &mut self,
+        addresses: &[String],
+    ) -> Result<Vec<ContractStorage>, Error> {
+        addresses
+            .iter()
+            .map(|a| self.query_wasm_contract_state_all(a))
+            .collect()
+    }
+    fn query_wasm_contracts_info(
+        &mut self,
+        addresses: &[String],
+    ) -> Result<Vec<ContractInfo>, Error> {
+        addresses
+            .iter()
+            .map(|a| self.query_wasm_contract_info(a))
+            .collect()
+    }
+    fn query_wasm_contract_codes(&mut self, code_ids: &[u64]) -> Result<Vec<Vec<u8>>, Error> {
+        code_ids
+            .iter()
+            .map(|c| self.query_wasm_contract_code(*c))
+            .collect()
+    }
+    /// protobuf encoded response of an arbitrary grpc query, e.g. /osmosis.gamm.v1beta1.Query/Pool
+    fn query_grpc(&mut self, path: &str, data: &[u8]) -> Result<Vec<u8>, Error>;
+    fn query_tx(&mut self, hash: &str) -> Result<TxInfo, Error>;
+    fn query_block_txs(&mut self, height: u64) -> Result<Vec<TxInfo>, Error>;
+    /// returns a client of the same chain, with queries pinned to height
+    fn at_height(&self, height: u64) -> Result<Box<dyn CwClientBackend>, Error>;
 }
 
 pub trait CwClientBackendClone {
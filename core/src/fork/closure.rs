@@ -0,0 +1,144 @@
+use crate::fork::api::human_to_canonical;
+use crate::ContractStorage;
+use cosmwasm_std::Addr;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// what Model::fork_closure loaded: the contracts with the depth they were found at, the root
+/// being at depth 0, and the code ids referenced by their storage
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForkClosure {
+    pub contracts: BTreeMap<Addr, usize>,
+    pub code_ids: BTreeSet<u64>,
+}
+
+/// reported by Model::fork_closure_with_progress as the closure is loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClosureProgress {
+    /// depth is about to be loaded, consisting of contracts contracts
+    Depth { depth: usize, contracts: usize },
+    /// code and state of a contract were loaded
+    Contract { address: Addr, depth: usize },
+    /// code referenced by a loaded contract was fetched and compiled
+    Code { code_id: u64 },
+}
+
+/// addresses and code ids found in storage
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct References {
+    pub addresses: BTreeSet<Addr>,
+    pub code_ids: BTreeSet<u64>,
+}
+
+/// scans keys and values of storage for bech32 addresses with the given prefix whose canonical
+/// form is canonical_length bytes long, which is what contract addresses are on recent chains,
+/// and json values for fields named code_id or ending in _code_id
+pub(crate) fn scan_references(
+    storage: &ContractStorage,
+    bech32_prefix: &str,
+    canonical_length: usize,
+) -> References {
+    let mut references = References::default();
+    for (key, value) in storage.iter() {
+        for bytes in [key, value] {
+            let text = String::from_utf8_lossy(bytes);
+            for address in find_addresses(&text, bech32_prefix) {
+                if human_to_canonical(address, bech32_prefix)
+                    .map_or(false, |c| c.len() == canonical_length)
+                {
+                    references.addresses.insert(Addr::unchecked(address));
+                }
+            }
+        }
+        if let Ok(json) = serde_json::from_slice::<Value>(value) {
+            find_code_ids(&json, &mut references.code_ids);
+        }
+    }
+    references
+}
+
+/// substrings of text which look like bech32 addresses with prefix
+fn find_addresses<'a>(text: &'a str, bech32_prefix: &str) -> Vec<&'a str> {
+    let hrp = format!("{}1", bech32_prefix);
+    let mut out = Vec::new();
+    let mut rest = 0;
+    while let Some(offset) = text[rest..].find(&hrp) {
+        let start = rest + offset;
+        let data = &text[start + hrp.len()..];
+        let len = data
+            .find(|c: char| !BECH32_CHARSET.contains(c))
+            .unwrap_or_else(|| data.len());
+        // the 6 characters of the checksum at least
+        if len >= 6 {
+            out.push(&text[start..start + hrp.len() + len]);
+        }
+        rest = start + hrp.len() + len;
+    }
+    out
+}
+
+fn find_code_ids(value: &Value, code_ids: &mut BTreeSet<u64>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter() {
+                if key == "code_id" || key.ends_with("_code_id") {
+                    let code_id = match value {
+                        Value::Number(n) => n.as_u64(),
+                        Value::String(s) => s.parse().ok(),
+                        _ => None,
+                    };
+                    code_ids.extend(code_id);
+                }
+                find_code_ids(value, code_ids);
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|v| find_code_ids(v, code_ids)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ContractStorage;
+    use cosmwasm_std::Addr;
+
+    use super::{find_addresses, scan_references};
+
+    const CONTRACT: &str = "wasm15le5evw4regnwf9lrjnpakr2075fcyp4n4yzpelvqcuevzkw2lss46hslz";
+    const ACCOUNT: &str = "wasm1zcnn5gh37jxg9c6dp4jcjc7995ae0s5f5hj0lj";
+
+    #[test]
+    fn test_find_addresses() {
+        let text = format!("{{\"owner\":\"{}\",\"pair\":\"{}\"}}", ACCOUNT, CONTRACT);
+        assert_eq!(find_addresses(&text, "wasm"), vec![ACCOUNT, CONTRACT]);
+        assert!(find_addresses("wasm1 wasm1xyz", "wasm").is_empty());
+    }
+
+    #[test]
+    fn test_scan_references() {
+        let mut storage = ContractStorage::new();
+        storage.insert(
+            b"config".to_vec(),
+            format!(
+                "{{\"owner\":\"{}\",\"token_code_id\":5,\"pair_configs\":[{{\"code_id\":\"7\"}}]}}",
+                ACCOUNT
+            )
+            .into_bytes(),
+        );
+        let mut key = b"\x00\x05pairs".to_vec();
+        key.extend_from_slice(CONTRACT.as_bytes());
+        storage.insert(key, b"{}".to_vec());
+        let references = scan_references(&storage, "wasm", 32);
+        // the account is 20 bytes long, only the contract is referenced
+        assert_eq!(
+            references.addresses.into_iter().collect::<Vec<Addr>>(),
+            vec![Addr::unchecked(CONTRACT)]
+        );
+        assert_eq!(
+            references.code_ids.into_iter().collect::<Vec<u64>>(),
+            vec![5, 7]
+        );
+    }
+}
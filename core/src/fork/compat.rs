@@ -0,0 +1,208 @@
+use crate::Error;
+use cosmwasm_std::{
+    IbcChannel, IbcChannelCloseMsg, IbcChannelConnectMsg, IbcChannelOpenMsg, IbcPacketAckMsg,
+    IbcPacketReceiveMsg, IbcPacketTimeoutMsg,
+};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use wasmparser::{ExternalKind, Parser, Payload};
+
+const INTERFACE_VERSION_PREFIX: &str = "interface_version_";
+/// marker export of contracts compiled against cosmwasm 0.14 to 0.16
+pub(crate) const LEGACY_INTERFACE_VERSION: &str = "interface_version_7";
+
+/// the cosmwasm-std version a contract was compiled against, as far as its marker exports tell.
+/// Contracts of 0.14 to 0.16 run with ibc entry points translated to their old signatures,
+/// contracts of 1.x run as they are
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CosmwasmVersion {
+    V0_14,
+    V1_0,
+    V1_1,
+    V1_2,
+    V1_3,
+    V1_4,
+}
+
+impl CosmwasmVersion {
+    /// detects the version from the interface_version_* and requires_cosmwasm_1_* exports
+    pub fn from_exports(exports: &HashSet<String>) -> Result<Self, Error> {
+        let interface_versions: Vec<&String> = exports
+            .iter()
+            .filter(|e| e.starts_with(INTERFACE_VERSION_PREFIX))
+            .collect();
+        let interface_version = match interface_versions.as_slice() {
+            [version] => version.as_str(),
+            [] => {
+                return Err(Error::invalid_argument(
+                    "contract has no interface_version_* export, it was not compiled against cosmwasm-std",
+                ))
+            }
+            _ => {
+                return Err(Error::invalid_argument(
+                    "contract has more than one interface_version_* export",
+                ))
+            }
+        };
+        match interface_version {
+            LEGACY_INTERFACE_VERSION => Ok(CosmwasmVersion::V0_14),
+            "interface_version_8" => {
+                let requires = |version: &str| exports.contains(&format!("requires_cosmwasm_{}", version));
+                Ok(if requires("1_4") {
+                    CosmwasmVersion::V1_4
+                } else if requires("1_3") {
+                    CosmwasmVersion::V1_3
+                } else if requires("1_2") {
+                    CosmwasmVersion::V1_2
+                } else if requires("1_1") {
+                    CosmwasmVersion::V1_1
+                } else {
+                    CosmwasmVersion::V1_0
+                })
+            }
+            version => Err(Error::invalid_argument(format!(
+                "contract has {}, only the interface versions of cosmwasm 0.14 and later are supported",
+                version
+            ))),
+        }
+    }
+
+    /// detects the version of wasm code
+    pub fn of_code(code: &[u8]) -> Result<Self, Error> {
+        let mut exports = HashSet::new();
+        for payload in Parser::new(0).parse_all(code) {
+            if let Payload::ExportSection(mut reader) = payload.map_err(Error::format_error)? {
+                for _ in 0..reader.get_count() {
+                    let export = reader.read().map_err(Error::format_error)?;
+                    if let ExternalKind::Function = export.kind {
+                        exports.insert(export.field.to_string());
+                    }
+                }
+            }
+        }
+        Self::from_exports(&exports)
+    }
+}
+
+// cosmwasm 0.16 passed the counterparty version of the handshake in the channel
+fn legacy_channel(channel: &IbcChannel, counterparty_version: Option<&String>) -> Value {
+    let mut value = serde_json::to_value(channel).unwrap();
+    value["counterparty_version"] = json!(counterparty_version);
+    value
+}
+
+/// the IbcChannel ibc_channel_open of cosmwasm 0.16 takes
+pub(crate) fn legacy_channel_open_msg(msg: &IbcChannelOpenMsg) -> Value {
+    match msg {
+        IbcChannelOpenMsg::OpenInit { channel } => legacy_channel(channel, None),
+        IbcChannelOpenMsg::OpenTry {
+            channel,
+            counterparty_version,
+        } => legacy_channel(channel, Some(counterparty_version)),
+    }
+}
+
+/// the IbcChannel ibc_channel_connect of cosmwasm 0.16 takes
+pub(crate) fn legacy_channel_connect_msg(msg: &IbcChannelConnectMsg) -> Value {
+    match msg {
+        IbcChannelConnectMsg::OpenAck {
+            channel,
+            counterparty_version,
+        } => legacy_channel(channel, Some(counterparty_version)),
+        IbcChannelConnectMsg::OpenConfirm { channel } => legacy_channel(channel, None),
+    }
+}
+
+/// the IbcChannel ibc_channel_close of cosmwasm 0.16 takes
+pub(crate) fn legacy_channel_close_msg(msg: &IbcChannelCloseMsg) -> Value {
+    legacy_channel(msg.channel(), None)
+}
+
+/// the IbcPacket ibc_packet_receive of cosmwasm 0.16 takes
+pub(crate) fn legacy_packet_receive_msg(msg: &IbcPacketReceiveMsg) -> Value {
+    serde_json::to_value(&msg.packet).unwrap()
+}
+
+/// the IbcAcknowledgementWithPacket ibc_packet_ack of cosmwasm 0.16 takes
+pub(crate) fn legacy_packet_ack_msg(msg: &IbcPacketAckMsg) -> Value {
+    json!({
+        "acknowledgement": msg.acknowledgement,
+        "original_packet": msg.original_packet,
+    })
+}
+
+/// the IbcPacket ibc_packet_timeout of cosmwasm 0.16 takes
+pub(crate) fn legacy_packet_timeout_msg(msg: &IbcPacketTimeoutMsg) -> Value {
+    serde_json::to_value(&msg.packet).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{IbcChannel, IbcChannelOpenMsg, IbcEndpoint, IbcOrder};
+    use std::collections::HashSet;
+
+    use super::{legacy_channel_open_msg, CosmwasmVersion};
+
+    fn exports(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cosmwasm_version() {
+        let version = |names: &[&str]| CosmwasmVersion::from_exports(&exports(names));
+        assert_eq!(
+            version(&["instantiate", "interface_version_7"]).unwrap(),
+            CosmwasmVersion::V0_14
+        );
+        assert_eq!(
+            version(&["interface_version_8", "requires_iterator"]).unwrap(),
+            CosmwasmVersion::V1_0
+        );
+        assert_eq!(
+            version(&[
+                "interface_version_8",
+                "requires_cosmwasm_1_1",
+                "requires_cosmwasm_1_2"
+            ])
+            .unwrap(),
+            CosmwasmVersion::V1_2
+        );
+        let err = version(&["init", "handle", "cosmwasm_vm_version_4"]).unwrap_err();
+        assert!(err.to_string().contains("no interface_version_* export"));
+        let err = version(&["interface_version_6"]).unwrap_err();
+        assert!(err.to_string().contains("interface_version_6"));
+
+        let code = wat::parse_str(
+            r#"(module
+            (func)
+            (export "interface_version_8" (func 0))
+            (export "requires_cosmwasm_1_3" (func 0)))"#,
+        )
+        .unwrap();
+        assert_eq!(
+            CosmwasmVersion::of_code(&code).unwrap(),
+            CosmwasmVersion::V1_3
+        );
+    }
+
+    #[test]
+    fn test_legacy_channel_open_msg() {
+        let endpoint = |port: &str| IbcEndpoint {
+            port_id: port.to_string(),
+            channel_id: "channel-0".to_string(),
+        };
+        let channel = IbcChannel::new(
+            endpoint("wasm.contract"),
+            endpoint("transfer"),
+            IbcOrder::Unordered,
+            "ics20-1",
+            "connection-0",
+        );
+        let init = legacy_channel_open_msg(&IbcChannelOpenMsg::new_init(channel.clone()));
+        assert_eq!(init["version"], "ics20-1");
+        assert!(init["counterparty_version"].is_null());
+        let open_try = legacy_channel_open_msg(&IbcChannelOpenMsg::new_try(channel, "ics20-2"));
+        assert_eq!(open_try["counterparty_version"], "ics20-2");
+        assert_eq!(open_try["endpoint"]["port_id"], "wasm.contract");
+    }
+}
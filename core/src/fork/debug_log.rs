@@ -1,12 +1,24 @@
-use cosmwasm_std::{Addr, Attribute, Binary, Event, Response};
+use crate::fork::labels::short_address;
+use crate::{
+    diff_storage, format_backtrace, BacktraceFrame, CallKind, ContractStorage, Print, PrintLevel,
+};
+use cosmwasm_std::{
+    Addr, Attribute, Binary, BlockInfo, Coin, ContractResult, Env, Event, Response, TransactionInfo,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 
-#[derive(Clone, Debug)]
+/// serialized as the tree of calls starting from the top-level call, see CallNode
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "CallNode", from = "CallNode")]
 pub struct CallTrace {
     pub call_graph: HashMap<usize, Vec<usize>>,
     pub call_graph_labels: HashMap<usize, String>,
+    // error each failed call failed with
+    call_errors: HashMap<usize, String>,
+    // gas used by the wasm code of each call
+    call_gas: HashMap<usize, u64>,
     call_id_counter: usize,
     current_call_id: usize,
 }
@@ -18,6 +30,8 @@ impl CallTrace {
         Self {
             call_graph: HashMap::new(),
             call_graph_labels,
+            call_errors: HashMap::new(),
+            call_gas: HashMap::new(),
             call_id_counter: 0,
             current_call_id: 0,
         }
@@ -43,6 +57,10 @@ impl CallTrace {
         parent_call_id
     }
 
+    pub fn current_call_id(&self) -> usize {
+        self.current_call_id
+    }
+
     /// restore to parent_call_id
     pub fn end_call(&mut self, parent_call_id: usize) {
         self.current_call_id = parent_call_id;
@@ -61,15 +79,345 @@ impl CallTrace {
         // save name for new call_id
         self.call_graph_labels
             .insert(call_id, error_str.to_string());
+        // the error propagates to the callers, keep the one of the call where it happened
+        self.call_errors
+            .entry(parent_call_id)
+            .or_insert_with(|| error_str.to_string());
+    }
+
+    pub fn add_gas_used(&mut self, gas_used: u64) {
+        *self.call_gas.entry(self.current_call_id).or_insert(0) += gas_used;
+    }
+
+    /// addresses of the called contracts, parsed from the labels of the calls
+    pub(crate) fn called_addresses(&self) -> BTreeSet<Addr> {
+        self.call_graph_labels
+            .values()
+            .filter_map(|label| label.split_once(':'))
+            // labels of errors may contain colons as well
+            .filter(|(address, _)| !address.is_empty() && !address.contains(char::is_whitespace))
+            .map(|(address, _)| Addr::unchecked(address))
+            .collect()
+    }
+
+    /// labels the calls to named contracts like name(wasm15le5e...):execute(msg)
+    pub(crate) fn apply_names(&mut self, names: &BTreeMap<Addr, String>) {
+        for label in self.call_graph_labels.values_mut() {
+            let renamed = match label.split_once(':') {
+                Some((address, call)) => names
+                    .get(&Addr::unchecked(address))
+                    .map(|name| format!("{}({}):{}", name, short_address(address), call)),
+                None => None,
+            };
+            if let Some(renamed) = renamed {
+                *label = renamed;
+            }
+        }
+    }
+
+    /// every call of the trace, ordered by call_id
+    pub fn frames(&self) -> Vec<CallFrame> {
+        let parents: HashMap<usize, usize> = self
+            .call_graph
+            .iter()
+            .flat_map(|(parent, children)| children.iter().map(move |child| (*child, *parent)))
+            .collect();
+        let mut frames: Vec<CallFrame> = self
+            .call_graph_labels
+            .iter()
+            .map(|(call_id, label)| CallFrame {
+                call_id: *call_id,
+                parent: parents.get(call_id).copied(),
+                label: label.clone(),
+                err_msg: self.call_errors.get(call_id).cloned(),
+                gas_used: self.call_gas.get(call_id).copied().unwrap_or(0),
+            })
+            .collect();
+        frames.sort_by_key(|f| f.call_id);
+        frames
+    }
+
+    /// the call trace in the Graphviz dot language, failed calls are drawn in red
+    pub fn to_dot(&self) -> String {
+        let frames = self.frames();
+        let mut out = String::from("digraph calls {\n");
+        for frame in frames.iter() {
+            let label = frame.label.replace('\\', "\\\\").replace('"', "\\\"");
+            let color = if frame.err_msg.is_some() {
+                ", color=red"
+            } else {
+                ""
+            };
+            out += &format!("    {} [label=\"{}\"{}];\n", frame.call_id, label, color);
+        }
+        for frame in frames.iter() {
+            if let Some(parent) = frame.parent {
+                out += &format!("    {} -> {};\n", parent, frame.call_id);
+            }
+        }
+        out += "}\n";
+        out
+    }
+
+    /// the call trace as nested objects starting from the top-level call,
+    /// with the call_id, label, err_msg, gas_used and children of every call
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self.tree()).unwrap()
+    }
+
+    /// the top-level call with the calls it made
+    pub fn tree(&self) -> CallNode {
+        self.node(0)
+    }
+
+    fn node(&self, call_id: usize) -> CallNode {
+        let children = match self.call_graph.get(&call_id) {
+            Some(children) => children.iter().map(|c| self.node(*c)).collect(),
+            None => Vec::new(),
+        };
+        CallNode {
+            call_id,
+            label: self
+                .call_graph_labels
+                .get(&call_id)
+                .cloned()
+                .unwrap_or_default(),
+            err_msg: self.call_errors.get(&call_id).cloned(),
+            gas_used: self.call_gas.get(&call_id).copied().unwrap_or(0),
+            children,
+        }
+    }
+
+    fn insert_node(&mut self, node: CallNode) {
+        self.call_id_counter = self.call_id_counter.max(node.call_id);
+        self.call_graph_labels.insert(node.call_id, node.label);
+        if let Some(err_msg) = node.err_msg {
+            self.call_errors.insert(node.call_id, err_msg);
+        }
+        if node.gas_used > 0 {
+            self.call_gas.insert(node.call_id, node.gas_used);
+        }
+        for child in node.children {
+            self.call_graph
+                .entry(node.call_id)
+                .or_insert_with(Vec::new)
+                .push(child.call_id);
+            self.insert_node(child);
+        }
+    }
+
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>, call_id: usize, depth: usize) -> fmt::Result {
+        let label = self
+            .call_graph_labels
+            .get(&call_id)
+            .map(|l| l.as_str())
+            .unwrap_or_default();
+        write!(f, "{:indent$}{}", "", label, indent = 2 * depth)?;
+        if let Some(gas_used) = self.call_gas.get(&call_id) {
+            write!(f, " [gas {}]", gas_used)?;
+        }
+        if let Some(err_msg) = self.call_errors.get(&call_id) {
+            write!(f, " [failed: {}]", err_msg)?;
+        }
+        writeln!(f)?;
+        if let Some(children) = self.call_graph.get(&call_id) {
+            for child in children.iter() {
+                self.fmt_node(f, *child, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// the call trace in the folded stack format read by flamegraph tools (inferno, flamegraph.pl),
+    /// one line per call weighted by the gas it used, excluding the calls it made.
+    /// Calls which used no gas are left out
+    pub fn to_folded(&self) -> String {
+        let mut out = String::new();
+        self.fold(0, &mut Vec::new(), &mut out);
+        out
+    }
+
+    fn fold(&self, call_id: usize, stack: &mut Vec<String>, out: &mut String) {
+        // ; separates the frames of a stack and lines end with the weight
+        let label = match self.call_graph_labels.get(&call_id) {
+            Some(label) => label.replace(';', ",").replace('\n', " "),
+            None => call_id.to_string(),
+        };
+        stack.push(label);
+        let gas_used = self.call_gas.get(&call_id).copied().unwrap_or(0);
+        if gas_used > 0 {
+            *out += &format!("{} {}\n", stack.join(";"), gas_used);
+        }
+        if let Some(children) = self.call_graph.get(&call_id) {
+            for child in children.iter() {
+                self.fold(*child, stack, out);
+            }
+        }
+        stack.pop();
+    }
+}
+
+impl From<CallTrace> for CallNode {
+    fn from(call_trace: CallTrace) -> Self {
+        call_trace.tree()
+    }
+}
+
+impl From<CallNode> for CallTrace {
+    fn from(tree: CallNode) -> Self {
+        let mut call_trace = CallTrace::new();
+        call_trace.insert_node(tree);
+        call_trace
+    }
+}
+
+/// the call tree rendered with one call per line, indented by depth
+impl fmt::Display for CallTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_node(f, 0, 0)
     }
 }
 
+/// a call with the calls it made in order, see CallTrace::tree
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CallNode {
+    pub call_id: usize,
+    pub label: String,
+    /// error the call failed with
+    pub err_msg: Option<String>,
+    /// CosmWasm gas used by the wasm code of the call, excluding the calls it made
+    pub gas_used: u64,
+    pub children: Vec<CallNode>,
+}
+
+/// a call in the call trace, see CallTrace::frames
+/// calls which failed also have a child labeled with the error
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    pub call_id: usize,
+    /// None for the top-level call
+    pub parent: Option<usize>,
+    pub label: String,
+    /// error the call failed with
+    pub err_msg: Option<String>,
+    /// CosmWasm gas used by the wasm code of the call, excluding the calls it made
+    pub gas_used: u64,
+}
+
+/// a contract call with the state it ran in, recorded in order of the calls
+/// if frame recording is enabled, see Model::enable_frame_recording and Model::rerun_frame
 #[derive(Clone, Debug)]
+pub struct FrameRecord {
+    pub kind: CallKind,
+    pub contract: Addr,
+    pub sender: Addr,
+    /// json encoded message, or the json encoded Reply of reply calls
+    pub msg: Binary,
+    pub funds: Vec<Coin>,
+    pub env: Env,
+    /// code instantiated or migrated to, or the code of the contract for other calls
+    pub code_id: u64,
+    /// number of calls being processed when the call started, 0 for top-level calls
+    pub depth: usize,
+    /// storage of the contract when the call started, empty for instantiations
+    pub storage_before: ContractStorage,
+    /// storage of the contract when the call returned, including changes of the calls it made
+    /// None if the call did not return
+    pub storage_after: Option<ContractStorage>,
+    pub result: Option<ContractResult<Response>>,
+}
+
+impl FrameRecord {
+    /// storage changes made by the call and the calls it made
+    pub fn storage_diff(&self) -> Vec<StorageChange> {
+        match &self.storage_after {
+            Some(storage_after) => diff_storage(&self.storage_before, storage_after),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// serialized with the call trace as a tree, recorded frames and aborts are not serialized
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DebugLog {
     pub logs: Vec<DebugLogEntry>,
     pub err_msg: Option<String>,
     pub stdout: Vec<String>,
+    // structured messages of contracts sent to the printer, see PrinterMsg
+    pub prints: Vec<Print>,
     pub call_trace: CallTrace,
+    // sender of the top-level call, and its account label if it was registered
+    pub sender: Option<Addr>,
+    pub sender_label: Option<String>,
+    // storage changes made by the call, empty if the call failed
+    pub storage_diff: BTreeMap<Addr, Vec<StorageChange>>,
+    // data returned by the call, after overrides by replies
+    pub data: Option<Binary>,
+    // address of the contract created by a top-level instantiate, None if it failed
+    pub contract_address: Option<Addr>,
+    // CosmWasm gas used by all contract calls, queries are not metered
+    pub gas_used: u64,
+    // block in the env of every call and query of the transaction, fixed when it started
+    pub block: Option<BlockInfo>,
+    // transaction info of the env of every call, and the synthetic hash of the transaction
+    pub transaction: Option<TransactionInfo>,
+    pub tx_hash: Option<String>,
+    // storage writes and accessed keys, only recorded if storage tracing is enabled
+    storage_trace: Vec<StorageWrite>,
+    access_sets: Vec<AccessSet>,
+    // reentrant calls and queries exceeding the depth limit, see RecursionPolicy
+    pub warnings: Vec<String>,
+    // wasm functions the contract trapped in, innermost first, only captured if backtraces are enabled
+    pub backtrace: Vec<BacktraceFrame>,
+    // events the chain emits for the call in wasmd's format and order, empty if the call failed
+    pub events: Vec<CallEvent>,
+    // names of the contracts and accounts the call involved, see Model::set_alias
+    pub names: BTreeMap<Addr, String>,
+    // set when the call must fail because of RecursionPolicy::Abort
+    #[serde(skip)]
+    aborted: Option<String>,
+    // contract calls with their state, only recorded if frame recording is enabled
+    #[serde(skip)]
+    frames: Vec<FrameRecord>,
+}
+
+/// change of a raw storage key
+/// old is None if the key was created, new is None if the key was deleted
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct StorageChange {
+    pub key: Binary,
+    pub old: Option<Binary>,
+    pub new: Option<Binary>,
+}
+
+/// a single write to contract storage, in the order it happened
+/// new is None if the key was removed
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct StorageWrite {
+    pub contract: Addr,
+    pub key: Binary,
+    pub old: Option<Binary>,
+    pub new: Option<Binary>,
+    /// call frame in the call trace which made the write
+    pub call_id: usize,
+}
+
+/// storage keys of a contract accessed by a single call frame
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct AccessSet {
+    pub call_id: usize,
+    pub contract: Addr,
+    pub reads: BTreeSet<Binary>,
+    pub writes: BTreeSet<Binary>,
+}
+
+/// an event emitted by the chain, with the call in the call trace which emitted it.
+/// Contract calls emit the events of their funds transfer, execute or instantiate event,
+/// and the wasm events of their response, other messages emit the events of their module
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CallEvent {
+    pub call_id: usize,
+    pub event: Event,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -86,20 +434,272 @@ impl fmt::Display for DebugLogEntry {
     }
 }
 
+/// human-readable rendering with the call tree indented by depth, the same for equal logs
+impl fmt::Display for DebugLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(sender) = &self.sender {
+            match &self.sender_label {
+                Some(label) => writeln!(f, "sender: {} ({})", sender, label)?,
+                None => writeln!(f, "sender: {}", sender)?,
+            }
+        }
+        if let Some(contract_address) = &self.contract_address {
+            match self.names.get(contract_address) {
+                Some(name) => writeln!(f, "contract: {} ({})", contract_address, name)?,
+                None => writeln!(f, "contract: {}", contract_address)?,
+            }
+        }
+        if let Some(err_msg) = &self.err_msg {
+            writeln!(f, "error: {}", err_msg)?;
+        }
+        if let Some(data) = &self.data {
+            writeln!(f, "data: {}", data)?;
+        }
+        writeln!(f, "gas used: {}", self.gas_used)?;
+        writeln!(f, "calls:")?;
+        self.call_trace.fmt_node(f, 0, 1)?;
+        if !self.logs.is_empty() {
+            writeln!(f, "logs:")?;
+            for entry in self.logs.iter() {
+                writeln!(f, "  {}", entry)?;
+            }
+        }
+        if !self.events.is_empty() {
+            writeln!(f, "events:")?;
+            for e in self.events.iter() {
+                let attributes: Vec<String> = e
+                    .event
+                    .attributes
+                    .iter()
+                    .map(|a| format!("{}={}", a.key, a.value))
+                    .collect();
+                writeln!(
+                    f,
+                    "  [{}] {} {}",
+                    e.call_id,
+                    e.event.ty,
+                    attributes.join(" ")
+                )?;
+            }
+        }
+        if !self.stdout.is_empty() {
+            writeln!(f, "stdout:")?;
+            for msg in self.stdout.iter() {
+                for line in msg.lines() {
+                    writeln!(f, "  {}", line)?;
+                }
+            }
+        }
+        if !self.prints.is_empty() {
+            writeln!(f, "prints:")?;
+            for print in self.prints.iter() {
+                let level = format!("{:?}", print.level).to_lowercase();
+                for line in print.msg.lines() {
+                    writeln!(f, "  [{}] {}", level, line)?;
+                }
+            }
+        }
+        if !self.warnings.is_empty() {
+            writeln!(f, "warnings:")?;
+            for warning in self.warnings.iter() {
+                writeln!(f, "  {}", warning)?;
+            }
+        }
+        if !self.backtrace.is_empty() {
+            writeln!(f, "backtrace:")?;
+            write!(f, "{}", format_backtrace(&self.backtrace))?;
+        }
+        if !self.storage_diff.is_empty() {
+            writeln!(f, "storage changes:")?;
+            let hex = |v: &Option<Binary>| match v {
+                Some(v) => hex::encode(v.as_slice()),
+                None => "none".to_string(),
+            };
+            for (contract, changes) in self.storage_diff.iter() {
+                writeln!(f, "  {}:", contract)?;
+                for change in changes.iter() {
+                    writeln!(
+                        f,
+                        "    {}: {} -> {}",
+                        hex::encode(change.key.as_slice()),
+                        hex(&change.old),
+                        hex(&change.new)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 impl DebugLog {
     pub fn new() -> Self {
         Self {
             logs: Vec::new(),
             err_msg: None,
             stdout: Vec::new(),
+            prints: Vec::new(),
             call_trace: CallTrace::new(),
+            sender: None,
+            sender_label: None,
+            storage_diff: BTreeMap::new(),
+            data: None,
+            contract_address: None,
+            gas_used: 0,
+            block: None,
+            transaction: None,
+            tx_hash: None,
+            storage_trace: Vec::new(),
+            access_sets: Vec::new(),
+            warnings: Vec::new(),
+            backtrace: Vec::new(),
+            events: Vec::new(),
+            names: BTreeMap::new(),
+            aborted: None,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn storage_trace(&self) -> &[StorageWrite] {
+        &self.storage_trace
+    }
+
+    /// recorded contract calls in the order they started
+    pub fn recorded_frames(&self) -> &[FrameRecord] {
+        &self.frames
+    }
+
+    /// returns the index of the frame, to complete it with end_frame
+    pub(crate) fn begin_frame(&mut self, frame: FrameRecord) -> usize {
+        self.frames.push(frame);
+        self.frames.len() - 1
+    }
+
+    pub(crate) fn end_frame(
+        &mut self,
+        index: usize,
+        storage_after: ContractStorage,
+        result: &ContractResult<Response>,
+    ) {
+        if let Some(frame) = self.frames.get_mut(index) {
+            frame.storage_after = Some(storage_after);
+            frame.result = Some(result.clone());
         }
     }
 
+    /// keys read and written by every call frame
+    pub fn access_sets(&self) -> &[AccessSet] {
+        &self.access_sets
+    }
+
+    /// all (contract, key) pairs read during the call
+    pub fn read_set(&self) -> BTreeSet<(Addr, Binary)> {
+        self.access_sets
+            .iter()
+            .flat_map(|a| a.reads.iter().map(move |k| (a.contract.clone(), k.clone())))
+            .collect()
+    }
+
+    /// all (contract, key) pairs written during the call
+    pub fn write_set(&self) -> BTreeSet<(Addr, Binary)> {
+        self.access_sets
+            .iter()
+            .flat_map(|a| {
+                a.writes
+                    .iter()
+                    .map(move |k| (a.contract.clone(), k.clone()))
+            })
+            .collect()
+    }
+
+    /// two calls conflict if one of them writes a key which the other reads or writes,
+    /// in which case their results may depend on the order they are executed in
+    pub fn conflicts_with(&self, other: &DebugLog) -> bool {
+        let (reads, writes) = (self.read_set(), self.write_set());
+        let (other_reads, other_writes) = (other.read_set(), other.write_set());
+        !writes.is_disjoint(&other_writes)
+            || !writes.is_disjoint(&other_reads)
+            || !reads.is_disjoint(&other_writes)
+    }
+
+    fn current_access_set(&mut self, contract: &Addr) -> &mut AccessSet {
+        let call_id = self.call_trace.current_call_id();
+        let pos = match self
+            .access_sets
+            .iter()
+            .position(|a| a.call_id == call_id && &a.contract == contract)
+        {
+            Some(pos) => pos,
+            None => {
+                self.access_sets.push(AccessSet {
+                    call_id,
+                    contract: contract.clone(),
+                    reads: BTreeSet::new(),
+                    writes: BTreeSet::new(),
+                });
+                self.access_sets.len() - 1
+            }
+        };
+        &mut self.access_sets[pos]
+    }
+
+    pub fn append_storage_read(&mut self, contract: &Addr, key: &[u8]) {
+        self.current_access_set(contract)
+            .reads
+            .insert(Binary::from(key));
+    }
+
+    pub fn append_storage_write(
+        &mut self,
+        contract: &Addr,
+        key: &[u8],
+        old: Option<Vec<u8>>,
+        new: Option<&[u8]>,
+    ) {
+        self.current_access_set(contract)
+            .writes
+            .insert(Binary::from(key));
+        self.storage_trace.push(StorageWrite {
+            contract: contract.clone(),
+            key: Binary::from(key),
+            old: old.map(Binary::from),
+            new: new.map(Binary::from),
+            call_id: self.call_trace.current_call_id(),
+        });
+    }
+
+    pub fn set_sender(&mut self, sender: &Addr, label: Option<&str>) {
+        self.sender = Some(sender.clone());
+        self.sender_label = label.map(|l| l.to_string());
+    }
+
+    /// names the calls of the call trace to contracts in names
+    pub(crate) fn set_names(&mut self, names: BTreeMap<Addr, String>) {
+        self.call_trace.apply_names(&names);
+        self.names = names;
+    }
+
+    pub fn name_of(&self, address: &Addr) -> Option<&str> {
+        self.names.get(address).map(|n| n.as_str())
+    }
+
     pub fn set_err_msg(&mut self, err_msg: &str) {
         self.err_msg = Some(err_msg.to_string());
     }
 
+    pub fn append_warning(&mut self, msg: &str) {
+        self.warnings.push(msg.to_string());
+    }
+
+    /// make the innermost running contract call fail with reason, even if the contract handled the error
+    pub(crate) fn abort(&mut self, reason: &str) {
+        self.aborted.get_or_insert_with(|| reason.to_string());
+    }
+
+    pub(crate) fn take_abort(&mut self) -> Option<String> {
+        self.aborted.take()
+    }
+
     pub fn append_log(&mut self, response: &Response) {
         self.logs.push(DebugLogEntry {
             attributes: response.attributes.clone(),
@@ -108,10 +708,65 @@ impl DebugLog {
         });
     }
 
+    /// records events emitted by the current call
+    pub fn append_events(&mut self, events: &[Event]) {
+        let call_id = self.call_trace.current_call_id();
+        self.events.extend(events.iter().map(|event| CallEvent {
+            call_id,
+            event: event.clone(),
+        }));
+    }
+
+    /// drops the events recorded after the first count, e.g. of a submessage which was rolled back
+    pub(crate) fn truncate_events(&mut self, count: usize) {
+        self.events.truncate(count);
+    }
+
+    /// events of the transaction as the chain emits them
+    pub fn tx_events(&self) -> Vec<Event> {
+        self.events.iter().map(|e| e.event.clone()).collect()
+    }
+
+    /// events emitted by the call call_id, excluding the calls it made
+    pub fn call_events(&self, call_id: usize) -> Vec<&Event> {
+        self.events
+            .iter()
+            .filter(|e| e.call_id == call_id)
+            .map(|e| &e.event)
+            .collect()
+    }
+
+    /// events of type ty, e.g. wasm-swap or transfer
+    pub fn events_of_type(&self, ty: &str) -> Vec<&Event> {
+        self.events
+            .iter()
+            .filter(|e| e.event.ty == ty)
+            .map(|e| &e.event)
+            .collect()
+    }
+
+    pub fn append_gas_used(&mut self, gas_used: u64) {
+        self.gas_used += gas_used;
+        self.call_trace.add_gas_used(gas_used);
+    }
+
     pub fn append_stdout(&mut self, msg: &str) {
         self.stdout.push(msg.to_string())
     }
 
+    pub fn append_print(&mut self, level: PrintLevel, msg: &str) {
+        self.prints.push(Print {
+            level,
+            msg: msg.to_string(),
+            call_id: self.call_trace.current_call_id(),
+        });
+    }
+
+    /// messages printed at level or above
+    pub fn prints_at(&self, level: PrintLevel) -> Vec<&Print> {
+        self.prints.iter().filter(|p| p.level >= level).collect()
+    }
+
     pub fn get_stdout(&self) -> String {
         let mut rv = String::new();
         for msg in self.stdout.iter() {
@@ -140,6 +795,16 @@ impl DebugLog {
         self.call_trace.end_call(parent_call_id);
     }
 
+    pub fn begin_migrate(&mut self, contract_addr: &Addr, msg: &[u8]) -> usize {
+        let msg_json: serde_json::Value = serde_json::from_slice(msg).unwrap();
+        let context_name = format!("{}:migrate({})", contract_addr, msg_json);
+        self.call_trace.begin_call(&context_name)
+    }
+
+    pub fn end_migrate(&mut self, parent_call_id: usize) {
+        self.call_trace.end_call(parent_call_id);
+    }
+
     pub fn begin_reply(&mut self, contract_addr: &Addr, msg: &[u8]) -> usize {
         let msg_json: serde_json::Value = serde_json::from_slice(msg).unwrap();
         let context_name = format!("{}:reply({})", contract_addr, msg_json);
@@ -150,6 +815,27 @@ impl DebugLog {
         self.call_trace.end_call(parent_call_id);
     }
 
+    pub fn begin_sudo(&mut self, contract_addr: &Addr, msg: &[u8]) -> usize {
+        let msg_json: serde_json::Value = serde_json::from_slice(msg).unwrap();
+        let context_name = format!("{}:sudo({})", contract_addr, msg_json);
+        self.call_trace.begin_call(&context_name)
+    }
+
+    pub fn end_sudo(&mut self, parent_call_id: usize) {
+        self.call_trace.end_call(parent_call_id);
+    }
+
+    /// call of an ibc entry point, e.g. ibc_packet_receive
+    pub fn begin_ibc(&mut self, contract_addr: &Addr, entry_point: &str, msg: &[u8]) -> usize {
+        let msg_json: serde_json::Value = serde_json::from_slice(msg).unwrap();
+        let context_name = format!("{}:{}({})", contract_addr, entry_point, msg_json);
+        self.call_trace.begin_call(&context_name)
+    }
+
+    pub fn end_ibc(&mut self, parent_call_id: usize) {
+        self.call_trace.end_call(parent_call_id);
+    }
+
     pub fn begin_query(&mut self, contract_addr: &Addr, msg: &[u8]) -> usize {
         let msg_json: serde_json::Value = serde_json::from_slice(msg).unwrap();
         let context_name = format!("{}:query({})", contract_addr, msg_json);
@@ -164,6 +850,10 @@ impl DebugLog {
         self.call_trace.error(error_str);
     }
 
+    pub fn call_frames(&self) -> Vec<CallFrame> {
+        self.call_trace.frames()
+    }
+
     pub fn get_call_trace(&self) -> (HashMap<usize, Vec<usize>>, HashMap<usize, String>) {
         (
             self.call_trace.call_graph.clone(),
@@ -171,3 +861,166 @@ impl DebugLog {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{Addr, Binary, Response};
+
+    use super::{DebugLog, StorageChange};
+
+    #[test]
+    fn test_access_sets() {
+        let contract = Addr::unchecked("wasm1contract");
+        let mut reader = DebugLog::new();
+        reader.append_storage_read(&contract, b"balance");
+        let mut writer = DebugLog::new();
+        writer.append_storage_write(&contract, b"balance", None, Some(b"1"));
+        let mut other = DebugLog::new();
+        other.append_storage_write(&contract, b"config", None, Some(b"1"));
+
+        assert!(reader.conflicts_with(&writer));
+        assert!(writer.conflicts_with(&reader));
+        assert!(!reader.conflicts_with(&reader));
+        assert!(!writer.conflicts_with(&other));
+        assert_eq!(writer.write_set().len(), 1);
+        assert_eq!(writer.storage_trace().len(), 1);
+    }
+
+    #[test]
+    fn test_call_frames() {
+        let contract = Addr::unchecked("wasm1contract");
+        let mut log = DebugLog::new();
+        let outer_parent = log.begin_execute(&contract, b"{}");
+        log.append_gas_used(100);
+        let inner_parent = log.begin_execute(&contract, b"{\"inner\":{}}");
+        log.append_gas_used(20);
+        log.begin_error("inner failed");
+        // the error propagated to the caller is attributed to the call where it happened
+        log.begin_error("inner failed again");
+        log.end_execute(inner_parent);
+        log.end_execute(outer_parent);
+
+        let frames = log.call_frames();
+        assert_eq!(log.gas_used, 120);
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0].parent, None);
+        assert_eq!(frames[1].parent, Some(0));
+        assert_eq!(frames[1].gas_used, 100);
+        assert_eq!(frames[1].err_msg, None);
+        assert_eq!(frames[2].parent, Some(1));
+        assert_eq!(frames[2].gas_used, 20);
+        assert_eq!(frames[2].err_msg.as_deref(), Some("inner failed"));
+        assert_eq!(frames[3].label, "inner failed");
+    }
+
+    #[test]
+    fn test_call_trace_export() {
+        let contract = Addr::unchecked("wasm1contract");
+        let mut log = DebugLog::new();
+        let outer_parent = log.begin_execute(&contract, b"{}");
+        log.append_gas_used(100);
+        let inner_parent = log.begin_execute(&contract, b"{\"inner\":{}}");
+        log.append_gas_used(20);
+        log.begin_error("inner failed");
+        log.end_execute(inner_parent);
+        log.end_execute(outer_parent);
+
+        let dot = log.call_trace.to_dot();
+        assert!(dot.starts_with("digraph calls {"));
+        assert!(dot.contains("0 -> 1;"));
+        assert!(dot.contains("1 -> 2;"));
+        let failed = "2 [label=\"wasm1contract:execute({\\\"inner\\\":{}})\", color=red];";
+        assert!(dot.contains(failed));
+
+        let json = log.call_trace.to_json();
+        assert_eq!(json["label"], "top");
+        let inner = &json["children"][0]["children"][0];
+        assert_eq!(inner["gas_used"], 20);
+        assert_eq!(inner["err_msg"], "inner failed");
+        assert_eq!(inner["children"][0]["label"], "inner failed");
+
+        assert_eq!(
+            log.call_trace.to_folded(),
+            "top;wasm1contract:execute({}) 100\n\
+             top;wasm1contract:execute({});wasm1contract:execute({\"inner\":{}}) 20\n"
+        );
+    }
+
+    #[test]
+    fn test_set_names() {
+        let pair =
+            Addr::unchecked("wasm15le5evw4regnwf9lrjnpakr2075fcyp4n4yzpelvqcuevzkw2lss46hslz");
+        let token = Addr::unchecked("wasm1token");
+        let mut log = DebugLog::new();
+        let parent = log.begin_execute(&pair, b"{\"swap\":{}}");
+        log.begin_error("Generic error: Querier system error: no such contract");
+        log.end_execute(parent);
+        log.begin_query(&token, b"{}");
+        assert_eq!(
+            log.call_trace
+                .called_addresses()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![pair.clone(), token.clone()]
+        );
+
+        let names = vec![(pair.clone(), "astroport_pair".to_string())]
+            .into_iter()
+            .collect();
+        log.set_names(names);
+        let labels = &log.call_trace.call_graph_labels;
+        assert_eq!(
+            labels[&1],
+            "astroport_pair(wasm15le5e...):execute({\"swap\":{}})"
+        );
+        assert!(labels[&2].starts_with("Generic error"));
+        assert_eq!(labels[&3], "wasm1token:query({})");
+        assert_eq!(log.name_of(&pair), Some("astroport_pair"));
+    }
+
+    #[test]
+    fn test_display_and_serde() {
+        let contract = Addr::unchecked("wasm1contract");
+        let mut log = DebugLog::new();
+        log.set_sender(&Addr::unchecked("wasm1alice"), Some("alice"));
+        let outer_parent = log.begin_execute(&contract, b"{}");
+        log.append_gas_used(100);
+        log.append_log(&Response::new().add_attribute("action", "test"));
+        let inner_parent = log.begin_execute(&contract, b"{\"inner\":{}}");
+        log.append_gas_used(20);
+        log.set_err_msg("inner failed");
+        log.begin_error("inner failed");
+        log.end_execute(inner_parent);
+        log.end_execute(outer_parent);
+        log.storage_diff.insert(
+            contract,
+            vec![StorageChange {
+                key: Binary::from(b"key"),
+                old: None,
+                new: Some(Binary::from(b"\x01")),
+            }],
+        );
+
+        let expected = r#"sender: wasm1alice (alice)
+error: inner failed
+gas used: 120
+calls:
+  top
+    wasm1contract:execute({}) [gas 100]
+      wasm1contract:execute({"inner":{}}) [gas 20] [failed: inner failed]
+        inner failed
+logs:
+  {"attributes":[{"key":"action","value":"test"}],"events":[],"data":null}
+storage changes:
+  wasm1contract:
+    6b6579: none -> 01
+"#;
+        assert_eq!(log.to_string(), expected);
+
+        let json = serde_json::to_string(&log).unwrap();
+        let parsed: DebugLog = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.to_string(), expected);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+        assert_eq!(parsed.call_frames(), log.call_frames());
+    }
+}
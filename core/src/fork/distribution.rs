@@ -0,0 +1,38 @@
+use cosmwasm_std::{Addr, Coin};
+use std::collections::HashMap;
+
+/// keeper of the distribution module.
+/// Pending rewards of a delegation are seeded from chain on first use, and do not accrue during the simulation
+#[derive(Clone, Debug, Default)]
+pub struct Distribution {
+    // only set if it differs from the delegator
+    withdraw_addresses: HashMap<Addr, Addr>,
+    // pending rewards by (delegator, validator)
+    rewards: HashMap<(Addr, String), Vec<Coin>>,
+}
+
+impl Distribution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn withdraw_address(&self, delegator: &Addr) -> Option<&Addr> {
+        self.withdraw_addresses.get(delegator)
+    }
+
+    pub fn set_withdraw_address(&mut self, delegator: &Addr, withdraw_address: &Addr) {
+        self.withdraw_addresses
+            .insert(delegator.clone(), withdraw_address.clone());
+    }
+
+    /// pending rewards of the delegation, None if they were not fetched yet
+    pub fn rewards(&self, delegator: &Addr, validator: &str) -> Option<&Vec<Coin>> {
+        self.rewards
+            .get(&(delegator.clone(), validator.to_string()))
+    }
+
+    pub fn set_rewards(&mut self, delegator: &Addr, validator: &str, rewards: Vec<Coin>) {
+        self.rewards
+            .insert((delegator.clone(), validator.to_string()), rewards);
+    }
+}
@@ -0,0 +1,129 @@
+use cosmwasm_std::{Addr, Coin, Event, Response};
+
+/// coins formatted like the sdk, e.g. 100uatom,5ucosm
+pub(crate) fn coins_string(amount: &[Coin]) -> String {
+    let coins: Vec<String> = amount
+        .iter()
+        .map(|c| format!("{}{}", c.amount, c.denom))
+        .collect();
+    coins.join(",")
+}
+
+pub(crate) fn coin_spent_event(spender: &Addr, amount: &[Coin]) -> Event {
+    Event::new("coin_spent")
+        .add_attribute("spender", spender)
+        .add_attribute("amount", coins_string(amount))
+}
+
+pub(crate) fn coin_received_event(receiver: &Addr, amount: &[Coin]) -> Event {
+    Event::new("coin_received")
+        .add_attribute("receiver", receiver)
+        .add_attribute("amount", coins_string(amount))
+}
+
+pub(crate) fn message_event(sender: &Addr) -> Event {
+    Event::new("message").add_attribute("sender", sender)
+}
+
+/// the events of the bank module for a transfer, in the order of the sdk
+pub(crate) fn transfer_events(sender: &Addr, recipient: &Addr, amount: &[Coin]) -> Vec<Event> {
+    vec![
+        coin_spent_event(sender, amount),
+        coin_received_event(recipient, amount),
+        Event::new("transfer")
+            .add_attribute("recipient", recipient)
+            .add_attribute("sender", sender)
+            .add_attribute("amount", coins_string(amount)),
+        message_event(sender),
+    ]
+}
+
+pub(crate) fn burn_events(burner: &Addr, amount: &[Coin]) -> Vec<Event> {
+    vec![
+        coin_spent_event(burner, amount),
+        Event::new("burn")
+            .add_attribute("burner", burner)
+            .add_attribute("amount", coins_string(amount)),
+    ]
+}
+
+pub(crate) fn instantiate_event(contract_addr: &Addr, code_id: u64) -> Event {
+    Event::new("instantiate")
+        .add_attribute("_contract_address", contract_addr)
+        .add_attribute("code_id", code_id.to_string())
+}
+
+pub(crate) fn execute_event(contract_addr: &Addr) -> Event {
+    Event::new("execute").add_attribute("_contract_address", contract_addr)
+}
+
+pub(crate) fn migrate_event(contract_addr: &Addr, code_id: u64) -> Event {
+    Event::new("migrate")
+        .add_attribute("code_id", code_id.to_string())
+        .add_attribute("_contract_address", contract_addr)
+}
+
+pub(crate) fn reply_event(contract_addr: &Addr) -> Event {
+    Event::new("reply").add_attribute("_contract_address", contract_addr)
+}
+
+pub(crate) fn sudo_event(contract_addr: &Addr) -> Event {
+    Event::new("sudo").add_attribute("_contract_address", contract_addr)
+}
+
+/// the events wasmd emits for a response of contract_addr: a wasm event with the attributes
+/// of the response, and the custom events prefixed by wasm-, all tagged with _contract_address
+pub fn contract_events(contract_addr: &Addr, response: &Response) -> Vec<Event> {
+    let wasm = Event::new("wasm")
+        .add_attribute("_contract_address", contract_addr)
+        .add_attributes(response.attributes.clone());
+    let custom = response.events.iter().map(|e| {
+        Event::new(format!("wasm-{}", e.ty))
+            .add_attribute("_contract_address", contract_addr)
+            .add_attributes(e.attributes.clone())
+    });
+    std::iter::once(wasm).chain(custom).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coin, Addr, Event, Response};
+
+    use super::{coins_string, contract_events, transfer_events};
+
+    #[test]
+    fn test_contract_events() {
+        let contract = Addr::unchecked("contract");
+        let response = Response::new()
+            .add_attribute("action", "swap")
+            .add_event(Event::new("swapped").add_attribute("amount", "5"));
+        let events = contract_events(&contract, &response);
+        assert_eq!(
+            events,
+            vec![
+                Event::new("wasm")
+                    .add_attribute("_contract_address", "contract")
+                    .add_attribute("action", "swap"),
+                Event::new("wasm-swapped")
+                    .add_attribute("_contract_address", "contract")
+                    .add_attribute("amount", "5"),
+            ]
+        );
+        // every call emits a wasm event, even without attributes
+        assert_eq!(contract_events(&contract, &Response::new()).len(), 1);
+    }
+
+    #[test]
+    fn test_transfer_events() {
+        let amount = vec![coin(100, "uatom"), coin(5, "ucosm")];
+        assert_eq!(coins_string(&amount), "100uatom,5ucosm");
+        let events = transfer_events(&Addr::unchecked("alice"), &Addr::unchecked("bob"), &amount);
+        let types: Vec<&str> = events.iter().map(|e| e.ty.as_str()).collect();
+        assert_eq!(
+            types,
+            vec!["coin_spent", "coin_received", "transfer", "message"]
+        );
+        assert_eq!(events[2].attributes[1].value, "alice");
+        assert_eq!(events[2].attributes[2].value, "100uatom,5ucosm");
+    }
+}
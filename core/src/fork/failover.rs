@@ -0,0 +1,402 @@
+use crate::fork::client_backend::{CodeInfo, ContractInfo, DenomMetadata, TxInfo};
+use crate::fork::lcd::CwLcdClient;
+use crate::{CacheConfig, ContractStorage, CwClientBackend, CwRpcClient, Error};
+use cosmwasm_std::Timestamp;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// substrings of error messages of requests which may succeed when retried
+const TRANSIENT_MARKERS: &[&str] = &[
+    "429",
+    "too many requests",
+    "rate limit",
+    "timeout",
+    "timed out",
+    "connect",
+    "502",
+    "503",
+    "504",
+    "bad gateway",
+    "unavailable",
+    "reset",
+    "broken pipe",
+    "eof",
+    "dns",
+    "lookup",
+];
+
+/// whether a request which failed with e might succeed when retried,
+/// e.g. because it was rate limited, timed out or the connection failed
+pub fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::RpcError(msg, _) | Error::HttpError(msg, _) | Error::TokioError(msg, _) => {
+            let msg = msg.to_lowercase();
+            TRANSIENT_MARKERS.iter().any(|m| msg.contains(m))
+        }
+        _ => false,
+    }
+}
+
+/// how a FailoverClient retries transient failures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// retries of a single request, across all endpoints
+    pub max_retries: u32,
+    /// wait after every endpoint failed once, doubled each time up to max_backoff
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 8,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// wait before the given round of retries, starting from 0
+    pub fn backoff(&self, round: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(1 << round.min(31))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// client backend spreading requests over several endpoints of the same chain.
+/// Requests failing transiently are retried on the next endpoint, backing off exponentially
+/// once all endpoints failed. The endpoint that answered last is used for later requests
+#[derive(Clone)]
+pub struct FailoverClient {
+    endpoints: Vec<Box<dyn CwClientBackend>>,
+    active: usize,
+    retry: RetryConfig,
+}
+
+impl FailoverClient {
+    /// endpoints must be clients of the same chain, pinned to the same height
+    pub fn new(endpoints: Vec<Box<dyn CwClientBackend>>) -> Result<Self, Error> {
+        if endpoints.is_empty() {
+            return Err(Error::invalid_argument("no endpoints given"));
+        }
+        let block_number = endpoints[0].block_number();
+        if let Some(e) = endpoints.iter().find(|e| e.block_number() != block_number) {
+            return Err(Error::invalid_argument(format!(
+                "endpoint {} is at height {}, expected {}",
+                e.url(),
+                e.block_number(),
+                block_number
+            )));
+        }
+        let mut rv = Self {
+            endpoints,
+            active: 0,
+            retry: RetryConfig::default(),
+        };
+        let chain_id = rv.endpoints[0].chain_id()?;
+        for endpoint in rv.endpoints.iter_mut().skip(1) {
+            let other = endpoint.chain_id()?;
+            if other != chain_id {
+                return Err(Error::invalid_argument(format!(
+                    "endpoint {} serves chain {}, expected {}",
+                    endpoint.url(),
+                    other,
+                    chain_id
+                )));
+            }
+        }
+        Ok(rv)
+    }
+
+    /// rpc clients of urls, pinned to block_number or to the latest block of the first reachable one.
+    /// Unreachable urls are left out, it fails if none is reachable
+    pub fn rpc(urls: &[&str], block_number: Option<u64>) -> Result<Self, Error> {
+        let mut block_number = block_number;
+        let mut endpoints: Vec<Box<dyn CwClientBackend>> = Vec::new();
+        let mut last_err = Error::invalid_argument("no endpoints given");
+        for url in urls.iter() {
+            match CwRpcClient::new(url, block_number) {
+                Ok(client) => {
+                    block_number = Some(client.block_number());
+                    endpoints.push(Box::new(client));
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        if endpoints.is_empty() {
+            return Err(last_err);
+        }
+        Self::new(endpoints)
+    }
+
+    /// lcd clients of urls, pinned like FailoverClient::rpc. Unreachable urls are left out
+    pub fn lcd(urls: &[&str], block_number: Option<u64>) -> Result<Self, Error> {
+        let mut block_number = block_number;
+        let mut endpoints: Vec<Box<dyn CwClientBackend>> = Vec::new();
+        let mut last_err = Error::invalid_argument("no endpoints given");
+        for url in urls.iter() {
+            match CwLcdClient::new(url, block_number) {
+                Ok(client) => {
+                    block_number = Some(client.block_number());
+                    endpoints.push(Box::new(client));
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        if endpoints.is_empty() {
+            return Err(last_err);
+        }
+        Self::new(endpoints)
+    }
+
+    pub fn with_retry_config(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// urls of all endpoints, in the order they are tried
+    pub fn urls(&self) -> Vec<&str> {
+        self.endpoints.iter().map(|e| e.url()).collect()
+    }
+
+    fn call<T, F>(&mut self, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut(&mut dyn CwClientBackend) -> Result<T, Error>,
+    {
+        let mut retries = 0;
+        loop {
+            match f(self.endpoints[self.active].as_mut()) {
+                Err(e) if retries < self.retry.max_retries && is_transient(&e) => {
+                    retries += 1;
+                    self.active = (self.active + 1) % self.endpoints.len();
+                    let endpoints = self.endpoints.len() as u32;
+                    if retries % endpoints == 0 {
+                        thread::sleep(self.retry.backoff(retries / endpoints - 1));
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl CwClientBackend for FailoverClient {
+    fn block_number(&self) -> u64 {
+        self.endpoints[self.active].block_number()
+    }
+
+    fn url(&self) -> &str {
+        self.endpoints[self.active].url()
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        self.endpoints[self.active].cache_key()
+    }
+
+    fn cache_config(&self) -> Option<CacheConfig> {
+        self.endpoints[self.active].cache_config()
+    }
+
+    fn set_cache_config(&mut self, config: CacheConfig) -> Result<(), Error> {
+        for endpoint in self.endpoints.iter_mut() {
+            endpoint.set_cache_config(config.clone())?;
+        }
+        Ok(())
+    }
+
+    fn export_cache(&self, path: &Path) -> Result<(), Error> {
+        self.endpoints[self.active].export_cache(path)
+    }
+
+    /// every endpoint caches its responses separately, so all of them import them
+    fn import_cache(&mut self, path: &Path) -> Result<usize, Error> {
+        let mut count = 0;
+        for endpoint in self.endpoints.iter_mut() {
+            count = endpoint.import_cache(path)?;
+        }
+        Ok(count)
+    }
+
+    fn chain_id(&mut self) -> Result<String, Error> {
+        self.call(|c| c.chain_id())
+    }
+
+    fn timestamp(&mut self) -> Result<Timestamp, Error> {
+        self.call(|c| c.timestamp())
+    }
+
+    fn block_height(&mut self) -> Result<u64, Error> {
+        self.call(|c| c.block_height())
+    }
+
+    fn earliest_available_block(&mut self) -> Result<u64, Error> {
+        self.call(|c| c.earliest_available_block())
+    }
+
+    fn query_bank_all_balances(&mut self, address: &str) -> Result<Vec<(String, u128)>, Error> {
+        self.call(|c| c.query_bank_all_balances(address))
+    }
+
+    fn query_bank_supply(&mut self, denom: &str) -> Result<u128, Error> {
+        self.call(|c| c.query_bank_supply(denom))
+    }
+
+    fn query_bank_denom_metadata(&mut self, denom: &str) -> Result<DenomMetadata, Error> {
+        self.call(|c| c.query_bank_denom_metadata(denom))
+    }
+
+    fn query_bank_all_denom_metadata(&mut self) -> Result<Vec<DenomMetadata>, Error> {
+        self.call(|c| c.query_bank_all_denom_metadata())
+    }
+
+    fn query_distribution_rewards(
+        &mut self,
+        delegator: &str,
+        validator: &str,
+    ) -> Result<Vec<(String, u128)>, Error> {
+        self.call(|c| c.query_distribution_rewards(delegator, validator))
+    }
+
+    fn query_distribution_withdraw_address(&mut self, delegator: &str) -> Result<String, Error> {
+        self.call(|c| c.query_distribution_withdraw_address(delegator))
+    }
+
+    fn query_gov_latest_proposal_id(&mut self) -> Result<u64, Error> {
+        self.call(|c| c.query_gov_latest_proposal_id())
+    }
+
+    fn query_wasm_contract_smart(
+        &mut self,
+        address: &str,
+        query_data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        self.call(|c| c.query_wasm_contract_smart(address, query_data))
+    }
+
+    fn query_wasm_contract_state_all(
+        &mut self,
+        address: &str,
+    ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, Error> {
+        self.call(|c| c.query_wasm_contract_state_all(address))
+    }
+
+    fn query_wasm_contract_state_raw(
+        &mut self,
+        address: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.call(|c| c.query_wasm_contract_state_raw(address, key))
+    }
+
+    fn query_wasm_contract_info(&mut self, address: &str) -> Result<ContractInfo, Error> {
+        self.call(|c| c.query_wasm_contract_info(address))
+    }
+
+    fn query_wasm_contract_code(&mut self, code_id: u64) -> Result<Vec<u8>, Error> {
+        self.call(|c| c.query_wasm_contract_code(code_id))
+    }
+
+    fn query_wasm_code_info(&mut self, code_id: u64) -> Result<CodeInfo, Error> {
+        self.call(|c| c.query_wasm_code_info(code_id))
+    }
+
+    fn query_wasm_contracts_state_all(
+        &mut self,
+        addresses: &[String],
+    ) -> Result<Vec<ContractStorage>, Error> {
+        self.call(|c| c.query_wasm_contracts_state_all(addresses))
+    }
+
+    fn query_wasm_contracts_info(
+        &mut self,
+        addresses: &[String],
+    ) -> Result<Vec<ContractInfo>, Error> {
+        self.call(|c| c.query_wasm_contracts_info(addresses))
+    }
+
+    fn query_wasm_contract_codes(&mut self, code_ids: &[u64]) -> Result<Vec<Vec<u8>>, Error> {
+        self.call(|c| c.query_wasm_contract_codes(code_ids))
+    }
+
+    fn query_grpc(&mut self, path: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.call(|c| c.query_grpc(path, data))
+    }
+
+    fn query_tx(&mut self, hash: &str) -> Result<TxInfo, Error> {
+        self.call(|c| c.query_tx(hash))
+    }
+
+    fn query_block_txs(&mut self, height: u64) -> Result<Vec<TxInfo>, Error> {
+        self.call(|c| c.query_block_txs(height))
+    }
+
+    fn at_height(&self, height: u64) -> Result<Box<dyn CwClientBackend>, Error> {
+        let endpoints = self
+            .endpoints
+            .iter()
+            .map(|e| e.at_height(height))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Box::new(Self {
+            endpoints,
+            active: self.active,
+            retry: self.retry,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{is_transient, FailoverClient, RetryConfig};
+    use crate::{CwClientBackend, Error};
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2246678;
+
+    #[test]
+    fn test_failover_rpc() {
+        // unreachable endpoints are left out
+        let mut client = FailoverClient::rpc(
+            &["http://localhost:1", MALAGA_RPC_URL],
+            Some(MALAGA_BLOCK_NUMBER),
+        )
+        .unwrap();
+        assert_eq!(client.urls(), vec![MALAGA_RPC_URL]);
+        assert_eq!(client.block_number(), MALAGA_BLOCK_NUMBER);
+        assert_eq!(client.chain_id().unwrap(), "malaga-420");
+        assert!(FailoverClient::rpc(&["http://localhost:1"], Some(MALAGA_BLOCK_NUMBER)).is_err());
+    }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(is_transient(&Error::http_error("429 Too Many Requests")));
+        assert!(is_transient(&Error::rpc_error(
+            "connection refused (os error 111)"
+        )));
+        assert!(is_transient(&Error::rpc_error("request timed out")));
+        // the node answered, retrying won't help
+        assert!(!is_transient(&Error::http_error("contract: not found")));
+        assert!(!is_transient(&Error::tendermint_error("timeout")));
+        assert!(!is_transient(&Error::format_error("connection")));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry = RetryConfig {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        assert_eq!(retry.backoff(0), Duration::from_millis(100));
+        assert_eq!(retry.backoff(2), Duration::from_millis(400));
+        assert_eq!(retry.backoff(4), Duration::from_secs(1));
+        assert_eq!(retry.backoff(100), Duration::from_secs(1));
+    }
+}
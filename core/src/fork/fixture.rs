@@ -0,0 +1,36 @@
+use cosmwasm_std::{Binary, Coin};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// a key of the storage of a contract and its value, base64 encoded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageEntry {
+    pub key: Binary,
+    pub value: Binary,
+}
+
+/// a contract as exported by Model::export_contract, which Model::import_contract loads without
+/// any network access. The code itself is not part of the fixture, only its checksum
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractFixture {
+    /// hex encoded sha256 of the wasm code
+    pub checksum: String,
+    pub code_id: u64,
+    pub creator: String,
+    pub admin: Option<String>,
+    pub label: String,
+    /// the whole storage, in the order of keys
+    pub storage: Vec<StorageEntry>,
+    pub balances: Vec<Coin>,
+}
+
+impl ContractFixture {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::from)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(Error::from)
+    }
+}
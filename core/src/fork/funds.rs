@@ -0,0 +1,107 @@
+use cosmwasm_std::{Coin, Uint128};
+use std::collections::BTreeMap;
+
+use crate::Error;
+
+/// fails if denom does not match [a-zA-Z][a-zA-Z0-9/:._-]{2,127}, the denom format of cosmos-sdk
+pub fn validate_denom(denom: &str) -> Result<(), Error> {
+    let mut chars = denom.chars();
+    let valid = (3..=128).contains(&denom.len())
+        && chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::invalid_argument(format!(
+            "invalid denom \"{}\" in funds, denoms must match [a-zA-Z][a-zA-Z0-9/:._-]{{2,127}}",
+            denom
+        )))
+    }
+}
+
+/// fails on the funds the chain rejects in a transaction: invalid denoms, zero amounts,
+/// duplicate denoms and denoms not sorted in ascending order
+pub fn validate_funds(funds: &[Coin]) -> Result<(), Error> {
+    for (i, coin) in funds.iter().enumerate() {
+        validate_denom(&coin.denom)?;
+        if coin.amount.is_zero() {
+            return Err(Error::invalid_argument(format!(
+                "zero amount of {} in funds",
+                coin.denom
+            )));
+        }
+        if let Some(previous) = i.checked_sub(1).map(|i| &funds[i]) {
+            if previous.denom == coin.denom {
+                return Err(Error::invalid_argument(format!(
+                    "duplicate denom {} in funds",
+                    coin.denom
+                )));
+            }
+            if previous.denom > coin.denom {
+                return Err(Error::invalid_argument(format!(
+                    "funds are not sorted by denom, {} comes before {}",
+                    previous.denom, coin.denom
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// adds up the amounts of duplicate denoms and drops zero amounts, sorting funds by denom.
+/// Fails on invalid denoms and on amounts which overflow
+pub fn merge_funds(funds: &[Coin]) -> Result<Vec<Coin>, Error> {
+    let mut merged: BTreeMap<&str, Uint128> = BTreeMap::new();
+    for coin in funds.iter() {
+        validate_denom(&coin.denom)?;
+        let amount = merged.entry(coin.denom.as_str()).or_default();
+        *amount = amount
+            .checked_add(coin.amount)
+            .map_err(|e| Error::invalid_argument(format!("funds of {}: {}", coin.denom, e)))?;
+    }
+    Ok(merged
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(denom, amount)| Coin::new(amount.u128(), denom))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coin, Coin};
+
+    use super::{merge_funds, validate_denom, validate_funds};
+
+    #[test]
+    fn test_validate_funds() {
+        assert!(validate_denom("umlg").is_ok());
+        assert!(validate_denom(
+            "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2"
+        )
+        .is_ok());
+        assert!(validate_denom("factory/wasm1abc/token").is_ok());
+        assert!(validate_denom("u").is_err());
+        assert!(validate_denom("1atom").is_err());
+        assert!(validate_denom("u mlg").is_err());
+
+        assert!(validate_funds(&[]).is_ok());
+        assert!(validate_funds(&[coin(1, "uatom"), coin(2, "umlg")]).is_ok());
+        let err = validate_funds(&[coin(1, "umlg"), coin(2, "umlg")]).unwrap_err();
+        assert!(err.to_string().contains("duplicate denom umlg"));
+        let err = validate_funds(&[coin(0, "umlg")]).unwrap_err();
+        assert!(err.to_string().contains("zero amount of umlg"));
+        let err = validate_funds(&[coin(2, "umlg"), coin(1, "uatom")]).unwrap_err();
+        assert!(err.to_string().contains("not sorted"));
+
+        let merged = merge_funds(&[
+            coin(2, "umlg"),
+            coin(0, "uosmo"),
+            coin(1, "uatom"),
+            coin(3, "umlg"),
+        ])
+        .unwrap();
+        assert_eq!(merged, vec![coin(1, "uatom"), coin(5, "umlg")]);
+        assert!(merge_funds(&[Coin::new(u128::MAX, "umlg"), coin(1, "umlg")]).is_err());
+        assert!(merge_funds(&[coin(1, "u")]).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use cosmwasm_std::{Addr, Binary, Coin, VoteOption};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProposalStatus {
+    VotingPeriod,
+    Passed,
+    Rejected,
+    /// passed, but executing its messages failed
+    Failed,
+}
+
+/// a proposal submitted during the simulation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Addr,
+    /// (type url, protobuf encoded message) executed by the gov module once the proposal passes,
+    /// always empty for legacy proposals
+    pub messages: Vec<(String, Binary)>,
+    pub deposits: Vec<(Addr, Vec<Coin>)>,
+    pub status: ProposalStatus,
+}
+
+/// keeper of the gov module.
+/// Votes are recorded but never tallied, proposals only pass or are rejected through cheats
+#[derive(Clone, Debug, Default)]
+pub struct Gov {
+    proposals: BTreeMap<u64, Proposal>,
+    // votes on proposals submitted on chain are recorded as well
+    votes: BTreeMap<(u64, Addr), VoteOption>,
+    // None until it is seeded from the latest proposal on chain
+    next_proposal_id: Option<u64>,
+}
+
+impl Gov {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn next_proposal_id(&self) -> Option<u64> {
+        self.next_proposal_id
+    }
+
+    pub fn set_next_proposal_id(&mut self, proposal_id: u64) {
+        self.next_proposal_id = Some(proposal_id);
+    }
+
+    /// stores a new proposal in voting period, returning its id.
+    /// The next proposal id must have been set before
+    pub fn submit_proposal(
+        &mut self,
+        proposer: &Addr,
+        messages: Vec<(String, Binary)>,
+        deposit: Vec<Coin>,
+    ) -> u64 {
+        let id = self.next_proposal_id.expect("next proposal id is not set");
+        self.next_proposal_id = Some(id + 1);
+        let deposits = if deposit.is_empty() {
+            Vec::new()
+        } else {
+            vec![(proposer.clone(), deposit)]
+        };
+        self.proposals.insert(
+            id,
+            Proposal {
+                id,
+                proposer: proposer.clone(),
+                messages,
+                deposits,
+                status: ProposalStatus::VotingPeriod,
+            },
+        );
+        id
+    }
+
+    pub fn proposal(&self, proposal_id: u64) -> Option<&Proposal> {
+        self.proposals.get(&proposal_id)
+    }
+
+    pub fn proposal_mut(&mut self, proposal_id: u64) -> Option<&mut Proposal> {
+        self.proposals.get_mut(&proposal_id)
+    }
+
+    /// fails if the proposal is not in voting period.
+    /// Proposals submitted on chain are assumed to be in voting period
+    fn check_active(&self, proposal_id: u64) -> Result<(), String> {
+        match self.proposals.get(&proposal_id) {
+            Some(p) if p.status != ProposalStatus::VotingPeriod => {
+                Err(format!("inactive proposal: {}", proposal_id))
+            }
+            None if Some(proposal_id) >= self.next_proposal_id => {
+                Err(format!("unknown proposal: {}", proposal_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// records the vote of voter, replacing their previous one
+    pub fn vote(
+        &mut self,
+        proposal_id: u64,
+        voter: &Addr,
+        option: VoteOption,
+    ) -> Result<(), String> {
+        self.check_active(proposal_id)?;
+        self.votes.insert((proposal_id, voter.clone()), option);
+        Ok(())
+    }
+
+    pub fn votes(&self, proposal_id: u64) -> Vec<(Addr, VoteOption)> {
+        self.votes
+            .range((proposal_id, Addr::unchecked(""))..)
+            .take_while(|((id, _), _)| *id == proposal_id)
+            .map(|((_, voter), option)| (voter.clone(), option.clone()))
+            .collect()
+    }
+
+    /// adds a deposit to a proposal submitted during the simulation
+    pub fn deposit(
+        &mut self,
+        proposal_id: u64,
+        depositor: &Addr,
+        amount: Vec<Coin>,
+    ) -> Result<(), String> {
+        self.check_active(proposal_id)?;
+        match self.proposals.get_mut(&proposal_id) {
+            Some(proposal) => {
+                proposal.deposits.push((depositor.clone(), amount));
+                Ok(())
+            }
+            None => Err(format!(
+                "deposits to proposal {} submitted on chain are not supported",
+                proposal_id
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{Addr, VoteOption};
+
+    use super::{Gov, ProposalStatus};
+
+    #[test]
+    fn test_votes() {
+        let alice = Addr::unchecked("wasm1alice");
+        let bob = Addr::unchecked("wasm1bob");
+        let mut gov = Gov::new();
+        gov.set_next_proposal_id(10);
+        let id = gov.submit_proposal(&alice, vec![], vec![]);
+        assert_eq!(id, 10);
+        assert_eq!(gov.next_proposal_id(), Some(11));
+
+        gov.vote(id, &alice, VoteOption::No).unwrap();
+        gov.vote(id, &alice, VoteOption::Yes).unwrap();
+        gov.vote(id, &bob, VoteOption::Abstain).unwrap();
+        // proposals submitted on chain can be voted on, but not unknown ones
+        gov.vote(3, &bob, VoteOption::Yes).unwrap();
+        assert!(gov.vote(11, &bob, VoteOption::Yes).is_err());
+        assert_eq!(
+            gov.votes(id),
+            vec![(alice, VoteOption::Yes), (bob.clone(), VoteOption::Abstain)]
+        );
+
+        gov.proposal_mut(id).unwrap().status = ProposalStatus::Passed;
+        assert!(gov.vote(id, &bob, VoteOption::No).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use cosmwasm_std::{Addr, Coin, ContractResult, Response};
+use std::sync::Mutex;
+
+/// entry point of a contract call seen by ExecutionHooks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallKind {
+    Instantiate,
+    Execute,
+    Migrate,
+    Reply,
+    Sudo,
+}
+
+/// callbacks observing executions as they happen, e.g. to monitor bank sends from a vault.
+/// Calls which are reverted later are seen as well.
+/// Hooks run while the model is borrowed, they must not call back into it
+pub trait ExecutionHooks: Send + Sync {
+    /// msg is the json encoded message, or the json encoded Reply of reply calls
+    fn on_call_start(&self, _kind: CallKind, _contract: &Addr, _sender: &Addr, _msg: &[u8]) {}
+
+    fn on_call_end(&self, _kind: CallKind, _contract: &Addr, _result: &ContractResult<Response>) {}
+
+    /// coins moved between accounts by bank sends, funds of calls, ibc escrows and module accounts.
+    /// Mints and burns are not transfers
+    fn on_bank_transfer(&self, _from: &Addr, _to: &Addr, _amount: &[Coin]) {}
+
+    /// value is None if the key was removed
+    fn on_storage_write(&self, _contract: &Addr, _key: &[u8], _value: Option<&[u8]>) {}
+
+    /// a contract requested a breakpoint through the printer, call is the label of the running call
+    /// in the call trace. Execution resumes when this returns
+    fn on_breakpoint(&self, _label: &str, _call: &str) {}
+}
+
+/// a lifecycle event recorded by HookRecorder
+#[derive(Clone, Debug, PartialEq)]
+pub enum HookEvent {
+    CallStart {
+        kind: CallKind,
+        contract: Addr,
+        sender: Addr,
+    },
+    CallEnd {
+        kind: CallKind,
+        contract: Addr,
+        /// the error of failed calls
+        error: Option<String>,
+    },
+    BankTransfer {
+        from: Addr,
+        to: Addr,
+        amount: Vec<Coin>,
+    },
+    StorageWrite {
+        contract: Addr,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+    Breakpoint {
+        label: String,
+        call: String,
+    },
+}
+
+/// hooks recording every event in order
+#[derive(Default)]
+pub struct HookRecorder {
+    events: Mutex<Vec<HookEvent>>,
+}
+
+impl HookRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<HookEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+
+    fn push(&self, event: HookEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl ExecutionHooks for HookRecorder {
+    fn on_call_start(&self, kind: CallKind, contract: &Addr, sender: &Addr, _msg: &[u8]) {
+        self.push(HookEvent::CallStart {
+            kind,
+            contract: contract.clone(),
+            sender: sender.clone(),
+        });
+    }
+
+    fn on_call_end(&self, kind: CallKind, contract: &Addr, result: &ContractResult<Response>) {
+        self.push(HookEvent::CallEnd {
+            kind,
+            contract: contract.clone(),
+            error: match result {
+                ContractResult::Ok(_) => None,
+                ContractResult::Err(e) => Some(e.clone()),
+            },
+        });
+    }
+
+    fn on_bank_transfer(&self, from: &Addr, to: &Addr, amount: &[Coin]) {
+        self.push(HookEvent::BankTransfer {
+            from: from.clone(),
+            to: to.clone(),
+            amount: amount.to_vec(),
+        });
+    }
+
+    fn on_storage_write(&self, contract: &Addr, key: &[u8], value: Option<&[u8]>) {
+        self.push(HookEvent::StorageWrite {
+            contract: contract.clone(),
+            key: key.to_vec(),
+            value: value.map(|v| v.to_vec()),
+        });
+    }
+
+    fn on_breakpoint(&self, label: &str, call: &str) {
+        self.push(HookEvent::Breakpoint {
+            label: label.to_string(),
+            call: call.to_string(),
+        });
+    }
+}
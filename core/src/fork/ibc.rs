@@ -0,0 +1,376 @@
+use crate::fork::api::canonical_to_human;
+use crate::Error;
+use cosmwasm_std::{
+    to_binary, Addr, Binary, IbcChannel, IbcEndpoint, IbcOrder, IbcPacket, IbcTimeout, Timestamp,
+    Uint128,
+};
+use cosmwasm_vm::internals::{deserialize_wasm, has_ibc_entry_points};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// port of the ICS20 transfer module
+pub const TRANSFER_PORT: &str = "transfer";
+pub const ICS20_VERSION: &str = "ics20-1";
+const WASM_PORT_PREFIX: &str = "wasm.";
+const CHANNEL_PREFIX: &str = "channel-";
+const VOUCHER_PREFIX: &str = "ibc/";
+
+/// port bound to the contract at contract_addr, as in wasmd
+pub fn contract_port(contract_addr: &Addr) -> String {
+    format!("{}{}", WASM_PORT_PREFIX, contract_addr)
+}
+
+/// the port wasmd binds to a contract when it is instantiated, None if its code does not export
+/// every ibc entry point
+pub fn bound_port(contract_addr: &Addr, code: &[u8]) -> Result<Option<String>, Error> {
+    let module = deserialize_wasm(code).map_err(Error::from)?;
+    Ok(has_ibc_entry_points(&module).then(|| contract_port(contract_addr)))
+}
+
+/// the contract bound to port_id, None for ports of native modules
+pub fn port_contract(port_id: &str) -> Option<Addr> {
+    port_id.strip_prefix(WASM_PORT_PREFIX).map(Addr::unchecked)
+}
+
+/// packet data of ICS20 transfers
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FungibleTokenPacketData {
+    /// full denom path, e.g. transfer/channel-0/uatom
+    pub denom: String,
+    pub amount: Uint128,
+    pub sender: String,
+    pub receiver: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub memo: String,
+}
+
+/// acknowledgement written by the transfer module, and for packets whose receipt failed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Acknowledgement {
+    Result(Binary),
+    Error(String),
+}
+
+impl Acknowledgement {
+    pub fn success() -> Binary {
+        to_binary(&Self::Result(Binary::from(vec![1]))).unwrap()
+    }
+
+    pub fn error<T: ToString>(msg: T) -> Binary {
+        to_binary(&Self::Error(msg.to_string())).unwrap()
+    }
+
+    /// the error of an ICS20 style acknowledgement, None if it is a success
+    pub fn error_of(ack: &Binary) -> Option<String> {
+        match cosmwasm_std::from_binary(ack) {
+            Ok(Self::Error(e)) => Some(e),
+            Ok(Self::Result(_)) => None,
+            Err(_) => Some(format!("invalid acknowledgement: {}", ack)),
+        }
+    }
+}
+
+/// the ibc/{hash} denom of the vouchers of a full denom path, the base denom if it has no path
+pub fn local_denom(denom_path: &str) -> String {
+    if denom_path.contains('/') {
+        format!(
+            "{}{}",
+            VOUCHER_PREFIX,
+            hex::encode_upper(Sha256::digest(denom_path.as_bytes()))
+        )
+    } else {
+        denom_path.to_string()
+    }
+}
+
+/// account holding the coins sent to other chains over a transfer channel
+pub fn escrow_address(port_id: &str, channel_id: &str, bech32_prefix: &str) -> Addr {
+    let mut pre_image = ICS20_VERSION.as_bytes().to_vec();
+    pre_image.push(0);
+    pre_image.extend_from_slice(format!("{}/{}", port_id, channel_id).as_bytes());
+    let hash = Sha256::digest(&pre_image);
+    Addr::unchecked(canonical_to_human(&hash[..20], bech32_prefix, 20).unwrap())
+}
+
+/// whether a packet with timeout can no longer be received by a chain at height and time
+pub fn is_timed_out(timeout: &IbcTimeout, height: u64, time: Timestamp) -> bool {
+    let by_height = timeout.block().is_some_and(|b| height >= b.height);
+    let by_time = timeout.timestamp().is_some_and(|t| time >= t);
+    by_height || by_time
+}
+
+/// an end of a channel opened during the simulation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelEnd {
+    pub channel: IbcChannel,
+    pub open: bool,
+    next_sequence_send: u64,
+    next_sequence_recv: u64,
+}
+
+/// keeper of the IBC channels opened during the simulation, and of the packets sent over them.
+/// Channels opened on chain before the fork are not known
+#[derive(Clone, Debug, Default)]
+pub struct Ibc {
+    channels: BTreeMap<String, ChannelEnd>,
+    // full denom paths of the vouchers received, by voucher denom
+    denom_traces: BTreeMap<String, String>,
+    // packets sent but not relayed yet, oldest first
+    outbox: Vec<IbcPacket>,
+}
+
+impl Ibc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// id of the next channel opened, channel ids are never reused
+    pub fn next_channel_id(&self) -> String {
+        format!("{}{}", CHANNEL_PREFIX, self.channels.len())
+    }
+
+    /// registers an open channel, whose id must be the next channel id
+    pub fn add_channel(&mut self, channel: IbcChannel) -> Result<(), String> {
+        let channel_id = channel.endpoint.channel_id.clone();
+        if channel_id != self.next_channel_id() {
+            return Err(format!(
+                "invalid channel id {}, expected {}",
+                channel_id,
+                self.next_channel_id()
+            ));
+        }
+        self.channels.insert(
+            channel_id,
+            ChannelEnd {
+                channel,
+                open: true,
+                next_sequence_send: 1,
+                next_sequence_recv: 1,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn channel(&self, channel_id: &str) -> Option<&ChannelEnd> {
+        self.channels.get(channel_id)
+    }
+
+    /// open channels bound to port_id
+    pub fn channels(&self, port_id: &str) -> Vec<IbcChannel> {
+        self.channels
+            .values()
+            .filter(|c| c.open && c.channel.endpoint.port_id == port_id)
+            .map(|c| c.channel.clone())
+            .collect()
+    }
+
+    /// every channel opened so far, including closed ones
+    pub fn all_channels(&self) -> Vec<ChannelEnd> {
+        self.channels.values().cloned().collect()
+    }
+
+    /// the open channel channel_id, if it is bound to port_id
+    fn open_channel(&self, port_id: &str, channel_id: &str) -> Result<&ChannelEnd, String> {
+        match self.channels.get(channel_id) {
+            Some(c) if c.channel.endpoint.port_id != port_id => Err(format!(
+                "channel {} is not bound to port {}",
+                channel_id, port_id
+            )),
+            Some(c) if !c.open => Err(format!("channel {} is closed", channel_id)),
+            Some(c) => Ok(c),
+            None => Err(format!("channel {} not found", channel_id)),
+        }
+    }
+
+    pub fn close_channel(&mut self, port_id: &str, channel_id: &str) -> Result<IbcChannel, String> {
+        self.open_channel(port_id, channel_id)?;
+        let end = self.channels.get_mut(channel_id).unwrap();
+        end.open = false;
+        Ok(end.channel.clone())
+    }
+
+    /// queues a packet sent by port_id over channel_id, to be relayed
+    pub fn send_packet(
+        &mut self,
+        port_id: &str,
+        channel_id: &str,
+        data: Binary,
+        timeout: IbcTimeout,
+    ) -> Result<IbcPacket, String> {
+        self.open_channel(port_id, channel_id)?;
+        let end = self.channels.get_mut(channel_id).unwrap();
+        let packet = IbcPacket::new(
+            data,
+            end.channel.endpoint.clone(),
+            end.channel.counterparty_endpoint.clone(),
+            end.next_sequence_send,
+            timeout,
+        );
+        end.next_sequence_send += 1;
+        self.outbox.push(packet.clone());
+        Ok(packet)
+    }
+
+    /// checks that packet can be received, packets of ordered channels must arrive in order
+    pub fn receive_packet(&mut self, packet: &IbcPacket) -> Result<(), String> {
+        let IbcEndpoint {
+            port_id,
+            channel_id,
+        } = &packet.dest;
+        let end = self.open_channel(port_id, channel_id)?;
+        if end.channel.counterparty_endpoint != packet.src {
+            return Err(format!(
+                "packet source {}/{} is not the counterparty of channel {}",
+                packet.src.port_id, packet.src.channel_id, channel_id
+            ));
+        }
+        if end.channel.order == IbcOrder::Ordered {
+            if packet.sequence != end.next_sequence_recv {
+                return Err(format!(
+                    "packet sequence {} != next receive sequence {} of ordered channel {}",
+                    packet.sequence, end.next_sequence_recv, channel_id
+                ));
+            }
+            self.channels
+                .get_mut(channel_id)
+                .unwrap()
+                .next_sequence_recv += 1;
+        }
+        Ok(())
+    }
+
+    /// packets sent but not relayed yet, oldest first
+    pub fn pending_packets(&self) -> &[IbcPacket] {
+        &self.outbox
+    }
+
+    pub fn take_packets(&mut self) -> Vec<IbcPacket> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// full denom path of a voucher received during the simulation
+    pub fn denom_trace(&self, denom: &str) -> Option<&str> {
+        self.denom_traces.get(denom).map(|t| t.as_str())
+    }
+
+    /// records the path of a received voucher, returning its denom
+    pub fn add_denom_trace(&mut self, denom_path: &str) -> String {
+        let denom = local_denom(denom_path);
+        if denom != denom_path {
+            self.denom_traces
+                .insert(denom.clone(), denom_path.to_string());
+        }
+        denom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        Addr, Binary, IbcChannel, IbcEndpoint, IbcOrder, IbcTimeout, IbcTimeoutBlock, Timestamp,
+    };
+
+    use super::{
+        contract_port, escrow_address, is_timed_out, local_denom, port_contract, Acknowledgement,
+        Ibc,
+    };
+
+    fn channel(order: IbcOrder) -> IbcChannel {
+        IbcChannel::new(
+            IbcEndpoint {
+                port_id: "transfer".to_string(),
+                channel_id: "channel-0".to_string(),
+            },
+            IbcEndpoint {
+                port_id: "transfer".to_string(),
+                channel_id: "channel-7".to_string(),
+            },
+            order,
+            "ics20-1",
+            "connection-0",
+        )
+    }
+
+    #[test]
+    fn test_ibc_packets() {
+        let mut ibc = Ibc::new();
+        let mut wrong_id = channel(IbcOrder::Ordered);
+        wrong_id.endpoint.channel_id = "channel-1".to_string();
+        assert!(ibc.add_channel(wrong_id).is_err());
+        ibc.add_channel(channel(IbcOrder::Ordered)).unwrap();
+        assert_eq!(ibc.next_channel_id(), "channel-1");
+
+        let timeout = IbcTimeout::with_timestamp(Timestamp::from_seconds(100));
+        assert!(ibc
+            .send_packet("wasm.x", "channel-0", Binary::default(), timeout.clone())
+            .is_err());
+        let first = ibc
+            .send_packet("transfer", "channel-0", Binary::default(), timeout.clone())
+            .unwrap();
+        let second = ibc
+            .send_packet("transfer", "channel-0", Binary::default(), timeout)
+            .unwrap();
+        assert_eq!((first.sequence, second.sequence), (1, 2));
+        assert_eq!(first.dest.channel_id, "channel-7");
+        assert_eq!(ibc.take_packets(), vec![first.clone(), second.clone()]);
+        assert!(ibc.pending_packets().is_empty());
+
+        // packets coming back over the channel are received in order
+        let incoming = |p: &cosmwasm_std::IbcPacket| {
+            let mut p = p.clone();
+            std::mem::swap(&mut p.src, &mut p.dest);
+            p
+        };
+        assert!(ibc.receive_packet(&incoming(&second)).is_err());
+        ibc.receive_packet(&incoming(&first)).unwrap();
+        ibc.receive_packet(&incoming(&second)).unwrap();
+
+        ibc.close_channel("transfer", "channel-0").unwrap();
+        assert!(ibc.channels("transfer").is_empty());
+        assert!(ibc.receive_packet(&incoming(&first)).is_err());
+    }
+
+    #[test]
+    fn test_ibc_helpers() {
+        let contract = Addr::unchecked("wasm1contract");
+        assert_eq!(contract_port(&contract), "wasm.wasm1contract");
+        assert_eq!(port_contract(&contract_port(&contract)), Some(contract));
+        assert_eq!(port_contract("transfer"), None);
+
+        // the denom of ATOM on osmosis
+        assert_eq!(
+            local_denom("transfer/channel-0/uatom"),
+            "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2"
+        );
+        assert_eq!(local_denom("uosmo"), "uosmo");
+        assert_eq!(
+            escrow_address("transfer", "channel-0", "cosmos").as_str(),
+            "cosmos1a53udazy8ayufvy0s434pfwjcedzqv34kvz9tw"
+        );
+
+        let ack = Acknowledgement::success();
+        assert_eq!(
+            ack.to_string(),
+            Binary::from(br#"{"result":"AQ=="}"#).to_string()
+        );
+        assert_eq!(Acknowledgement::error_of(&ack), None);
+        let ack = Acknowledgement::error("insufficient funds");
+        assert_eq!(
+            Acknowledgement::error_of(&ack),
+            Some("insufficient funds".to_string())
+        );
+
+        let timeout = IbcTimeout::with_both(
+            IbcTimeoutBlock {
+                revision: 1,
+                height: 10,
+            },
+            Timestamp::from_seconds(100),
+        );
+        assert!(!is_timed_out(&timeout, 9, Timestamp::from_seconds(99)));
+        assert!(is_timed_out(&timeout, 10, Timestamp::from_seconds(99)));
+        assert!(is_timed_out(&timeout, 9, Timestamp::from_seconds(100)));
+    }
+}
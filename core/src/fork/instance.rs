@@ -1,36 +1,129 @@
 use cosmwasm_std::{
-    to_binary, Addr, Binary, Coin, ContractInfo, ContractResult, Env, MessageInfo, Reply, Response,
-    WasmQuery,
+    from_slice, to_vec, Addr, Binary, Coin, ContractInfo, ContractResult, Env,
+    Ibc3ChannelOpenResponse, IbcBasicResponse, IbcChannelCloseMsg, IbcChannelConnectMsg,
+    IbcChannelOpenMsg, IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg,
+    IbcReceiveResponse, MessageInfo, Reply, Response, WasmQuery,
 };
+use cosmwasm_vm::internals::take_failed_grow;
 use cosmwasm_vm::{
-    call_execute, call_instantiate, call_query, call_reply, Instance, Storage, VmError,
+    call_execute, call_ibc_channel_close, call_ibc_channel_close_raw, call_ibc_channel_connect,
+    call_ibc_channel_connect_raw, call_ibc_channel_open, call_ibc_channel_open_raw,
+    call_ibc_packet_ack, call_ibc_packet_ack_raw, call_ibc_packet_receive,
+    call_ibc_packet_receive_raw, call_ibc_packet_timeout, call_ibc_packet_timeout_raw,
+    call_instantiate, call_migrate, call_query, call_reply, call_sudo, Instance, Storage, VmError,
+    VmResult,
 };
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
+use crate::fork::compat::{
+    legacy_channel_close_msg, legacy_channel_connect_msg, legacy_channel_open_msg,
+    legacy_packet_ack_msg, legacy_packet_receive_msg, legacy_packet_timeout_msg,
+    LEGACY_INTERFACE_VERSION,
+};
 use crate::fork::{querier::RpcMockQuerier, RpcBackend, RpcMockApi, RpcMockStorage};
 use crate::Error;
 
 pub type RpcInstance = Instance<RpcMockApi, RpcMockStorage, RpcMockQuerier>;
 
+/// the messages, attributes and events of an ibc entry point as a Response
+fn basic_response(result: ContractResult<IbcBasicResponse>) -> ContractResult<Response> {
+    result
+        .into_result()
+        .map(|r| {
+            Response::new()
+                .add_submessages(r.messages)
+                .add_attributes(r.attributes)
+                .add_events(r.events)
+        })
+        .into()
+}
+
 pub struct RpcContractInstance {
     contract_info: ContractInfo,
     pub instance: RpcInstance,
+    // memory limit the module of the instance was compiled with, in bytes
+    memory_limit: Option<usize>,
+    // code id of the contract, for the context of errors
+    code_id: Option<u64>,
 }
 
 impl RpcContractInstance {
     pub fn new(address: &Addr, instance: RpcInstance) -> Self {
+        // grows which failed in earlier calls are not the cause of the errors of this one
+        take_failed_grow();
         let contract_info = ContractInfo {
             address: address.clone(),
         };
         Self {
             contract_info,
             instance,
+            memory_limit: None,
+            code_id: None,
+        }
+    }
+
+    /// name code_id in the errors of the instance
+    pub fn with_code_id(mut self, code_id: u64) -> Self {
+        self.code_id = Some(code_id);
+        self
+    }
+
+    pub fn code_id(&self) -> Option<u64> {
+        self.code_id
+    }
+
+    /// report traps of the instance after a failed memory.grow as exceeding memory_limit
+    pub fn with_memory_limit(mut self, memory_limit: Option<usize>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// running out of gas is told apart from other errors, as gas limited submessages recover from it
+    fn call_error(&self, e: VmError) -> Error {
+        let failed_grow = take_failed_grow();
+        match e {
+            VmError::GasDepletion { .. } => Error::out_of_gas(e),
+            e => match self.memory_limit {
+                // contracts trap when their allocator fails to grow the memory
+                Some(limit) if failed_grow => {
+                    Error::limit_exceeded(format!("memory limit of {} bytes: {}", limit, e))
+                }
+                _ => Error::contract_vm_error(&self.contract_info.address, self.code_id, e),
+            },
         }
     }
 
+    // contracts of cosmwasm 0.14 to 0.16 take the channel or packet instead of the ibc messages
+    fn is_legacy(&self) -> bool {
+        !self
+            .instance
+            .exported_function_names(Some(LEGACY_INTERFACE_VERSION))
+            .is_empty()
+    }
+
+    fn call_legacy<T: DeserializeOwned>(
+        &mut self,
+        call: fn(&mut RpcInstance, &[u8], &[u8]) -> VmResult<Vec<u8>>,
+        env: &Env,
+        msg: Value,
+    ) -> Result<T, Error> {
+        let env = to_vec(env).map_err(Error::format_error)?;
+        let msg = serde_json::to_vec(&msg).map_err(Error::from)?;
+        let data = call(&mut self.instance, &env, &msg).map_err(|e| self.call_error(e))?;
+        from_slice(&data).map_err(Error::format_error)
+    }
+
     pub fn address(&self) -> Addr {
         self.contract_info.address.clone()
     }
 
+    /// gas used by all calls of the instance so far
+    pub fn gas_used(&self) -> u64 {
+        let report = self.instance.create_gas_report();
+        report.limit.saturating_sub(report.remaining)
+    }
+
     pub fn instantiate(
         &mut self,
         env: &Env,
@@ -42,7 +135,7 @@ impl RpcContractInstance {
             sender: sender.clone(),
             funds: funds.to_vec(),
         };
-        call_instantiate(&mut self.instance, env, &info, msg).map_err(Error::vm_error)
+        call_instantiate(&mut self.instance, env, &info, msg).map_err(|e| self.call_error(e))
     }
 
     pub fn execute(
@@ -56,18 +149,142 @@ impl RpcContractInstance {
             sender: sender.clone(),
             funds: funds.to_vec(),
         };
-        call_execute(&mut self.instance, env, &info, msg).map_err(Error::vm_error)
+        call_execute(&mut self.instance, env, &info, msg).map_err(|e| self.call_error(e))
+    }
+
+    pub fn migrate(&mut self, env: &Env, msg: &[u8]) -> Result<ContractResult<Response>, Error> {
+        call_migrate(&mut self.instance, env, msg).map_err(|e| self.call_error(e))
     }
 
     pub fn reply(&mut self, env: &Env, msg: &Reply) -> Result<ContractResult<Response>, Error> {
-        call_reply(&mut self.instance, env, msg).map_err(Error::vm_error)
+        call_reply(&mut self.instance, env, msg).map_err(|e| self.call_error(e))
+    }
+
+    /// call of the contract by a chain module rather than an account, e.g. by governance
+    pub fn sudo(&mut self, env: &Env, msg: &[u8]) -> Result<ContractResult<Response>, Error> {
+        call_sudo(&mut self.instance, env, msg).map_err(|e| self.call_error(e))
+    }
+
+    /// the version the contract proposes instead of the one of msg, if any
+    pub fn ibc_channel_open(
+        &mut self,
+        env: &Env,
+        msg: &IbcChannelOpenMsg,
+    ) -> Result<ContractResult<Option<String>>, Error> {
+        let result: ContractResult<Option<Ibc3ChannelOpenResponse>> = if self.is_legacy() {
+            self.call_legacy(call_ibc_channel_open_raw, env, legacy_channel_open_msg(msg))?
+        } else {
+            call_ibc_channel_open(&mut self.instance, env, msg).map_err(|e| self.call_error(e))?
+        };
+        Ok(result.into_result().map(|r| r.map(|r| r.version)).into())
+    }
+
+    pub fn ibc_channel_connect(
+        &mut self,
+        env: &Env,
+        msg: &IbcChannelConnectMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        if self.is_legacy() {
+            return self
+                .call_legacy(
+                    call_ibc_channel_connect_raw,
+                    env,
+                    legacy_channel_connect_msg(msg),
+                )
+                .map(basic_response);
+        }
+        call_ibc_channel_connect(&mut self.instance, env, msg)
+            .map(basic_response)
+            .map_err(|e| self.call_error(e))
+    }
+
+    pub fn ibc_channel_close(
+        &mut self,
+        env: &Env,
+        msg: &IbcChannelCloseMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        if self.is_legacy() {
+            return self
+                .call_legacy(
+                    call_ibc_channel_close_raw,
+                    env,
+                    legacy_channel_close_msg(msg),
+                )
+                .map(basic_response);
+        }
+        call_ibc_channel_close(&mut self.instance, env, msg)
+            .map(basic_response)
+            .map_err(|e| self.call_error(e))
+    }
+
+    /// the acknowledgement is returned as the data of the response
+    pub fn ibc_packet_receive(
+        &mut self,
+        env: &Env,
+        msg: &IbcPacketReceiveMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        let result: ContractResult<IbcReceiveResponse> = if self.is_legacy() {
+            self.call_legacy(
+                call_ibc_packet_receive_raw,
+                env,
+                legacy_packet_receive_msg(msg),
+            )?
+        } else {
+            call_ibc_packet_receive(&mut self.instance, env, msg).map_err(|e| self.call_error(e))?
+        };
+        Ok(result
+            .into_result()
+            .map(|r| {
+                Response::new()
+                    .add_submessages(r.messages)
+                    .add_attributes(r.attributes)
+                    .add_events(r.events)
+                    .set_data(r.acknowledgement)
+            })
+            .into())
+    }
+
+    pub fn ibc_packet_ack(
+        &mut self,
+        env: &Env,
+        msg: &IbcPacketAckMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        if self.is_legacy() {
+            return self
+                .call_legacy(call_ibc_packet_ack_raw, env, legacy_packet_ack_msg(msg))
+                .map(basic_response);
+        }
+        call_ibc_packet_ack(&mut self.instance, env, msg)
+            .map(basic_response)
+            .map_err(|e| self.call_error(e))
+    }
+
+    pub fn ibc_packet_timeout(
+        &mut self,
+        env: &Env,
+        msg: &IbcPacketTimeoutMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        if self.is_legacy() {
+            return self
+                .call_legacy(
+                    call_ibc_packet_timeout_raw,
+                    env,
+                    legacy_packet_timeout_msg(msg),
+                )
+                .map(basic_response);
+        }
+        call_ibc_packet_timeout(&mut self.instance, env, msg)
+            .map(basic_response)
+            .map_err(|e| self.call_error(e))
     }
 
     pub fn query(&mut self, env: &Env, wasm_query: &WasmQuery) -> Result<Binary, Error> {
         match wasm_query {
-            WasmQuery::ContractInfo { contract_addr: _ } => {
-                Ok(to_binary(&self.contract_info).unwrap())
-            }
+            // the code id, admin and pinning of the contract are not known to its instance
+            WasmQuery::ContractInfo { contract_addr } => Err(Error::invalid_argument(format!(
+                "contract info of {} is answered by the querier of the model",
+                contract_addr
+            ))),
             WasmQuery::Raw {
                 contract_addr: _,
                 key,
@@ -81,7 +298,7 @@ impl RpcContractInstance {
                             Err(e) => Err(VmError::BackendErr { source: e }),
                         }
                     })
-                    .map_err(Error::vm_error)?
+                    .map_err(Error::from)?
                 {
                     Ok(Binary::from(value.as_slice()))
                 } else {
@@ -93,10 +310,14 @@ impl RpcContractInstance {
                 msg,
             } => {
                 match call_query(&mut self.instance, env, msg.as_slice())
-                    .map_err(Error::vm_error)?
+                    .map_err(|e| self.call_error(e))?
                 {
                     ContractResult::Ok(r) => Ok(r),
-                    ContractResult::Err(e) => Err(Error::vm_error(&e)),
+                    ContractResult::Err(e) => Err(Error::contract_error(
+                        &self.contract_info.address,
+                        self.code_id,
+                        e,
+                    )),
                 }
             }
             _ => unimplemented!(),
@@ -114,7 +335,7 @@ impl RpcContractInstance {
                 let (b, _) = s.set(key, value);
                 b.map_err(|e| VmError::BackendErr { source: e })
             })
-            .map_err(Error::vm_error)?;
+            .map_err(Error::from)?;
         Ok(())
     }
 }
@@ -0,0 +1,58 @@
+use cosmwasm_std::Addr;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::RpcInstance;
+
+/// number of idle instances kept per contract, more are only needed for recursive queries
+const MAX_IDLE_INSTANCES: usize = 2;
+
+struct PooledInstance {
+    // checksum of the code the instance was created from, so that instances of replaced code are not reused
+    checksum: Vec<u8>,
+    instance: RpcInstance,
+}
+
+/// idle instances of queried contracts, so that repeated queries skip instantiation.
+/// Clones share the same pool.
+#[derive(Clone, Default)]
+pub struct InstancePool {
+    inner: Arc<Mutex<HashMap<Addr, Vec<PooledInstance>>>>,
+}
+
+impl InstancePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// takes an idle instance of contract_addr created from code with the given checksum
+    pub fn take(&self, contract_addr: &Addr, checksum: &[u8]) -> Option<RpcInstance> {
+        let mut inner = self.inner.lock().unwrap();
+        let idle = inner.get_mut(contract_addr)?;
+        // instances of old code will never be used again
+        idle.retain(|p| p.checksum == checksum);
+        idle.pop().map(|p| p.instance)
+    }
+
+    /// returns an instance to the pool, the caller is responsible for detaching it from per-call state
+    pub fn put(&self, contract_addr: &Addr, checksum: Vec<u8>, instance: RpcInstance) {
+        let mut inner = self.inner.lock().unwrap();
+        let idle = inner.entry(contract_addr.clone()).or_default();
+        if idle.len() < MAX_IDLE_INSTANCES {
+            idle.push(PooledInstance { checksum, instance });
+        }
+    }
+
+    /// number of idle instances
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().values().map(|v| v.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().clear();
+    }
+}
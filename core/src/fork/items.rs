@@ -28,5 +28,47 @@ pub mod rpc_items {
                 include_proto!("cosmos.bank.v1beta1");
             }
         }
+        pub mod distribution {
+            pub mod v1beta1 {
+                include_proto!("cosmos.distribution.v1beta1");
+            }
+        }
+        pub mod gov {
+            pub mod v1 {
+                include_proto!("cosmos.gov.v1");
+            }
+            pub mod v1beta1 {
+                include_proto!("cosmos.gov.v1beta1");
+            }
+        }
+        pub mod tx {
+            pub mod v1beta1 {
+                include_proto!("cosmos.tx.v1beta1");
+            }
+            pub mod signing {
+                pub mod v1beta1 {
+                    include_proto!("cosmos.tx.signing.v1beta1");
+                }
+            }
+        }
+        pub mod crypto {
+            pub mod multisig {
+                pub mod v1beta1 {
+                    include_proto!("cosmos.crypto.multisig.v1beta1");
+                }
+            }
+        }
+    }
+    pub mod osmosis {
+        pub mod gamm {
+            pub mod v1beta1 {
+                include_proto!("osmosis.gamm.v1beta1");
+            }
+        }
+        pub mod tokenfactory {
+            pub mod v1beta1 {
+                include_proto!("osmosis.tokenfactory.v1beta1");
+            }
+        }
     }
 }
@@ -0,0 +1,130 @@
+use crate::ContractInfo;
+use cosmwasm_std::Addr;
+use std::sync::{Arc, Mutex};
+
+/// a change made to the states, with what is needed to undo it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Undo {
+    /// key of the storage of contract held old
+    Storage {
+        contract: Addr,
+        key: Vec<u8>,
+        old: Option<Vec<u8>>,
+    },
+    /// contract was instantiated
+    Created { contract: Addr },
+    /// contract had code and info, before it was migrated or its admin changed
+    Metadata {
+        contract: Addr,
+        code: Arc<Vec<u8>>,
+        info: ContractInfo,
+    },
+}
+
+/// log of the changes made to the states, oldest first, so that they can be undone, see AllStates::checkpoint.
+/// Instances record their storage writes to it while they run. Clones get their own copy of the log
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    undo: Arc<Mutex<Vec<Undo>>>,
+}
+
+impl Clone for Journal {
+    fn clone(&self) -> Self {
+        Self {
+            undo: Arc::new(Mutex::new(self.undo.lock().unwrap().clone())),
+        }
+    }
+}
+
+impl Journal {
+    pub fn len(&self) -> usize {
+        self.undo.lock().unwrap().len()
+    }
+
+    pub fn record(&self, undo: Undo) {
+        self.undo.lock().unwrap().push(undo);
+    }
+
+    /// the changes recorded after the first len ones
+    pub fn since(&self, len: usize) -> Vec<Undo> {
+        let undo = self.undo.lock().unwrap();
+        undo[len.min(undo.len())..].to_vec()
+    }
+
+    /// removes the changes recorded after the first len ones and returns them
+    pub fn split_off(&self, len: usize) -> Vec<Undo> {
+        let mut undo = self.undo.lock().unwrap();
+        let len = len.min(undo.len());
+        undo.split_off(len)
+    }
+
+    /// forgets the changes recorded after the first len ones, they can no longer be undone
+    pub fn truncate(&self, len: usize) {
+        self.undo.lock().unwrap().truncate(len);
+    }
+
+    /// where the storage of contract records its writes
+    pub fn storage_journal(&self, contract: &Addr) -> StorageJournal {
+        StorageJournal {
+            contract: contract.clone(),
+            undo: Arc::clone(&self.undo),
+        }
+    }
+}
+
+/// records the writes to the storage of a contract in a Journal
+#[derive(Clone, Debug)]
+pub(crate) struct StorageJournal {
+    contract: Addr,
+    undo: Arc<Mutex<Vec<Undo>>>,
+}
+
+impl StorageJournal {
+    pub fn record_write(&self, key: &[u8], old: Option<Vec<u8>>) {
+        self.undo.lock().unwrap().push(Undo::Storage {
+            contract: self.contract.clone(),
+            key: key.to_vec(),
+            old,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+
+    use super::{Journal, Undo};
+
+    #[test]
+    fn test_journal() {
+        let journal = Journal::default();
+        let contract = Addr::unchecked("contract");
+        journal.record(Undo::Created {
+            contract: contract.clone(),
+        });
+        let storage = journal.storage_journal(&contract);
+        storage.record_write(b"key", None);
+        storage.record_write(b"key", Some(b"1".to_vec()));
+        assert_eq!(journal.len(), 3);
+        assert_eq!(
+            journal.since(2),
+            vec![Undo::Storage {
+                contract: contract.clone(),
+                key: b"key".to_vec(),
+                old: Some(b"1".to_vec()),
+            }]
+        );
+
+        // clones don't see the writes recorded to the original
+        let clone = journal.clone();
+        storage.record_write(b"other", None);
+        assert_eq!(clone.len(), 3);
+        assert_eq!(journal.len(), 4);
+
+        assert_eq!(journal.split_off(1).len(), 3);
+        assert_eq!(journal.since(0), vec![Undo::Created { contract }]);
+        journal.truncate(0);
+        assert_eq!(journal.len(), 0);
+        assert!(journal.since(5).is_empty());
+    }
+}
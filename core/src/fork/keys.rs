@@ -0,0 +1,179 @@
+use bech32::{ToBase32, Variant};
+use cosmwasm_std::Addr;
+use k256::ecdsa::signature::DigestSigner;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Error;
+
+/// the signature schemes contracts verify with the secp256k1_verify and ed25519_verify imports
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyAlgo {
+    Secp256k1,
+    Ed25519,
+}
+
+impl FromStr for KeyAlgo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "secp256k1" => Ok(KeyAlgo::Secp256k1),
+            "ed25519" => Ok(KeyAlgo::Ed25519),
+            _ => Err(Error::invalid_argument(format!(
+                "unknown key algorithm {}, expected secp256k1 or ed25519",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum SigningKey {
+    Secp256k1(k256::ecdsa::SigningKey),
+    Ed25519(ed25519_zebra::SigningKey),
+}
+
+/// a keypair for contracts verifying signatures, e.g. off-chain approvals. Keys derived from a
+/// label are the same on every run, so that signed payloads can be kept in test vectors.
+/// Signatures are checked with cosmwasm-crypto, as the vm does for contracts
+#[derive(Clone)]
+pub struct CheatKey {
+    key: SigningKey,
+}
+
+impl fmt::Debug for CheatKey {
+    // the secret key is left out
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CheatKey")
+            .field("algo", &self.algo())
+            .field("public_key", &hex::encode(self.public_key()))
+            .finish()
+    }
+}
+
+impl CheatKey {
+    /// the key of algo derived from label
+    pub fn from_label(algo: KeyAlgo, label: &str) -> Self {
+        let seed: [u8; 32] = Sha256::digest(format!("cheat_key_{}", label).as_bytes()).into();
+        Self::from_seed(algo, seed)
+    }
+
+    /// a fresh key of algo
+    pub fn random(algo: KeyAlgo) -> Self {
+        Self::from_seed(algo, rand::random())
+    }
+
+    fn from_seed(algo: KeyAlgo, seed: [u8; 32]) -> Self {
+        let key = match algo {
+            KeyAlgo::Secp256k1 => {
+                // a seed out of the range of scalars is hashed again, which almost never happens
+                let mut seed = seed;
+                loop {
+                    match k256::ecdsa::SigningKey::from_bytes(&seed) {
+                        Ok(key) => break SigningKey::Secp256k1(key),
+                        Err(_) => seed = Sha256::digest(seed).into(),
+                    }
+                }
+            }
+            KeyAlgo::Ed25519 => SigningKey::Ed25519(ed25519_zebra::SigningKey::from(seed)),
+        };
+        CheatKey { key }
+    }
+
+    pub fn algo(&self) -> KeyAlgo {
+        match self.key {
+            SigningKey::Secp256k1(_) => KeyAlgo::Secp256k1,
+            SigningKey::Ed25519(_) => KeyAlgo::Ed25519,
+        }
+    }
+
+    /// the compressed 33 byte secp256k1 key or the 32 byte ed25519 key
+    pub fn public_key(&self) -> Vec<u8> {
+        match &self.key {
+            SigningKey::Secp256k1(key) => key.verifying_key().to_bytes().to_vec(),
+            SigningKey::Ed25519(key) => {
+                <[u8; 32]>::from(ed25519_zebra::VerificationKey::from(key)).to_vec()
+            }
+        }
+    }
+
+    /// the 64 byte signature of msg. secp256k1 signs the sha256 hash of msg, which contracts
+    /// pass to secp256k1_verify as message_hash, ed25519 signs msg itself
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        match &self.key {
+            SigningKey::Secp256k1(key) => {
+                let signature: k256::ecdsa::Signature =
+                    key.sign_digest(Sha256::new().chain_update(msg));
+                signature.as_ref().to_vec()
+            }
+            SigningKey::Ed25519(key) => <[u8; 64]>::from(key.sign(msg)).to_vec(),
+        }
+    }
+
+    /// whether signature is a signature of msg by the key, as a contract would check it
+    pub fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        let public_key = self.public_key();
+        let verified = match self.key {
+            SigningKey::Secp256k1(_) => {
+                cosmwasm_crypto::secp256k1_verify(&Sha256::digest(msg), signature, &public_key)
+            }
+            SigningKey::Ed25519(_) => cosmwasm_crypto::ed25519_verify(msg, signature, &public_key),
+        };
+        verified.unwrap_or(false)
+    }
+
+    /// the account address of the key as the cosmos sdk derives it, ripemd160(sha256(key))
+    /// for secp256k1 and the first 20 bytes of sha256(key) for ed25519
+    pub fn address(&self, bech32_prefix: &str) -> Result<Addr, Error> {
+        let hash = Sha256::digest(self.public_key());
+        let canonical = match self.key {
+            SigningKey::Secp256k1(_) => Ripemd160::digest(hash).to_vec(),
+            SigningKey::Ed25519(_) => hash[..20].to_vec(),
+        };
+        bech32::encode(bech32_prefix, canonical.to_base32(), Variant::Bech32)
+            .map(Addr::unchecked)
+            .map_err(|e| Error::invalid_argument(format!("bech32 prefix {}: {}", bech32_prefix, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheatKey, KeyAlgo};
+
+    #[test]
+    fn test_cheat_keys() {
+        for algo in [KeyAlgo::Secp256k1, KeyAlgo::Ed25519].iter().copied() {
+            let key = CheatKey::from_label(algo, "alice");
+            assert_eq!(key.algo(), algo);
+            assert_eq!(
+                key.public_key(),
+                CheatKey::from_label(algo, "alice").public_key()
+            );
+            assert_ne!(
+                key.public_key(),
+                CheatKey::from_label(algo, "bob").public_key()
+            );
+
+            let signature = key.sign(b"approve");
+            assert_eq!(signature.len(), 64);
+            assert!(key.verify(b"approve", &signature));
+            assert!(!key.verify(b"reject", &signature));
+            assert!(!CheatKey::random(algo).verify(b"approve", &signature));
+
+            let address = key.address("wasm").unwrap();
+            assert!(address.as_str().starts_with("wasm1"));
+            assert!(key.address("").is_err());
+        }
+        assert_eq!(
+            CheatKey::from_label(KeyAlgo::Secp256k1, "alice")
+                .public_key()
+                .len(),
+            33
+        );
+        assert_eq!("ed25519".parse::<KeyAlgo>().unwrap(), KeyAlgo::Ed25519);
+        assert!("rsa".parse::<KeyAlgo>().is_err());
+    }
+}
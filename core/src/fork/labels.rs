@@ -0,0 +1,73 @@
+use cosmwasm_std::Addr;
+use std::collections::BTreeMap;
+
+/// names given to addresses by the user, see Model::set_alias.
+/// Together with account labels and the labels of contracts, they name calls in call traces
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LabelRegistry {
+    aliases: BTreeMap<String, Addr>,
+}
+
+impl LabelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// names address, replacing an earlier alias of the same name
+    pub fn set_alias(&mut self, name: &str, address: &Addr) {
+        self.aliases.insert(name.to_string(), address.clone());
+    }
+
+    pub fn remove_alias(&mut self, name: &str) -> Option<Addr> {
+        self.aliases.remove(name)
+    }
+
+    pub fn address(&self, name: &str) -> Option<&Addr> {
+        self.aliases.get(name)
+    }
+
+    /// the first alias of address in alphabetical order
+    pub fn alias_of(&self, address: &Addr) -> Option<&str> {
+        self.aliases
+            .iter()
+            .find(|(_, a)| *a == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn aliases(&self) -> &BTreeMap<String, Addr> {
+        &self.aliases
+    }
+}
+
+/// address abbreviated to its first 10 characters, e.g. wasm15le5e...
+pub(crate) fn short_address(address: &str) -> String {
+    if address.len() > 13 && address.is_char_boundary(10) {
+        format!("{}...", &address[..10])
+    } else {
+        address.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+
+    use super::{short_address, LabelRegistry};
+
+    #[test]
+    fn test_label_registry() {
+        let mut registry = LabelRegistry::new();
+        let pair =
+            Addr::unchecked("wasm15le5evw4regnwf9lrjnpakr2075fcyp4n4yzpelvqcuevzkw2lss46hslz");
+        registry.set_alias("pair", &pair);
+        registry.set_alias("astroport_pair", &pair);
+        assert_eq!(registry.address("pair"), Some(&pair));
+        assert_eq!(registry.alias_of(&pair), Some("astroport_pair"));
+        assert_eq!(registry.remove_alias("astroport_pair"), Some(pair.clone()));
+        assert_eq!(registry.alias_of(&pair), Some("pair"));
+        assert_eq!(registry.address("astroport_pair"), None);
+
+        assert_eq!(short_address(pair.as_str()), "wasm15le5e...");
+        assert_eq!(short_address("contract"), "contract");
+    }
+}
@@ -1,20 +1,28 @@
-use super::client_backend::ContractInfo;
+use super::client_backend::{
+    is_pruned_message, lowest_height_in, CodeInfo, ContractInfo, DenomMetadata, TxInfo,
+};
 use crate::{CwClientBackend, Error};
 use chrono::DateTime;
-use cosmwasm_std::Timestamp;
-use oxhttp::model::{Method, Request, Status, Url};
+use cosmwasm_std::{Event, Timestamp};
+use oxhttp::model::{HeaderName, Method, Request, Status, Url};
 use oxhttp::Client;
 use serde::{Deserialize, Serialize};
-use serde_json::from_str;
+use serde_json::{from_str, value::RawValue};
+use sha2::{Digest, Sha256};
+use tracing::{debug, debug_span};
 
 use std::collections::BTreeMap;
 
 #[derive(Clone)]
 pub struct CwLcdClient {
     url: String,
+    // queries target this block, 0 only while the latest block is looked up
     block_number: u64,
 }
 
+// grpc-gateway header selecting the height queries are answered at
+const BLOCK_HEIGHT_HEADER: &str = "x-cosmos-block-height";
+
 // never change the field names of this struct
 #[derive(Serialize, Deserialize)]
 struct BlockHeaderRawOuterOuter {
@@ -55,6 +63,25 @@ struct ContractInfoRaw {
 #[derive(Serialize, Deserialize)]
 struct ContractStateAll {
     models: Vec<KeyValueEntry>,
+    #[serde(default)]
+    pagination: Option<PageResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PageResponse {
+    // base64, null on the last page
+    next_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ContractSmartResponse<'a> {
+    #[serde(borrow)]
+    data: &'a RawValue,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ContractStateRaw {
+    data: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -69,12 +96,68 @@ struct CodeResponse {
     data: String,
 }
 
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct CodeInfoResponse {
+    code_info: CodeInfoRaw,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct CodeInfoRaw {
+    creator: String,
+    // hex
+    data_hash: String,
+}
+
 // never change the field names of this struct
 #[derive(Serialize, Deserialize)]
 struct BankBalancesResponse {
     balances: Vec<CoinRaw>,
 }
 
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct BankSupplyResponse {
+    amount: CoinRaw,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct DenomMetadataResponse {
+    metadata: DenomMetadata,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct DenomsMetadataResponse {
+    metadatas: Vec<DenomMetadata>,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct DelegationRewardsResponse {
+    rewards: Vec<CoinRaw>,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct WithdrawAddressResponse {
+    withdraw_address: String,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct GovProposalsResponse {
+    proposals: Vec<GovProposalRaw>,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct GovProposalRaw {
+    proposal_id: String,
+}
+
 // never change the field names of this struct
 #[derive(Serialize, Deserialize)]
 struct CoinRaw {
@@ -82,6 +165,62 @@ struct CoinRaw {
     amount: String,
 }
 
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct GetTxResponse {
+    tx_response: TxResponseRaw,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct TxResponseRaw {
+    height: String,
+    txhash: String,
+    code: u32,
+    timestamp: String,
+    logs: Vec<MessageLogRaw>,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct MessageLogRaw {
+    events: Vec<EventRaw>,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct EventRaw {
+    #[serde(rename = "type")]
+    ty: String,
+    attributes: Vec<AttributeRaw>,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct AttributeRaw {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct BlockResponse {
+    block: BlockRaw,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct BlockRaw {
+    data: BlockDataRaw,
+}
+
+// never change the field names of this struct
+#[derive(Serialize, Deserialize)]
+struct BlockDataRaw {
+    txs: Vec<String>,
+}
+
 // never change the field names of this struct
 #[derive(Serialize, Deserialize)]
 struct ErrorResponseBody {
@@ -89,23 +228,44 @@ struct ErrorResponseBody {
     message: String,
 }
 
+/// escapes the characters of base64 which are not allowed in query parameters
+fn url_encode_base64(b64: &str) -> String {
+    b64.replace('+', "%2B")
+        .replace('/', "%2F")
+        .replace('=', "%3D")
+}
+
 impl CwLcdClient {
-    pub fn new(url: &str) -> Result<Self, Error> {
+    /// client with queries pinned to block_number, or to the latest block at the time it is created.
+    /// Historical queries need an archive node
+    pub fn new(url: &str, block_number: Option<u64>) -> Result<Self, Error> {
         let mut rv = Self {
             url: url.to_string(),
             block_number: 0,
         };
-        rv.block_number = rv.block_height()?;
+        rv.block_number = match block_number {
+            Some(bn) => bn,
+            None => rv.block_height()?,
+        };
         Ok(rv)
     }
 
     fn request_inner(&self, uri: &str) -> Result<String, Error> {
+        let _span = debug_span!("lcd_request", uri, height = self.block_number).entered();
         let request_url =
             Url::parse(&format!("{}{}", &self.url, uri)).map_err(Error::format_error)?;
         let client = Client::new();
-        let request = Request::builder(Method::GET, request_url).build();
+        let mut request = Request::builder(Method::GET, request_url);
+        if self.block_number != 0 {
+            let header: HeaderName = BLOCK_HEIGHT_HEADER.parse().map_err(Error::format_error)?;
+            request = request
+                .with_header(header, self.block_number.to_string())
+                .map_err(Error::format_error)?;
+        }
+        let request = request.build();
         let response = client.request(request).map_err(Error::http_error)?;
         let status = response.status();
+        debug!(%status, "response received");
         let body_str = response
             .into_body()
             .to_string()
@@ -113,17 +273,32 @@ impl CwLcdClient {
         if status == Status::OK {
             Ok(body_str)
         } else {
-            let err_body: ErrorResponseBody = from_str(&body_str).map_err(Error::format_error)?;
-            Err(Error::http_error(&err_body.message))
+            // rate limiters and gateways usually don't answer with a json body
+            match from_str::<ErrorResponseBody>(&body_str) {
+                Ok(err_body) if is_pruned_message(&err_body.message) => Err(Error::height_pruned(
+                    self.block_number,
+                    lowest_height_in(&err_body.message),
+                )),
+                Ok(err_body) => Err(Error::http_error(&err_body.message)),
+                Err(_) => Err(Error::http_error(status)),
+            }
         }
     }
 
-    fn get_latest_block_header(&mut self) -> Result<BlockHeaderRaw, Error> {
-        let body_str = self.request_inner("/blocks/latest")?;
-        let block_header: BlockHeaderRawOuterOuter =
-            from_str(&body_str).map_err(Error::format_error)?;
+    fn get_block_header(&mut self, height: Option<u64>) -> Result<BlockHeaderRaw, Error> {
+        let uri = match height {
+            Some(height) => format!("/blocks/{}", height),
+            None => "/blocks/latest".to_string(),
+        };
+        let body_str = self.request_inner(&uri)?;
+        let block_header: BlockHeaderRawOuterOuter = from_str(&body_str).map_err(Error::from)?;
         Ok(block_header.block.header)
     }
+
+    /// header of the block queries target
+    fn get_pinned_block_header(&mut self) -> Result<BlockHeaderRaw, Error> {
+        self.get_block_header(Some(self.block_number).filter(|bn| *bn != 0))
+    }
 }
 
 impl CwClientBackend for CwLcdClient {
@@ -131,30 +306,51 @@ impl CwClientBackend for CwLcdClient {
         self.block_number
     }
 
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    // responses of the LCD client are never cached
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+
     fn chain_id(&mut self) -> Result<String, crate::Error> {
-        let block_header = self.get_latest_block_header()?;
+        let block_header = self.get_pinned_block_header()?;
         Ok(block_header.chain_id)
     }
 
     fn timestamp(&mut self) -> Result<Timestamp, crate::Error> {
-        let block_header = self.get_latest_block_header()?;
+        let block_header = self.get_pinned_block_header()?;
         let date_time =
             DateTime::parse_from_rfc3339(&block_header.time).map_err(Error::format_error)?;
         Ok(Timestamp::from_nanos(date_time.timestamp_nanos() as u64))
     }
 
     fn block_height(&mut self) -> Result<u64, crate::Error> {
-        let block_header = self.get_latest_block_header()?;
+        let block_header = self.get_block_header(None)?;
         let height: u64 = block_header.height.parse().unwrap();
         Ok(height)
     }
 
+    fn earliest_available_block(&mut self) -> Result<u64, Error> {
+        // pruning nodes answer the query of block 1 with the lowest height they have
+        match self.get_block_header(Some(1)) {
+            Ok(_) => Ok(1),
+            Err(Error::HeightPruned {
+                earliest: Some(earliest),
+                ..
+            }) => Ok(earliest),
+            Err(e) => Err(e),
+        }
+    }
+
     fn query_bank_all_balances(
         &mut self,
         address: &str,
     ) -> Result<Vec<(String, u128)>, crate::Error> {
         let body_str = self.request_inner(&format!("/cosmos/bank/v1beta1/balances/{}", address))?;
-        let balances: BankBalancesResponse = from_str(&body_str).map_err(Error::format_error)?;
+        let balances: BankBalancesResponse = from_str(&body_str).map_err(Error::from)?;
         let mut out = Vec::new();
         for coin in balances.balances {
             out.push((coin.denom, coin.amount.parse().unwrap()));
@@ -162,6 +358,25 @@ impl CwClientBackend for CwLcdClient {
         Ok(out)
     }
 
+    fn query_bank_supply(&mut self, denom: &str) -> Result<u128, crate::Error> {
+        let body_str = self.request_inner(&format!("/cosmos/bank/v1beta1/supply/{}", denom))?;
+        let supply: BankSupplyResponse = from_str(&body_str).map_err(Error::from)?;
+        supply.amount.amount.parse().map_err(Error::format_error)
+    }
+
+    fn query_bank_denom_metadata(&mut self, denom: &str) -> Result<DenomMetadata, Error> {
+        let body_str =
+            self.request_inner(&format!("/cosmos/bank/v1beta1/denoms_metadata/{}", denom))?;
+        let resp: DenomMetadataResponse = from_str(&body_str).map_err(Error::from)?;
+        Ok(resp.metadata)
+    }
+
+    fn query_bank_all_denom_metadata(&mut self) -> Result<Vec<DenomMetadata>, Error> {
+        let body_str = self.request_inner("/cosmos/bank/v1beta1/denoms_metadata")?;
+        let resp: DenomsMetadataResponse = from_str(&body_str).map_err(Error::from)?;
+        Ok(resp.metadatas)
+    }
+
     fn query_wasm_contract_smart(
         &mut self,
         address: &str,
@@ -172,46 +387,199 @@ impl CwClientBackend for CwLcdClient {
             "/cosmwasm/wasm/v1/contract/{}/smart/{}",
             address, query_data_b64
         ))?;
-        let response: serde_json::Value = from_str(&body_str).map_err(Error::format_error)?;
-        if let Some(data) = response.get("data") {
-            Ok(data.to_string().as_bytes().to_vec())
-        } else {
-            Err(Error::format_error(
-                &"key 'data' not present in response".to_string(),
-            ))
-        }
+        // the gateway embeds the json returned by the contract as is, keep its exact bytes
+        let response: ContractSmartResponse = from_str(&body_str).map_err(Error::from)?;
+        Ok(response.data.get().as_bytes().to_vec())
     }
 
     fn query_wasm_contract_state_all(
         &mut self,
         address: &str,
     ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, crate::Error> {
-        let body_str =
-            self.request_inner(&format!("/cosmwasm/wasm/v1/contract/{}/state", address))?;
-        let response: ContractStateAll = from_str(&body_str).map_err(Error::format_error)?;
         let mut out = BTreeMap::new();
-        for kv in response.models {
-            let key = hex::decode(kv.key).map_err(Error::format_error)?;
-            let value = base64::decode(kv.value).map_err(Error::format_error)?;
-            out.insert(key, value);
+        let mut next_key: Option<String> = None;
+        loop {
+            let mut uri = format!("/cosmwasm/wasm/v1/contract/{}/state", address);
+            if let Some(key) = &next_key {
+                uri.push_str(&format!("?pagination.key={}", url_encode_base64(key)));
+            }
+            let body_str = self.request_inner(&uri)?;
+            let response: ContractStateAll = from_str(&body_str).map_err(Error::from)?;
+            for kv in response.models {
+                let key = hex::decode(kv.key).map_err(Error::format_error)?;
+                let value = base64::decode(kv.value).map_err(Error::format_error)?;
+                out.insert(key, value);
+            }
+            next_key = response
+                .pagination
+                .and_then(|p| p.next_key)
+                .filter(|k| !k.is_empty());
+            if next_key.is_none() {
+                return Ok(out);
+            }
+        }
+    }
+
+    fn query_wasm_contract_state_raw(
+        &mut self,
+        address: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, crate::Error> {
+        let body_str = self.request_inner(&format!(
+            "/cosmwasm/wasm/v1/contract/{}/raw/{}",
+            address,
+            base64::encode(key)
+        ))?;
+        let response: ContractStateRaw = from_str(&body_str).map_err(Error::from)?;
+        match response.data {
+            Some(data) if !data.is_empty() => {
+                Ok(Some(base64::decode(data).map_err(Error::format_error)?))
+            }
+            _ => Ok(None),
         }
-        Ok(out)
     }
 
     fn query_wasm_contract_info(&mut self, address: &str) -> Result<ContractInfo, crate::Error> {
         let body_str = self.request_inner(&format!("/cosmwasm/wasm/v1/contract/{}", address))?;
-        let response: ContractInfoResponse = from_str(&body_str).map_err(Error::format_error)?;
+        let response: ContractInfoResponse = from_str(&body_str).map_err(Error::from)?;
+        let info = response.contract_info;
         Ok(ContractInfo {
-            code_id: response.contract_info.code_id.parse().unwrap(),
+            code_id: info.code_id.parse().map_err(Error::format_error)?,
+            creator: info.creator,
+            admin: Some(info.admin).filter(|a| !a.is_empty()),
+            label: info.label,
         })
     }
 
     fn query_wasm_contract_code(&mut self, code_id: u64) -> Result<Vec<u8>, crate::Error> {
         let body_str = self.request_inner(&format!("/cosmwasm/wasm/v1/code/{}", code_id))?;
-        let response: CodeResponse = from_str(&body_str).map_err(Error::format_error)?;
+        let response: CodeResponse = from_str(&body_str).map_err(Error::from)?;
         let code = base64::decode(&response.data).map_err(Error::format_error)?;
         Ok(code)
     }
+
+    fn query_wasm_code_info(&mut self, code_id: u64) -> Result<CodeInfo, crate::Error> {
+        let body_str = self.request_inner(&format!("/cosmwasm/wasm/v1/code/{}", code_id))?;
+        let response: CodeInfoResponse = from_str(&body_str).map_err(Error::from)?;
+        let info = response.code_info;
+        Ok(CodeInfo {
+            code_id,
+            creator: info.creator,
+            checksum: hex::decode(&info.data_hash).map_err(Error::format_error)?,
+        })
+    }
+
+    fn query_tx(&mut self, hash: &str) -> Result<TxInfo, crate::Error> {
+        let body_str = self.request_inner(&format!("/cosmos/tx/v1beta1/txs/{}", hash))?;
+        let response: GetTxResponse = from_str(&body_str).map_err(Error::from)?;
+        let tx_response = response.tx_response;
+        let height: u64 = tx_response.height.parse().map_err(Error::format_error)?;
+        let date_time =
+            DateTime::parse_from_rfc3339(&tx_response.timestamp).map_err(Error::format_error)?;
+        // the LCD only returns the tx in JSON, so fetch the raw tx from the block
+        let body_str = self.request_inner(&format!(
+            "/cosmos/base/tendermint/v1beta1/blocks/{}",
+            height
+        ))?;
+        let block: BlockResponse = from_str(&body_str).map_err(Error::from)?;
+        let mut tx = None;
+        for tx_b64 in block.block.data.txs.iter() {
+            let raw = base64::decode(tx_b64).map_err(Error::format_error)?;
+            if hex::encode_upper(Sha256::digest(&raw)) == tx_response.txhash.to_uppercase() {
+                tx = Some(raw);
+                break;
+            }
+        }
+        let tx = tx.ok_or_else(|| {
+            Error::format_error(format!("tx {} not found in block {}", hash, height))
+        })?;
+        let events = tx_response
+            .logs
+            .iter()
+            .flat_map(|log| log.events.iter())
+            .map(|e| {
+                Event::new(&e.ty).add_attributes(
+                    e.attributes
+                        .iter()
+                        .map(|a| (a.key.to_string(), a.value.to_string())),
+                )
+            })
+            .collect();
+        Ok(TxInfo {
+            hash: tx_response.txhash,
+            height,
+            timestamp: Timestamp::from_nanos(date_time.timestamp_nanos() as u64),
+            success: tx_response.code == 0,
+            events,
+            tx,
+        })
+    }
+
+    fn query_block_txs(&mut self, height: u64) -> Result<Vec<TxInfo>, crate::Error> {
+        let body_str = self.request_inner(&format!(
+            "/cosmos/base/tendermint/v1beta1/blocks/{}",
+            height
+        ))?;
+        let block: BlockResponse = from_str(&body_str).map_err(Error::from)?;
+        let mut out = Vec::new();
+        for tx_b64 in block.block.data.txs.iter() {
+            let raw = base64::decode(tx_b64).map_err(Error::format_error)?;
+            out.push(self.query_tx(&hex::encode_upper(Sha256::digest(&raw)))?);
+        }
+        Ok(out)
+    }
+
+    fn query_distribution_rewards(
+        &mut self,
+        delegator: &str,
+        validator: &str,
+    ) -> Result<Vec<(String, u128)>, Error> {
+        let body_str = self.request_inner(&format!(
+            "/cosmos/distribution/v1beta1/delegators/{}/rewards/{}",
+            delegator, validator
+        ))?;
+        let resp: DelegationRewardsResponse = from_str(&body_str).map_err(Error::from)?;
+        resp.rewards
+            .into_iter()
+            .map(|coin| {
+                // decimal coins are formatted with a fractional part, which is truncated
+                let whole = coin.amount.split('.').next().unwrap_or_default();
+                Ok((coin.denom, whole.parse().map_err(Error::format_error)?))
+            })
+            .collect()
+    }
+
+    fn query_distribution_withdraw_address(&mut self, delegator: &str) -> Result<String, Error> {
+        let body_str = self.request_inner(&format!(
+            "/cosmos/distribution/v1beta1/delegators/{}/withdraw_address",
+            delegator
+        ))?;
+        let resp: WithdrawAddressResponse = from_str(&body_str).map_err(Error::from)?;
+        Ok(resp.withdraw_address)
+    }
+
+    fn query_gov_latest_proposal_id(&mut self) -> Result<u64, Error> {
+        let body_str = self.request_inner(
+            "/cosmos/gov/v1beta1/proposals?pagination.limit=1&pagination.reverse=true",
+        )?;
+        let resp: GovProposalsResponse = from_str(&body_str).map_err(Error::from)?;
+        match resp.proposals.first() {
+            Some(proposal) => proposal.proposal_id.parse().map_err(Error::format_error),
+            None => Ok(0),
+        }
+    }
+
+    fn query_grpc(&mut self, path: &str, _data: &[u8]) -> Result<Vec<u8>, crate::Error> {
+        // the LCD only serves the json gateway of grpc queries
+        Err(Error::backend_error(format!(
+            "protobuf query {} is not supported by the LCD client",
+            path
+        )))
+    }
+
+    fn at_height(&self, height: u64) -> Result<Box<dyn CwClientBackend>, crate::Error> {
+        Ok(Box::new(CwLcdClient::new(&self.url, Some(height))?))
+    }
 }
 
 #[cfg(test)]
@@ -219,7 +587,7 @@ mod tests {
     use cosmwasm_std::CanonicalAddr;
     use serde::{Deserialize, Serialize};
 
-    use super::{CwClientBackend, CwLcdClient};
+    use super::{url_encode_base64, ContractSmartResponse, CwClientBackend, CwLcdClient};
     const TERRASWAP_FACTORY_ADDRESS: &str =
         "terra1466nf3zuxpya8q9emxukd7vftaf6h4psr0a07srl5zw74zh84yjqxl5qul";
 
@@ -232,7 +600,7 @@ mod tests {
 
     #[test]
     fn test_lcd_basic() {
-        let mut lcd_client = CwLcdClient::new("https://phoenix-lcd.terra.dev").unwrap();
+        let mut lcd_client = CwLcdClient::new("https://phoenix-lcd.terra.dev", None).unwrap();
         assert!(lcd_client.block_number() > 2529402);
         assert!(lcd_client.timestamp().unwrap().nanos() > 1668950758945436944);
 
@@ -268,4 +636,25 @@ mod tests {
             .unwrap();
         assert!(!balances.is_empty());
     }
+
+    #[test]
+    fn test_smart_response_bytes() {
+        // field order, whitespace and escapes are kept as returned by the contract
+        let body = r#"{"data":{"b":1, "a":"\u0041"}}"#;
+        let response: ContractSmartResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response.data.get(), r#"{"b":1, "a":"\u0041"}"#);
+        assert_eq!(url_encode_base64("a+b/c=="), "a%2Bb%2Fc%3D%3D");
+    }
+
+    #[test]
+    fn test_lcd_pinned() {
+        let mut latest = CwLcdClient::new("https://phoenix-lcd.terra.dev", None).unwrap();
+        let height = latest.block_number() - 100;
+        let mut pinned = latest.at_height(height).unwrap();
+        assert_eq!(pinned.block_number(), height);
+        assert!(pinned.timestamp().unwrap() < latest.timestamp().unwrap());
+        assert_eq!(pinned.chain_id().unwrap(), latest.chain_id().unwrap());
+        // the latest height is not affected by pinning
+        assert!(pinned.block_height().unwrap() > height);
+    }
 }
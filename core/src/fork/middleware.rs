@@ -0,0 +1,153 @@
+use cosmwasm_std::{Addr, ContractResult, CosmosMsg, Response};
+use std::sync::{Arc, Mutex};
+
+use crate::Error;
+
+/// what the model does with a message after a middleware has seen it
+#[derive(Clone, Debug, PartialEq)]
+pub enum MsgAction {
+    /// pass the message on unchanged
+    Continue,
+    /// pass this message on instead
+    Replace(CosmosMsg),
+    /// skip dispatching, and use this result as if the message was executed.
+    /// Later middlewares do not see the message
+    Respond(ContractResult<Response>),
+}
+
+/// observes or rewrites every CosmosMsg before the model dispatches it,
+/// e.g. to stub messages the simulator does not support or to inject failures.
+/// An error aborts the whole execution
+pub trait MsgMiddleware: Send + Sync {
+    fn handle(&self, sender: &Addr, msg: &CosmosMsg) -> Result<MsgAction, Error>;
+}
+
+impl<F> MsgMiddleware for F
+where
+    F: Fn(&Addr, &CosmosMsg) -> Result<MsgAction, Error> + Send + Sync,
+{
+    fn handle(&self, sender: &Addr, msg: &CosmosMsg) -> Result<MsgAction, Error> {
+        self(sender, msg)
+    }
+}
+
+/// middlewares run in the order they were added
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    middlewares: Vec<Arc<dyn MsgMiddleware>>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, middleware: Arc<dyn MsgMiddleware>) {
+        self.middlewares.push(middleware);
+    }
+
+    pub fn clear(&mut self) {
+        self.middlewares.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.middlewares.is_empty()
+    }
+
+    /// runs msg through every middleware, returning Replace if any of them rewrote it
+    pub fn run(&self, sender: &Addr, msg: &CosmosMsg) -> Result<MsgAction, Error> {
+        let mut replaced: Option<CosmosMsg> = None;
+        for middleware in self.middlewares.iter() {
+            match middleware.handle(sender, replaced.as_ref().unwrap_or(msg))? {
+                MsgAction::Continue => {}
+                MsgAction::Replace(msg) => replaced = Some(msg),
+                respond => return Ok(respond),
+            }
+        }
+        Ok(replaced.map_or(MsgAction::Continue, MsgAction::Replace))
+    }
+}
+
+/// middleware recording every message with its sender, as seen at its position in the chain
+#[derive(Default)]
+pub struct MsgRecorder {
+    messages: Mutex<Vec<(Addr, CosmosMsg)>>,
+}
+
+impl MsgRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages(&self) -> Vec<(Addr, CosmosMsg)> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.messages.lock().unwrap().clear();
+    }
+}
+
+impl MsgMiddleware for MsgRecorder {
+    fn handle(&self, sender: &Addr, msg: &CosmosMsg) -> Result<MsgAction, Error> {
+        self.messages
+            .lock()
+            .unwrap()
+            .push((sender.clone(), msg.clone()));
+        Ok(MsgAction::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coins, Addr, BankMsg, ContractResult, CosmosMsg, Response};
+    use std::sync::Arc;
+
+    use super::{MiddlewareChain, MsgAction, MsgRecorder};
+    use crate::Error;
+
+    #[test]
+    fn test_middleware_chain() {
+        let alice = Addr::unchecked("wasm1alice");
+        let send = CosmosMsg::Bank(BankMsg::Send {
+            to_address: "wasm1bob".to_string(),
+            amount: coins(1, "umlg"),
+        });
+        let burn = CosmosMsg::Bank(BankMsg::Burn {
+            amount: coins(1, "umlg"),
+        });
+        let mut chain = MiddlewareChain::new();
+        assert_eq!(chain.run(&alice, &send).unwrap(), MsgAction::Continue);
+
+        // burns are rewritten into sends, and sends are stubbed
+        let rewritten = send.clone();
+        chain.push(Arc::new(move |_: &Addr, msg: &CosmosMsg| match msg {
+            CosmosMsg::Bank(BankMsg::Burn { .. }) => Ok(MsgAction::Replace(rewritten.clone())),
+            _ => Ok(MsgAction::Continue),
+        }));
+        let recorder = Arc::new(MsgRecorder::new());
+        chain.push(recorder.clone());
+        assert_eq!(
+            chain.run(&alice, &burn).unwrap(),
+            MsgAction::Replace(send.clone())
+        );
+        chain.push(Arc::new(|_: &Addr, _: &CosmosMsg| {
+            Ok(MsgAction::Respond(ContractResult::Ok(Response::new())))
+        }));
+        assert_eq!(
+            chain.run(&alice, &send).unwrap(),
+            MsgAction::Respond(ContractResult::Ok(Response::new()))
+        );
+        // the recorder sees the rewritten message
+        assert_eq!(
+            recorder.messages(),
+            vec![(alice.clone(), send.clone()), (alice.clone(), send.clone())]
+        );
+
+        chain.push(Arc::new(|_: &Addr, _: &CosmosMsg| {
+            Err(Error::invalid_argument("unreachable"))
+        }));
+        // middlewares after a response are not run
+        assert!(chain.run(&alice, &send).is_ok());
+    }
+}
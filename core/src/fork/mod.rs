@@ -1,22 +1,99 @@
 mod api;
+mod assertions;
+mod auth;
+mod backtrace;
+mod build;
+mod capabilities;
 mod client_backend;
+mod closure;
+mod compat;
 mod debug_log;
+mod distribution;
+mod events;
+mod failover;
+mod fixture;
+mod funds;
+mod gov;
+mod hooks;
+mod ibc;
 mod instance;
+mod instance_pool;
 mod items;
+mod journal;
+mod keys;
+mod labels;
 mod lcd;
+mod middleware;
 mod model;
+mod module_cache;
+mod native;
+#[cfg(feature = "osmosis")]
+pub mod osmosis;
+mod printer;
+mod profile;
 mod querier;
+mod query_handle;
+mod redact;
+mod relayer;
+mod repro;
 mod rpc;
+mod schema;
+mod staking;
 mod states;
 mod storage;
+mod tokenfactory;
+mod watcher;
 
-pub use api::RpcMockApi;
-pub use client_backend::CwClientBackend;
-pub use debug_log::DebugLog;
+pub use api::{address_prefix, module_address, RpcMockApi};
+pub use auth::detect_bech32_prefix;
+pub use backtrace::{format_backtrace, take_backtrace, BacktraceFrame};
+pub use build::{build_wasm, check_wasm, read_wasm, WasmBuild};
+pub use capabilities::{
+    check_capabilities, code_capabilities, default_capabilities, DEFAULT_CAPABILITIES,
+};
+pub use client_backend::{
+    CacheConfig, CodeInfo, ContractInfo, CwClientBackend, DenomMetadata, DenomUnit, TxInfo,
+};
+pub use closure::{ClosureProgress, ForkClosure};
+pub use compat::CosmwasmVersion;
+pub use debug_log::{
+    AccessSet, CallEvent, CallFrame, CallNode, CallTrace, DebugLog, FrameRecord, StorageChange,
+    StorageWrite,
+};
+pub use distribution::Distribution;
+pub use events::contract_events;
+pub use failover::{is_transient, FailoverClient, RetryConfig};
+pub use fixture::{ContractFixture, StorageEntry};
+pub use funds::{merge_funds, validate_denom, validate_funds};
+pub use gov::{Gov, Proposal, ProposalStatus};
+pub use hooks::{CallKind, ExecutionHooks, HookEvent, HookRecorder};
+pub use ibc::{
+    contract_port, escrow_address, local_denom, port_contract, Acknowledgement, ChannelEnd,
+    FungibleTokenPacketData, Ibc, ICS20_VERSION, TRANSFER_PORT,
+};
 pub use instance::{RpcContractInstance, RpcInstance};
+pub use instance_pool::InstancePool;
 pub use items::rpc_items;
-pub use model::{Model, RpcBackend};
-pub use querier::RpcMockQuerier;
+pub use keys::{CheatKey, KeyAlgo};
+pub use labels::LabelRegistry;
+pub use middleware::{MiddlewareChain, MsgAction, MsgMiddleware, MsgRecorder};
+pub use model::{Model, RpcBackend, SandboxLimits, STORED_CODE_ID_START};
+pub use module_cache::{code_checksum, ModuleCache};
+pub use native::{NativeContract, NativeHandler};
+pub use printer::{hexdump, Print, PrintLevel, PrinterMsg, PRINTER_ADDR};
+pub use profile::{ContractProfile, FrameProfile, ProfileReport, ProfileSort};
+pub use querier::{RecursionConfig, RecursionPolicy, RpcMockQuerier};
+pub use query_handle::QueryHandle;
+pub use relayer::{DeliveryOrder, LinkedModels, RelayConfig, RelayedPacket};
+pub use repro::{CodeOverride, CustomCode, ReproReport};
 pub use rpc::CwRpcClient;
-pub use states::{AllStates, ContractState, ContractStorage};
-pub use storage::RpcMockStorage;
+pub use schema::{ContractSchema, MsgKind, MsgSchema};
+pub use states::{
+    diff_storage, AllStates, BlockConfig, CodeInfoQuery, ContractMetrics, ContractState,
+    ContractStorage, DenomMetadataQuery, DistributionQuery, MemoryStats, PageRequest,
+};
+pub use storage::{
+    namespace_prefix, prefix_end, scan_range, shared_storage, split_namespaced_key, LazyStorage,
+    RpcMockStorage, SharedStorage, StorageTracer,
+};
+pub use tokenfactory::TokenFactory;
@@ -1,28 +1,87 @@
+use crate::analyzer::WasmAnalysis;
 use crate::coverage::CoverageInfo;
-use crate::fork::api::canonical_to_human;
+use crate::fork::api::{canonical_to_human, human_to_canonical};
+use crate::fork::profile::Profiler;
+use crate::fork::states::StatesCheckpoint;
 use crate::{
-    rpc_items, AllStates, ContractState, ContractStorage, CwClientBackend, CwRpcClient, DebugLog,
-    Error, RpcContractInstance, RpcInstance, RpcMockApi, RpcMockQuerier, RpcMockStorage,
+    check_capabilities, format_backtrace, namespace_prefix, prefix_end, rpc_items, scan_range,
+    shared_storage, take_backtrace, AllStates, BlockConfig, CacheConfig, CallKind, ChannelEnd,
+    CheatKey, ClosureProgress, CodeInfo, CodeOverride, ContractFixture, ContractInfo,
+    ContractMetrics, ContractSchema, ContractState, ContractStorage, CosmwasmVersion, CustomCode,
+    CwClientBackend, CwRpcClient, DebugLog, DenomMetadata, Error, ExecutionHooks, FailoverClient,
+    ForkClosure, FrameRecord, InstancePool, KeyAlgo, LazyStorage, MemoryStats, MiddlewareChain,
+    ModuleCache, MsgAction, MsgKind, MsgMiddleware, NativeContract, ProfileReport, ProfileSort,
+    Proposal, ProposalStatus, QueryHandle, RecursionConfig, RecursionPolicy, ReproReport,
+    RpcContractInstance, RpcInstance, RpcMockApi, RpcMockQuerier, RpcMockStorage, SharedStorage,
+    StorageEntry, StorageTracer,
 };
 
 use cosmwasm_std::{
-    from_binary, Addr, BankMsg, BankQuery, Binary, Coin, ContractInfo, ContractResult, CosmosMsg,
-    Env, Event, Reply, ReplyOn, Response, SubMsgResponse, SubMsgResult, Timestamp, Uint128,
-    WasmMsg, WasmQuery,
+    from_binary, from_slice, to_binary, to_vec, Addr, BankMsg, BankQuery, Binary, BlockInfo, Coin,
+    ContractResult, CosmosMsg, Empty, Env, Event, IbcAcknowledgement, IbcChannelCloseMsg,
+    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcMsg, IbcOrder, IbcPacket, IbcPacketAckMsg,
+    IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcTimeout, MessageInfo, Order, QueryRequest, Record,
+    Reply, ReplyOn, Response, SubMsg, SubMsgResponse, SubMsgResult, SystemResult, Timestamp,
+    TransactionInfo, Uint128, WasmMsg, WasmQuery,
 };
 use cosmwasm_vm::internals::instance_from_module;
-use cosmwasm_vm::{Backend, InstanceOptions};
+use cosmwasm_vm::{Backend, BackendApi, InstanceOptions, Querier};
+use flate2::read::GzDecoder;
 use prost::Message;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::io::Read;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
-use wasmer::Module;
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, debug_span, info, info_span, warn};
+use tracing_subscriber::EnvFilter;
 
+use super::auth::detect_bech32_prefix;
+use super::build::{build_wasm, read_wasm, WasmBuild};
+use super::closure::scan_references;
+use super::events::{
+    contract_events, execute_event, instantiate_event, migrate_event, reply_event, sudo_event,
+};
+use super::funds::{merge_funds, validate_funds};
+use super::ibc::{is_timed_out, port_contract, Acknowledgement, ICS20_VERSION, TRANSFER_PORT};
+use super::labels::LabelRegistry;
 use super::lcd::CwLcdClient;
+use super::module_cache::code_checksum;
+use super::redact::{
+    msg_error_code, redact_error, AbciCode, EXECUTE_FAILED, INSTANTIATE_FAILED, MIGRATION_FAILED,
+    UNAUTHORIZED, UNDEFINED,
+};
+use super::watcher::Watcher;
 
 pub type RpcBackend = Backend<RpcMockApi, RpcMockStorage, RpcMockQuerier>;
 
+/// limits protecting the host from runaway contracts, and emulating the limits of a chain.
+/// Exceeding them fails the top-level call with Error::LimitExceeded.
+/// The depth of nested queries is limited by RecursionConfig
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SandboxLimits {
+    /// maximum memory of a contract instance in bytes, None for the 4 GiB of wasm32
+    pub memory_limit: Option<usize>,
+    /// maximum number of nested contract calls, counting the top-level call
+    pub max_call_depth: Option<usize>,
+}
+
+/// state changes made after a checkpoint can be rolled back, like a cached context of wasmd
+struct Checkpoint {
+    states: StatesCheckpoint,
+    code_id_counters: HashMap<u64, u64>,
+    // number of events recorded in the debug log
+    events: usize,
+}
+
 pub struct Model {
     states: Arc<RwLock<AllStates>>,
     // similar to tx.origin of solidity
@@ -35,23 +94,111 @@ pub struct Model {
     custom_codes: HashMap<u64, Vec<u8>>,
     // for code coverage
     pub coverage_info: CoverageInfo,
-    // for saving webassembly compilation time
-    pub wasm_cache: HashMap<Vec<u8>, Module>,
+    // for saving webassembly compilation time, shared across clones
+    pub wasm_cache: ModuleCache,
+    // idle instances of queried contracts, shared across clones
+    instance_pool: InstancePool,
+    // labeled accounts, for scenarios with multiple actors
+    accounts: HashMap<String, Addr>,
+    // names of contracts given by the user, shown in call traces
+    labels: LabelRegistry,
+    // record every storage write in the debug log
+    trace_storage: bool,
+    // charge the gas of queried contracts to the contracts querying them
+    meter_queries: bool,
+    // record every call with the state it ran in in the debug log
+    record_frames: bool,
+    // attach the wasm backtrace of contracts which trapped to errors and the debug log
+    capture_backtraces: bool,
+    // how reentrancy and deep query recursion are handled
+    recursion: RecursionConfig,
+    // contracts whose messages are being processed, outermost first
+    call_stack: Vec<Addr>,
+    // only the admin of a contract may migrate it or change its admin
+    admin_checks: bool,
+    // merge duplicate denoms and drop zero amounts of funds instead of rejecting them
+    merge_funds: bool,
+    // replies see only the codespace and code of errors like on wasmd, see enable_reply_error_redaction
+    redact_reply_errors: bool,
+    // gas left to the innermost gas limited submessage being processed, if any
+    gas_left: Option<u64>,
+    // see every message before it is dispatched
+    middlewares: MiddlewareChain,
+    // salts generated addresses, None keeps addresses compatible with unseeded models
+    seed: Option<u64>,
+    // source of randomness for simulation utilities, seeded with seed
+    rng: StdRng,
+    // checksums of the code set by cheat_code
+    code_overrides: BTreeMap<Addr, Vec<u8>>,
+    // schemas of the messages of codes, checked before top-level messages are run
+    schemas: HashMap<u64, Arc<ContractSchema>>,
+    // see SandboxLimits, the memory limit is kept by the module cache
+    max_call_depth: Option<usize>,
+    // memory and gas used by the calls of each contract, see contract_metrics
+    call_metrics: HashMap<Addr, ContractMetrics>,
+    // times the calls of contracts if profiling is enabled, see profile_report
+    profiler: Option<Profiler>,
+    // wasm files contracts are reloaded from, see watch_and_reload
+    watcher: Watcher,
+    // balances the sender is topped up to, see with_faucet
+    faucet: Vec<Coin>,
+    // block of the running transaction, fixed when it started so that every call and query
+    // of the transaction sees the same env
+    tx_block: Option<BlockInfo>,
+    // transactions run so far, which tells apart the hashes of transactions in the same block
+    tx_sequence: u64,
+    // set between begin_block and end_block, to the transaction index set before the block
+    open_block: Option<u32>,
 }
 
 const WASM_MAGIC: [u8; 4] = [0, 97, 115, 109];
-const GZIP_MAGIC: [u8; 4] = [0, 0, 0, 0];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 const BASE_EOA: &str = "wasm1zcnn5gh37jxg9c6dp4jcjc7995ae0s5f5hj0lj";
+/// code stored by Model::store_code gets code ids from here on, far beyond the code ids of any chain
+pub const STORED_CODE_ID_START: u64 = 1_000_000_000;
+/// CosmWasm gas per SDK gas, as in wasmd. SubMsg.gas_limit is given in SDK gas
+const GAS_MULTIPLIER: u64 = 140_000_000;
+
+/// BASE_EOA with the bech32 prefix of the chain
+fn base_eoa(bech32_prefix: &str) -> Result<String, Error> {
+    let canonical = human_to_canonical(BASE_EOA, "wasm").map_err(Error::format_error)?;
+    canonical_to_human(&canonical, bech32_prefix, canonical.len()).map_err(Error::format_error)
+}
+
+/// timestamp moved forward by duration, failing instead of overflowing
+fn advance_timestamp(timestamp: Timestamp, duration: Duration) -> Result<Timestamp, Error> {
+    u64::try_from(duration.as_nanos())
+        .ok()
+        .and_then(|nanos| timestamp.nanos().checked_add(nanos))
+        .map(Timestamp::from_nanos)
+        .ok_or_else(|| {
+            Error::invalid_argument(format!(
+                "advancing the block time {} by {:?} overflows",
+                timestamp, duration
+            ))
+        })
+}
 
+/// chains may store wasm code gzipped, decompress it if needed
 pub fn maybe_unzip(input: Vec<u8>) -> Result<Vec<u8>, Error> {
-    let magic = &input[0..4];
-    if magic == WASM_MAGIC {
+    if input.starts_with(&WASM_MAGIC) {
         Ok(input)
-    } else if magic == GZIP_MAGIC {
-        unimplemented!();
+    } else if input.starts_with(&GZIP_MAGIC) {
+        let mut decoder = GzDecoder::new(input.as_slice());
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).map_err(Error::from)?;
+        if output.starts_with(&WASM_MAGIC) {
+            Ok(output)
+        } else {
+            Err(Error::format_error(
+                "decompressed code is not a wasm binary",
+            ))
+        }
     } else {
-        eprintln!("unidentifiable magic: {:?}", magic);
-        unimplemented!();
+        Err(Error::format_error(format!(
+            "unidentifiable magic: {:?}",
+            &input[0..input.len().min(4)]
+        )))
     }
 }
 
@@ -65,44 +212,207 @@ impl Clone for Model {
             custom_codes: self.custom_codes.clone(),
             coverage_info: self.coverage_info.clone(),
             wasm_cache: self.wasm_cache.clone(),
+            instance_pool: self.instance_pool.clone(),
+            accounts: self.accounts.clone(),
+            labels: self.labels.clone(),
+            trace_storage: self.trace_storage,
+            meter_queries: self.meter_queries,
+            record_frames: self.record_frames,
+            capture_backtraces: self.capture_backtraces,
+            recursion: self.recursion,
+            call_stack: self.call_stack.clone(),
+            admin_checks: self.admin_checks,
+            merge_funds: self.merge_funds,
+            redact_reply_errors: self.redact_reply_errors,
+            gas_left: self.gas_left,
+            middlewares: self.middlewares.clone(),
+            seed: self.seed,
+            rng: self.rng.clone(),
+            code_overrides: self.code_overrides.clone(),
+            schemas: self.schemas.clone(),
+            max_call_depth: self.max_call_depth,
+            call_metrics: self.call_metrics.clone(),
+            profiler: self.profiler.clone(),
+            watcher: self.watcher.clone(),
+            faucet: self.faucet.clone(),
+            // a copy has no running transaction
+            tx_block: None,
+            tx_sequence: self.tx_sequence,
+            open_block: self.open_block,
         }
     }
 }
 
 impl Model {
-    pub fn new_lcd(url: &str, bech32_prefix: &str) -> Result<Self, Error> {
-        let client: Box<dyn CwClientBackend> = Box::new(CwLcdClient::new(url)?);
-        Ok(Model {
-            states: Arc::new(RwLock::new(AllStates::new(client, 32, bech32_prefix)?)),
-            sender: BASE_EOA.to_string(),
-            code_id_counters: HashMap::new(),
-            debug_log: Arc::new(Mutex::new(DebugLog::new())),
-            custom_codes: HashMap::new(),
-            coverage_info: CoverageInfo::new(),
-            wasm_cache: HashMap::new(),
-        })
+    /// forks from an LCD endpoint, pinned to block_number or to the latest block like Model::new
+    pub fn new_lcd(
+        url: &str,
+        block_number: Option<u64>,
+        bech32_prefix: &str,
+    ) -> Result<Self, Error> {
+        Self::from_client(
+            Box::new(CwLcdClient::new(url, block_number)?),
+            bech32_prefix,
+        )
     }
 
     pub fn new(url: &str, block_number: Option<u64>, bech32_prefix: &str) -> Result<Self, Error> {
         // for now, let's not use LCD and default to RPC
-        let client: Box<dyn CwClientBackend> = Box::new(CwRpcClient::new(url, block_number)?);
+        Self::from_client(
+            Box::new(CwRpcClient::new(url, block_number)?),
+            bech32_prefix,
+        )
+    }
+
+    /// forks like Model::new, with the bech32 prefix of the chain instead of a given one, see
+    /// detect_bech32_prefix. Accounts and contracts may have 20 or 32 byte canonical addresses
+    pub fn new_auto(url: &str, block_number: Option<u64>) -> Result<Self, Error> {
+        let mut client = CwRpcClient::new(url, block_number)?;
+        let bech32_prefix = detect_bech32_prefix(&mut client)?;
+        Self::from_client(Box::new(client), &bech32_prefix)
+    }
+
+    /// forks from a cache of rpc responses without any network access, see CwRpcClient::offline.
+    /// Anything that was not cached fails with an error naming the missing query
+    pub fn new_offline(
+        cache_path: &Path,
+        block_number: u64,
+        bech32_prefix: &str,
+    ) -> Result<Self, Error> {
+        Self::from_client(
+            Box::new(CwRpcClient::offline(cache_path, block_number)?),
+            bech32_prefix,
+        )
+    }
+
+    /// forks from several rpc endpoints of the same chain, failing over to the next one
+    /// whenever a request fails transiently, see FailoverClient
+    pub fn new_failover(
+        urls: &[&str],
+        block_number: Option<u64>,
+        bech32_prefix: &str,
+    ) -> Result<Self, Error> {
+        Self::from_client(
+            Box::new(FailoverClient::rpc(urls, block_number)?),
+            bech32_prefix,
+        )
+    }
+
+    /// forks the chain served by client
+    pub fn from_client(
+        client: Box<dyn CwClientBackend>,
+        bech32_prefix: &str,
+    ) -> Result<Self, Error> {
         Ok(Model {
             states: Arc::new(RwLock::new(AllStates::new(client, 32, bech32_prefix)?)),
-            sender: BASE_EOA.to_string(),
+            sender: base_eoa(bech32_prefix)?,
             code_id_counters: HashMap::new(),
             debug_log: Arc::new(Mutex::new(DebugLog::new())),
             custom_codes: HashMap::new(),
             coverage_info: CoverageInfo::new(),
-            wasm_cache: HashMap::new(),
+            wasm_cache: ModuleCache::default(),
+            instance_pool: InstancePool::new(),
+            accounts: HashMap::new(),
+            labels: LabelRegistry::new(),
+            trace_storage: false,
+            meter_queries: false,
+            record_frames: false,
+            capture_backtraces: false,
+            recursion: RecursionConfig::default(),
+            call_stack: Vec::new(),
+            admin_checks: true,
+            merge_funds: false,
+            redact_reply_errors: false,
+            gas_left: None,
+            middlewares: MiddlewareChain::new(),
+            seed: None,
+            rng: StdRng::seed_from_u64(0),
+            code_overrides: BTreeMap::new(),
+            schemas: HashMap::new(),
+            max_call_depth: None,
+            call_metrics: HashMap::new(),
+            profiler: None,
+            watcher: Watcher::default(),
+            faucet: Vec::new(),
+            tx_block: None,
+            tx_sequence: 0,
+            open_block: None,
         })
     }
 
+    /// sends top-level messages from sender instead of BASE_EOA
+    pub fn with_sender(mut self, sender: &Addr) -> Result<Self, Error> {
+        self.sender = sender.to_string();
+        self.fund_sender()?;
+        Ok(self)
+    }
+
+    /// tops the balances of the sender, and of the senders given later with with_sender, up to
+    /// funds, so that the first messages don't fail for an unknown balance of the sender
+    pub fn with_faucet(mut self, funds: &[Coin]) -> Result<Self, Error> {
+        self.faucet = funds.to_vec();
+        self.fund_sender()?;
+        Ok(self)
+    }
+
+    fn fund_sender(&mut self) -> Result<(), Error> {
+        let sender = self.sender();
+        let mut states = self.states.write().unwrap();
+        for coin in self.faucet.iter() {
+            if states.get_balance(&sender, &coin.denom)? < coin.amount {
+                states.set_balance(&sender, &coin.denom, coin.amount)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn block_number(&self) -> u64 {
         self.states.read().unwrap().client.block_number()
     }
 
+    /// lowest height the node still has blocks for, to pick a fork point when a query
+    /// fails with Error::HeightPruned
+    pub fn earliest_available_block(&self) -> Result<u64, Error> {
+        self.with_client(|client| client.earliest_available_block())
+    }
+
+    /// runs f with the client backend of this model
+    pub(crate) fn with_client<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut dyn CwClientBackend) -> Result<T, Error>,
+    {
+        f(self.states.write().unwrap().client.as_mut())
+    }
+
+    /// address used as the sender of top-level messages
+    pub fn sender(&self) -> Addr {
+        Addr::unchecked(&self.sender)
+    }
+
+    /// returns a read-only handle which can be used to query the model from other threads
+    pub fn query_handle(&self) -> QueryHandle {
+        QueryHandle::new(&self.states).with_module_cache(&self.wasm_cache)
+    }
+
+    /// a querier of the states of the model as contracts see them, e.g. to build a QuerierWrapper.
+    /// Its queries are not recorded in the debug log
+    pub fn querier(&self) -> RpcMockQuerier {
+        RpcMockQuerier::new(&self.states, &Arc::new(Mutex::new(DebugLog::new())))
+            .with_module_cache(&self.wasm_cache)
+    }
+
+    /// the block contracts see in their env
+    pub fn block_info(&self) -> BlockInfo {
+        let states = self.states.read().unwrap();
+        BlockInfo {
+            height: states.block_number,
+            time: states.block_timestamp,
+            chain_id: states.chain_id.to_string(),
+        }
+    }
+
     /// Does nothing if the state already exists
-    fn fetch_contract_state(&self, contract_addr: &Addr) -> Result<(), Error> {
+    pub(crate) fn fetch_contract_state(&self, contract_addr: &Addr) -> Result<(), Error> {
         if self
             .states
             .read()
@@ -112,28 +422,37 @@ impl Model {
         {
             return Ok(());
         }
-        let contract_info = self
-            .states
-            .write()
-            .unwrap()
+        let mut states = self.states.write().unwrap();
+        states.check_fetched_address(contract_addr)?;
+        let contract_info = states
             .client
             .query_wasm_contract_info(contract_addr.as_str())?;
-        let wasm_code = maybe_unzip(
-            self.states
-                .write()
-                .unwrap()
-                .client
-                .query_wasm_contract_code(contract_info.code_id)?,
-        )?;
+        let wasm_code = states
+            .client
+            .query_wasm_contract_code(contract_info.code_id)?;
+        let (storage, lazy) = states.fetch_contract_storage(contract_addr)?;
+        drop(states);
+        self.insert_fetched_contract(contract_addr, contract_info, wasm_code, storage, lazy)
+    }
+
+    fn insert_fetched_contract(
+        &self,
+        contract_addr: &Addr,
+        info: ContractInfo,
+        wasm_code: Vec<u8>,
+        storage: ContractStorage,
+        lazy: Option<Arc<LazyStorage>>,
+    ) -> Result<(), Error> {
+        self.states
+            .read()
+            .unwrap()
+            .check_fetched_code_id(contract_addr, info.code_id)?;
+        let wasm_code = self.maybe_instrument(contract_addr, maybe_unzip(wasm_code)?)?;
         let contract_state = ContractState {
-            code: wasm_code,
-            storage: Arc::new(RwLock::new(
-                self.states
-                    .write()
-                    .unwrap()
-                    .client
-                    .query_wasm_contract_state_all(contract_addr.as_str())?,
-            )),
+            code: Arc::new(wasm_code),
+            storage: shared_storage(storage),
+            info,
+            lazy,
         };
         self.states
             .write()
@@ -142,9 +461,161 @@ impl Model {
         Ok(())
     }
 
+    /// fetches code and state of the given contracts ahead of time, and compiles their code in parallel
+    /// so that the first execution touching them isn't dominated by lazy fetching and compilation.
+    /// The RPC backend sends the queries of all contracts concurrently
+    pub fn preload_contracts(&mut self, contract_addrs: &[Addr]) -> Result<(), Error> {
+        let mut missing: Vec<Addr> = {
+            let states = self.states.read().unwrap();
+            contract_addrs
+                .iter()
+                .filter(|a| states.contract_state_get(a).is_none())
+                .cloned()
+                .collect()
+        };
+        missing.sort();
+        missing.dedup();
+        let addresses: Vec<String> = missing.iter().map(|a| a.to_string()).collect();
+        let (infos, codes, storages) = {
+            let mut states = self.states.write().unwrap();
+            let infos = states.client.query_wasm_contracts_info(&addresses)?;
+            let mut code_ids: Vec<u64> = infos.iter().map(|i| i.code_id).collect();
+            code_ids.sort_unstable();
+            code_ids.dedup();
+            let codes = states.client.query_wasm_contract_codes(&code_ids)?;
+            let codes: HashMap<u64, Vec<u8>> = code_ids.into_iter().zip(codes).collect();
+            // lazily loaded contracts start out empty
+            let storages = if states.lazy_storage {
+                vec![ContractStorage::new(); addresses.len()]
+            } else {
+                states.client.query_wasm_contracts_state_all(&addresses)?
+            };
+            (infos, codes, storages)
+        };
+        for ((contract_addr, info), storage) in missing.iter().zip(infos).zip(storages) {
+            let code = codes[&info.code_id].clone();
+            let lazy = self.states.read().unwrap().lazy_source(contract_addr);
+            self.insert_fetched_contract(contract_addr, info, code, storage, lazy)?;
+        }
+        // contracts sharing the same code only need to be compiled once
+        let mut codes = HashMap::new();
+        {
+            let states = self.states.read().unwrap();
+            for contract_addr in contract_addrs.iter() {
+                let code = &states.contract_state_get(contract_addr).unwrap().code;
+                codes
+                    .entry(code_checksum(code))
+                    .or_insert_with(|| code.clone());
+            }
+        }
+        let codes: Vec<Arc<Vec<u8>>> = codes.into_values().collect();
+        let parallelism = thread::available_parallelism().map_or(1, |n| n.get());
+        let wasm_cache = &self.wasm_cache;
+        for chunk in codes.chunks(parallelism) {
+            thread::scope(|s| {
+                let handles: Vec<_> = chunk
+                    .iter()
+                    .map(|code| s.spawn(move || wasm_cache.get_or_compile(code)))
+                    .collect();
+                handles
+                    .into_iter()
+                    .try_for_each(|h| h.join().unwrap().map(|_| ()))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// loads root and the contracts it depends on ahead of time, e.g. a factory with its pairs and tokens,
+    /// see Model::fork_closure_with_progress
+    pub fn fork_closure(&mut self, root: &Addr, depth: usize) -> Result<ForkClosure, Error> {
+        self.fork_closure_with_progress(root, depth, |_| {})
+    }
+
+    /// loads root, then the contracts whose addresses are found in the storage of loaded contracts
+    /// up to depth levels away from root, with preload_contracts.
+    /// Code ids referenced by their storage, e.g. the pair code of a factory, are fetched and compiled too.
+    /// Only addresses as long as contract addresses are followed, so that accounts, e.g. token holders,
+    /// aren't looked up one by one
+    pub fn fork_closure_with_progress<F>(
+        &mut self,
+        root: &Addr,
+        depth: usize,
+        mut progress: F,
+    ) -> Result<ForkClosure, Error>
+    where
+        F: FnMut(&ClosureProgress),
+    {
+        let mut closure = ForkClosure::default();
+        let mut level = vec![root.clone()];
+        for current in 0..=depth {
+            progress(&ClosureProgress::Depth {
+                depth: current,
+                contracts: level.len(),
+            });
+            self.preload_contracts(&level)?;
+            for address in level.iter() {
+                closure.contracts.insert(address.clone(), current);
+                progress(&ClosureProgress::Contract {
+                    address: address.clone(),
+                    depth: current,
+                });
+            }
+            let mut references = BTreeSet::new();
+            for address in level.iter() {
+                let storage = self.contract_storage(address)?;
+                let states = self.states.read().unwrap();
+                let found = scan_references(
+                    &storage,
+                    &states.bech32_prefix,
+                    states.canonical_address_length,
+                );
+                references.extend(found.addresses);
+                closure.code_ids.extend(found.code_ids);
+            }
+            if current == depth {
+                break;
+            }
+            // addresses without contract info are accounts
+            let mut states = self.states.write().unwrap();
+            level = references
+                .into_iter()
+                .filter(|a| !closure.contracts.contains_key(a))
+                .filter(|a| {
+                    states.contract_state_get(a).is_some()
+                        || states.client.query_wasm_contract_info(a.as_str()).is_ok()
+                })
+                .collect();
+            if level.is_empty() {
+                break;
+            }
+        }
+        let loaded: BTreeSet<u64> = {
+            let states = self.states.read().unwrap();
+            closure
+                .contracts
+                .keys()
+                .map(|a| states.contract_state_get(a).unwrap().info.code_id)
+                .collect()
+        };
+        for code_id in closure.code_ids.iter() {
+            if loaded.contains(code_id) {
+                continue;
+            }
+            // code ids which don't exist are usually numbers which happen to be named so
+            if let Ok(code) = self.fetch_code(*code_id) {
+                self.wasm_cache.get_or_compile(&code)?;
+                progress(&ClosureProgress::Code { code_id: *code_id });
+            }
+        }
+        Ok(closure)
+    }
+
     fn generate_address(&mut self, code_id: u64) -> Result<Addr, Error> {
         let code_id_counter = self.code_id_counters.entry(code_id).or_insert(0);
-        let seed = format!("seeeed_{}_{}", code_id, *code_id_counter);
+        let seed = match self.seed {
+            Some(seed) => format!("seeeed_{}_{}_{}", seed, code_id, *code_id_counter),
+            None => format!("seeeed_{}_{}", code_id, *code_id_counter),
+        };
         // TODO: counter must not be incremented if instantiation fails
         *code_id_counter += 1;
         let mut hasher = Sha256::new();
@@ -159,34 +630,51 @@ impl Model {
         Ok(Addr::unchecked(addr))
     }
 
-    fn revert(&mut self, prev_state: Model) -> Model {
+    pub(crate) fn revert(&mut self, prev_state: Model) -> Model {
         // don't revert coverage state
-        let cur_state: Model = mem::replace(self, prev_state);
+        let mut cur_state: Model = mem::replace(self, prev_state);
         self.coverage_info = cur_state.coverage_info.clone();
+        // keep the original states handle, so that QueryHandles observe the reverted states
+        mem::swap(
+            &mut *self.states.write().unwrap(),
+            &mut *cur_state.states.write().unwrap(),
+        );
+        mem::swap(&mut self.states, &mut cur_state.states);
         cur_state
     }
 
+    /// restores the state of snapshot, a clone of this model taken earlier.
+    /// Coverage collected since is kept, and QueryHandles observe the restored state
+    pub fn restore(&mut self, snapshot: &Model) {
+        self.revert(snapshot.clone());
+    }
+
+    /// a query which exceeded the depth limit under RecursionPolicy::Abort fails the call,
+    /// even if the contract handled the query error
+    fn check_aborted(
+        &self,
+        result: Result<ContractResult<Response>, Error>,
+    ) -> Result<ContractResult<Response>, Error> {
+        match self.debug_log.lock().unwrap().take_abort() {
+            Some(reason) => Ok(ContractResult::Err(reason)),
+            None => result,
+        }
+    }
+
     fn create_instance(&self, contract_addr: &Addr) -> Result<RpcContractInstance, Error> {
         self.fetch_contract_state(contract_addr)?;
         let states = self.states.read().unwrap();
         let contract_state = states.contract_state_get(contract_addr).unwrap();
-        let deps = self.new_mock(&contract_state.storage)?;
+        let deps = self.new_mock(contract_addr, &contract_state.storage, &contract_state.lazy)?;
         let options = InstanceOptions {
-            gas_limit: u64::MAX,
+            gas_limit: self.gas_left.unwrap_or(u64::MAX),
             print_debug: false,
         };
-        let wasm_instance = match cosmwasm_vm::Instance::from_code(
-            contract_state.code.as_slice(),
-            deps,
-            options,
-            None,
-        ) {
-            Err(e) => {
-                return Err(Error::vm_error(e));
-            }
-            Ok(i) => i,
-        };
-        Ok(RpcContractInstance::new(contract_addr, wasm_instance))
+        let wasm_instance =
+            self.create_instance_from_code(contract_state.code.as_slice(), deps, options)?;
+        Ok(RpcContractInstance::new(contract_addr, wasm_instance)
+            .with_memory_limit(self.wasm_cache.memory_limit())
+            .with_code_id(contract_state.info.code_id))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -197,436 +685,541 @@ impl Model {
         code_id: u64,
         msg: &Binary,
         funds: &[Coin],
+        label: &str,
+        gas_limit: Option<u64>,
         sub_msg_id: u64,
         reply_on: &ReplyOn,
     ) -> Result<ContractResult<Response>, Error> {
-        let (response, new_addr) = match admin {
-            Some(allowed) => {
-                if allowed != origin {
-                    (
-                        ContractResult::Err("cannot instantiate contract".to_string()),
-                        None,
-                    )
-                } else {
-                    let (res, new_addr) = self.instantiate_inner(code_id, origin, msg, funds)?;
-                    (res, new_addr)
-                }
-            }
-            None => {
-                let (res, new_addr) = self.instantiate_inner(code_id, origin, msg, funds)?;
-                (res, new_addr)
-            }
-        };
-        let do_reply = match reply_on {
-            ReplyOn::Always => true,
-            ReplyOn::Success => response.is_ok(),
-            ReplyOn::Error => response.is_err(),
-            ReplyOn::Never => false,
-        };
-        if do_reply {
+        let checkpoint = self.reply_checkpoint(reply_on);
+        let (response, new_addr) = self
+            .with_gas_limit(gas_limit, |model| {
+                model.instantiate_inner(code_id, origin, msg, funds, admin.clone(), label)
+            })?
+            .unwrap_or_else(|e| (ContractResult::Err(e), None));
+        if let (ContractResult::Err(_), Some(checkpoint)) = (&response, checkpoint) {
+            self.rollback(checkpoint);
+        }
+        let encode_data = |data: Option<Binary>| {
             let data = rpc_items::cosmwasm::wasm::v1::MsgInstantiateContractResponse {
-                address: if let Some(a) = new_addr {
-                    a.to_string()
-                } else {
-                    "".to_string()
-                },
-                data: Vec::new(),
-            };
-            let env = self.env(origin)?;
-            let reply = Reply {
-                id: sub_msg_id,
-                result: match response {
-                    ContractResult::Ok(r) => SubMsgResult::Ok(SubMsgResponse {
-                        events: r.events,
-                        data: Some(Binary::from(Message::encode_to_vec(&data))),
-                    }),
-                    ContractResult::Err(e) => SubMsgResult::Err(e),
-                },
+                address: new_addr.map(|a| a.to_string()).unwrap_or_default(),
+                data: data.map(|d| d.to_vec()).unwrap_or_default(),
             };
-
-            let mut instance = self.create_instance(origin)?;
-
-            // open new call context
-            let call_id = self.debug_log.lock().unwrap().begin_reply(origin, msg);
-
-            let maybe_response = instance.reply(&env, &reply)?;
-            self.handle_coverage(&mut instance)?;
-
-            if let ContractResult::Err(e) = &maybe_response {
-                // propagate error. instance.reply need not error handling
-                // no need to re-insert the instance
-                self.debug_log.lock().unwrap().begin_error(e);
-                Ok(maybe_response)
-            } else {
-                let response = maybe_response.unwrap();
-                self.debug_log.lock().unwrap().append_log(&response);
-                let response = self.handle_response(origin, &response)?;
-                // close call context
-                self.debug_log.lock().unwrap().end_reply(call_id);
-                Ok(response)
-            }
-        }
-        // if reply is not called, but the current result is an error, propagate the error
-        else if let ContractResult::Err(e) = &response {
-            self.debug_log.lock().unwrap().begin_error(e);
-            Ok(ContractResult::Err(response.unwrap_err()))
-        }
-        // otherwise, recursively handle the submessages
-        else {
-            self.handle_response(origin, &response.unwrap())
-        }
+            Some(Binary::from(Message::encode_to_vec(&data)))
+        };
+        self.handle_submessage_result(
+            origin,
+            msg,
+            response,
+            encode_data,
+            INSTANTIATE_FAILED,
+            sub_msg_id,
+            reply_on,
+        )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn handle_submessage_execute(
         &mut self,
         origin: &Addr,
         target_addr: &Addr,
         msg: &Binary,
         funds: &[Coin],
+        gas_limit: Option<u64>,
         sub_msg_id: u64,
         reply_on: &ReplyOn,
     ) -> Result<ContractResult<Response>, Error> {
-        let response = self.execute_inner(target_addr, origin, msg.as_slice(), funds)?;
-        let do_reply = match reply_on {
-            ReplyOn::Always => true,
-            ReplyOn::Success => response.is_ok(),
-            ReplyOn::Error => response.is_err(),
-            ReplyOn::Never => false,
-        };
-        if do_reply {
-            let data =
-                rpc_items::cosmwasm::wasm::v1::MsgExecuteContractResponse { data: Vec::new() };
-            let env = self.env(origin)?;
-            let reply = Reply {
-                id: sub_msg_id,
-                result: match response {
-                    ContractResult::Ok(r) => SubMsgResult::Ok(SubMsgResponse {
-                        events: r.events,
-                        data: Some(Binary::from(Message::encode_to_vec(&data))),
-                    }),
-                    ContractResult::Err(e) => SubMsgResult::Err(e),
-                },
+        let checkpoint = self.reply_checkpoint(reply_on);
+        let response = self
+            .with_gas_limit(gas_limit, |model| {
+                model.execute_inner(target_addr, origin, msg.as_slice(), funds, true)
+            })?
+            .unwrap_or_else(ContractResult::Err);
+        if let (ContractResult::Err(_), Some(checkpoint)) = (&response, checkpoint) {
+            self.rollback(checkpoint);
+        }
+        let encode_data = |data: Option<Binary>| {
+            let data = rpc_items::cosmwasm::wasm::v1::MsgExecuteContractResponse {
+                data: data.map(|d| d.to_vec()).unwrap_or_default(),
             };
+            Some(Binary::from(Message::encode_to_vec(&data)))
+        };
+        self.handle_submessage_result(
+            origin,
+            msg,
+            response,
+            encode_data,
+            EXECUTE_FAILED,
+            sub_msg_id,
+            reply_on,
+        )
+    }
 
-            let mut instance = self.create_instance(origin)?;
-
-            // open new call context
-            let call_id = self.debug_log.lock().unwrap().begin_reply(origin, msg);
-
-            let maybe_response = instance.reply(&env, &reply)?;
-            self.handle_coverage(&mut instance)?;
-
-            if let ContractResult::Err(e) = &maybe_response {
-                // propagate error. instance.reply need not error handling
-                // no need to re-insert the instance
-                self.debug_log.lock().unwrap().begin_error(e);
-                Ok(maybe_response)
-            } else {
-                let response = maybe_response.unwrap();
-                self.debug_log.lock().unwrap().append_log(&response);
-                let response = self.handle_response(origin, &response)?;
-                // close call context
-                self.debug_log.lock().unwrap().end_reply(call_id);
-                Ok(response)
-            }
-        }
-        // if reply is not called, but the current result is an error, propagate the error
-        else if let ContractResult::Err(e) = &response {
-            self.debug_log.lock().unwrap().begin_error(e);
-            Ok(ContractResult::Err(response.unwrap_err()))
+    #[allow(clippy::too_many_arguments)]
+    fn handle_submessage_migrate(
+        &mut self,
+        origin: &Addr,
+        target_addr: &Addr,
+        new_code_id: u64,
+        msg: &Binary,
+        gas_limit: Option<u64>,
+        sub_msg_id: u64,
+        reply_on: &ReplyOn,
+    ) -> Result<ContractResult<Response>, Error> {
+        let checkpoint = self.reply_checkpoint(reply_on);
+        let response = self
+            .with_gas_limit(gas_limit, |model| {
+                model.migrate_inner(target_addr, origin, new_code_id, msg.as_slice())
+            })?
+            .unwrap_or_else(ContractResult::Err);
+        if let (ContractResult::Err(_), Some(checkpoint)) = (&response, checkpoint) {
+            self.rollback(checkpoint);
         }
-        // otherwise, recursively handle the submessages
-        else {
-            self.handle_response(origin, &response.unwrap())
+        let encode_data = |data: Option<Binary>| {
+            let data = rpc_items::cosmwasm::wasm::v1::MsgMigrateContractResponse {
+                data: data.map(|d| d.to_vec()).unwrap_or_default(),
+            };
+            Some(Binary::from(Message::encode_to_vec(&data)))
+        };
+        self.handle_submessage_result(
+            origin,
+            msg,
+            response,
+            encode_data,
+            MIGRATION_FAILED,
+            sub_msg_id,
+            reply_on,
+        )
+    }
+
+    /// state to roll a failed submessage back to, before its error is passed to reply.
+    /// Without a reply on error, the error fails the caller as well, so no checkpoint is needed
+    fn reply_checkpoint(&self, reply_on: &ReplyOn) -> Option<Checkpoint> {
+        match reply_on {
+            ReplyOn::Always | ReplyOn::Error => Some(self.checkpoint()),
+            ReplyOn::Success | ReplyOn::Never => None,
         }
     }
 
-    fn handle_response(
+    /// executes a submessage to a module other than wasm, whose messages return no data.
+    /// Like wasmd, a failed message is rolled back and passed to reply if origin asked for it,
+    /// otherwise it fails origin
+    fn handle_submessage_module<F>(
         &mut self,
         origin: &Addr,
-        response: &Response,
-    ) -> Result<ContractResult<Response>, Error> {
-        // last_response is the response of the latest execution
-        // If there are no submessages, this will be returned. Otherwise, response from the submessages will be returned
-        if response.messages.is_empty() {
-            return Ok(ContractResult::Ok(response.clone()));
-        }
-        // this will be overwritten at least once
-        let mut last_response = ContractResult::Ok(Response::new());
-        // otherwise, execute the submessages
-        for sub_msg in response.messages.iter() {
-            let response = match &sub_msg.msg {
-                CosmosMsg::Wasm(wasm_msg) => match wasm_msg {
-                    WasmMsg::Instantiate {
-                        admin,
-                        code_id,
-                        msg,
-                        funds,
-                        label: _,
-                    } => self.handle_submessage_instantiate(
-                        origin,
-                        admin,
-                        *code_id,
-                        msg,
-                        funds,
-                        sub_msg.id,
-                        &sub_msg.reply_on,
-                    )?,
-                    WasmMsg::Execute {
-                        contract_addr: target_addr,
-                        msg,
-                        funds,
-                    } => self.handle_submessage_execute(
-                        origin,
-                        &Addr::unchecked(target_addr),
-                        msg,
-                        funds,
-                        sub_msg.id,
-                        &sub_msg.reply_on,
-                    )?,
-                    _ => unimplemented!(),
-                },
-                CosmosMsg::Bank(bank_msg) => {
-                    // if bank fails, revert the entire transaction
-                    self.states
-                        .write()
-                        .unwrap()
-                        .bank_execute(origin, bank_msg)?
-                }
-                _ => unimplemented!(),
-            };
-            if response.is_err() {
-                return Ok(response);
-            } else {
-                last_response = response;
-            }
+        sub_msg: &SubMsg,
+        execute: F,
+    ) -> Result<ContractResult<Response>, Error>
+    where
+        F: FnOnce(&mut AllStates) -> Result<ContractResult<Response>, Error>,
+    {
+        let checkpoint = self.reply_checkpoint(&sub_msg.reply_on);
+        let response = execute(&mut *self.states.write().unwrap())?;
+        match (&response, checkpoint) {
+            (ContractResult::Ok(r), _) => self.debug_log.lock().unwrap().append_events(&r.events),
+            (ContractResult::Err(_), Some(checkpoint)) => self.rollback(checkpoint),
+            _ => {}
         }
-        Ok(last_response)
+        let msg = to_binary(&sub_msg.msg).map_err(Error::format_error)?;
+        self.handle_submessage_result(
+            origin,
+            &msg,
+            response,
+            |_| None,
+            UNDEFINED,
+            sub_msg.id,
+            &sub_msg.reply_on,
+        )
     }
 
-    pub fn add_custom_code(&mut self, code_id: u64, code: &[u8]) -> Result<(), Error> {
-        self.custom_codes.insert(code_id, code.to_vec());
-        Ok(())
+    /// state changes made after this can be rolled back, until the transaction is over
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            states: self.states.read().unwrap().checkpoint(),
+            code_id_counters: self.code_id_counters.clone(),
+            events: self.debug_log.lock().unwrap().events.len(),
+        }
     }
 
-    pub fn create_instance_from_code(
+    /// discards all state changes made since checkpoint
+    fn rollback(&mut self, checkpoint: Checkpoint) {
+        // the states are rolled back in place, so that QueryHandles observe the rolled back states
+        self.states.write().unwrap().rollback(checkpoint.states);
+        self.code_id_counters = checkpoint.code_id_counters;
+        // like wasmd, the events of a failed submessage are not passed on
+        self.debug_log
+            .lock()
+            .unwrap()
+            .truncate_events(checkpoint.events);
+    }
+
+    /// records the events the chain emits for a contract call in its call frame.
+    /// They replace the events of response, so that replies and callers see them like on wasmd
+    fn emit_events(&self, mut response: Response, events: Vec<Event>) -> Response {
+        self.debug_log.lock().unwrap().append_events(&events);
+        response.events = events;
+        response
+    }
+
+    /// runs f within the gas limit of a submessage, given in SDK gas.
+    /// Running out of gas fails only the submessage, unless the caller itself runs out of gas.
+    /// Gas used by queries is not metered
+    fn with_gas_limit<T, F>(
         &mut self,
-        code: &[u8],
-        deps: RpcBackend,
-        options: InstanceOptions,
-    ) -> Result<RpcInstance, Error> {
-        use cosmwasm_vm::internals::compile;
-        let mut hasher = Sha256::new();
-        hasher.update(code);
-        let code_hash = hasher.finalize().to_vec();
-        let module = if let Some(module) = self.wasm_cache.get(&code_hash) {
-            module.clone()
-        } else {
-            let module = compile(code, None, &[]).map_err(Error::vm_error)?;
-            self.wasm_cache.insert(code_hash, module.clone());
-            module
+        gas_limit: Option<u64>,
+        f: F,
+    ) -> Result<Result<T, String>, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        let gas_limit = match gas_limit {
+            Some(l) => l,
+            None => return f(self).map(Ok),
+        };
+        let outer = self.gas_left;
+        let limit = gas_limit.saturating_mul(GAS_MULTIPLIER);
+        let budget = outer.map_or(limit, |o| o.min(limit));
+        self.gas_left = Some(budget);
+        let result = f(self);
+        let used = match &result {
+            Err(Error::OutOfGas(_)) => budget,
+            _ => budget - self.gas_left.unwrap(),
         };
-        match instance_from_module(&module, deps, options.gas_limit, options.print_debug, None) {
-            Err(e) => Err(Error::vm_error(e)),
-            Ok(i) => Ok(i),
+        self.gas_left = outer.map(|o| o - used);
+        match result {
+            Err(Error::OutOfGas(_)) if budget == limit => {
+                let e = format!(
+                    "out of gas: submessage exceeded its gas limit of {}",
+                    gas_limit
+                );
+                let mut debug_log = self.debug_log.lock().unwrap();
+                debug_log.set_err_msg(&e);
+                debug_log.begin_error(&e);
+                Ok(Err(e))
+            }
+            r => r.map(Ok),
         }
     }
 
-    pub fn instantiate(
+    /// runs call as a call of entry_point on contract_addr, timed if profiling is enabled
+    fn profiled<T, F>(
         &mut self,
-        code_id: u64,
-        msg: &[u8],
-        funds: &[Coin],
-    ) -> Result<DebugLog, Error> {
-        let sender = self.sender.clone();
-        let empty_log = DebugLog::new();
-        let state_copy = self.clone();
-
-        let (res, _) = self.instantiate_inner(code_id, &Addr::unchecked(sender), msg, funds)?;
-        if res.is_err() {
-            let orig_state = self.revert(state_copy);
-            let debug_log: DebugLog =
-                mem::replace(&mut orig_state.debug_log.lock().unwrap(), empty_log);
-            Ok(debug_log)
-        } else {
-            self.states.write().unwrap().update_block();
-            Ok(mem::replace(&mut self.debug_log.lock().unwrap(), empty_log))
+        entry_point: &str,
+        contract_addr: &Addr,
+        call: F,
+    ) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter(entry_point, contract_addr);
         }
+        let result = call(self);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit();
+        }
+        result
     }
 
-    fn instantiate_inner(
-        &mut self,
-        // this argument should be removed someday
-        code_id: u64,
+    fn hook_call_start(&self, kind: CallKind, contract_addr: &Addr, sender: &Addr, msg: &[u8]) {
+        if let Some(hooks) = self.hooks() {
+            hooks.on_call_start(kind, contract_addr, sender, msg);
+        }
+    }
+
+    fn hook_call_end(
+        &self,
+        kind: CallKind,
+        contract_addr: &Addr,
+        result: &ContractResult<Response>,
+    ) {
+        if let Some(hooks) = self.hooks() {
+            hooks.on_call_end(kind, contract_addr, result);
+        }
+    }
+
+    /// records the call in the debug log with the state it starts in, if frame recording is enabled.
+    /// Returns the index of the recorded frame
+    fn record_frame_start(
+        &self,
+        kind: CallKind,
+        contract_addr: &Addr,
         sender: &Addr,
         msg: &[u8],
         funds: &[Coin],
-    ) -> Result<(ContractResult<Response>, Option<Addr>), Error> {
-        // generate an address
-        let contract_addr = self.generate_address(code_id)?;
-
-        // transfer coins
-        if funds.len() > 0 {
-            let bank_msg = BankMsg::Send {
-                to_address: contract_addr.to_string(),
-                amount: funds.to_vec(),
-            };
-            match self
-                .states
-                .write()
-                .unwrap()
-                .bank_execute(sender, &bank_msg)?
-            {
-                ContractResult::Ok(r) => {
-                    self.debug_log.lock().unwrap().append_log(&r);
-                }
-                ContractResult::Err(e) => {
-                    self.debug_log.lock().unwrap().set_err_msg(&e);
-                    return Ok((ContractResult::Err(e), None));
-                }
-            };
+        code_id: Option<u64>,
+    ) -> Result<Option<usize>, Error> {
+        // native contracts have no storage or code to replay them with
+        if !self.record_frames || self.native_contract(contract_addr).is_some() {
+            return Ok(None);
         }
+        let storage_before = match kind {
+            CallKind::Instantiate => ContractStorage::new(),
+            _ => self.contract_storage(contract_addr)?,
+        };
+        let code_id = match code_id {
+            Some(code_id) => code_id,
+            None => self.contract_info(contract_addr)?.code_id,
+        };
+        let frame = FrameRecord {
+            kind,
+            contract: contract_addr.clone(),
+            sender: sender.clone(),
+            msg: Binary::from(msg),
+            funds: funds.to_vec(),
+            env: self.env(contract_addr)?,
+            code_id,
+            depth: self.call_stack.len().saturating_sub(1),
+            storage_before,
+            storage_after: None,
+            result: None,
+        };
+        Ok(Some(self.debug_log.lock().unwrap().begin_frame(frame)))
+    }
 
-        // because contract address does not exist on chain, create mock storage from empty set
-        let emtpy_storage = Arc::new(RwLock::new(ContractStorage::new()));
-        let deps = self.new_mock(&emtpy_storage)?;
-        let options = InstanceOptions {
-            gas_limit: u64::MAX,
-            print_debug: false,
+    fn record_frame_end(
+        &self,
+        frame: Option<usize>,
+        contract_addr: &Addr,
+        result: &ContractResult<Response>,
+    ) -> Result<(), Error> {
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return Ok(()),
         };
-        let wasm_code = if let Some(code) = self.custom_codes.get(&code_id) {
-            code.clone()
+        // failed instantiations do not create the contract
+        let exists = self
+            .states
+            .read()
+            .unwrap()
+            .contract_state_get(contract_addr)
+            .is_some();
+        let storage_after = if exists {
+            self.contract_storage(contract_addr)?
         } else {
-            maybe_unzip(
-                self.states
-                    .write()
-                    .unwrap()
-                    .client
-                    .query_wasm_contract_code(code_id)?,
-            )?
+            ContractStorage::new()
         };
-        let wasm_instance = self.create_instance_from_code(wasm_code.as_slice(), deps, options)?;
+        self.debug_log
+            .lock()
+            .unwrap()
+            .end_frame(frame, storage_after, result);
+        Ok(())
+    }
 
-        // create a temporary contract_state, which will be deleted if instantiation fails
-        let contract_state = ContractState {
-            code: wasm_code,
-            storage: emtpy_storage,
+    /// gas used by instance is recorded in the debug log,
+    /// and taken from the innermost gas limited submessage
+    fn charge_gas(&mut self, instance: &RpcContractInstance) {
+        self.record_call_metrics(instance);
+        let gas_used = instance.gas_used();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.add_gas(gas_used);
+        }
+        self.debug_log.lock().unwrap().append_gas_used(gas_used);
+        if let Some(gas_left) = self.gas_left.as_mut() {
+            *gas_left = gas_left.saturating_sub(gas_used);
+        }
+    }
+
+    /// calls reply on origin if requested by reply_on, or propagates the error of the submessage.
+    /// encode_data wraps the data returned by the submessage into the message response of the chain.
+    /// The returned response holds the events emitted by the submessage and the reply,
+    /// and the data returned by the reply, which overrides the data of origin if set
+    #[allow(clippy::too_many_arguments)]
+    fn handle_submessage_result<F>(
+        &mut self,
+        origin: &Addr,
+        msg: &Binary,
+        response: ContractResult<Response>,
+        encode_data: F,
+        error_code: AbciCode,
+        sub_msg_id: u64,
+        reply_on: &ReplyOn,
+    ) -> Result<ContractResult<Response>, Error>
+    where
+        F: FnOnce(Option<Binary>) -> Option<Binary>,
+    {
+        let do_reply = match reply_on {
+            ReplyOn::Always => true,
+            ReplyOn::Success => response.is_ok(),
+            ReplyOn::Error => response.is_err(),
+            ReplyOn::Never => false,
         };
-        self.states
-            .write()
-            .unwrap()
-            .contract_state_insert(contract_addr.clone(), contract_state);
-        let mut instance = RpcContractInstance::new(&contract_addr, wasm_instance);
-        let env = self.env(&contract_addr)?;
+        debug!(
+            id = sub_msg_id,
+            failed = response.is_err(),
+            reply = do_reply,
+            "submessage done"
+        );
+        if do_reply {
+            let events = match &response {
+                ContractResult::Ok(r) => r.events.clone(),
+                ContractResult::Err(_) => Vec::new(),
+            };
+            let reply = Reply {
+                id: sub_msg_id,
+                result: match response {
+                    ContractResult::Ok(r) => SubMsgResult::Ok(SubMsgResponse {
+                        events: r.events,
+                        data: encode_data(r.data),
+                    }),
+                    ContractResult::Err(e) if self.redact_reply_errors => {
+                        SubMsgResult::Err(redact_error(error_code, &e))
+                    }
+                    ContractResult::Err(e) => SubMsgResult::Err(e),
+                },
+            };
+
+            match self.reply_inner(origin, msg, &reply)? {
+                ContractResult::Ok(response) => {
+                    let mut result = Response::new().add_events(events);
+                    result.events.extend(response.events);
+                    result.data = response.data;
+                    Ok(ContractResult::Ok(result))
+                }
+                err => Ok(err),
+            }
+        }
+        // if reply is not called, but the current result is an error, propagate the error
+        else if let ContractResult::Err(e) = &response {
+            self.debug_log.lock().unwrap().begin_error(e);
+            Ok(ContractResult::Err(response.unwrap_err()))
+        }
+        // otherwise, the data of the submessage is dropped
+        else {
+            Ok(ContractResult::Ok(
+                Response::new().add_events(response.unwrap().events),
+            ))
+        }
+    }
+
+    /// calls reply on origin and executes the submessages of the reply.
+    /// msg labels the call in the call trace, it is the submessage the reply is for
+    fn reply_inner(
+        &mut self,
+        origin: &Addr,
+        msg: &Binary,
+        reply: &Reply,
+    ) -> Result<ContractResult<Response>, Error> {
+        self.profiled("reply", origin, |model| {
+            model.reply_frame(origin, msg, reply)
+        })
+    }
+
+    fn reply_frame(
+        &mut self,
+        origin: &Addr,
+        msg: &Binary,
+        reply: &Reply,
+    ) -> Result<ContractResult<Response>, Error> {
+        let env = self.env(origin)?;
+        let mut instance = self.create_instance(origin)?;
 
         // open new call context
-        let call_id = self
-            .debug_log
-            .lock()
-            .unwrap()
-            .begin_instantiate(&contract_addr, msg);
+        let call_id = self.debug_log.lock().unwrap().begin_reply(origin, msg);
 
-        // propagate contract error downwards
-        let result = instance.instantiate(&env, msg, sender, funds)?;
+        let reply_msg = to_binary(reply).map_err(Error::format_error)?;
+        self.hook_call_start(CallKind::Reply, origin, origin, reply_msg.as_slice());
+        let frame = self.record_frame_start(
+            CallKind::Reply,
+            origin,
+            origin,
+            reply_msg.as_slice(),
+            &[],
+            None,
+        )?;
+        let maybe_response = self.check_aborted(instance.reply(&env, reply))?;
         self.handle_coverage(&mut instance)?;
-        let response = match result {
-            ContractResult::Ok(r) => {
-                let instantiate_event = Event::new("instantiate")
-                    .add_attribute("code_id", code_id.to_string())
-                    .add_attribute("_contract_address", contract_addr.to_string());
-                let r = r.add_event(instantiate_event);
-                self.debug_log.lock().unwrap().append_log(&r);
-                r
-            }
-            ContractResult::Err(e) => {
-                // remove the temporary contract_state created previously
-                self.states
-                    .write()
-                    .unwrap()
-                    .contract_state_remove(&contract_addr);
-                let mut debug_log = self.debug_log.lock().unwrap();
-                debug_log.set_err_msg(&e);
-                debug_log.begin_error(&e);
-                return Ok((ContractResult::Err(e), None));
-            }
-        };
-        let response = self.handle_response(&contract_addr, &response)?;
+        self.charge_gas(&instance);
+        self.hook_call_end(CallKind::Reply, origin, &maybe_response);
 
-        // close calling context
-        self.debug_log.lock().unwrap().end_instantiate(call_id);
-        Ok((response, Some(contract_addr)))
+        if let ContractResult::Err(e) = &maybe_response {
+            // propagate error. instance.reply need not error handling
+            // no need to re-insert the instance
+            self.debug_log.lock().unwrap().begin_error(e);
+            self.record_frame_end(frame, origin, &maybe_response)?;
+            return Ok(maybe_response);
+        }
+        let response = maybe_response.unwrap();
+        let mut debug_log = self.debug_log.lock().unwrap();
+        // the error of the submessage was handled by the reply
+        debug_log.err_msg = None;
+        debug_log.append_log(&response);
+        drop(debug_log);
+        let mut events = vec![reply_event(origin)];
+        events.extend(contract_events(origin, &response));
+        let response = self.emit_events(response, events);
+        let result = self.handle_response(origin, &response)?;
+        if result.is_ok() {
+            // close call context
+            self.debug_log.lock().unwrap().end_reply(call_id);
+        }
+        self.record_frame_end(frame, origin, &result)?;
+        Ok(result)
     }
 
-    pub fn execute(
+    /// calls the reply entry point of contract_addr with a handcrafted reply, as if a submessage
+    /// with the id of reply had finished, e.g. to exercise replies to errors hard to produce.
+    /// Submessages of the reply are executed, and state is reverted if the reply fails
+    pub fn call_reply(&mut self, contract_addr: &Addr, reply: &Reply) -> Result<DebugLog, Error> {
+        let msg = to_binary(reply).map_err(Error::format_error)?;
+        self.run_top(contract_addr, |model| {
+            model.reply_inner(contract_addr, &msg, reply)
+        })
+    }
+
+    /// calls the sudo entry point of contract_addr, as a chain module like governance would.
+    /// Submessages of the call are executed, and state is reverted if it fails
+    pub fn sudo(&mut self, contract_addr: &Addr, msg: &[u8]) -> Result<DebugLog, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        self.run_top(contract_addr, |model| model.sudo_inner(contract_addr, msg))
+    }
+
+    fn sudo_inner(
         &mut self,
         contract_addr: &Addr,
         msg: &[u8],
-        funds: &[Coin],
-    ) -> Result<DebugLog, Error> {
-        let empty_log = DebugLog::new();
-        let sender = self.sender.clone();
-        let state_copy = self.clone();
-        if self
-            .execute_inner(contract_addr, &Addr::unchecked(sender), msg, funds)?
-            .is_err()
-        {
-            let orig_state = self.revert(state_copy);
-            let debug_log: DebugLog =
-                mem::replace(&mut orig_state.debug_log.lock().unwrap(), empty_log);
-            Ok(debug_log)
-        } else {
-            self.states.write().unwrap().update_block();
-            Ok(mem::replace(&mut self.debug_log.lock().unwrap(), empty_log))
+    ) -> Result<ContractResult<Response>, Error> {
+        let _span = debug_span!("sudo", contract = %contract_addr).entered();
+        self.check_call_depth()?;
+        self.call_stack.push(contract_addr.clone());
+        // sudo calls have no sender, hooks see the contract as its own sender like for replies
+        self.hook_call_start(CallKind::Sudo, contract_addr, contract_addr, msg);
+        let frame =
+            self.record_frame_start(CallKind::Sudo, contract_addr, contract_addr, msg, &[], None)?;
+        let result = self.profiled("sudo", contract_addr, |model| {
+            model.sudo_frame(contract_addr, msg)
+        });
+        if let Ok(result) = &result {
+            self.hook_call_end(CallKind::Sudo, contract_addr, result);
+            self.record_frame_end(frame, contract_addr, result)?;
         }
+        self.call_stack.pop();
+        result
     }
 
-    fn execute_inner(
+    fn sudo_frame(
         &mut self,
         contract_addr: &Addr,
-        sender: &Addr,
         msg: &[u8],
-        funds: &[Coin],
     ) -> Result<ContractResult<Response>, Error> {
         let env = self.env(contract_addr)?;
         let mut instance = self.create_instance(contract_addr)?;
 
-        if funds.len() > 0 {
-            // transfer coins
-            let bank_msg = BankMsg::Send {
-                to_address: contract_addr.to_string(),
-                amount: funds.to_vec(),
-            };
-            match self
-                .states
-                .write()
-                .unwrap()
-                .bank_execute(sender, &bank_msg)?
-            {
-                ContractResult::Ok(r) => {
-                    self.debug_log.lock().unwrap().append_log(&r);
-                }
-                ContractResult::Err(e) => {
-                    self.debug_log.lock().unwrap().set_err_msg(&e);
-                    return Ok(ContractResult::Err(e));
-                }
-            };
-        }
-
         // open new call context
         let call_id = self
             .debug_log
             .lock()
             .unwrap()
-            .begin_execute(contract_addr, msg);
+            .begin_sudo(contract_addr, msg);
 
-        // execute contract code
-        // propagate contract error downwards
-        let result = instance.execute(&env, msg, sender, funds)?;
+        let result = self.check_aborted(instance.sudo(&env, msg))?;
         self.handle_coverage(&mut instance)?;
+        self.charge_gas(&instance);
         let response = match result {
             ContractResult::Ok(r) => {
                 self.debug_log.lock().unwrap().append_log(&r);
-                r
+                let mut events = vec![sudo_event(contract_addr)];
+                events.extend(contract_events(contract_addr, &r));
+                self.emit_events(r, events)
             }
             ContractResult::Err(e) => {
                 let mut debug_log = self.debug_log.lock().unwrap();
@@ -638,281 +1231,5003 @@ impl Model {
         let response = self.handle_response(contract_addr, &response)?;
 
         // close calling context
-        self.debug_log.lock().unwrap().end_execute(call_id);
+        self.debug_log.lock().unwrap().end_sudo(call_id);
         Ok(response)
     }
 
-    /// for now, only support WASM queries
-    pub fn wasm_query(&mut self, contract_addr: &Addr, msg: &[u8]) -> Result<Binary, Error> {
-        let env = self.env(contract_addr)?;
-        let mut instance = self.create_instance(contract_addr)?;
-        let wasm_query = WasmQuery::Smart {
-            contract_addr: contract_addr.to_string(),
-            msg: Binary::from(msg),
-        };
-        // TODO: fix this, propagate contract error down
-        let result = instance.query(&env, &wasm_query);
-        self.handle_coverage(&mut instance)?;
-        Ok(result?)
-    }
-
-    pub fn bank_query(&mut self, bank_query_: &[u8]) -> Result<Binary, Error> {
-        let bank_query: BankQuery =
-            from_binary(&Binary::from(bank_query_)).map_err(Error::format_error)?;
-        self.states.write().unwrap().bank_query(&bank_query)
-    }
-
-    fn new_mock(
-        &self,
-        contract_storage: &Arc<RwLock<ContractStorage>>,
-    ) -> Result<RpcBackend, Error> {
-        let states = self.states.read().unwrap();
-        let canonical_address_length = states.canonical_address_length;
-        let bech32_prefix = states.bech32_prefix.to_string();
-        Ok(Backend {
-            storage: self.mock_storage(contract_storage)?,
-            // is this correct?
-            api: RpcMockApi::new(canonical_address_length, bech32_prefix.as_str())?,
-            querier: RpcMockQuerier::new(&self.states, &self.debug_log),
-        })
-    }
-
-    fn env(&self, contract_addr: &Addr) -> Result<Env, Error> {
-        let states = self.states.read().unwrap();
-        let block_number = states.block_number;
-        let block_timestamp = states.block_timestamp;
-        let chain_id = states.chain_id.to_string();
-        Ok(Env {
-            block: cosmwasm_std::BlockInfo {
-                height: block_number,
-                time: block_timestamp,
-                chain_id,
-            },
-            // assumption: all blocks have only 1 transaction
-            transaction: Some(cosmwasm_std::TransactionInfo { index: 0 }),
-            // I don't really know what this is for, so for now, set it to the target contract address
-            contract: ContractInfo {
-                address: contract_addr.clone(),
-            },
-        })
+    /// executes the submessages of response, sent by origin.
+    /// The result holds the events of response and all submessages,
+    /// and the data of response unless it was overridden by a reply
+    fn handle_response(
+        &mut self,
+        origin: &Addr,
+        response: &Response,
+    ) -> Result<ContractResult<Response>, Error> {
+        let mut result = Response::new().add_events(response.events.clone());
+        result.data = response.data.clone();
+        for sub_msg in response.messages.iter() {
+            let response = match self.middlewares.run(origin, &sub_msg.msg)? {
+                // stubbed messages may be replied to like any other message
+                MsgAction::Respond(response) => {
+                    if let ContractResult::Ok(r) = &response {
+                        self.debug_log.lock().unwrap().append_events(&r.events);
+                    }
+                    let msg = to_binary(&sub_msg.msg).map_err(Error::format_error)?;
+                    self.handle_submessage_result(
+                        origin,
+                        &msg,
+                        response,
+                        |data| data,
+                        msg_error_code(&sub_msg.msg),
+                        sub_msg.id,
+                        &sub_msg.reply_on,
+                    )?
+                }
+                MsgAction::Replace(msg) => {
+                    let sub_msg = SubMsg {
+                        msg,
+                        ..sub_msg.clone()
+                    };
+                    self.dispatch_submessage(origin, &sub_msg)?
+                }
+                MsgAction::Continue => self.dispatch_submessage(origin, sub_msg)?,
+            };
+            match response {
+                ContractResult::Ok(r) => {
+                    result.events.extend(r.events);
+                    if r.data.is_some() {
+                        result.data = r.data;
+                    }
+                }
+                err => return Ok(err),
+            }
+        }
+        Ok(ContractResult::Ok(result))
     }
 
-    fn mock_storage(
-        &self,
-        contract_storage: &Arc<RwLock<ContractStorage>>,
-    ) -> Result<RpcMockStorage, Error> {
-        let storage = RpcMockStorage::new(contract_storage);
-        Ok(storage)
+    /// executes a single submessage sent by origin
+    fn dispatch_submessage(
+        &mut self,
+        origin: &Addr,
+        sub_msg: &SubMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        let _span = debug_span!(
+            "submessage",
+            origin = %origin,
+            id = sub_msg.id,
+            reply_on = ?sub_msg.reply_on
+        )
+        .entered();
+        Ok(match &sub_msg.msg {
+            CosmosMsg::Wasm(wasm_msg) => match wasm_msg {
+                WasmMsg::Instantiate {
+                    admin,
+                    code_id,
+                    msg,
+                    funds,
+                    label,
+                } => self.handle_submessage_instantiate(
+                    origin,
+                    admin,
+                    *code_id,
+                    msg,
+                    funds,
+                    label,
+                    sub_msg.gas_limit,
+                    sub_msg.id,
+                    &sub_msg.reply_on,
+                )?,
+                WasmMsg::Execute {
+                    contract_addr: target_addr,
+                    msg,
+                    funds,
+                } => self.handle_submessage_execute(
+                    origin,
+                    &Addr::unchecked(target_addr),
+                    msg,
+                    funds,
+                    sub_msg.gas_limit,
+                    sub_msg.id,
+                    &sub_msg.reply_on,
+                )?,
+                WasmMsg::Migrate {
+                    contract_addr: target_addr,
+                    new_code_id,
+                    msg,
+                } => self.handle_submessage_migrate(
+                    origin,
+                    &Addr::unchecked(target_addr),
+                    *new_code_id,
+                    msg,
+                    sub_msg.gas_limit,
+                    sub_msg.id,
+                    &sub_msg.reply_on,
+                )?,
+                WasmMsg::UpdateAdmin {
+                    contract_addr: target_addr,
+                    admin,
+                } => {
+                    let target_addr = Addr::unchecked(target_addr);
+                    let response =
+                        self.update_admin_inner(&target_addr, origin, Some(admin.clone()))?;
+                    let msg = to_binary(wasm_msg).map_err(Error::format_error)?;
+                    self.handle_submessage_result(
+                        origin,
+                        &msg,
+                        response,
+                        |_| None,
+                        UNAUTHORIZED,
+                        sub_msg.id,
+                        &sub_msg.reply_on,
+                    )?
+                }
+                WasmMsg::ClearAdmin {
+                    contract_addr: target_addr,
+                } => {
+                    let target_addr = Addr::unchecked(target_addr);
+                    let response = self.update_admin_inner(&target_addr, origin, None)?;
+                    let msg = to_binary(wasm_msg).map_err(Error::format_error)?;
+                    self.handle_submessage_result(
+                        origin,
+                        &msg,
+                        response,
+                        |_| None,
+                        UNAUTHORIZED,
+                        sub_msg.id,
+                        &sub_msg.reply_on,
+                    )?
+                }
+                _ => unimplemented!(),
+            },
+            CosmosMsg::Bank(bank_msg) => {
+                self.handle_submessage_module(origin, sub_msg, |states| {
+                    states.bank_execute(origin, bank_msg)
+                })?
+            }
+            CosmosMsg::Distribution(distribution_msg) => {
+                self.handle_submessage_module(origin, sub_msg, |states| {
+                    states.distribution_execute(origin, distribution_msg)
+                })?
+            }
+            CosmosMsg::Gov(gov_msg) => {
+                self.handle_submessage_module(origin, sub_msg, |states| {
+                    states.gov_execute(origin, gov_msg)
+                })?
+            }
+            CosmosMsg::Stargate { type_url, value } => {
+                // unlike bank messages, stargate messages may be replied to,
+                // e.g. to learn the denom created by MsgCreateDenom
+                let checkpoint = self.reply_checkpoint(&sub_msg.reply_on);
+                let response = self
+                    .states
+                    .write()
+                    .unwrap()
+                    .stargate_execute(origin, type_url, value)?;
+                match (&response, checkpoint) {
+                    (ContractResult::Ok(r), _) => {
+                        self.debug_log.lock().unwrap().append_events(&r.events)
+                    }
+                    (ContractResult::Err(_), Some(checkpoint)) => self.rollback(checkpoint),
+                    _ => {}
+                }
+                let msg = to_binary(&sub_msg.msg).map_err(Error::format_error)?;
+                self.handle_submessage_result(
+                    origin,
+                    &msg,
+                    response,
+                    |data| data,
+                    UNDEFINED,
+                    sub_msg.id,
+                    &sub_msg.reply_on,
+                )?
+            }
+            CosmosMsg::Ibc(ibc_msg) => {
+                // the packets sent are relayed once the whole transaction succeeded
+                let checkpoint = self.reply_checkpoint(&sub_msg.reply_on);
+                let response = self.states.write().unwrap().ibc_execute(origin, ibc_msg)?;
+                match (&response, checkpoint) {
+                    (ContractResult::Ok(r), _) => {
+                        self.debug_log.lock().unwrap().append_events(&r.events)
+                    }
+                    (ContractResult::Err(_), Some(checkpoint)) => self.rollback(checkpoint),
+                    _ => {}
+                }
+                let msg = to_binary(&sub_msg.msg).map_err(Error::format_error)?;
+                self.handle_submessage_result(
+                    origin,
+                    &msg,
+                    response,
+                    |_| None,
+                    UNDEFINED,
+                    sub_msg.id,
+                    &sub_msg.reply_on,
+                )?
+            }
+            _ => unimplemented!(),
+        })
     }
 
-    /// modify block number
-    pub fn cheat_block_number(&mut self, new_number: u64) -> Result<(), Error> {
-        self.states.write().unwrap().block_number = new_number;
+    pub fn add_custom_code(&mut self, code_id: u64, code: &[u8]) -> Result<(), Error> {
+        let unzipped = maybe_unzip(code.to_vec())?;
+        check_capabilities(&unzipped, &self.capabilities())?;
+        self.custom_codes.insert(code_id, code.to_vec());
+        // answers WasmQuery::CodeInfo of contracts, the creator is set by store_code
+        self.states.write().unwrap().insert_custom_code(CodeInfo {
+            code_id,
+            creator: String::new(),
+            checksum: code_checksum(&unzipped),
+        });
         Ok(())
     }
 
-    /// modify block timestamp
-    pub fn cheat_block_timestamp(&mut self, new_timestamp: Timestamp) -> Result<(), Error> {
-        self.states.write().unwrap().block_timestamp = new_timestamp;
-        Ok(())
+    /// uploads code as if the current sender had sent MsgStoreCode, returning the code id allocated
+    /// to it. Code ids are allocated from STORED_CODE_ID_START on, so that stored code never
+    /// shadows code of the chain
+    pub fn store_code(&mut self, code: &[u8]) -> Result<u64, Error> {
+        let code_id = self
+            .custom_codes
+            .keys()
+            .filter(|code_id| **code_id >= STORED_CODE_ID_START)
+            .max()
+            .map_or(STORED_CODE_ID_START, |code_id| code_id + 1);
+        if self.states.read().unwrap().code_id_in_use(code_id) {
+            return Err(Error::invalid_argument(format!(
+                "code id {} is used by a contract on chain",
+                code_id
+            )));
+        }
+        let checksum = code_checksum(&maybe_unzip(code.to_vec())?);
+        self.add_custom_code(code_id, code)?;
+        self.states.write().unwrap().insert_stored_code(CodeInfo {
+            code_id,
+            creator: self.sender.clone(),
+            checksum,
+        });
+        Ok(code_id)
     }
 
-    /// modify bank balance
-    pub fn cheat_bank_balance(
+    /// creator and checksum of code_id, as answered to WasmQuery::CodeInfo. Code added with
+    /// add_custom_code has no creator, code unknown to the simulation is queried from chain
+    pub fn code_info(&self, code_id: u64) -> Result<CodeInfo, Error> {
+        self.states.write().unwrap().code_info(code_id)
+    }
+
+    /// sha256 checksum of the code contract_addr runs, including code set by cheat_code
+    pub fn contract_checksum(&self, contract_addr: &Addr) -> Result<Vec<u8>, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        let state = states.contract_state_get(contract_addr).unwrap();
+        Ok(code_checksum(&maybe_unzip(state.code.to_vec())?))
+    }
+
+    /// sha256 checksum of the code registered as code_id with add_custom_code
+    pub fn custom_code_checksum(&self, code_id: u64) -> Option<Vec<u8>> {
+        self.custom_codes
+            .get(&code_id)
+            .map(|code| code_checksum(code))
+    }
+
+    /// the storage, info and balances of contract_addr as a fixture for import_contract,
+    /// downloading the whole storage if it is lazy
+    pub fn export_contract(&self, contract_addr: &Addr) -> Result<ContractFixture, Error> {
+        let checksum = self.contract_checksum(contract_addr)?;
+        let mut states = self.states.write().unwrap();
+        let state = states.contract_state_get(contract_addr).unwrap();
+        state.fetch_all()?;
+        let storage = state
+            .storage
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| StorageEntry {
+                key: Binary(key.clone()),
+                value: Binary(value.clone()),
+            })
+            .collect();
+        let info = state.info.clone();
+        Ok(ContractFixture {
+            checksum: hex::encode(checksum),
+            code_id: info.code_id,
+            creator: info.creator,
+            admin: info.admin,
+            label: info.label,
+            storage,
+            balances: states.get_balances(contract_addr)?,
+        })
+    }
+
+    /// loads contract_addr from fixture instead of from chain, replacing the contract if it is
+    /// loaded already. The code of the fixture must be known to the model, as custom code or as
+    /// the code of a loaded contract, since fixtures only hold its checksum
+    pub fn import_contract(
         &mut self,
-        address: &Addr,
-        denom: &str,
-        new_balance: u128,
+        fixture: &ContractFixture,
+        contract_addr: &Addr,
     ) -> Result<(), Error> {
-        self.states
-            .write()
-            .unwrap()
-            .set_balance(address, denom, Uint128::new(new_balance))?;
+        let checksum = hex::decode(&fixture.checksum).map_err(Error::format_error)?;
+        let code = self.code_by_checksum(&checksum)?.ok_or_else(|| {
+            Error::invalid_argument(format!(
+                "code {} of the fixture is unknown, add it with add_custom_code first",
+                fixture.checksum
+            ))
+        })?;
+        self.coverage_info.forget_layout(contract_addr);
+        let code = self.maybe_instrument(contract_addr, code)?;
+        let storage: ContractStorage = fixture
+            .storage
+            .iter()
+            .map(|entry| (entry.key.to_vec(), entry.value.to_vec()))
+            .collect();
+        let balances = fixture
+            .balances
+            .iter()
+            .map(|coin| (coin.denom.clone(), coin.amount))
+            .collect();
+        let contract_state = ContractState {
+            code: Arc::new(code),
+            storage: shared_storage(storage),
+            info: ContractInfo {
+                code_id: fixture.code_id,
+                creator: fixture.creator.clone(),
+                admin: fixture.admin.clone(),
+                label: fixture.label.clone(),
+            },
+            lazy: None,
+        };
+        let mut states = self.states.write().unwrap();
+        states.contract_state_insert(contract_addr.clone(), contract_state);
+        states.insert_bank_state(contract_addr.clone(), balances);
         Ok(())
     }
 
-    /// modify code
-    pub fn cheat_code(&mut self, contract_addr: &Addr, new_code: &[u8]) -> Result<(), Error> {
-        self.fetch_contract_state(contract_addr)?;
+    // the unzipped code with checksum among custom codes and the code of loaded contracts
+    fn code_by_checksum(&self, checksum: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        for code in self.custom_codes.values() {
+            let code = maybe_unzip(code.clone())?;
+            if code_checksum(&code) == checksum {
+                return Ok(Some(code));
+            }
+        }
+        let states = self.states.read().unwrap();
+        Ok(states.loaded_code(checksum).map(|code| code.to_vec()))
+    }
 
-        let old_contract_state = self
+    /// registers the wasm file at path, e.g. ./artifacts/contract.wasm, as code_id
+    pub fn add_code_from_path(&mut self, code_id: u64, path: &Path) -> Result<(), Error> {
+        let code = read_wasm(path)?;
+        self.add_custom_code(code_id, &code)
+    }
+
+    /// compiles the contract crate at project_dir with cargo and registers it as code_id
+    /// returns the path of the compiled wasm file
+    pub fn add_code_from_project(
+        &mut self,
+        code_id: u64,
+        project_dir: &Path,
+        config: &WasmBuild,
+    ) -> Result<PathBuf, Error> {
+        let wasm_path = build_wasm(project_dir, config)?;
+        self.add_code_from_path(code_id, &wasm_path)?;
+        Ok(wasm_path)
+    }
+
+    /// checks the top-level messages sent to contracts of code_id against schema before running them
+    /// messages sent by contracts are not checked
+    pub fn register_schema(&mut self, code_id: u64, schema: ContractSchema) {
+        self.schemas.insert(code_id, Arc::new(schema));
+    }
+
+    pub fn remove_schema(&mut self, code_id: u64) {
+        self.schemas.remove(&code_id);
+    }
+
+    fn validate_msg(&self, kind: MsgKind, code_id: u64, msg: &[u8]) -> Result<(), Error> {
+        match self.schemas.get(&code_id) {
+            Some(schema) => schema.validate(kind, msg),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_contract_msg(
+        &self,
+        kind: MsgKind,
+        contract_addr: &Addr,
+        msg: &[u8],
+    ) -> Result<(), Error> {
+        // native contracts have no code id to look up the schema by
+        if self.schemas.is_empty() || self.native_contract(contract_addr).is_some() {
+            return Ok(());
+        }
+        let code_id = self.contract_info(contract_addr)?.code_id;
+        self.validate_msg(kind, code_id, msg)
+    }
+
+    /// set the maximum number of compiled modules kept in the module cache
+    /// the cache is shared with all clones of this model
+    pub fn set_module_cache_size(&mut self, size: usize) {
+        self.wasm_cache.set_capacity(size);
+    }
+
+    /// memory held by the loaded contracts, compiled modules and idle instances, to keep an eye on long
+    /// runs such as fuzzing campaigns. Clones of a model share code, and storages until they are written to
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            compiled_modules: self.wasm_cache.len(),
+            idle_instances: self.instance_pool.len(),
+            ..self.states.read().unwrap().memory_stats()
+        }
+    }
+
+    /// canonical sha256 hash of the block, the loaded contracts and the loaded balances, e.g. to
+    /// check that two ways of reaching a state end in the same one. Contracts and accounts loaded
+    /// without being changed count as well, so compare models which loaded the same ones.
+    /// The whole storage of lazily loaded contracts is downloaded first, which takes a while
+    /// for contracts with large storages
+    pub fn state_hash(&self) -> Result<Vec<u8>, Error> {
+        self.states.read().unwrap().state_hash()
+    }
+
+    /// code size, compiled module size, memory pages used in the last call and cumulative gas of
+    /// contract_addr. Calls made by the model and its top-level queries are counted,
+    /// queries of contracts by other contracts are not
+    pub fn contract_metrics(&self, contract_addr: &Addr) -> Result<ContractMetrics, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let code = self
             .states
             .read()
             .unwrap()
             .contract_state_get(contract_addr)
             .unwrap()
+            .code
             .clone();
-        let mut new_contract_state = old_contract_state.clone();
-        new_contract_state.code = new_code.to_vec();
-        self.states
-            .write()
+        let module = self.wasm_cache.get_or_compile(&code)?;
+        let module_size = module.serialize().map_err(Error::vm_error)?.len();
+        Ok(ContractMetrics {
+            code_size: code.len(),
+            module_size,
+            ..self
+                .call_metrics
+                .get(contract_addr)
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    /// the exports, embedded schema and candidate message variants of the code of a contract
+    pub fn analyze_contract(&self, contract_addr: &Addr) -> Result<WasmAnalysis, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let code = self
+            .states
+            .read()
             .unwrap()
-            .contract_state_insert(contract_addr.clone(), new_contract_state);
-        // try creating an instance to check if provided wasm is valid
-        self.create_instance(contract_addr).map_err(|e| {
-            self.states
-                .write()
-                .unwrap()
-                .contract_state_insert(contract_addr.clone(), old_contract_state);
-            e
+            .contract_state_get(contract_addr)
+            .unwrap()
+            .code
+            .clone();
+        WasmAnalysis::from_code(&code)
+    }
+
+    /// forgets the memory and gas used by the calls so far, e.g. to measure a single scenario
+    pub fn clear_contract_metrics(&mut self) {
+        self.call_metrics.clear();
+    }
+
+    /// time every call of contracts from now on, until disable_profiling. Calls are timed
+    /// by the wall clock, the time of queries of other contracts is counted in the time of the caller
+    pub fn enable_profiling(&mut self) {
+        if self.profiler.is_none() {
+            self.profiler = Some(Profiler::default());
+        }
+    }
+
+    /// stops timing calls and forgets the calls timed so far
+    pub fn disable_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    /// time and gas of every call since profiling was enabled or the profile cleared, per frame
+    /// and per contract with the contracts sorted by sort, the hottest first
+    pub fn profile_report(&self, sort: ProfileSort) -> ProfileReport {
+        self.profiler
+            .as_ref()
+            .map(|profiler| profiler.report(sort))
+            .unwrap_or_default()
+    }
+
+    pub fn clear_profile(&mut self) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.clear();
+        }
+    }
+
+    fn record_call_metrics(&mut self, instance: &RpcContractInstance) {
+        let metrics = self.call_metrics.entry(instance.address()).or_default();
+        metrics.memory_pages = instance.instance.memory_pages();
+        metrics.gas_used += instance.gas_used();
+        metrics.calls += 1;
+    }
+
+    /// drops the idle instances kept for queries, the pool is shared with all clones of this model.
+    /// Instances are created again as contracts are queried
+    pub fn clear_instance_pool(&mut self) {
+        self.instance_pool.clear();
+    }
+
+    /// configuration of the on-disk cache of rpc responses, None if the client does not cache them
+    pub fn cache_config(&self) -> Option<CacheConfig> {
+        self.states.read().unwrap().client.cache_config()
+    }
+
+    /// reopens the cache of rpc responses with config, keeping the responses cached so far
+    pub fn set_cache_config(&mut self, config: CacheConfig) -> Result<(), Error> {
+        self.states.write().unwrap().client.set_cache_config(config)
+    }
+
+    fn update_cache_config<F: FnOnce(&mut CacheConfig)>(&mut self, f: F) -> Result<(), Error> {
+        let mut config = self
+            .cache_config()
+            .ok_or_else(|| Error::backend_error("responses of this client are not cached"))?;
+        f(&mut config);
+        self.set_cache_config(config)
+    }
+
+    /// directory of the cache of rpc responses, ~/.cw-rpc-cache by default
+    pub fn set_cache_dir(&mut self, dir: PathBuf) -> Result<(), Error> {
+        self.update_cache_config(|c| c.dir = dir)
+    }
+
+    /// use cached rpc responses without ever writing new ones to disk
+    pub fn set_cache_read_only(&mut self, read_only: bool) -> Result<(), Error> {
+        self.update_cache_config(|c| c.read_only = read_only)
+    }
+
+    /// share contract code with the caches of all heights of the chain, enabled by default
+    pub fn set_cache_share_code(&mut self, enabled: bool) -> Result<(), Error> {
+        self.update_cache_config(|c| c.share_code = enabled)
+    }
+
+    /// writes every rpc response cached so far to path, e.g. to pre-warm the cache of CI machines
+    pub fn export_cache(&self, path: &Path) -> Result<(), Error> {
+        self.states.read().unwrap().client.export_cache(path)
+    }
+
+    /// adds responses exported by a model forked from the same chain and height,
+    /// returning how many there were
+    pub fn import_cache(&mut self, path: &Path) -> Result<usize, Error> {
+        self.states.write().unwrap().client.import_cache(path)
+    }
+
+    /// set the directory where compiled modules are persisted, None disables the disk cache
+    /// defaults to ~/.cw-sim-module-cache/wasmer-<version>, or no disk cache if HOME is not set
+    pub fn set_module_cache_dir(&mut self, dir: Option<PathBuf>) {
+        self.wasm_cache.set_disk_cache_dir(dir);
+    }
+
+    pub fn create_instance_from_code(
+        &self,
+        code: &[u8],
+        deps: RpcBackend,
+        options: InstanceOptions,
+    ) -> Result<RpcInstance, Error> {
+        let module = self.wasm_cache.get_or_compile(code)?;
+        // modules whose initial memory exceeds the limit of the module cache can't be instantiated
+        if let Some(limit) = self.wasm_cache.memory_limit() {
+            let initial = module
+                .exports()
+                .memories()
+                .map(|memory| memory.ty().minimum.bytes().0)
+                .max()
+                .unwrap_or(0);
+            if initial > limit {
+                return Err(Error::limit_exceeded(format!(
+                    "memory limit of the module cache: the initial memory of {} bytes exceeds the limit of {} bytes",
+                    initial, limit
+                )));
+            }
+        }
+        let instance =
+            instance_from_module(&module, deps, options.gas_limit, options.print_debug, None)
+                .map_err(Error::from)?;
+        // contracts of unsupported cosmwasm versions fail with the reason instead of missing exports
+        CosmwasmVersion::from_exports(&instance.exported_function_names(None))?;
+        Ok(instance)
+    }
+
+    /// cosmwasm version contract_addr was compiled against
+    pub fn cosmwasm_version(&self, contract_addr: &Addr) -> Result<CosmwasmVersion, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        let contract_state = states.contract_state_get(contract_addr).unwrap();
+        CosmwasmVersion::of_code(&contract_state.code)
+    }
+
+    pub fn instantiate(
+        &mut self,
+        code_id: u64,
+        msg: &[u8],
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        self.instantiate_with_admin(code_id, msg, funds, None, "")
+    }
+
+    /// instantiate a contract which can be migrated by admin
+    pub fn instantiate_with_admin(
+        &mut self,
+        code_id: u64,
+        msg: &[u8],
+        funds: &[Coin],
+        admin: Option<&Addr>,
+        label: &str,
+    ) -> Result<DebugLog, Error> {
+        self.validate_msg(MsgKind::Instantiate, code_id, msg)?;
+        let funds = &self.checked_funds(funds)?;
+        let sender = Addr::unchecked(&self.sender);
+        let admin = admin.map(|a| a.to_string());
+        let mut contract_address = None;
+        let mut debug_log = self.run_top(&sender, |model| {
+            let (res, addr) =
+                model.instantiate_inner(code_id, &sender, msg, funds, admin, label)?;
+            if res.is_ok() {
+                contract_address = addr;
+            }
+            Ok(res)
         })?;
-        Ok(())
+        debug_log.contract_address = contract_address;
+        Ok(debug_log)
+    }
+
+    /// runs a top-level message of sender, reverting all changes if it fails.
+    /// The block is fixed for the whole transaction, transactions started within it share its block
+    fn run_top<F>(&mut self, sender: &Addr, f: F) -> Result<DebugLog, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<ContractResult<Response>, Error>,
+    {
+        let outer = self.tx_block.is_none();
+        if outer {
+            self.tx_block = Some(self.block_info());
+            let mut states = self.states.write().unwrap();
+            states.tx_hash = Some(states.synthetic_tx_hash(self.tx_sequence));
+            self.tx_sequence += 1;
+        }
+        let block = self.tx_block.clone();
+        let (tx_index, tx_hash) = {
+            let states = self.states.read().unwrap();
+            (states.tx_index, states.tx_hash.clone())
+        };
+        let result = self.run_top_in_block(sender, f);
+        if outer {
+            self.tx_block = None;
+            let mut states = self.states.write().unwrap();
+            states.tx_hash = None;
+            // failed transactions take their place in the block as well
+            if self.open_block.is_some() {
+                states.tx_index += 1;
+            }
+        }
+        let mut debug_log = result?;
+        debug_log.block = block;
+        debug_log.transaction = Some(TransactionInfo { index: tx_index });
+        debug_log.tx_hash = tx_hash;
+        Ok(debug_log)
+    }
+
+    fn run_top_in_block<F>(&mut self, sender: &Addr, f: F) -> Result<DebugLog, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<ContractResult<Response>, Error>,
+    {
+        self.reload_watched_or_warn();
+        let _span = info_span!("transaction", sender = %sender).entered();
+        let empty_log = DebugLog::new();
+        let checkpoint = self.checkpoint();
+        self.record_sender(sender);
+        self.coverage_info.begin_run();
+        if self.capture_backtraces {
+            // drop the trace of a trap of an earlier run
+            take_backtrace();
+        }
+        let result = match f(self) {
+            Ok(result) => result,
+            Err(e) => {
+                debug!(error = %e, "transaction aborted");
+                // changes made before the error are kept
+                self.states.write().unwrap().commit(checkpoint.states);
+                return Err(self.attach_backtrace(e));
+            }
+        };
+        match result {
+            ContractResult::Err(_) => {
+                let mut debug_log: DebugLog =
+                    mem::replace(&mut self.debug_log.lock().unwrap(), empty_log);
+                // failed transactions emit no events
+                debug_log.events.clear();
+                debug!(
+                    error = debug_log.err_msg.as_deref().unwrap_or_default(),
+                    "transaction reverted"
+                );
+                self.name_calls(&mut debug_log);
+                // unlike restoring a snapshot, this keeps coverage collected by the transaction
+                self.rollback(checkpoint);
+                Ok(debug_log)
+            }
+            ContractResult::Ok(response) => {
+                self.record_storage_diff(&checkpoint);
+                self.states.write().unwrap().commit(checkpoint.states);
+                if self.open_block.is_none() {
+                    self.states.write().unwrap().update_block();
+                }
+                let mut debug_log: DebugLog =
+                    mem::replace(&mut self.debug_log.lock().unwrap(), empty_log);
+                debug_log.data = response.data;
+                self.name_calls(&mut debug_log);
+                Ok(debug_log)
+            }
+        }
+    }
+
+    fn instantiate_inner(
+        &mut self,
+        // this argument should be removed someday
+        code_id: u64,
+        sender: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+        admin: Option<String>,
+        label: &str,
+    ) -> Result<(ContractResult<Response>, Option<Addr>), Error> {
+        let _span = debug_span!("instantiate", code_id, sender = %sender).entered();
+        // generate an address
+        let contract_addr = self.generate_address(code_id)?;
+        let info = ContractInfo {
+            code_id,
+            creator: sender.to_string(),
+            admin,
+            label: label.to_string(),
+        };
+        self.check_call_depth()?;
+        self.call_stack.push(contract_addr.clone());
+        self.hook_call_start(CallKind::Instantiate, &contract_addr, sender, msg);
+        let frame = self.record_frame_start(
+            CallKind::Instantiate,
+            &contract_addr,
+            sender,
+            msg,
+            funds,
+            Some(code_id),
+        )?;
+        let result = self.profiled("instantiate", &contract_addr, |model| {
+            model.instantiate_frame(info, &contract_addr, sender, msg, funds)
+        });
+        if let Ok((result, _)) = &result {
+            self.hook_call_end(CallKind::Instantiate, &contract_addr, result);
+            self.record_frame_end(frame, &contract_addr, result)?;
+        }
+        self.call_stack.pop();
+        result
+    }
+
+    /// code of code_id, either provided by the user or fetched from the chain
+    fn fetch_code(&self, code_id: u64) -> Result<Vec<u8>, Error> {
+        if let Some(code) = self.custom_codes.get(&code_id) {
+            Ok(code.clone())
+        } else {
+            maybe_unzip(
+                self.states
+                    .write()
+                    .unwrap()
+                    .client
+                    .query_wasm_contract_code(code_id)?,
+            )
+        }
     }
 
-    /// modify message sender
-    pub fn cheat_message_sender(&mut self, my_addr: &Addr) -> Result<(), Error> {
-        self.sender = my_addr.to_string();
-        Ok(())
+    fn instantiate_frame(
+        &mut self,
+        info: ContractInfo,
+        contract_addr: &Addr,
+        sender: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+    ) -> Result<(ContractResult<Response>, Option<Addr>), Error> {
+        let code_id = info.code_id;
+        let contract_addr = contract_addr.clone();
+
+        // transfer coins
+        let mut events = Vec::new();
+        if funds.len() > 0 {
+            let bank_msg = BankMsg::Send {
+                to_address: contract_addr.to_string(),
+                amount: funds.to_vec(),
+            };
+            match self
+                .states
+                .write()
+                .unwrap()
+                .bank_execute(sender, &bank_msg)?
+            {
+                ContractResult::Ok(r) => {
+                    self.debug_log.lock().unwrap().append_log(&r);
+                    events.extend(r.events);
+                }
+                ContractResult::Err(e) => {
+                    self.debug_log.lock().unwrap().set_err_msg(&e);
+                    return Ok((ContractResult::Err(e), None));
+                }
+            };
+        }
+
+        // because contract address does not exist on chain, create mock storage from empty set
+        let emtpy_storage = shared_storage(ContractStorage::new());
+        let deps = self.new_mock(&contract_addr, &emtpy_storage, &None)?;
+        let options = InstanceOptions {
+            gas_limit: self.gas_left.unwrap_or(u64::MAX),
+            print_debug: false,
+        };
+        let wasm_code = self.fetch_code(code_id)?;
+        let wasm_code = self.maybe_instrument(&contract_addr, wasm_code)?;
+        let wasm_instance = self.create_instance_from_code(wasm_code.as_slice(), deps, options)?;
+
+        // create a temporary contract_state, which will be deleted if instantiation fails
+        let contract_state = ContractState {
+            code: Arc::new(wasm_code),
+            storage: emtpy_storage,
+            info,
+            lazy: None,
+        };
+        self.states
+            .write()
+            .unwrap()
+            .contract_state_create(contract_addr.clone(), contract_state);
+        let mut instance = RpcContractInstance::new(&contract_addr, wasm_instance)
+            .with_memory_limit(self.wasm_cache.memory_limit())
+            .with_code_id(code_id);
+        let env = self.env(&contract_addr)?;
+
+        // open new call context
+        let call_id = self
+            .debug_log
+            .lock()
+            .unwrap()
+            .begin_instantiate(&contract_addr, msg);
+
+        // propagate contract error downwards
+        let result = self.check_aborted(instance.instantiate(&env, msg, sender, funds))?;
+        self.handle_coverage(&mut instance)?;
+        self.charge_gas(&instance);
+        let response = match result {
+            ContractResult::Ok(r) => {
+                events.push(instantiate_event(&contract_addr, code_id));
+                events.extend(contract_events(&contract_addr, &r));
+                // logs hold the response as returned by the contract, plus the instantiate event
+                let event = Event::new("instantiate")
+                    .add_attribute("code_id", code_id.to_string())
+                    .add_attribute("_contract_address", contract_addr.to_string());
+                let r = r.add_event(event);
+                self.debug_log.lock().unwrap().append_log(&r);
+                self.emit_events(r, events)
+            }
+            ContractResult::Err(e) => {
+                // remove the temporary contract_state created previously
+                self.states
+                    .write()
+                    .unwrap()
+                    .contract_state_remove(&contract_addr);
+                let mut debug_log = self.debug_log.lock().unwrap();
+                debug_log.set_err_msg(&e);
+                debug_log.begin_error(&e);
+                return Ok((ContractResult::Err(e), None));
+            }
+        };
+        let response = self.handle_response(&contract_addr, &response)?;
+
+        // close calling context
+        self.debug_log.lock().unwrap().end_instantiate(call_id);
+        Ok((response, Some(contract_addr)))
+    }
+
+    pub fn execute(
+        &mut self,
+        contract_addr: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        let sender = Addr::unchecked(&self.sender);
+        self.execute_top(&sender, contract_addr, msg, funds, true)
+    }
+
+    /// execute with the given sender, without changing the global sender
+    pub fn execute_as(
+        &mut self,
+        sender: &Addr,
+        contract_addr: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        self.execute_top(sender, contract_addr, msg, funds, true)
+    }
+
+    /// execute with the given sender, but funds are not debited from the sender
+    /// the contract still sees funds in info.funds and is credited with them
+    /// useful when the sender's balance is unknown or irrelevant
+    pub fn execute_as_without_transfer(
+        &mut self,
+        sender: &Addr,
+        contract_addr: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        self.execute_top(sender, contract_addr, msg, funds, false)
+    }
+
+    /// instantiate with msg serialized to json
+    pub fn instantiate_typed<M: Serialize>(
+        &mut self,
+        code_id: u64,
+        msg: &M,
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        let msg = typed_msg(msg)?;
+        self.instantiate(code_id, msg.as_slice(), funds)
+    }
+
+    /// execute with msg serialized to json
+    pub fn execute_typed<M: Serialize>(
+        &mut self,
+        contract_addr: &Addr,
+        msg: &M,
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        let msg = typed_msg(msg)?;
+        self.execute(contract_addr, msg.as_slice(), funds)
+    }
+
+    /// execute_as with msg serialized to json
+    pub fn execute_as_typed<M: Serialize>(
+        &mut self,
+        sender: &Addr,
+        contract_addr: &Addr,
+        msg: &M,
+        funds: &[Coin],
+    ) -> Result<DebugLog, Error> {
+        let msg = typed_msg(msg)?;
+        self.execute_as(sender, contract_addr, msg.as_slice(), funds)
+    }
+
+    /// runs msg as a top-level message of sender, e.g. a bank send from an account,
+    /// reverting all changes if it fails
+    pub fn execute_msg_as(&mut self, sender: &Addr, msg: &CosmosMsg) -> Result<DebugLog, Error> {
+        let sub_msg = SubMsg::new(msg.clone());
+        self.run_top(sender, |model| model.dispatch_submessage(sender, &sub_msg))
+    }
+
+    fn execute_top(
+        &mut self,
+        sender: &Addr,
+        contract_addr: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+        transfer_funds: bool,
+    ) -> Result<DebugLog, Error> {
+        self.validate_contract_msg(MsgKind::Execute, contract_addr, msg)?;
+        let funds = &self.checked_funds(funds)?;
+        self.run_top(sender, |model| {
+            model.execute_inner(contract_addr, sender, msg, funds, transfer_funds)
+        })
+    }
+
+    fn execute_inner(
+        &mut self,
+        contract_addr: &Addr,
+        sender: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+        transfer_funds: bool,
+    ) -> Result<ContractResult<Response>, Error> {
+        let _span = debug_span!("execute", contract = %contract_addr, sender = %sender).entered();
+        if self.call_stack.contains(contract_addr) {
+            let stack: Vec<&str> = self.call_stack.iter().map(|a| a.as_str()).collect();
+            let warning = format!(
+                "reentrant call to {} (call stack: {})",
+                contract_addr,
+                stack.join(" -> ")
+            );
+            let mut debug_log = self.debug_log.lock().unwrap();
+            debug_log.append_warning(&warning);
+            if self.recursion.policy == RecursionPolicy::Abort {
+                debug_log.set_err_msg(&warning);
+                debug_log.begin_error(&warning);
+                return Ok(ContractResult::Err(warning));
+            }
+        }
+        self.check_call_depth()?;
+        self.call_stack.push(contract_addr.clone());
+        self.hook_call_start(CallKind::Execute, contract_addr, sender, msg);
+        let frame =
+            self.record_frame_start(CallKind::Execute, contract_addr, sender, msg, funds, None)?;
+        let result = self.profiled("execute", contract_addr, |model| {
+            model.execute_frame(contract_addr, sender, msg, funds, transfer_funds)
+        });
+        if let Ok(result) = &result {
+            self.hook_call_end(CallKind::Execute, contract_addr, result);
+            self.record_frame_end(frame, contract_addr, result)?;
+        }
+        self.call_stack.pop();
+        result
+    }
+
+    fn execute_frame(
+        &mut self,
+        contract_addr: &Addr,
+        sender: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+        transfer_funds: bool,
+    ) -> Result<ContractResult<Response>, Error> {
+        let env = self.env(contract_addr)?;
+        // events of the funds transfer, emitted before those of the contract
+        let mut events = Vec::new();
+        if !funds.is_empty() && !transfer_funds {
+            let r = self
+                .states
+                .write()
+                .unwrap()
+                .bank_mint(contract_addr, funds)?;
+            self.debug_log.lock().unwrap().append_log(&r);
+            events.extend(r.events);
+        } else if funds.len() > 0 {
+            // transfer coins
+            let bank_msg = BankMsg::Send {
+                to_address: contract_addr.to_string(),
+                amount: funds.to_vec(),
+            };
+            match self
+                .states
+                .write()
+                .unwrap()
+                .bank_execute(sender, &bank_msg)?
+            {
+                ContractResult::Ok(r) => {
+                    self.debug_log.lock().unwrap().append_log(&r);
+                    events.extend(r.events);
+                }
+                ContractResult::Err(e) => {
+                    self.debug_log.lock().unwrap().set_err_msg(&e);
+                    return Ok(ContractResult::Err(e));
+                }
+            };
+        }
+
+        // open new call context
+        let call_id = self
+            .debug_log
+            .lock()
+            .unwrap()
+            .begin_execute(contract_addr, msg);
+
+        // execute contract code
+        // propagate contract error downwards
+        let result = match self.native_contract(contract_addr) {
+            // native contracts run no wasm, so there is nothing to cover or meter
+            Some(native) => {
+                let info = MessageInfo {
+                    sender: sender.clone(),
+                    funds: funds.to_vec(),
+                };
+                native.execute(&env, &info, msg).into()
+            }
+            None => {
+                let mut instance = self.create_instance(contract_addr)?;
+                let result = self.check_aborted(instance.execute(&env, msg, sender, funds))?;
+                self.handle_coverage(&mut instance)?;
+                self.charge_gas(&instance);
+                result
+            }
+        };
+        let response = match result {
+            ContractResult::Ok(r) => {
+                self.debug_log.lock().unwrap().append_log(&r);
+                events.push(execute_event(contract_addr));
+                events.extend(contract_events(contract_addr, &r));
+                self.emit_events(r, events)
+            }
+            ContractResult::Err(e) => {
+                let mut debug_log = self.debug_log.lock().unwrap();
+                debug_log.set_err_msg(&e);
+                debug_log.begin_error(&e);
+                return Ok(ContractResult::Err(e));
+            }
+        };
+        let response = self.handle_response(contract_addr, &response)?;
+
+        // close calling context
+        self.debug_log.lock().unwrap().end_execute(call_id);
+        Ok(response)
+    }
+
+    /// migrate contract_addr to new_code_id, sent by the current sender
+    pub fn migrate(
+        &mut self,
+        contract_addr: &Addr,
+        new_code_id: u64,
+        msg: &[u8],
+    ) -> Result<DebugLog, Error> {
+        self.validate_msg(MsgKind::Migrate, new_code_id, msg)?;
+        let sender = Addr::unchecked(&self.sender);
+        self.run_top(&sender, |model| {
+            model.migrate_inner(contract_addr, &sender, new_code_id, msg)
+        })
+    }
+
+    /// change the admin of contract_addr, sent by the current sender
+    pub fn update_admin(&mut self, contract_addr: &Addr, admin: &Addr) -> Result<DebugLog, Error> {
+        let sender = Addr::unchecked(&self.sender);
+        self.run_top(&sender, |model| {
+            model.update_admin_inner(contract_addr, &sender, Some(admin.to_string()))
+        })
+    }
+
+    /// remove the admin of contract_addr, sent by the current sender
+    pub fn clear_admin(&mut self, contract_addr: &Addr) -> Result<DebugLog, Error> {
+        let sender = Addr::unchecked(&self.sender);
+        self.run_top(&sender, |model| {
+            model.update_admin_inner(contract_addr, &sender, None)
+        })
+    }
+
+    /// channels opened during the simulation, including closed ones
+    pub fn ibc_channels(&self) -> Vec<ChannelEnd> {
+        self.states.read().unwrap().ibc().all_channels()
+    }
+
+    pub fn ibc_channel(&self, channel_id: &str) -> Option<ChannelEnd> {
+        self.states
+            .read()
+            .unwrap()
+            .ibc()
+            .channel(channel_id)
+            .cloned()
+    }
+
+    /// sends amount to to_address on the other end of a transfer channel, like MsgTransfer
+    /// signed by the current sender
+    pub fn ibc_transfer(
+        &mut self,
+        channel_id: &str,
+        to_address: &str,
+        amount: &Coin,
+        timeout: IbcTimeout,
+    ) -> Result<DebugLog, Error> {
+        let sender = Addr::unchecked(&self.sender);
+        let msg = IbcMsg::Transfer {
+            channel_id: channel_id.to_string(),
+            to_address: to_address.to_string(),
+            amount: amount.clone(),
+            timeout,
+        };
+        self.run_ibc(&sender, |model| {
+            model.states.write().unwrap().ibc_execute(&sender, &msg)
+        })
+    }
+
+    /// id the next channel opened on this chain gets
+    pub fn next_ibc_channel_id(&self) -> String {
+        self.states.read().unwrap().ibc().next_channel_id()
+    }
+
+    /// packets sent by this chain which were not relayed yet, oldest first
+    pub fn pending_ibc_packets(&self) -> Vec<IbcPacket> {
+        self.states.read().unwrap().ibc().pending_packets().to_vec()
+    }
+
+    /// removes the pending packets, to be relayed
+    pub fn take_ibc_packets(&mut self) -> Vec<IbcPacket> {
+        self.states.write().unwrap().ibc_mut().take_packets()
+    }
+
+    /// whether a packet with timeout can no longer be received at the current block
+    pub fn is_ibc_timed_out(&self, timeout: &IbcTimeout) -> bool {
+        let states = self.states.read().unwrap();
+        is_timed_out(timeout, states.block_number, states.block_timestamp)
+    }
+
+    /// first step of opening a channel on this chain, OpenInit or OpenTry.
+    /// The contract bound to the port of the channel may reject it, or return the version it
+    /// proposes instead. The transfer port only accepts unordered ics20-1 channels
+    pub fn ibc_channel_open(&mut self, msg: &IbcChannelOpenMsg) -> Result<Option<String>, Error> {
+        let channel = msg.channel();
+        match port_contract(&channel.endpoint.port_id) {
+            Some(contract_addr) => {
+                let env = self.env(&contract_addr)?;
+                let mut instance = self.create_instance(&contract_addr)?;
+                let result = instance.ibc_channel_open(&env, msg)?;
+                self.handle_coverage(&mut instance)?;
+                match result {
+                    ContractResult::Ok(version) => Ok(version),
+                    ContractResult::Err(e) => Err(Error::contract_error(
+                        &contract_addr,
+                        instance.code_id(),
+                        format!("rejected channel {}: {}", channel.endpoint.channel_id, e),
+                    )),
+                }
+            }
+            None if channel.endpoint.port_id == TRANSFER_PORT => {
+                let version = msg.counterparty_version().unwrap_or(&channel.version);
+                if channel.order != IbcOrder::Unordered || version != ICS20_VERSION {
+                    return Err(Error::invalid_argument(format!(
+                        "transfer channels must be unordered with version {}",
+                        ICS20_VERSION
+                    )));
+                }
+                Ok(None)
+            }
+            None => Err(Error::invalid_argument(format!(
+                "no module is bound to port {}",
+                channel.endpoint.port_id
+            ))),
+        }
+    }
+
+    /// last step of opening a channel on this chain, OpenAck or OpenConfirm, sent by relayer.
+    /// The channel is registered and the contract bound to its port is told about it
+    pub fn ibc_channel_connect(
+        &mut self,
+        msg: &IbcChannelConnectMsg,
+        relayer: &Addr,
+    ) -> Result<DebugLog, Error> {
+        let channel = msg.channel();
+        self.run_ibc(relayer, |model| {
+            let added = model
+                .states
+                .write()
+                .unwrap()
+                .ibc_mut()
+                .add_channel(channel.clone());
+            if let Err(e) = added {
+                return Ok(ContractResult::Err(e));
+            }
+            match port_contract(&channel.endpoint.port_id) {
+                Some(contract_addr) => {
+                    let raw = to_vec(msg).map_err(Error::from)?;
+                    model.ibc_call(&contract_addr, "ibc_channel_connect", &raw, |i, env| {
+                        i.ibc_channel_connect(env, msg)
+                    })
+                }
+                None => Ok(ContractResult::Ok(Response::new())),
+            }
+        })
+    }
+
+    /// closes the end of a channel on this chain after its counterparty was closed, sent by relayer
+    pub fn ibc_channel_close(
+        &mut self,
+        msg: &IbcChannelCloseMsg,
+        relayer: &Addr,
+    ) -> Result<DebugLog, Error> {
+        let endpoint = &msg.channel().endpoint;
+        self.run_ibc(relayer, |model| {
+            let closed = model
+                .states
+                .write()
+                .unwrap()
+                .ibc_mut()
+                .close_channel(&endpoint.port_id, &endpoint.channel_id);
+            if let Err(e) = closed {
+                return Ok(ContractResult::Err(e));
+            }
+            match port_contract(&endpoint.port_id) {
+                Some(contract_addr) => {
+                    let raw = to_vec(msg).map_err(Error::from)?;
+                    model.ibc_call(&contract_addr, "ibc_channel_close", &raw, |i, env| {
+                        i.ibc_channel_close(env, msg)
+                    })
+                }
+                None => Ok(ContractResult::Ok(Response::new())),
+            }
+        })
+    }
+
+    /// delivers a packet sent to this chain by relayer. The acknowledgement written is the data
+    /// of the returned log, if the receipt failed all its changes are reverted and err_msg is set
+    pub fn ibc_packet_receive(
+        &mut self,
+        packet: &IbcPacket,
+        relayer: &Addr,
+    ) -> Result<DebugLog, Error> {
+        if self.is_ibc_timed_out(&packet.timeout) {
+            return Err(Error::invalid_argument(format!(
+                "packet {} of channel {} timed out",
+                packet.sequence, packet.dest.channel_id
+            )));
+        }
+        // the sequence is used up even if the receipt fails
+        self.states
+            .write()
+            .unwrap()
+            .ibc_mut()
+            .receive_packet(packet)
+            .map_err(Error::invalid_argument)?;
+        self.run_ibc(relayer, |model| match port_contract(&packet.dest.port_id) {
+            Some(contract_addr) => {
+                let msg = IbcPacketReceiveMsg::new(packet.clone(), relayer.clone());
+                let raw = to_vec(&msg).map_err(Error::from)?;
+                model.ibc_call(&contract_addr, "ibc_packet_receive", &raw, |i, env| {
+                    i.ibc_packet_receive(env, &msg)
+                })
+            }
+            None => model.states.write().unwrap().ibc_transfer_receive(packet),
+        })
+    }
+
+    /// delivers the acknowledgement of a packet sent by this chain
+    pub fn ibc_packet_ack(
+        &mut self,
+        packet: &IbcPacket,
+        ack: &Binary,
+        relayer: &Addr,
+    ) -> Result<DebugLog, Error> {
+        self.run_ibc(relayer, |model| match port_contract(&packet.src.port_id) {
+            Some(contract_addr) => {
+                let msg = IbcPacketAckMsg::new(
+                    IbcAcknowledgement::new(ack.clone()),
+                    packet.clone(),
+                    relayer.clone(),
+                );
+                let raw = to_vec(&msg).map_err(Error::from)?;
+                model.ibc_call(&contract_addr, "ibc_packet_ack", &raw, |i, env| {
+                    i.ibc_packet_ack(env, &msg)
+                })
+            }
+            None => match Acknowledgement::error_of(ack) {
+                Some(_) => model.states.write().unwrap().ibc_transfer_refund(packet),
+                None => Ok(ContractResult::Ok(Response::new())),
+            },
+        })
+    }
+
+    /// tells this chain that a packet it sent timed out or can't be received since its channel
+    /// was closed. Timeouts close ordered channels
+    pub fn ibc_packet_timeout(
+        &mut self,
+        packet: &IbcPacket,
+        relayer: &Addr,
+    ) -> Result<DebugLog, Error> {
+        self.run_ibc(relayer, |model| {
+            let mut states = model.states.write().unwrap();
+            // packets of closed channels time out as well
+            let close = states
+                .ibc()
+                .channel(&packet.src.channel_id)
+                .is_some_and(|c| c.open && c.channel.order == IbcOrder::Ordered);
+            if close {
+                if let Err(e) = states
+                    .ibc_mut()
+                    .close_channel(&packet.src.port_id, &packet.src.channel_id)
+                {
+                    return Ok(ContractResult::Err(e));
+                }
+            }
+            drop(states);
+            match port_contract(&packet.src.port_id) {
+                Some(contract_addr) => {
+                    let msg = IbcPacketTimeoutMsg::new(packet.clone(), relayer.clone());
+                    let raw = to_vec(&msg).map_err(Error::from)?;
+                    model.ibc_call(&contract_addr, "ibc_packet_timeout", &raw, |i, env| {
+                        i.ibc_packet_timeout(env, &msg)
+                    })
+                }
+                None => model.states.write().unwrap().ibc_transfer_refund(packet),
+            }
+        })
+    }
+
+    /// run_top for ibc transactions, which always tell why they failed in err_msg
+    fn run_ibc<F>(&mut self, relayer: &Addr, f: F) -> Result<DebugLog, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<ContractResult<Response>, Error>,
+    {
+        self.run_top(relayer, |model| {
+            let result = f(model)?;
+            if let ContractResult::Err(e) = &result {
+                let mut debug_log = model.debug_log.lock().unwrap();
+                if debug_log.err_msg.is_none() {
+                    debug_log.set_err_msg(e);
+                }
+            }
+            Ok(result)
+        })
+    }
+
+    /// calls an ibc entry point of contract_addr and dispatches the messages of its response.
+    /// The data of the entry point, i.e. the acknowledgement of a receipt, is never overridden by replies
+    fn ibc_call<F>(
+        &mut self,
+        contract_addr: &Addr,
+        entry_point: &str,
+        msg: &[u8],
+        call: F,
+    ) -> Result<ContractResult<Response>, Error>
+    where
+        F: FnOnce(&mut RpcContractInstance, &Env) -> Result<ContractResult<Response>, Error>,
+    {
+        self.profiled(entry_point, contract_addr, |model| {
+            model.ibc_frame(contract_addr, entry_point, msg, call)
+        })
+    }
+
+    fn ibc_frame<F>(
+        &mut self,
+        contract_addr: &Addr,
+        entry_point: &str,
+        msg: &[u8],
+        call: F,
+    ) -> Result<ContractResult<Response>, Error>
+    where
+        F: FnOnce(&mut RpcContractInstance, &Env) -> Result<ContractResult<Response>, Error>,
+    {
+        let env = self.env(contract_addr)?;
+        let mut instance = self.create_instance(contract_addr)?;
+        let call_id = self
+            .debug_log
+            .lock()
+            .unwrap()
+            .begin_ibc(contract_addr, entry_point, msg);
+        self.call_stack.push(contract_addr.clone());
+        let result = self.check_aborted(call(&mut instance, &env));
+        let result = match result {
+            Ok(r) => self.handle_coverage(&mut instance).map(|_| r),
+            Err(e) => Err(e),
+        };
+        self.charge_gas(&instance);
+        let response = match result {
+            Ok(ContractResult::Ok(r)) => {
+                self.debug_log.lock().unwrap().append_log(&r);
+                let events = contract_events(contract_addr, &r);
+                self.emit_events(r, events)
+            }
+            Ok(ContractResult::Err(e)) => {
+                self.call_stack.pop();
+                let mut debug_log = self.debug_log.lock().unwrap();
+                debug_log.set_err_msg(&e);
+                debug_log.begin_error(&e);
+                return Ok(ContractResult::Err(e));
+            }
+            Err(e) => {
+                self.call_stack.pop();
+                return Err(e);
+            }
+        };
+        let result = self.handle_response(contract_addr, &response);
+        self.call_stack.pop();
+        self.debug_log.lock().unwrap().end_ibc(call_id);
+        Ok(match result? {
+            ContractResult::Ok(mut r) => {
+                r.data = response.data;
+                ContractResult::Ok(r)
+            }
+            err => err,
+        })
+    }
+
+    /// an error message if sender is not allowed to migrate or change the admin of contract_addr
+    fn check_admin(&self, contract_addr: &Addr, sender: &Addr) -> Result<Option<String>, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        if !self.admin_checks {
+            return Ok(None);
+        }
+        let states = self.states.read().unwrap();
+        let info = &states.contract_state_get(contract_addr).unwrap().info;
+        match &info.admin {
+            Some(admin) if admin == sender.as_str() => Ok(None),
+            Some(admin) => Ok(Some(format!(
+                "unauthorized: {} is not the admin of {} ({})",
+                sender, contract_addr, admin
+            ))),
+            None => Ok(Some(format!(
+                "unauthorized: {} has no admin",
+                contract_addr
+            ))),
+        }
+    }
+
+    fn update_admin_inner(
+        &mut self,
+        contract_addr: &Addr,
+        sender: &Addr,
+        admin: Option<String>,
+    ) -> Result<ContractResult<Response>, Error> {
+        if let Some(e) = self.check_admin(contract_addr, sender)? {
+            let mut debug_log = self.debug_log.lock().unwrap();
+            debug_log.set_err_msg(&e);
+            debug_log.begin_error(&e);
+            return Ok(ContractResult::Err(e));
+        }
+        let event = Event::new("update_contract_admin")
+            .add_attribute("_contract_address", contract_addr.to_string())
+            .add_attribute("new_admin_address", admin.clone().unwrap_or_default());
+        self.states
+            .write()
+            .unwrap()
+            .update_contract_metadata(contract_addr, |_, info| info.admin = admin);
+        let response = Response::new().add_event(event);
+        let mut debug_log = self.debug_log.lock().unwrap();
+        debug_log.append_log(&response);
+        debug_log.append_events(&response.events);
+        Ok(ContractResult::Ok(response))
+    }
+
+    fn migrate_inner(
+        &mut self,
+        contract_addr: &Addr,
+        sender: &Addr,
+        new_code_id: u64,
+        msg: &[u8],
+    ) -> Result<ContractResult<Response>, Error> {
+        let _span = debug_span!("migrate", contract = %contract_addr, new_code_id).entered();
+        if let Some(e) = self.check_admin(contract_addr, sender)? {
+            let mut debug_log = self.debug_log.lock().unwrap();
+            debug_log.set_err_msg(&e);
+            debug_log.begin_error(&e);
+            return Ok(ContractResult::Err(e));
+        }
+        self.check_call_depth()?;
+        self.call_stack.push(contract_addr.clone());
+        self.hook_call_start(CallKind::Migrate, contract_addr, sender, msg);
+        let frame = self.record_frame_start(
+            CallKind::Migrate,
+            contract_addr,
+            sender,
+            msg,
+            &[],
+            Some(new_code_id),
+        )?;
+        let result = self.profiled("migrate", contract_addr, |model| {
+            model.migrate_frame(contract_addr, new_code_id, msg)
+        });
+        if let Ok(result) = &result {
+            self.hook_call_end(CallKind::Migrate, contract_addr, result);
+            self.record_frame_end(frame, contract_addr, result)?;
+        }
+        self.call_stack.pop();
+        result
+    }
+
+    fn migrate_frame(
+        &mut self,
+        contract_addr: &Addr,
+        new_code_id: u64,
+        msg: &[u8],
+    ) -> Result<ContractResult<Response>, Error> {
+        let wasm_code = self.fetch_code(new_code_id)?;
+        self.coverage_info.forget_layout(contract_addr);
+        let wasm_code = self.maybe_instrument(contract_addr, wasm_code)?;
+
+        // swap the code, the old code is restored if migration fails
+        let (old_code, old_code_id) = self
+            .states
+            .read()
+            .unwrap()
+            .contract_state_get(contract_addr)
+            .map(|s| (s.code.clone(), s.info.code_id))
+            .unwrap();
+        self.states
+            .write()
+            .unwrap()
+            .update_contract_metadata(contract_addr, |code, info| {
+                *code = Arc::new(wasm_code);
+                info.code_id = new_code_id;
+            });
+        let restore = |model: &mut Self| {
+            model
+                .states
+                .write()
+                .unwrap()
+                .update_contract_metadata(contract_addr, |code, info| {
+                    *code = old_code.clone();
+                    info.code_id = old_code_id;
+                });
+        };
+
+        let env = self.env(contract_addr)?;
+        let mut instance = match self.create_instance(contract_addr) {
+            Ok(i) => i,
+            Err(e) => {
+                restore(self);
+                return Err(e);
+            }
+        };
+
+        // open new call context
+        let call_id = self
+            .debug_log
+            .lock()
+            .unwrap()
+            .begin_migrate(contract_addr, msg);
+
+        let result = self.check_aborted(instance.migrate(&env, msg))?;
+        self.handle_coverage(&mut instance)?;
+        self.charge_gas(&instance);
+        let response = match result {
+            ContractResult::Ok(r) => {
+                let mut events = vec![migrate_event(contract_addr, new_code_id)];
+                events.extend(contract_events(contract_addr, &r));
+                self.debug_log.lock().unwrap().append_log(&r);
+                self.emit_events(r, events)
+            }
+            ContractResult::Err(e) => {
+                restore(self);
+                let mut debug_log = self.debug_log.lock().unwrap();
+                debug_log.set_err_msg(&e);
+                debug_log.begin_error(&e);
+                return Ok(ContractResult::Err(e));
+            }
+        };
+        let response = self.handle_response(contract_addr, &response)?;
+
+        // close calling context
+        self.debug_log.lock().unwrap().end_migrate(call_id);
+        Ok(response)
+    }
+
+    /// metadata of contract_addr, including its code id and admin
+    pub fn contract_info(&self, contract_addr: &Addr) -> Result<ContractInfo, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        Ok(states
+            .contract_state_get(contract_addr)
+            .unwrap()
+            .info
+            .clone())
+    }
+
+    /// pins code_id to the wasm cache, as reported by ContractInfo queries to its contracts.
+    /// Codes pinned on chain are pinned unless unpinned
+    pub fn pin_code(&mut self, code_id: u64) {
+        self.states.write().unwrap().set_pinned(code_id, true);
+    }
+
+    pub fn unpin_code(&mut self, code_id: u64) {
+        self.states.write().unwrap().set_pinned(code_id, false);
+    }
+
+    /// seeds the addresses of instantiated contracts and the rng of the model,
+    /// so that simulations using the same seed can be reproduced exactly
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// randomness for simulation utilities, deterministic given the seed
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// describes the setup of this model, to re-run a simulation elsewhere
+    pub fn repro_report(&self) -> ReproReport {
+        let states = self.states.read().unwrap();
+        let mut custom_codes: Vec<CustomCode> = self
+            .custom_codes
+            .iter()
+            .map(|(code_id, code)| CustomCode {
+                code_id: *code_id,
+                checksum: hex::encode(code_checksum(code)),
+            })
+            .collect();
+        custom_codes.sort_by_key(|c| c.code_id);
+        ReproReport {
+            url: states.client.url().to_string(),
+            block_number: states.client.block_number(),
+            chain_id: states.chain_id.clone(),
+            bech32_prefix: states.bech32_prefix.clone(),
+            rpc_cache_key: states.client.cache_key(),
+            seed: self.seed,
+            sender: self.sender(),
+            code_overrides: self
+                .code_overrides
+                .iter()
+                .map(|(contract, checksum)| CodeOverride {
+                    contract: contract.clone(),
+                    checksum: hex::encode(checksum),
+                })
+                .collect(),
+            custom_codes,
+        }
+    }
+
+    /// fetch the storage of contracts loaded from now on key by key as it is accessed,
+    /// instead of downloading it entirely. Iterating over the storage of a contract
+    /// still downloads it entirely the first time
+    pub fn set_lazy_storage(&mut self, enabled: bool) {
+        self.states.write().unwrap().lazy_storage = enabled;
+    }
+
+    /// whether migrations and admin changes are restricted to the contract admin
+    pub fn set_admin_checks(&mut self, enabled: bool) {
+        self.admin_checks = enabled;
+    }
+
+    /// adds a middleware seeing every message emitted by contracts or proposals before it is dispatched,
+    /// after the middlewares added before it. Clones of the model keep the middlewares added so far
+    pub fn add_middleware(&mut self, middleware: Arc<dyn MsgMiddleware>) {
+        self.middlewares.push(middleware);
+    }
+
+    pub fn clear_middlewares(&mut self) {
+        self.middlewares.clear();
+    }
+
+    /// observe calls, bank transfers and storage writes as they happen, replacing the hooks set before.
+    /// Clones of the model keep the hooks
+    pub fn set_hooks(&mut self, hooks: Arc<dyn ExecutionHooks>) {
+        self.states.write().unwrap().hooks = Some(hooks);
+    }
+
+    pub fn clear_hooks(&mut self) {
+        self.states.write().unwrap().hooks = None;
+    }
+
+    fn hooks(&self) -> Option<Arc<dyn ExecutionHooks>> {
+        self.states.read().unwrap().hooks.clone()
+    }
+
+    /// answers the execute messages and smart queries sent to address with contract instead of
+    /// wasm, e.g. to stub an oracle with fixed prices. A contract of the chain at address is
+    /// replaced, raw queries see an empty storage. Clones of the model keep native contracts
+    pub fn register_native_contract(&mut self, address: &Addr, contract: Arc<dyn NativeContract>) {
+        self.states
+            .write()
+            .unwrap()
+            .native_contracts
+            .insert(address.clone(), contract);
+    }
+
+    /// address is answered by its wasm contract again, if any
+    pub fn remove_native_contract(&mut self, address: &Addr) {
+        self.states
+            .write()
+            .unwrap()
+            .native_contracts
+            .remove(address);
+    }
+
+    fn native_contract(&self, address: &Addr) -> Option<Arc<dyn NativeContract>> {
+        self.states
+            .read()
+            .unwrap()
+            .native_contracts
+            .get(address)
+            .cloned()
+    }
+
+    /// wasm_query with msg serialized to json and the response parsed as R
+    pub fn query_typed<Q: Serialize, R: DeserializeOwned>(
+        &mut self,
+        contract_addr: &Addr,
+        msg: &Q,
+    ) -> Result<R, Error> {
+        let msg = typed_msg(msg)?;
+        let response = self.wasm_query(contract_addr, msg.as_slice())?;
+        from_binary(&response).map_err(|e| {
+            Error::format_error(format!(
+                "cannot parse the response of {} to {} as {}: {}, response: {}",
+                contract_addr,
+                String::from_utf8_lossy(msg.as_slice()),
+                std::any::type_name::<R>(),
+                e,
+                String::from_utf8_lossy(response.as_slice())
+            ))
+        })
+    }
+
+    /// smart query of a contract, see Model::query
+    pub fn wasm_query(&mut self, contract_addr: &Addr, msg: &[u8]) -> Result<Binary, Error> {
+        self.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: contract_addr.to_string(),
+            msg: Binary::from(msg),
+        }))
+    }
+
+    /// answers request the same way as the queries of contracts, i.e. bank, wasm, staking and
+    /// stargate queries. Smart queries record coverage and call metrics like wasm_query
+    pub fn query(&mut self, request: &QueryRequest<Empty>) -> Result<Binary, Error> {
+        if let QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) = request {
+            return self.query_with_block(&Addr::unchecked(contract_addr), msg.as_slice(), None);
+        }
+        let request = to_vec(request).map_err(Error::from)?;
+        self.query_json(&request)
+    }
+
+    /// query with a json encoded QueryRequest, which may also be one of the queries of later
+    /// cosmwasm versions the vendored QueryRequest lacks, e.g. {"distribution":{...}} of cosmwasm 1.4
+    pub fn query_json(&mut self, request: &[u8]) -> Result<Binary, Error> {
+        if let Ok(QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })) =
+            from_slice::<QueryRequest<Empty>>(request)
+        {
+            return self.query_with_block(&Addr::unchecked(contract_addr), msg.as_slice(), None);
+        }
+        self.reload_watched_or_warn();
+        let (result, _) = self.contract_querier().query_raw(request, u64::MAX);
+        match result.map_err(Error::backend_error)? {
+            SystemResult::Ok(ContractResult::Ok(response)) => Ok(response),
+            SystemResult::Ok(ContractResult::Err(e)) => Err(Error::std_error(e)),
+            SystemResult::Err(e) => Err(Error::invalid_argument(e)),
+        }
+    }
+
+    /// wasm_query with the block height and time seen by the contract, and the contracts it queries,
+    /// overridden, e.g. to check the interest accrued a week from now. The block of the model is kept
+    pub fn wasm_query_at(
+        &mut self,
+        contract_addr: &Addr,
+        msg: &[u8],
+        height: u64,
+        time: Timestamp,
+    ) -> Result<Binary, Error> {
+        let block = BlockInfo {
+            height,
+            time,
+            chain_id: self.states.read().unwrap().chain_id.clone(),
+        };
+        self.query_with_block(contract_addr, msg, Some(block))
+    }
+
+    fn query_with_block(
+        &mut self,
+        contract_addr: &Addr,
+        msg: &[u8],
+        block: Option<BlockInfo>,
+    ) -> Result<Binary, Error> {
+        self.reload_watched_or_warn();
+        self.validate_contract_msg(MsgKind::Query, contract_addr, msg)?;
+        let mut env = self.env(contract_addr)?;
+        if let Some(native) = self.native_contract(contract_addr) {
+            if let Some(block) = block {
+                env.block = block;
+            }
+            return native.query(&env, msg).map_err(Error::from);
+        }
+        let mut instance = self.create_instance(contract_addr)?;
+        if let Some(block) = block {
+            env.block = block.clone();
+            instance
+                .instance
+                .with_querier(|q| {
+                    *q = q.clone().with_block(Some(block));
+                    Ok(())
+                })
+                .map_err(Error::from)?;
+        }
+        let wasm_query = WasmQuery::Smart {
+            contract_addr: contract_addr.to_string(),
+            msg: Binary::from(msg),
+        };
+        // TODO: fix this, propagate contract error down
+        let result = instance.query(&env, &wasm_query);
+        if let Some(reason) = self.debug_log.lock().unwrap().take_abort() {
+            return Err(Error::vm_error(reason));
+        }
+        self.handle_coverage(&mut instance)?;
+        self.record_call_metrics(&instance);
+        Ok(result?)
+    }
+
+    /// reads a raw key from the storage of a contract
+    pub fn wasm_raw_query(
+        &self,
+        contract_addr: &Addr,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        let contract_state = states.contract_state_get(contract_addr).unwrap();
+        contract_state.fetch_key(key)?;
+        let value = contract_state.storage.read().unwrap().get(key).cloned();
+        Ok(value)
+    }
+
+    /// returns a copy of the entire storage of a contract
+    pub(crate) fn contract_storage(&self, contract_addr: &Addr) -> Result<ContractStorage, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        let contract_state = states.contract_state_get(contract_addr).unwrap();
+        contract_state.fetch_all()?;
+        let storage = contract_state.storage.read().unwrap().as_ref().clone();
+        Ok(storage)
+    }
+
+    /// key/value pairs of the storage of a contract with keys in [start, end), at most limit of them
+    pub fn scan_storage(
+        &self,
+        contract_addr: &Addr,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+        limit: Option<usize>,
+    ) -> Result<Vec<Record>, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        let contract_state = states.contract_state_get(contract_addr).unwrap();
+        contract_state.fetch_all()?;
+        let mut records = scan_range(&contract_state.storage.read().unwrap(), start, end, order);
+        if let Some(limit) = limit {
+            records.truncate(limit);
+        }
+        Ok(records)
+    }
+
+    /// entries of the cw-storage-plus Map stored under namespace, with the namespace stripped from the keys
+    pub fn scan_map(
+        &self,
+        contract_addr: &Addr,
+        namespace: &str,
+        order: Order,
+        limit: Option<usize>,
+    ) -> Result<Vec<Record>, Error> {
+        let prefix = namespace_prefix(&[namespace.as_bytes()]);
+        let end = prefix_end(&prefix);
+        let records =
+            self.scan_storage(contract_addr, Some(&prefix), end.as_deref(), order, limit)?;
+        Ok(records
+            .into_iter()
+            .map(|(k, v)| (k[prefix.len()..].to_vec(), v))
+            .collect())
+    }
+
+    /// code of an already loaded contract
+    pub(crate) fn contract_code(&self, contract_addr: &Addr) -> Option<Vec<u8>> {
+        self.states
+            .read()
+            .unwrap()
+            .contract_state_get(contract_addr)
+            .map(|s| s.code.to_vec())
+    }
+
+    /// json encoded BankQuery, see Model::query
+    pub fn bank_query(&mut self, bank_query_: &[u8]) -> Result<Binary, Error> {
+        let bank_query: BankQuery =
+            from_binary(&Binary::from(bank_query_)).map_err(Error::format_error)?;
+        self.query(&QueryRequest::Bank(bank_query))
+    }
+
+    // the querier of the contracts called by the model, recording their queries in the debug log
+    fn contract_querier(&self) -> RpcMockQuerier {
+        RpcMockQuerier::new(&self.states, &self.debug_log)
+            .with_storage_trace(self.trace_storage)
+            .with_query_metering(self.meter_queries)
+            .with_recursion(self.recursion, 0)
+            .with_module_cache(&self.wasm_cache)
+            .with_instance_pool(&self.instance_pool)
+            .with_block(self.tx_block.clone())
+    }
+
+    fn new_mock(
+        &self,
+        contract_addr: &Addr,
+        contract_storage: &SharedStorage,
+        lazy: &Option<Arc<LazyStorage>>,
+    ) -> Result<RpcBackend, Error> {
+        let api = self.states.read().unwrap().api()?;
+        Ok(Backend {
+            storage: self
+                .mock_storage(contract_addr, contract_storage)?
+                .with_lazy(lazy.clone()),
+            api,
+            querier: self.contract_querier(),
+        })
+    }
+
+    fn env(&self, contract_addr: &Addr) -> Result<Env, Error> {
+        let block = match &self.tx_block {
+            Some(block) => block.clone(),
+            None => self.block_info(),
+        };
+        Ok(Env {
+            block,
+            transaction: Some(cosmwasm_std::TransactionInfo {
+                index: self.states.read().unwrap().tx_index,
+            }),
+            // I don't really know what this is for, so for now, set it to the target contract address
+            contract: cosmwasm_std::ContractInfo {
+                address: contract_addr.clone(),
+            },
+        })
+    }
+
+    fn mock_storage(
+        &self,
+        contract_addr: &Addr,
+        contract_storage: &SharedStorage,
+    ) -> Result<RpcMockStorage, Error> {
+        let storage = RpcMockStorage::new(contract_storage)
+            .with_hooks(contract_addr, self.hooks())
+            .with_journal(self.states.read().unwrap().storage_journal(contract_addr));
+        if self.trace_storage {
+            Ok(storage.with_tracer(StorageTracer::new(contract_addr, &self.debug_log)))
+        } else {
+            Ok(storage)
+        }
+    }
+
+    /// prints the spans and events of the simulator, i.e. rpc queries, cache hits and misses,
+    /// contract calls and submessages, to stderr. level is a level like "debug", or per module
+    /// directives like "info,cosmwasm_simulate::fork::rpc=trace". RUST_LOG takes precedence.
+    /// Fails if a global subscriber is set already, e.g. by the user to collect the spans differently
+    pub fn enable_tracing(level: &str) -> Result<(), Error> {
+        let directives = if level.contains('=') {
+            level.to_string()
+        } else {
+            // a bare level would enable the logs of the rpc and wasm dependencies as well
+            format!("cosmwasm_simulate={}", level)
+        };
+        let filter = match EnvFilter::try_from_default_env() {
+            Ok(filter) => filter,
+            Err(_) => EnvFilter::try_new(directives).map_err(Error::invalid_argument)?,
+        };
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .try_init()
+            .map_err(Error::invalid_argument)
+    }
+
+    /// record every storage write in DebugLog::storage_trace,
+    /// and the keys read and written by every call in DebugLog::access_sets
+    pub fn enable_storage_trace(&mut self) {
+        self.trace_storage = true;
+    }
+
+    pub fn disable_storage_trace(&mut self) {
+        self.trace_storage = false;
+    }
+
+    /// charge the gas used by queried contracts, and the contracts they query, to the querying
+    /// contract like wasmd does. Queries are free unless enabled, so that query-heavy contracts
+    /// use less gas than on chain. Queries using more gas than the querying contract has left run
+    /// it out of gas
+    pub fn enable_query_metering(&mut self) {
+        self.meter_queries = true;
+    }
+
+    pub fn disable_query_metering(&mut self) {
+        self.meter_queries = false;
+    }
+
+    /// record every contract call with its message, env and the storage of the contract
+    /// before and after it in DebugLog::recorded_frames. This copies the storage of the contract
+    /// twice per call, and fetches the whole storage of lazily loaded contracts
+    pub fn enable_frame_recording(&mut self) {
+        self.record_frames = true;
+    }
+
+    pub fn disable_frame_recording(&mut self) {
+        self.record_frames = false;
+    }
+
+    /// when a contract traps, e.g. because it panicked, append the wasm functions it trapped in
+    /// to the error, and keep them in DebugLog::backtrace of the debug log of the model.
+    /// Function names are only known for contracts with a name section
+    pub fn enable_backtraces(&mut self) {
+        self.capture_backtraces = true;
+    }
+
+    pub fn disable_backtraces(&mut self) {
+        self.capture_backtraces = false;
+    }
+
+    /// instead of rejecting the funds of execute and instantiate with duplicate denoms, zero amounts
+    /// or unsorted denoms like the chain, add up duplicates, drop zero amounts and sort them,
+    /// the same way wasmd converts the funds of submessages
+    pub fn enable_funds_merging(&mut self) {
+        self.merge_funds = true;
+    }
+
+    pub fn disable_funds_merging(&mut self) {
+        self.merge_funds = false;
+    }
+
+    /// passes the errors of failed submessages to reply as wasmd does, e.g.
+    /// "codespace: wasm, code: 5" for a failed execution, instead of the error message.
+    /// For contracts which parse the error in reply, the debug log keeps the whole error
+    pub fn enable_reply_error_redaction(&mut self) {
+        self.redact_reply_errors = true;
+    }
+
+    pub fn disable_reply_error_redaction(&mut self) {
+        self.redact_reply_errors = false;
+    }
+
+    // funds of a top-level message as the chain accepts them, see enable_funds_merging
+    fn checked_funds(&self, funds: &[Coin]) -> Result<Vec<Coin>, Error> {
+        if self.merge_funds {
+            merge_funds(funds)
+        } else {
+            validate_funds(funds)?;
+            Ok(funds.to_vec())
+        }
+    }
+
+    fn attach_backtrace(&self, e: Error) -> Error {
+        if !self.capture_backtraces {
+            return e;
+        }
+        match e {
+            Error::ContractVmError {
+                contract,
+                code_id,
+                source,
+                wasm_backtrace: None,
+            } => {
+                let backtrace = take_backtrace();
+                let mut wasm_backtrace = None;
+                if !backtrace.is_empty() {
+                    wasm_backtrace = Some(format_backtrace(&backtrace));
+                    self.debug_log.lock().unwrap().backtrace = backtrace;
+                }
+                Error::ContractVmError {
+                    contract,
+                    code_id,
+                    source,
+                    wasm_backtrace,
+                }
+            }
+            e => e,
+        }
+    }
+
+    /// runs a recorded call again on a copy of the model, with the storage of the contract and
+    /// the block it started with. Messages returned by the call are not dispatched and hooks are
+    /// not called, queries see the current state of the model.
+    /// Calls other than instantiations and migrations run the current code of the contract.
+    /// Returns the result of the call and the storage of the contract after it
+    pub fn rerun_frame(
+        &self,
+        frame: &FrameRecord,
+    ) -> Result<(ContractResult<Response>, ContractStorage), Error> {
+        let mut model = self.clone();
+        model.clear_hooks();
+        {
+            let mut states = model.states.write().unwrap();
+            states.block_number = frame.env.block.height;
+            states.block_timestamp = frame.env.block.time;
+        }
+        let code = match frame.kind {
+            CallKind::Instantiate | CallKind::Migrate => model.fetch_code(frame.code_id)?,
+            CallKind::Execute | CallKind::Reply | CallKind::Sudo => {
+                model.fetch_contract_state(&frame.contract)?;
+                let states = model.states.read().unwrap();
+                states
+                    .contract_state_get(&frame.contract)
+                    .unwrap()
+                    .code
+                    .to_vec()
+            }
+        };
+        let storage = shared_storage(frame.storage_before.clone());
+        let deps = model.new_mock(&frame.contract, &storage, &None)?;
+        let options = InstanceOptions {
+            gas_limit: u64::MAX,
+            print_debug: false,
+        };
+        let wasm_instance = model.create_instance_from_code(&code, deps, options)?;
+        let mut instance = RpcContractInstance::new(&frame.contract, wasm_instance)
+            .with_memory_limit(model.wasm_cache.memory_limit())
+            .with_code_id(frame.code_id);
+        let result = match frame.kind {
+            CallKind::Instantiate => {
+                instance.instantiate(&frame.env, &frame.msg, &frame.sender, &frame.funds)?
+            }
+            CallKind::Execute => {
+                instance.execute(&frame.env, &frame.msg, &frame.sender, &frame.funds)?
+            }
+            CallKind::Migrate => instance.migrate(&frame.env, &frame.msg)?,
+            CallKind::Reply => {
+                let reply: Reply = from_binary(&frame.msg).map_err(Error::format_error)?;
+                instance.reply(&frame.env, &reply)?
+            }
+            CallKind::Sudo => instance.sudo(&frame.env, &frame.msg)?,
+        };
+        let storage_after = storage.read().unwrap().as_ref().clone();
+        Ok((result, storage_after))
+    }
+
+    pub fn limits(&self) -> SandboxLimits {
+        SandboxLimits {
+            memory_limit: self.wasm_cache.memory_limit(),
+            max_call_depth: self.max_call_depth,
+        }
+    }
+
+    /// the memory limit applies to the module cache, which is shared with the clones of the model.
+    /// Changing it drops the compiled modules kept in memory
+    pub fn set_limits(&mut self, limits: SandboxLimits) {
+        self.wasm_cache.set_memory_limit(limits.memory_limit);
+        self.max_call_depth = limits.max_call_depth;
+    }
+
+    fn check_call_depth(&self) -> Result<(), Error> {
+        match self.max_call_depth {
+            Some(max_call_depth) if self.call_stack.len() >= max_call_depth => {
+                Err(Error::limit_exceeded(format!(
+                    "call depth exceeds the limit of {}",
+                    max_call_depth
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn recursion_config(&self) -> RecursionConfig {
+        self.recursion
+    }
+
+    /// set how reentrant calls and deep query recursion are handled, see RecursionConfig
+    pub fn set_recursion_config(&mut self, recursion: RecursionConfig) {
+        self.recursion = recursion;
+    }
+
+    /// capabilities advertised by the chain, DEFAULT_CAPABILITIES unless set
+    pub fn capabilities(&self) -> BTreeSet<String> {
+        self.states.read().unwrap().capabilities.clone()
+    }
+
+    /// set the capabilities advertised by the chain, e.g. cosmwasm_1_2. Code requiring others
+    /// can't be added and queries needing others fail as unsupported
+    pub fn set_capabilities(&mut self, capabilities: &[&str]) {
+        self.states.write().unwrap().capabilities =
+            capabilities.iter().map(|c| c.to_string()).collect();
+    }
+
+    /// modify block number
+    pub fn cheat_block_number(&mut self, new_number: u64) -> Result<(), Error> {
+        self.states.write().unwrap().block_number = new_number;
+        Ok(())
+    }
+
+    /// sets the index of transactions in their block, which contracts see in env.transaction,
+    /// for every transaction from now on. It is 0 unless set
+    pub fn cheat_transaction_info(&mut self, index: u32) -> Result<(), Error> {
+        self.states.write().unwrap().tx_index = index;
+        Ok(())
+    }
+
+    /// modify block timestamp
+    pub fn cheat_block_timestamp(&mut self, new_timestamp: Timestamp) -> Result<(), Error> {
+        self.states.write().unwrap().block_timestamp = new_timestamp;
+        Ok(())
+    }
+
+    /// moves the block time forward by duration and leaves the height as is, e.g. to the end
+    /// of a vesting period
+    pub fn cheat_advance_time(&mut self, duration: Duration) -> Result<(), Error> {
+        let mut states = self.states.write().unwrap();
+        states.block_timestamp = advance_timestamp(states.block_timestamp, duration)?;
+        Ok(())
+    }
+
+    /// moves the chain forward by n blocks of seconds_per_block each like produce_block,
+    /// failing instead of overflowing
+    pub fn cheat_advance_blocks(&mut self, n: u64) -> Result<(), Error> {
+        let mut states = self.states.write().unwrap();
+        let overflow = || Error::invalid_argument(format!("advancing by {} blocks overflows", n));
+        let block_number = states.block_number.checked_add(n).ok_or_else(overflow)?;
+        let seconds = n
+            .checked_mul(states.block_config.seconds_per_block)
+            .ok_or_else(overflow)?;
+        let block_timestamp =
+            advance_timestamp(states.block_timestamp, Duration::from_secs(seconds))?;
+        states.block_number = block_number;
+        states.block_timestamp = block_timestamp;
+        Ok(())
+    }
+
+    pub fn block_config(&self) -> BlockConfig {
+        self.states.read().unwrap().block_config
+    }
+
+    /// set how blocks are produced, see BlockConfig
+    pub fn set_block_config(&mut self, block_config: BlockConfig) {
+        self.states.write().unwrap().block_config = block_config;
+    }
+
+    /// advance the chain by n blocks, regardless of block_config
+    pub fn produce_block(&mut self, n: u64) {
+        self.states.write().unwrap().produce_blocks(n);
+    }
+
+    /// opens a block, into which every transaction until end_block goes, e.g. those of deliver.
+    /// They all see the same height and time, and the indexes 0, 1, ... in env.transaction
+    pub fn begin_block(&mut self) -> Result<(), Error> {
+        if self.open_block.is_some() {
+            return Err(Error::invalid_argument(
+                "a block is open already, end it with end_block first",
+            ));
+        }
+        let mut states = self.states.write().unwrap();
+        self.open_block = Some(states.tx_index);
+        states.tx_index = 0;
+        Ok(())
+    }
+
+    /// runs msgs of sender in order as a single transaction, which reverts if one of them fails.
+    /// The debug log holds the data of the last message
+    pub fn deliver(&mut self, sender: &Addr, msgs: &[CosmosMsg]) -> Result<DebugLog, Error> {
+        self.run_top(sender, |model| {
+            let mut result = Response::new();
+            for msg in msgs.iter() {
+                match model.dispatch_submessage(sender, &SubMsg::new(msg.clone()))? {
+                    ContractResult::Ok(r) => {
+                        result.events.extend(r.events);
+                        result.data = r.data;
+                    }
+                    err => return Ok(err),
+                }
+            }
+            Ok(ContractResult::Ok(result))
+        })
+    }
+
+    /// closes the block opened by begin_block and moves on to the next block,
+    /// regardless of block_config
+    pub fn end_block(&mut self) -> Result<(), Error> {
+        let tx_index = self
+            .open_block
+            .take()
+            .ok_or_else(|| Error::invalid_argument("no block is open, see begin_block"))?;
+        let mut states = self.states.write().unwrap();
+        states.tx_index = tx_index;
+        states.produce_blocks(1);
+        Ok(())
+    }
+
+    /// modify bank balance, the total supply is left unchanged
+    pub fn cheat_bank_balance(
+        &mut self,
+        address: &Addr,
+        denom: &str,
+        new_balance: u128,
+    ) -> Result<(), Error> {
+        self.states
+            .write()
+            .unwrap()
+            .set_balance(address, denom, Uint128::new(new_balance))?;
+        Ok(())
+    }
+
+    /// mint coins to address, increasing their total supply unlike cheat_bank_balance
+    pub fn cheat_mint(&mut self, address: &Addr, amount: &[Coin]) -> Result<(), Error> {
+        self.states.write().unwrap().bank_mint(address, amount)?;
+        Ok(())
+    }
+
+    /// total supply of denom
+    pub fn supply(&self, denom: &str) -> Result<Uint128, Error> {
+        self.states.write().unwrap().get_supply(denom)
+    }
+
+    /// pending staking rewards of delegator from validator
+    pub fn delegation_rewards(
+        &self,
+        delegator: &Addr,
+        validator: &str,
+    ) -> Result<Vec<Coin>, Error> {
+        self.states
+            .write()
+            .unwrap()
+            .get_delegation_rewards(delegator, validator)
+    }
+
+    /// address receiving the staking rewards of delegator
+    pub fn withdraw_address(&self, delegator: &Addr) -> Result<Addr, Error> {
+        self.states.write().unwrap().get_withdraw_address(delegator)
+    }
+
+    /// set the pending staking rewards of delegator from validator,
+    /// the distribution module account must hold enough funds to pay them out
+    pub fn cheat_delegation_rewards(
+        &mut self,
+        delegator: &Addr,
+        validator: &str,
+        rewards: &[Coin],
+    ) {
+        self.states
+            .write()
+            .unwrap()
+            .set_delegation_rewards(delegator, validator, rewards.to_vec());
+    }
+
+    /// gov proposal submitted during the simulation
+    pub fn proposal(&self, proposal_id: u64) -> Option<Proposal> {
+        self.states.read().unwrap().proposal(proposal_id).cloned()
+    }
+
+    /// executes the messages of a proposal in voting period as the gov module, as if it passed.
+    /// The proposal is marked as failed if any message fails, in which case all changes are reverted.
+    /// Deposits are refunded either way
+    pub fn cheat_pass_proposal(&mut self, proposal_id: u64) -> Result<DebugLog, Error> {
+        let proposal = self.active_proposal(proposal_id)?;
+        let mut response = Response::new();
+        for (type_url, value) in proposal.messages.iter() {
+            response = response.add_message(Self::proposal_msg(type_url, value)?);
+        }
+        let gov = self.module_address("gov");
+        let debug_log = self.run_top(&gov, |model| model.handle_response(&gov, &response))?;
+        let status = if debug_log.err_msg.is_some() {
+            ProposalStatus::Failed
+        } else {
+            ProposalStatus::Passed
+        };
+        self.close_proposal(proposal_id, status)?;
+        Ok(debug_log)
+    }
+
+    /// rejects a proposal in voting period, refunding its deposits
+    pub fn cheat_reject_proposal(&mut self, proposal_id: u64) -> Result<(), Error> {
+        self.active_proposal(proposal_id)?;
+        self.close_proposal(proposal_id, ProposalStatus::Rejected)
+    }
+
+    fn active_proposal(&self, proposal_id: u64) -> Result<Proposal, Error> {
+        match self.proposal(proposal_id) {
+            Some(p) if p.status == ProposalStatus::VotingPeriod => Ok(p),
+            Some(_) => Err(Error::invalid_argument(format!(
+                "proposal {} is not in voting period",
+                proposal_id
+            ))),
+            None => Err(Error::invalid_argument(format!(
+                "proposal {} was not submitted in the simulation",
+                proposal_id
+            ))),
+        }
+    }
+
+    fn close_proposal(&mut self, proposal_id: u64, status: ProposalStatus) -> Result<(), Error> {
+        let mut states = self.states.write().unwrap();
+        if let ContractResult::Err(e) = states.refund_proposal_deposits(proposal_id)? {
+            return Err(Error::invalid_argument(e));
+        }
+        states.proposal_mut(proposal_id).unwrap().status = status;
+        Ok(())
+    }
+
+    fn module_address(&self, name: &str) -> Addr {
+        crate::module_address(name, &self.states.read().unwrap().bech32_prefix)
+    }
+
+    /// converts a message of a proposal, wasm messages are executed by the simulator itself
+    fn proposal_msg(type_url: &str, value: &Binary) -> Result<CosmosMsg, Error> {
+        use rpc_items::cosmwasm::wasm::v1::{MsgExecuteContract, MsgMigrateContract};
+        match type_url {
+            "/cosmwasm.wasm.v1.MsgExecuteContract" => {
+                let msg =
+                    MsgExecuteContract::decode(value.as_slice()).map_err(Error::format_error)?;
+                let funds = msg
+                    .funds
+                    .into_iter()
+                    .map(|c| {
+                        let amount = c.amount.parse().map_err(Error::format_error)?;
+                        Ok(Coin::new(amount, c.denom))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: msg.contract,
+                    msg: Binary::from(msg.msg),
+                    funds,
+                }))
+            }
+            "/cosmwasm.wasm.v1.MsgMigrateContract" => {
+                let msg =
+                    MsgMigrateContract::decode(value.as_slice()).map_err(Error::format_error)?;
+                Ok(CosmosMsg::Wasm(WasmMsg::Migrate {
+                    contract_addr: msg.contract,
+                    new_code_id: msg.code_id,
+                    msg: Binary::from(msg.msg),
+                }))
+            }
+            _ => Ok(CosmosMsg::Stargate {
+                type_url: type_url.to_string(),
+                value: value.clone(),
+            }),
+        }
+    }
+
+    /// tokenfactory admin of denom, None if it is not a tokenfactory denom or has no admin
+    pub fn denom_admin(&self, denom: &str) -> Option<Addr> {
+        self.states.read().unwrap().tokenfactory().admin(denom)
+    }
+
+    /// tokenfactory denoms created by creator during the simulation
+    pub fn tokenfactory_denoms(&self, creator: &Addr) -> Vec<String> {
+        self.states
+            .read()
+            .unwrap()
+            .tokenfactory()
+            .denoms_from_creator(creator.as_str())
+    }
+
+    /// set the tokenfactory admin of denom, None renounces it
+    pub fn cheat_denom_admin(&mut self, denom: &str, admin: Option<&Addr>) {
+        self.states
+            .write()
+            .unwrap()
+            .tokenfactory_mut()
+            .set_admin(denom, admin);
+    }
+
+    /// balancer pool of the gamm module, including the changes made by simulated swaps
+    #[cfg(feature = "osmosis")]
+    pub fn gamm_pool(
+        &self,
+        pool_id: u64,
+    ) -> Result<rpc_items::osmosis::gamm::v1beta1::Pool, Error> {
+        let mut states = self.states.write().unwrap();
+        Ok(states.gamm_pool(pool_id)?.clone())
+    }
+
+    /// replace the state of a balancer pool
+    #[cfg(feature = "osmosis")]
+    pub fn cheat_gamm_pool(&mut self, pool: rpc_items::osmosis::gamm::v1beta1::Pool) {
+        self.states.write().unwrap().gamm_mut().set_pool(pool);
+    }
+
+    /// metadata of denom, fails if none is registered
+    pub fn denom_metadata(&self, denom: &str) -> Result<DenomMetadata, Error> {
+        self.states.write().unwrap().get_denom_metadata(denom)
+    }
+
+    /// metadata of all registered denoms, ordered by base denom
+    pub fn all_denom_metadata(&self) -> Result<Vec<DenomMetadata>, Error> {
+        self.states.write().unwrap().get_all_denom_metadata()
+    }
+
+    /// register metadata under its base denom, replacing the one on chain if any
+    pub fn cheat_denom_metadata(&mut self, metadata: DenomMetadata) {
+        self.states.write().unwrap().set_denom_metadata(metadata);
+    }
+
+    /// modify code
+    pub fn cheat_code(&mut self, contract_addr: &Addr, new_code: &[u8]) -> Result<(), Error> {
+        check_capabilities(&maybe_unzip(new_code.to_vec())?, &self.capabilities())?;
+        self.fetch_contract_state(contract_addr)?;
+        self.coverage_info.forget_layout(contract_addr);
+
+        let old_contract_state = self
+            .states
+            .read()
+            .unwrap()
+            .contract_state_get(contract_addr)
+            .unwrap()
+            .clone();
+        let mut new_contract_state = old_contract_state.clone();
+        new_contract_state.code = Arc::new(new_code.to_vec());
+        self.states
+            .write()
+            .unwrap()
+            .contract_state_insert(contract_addr.clone(), new_contract_state);
+        // try creating an instance to check if provided wasm is valid
+        self.create_instance(contract_addr).map_err(|e| {
+            self.states
+                .write()
+                .unwrap()
+                .contract_state_insert(contract_addr.clone(), old_contract_state);
+            e
+        })?;
+        self.code_overrides
+            .insert(contract_addr.clone(), code_checksum(new_code));
+        Ok(())
+    }
+
+    /// sets the code of a contract to the wasm file at path, like cheat_code, and sets it again
+    /// whenever the file changes, e.g. after cargo build. The file is checked before every
+    /// transaction and query, code which fails to load is logged and the previous code kept
+    pub fn watch_and_reload(&mut self, contract_addr: &Addr, path: &Path) -> Result<(), Error> {
+        if !path.is_file() {
+            return Err(Error::invalid_argument(format!(
+                "{} is not a file",
+                path.display()
+            )));
+        }
+        self.watcher.watch(contract_addr, path);
+        self.reload_watched().map(|_| ()).map_err(|e| {
+            self.watcher.unwatch(contract_addr);
+            e
+        })
+    }
+
+    /// stops reloading the code of a contract, the code loaded last is kept
+    pub fn unwatch(&mut self, contract_addr: &Addr) -> bool {
+        self.watcher.unwatch(contract_addr)
+    }
+
+    /// the contracts watched by watch_and_reload, with their wasm files
+    pub fn watched(&self) -> Vec<(Addr, PathBuf)> {
+        self.watcher.watched()
+    }
+
+    /// reloads the code of the watched contracts whose file changed, returns the contracts reloaded.
+    /// A file which fails to load is not tried again until it changes, the other files are still reloaded
+    pub fn reload_watched(&mut self) -> Result<Vec<Addr>, Error> {
+        let mut reloaded = Vec::new();
+        let mut first_error = None;
+        for (contract_addr, code) in self.watcher.changed()? {
+            match self.cheat_code(&contract_addr, &code) {
+                Ok(()) => {
+                    info!(contract = %contract_addr, size = code.len(), "reloaded code");
+                    reloaded.push(contract_addr);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(reloaded),
+        }
+    }
+
+    // calls keep running with the previous code while an artifact does not load
+    fn reload_watched_or_warn(&mut self) {
+        if self.watcher.is_empty() {
+            return;
+        }
+        if let Err(e) = self.reload_watched() {
+            warn!(error = %e, "failed to reload watched code");
+        }
+    }
+
+    /// modify the admin of a contract
+    pub fn cheat_admin(&mut self, contract_addr: &Addr, admin: Option<&Addr>) -> Result<(), Error> {
+        self.fetch_contract_state(contract_addr)?;
+        self.states
+            .write()
+            .unwrap()
+            .contract_state_get_mut(contract_addr)
+            .unwrap()
+            .info
+            .admin = admin.map(|a| a.to_string());
+        Ok(())
+    }
+
+    /// modify message sender
+    pub fn cheat_message_sender(&mut self, my_addr: &Addr) -> Result<(), Error> {
+        self.sender = my_addr.to_string();
+        Ok(())
+    }
+
+    fn record_storage_diff(&mut self, checkpoint: &Checkpoint) {
+        let storage_diff = self
+            .states
+            .read()
+            .unwrap()
+            .storage_changes(&checkpoint.states);
+        self.debug_log.lock().unwrap().storage_diff = storage_diff;
+    }
+
+    fn record_sender(&mut self, sender: &Addr) {
+        // the lowest label if several accounts share the address, like name_of
+        let label = self
+            .accounts
+            .iter()
+            .filter(|(_, addr)| *addr == sender)
+            .map(|(label, _)| label.as_str())
+            .min();
+        self.debug_log.lock().unwrap().set_sender(sender, label);
+    }
+
+    /// register an existing address (e.g. a victim on mainnet) under label, and fund it with the given balances
+    pub fn register_account(
+        &mut self,
+        label: &str,
+        address: &Addr,
+        funds: &[Coin],
+    ) -> Result<(), Error> {
+        for coin in funds.iter() {
+            self.cheat_bank_balance(address, &coin.denom, coin.amount.u128())?;
+        }
+        self.accounts.insert(label.to_string(), address.clone());
+        Ok(())
+    }
+
+    /// register a fresh account whose address is derived from label, and fund it with the given balances
+    pub fn create_account(&mut self, label: &str, funds: &[Coin]) -> Result<Addr, Error> {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("account_{}", label));
+        let bytes = hasher.finalize();
+        // externally owned accounts have 20 byte addresses
+        let addr = canonical_to_human(
+            &bytes.as_slice()[0..20],
+            &self.states.read().unwrap().bech32_prefix,
+            self.states.read().unwrap().canonical_address_length,
+        )
+        .map_err(|e| Error::format_error(&e))?;
+        let address = Addr::unchecked(addr);
+        self.register_account(label, &address, funds)?;
+        Ok(address)
+    }
+
+    /// register an account holding the key of algo derived from label, and fund it with the given
+    /// balances. The key signs payloads for contracts verifying signatures, its address is
+    /// derived from its public key as the cosmos sdk does
+    pub fn create_key_account(
+        &mut self,
+        label: &str,
+        algo: KeyAlgo,
+        funds: &[Coin],
+    ) -> Result<CheatKey, Error> {
+        let key = CheatKey::from_label(algo, label);
+        let address = key.address(&self.states.read().unwrap().bech32_prefix)?;
+        self.register_account(label, &address, funds)?;
+        Ok(key)
+    }
+
+    pub fn account(&self, label: &str) -> Option<Addr> {
+        self.accounts.get(label).cloned()
+    }
+
+    /// makes contracts accept addresses with prefix besides the ones of the chain, e.g. the
+    /// addresses of an ibc counterparty. Their canonical addresses are at most canonical_length long
+    pub fn add_bech32_prefix(
+        &mut self,
+        prefix: &str,
+        canonical_length: usize,
+    ) -> Result<(), Error> {
+        let mut states = self.states.write().unwrap();
+        states.api()?.with_prefix(prefix, canonical_length)?;
+        states.extra_bech32_prefixes.retain(|(p, _)| p != prefix);
+        states
+            .extra_bech32_prefixes
+            .push((prefix.to_string(), canonical_length));
+        Ok(())
+    }
+
+    /// sets the longest canonical address of the chain, 32 bytes by default
+    pub fn set_canonical_address_length(&mut self, canonical_length: usize) -> Result<(), Error> {
+        let mut states = self.states.write().unwrap();
+        RpcMockApi::new(canonical_length, &states.bech32_prefix)?;
+        states.canonical_address_length = canonical_length;
+        Ok(())
+    }
+
+    /// the canonical address of human as contracts see it, see RpcMockApi for other prefixes
+    pub fn human_to_canonical(&self, human: &str) -> Result<Vec<u8>, Error> {
+        let api = self.states.read().unwrap().api()?;
+        api.canonical_address(human)
+            .0
+            .map_err(|e| Error::invalid_argument(e.to_string()))
+    }
+
+    /// the human address of canonical as contracts see it
+    pub fn canonical_to_human(&self, canonical: &[u8]) -> Result<Addr, Error> {
+        let api = self.states.read().unwrap().api()?;
+        api.human_address(canonical)
+            .0
+            .map(Addr::unchecked)
+            .map_err(|e| Error::invalid_argument(e.to_string()))
+    }
+
+    /// names address, e.g. a contract as astroport_pair. Calls to it are labeled with the name in
+    /// call traces, and Model::lookup resolves the name
+    pub fn set_alias(&mut self, name: &str, address: &Addr) {
+        self.labels.set_alias(name, address);
+    }
+
+    pub fn remove_alias(&mut self, name: &str) -> Option<Addr> {
+        self.labels.remove_alias(name)
+    }
+
+    pub fn labels(&self) -> &LabelRegistry {
+        &self.labels
+    }
+
+    /// the address named label: an alias, an account, or the label a loaded contract was
+    /// instantiated with, in this order
+    pub fn lookup(&self, label: &str) -> Option<Addr> {
+        if let Some(address) = self.labels.address(label) {
+            return Some(address.clone());
+        }
+        if let Some(address) = self.account(label) {
+            return Some(address);
+        }
+        self.states.read().unwrap().contract_by_label(label)
+    }
+
+    /// the name of address shown in call traces: an alias, an account label,
+    /// or the label of a loaded contract, in this order
+    pub fn name_of(&self, address: &Addr) -> Option<String> {
+        if let Some(alias) = self.labels.alias_of(address) {
+            return Some(alias.to_string());
+        }
+        let account = self
+            .accounts
+            .iter()
+            .filter(|(_, a)| *a == address)
+            .map(|(label, _)| label)
+            .min();
+        if let Some(label) = account {
+            return Some(label.clone());
+        }
+        let states = self.states.read().unwrap();
+        match states.contract_state_get(address) {
+            Some(state) if !state.info.label.is_empty() => Some(state.info.label.clone()),
+            _ => None,
+        }
+    }
+
+    /// names the calls of debug_log to contracts with a name
+    fn name_calls(&self, debug_log: &mut DebugLog) {
+        let mut addresses = debug_log.call_trace.called_addresses();
+        addresses.extend(debug_log.contract_address.clone());
+        let names = addresses
+            .into_iter()
+            .filter_map(|address| self.name_of(&address).map(|name| (address, name)))
+            .collect();
+        debug_log.set_names(names);
+    }
+
+    /// use the account registered under label as the sender of top-level messages
+    pub fn switch_account(&mut self, label: &str) -> Result<(), Error> {
+        let address = self
+            .account(label)
+            .ok_or_else(|| Error::invalid_argument(format!("unknown account: {}", label)))?;
+        self.cheat_message_sender(&address)
+    }
+
+    /// modify storage of a contract
+    pub fn cheat_storage(
+        &mut self,
+        contract_addr: &Addr,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let mut states = self.states.write().unwrap();
+        let contract_storage = states.contract_state_get_mut(contract_addr).unwrap();
+        // the value on chain is needed for storage diffs, and must not overwrite this one later
+        contract_storage.fetch_key(key)?;
+        Arc::make_mut(&mut *contract_storage.storage.write().unwrap())
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+}
+
+fn typed_msg<M: Serialize>(msg: &M) -> Result<Binary, Error> {
+    to_binary(msg).map_err(|e| {
+        Error::format_error(format!(
+            "cannot serialize {}: {}",
+            std::any::type_name::<M>(),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+
+    use cosmwasm_std::{
+        from_binary, to_binary, Addr, BalanceResponse, BankQuery, Binary, Coin, Order, Uint128,
+    };
+    use serde_json::json;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use crate::{
+        code_checksum,
+        fork::api::canonical_to_human,
+        fork::debug_log::DebugLogEntry,
+        fork::model::{maybe_unzip, Model, BASE_EOA, STORED_CODE_ID_START},
+        BlockConfig, ClosureProgress, ContractFixture, ContractSchema, KeyAlgo, MsgKind,
+        RecursionConfig, RecursionPolicy,
+    };
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+    const FACTORY_ADDRESS_MALAGA: &str =
+        "wasm1hczjykytm4suw4586j5v42qft60gc4j307gf7cxuazfg7jxt4h4sjvp7rx";
+    const PAIR_ADDRESS_MALAGA: &str =
+        "wasm15le5evw4regnwf9lrjnpakr2075fcyp4n4yzpelvqcuevzkw2lss46hslz";
+    const TOKEN_ADDRESS_MALAGA: &str =
+        "wasm124v54ngky9wxhx87t252x4xfgujmdsu7uhjdugtkkqt39nld0e6st7e64h";
+    const VAULT_ADDRESS: &str = "wasm1fedmcgtsvmymyr6jssgar0h7uhhcuxhr7ygjjw5q2epgzef3jy0svcr5jx";
+    const VAULT_ROUTER_ADDRESS: &str =
+        "wasm1xp8prmlsx9erdkrk43qjtrw54755zwm9f4x52m8k3an6jgcaldpqpmsd23";
+
+    #[test]
+    fn test_swap_basic_testnet() {
+        use serde_json::Value::Null;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        let token_address = Addr::unchecked(TOKEN_ADDRESS_MALAGA);
+        let my_address = model.sender.clone();
+
+        let swap_msg_json = json!({
+            "swap": {
+            "offer_asset": {
+                "info": { "native_token": { "denom": "umlg" } },
+                "amount": "10"
+            },
+            "belief_price": Null,
+            "max_spread": Null,
+            "to": Null
+            }
+        });
+        let swap_msg = serde_json::to_string(&swap_msg_json).unwrap();
+        let funds = vec![Coin {
+            denom: "umlg".to_string(),
+            amount: Uint128::new(10),
+        }];
+
+        // balance before the swap
+        let query_balance_msg_json = json!({
+            "balance": {"address": my_address, }
+        });
+        let query_balance_msg = serde_json::to_string(&query_balance_msg_json).unwrap();
+        let resp = model
+            .wasm_query(&token_address, query_balance_msg.as_bytes())
+            .unwrap();
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.as_slice()).unwrap();
+        let token_balance_before = u128::from_str(resp_json["balance"].as_str().unwrap()).unwrap();
+        let bank_query = BankQuery::Balance {
+            address: my_address,
+            denom: "umlg".to_string(),
+        };
+        let resp = model
+            .bank_query(to_binary(&bank_query).unwrap().as_slice())
+            .unwrap();
+        let resp_bank: BalanceResponse = serde_json::from_slice(resp.as_slice()).unwrap();
+        let umlg_balance_before: u128 = resp_bank.amount.amount.into();
+        let prev_block_num = model.states.read().unwrap().block_number;
+
+        // execute the swap transaction
+        let _ = model
+            .execute(&pair_address, swap_msg.as_bytes(), &funds)
+            .unwrap();
+
+        // check the results
+        // block number incremented
+        assert_eq!(
+            model.states.read().unwrap().block_number,
+            prev_block_num + 1
+        );
+
+        let resp = model
+            .wasm_query(&token_address, query_balance_msg.as_bytes())
+            .unwrap();
+        let resp_json: serde_json::Value = serde_json::from_slice(resp.as_slice()).unwrap();
+        let token_balance_after = u128::from_str(resp_json["balance"].as_str().unwrap()).unwrap();
+        let resp = model
+            .bank_query(to_binary(&bank_query).unwrap().as_slice())
+            .unwrap();
+        let resp_bank: BalanceResponse = serde_json::from_slice(resp.as_slice()).unwrap();
+        let umlg_balance_after: u128 = resp_bank.amount.amount.into();
+
+        // token and umlg balance as expected
+        assert_eq!(token_balance_after - token_balance_before, 9);
+        assert_eq!(umlg_balance_before - umlg_balance_after, 10);
+    }
+
+    #[test]
+    fn test_flashloan() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let _vault_address = Addr::unchecked(VAULT_ADDRESS);
+        let vault_router_address = Addr::unchecked(VAULT_ROUTER_ADDRESS);
+        let _my_address = model.sender.clone();
+
+        let loan_msg_json = json!({
+            "flash_loan": {
+                "assets": [{
+                    "info": { "native_token": { "denom": "umlg" } },
+                    "amount": "10"
+                }],
+                "msgs": [],
+            }
+        });
+        let loan_msg = serde_json::to_string(&loan_msg_json).unwrap();
+        let prev_block_num = model.states.read().unwrap().block_number;
+        // execute the swap transaction
+        let log = model
+            .execute(&vault_router_address, loan_msg.as_bytes(), &[])
+            .unwrap();
+
+        assert_eq!(
+            model.states.read().unwrap().block_number,
+            prev_block_num + 1
+        );
+        assert_eq!(log.err_msg, None);
+    }
+
+    #[test]
+    fn test_block_config() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+
+        model.set_block_config(BlockConfig {
+            seconds_per_block: 6,
+            blocks_per_execute: 2,
+        });
+        let prev_block_num = model.states.read().unwrap().block_number;
+        let prev_timestamp = model.states.read().unwrap().block_timestamp;
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert_eq!(
+            model.states.read().unwrap().block_number,
+            prev_block_num + 2
+        );
+        assert_eq!(
+            model.states.read().unwrap().block_timestamp,
+            prev_timestamp.plus_seconds(12)
+        );
+
+        // in manual mode, executions do not produce blocks
+        model.set_block_config(BlockConfig::manual(5));
+        let prev_block_num = model.states.read().unwrap().block_number;
+        let prev_timestamp = model.states.read().unwrap().block_timestamp;
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert_eq!(model.states.read().unwrap().block_number, prev_block_num);
+        model.produce_block(10);
+        assert_eq!(
+            model.states.read().unwrap().block_number,
+            prev_block_num + 10
+        );
+        assert_eq!(
+            model.states.read().unwrap().block_timestamp,
+            prev_timestamp.plus_seconds(50)
+        );
+
+        model.cheat_advance_blocks(3).unwrap();
+        assert_eq!(
+            model.states.read().unwrap().block_number,
+            prev_block_num + 13
+        );
+        model
+            .cheat_advance_time(Duration::from_secs(7 * 24 * 3600))
+            .unwrap();
+        assert_eq!(
+            model.states.read().unwrap().block_number,
+            prev_block_num + 13
+        );
+        assert_eq!(
+            model.states.read().unwrap().block_timestamp,
+            prev_timestamp.plus_seconds(65 + 7 * 24 * 3600)
+        );
+        assert!(model.cheat_advance_blocks(u64::MAX).is_err());
+        assert!(model.cheat_advance_time(Duration::MAX).is_err());
+    }
+
+    #[test]
+    fn test_execute_as() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        // an address without any balance
+        let attacker = Addr::unchecked("wasm1k6n9ew2kc5g29zd8n4gqp0gnxncuz8ls4cgrcm");
+        let swap_msg_json = json!({
+            "swap": {
+                "offer_asset": {
+                    "info": { "native_token": { "denom": "umlg" } },
+                    "amount": "10"
+                },
+            }
+        });
+        let swap_msg = serde_json::to_string(&swap_msg_json).unwrap();
+        let funds = vec![Coin {
+            denom: "umlg".to_string(),
+            amount: Uint128::new(10),
+        }];
+
+        let log = model
+            .execute_as(&attacker, &pair_address, swap_msg.as_bytes(), &funds)
+            .unwrap();
+        assert!(log.err_msg.is_some());
+
+        let log = model
+            .execute_as_without_transfer(&attacker, &pair_address, swap_msg.as_bytes(), &funds)
+            .unwrap();
+        assert_eq!(log.err_msg, None);
+        // the global sender is untouched
+        assert_eq!(model.sender(), Addr::unchecked(BASE_EOA));
+    }
+
+    #[test]
+    fn test_sender_faucet() {
+        let balance = |model: &Model, address: &Addr| {
+            let mut states = model.states.write().unwrap();
+            states.get_balance(address, "umlg").unwrap().u128()
+        };
+        let alice = Addr::unchecked("wasm1alice");
+        let model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm")
+            .unwrap()
+            .with_faucet(&[Coin::new(1000, "umlg")])
+            .unwrap();
+        assert_eq!(balance(&model, &model.sender()), 1000);
+        let model = model.with_sender(&alice).unwrap();
+        assert_eq!(model.sender(), alice);
+        assert_eq!(balance(&model, &alice), 1000);
+
+        // the default sender has the prefix of the chain
+        let model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "juno").unwrap();
+        assert!(model.sender().as_str().starts_with("juno1"));
+    }
+
+    #[test]
+    fn test_bech32_prefix_detection() {
+        let model = Model::new_auto(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER)).unwrap();
+        assert_eq!(model.states.read().unwrap().bech32_prefix, "wasm");
+
+        // a misconfigured prefix fails when the first contract is fetched, naming the prefix
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "juno").unwrap();
+        let err = model
+            .wasm_query(&Addr::unchecked(PAIR_ADDRESS_MALAGA), br#"{"pair":{}}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("has bech32 prefix wasm"));
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.set_canonical_address_length(20).unwrap();
+        let err = model
+            .wasm_query(&Addr::unchecked(PAIR_ADDRESS_MALAGA), br#"{"pair":{}}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("32 byte canonical address"));
+    }
+
+    #[test]
+    fn test_contract_fixture() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        let fixture = model.export_contract(&pair_address).unwrap();
+        assert_eq!(
+            fixture.checksum,
+            hex::encode(model.contract_checksum(&pair_address).unwrap())
+        );
+        assert!(!fixture.storage.is_empty());
+        let fixture = ContractFixture::from_json(&fixture.to_json().unwrap()).unwrap();
+
+        // the imported copy answers queries from the fixture, using the code of the pair
+        let copy_address =
+            Addr::unchecked("wasm1qqqsyqcyq5rqwzqfpg9scrgwpugpzysnzs23v9ccrydpk8qarc0sutya6q");
+        model.import_contract(&fixture, &copy_address).unwrap();
+        let query = br#"{"pair":{}}"#;
+        assert_eq!(
+            model.wasm_query(&copy_address, query).unwrap(),
+            model.wasm_query(&pair_address, query).unwrap()
+        );
+        assert_eq!(
+            model.export_contract(&copy_address).unwrap().storage,
+            fixture.storage
+        );
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let err = model.import_contract(&fixture, &copy_address).unwrap_err();
+        assert!(err.to_string().contains("is unknown"));
+    }
+
+    #[test]
+    fn test_accounts() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        let funds = vec![Coin {
+            denom: "umlg".to_string(),
+            amount: Uint128::new(1000),
+        }];
+        let attacker = model.create_account("attacker", &funds).unwrap();
+        let victim = model.create_account("victim", &[]).unwrap();
+        assert_ne!(attacker, victim);
+        assert_eq!(model.account("attacker"), Some(attacker.clone()));
+        assert!(model.switch_account("admin").is_err());
+
+        model.switch_account("attacker").unwrap();
+        assert_eq!(model.sender(), attacker);
+        let swap_msg_json = json!({
+            "swap": {
+                "offer_asset": {
+                    "info": { "native_token": { "denom": "umlg" } },
+                    "amount": "10"
+                },
+            }
+        });
+        let swap_msg = serde_json::to_string(&swap_msg_json).unwrap();
+        let log = model
+            .execute(
+                &pair_address,
+                swap_msg.as_bytes(),
+                &[Coin {
+                    denom: "umlg".to_string(),
+                    amount: Uint128::new(10),
+                }],
+            )
+            .unwrap();
+        assert_eq!(log.err_msg, None);
+        assert_eq!(log.sender, Some(attacker));
+        assert_eq!(log.sender_label, Some("attacker".to_string()));
+    }
+
+    #[test]
+    fn test_all_balances_order() {
+        use cosmwasm_std::AllBalanceResponse;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let denoms = [
+            "uusd",
+            "ibc/27394FB092D2ECCD",
+            "umlg",
+            "factory/wasm1x/a",
+            "uatom",
+        ];
+        let funds: Vec<Coin> = denoms.iter().map(|d| Coin::new(1, *d)).collect();
+        let alice = model.create_account("alice", &funds).unwrap();
+        let msg = to_binary(&BankQuery::AllBalances {
+            address: alice.to_string(),
+        })
+        .unwrap();
+        let res: AllBalanceResponse =
+            from_binary(&model.bank_query(msg.as_slice()).unwrap()).unwrap();
+        let mut sorted = denoms.to_vec();
+        sorted.sort_unstable();
+        let got: Vec<&str> = res.amount.iter().map(|c| c.denom.as_str()).collect();
+        assert_eq!(got, sorted);
+    }
+
+    #[test]
+    fn test_bank_supply() {
+        use crate::rpc_items::cosmos::bank::v1beta1::{Input, MsgMultiSend, Output};
+        use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+        use crate::rpc_items::osmosis::tokenfactory::v1beta1::MsgMint;
+        use prost::Message;
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let denom = "umlg";
+        let supply = model.supply(denom).unwrap();
+        assert!(!supply.is_zero());
+        let alice = model
+            .create_account("alice", &[Coin::new(1000, denom)])
+            .unwrap();
+        let bob = model.create_account("bob", &[]).unwrap();
+        let carol = model.create_account("carol", &[]).unwrap();
+        assert_eq!(model.supply(denom).unwrap(), supply);
+
+        model.cheat_mint(&alice, &[Coin::new(500, denom)]).unwrap();
+        let supply = supply + Uint128::new(500);
+        let msg = to_binary(&json!({ "supply": { "denom": denom } })).unwrap();
+        let res: serde_json::Value =
+            from_binary(&model.bank_query(msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(res["amount"]["amount"], supply.to_string());
+
+        let proto_coin = |amount: u128| ProtoCoin {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        };
+        let multi_send = |total: u128| MsgMultiSend {
+            inputs: vec![Input {
+                address: alice.to_string(),
+                coins: vec![proto_coin(total)],
+            }],
+            outputs: vec![
+                Output {
+                    address: bob.to_string(),
+                    coins: vec![proto_coin(1000)],
+                },
+                Output {
+                    address: carol.to_string(),
+                    coins: vec![proto_coin(500)],
+                },
+            ],
+        };
+        let mut states = model.states.write().unwrap();
+        let msg = Binary::from(multi_send(1400).encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/cosmos.bank.v1beta1.MsgMultiSend", &msg)
+            .unwrap();
+        assert_eq!(res.unwrap_err(), "sum inputs != sum outputs");
+        let msg = Binary::from(multi_send(1500).encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/cosmos.bank.v1beta1.MsgMultiSend", &msg)
+            .unwrap();
+        assert!(res.is_ok());
+        assert!(states.get_balance(&alice, denom).unwrap().is_zero());
+        assert_eq!(states.get_balance(&bob, denom).unwrap().u128(), 1000);
+        assert_eq!(states.get_balance(&carol, denom).unwrap().u128(), 500);
+        assert_eq!(states.get_supply(denom).unwrap(), supply);
+
+        // only the creator of a tokenfactory denom may mint it
+        let factory_denom = format!("factory/{}/token", alice);
+        let mint = |denom: &str| MsgMint {
+            sender: alice.to_string(),
+            amount: Some(ProtoCoin {
+                denom: denom.to_string(),
+                amount: "42".to_string(),
+            }),
+            mint_to_address: bob.to_string(),
+        };
+        let msg = Binary::from(mint(&factory_denom).encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/osmosis.tokenfactory.v1beta1.MsgMint", &msg)
+            .unwrap();
+        assert!(res.is_ok());
+        assert_eq!(states.get_balance(&bob, &factory_denom).unwrap().u128(), 42);
+        assert_eq!(states.get_supply(&factory_denom).unwrap().u128(), 42);
+        let msg = Binary::from(mint(&format!("factory/{}/token", bob)).encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/osmosis.tokenfactory.v1beta1.MsgMint", &msg)
+            .unwrap();
+        assert!(res.unwrap_err().starts_with("unauthorized"));
+    }
+
+    #[test]
+    fn test_denom_metadata() {
+        use crate::rpc_items::cosmos::bank::v1beta1::{
+            QueryDenomMetadataRequest, QueryDenomMetadataResponse,
+        };
+        use crate::{DenomMetadata, DenomMetadataQuery, DenomUnit};
+        use prost::Message;
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let metadata = |base: &str, symbol: &str| DenomMetadata {
+            description: format!("{} token", symbol),
+            denom_units: vec![
+                DenomUnit {
+                    denom: base.to_string(),
+                    exponent: 0,
+                    aliases: vec![],
+                },
+                DenomUnit {
+                    denom: symbol.to_lowercase(),
+                    exponent: 6,
+                    aliases: vec![],
+                },
+            ],
+            base: base.to_string(),
+            display: symbol.to_lowercase(),
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        model.cheat_denom_metadata(metadata("ufoo", "FOO"));
+        model.cheat_denom_metadata(metadata("ubar", "BAR"));
+        assert_eq!(
+            model.denom_metadata("ufoo").unwrap(),
+            metadata("ufoo", "FOO")
+        );
+        let all = model.all_denom_metadata().unwrap();
+        assert!(all.contains(&metadata("ubar", "BAR")));
+
+        // cosmwasm 1.3 contracts query with BankQuery::DenomMetadata
+        let mut states = model.states.write().unwrap();
+        let query: DenomMetadataQuery =
+            serde_json::from_value(json!({ "denom_metadata": { "denom": "ubar" } })).unwrap();
+        let res: serde_json::Value =
+            from_binary(&states.denom_metadata_query(&query).unwrap()).unwrap();
+        assert_eq!(res["metadata"]["symbol"], "BAR");
+        assert_eq!(res["metadata"]["denom_units"][1]["exponent"], 6);
+        let query: DenomMetadataQuery = serde_json::from_value(json!({
+            "all_denom_metadata": { "pagination": { "key": null, "limit": 1, "reverse": false } }
+        }))
+        .unwrap();
+        let res: serde_json::Value =
+            from_binary(&states.denom_metadata_query(&query).unwrap()).unwrap();
+        assert_eq!(res["metadata"].as_array().unwrap().len(), 1);
+        assert!(!res["next_key"].is_null());
+
+        // and older ones with a stargate query
+        let request = QueryDenomMetadataRequest {
+            denom: "ufoo".to_string(),
+        };
+        let res = states
+            .stargate_query(
+                "/cosmos.bank.v1beta1.Query/DenomMetadata",
+                &Binary::from(request.encode_to_vec()),
+            )
+            .unwrap();
+        let res = QueryDenomMetadataResponse::decode(res.as_slice()).unwrap();
+        assert_eq!(res.metadata.unwrap().symbol, "FOO");
+        assert!(states.get_denom_metadata("unknown").is_err());
+    }
+
+    #[test]
+    fn test_tokenfactory() {
+        use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+        use crate::rpc_items::osmosis::tokenfactory::v1beta1::{
+            MsgChangeAdmin, MsgCreateDenom, MsgCreateDenomResponse, MsgMint,
+            QueryDenomsFromCreatorRequest, QueryDenomsFromCreatorResponse,
+        };
+        use prost::Message;
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let alice = model.create_account("alice", &[]).unwrap();
+        let bob = model.create_account("bob", &[]).unwrap();
+
+        let mut states = model.states.write().unwrap();
+        let create = MsgCreateDenom {
+            sender: alice.to_string(),
+            subdenom: "token".to_string(),
+        };
+        let msg = Binary::from(create.encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/osmosis.tokenfactory.v1beta1.MsgCreateDenom", &msg)
+            .unwrap()
+            .unwrap();
+        let data = MsgCreateDenomResponse::decode(res.data.unwrap().as_slice()).unwrap();
+        let denom = data.new_token_denom;
+        assert_eq!(denom, format!("factory/{}/token", alice));
+        assert_eq!(states.get_denom_metadata(&denom).unwrap().base, denom);
+        let res = states
+            .stargate_execute(&alice, "/osmosis.tokenfactory.v1beta1.MsgCreateDenom", &msg)
+            .unwrap();
+        assert!(res.is_err());
+
+        let mint = MsgMint {
+            sender: alice.to_string(),
+            amount: Some(ProtoCoin {
+                denom: denom.clone(),
+                amount: "100".to_string(),
+            }),
+            mint_to_address: alice.to_string(),
+        };
+        let msg = Binary::from(mint.encode_to_vec());
+        let change_admin = MsgChangeAdmin {
+            sender: alice.to_string(),
+            denom: denom.clone(),
+            new_admin: bob.to_string(),
+        };
+        let res = states
+            .stargate_execute(
+                &alice,
+                "/osmosis.tokenfactory.v1beta1.MsgChangeAdmin",
+                &Binary::from(change_admin.encode_to_vec()),
+            )
+            .unwrap();
+        assert!(res.is_ok());
+        // only the new admin may mint
+        let res = states
+            .stargate_execute(&alice, "/osmosis.tokenfactory.v1beta1.MsgMint", &msg)
+            .unwrap();
+        assert!(res.unwrap_err().starts_with("unauthorized"));
+        let res = states
+            .stargate_execute(&bob, "/osmosis.tokenfactory.v1beta1.MsgMint", &msg)
+            .unwrap();
+        assert!(res.is_ok());
+        assert_eq!(states.get_balance(&alice, &denom).unwrap().u128(), 100);
+
+        let request = QueryDenomsFromCreatorRequest {
+            creator: alice.to_string(),
+        };
+        let res = states
+            .stargate_query(
+                "/osmosis.tokenfactory.v1beta1.Query/DenomsFromCreator",
+                &Binary::from(request.encode_to_vec()),
+            )
+            .unwrap();
+        let res = QueryDenomsFromCreatorResponse::decode(res.as_slice()).unwrap();
+        assert_eq!(res.denoms, vec![denom.clone()]);
+        drop(states);
+
+        model.cheat_denom_admin(&denom, None);
+        assert_eq!(model.denom_admin(&denom), None);
+        assert_eq!(model.tokenfactory_denoms(&alice), vec![denom]);
+    }
+
+    #[test]
+    fn test_distribution_rewards() {
+        use crate::module_address;
+        use crate::rpc_items::cosmos::distribution::v1beta1::{
+            QueryDelegationRewardsRequest, QueryDelegationRewardsResponse,
+        };
+        use cosmwasm_std::DistributionMsg;
+        use prost::Message;
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let denom = "umlg";
+        let validator = "wasmvaloper1validator";
+        let vault = model.create_account("vault", &[]).unwrap();
+        let treasury = model.create_account("treasury", &[]).unwrap();
+        let module = module_address("distribution", "wasm");
+        model.cheat_bank_balance(&module, denom, 1000).unwrap();
+        model.cheat_delegation_rewards(&vault, validator, &[Coin::new(300, denom)]);
+        assert_eq!(model.withdraw_address(&vault).unwrap(), vault);
+
+        let mut states = model.states.write().unwrap();
+        let request = QueryDelegationRewardsRequest {
+            delegator_address: vault.to_string(),
+            validator_address: validator.to_string(),
+        };
+        let res = states
+            .stargate_query(
+                "/cosmos.distribution.v1beta1.Query/DelegationRewards",
+                &Binary::from(request.encode_to_vec()),
+            )
+            .unwrap();
+        let res = QueryDelegationRewardsResponse::decode(res.as_slice()).unwrap();
+        assert_eq!(res.rewards[0].amount, format!("300{}", "0".repeat(18)));
+
+        let msg = DistributionMsg::SetWithdrawAddress {
+            address: treasury.to_string(),
+        };
+        assert!(states.distribution_execute(&vault, &msg).unwrap().is_ok());
+        let msg = DistributionMsg::WithdrawDelegatorReward {
+            validator: validator.to_string(),
+        };
+        assert!(states.distribution_execute(&vault, &msg).unwrap().is_ok());
+        assert_eq!(states.get_balance(&treasury, denom).unwrap().u128(), 300);
+        assert_eq!(states.get_balance(&module, denom).unwrap().u128(), 700);
+        assert!(states
+            .get_delegation_rewards(&vault, validator)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_gov_proposal() {
+        use crate::rpc_items::cosmos::bank::v1beta1::MsgSend;
+        use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+        use crate::rpc_items::cosmos::gov::v1::{MsgSubmitProposal, MsgSubmitProposalResponse};
+        use crate::{module_address, ProposalStatus};
+        use cosmwasm_std::{GovMsg, VoteOption};
+        use prost::Message;
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let denom = "umlg";
+        let dao = model
+            .create_account("dao", &[Coin::new(100, denom)])
+            .unwrap();
+        let treasury = model.create_account("treasury", &[]).unwrap();
+        let gov = module_address("gov", "wasm");
+        model.cheat_bank_balance(&gov, denom, 500).unwrap();
+        let balance = |model: &Model, address: &Addr| {
+            let mut states = model.states.write().unwrap();
+            states.get_balance(address, denom).unwrap().u128()
+        };
+
+        // the community pool pays the treasury once the proposal passes
+        let send = MsgSend {
+            from_address: gov.to_string(),
+            to_address: treasury.to_string(),
+            amount: vec![ProtoCoin {
+                denom: denom.to_string(),
+                amount: "200".to_string(),
+            }],
+        };
+        let msg = MsgSubmitProposal {
+            messages: vec![prost_types::Any {
+                type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(),
+                value: send.encode_to_vec(),
+            }],
+            initial_deposit: vec![ProtoCoin {
+                denom: denom.to_string(),
+                amount: "100".to_string(),
+            }],
+            proposer: dao.to_string(),
+            metadata: String::new(),
+        };
+        let proposal_id = {
+            let mut states = model.states.write().unwrap();
+            let res = states
+                .stargate_execute(
+                    &dao,
+                    "/cosmos.gov.v1.MsgSubmitProposal",
+                    &Binary::from(msg.encode_to_vec()),
+                )
+                .unwrap()
+                .unwrap();
+            let data = MsgSubmitProposalResponse::decode(res.data.unwrap().as_slice()).unwrap();
+            let vote = GovMsg::Vote {
+                proposal_id: data.proposal_id,
+                vote: VoteOption::Yes,
+            };
+            assert!(states.gov_execute(&dao, &vote).unwrap().is_ok());
+            assert_eq!(
+                states.proposal_votes(data.proposal_id),
+                vec![(dao.clone(), VoteOption::Yes)]
+            );
+            data.proposal_id
+        };
+        assert_eq!(balance(&model, &dao), 0);
+        assert_eq!(
+            model.proposal(proposal_id).unwrap().status,
+            ProposalStatus::VotingPeriod
+        );
+
+        let debug_log = model.cheat_pass_proposal(proposal_id).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(
+            model.proposal(proposal_id).unwrap().status,
+            ProposalStatus::Passed
+        );
+        assert_eq!(balance(&model, &treasury), 200);
+        // the deposit is refunded
+        assert_eq!(balance(&model, &dao), 100);
+        assert_eq!(balance(&model, &gov), 300);
+        assert!(model.cheat_pass_proposal(proposal_id).is_err());
+    }
+    #[cfg(feature = "osmosis")]
+    #[test]
+    fn test_gamm_swap() {
+        use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+        use crate::rpc_items::osmosis::gamm::v1beta1::{
+            MsgSwapExactAmountIn, MsgSwapExactAmountInResponse, Pool, PoolAsset, PoolParams,
+            QueryPoolRequest, QueryPoolResponse, SwapAmountInRoute,
+        };
+        use prost::Message;
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pool_address = model
+            .create_account(
+                "pool",
+                &[Coin::new(1_000_000, "uatom"), Coin::new(2_000_000, "uosmo")],
+            )
+            .unwrap();
+        let alice = model
+            .create_account("alice", &[Coin::new(1000, "uatom")])
+            .unwrap();
+        let asset = |denom: &str, amount: u128| PoolAsset {
+            token: Some(ProtoCoin {
+                denom: denom.to_string(),
+                amount: amount.to_string(),
+            }),
+            weight: "1".to_string(),
+        };
+        model.cheat_gamm_pool(Pool {
+            address: pool_address.to_string(),
+            id: 1,
+            pool_params: Some(PoolParams {
+                swap_fee: "2000000000000000".to_string(),
+                exit_fee: "0".to_string(),
+            }),
+            pool_assets: vec![asset("uatom", 1_000_000), asset("uosmo", 2_000_000)],
+            ..Default::default()
+        });
+
+        let swap = |min: u128| MsgSwapExactAmountIn {
+            sender: alice.to_string(),
+            routes: vec![SwapAmountInRoute {
+                pool_id: 1,
+                token_out_denom: "uosmo".to_string(),
+            }],
+            token_in: Some(ProtoCoin {
+                denom: "uatom".to_string(),
+                amount: "1000".to_string(),
+            }),
+            token_out_min_amount: min.to_string(),
+        };
+        let mut states = model.states.write().unwrap();
+        let msg = Binary::from(swap(2000).encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn", &msg)
+            .unwrap();
+        assert!(res.unwrap_err().contains("lesser than min amount"));
+        let msg = Binary::from(swap(1900).encode_to_vec());
+        let res = states
+            .stargate_execute(&alice, "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn", &msg)
+            .unwrap()
+            .unwrap();
+        let data = MsgSwapExactAmountInResponse::decode(res.data.unwrap().as_slice()).unwrap();
+        assert_eq!(data.token_out_amount, "1994");
+        assert_eq!(states.get_balance(&alice, "uosmo").unwrap().u128(), 1994);
+        assert_eq!(
+            states.get_balance(&pool_address, "uatom").unwrap().u128(),
+            1_001_000
+        );
+
+        // the swapped pool is returned to contracts
+        let request = QueryPoolRequest { pool_id: 1 };
+        let res = states
+            .stargate_query(
+                "/osmosis.gamm.v1beta1.Query/Pool",
+                &Binary::from(request.encode_to_vec()),
+            )
+            .unwrap();
+        let pool = QueryPoolResponse::decode(res.as_slice())
+            .unwrap()
+            .pool
+            .unwrap();
+        let pool = Pool::decode(pool.value.as_slice()).unwrap();
+        assert_eq!(
+            pool.pool_assets[1].token.as_ref().unwrap().amount,
+            (2_000_000 - 1994).to_string()
+        );
+    }
+    #[test]
+    fn test_storage_write() {
+        use test_contract::msg::ExecuteMsg;
+        // test if querier can view writes to the current contract
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        for log in res.logs {
+            for event in log.events {
+                if event.ty == "read_number" {
+                    assert_eq!(event.attributes[0].value.as_str(), "2");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_storage_diff() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        // the number is written twice, but only the final value is part of the diff
+        let changes = &res.storage_diff[&pair_address];
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].key.as_slice(), b"number");
+        assert_eq!(changes[0].old, None);
+        assert_eq!(changes[0].new.as_ref().unwrap().as_slice(), b"1");
+
+        // failed calls do not change storage
+        let msg = to_binary(&ExecuteMsg::TestAtomic {}).unwrap();
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(res.storage_diff.is_empty());
+    }
+
+    #[test]
+    fn test_failed_transaction_rollback() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let handle = model.query_handle();
+
+        // the writes of the failed transaction are undone in place
+        let msg = to_binary(&ExecuteMsg::TestAtomic {}).unwrap();
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(res.err_msg.is_some());
+        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
+        let res: ReadNumberResponse =
+            from_binary(&handle.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(res.value, 1);
+
+        // the next transaction writes 2 then 1 again, which changes nothing
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(res.storage_diff.is_empty());
+    }
+
+    #[test]
+    fn test_storage_trace() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(res.storage_trace().is_empty());
+
+        model.enable_storage_trace();
+        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let trace = res.storage_trace();
+        assert_eq!(trace.len(), 2);
+        assert!(trace.iter().all(|w| w.contract == pair_address));
+        assert!(trace.iter().all(|w| w.key.as_slice() == b"number"));
+        assert_eq!(trace[0].old.as_ref().unwrap().as_slice(), b"1");
+        assert_eq!(trace[0].new.as_ref().unwrap().as_slice(), b"2");
+        assert_eq!(trace[1].new.as_ref().unwrap().as_slice(), b"1");
+        // both writes happen in the top-level execute
+        assert_eq!(trace[0].call_id, 1);
+
+        // the self query is a separate call frame, which only reads
+        let access_sets = res.access_sets();
+        assert_eq!(access_sets.len(), 2);
+        assert_eq!(access_sets[0].call_id, 1);
+        assert!(access_sets[0].writes.contains(&Binary::from(b"number")));
+        assert_eq!(access_sets[1].call_id, 2);
+        assert!(access_sets[1].reads.contains(&Binary::from(b"number")));
+        assert!(access_sets[1].writes.is_empty());
+        assert!(res.conflicts_with(&res));
+    }
+
+    #[test]
+    fn test_atomicity() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
+        // test if querier can view writes to the current contract
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+
+        // set NUMBER to 2
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let _ = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+
+        // query value of NUMBER
+        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
+        let query_res1: ReadNumberResponse =
+            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+
+        // run failing execute()
+        let msg = to_binary(&ExecuteMsg::TestAtomic {}).unwrap();
+        let _ = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+
+        // query value of NUMBER again, it should be same as previous value
+        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
+        let query_res2: ReadNumberResponse =
+            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(query_res1.value, query_res2.value);
+    }
+
+    #[test]
+    fn test_reentrancy() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+
+        // the contract executing itself through a submessage is only reported by default
+        let msg = to_binary(&ExecuteMsg::TestReenter {}).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.warnings.len(), 1);
+        assert!(debug_log.warnings[0].contains(PAIR_ADDRESS_MALAGA));
+
+        // non-reentrant calls are not reported
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.warnings.is_empty());
+
+        model.set_recursion_config(RecursionConfig {
+            policy: RecursionPolicy::Abort,
+            ..RecursionConfig::default()
+        });
+        let msg = to_binary(&ExecuteMsg::TestReenter {}).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.unwrap().starts_with("reentrant call"));
+    }
+
+    #[test]
+    fn test_reply_data() {
+        use crate::rpc_items::cosmwasm::wasm::v1::MsgExecuteContractResponse;
+        use prost::Message;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+
+        let data = Binary::from(b"minted denom");
+        // built as json, the test contract depends on another version of cosmwasm-std
+        let msg = to_binary(&json!({ "set_data": { "data": data } })).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert_eq!(debug_log.data, Some(data.clone()));
+
+        // the reply sees the data of the submessage, and its own data overrides the data of the caller
+        let msg = to_binary(&json!({ "test_reply_data": { "data": data } })).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        let response =
+            MsgExecuteContractResponse::decode(debug_log.data.unwrap().as_slice()).unwrap();
+        assert_eq!(response.data, data.to_vec());
+    }
+
+    #[test]
+    fn test_middleware() {
+        use crate::rpc_items::cosmwasm::wasm::v1::MsgExecuteContractResponse;
+        use crate::{MsgAction, MsgRecorder};
+        use cosmwasm_std::{ContractResult, CosmosMsg, Response, WasmMsg};
+        use prost::Message;
+        use std::sync::Arc;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let recorder = Arc::new(MsgRecorder::new());
+        model.add_middleware(recorder.clone());
+
+        // the submessage is stubbed, and the reply sees the stubbed data
+        let stubbed = MsgExecuteContractResponse {
+            data: b"stubbed".to_vec(),
+        };
+        model.add_middleware(Arc::new(move |_: &Addr, msg: &CosmosMsg| match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { .. }) => Ok(MsgAction::Respond(ContractResult::Ok(
+                Response::new().set_data(stubbed.encode_to_vec()),
+            ))),
+            _ => Ok(MsgAction::Continue),
+        }));
+        let data = Binary::from(b"minted denom");
+        let msg = to_binary(&json!({ "test_reply_data": { "data": data } })).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        let response =
+            MsgExecuteContractResponse::decode(debug_log.data.unwrap().as_slice()).unwrap();
+        assert_eq!(response.data, b"stubbed".to_vec());
+        let messages = recorder.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, pair_address);
+
+        // without middlewares, the submessage is executed again
+        model.clear_middlewares();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let response =
+            MsgExecuteContractResponse::decode(debug_log.data.unwrap().as_slice()).unwrap();
+        assert_eq!(response.data, data.to_vec());
+        assert_eq!(recorder.messages().len(), 1);
+    }
+
+    #[test]
+    fn test_hooks() {
+        use crate::{CallKind, HookEvent, HookRecorder};
+        use std::sync::Arc;
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let alice = model
+            .create_account("alice", &[Coin::new(100, "umlg")])
+            .unwrap();
+        model.switch_account("alice").unwrap();
+        let recorder = Arc::new(HookRecorder::new());
+        model.set_hooks(recorder.clone());
+
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[Coin::new(10, "umlg")])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+        let events = recorder.events();
+        assert_eq!(
+            events[0],
+            HookEvent::CallStart {
+                kind: CallKind::Instantiate,
+                contract: contract.clone(),
+                sender: alice.clone(),
+            }
+        );
+        assert_eq!(
+            events[1],
+            HookEvent::BankTransfer {
+                from: alice.clone(),
+                to: contract.clone(),
+                amount: vec![Coin::new(10, "umlg")],
+            }
+        );
+        match &events[2] {
+            HookEvent::StorageWrite {
+                contract: c, value, ..
+            } => {
+                assert_eq!(c, &contract);
+                assert!(value.is_some());
+            }
+            e => panic!("expected a storage write, got {:?}", e),
+        }
+        assert_eq!(
+            events.last().unwrap(),
+            &HookEvent::CallEnd {
+                kind: CallKind::Instantiate,
+                contract: contract.clone(),
+                error: None,
+            }
+        );
+
+        // the submessage and the reply are seen within the top-level call
+        recorder.clear();
+        let msg =
+            to_binary(&json!({ "test_reply_data": { "data": Binary::from(b"data") } })).unwrap();
+        model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        let calls: Vec<(CallKind, bool)> = recorder
+            .events()
+            .into_iter()
+            .filter_map(|e| match e {
+                HookEvent::CallStart { kind, .. } => Some((kind, true)),
+                HookEvent::CallEnd { kind, .. } => Some((kind, false)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (CallKind::Execute, true),
+                (CallKind::Execute, true),
+                (CallKind::Execute, false),
+                (CallKind::Reply, true),
+                (CallKind::Reply, false),
+                (CallKind::Execute, false),
+            ]
+        );
+
+        model.clear_hooks();
+        recorder.clear();
+        model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        assert!(recorder.events().is_empty());
+    }
+
+    #[test]
+    fn test_sandbox_limits() {
+        use crate::{Error, SandboxLimits};
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+
+        // the contract executing itself is a nested call
+        model.set_limits(SandboxLimits {
+            memory_limit: None,
+            max_call_depth: Some(1),
+        });
+        let msg = to_binary(&json!({ "test_reenter": {} })).unwrap();
+        match model.execute(&contract, msg.as_slice(), &[]) {
+            Err(Error::LimitExceeded(_)) => {}
+            r => panic!("expected the call depth limit to be exceeded, got {:?}", r),
+        }
+        let msg = to_binary(&json!({ "test_query_self": {} })).unwrap();
+        assert!(model.execute(&contract, msg.as_slice(), &[]).is_ok());
+
+        // a single page is less than the initial memory of any contract
+        model.set_limits(SandboxLimits {
+            memory_limit: Some(64 * 1024),
+            max_call_depth: None,
+        });
+        assert_eq!(model.limits().memory_limit, Some(64 * 1024));
+        match model.instantiate_typed(1337, &InstantiateMsg {}, &[]) {
+            Err(Error::LimitExceeded(_)) => {}
+            r => panic!("expected the memory limit to be exceeded, got {:?}", r),
+        }
+
+        model.set_limits(SandboxLimits::default());
+        assert!(model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_backtraces() {
+        use crate::Error;
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+        // the contract unwraps the result of querying itself, which is refused
+        model.set_recursion_config(RecursionConfig {
+            policy: RecursionPolicy::Warn,
+            max_query_depth: 0,
+        });
+        let msg = to_binary(&json!({ "test_query_self": {} })).unwrap();
+
+        match model.execute(&contract, msg.as_slice(), &[]) {
+            Err(Error::ContractVmError {
+                contract: c,
+                wasm_backtrace,
+                ..
+            }) => assert_eq!((c, wasm_backtrace), (contract.clone(), None)),
+            r => panic!("expected the contract to trap, got {:?}", r),
+        }
+
+        model.enable_backtraces();
+        match model.execute(&contract, msg.as_slice(), &[]) {
+            Err(e @ Error::ContractVmError { .. }) => {
+                assert!(e.to_string().contains("wasm backtrace"))
+            }
+            r => panic!("expected the contract to trap, got {:?}", r),
+        }
+        let backtrace = model.debug_log.lock().unwrap().backtrace.clone();
+        assert!(backtrace
+            .iter()
+            .any(|f| f.name.as_deref().map_or(false, |n| n.contains("execute"))));
+    }
+
+    #[test]
+    fn test_frame_recording() {
+        use crate::CallKind;
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        model.enable_frame_recording();
+
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.clone().unwrap();
+        let frames = debug_log.recorded_frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].kind, CallKind::Instantiate);
+        assert_eq!(frames[0].code_id, 1337);
+        assert!(frames[0].storage_before.is_empty());
+        assert_eq!(frames[0].storage_diff().len(), 1);
+        // rerunning the instantiation writes the same storage
+        let (result, storage) = model.rerun_frame(&frames[0]).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(Some(storage), frames[0].storage_after);
+
+        let msg =
+            to_binary(&json!({ "test_reply_data": { "data": Binary::from(b"data") } })).unwrap();
+        let debug_log = model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        let frames: Vec<(CallKind, usize)> = debug_log
+            .recorded_frames()
+            .iter()
+            .map(|f| (f.kind, f.depth))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                (CallKind::Execute, 0),
+                (CallKind::Execute, 1),
+                (CallKind::Reply, 0),
+            ]
+        );
+        let reply = &debug_log.recorded_frames()[2];
+        assert_eq!(reply.sender, contract);
+        assert!(reply.result.as_ref().unwrap().is_ok());
+        let (result, storage) = model.rerun_frame(reply).unwrap();
+        assert!(result.is_ok());
+        assert_eq!(storage, reply.storage_before);
+
+        model.disable_frame_recording();
+        let debug_log = model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.recorded_frames().is_empty());
+    }
+
+    #[test]
+    fn test_block_fixed_during_transaction() {
+        use crate::{AllStates, CallKind, ExecutionHooks};
+        use std::sync::{Arc, RwLock, Weak};
+        use test_contract::msg::InstantiateMsg;
+
+        // moves the block on as every call starts, as a cheat in the middle of a transaction would
+        struct BlockBumper(Weak<RwLock<AllStates>>);
+        impl ExecutionHooks for BlockBumper {
+            fn on_call_start(
+                &self,
+                _kind: CallKind,
+                _contract: &Addr,
+                _sender: &Addr,
+                _msg: &[u8],
+            ) {
+                if let Some(states) = self.0.upgrade() {
+                    states.write().unwrap().produce_blocks(10);
+                }
+            }
+        }
+
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let contract = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap()
+            .contract_address
+            .unwrap();
+        model.enable_frame_recording();
+        model.set_hooks(Arc::new(BlockBumper(Arc::downgrade(&model.states))));
+
+        let block = model.block_info();
+        let msg =
+            to_binary(&json!({ "test_reply_data": { "data": Binary::from(b"data") } })).unwrap();
+        let debug_log = model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        assert_eq!(debug_log.block, Some(block.clone()));
+        let frames = debug_log.recorded_frames();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.env.block == block));
+        // the block moves on after the transaction
+        assert!(model.block_info().height > block.height);
+    }
+
+    #[test]
+    fn test_transaction_info() {
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let new_model = || {
+            let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+            model.add_custom_code(1337, code).unwrap();
+            model
+        };
+        let mut model = new_model();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        assert_eq!(debug_log.transaction.unwrap().index, 0);
+        let first_hash = debug_log.tx_hash.unwrap();
+        assert_eq!(first_hash.len(), 64);
+        let contract = debug_log.contract_address.unwrap();
+
+        model.cheat_transaction_info(3).unwrap();
+        model.enable_frame_recording();
+        let msg =
+            to_binary(&json!({ "test_reply_data": { "data": Binary::from(b"data") } })).unwrap();
+        let debug_log = model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        assert_eq!(debug_log.transaction.unwrap().index, 3);
+        assert!(debug_log.recorded_frames().iter().all(|f| f
+            .env
+            .transaction
+            .as_ref()
+            .unwrap()
+            .index
+            == 3));
+        assert_ne!(debug_log.tx_hash.unwrap(), first_hash);
+        assert_eq!(model.states.read().unwrap().tx_hash, None);
+
+        // the same transactions have the same hashes
+        let debug_log = new_model()
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        assert_eq!(debug_log.tx_hash.unwrap(), first_hash);
+    }
+
+    #[test]
+    fn test_block_construction() {
+        use cosmwasm_std::{CosmosMsg, WasmMsg};
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let contract = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap()
+            .contract_address
+            .unwrap();
+        let msg = CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract.to_string(),
+            msg: to_binary(&json!({ "test_reply_data": { "data": Binary::from(b"data") } }))
+                .unwrap(),
+            funds: vec![],
+        });
+        let sender = model.sender();
+        assert!(model.end_block().is_err());
+
+        let block = model.block_info();
+        model.begin_block().unwrap();
+        assert!(model.begin_block().is_err());
+        let first = model.deliver(&sender, &[msg.clone(), msg.clone()]).unwrap();
+        assert!(first.err_msg.is_none());
+        let second = model.execute_msg_as(&sender, &msg).unwrap();
+        assert_eq!(first.block, Some(block.clone()));
+        assert_eq!(second.block, Some(block.clone()));
+        assert_eq!(first.transaction.unwrap().index, 0);
+        assert_eq!(second.transaction.unwrap().index, 1);
+        assert_eq!(model.block_info(), block);
+
+        model.end_block().unwrap();
+        assert_eq!(model.block_info().height, block.height + 1);
+        let debug_log = model.execute_msg_as(&sender, &msg).unwrap();
+        assert_eq!(debug_log.transaction.unwrap().index, 0);
+    }
+
+    #[test]
+    fn test_submessage_gas_limit() {
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+
+        // running out of gas fails only the submessage, and the reply sees why
+        let msg = to_binary(&json!({ "test_gas_limit": { "gas_limit": 1 } })).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let reply_err = String::from_utf8(debug_log.data.unwrap().to_vec()).unwrap();
+        assert!(reply_err.starts_with("out of gas"));
+
+        // like on wasmd, the reply only sees the code of the error
+        model.enable_reply_error_redaction();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert_eq!(
+            debug_log.data,
+            Some(Binary::from(b"codespace: sdk, code: 11"))
+        );
+        model.disable_reply_error_redaction();
+
+        let msg = to_binary(&json!({ "test_gas_limit": { "gas_limit": 10_000_000 } })).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.data, Some(Binary::from(b"")));
+    }
+
+    #[test]
+    fn test_reply_on_error_rollback() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+
+        // the failed submessage wrote 100, but only the reply's view of the state is kept
+        let msg = to_binary(&ExecuteMsg::TestReplyOnError {}).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.data, Some(to_binary(&1u32).unwrap()));
+        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
+        let res: ReadNumberResponse =
+            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(res.value, 1);
+    }
+
+    /// runs TestReplyOnError with its submessage replaced by msg, after setup got the model and
+    /// the contract. The data of the result is the number seen by the reply, or None if the reply
+    /// was not called
+    fn reply_on_error_of<F>(msg: cosmwasm_std::CosmosMsg, setup: F) -> crate::DebugLog
+    where
+        F: FnOnce(&mut Model, &Addr),
+    {
+        use crate::MsgAction;
+        use cosmwasm_std::{CosmosMsg, WasmMsg};
+        use std::sync::Arc;
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let query_self = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model
+            .execute(&pair_address, query_self.as_slice(), &[])
+            .unwrap();
+        setup(&mut model, &pair_address);
+        model.add_middleware(Arc::new(move |_: &Addr, m: &CosmosMsg| match m {
+            CosmosMsg::Wasm(WasmMsg::Execute { .. }) => Ok(MsgAction::Replace(msg.clone())),
+            _ => Ok(MsgAction::Continue),
+        }));
+        let reply_on_error = to_binary(&ExecuteMsg::TestReplyOnError {}).unwrap();
+        model
+            .execute(&pair_address, reply_on_error.as_slice(), &[])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_bank_submessage_reply_on_error() {
+        use cosmwasm_std::BankMsg;
+        // the contract lacks the funds, like on wasmd the error is passed to the reply
+        let debug_log = reply_on_error_of(
+            BankMsg::Send {
+                to_address: BASE_EOA.to_string(),
+                amount: vec![Coin::new(1, "unonexistent")],
+            }
+            .into(),
+            |_, _| {},
+        );
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.data, Some(to_binary(&1u32).unwrap()));
+    }
+
+    #[test]
+    fn test_distribution_submessage_reply_on_error() {
+        use cosmwasm_std::DistributionMsg;
+        let validator = "wasmvaloper1validator";
+        // the distribution module can't pay out rewards in a denom it does not hold
+        let debug_log = reply_on_error_of(
+            DistributionMsg::WithdrawDelegatorReward {
+                validator: validator.to_string(),
+            }
+            .into(),
+            |model, contract| {
+                model.cheat_delegation_rewards(contract, validator, &[Coin::new(1, "unonexistent")])
+            },
+        );
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.data, Some(to_binary(&1u32).unwrap()));
+    }
+
+    #[test]
+    fn test_gov_submessage_reply_on_error() {
+        use cosmwasm_std::{GovMsg, VoteOption};
+        // votes on proposals which don't exist fail
+        let debug_log = reply_on_error_of(
+            GovMsg::Vote {
+                proposal_id: u64::MAX,
+                vote: VoteOption::Yes,
+            }
+            .into(),
+            |_, _| {},
+        );
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.data, Some(to_binary(&1u32).unwrap()));
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let stats = model.memory_stats();
+        assert_eq!(stats.contracts, 1);
+        assert_eq!(stats.code_bytes, wasm_code.len());
+        assert!(stats.storage_records > 0);
+        assert_eq!(stats.shared_storages, 0);
+
+        // clones share storages until they are written to
+        let snapshot = model.clone();
+        assert_eq!(model.memory_stats().shared_storages, 1);
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert_eq!(model.memory_stats().shared_storages, 0);
+        assert_eq!(snapshot.memory_stats().shared_storages, 0);
+        assert_ne!(
+            model.contract_storage(&pair_address).unwrap(),
+            snapshot.contract_storage(&pair_address).unwrap()
+        );
+
+        model.clear_instance_pool();
+        assert_eq!(model.memory_stats().idle_instances, 0);
+    }
+
+    #[test]
+    fn test_address_conversion() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        let canonical = model.human_to_canonical(pair_address.as_str()).unwrap();
+        assert_eq!(canonical.len(), 32);
+        assert_eq!(model.canonical_to_human(&canonical).unwrap(), pair_address);
+
+        let osmo = canonical_to_human(&[1; 20], "osmo", 20).unwrap();
+        assert!(model.human_to_canonical(&osmo).is_err());
+        model.add_bech32_prefix("osmo", 20).unwrap();
+        let canonical = model.human_to_canonical(&osmo).unwrap();
+        assert_eq!(model.canonical_to_human(&canonical).unwrap().as_str(), osmo);
+        assert!(model.add_bech32_prefix("wasm", 20).is_err());
+
+        model.set_canonical_address_length(20).unwrap();
+        assert!(model.human_to_canonical(pair_address.as_str()).is_err());
+        assert!(model.set_canonical_address_length(0).is_err());
+    }
+
+    #[test]
+    fn test_create_key_account() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let funds = vec![Coin::new(100, "umlg")];
+        let key = model
+            .create_key_account("signer", KeyAlgo::Secp256k1, &funds)
+            .unwrap();
+        let address = model.account("signer").unwrap();
+        assert_eq!(key.address("wasm").unwrap(), address);
+        // the address is a valid account address of the chain
+        assert_eq!(
+            model.human_to_canonical(address.as_str()).unwrap().len(),
+            20
+        );
+        let signature = key.sign(b"approve");
+        assert!(key.verify(b"approve", &signature));
+        let msg = to_binary(&BankQuery::Balance {
+            address: address.to_string(),
+            denom: "umlg".to_string(),
+        })
+        .unwrap();
+        let balance: BalanceResponse =
+            from_binary(&model.bank_query(msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(balance.amount.amount.u128(), 100);
+    }
+
+    #[test]
+    fn test_watch_and_reload() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let dir = std::env::temp_dir().join(format!("cw-watch-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test_contract.wasm");
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        assert!(model.watch_and_reload(&pair_address, &path).is_err());
+
+        std::fs::write(&path, wasm_code).unwrap();
+        model.watch_and_reload(&pair_address, &path).unwrap();
+        assert_eq!(model.watched(), vec![(pair_address.clone(), path.clone())]);
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+
+        // code which fails to load leaves the previous code in place
+        std::fs::write(&path, b"not wasm").unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(model.reload_watched().unwrap().is_empty());
+
+        std::fs::write(&path, wasm_code).unwrap();
+        assert_eq!(model.reload_watched().unwrap(), vec![pair_address.clone()]);
+        assert!(model.unwatch(&pair_address));
+        assert!(model.watched().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_contract_metrics() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let metrics = model.contract_metrics(&pair_address).unwrap();
+        assert_eq!(metrics.code_size, wasm_code.len());
+        assert!(metrics.module_size > 0);
+        assert_eq!(
+            (metrics.calls, metrics.gas_used, metrics.memory_pages),
+            (0, 0, 0)
+        );
+
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
+        model.wasm_query(&pair_address, msg.as_slice()).unwrap();
+        let metrics = model.contract_metrics(&pair_address).unwrap();
+        assert_eq!(metrics.calls, 2);
+        assert!(metrics.gas_used > 0);
+        assert!(metrics.memory_pages > 0);
+
+        model.clear_contract_metrics();
+        assert_eq!(model.contract_metrics(&pair_address).unwrap().calls, 0);
+    }
+
+    #[test]
+    fn test_state_hash() {
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let initial_hash = model.state_hash().unwrap();
+        assert_eq!(model.state_hash().unwrap(), initial_hash);
+
+        // writing directly and through a reentrant submessage end in the same state
+        let mut direct = model.clone();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        direct.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let mut reentrant = model.clone();
+        let msg = to_binary(&ExecuteMsg::TestReenter {}).unwrap();
+        reentrant
+            .execute(&pair_address, msg.as_slice(), &[])
+            .unwrap();
+        assert_ne!(direct.state_hash().unwrap(), initial_hash);
+        assert_eq!(
+            direct.state_hash().unwrap(),
+            reentrant.state_hash().unwrap()
+        );
+
+        direct.cheat_bank_balance(&pair_address, "umlg", 1).unwrap();
+        assert_ne!(
+            direct.state_hash().unwrap(),
+            reentrant.state_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_profile_report() {
+        use crate::ProfileSort;
+        use test_contract::msg::ExecuteMsg;
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        let msg = to_binary(&ExecuteMsg::TestReplyData {
+            data: Binary::from(b"data"),
+        })
+        .unwrap();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(model
+            .profile_report(ProfileSort::TotalTime)
+            .frames
+            .is_empty());
+
+        model.enable_profiling();
+        model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let report = model.profile_report(ProfileSort::SelfTime);
+        // the submessage and the reply finish before the call they are nested in
+        let frames: Vec<(&str, usize)> = report
+            .frames
+            .iter()
+            .map(|f| (f.entry_point.as_str(), f.depth))
+            .collect();
+        assert_eq!(frames, vec![("execute", 1), ("reply", 1), ("execute", 0)]);
+        assert!(report.frames.iter().all(|f| f.gas_used > 0));
+        let top = &report.frames[2];
+        assert!(top.self_time < top.total_time);
+
+        assert_eq!(report.contracts.len(), 1);
+        let profile = &report.contracts[0];
+        assert_eq!(profile.contract, pair_address);
+        assert_eq!(profile.calls, 3);
+        assert_eq!(profile.total_time, top.total_time);
+        assert_eq!(
+            profile.gas_used,
+            report.frames.iter().map(|f| f.gas_used).sum::<u64>()
+        );
+
+        model.clear_profile();
+        assert!(model.profile_report(ProfileSort::Gas).contracts.is_empty());
     }
 
-    /// modify storage of a contract
-    pub fn cheat_storage(
-        &mut self,
-        contract_addr: &Addr,
-        key: &[u8],
-        value: &[u8],
-    ) -> Result<(), Error> {
-        self.fetch_contract_state(contract_addr)?;
-        let mut states = self.states.write().unwrap();
-        let contract_storage = states.contract_state_get_mut(contract_addr).unwrap();
-        contract_storage
-            .storage
-            .write()
+    #[test]
+    fn test_funds_validation() {
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, wasm_code).unwrap();
+        let alice = model
+            .create_account("alice", &[Coin::new(1000, "umlg")])
+            .unwrap();
+        model.cheat_message_sender(&alice).unwrap();
+        let err = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[Coin::new(0, "umlg")])
+            .unwrap_err();
+        assert!(err.to_string().contains("zero amount of umlg"));
+        let contract = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
             .unwrap()
-            .insert(key.to_vec(), value.to_vec());
-        Ok(())
+            .contract_address
+            .unwrap();
+
+        let funds = [Coin::new(100, "umlg"), Coin::new(50, "umlg")];
+        let err = model
+            .execute_typed(&contract, &ExecuteMsg::TestQuerySelf {}, &funds)
+            .unwrap_err();
+        assert!(err.to_string().contains("duplicate denom umlg"));
+        let balance = |model: &Model| {
+            let mut states = model.states.write().unwrap();
+            states.get_balance(&contract, "umlg").unwrap().u128()
+        };
+        assert_eq!(balance(&model), 0);
+
+        model.enable_funds_merging();
+        let debug_log = model
+            .execute_typed(&contract, &ExecuteMsg::TestQuerySelf {}, &funds)
+            .unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(balance(&model), 150);
     }
-}
 
-#[cfg(test)]
-mod test {
+    #[test]
+    fn test_wasmd_events() {
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, wasm_code).unwrap();
+        let alice = model
+            .create_account("alice", &[Coin::new(1000, "umlg")])
+            .unwrap();
+        model.cheat_message_sender(&alice).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[Coin::new(100, "umlg")])
+            .unwrap();
+        let contract = debug_log.contract_address.clone().unwrap();
+        let types: Vec<&str> = debug_log
+            .events
+            .iter()
+            .map(|e| e.event.ty.as_str())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                "coin_spent",
+                "coin_received",
+                "transfer",
+                "message",
+                "instantiate",
+                "wasm"
+            ]
+        );
+        let transfer = &debug_log.events_of_type("transfer")[0];
+        assert_eq!(transfer.attributes[0].value, contract.as_str());
+        assert_eq!(transfer.attributes[1].value, alice.as_str());
+        assert_eq!(transfer.attributes[2].value, "100umlg");
 
-    use cosmwasm_std::{from_binary, to_binary, Addr, BalanceResponse, BankQuery, Coin, Uint128};
-    use serde_json::json;
-    use std::str::FromStr;
+        // the events of the submessage are recorded in its own call
+        let debug_log = model
+            .execute_typed(&contract, &ExecuteMsg::TestReenter {}, &[])
+            .unwrap();
+        let types: Vec<&str> = debug_log
+            .events
+            .iter()
+            .map(|e| e.event.ty.as_str())
+            .collect();
+        assert_eq!(
+            types,
+            vec!["execute", "wasm", "execute", "wasm", "wasm-read_number"]
+        );
+        let inner = debug_log.events[2].call_id;
+        assert_ne!(debug_log.events[0].call_id, inner);
+        assert_eq!(debug_log.call_events(inner).len(), 3);
+        let read_number = &debug_log.events_of_type("wasm-read_number")[0];
+        assert_eq!(read_number.attributes[0].key, "_contract_address");
+        assert_eq!(read_number.attributes[1].value, "2");
 
-    use crate::{fork::debug_log::DebugLogEntry, fork::model::Model};
+        // the failed submessage emits nothing, the reply does
+        let debug_log = model
+            .execute_typed(&contract, &ExecuteMsg::TestReplyOnError {}, &[])
+            .unwrap();
+        let types: Vec<&str> = debug_log.tx_events().iter().map(|e| e.ty.clone()).collect();
+        assert_eq!(types, vec!["execute", "wasm", "reply", "wasm"]);
 
-    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
-    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
-    const FACTORY_ADDRESS_MALAGA: &str =
-        "wasm1hczjykytm4suw4586j5v42qft60gc4j307gf7cxuazfg7jxt4h4sjvp7rx";
-    const PAIR_ADDRESS_MALAGA: &str =
-        "wasm15le5evw4regnwf9lrjnpakr2075fcyp4n4yzpelvqcuevzkw2lss46hslz";
-    const TOKEN_ADDRESS_MALAGA: &str =
-        "wasm124v54ngky9wxhx87t252x4xfgujmdsu7uhjdugtkkqt39nld0e6st7e64h";
-    const VAULT_ADDRESS: &str = "wasm1fedmcgtsvmymyr6jssgar0h7uhhcuxhr7ygjjw5q2epgzef3jy0svcr5jx";
-    const VAULT_ROUTER_ADDRESS: &str =
-        "wasm1xp8prmlsx9erdkrk43qjtrw54755zwm9f4x52m8k3an6jgcaldpqpmsd23";
+        // failed transactions emit no events
+        let debug_log = model
+            .execute_typed(&contract, &ExecuteMsg::TestAtomic {}, &[])
+            .unwrap();
+        assert!(debug_log.err_msg.is_some());
+        assert!(debug_log.events.is_empty());
+    }
 
     #[test]
-    fn test_swap_basic_testnet() {
-        use serde_json::Value::Null;
+    fn test_labels() {
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
         let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
-        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
-        let token_address = Addr::unchecked(TOKEN_ADDRESS_MALAGA);
-        let my_address = model.sender.clone();
+        model.add_custom_code(1337, wasm_code).unwrap();
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model
+            .instantiate_with_admin(1337, msg.as_slice(), &[], None, "test_contract")
+            .unwrap();
+        let contract = debug_log.contract_address.clone().unwrap();
+        assert_eq!(debug_log.name_of(&contract), Some("test_contract"));
+        assert!(
+            debug_log.call_trace.call_graph_labels[&1].starts_with("test_contract(wasm1")
+                && debug_log.call_trace.call_graph_labels[&1].contains("...):instantiate({})")
+        );
+        assert_eq!(model.lookup("test_contract"), Some(contract.clone()));
 
-        let swap_msg_json = json!({
-            "swap": {
-            "offer_asset": {
-                "info": { "native_token": { "denom": "umlg" } },
-                "amount": "10"
-            },
-            "belief_price": Null,
-            "max_spread": Null,
-            "to": Null
-            }
+        // aliases take precedence over labels
+        model.set_alias("mine", &contract);
+        assert_eq!(model.name_of(&contract), Some("mine".to_string()));
+        let debug_log = model
+            .execute_typed(&contract, &ExecuteMsg::TestQuerySelf {}, &[])
+            .unwrap();
+        let labels: Vec<&String> = debug_log.call_trace.call_graph_labels.values().collect();
+        assert!(labels
+            .iter()
+            .any(|l| l.starts_with("mine(") && l.contains(":execute(")));
+        assert!(labels
+            .iter()
+            .any(|l| l.starts_with("mine(") && l.contains(":query(")));
+        assert_eq!(model.lookup("mine"), Some(contract.clone()));
+
+        let alice = model.create_account("alice", &[]).unwrap();
+        assert_eq!(model.lookup("alice"), Some(alice));
+        assert_eq!(model.remove_alias("mine"), Some(contract.clone()));
+        assert_eq!(model.name_of(&contract), Some("test_contract".to_string()));
+        assert_eq!(model.lookup("nobody"), None);
+    }
+
+    #[test]
+    fn test_query_depth() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        model.set_recursion_config(RecursionConfig {
+            policy: RecursionPolicy::Warn,
+            max_query_depth: 5,
         });
-        let swap_msg = serde_json::to_string(&swap_msg_json).unwrap();
-        let funds = vec![Coin {
-            denom: "umlg".to_string(),
-            amount: Uint128::new(10),
-        }];
 
-        // balance before the swap
-        let query_balance_msg_json = json!({
-            "balance": {"address": my_address, }
+        let msg = to_binary(&QueryMsg::Recurse { depth: 5 }).unwrap();
+        let res: ReadNumberResponse =
+            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(res.value, 5);
+        assert!(model.debug_log.lock().unwrap().warnings.is_empty());
+
+        // the sixth nested query is refused
+        let msg = to_binary(&QueryMsg::Recurse { depth: 6 }).unwrap();
+        assert!(model.wasm_query(&pair_address, msg.as_slice()).is_err());
+        assert_eq!(model.debug_log.lock().unwrap().warnings.len(), 1);
+
+        // aborting fails the call with the reason instead of the error of the contract
+        model.set_recursion_config(RecursionConfig {
+            policy: RecursionPolicy::Abort,
+            max_query_depth: 0,
         });
-        let query_balance_msg = serde_json::to_string(&query_balance_msg_json).unwrap();
-        let resp = model
-            .wasm_query(&token_address, query_balance_msg.as_bytes())
-            .unwrap();
-        let resp_json: serde_json::Value = serde_json::from_slice(resp.as_slice()).unwrap();
-        let token_balance_before = u128::from_str(resp_json["balance"].as_str().unwrap()).unwrap();
-        let bank_query = BankQuery::Balance {
-            address: my_address,
-            denom: "umlg".to_string(),
-        };
-        let resp = model
-            .bank_query(to_binary(&bank_query).unwrap().as_slice())
-            .unwrap();
-        let resp_bank: BalanceResponse = serde_json::from_slice(resp.as_slice()).unwrap();
-        let umlg_balance_before: u128 = resp_bank.amount.amount.into();
-        let prev_block_num = model.states.read().unwrap().block_number;
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.unwrap().contains("maximum query depth"));
+    }
 
-        // execute the swap transaction
-        let _ = model
-            .execute(&pair_address, swap_msg.as_bytes(), &funds)
+    #[test]
+    fn test_instance_pool() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        model.cheat_code(&pair_address, wasm_code).unwrap();
+        assert!(model.instance_pool.is_empty());
+
+        // the instance created for the nested query is pooled and reused by the next one
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(model.instance_pool.len(), 1);
+        let debug_log = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        assert_eq!(debug_log.logs[0].events[0].attributes[0].value, "2");
+        assert_eq!(model.instance_pool.len(), 1);
+
+        // recursive queries need one instance per level
+        let msg = to_binary(&QueryMsg::Recurse { depth: 3 }).unwrap();
+        let res: ReadNumberResponse =
+            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(res.value, 3);
+        assert_eq!(model.instance_pool.len(), 2);
+    }
+
+    #[test]
+    fn test_new_offline() {
+        use crate::CacheConfig;
+        let token_address = Addr::unchecked(TOKEN_ADDRESS_MALAGA);
+        let model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let expected = model
+            .scan_storage(&token_address, None, None, Order::Ascending, None)
             .unwrap();
+        let cache_key = model.repro_report().rpc_cache_key.unwrap();
+        // the cache is saved once the model is dropped
+        drop(model);
 
-        // check the results
-        // block number incremented
+        let path = CacheConfig::default().dir.join(cache_key);
+        let offline = Model::new_offline(&path, MALAGA_BLOCK_NUMBER, "wasm").unwrap();
+        assert_eq!(offline.block_number(), MALAGA_BLOCK_NUMBER);
         assert_eq!(
-            model.states.read().unwrap().block_number,
-            prev_block_num + 1
+            offline
+                .scan_storage(&token_address, None, None, Order::Ascending, None)
+                .unwrap(),
+            expected
         );
+        let err = offline
+            .wasm_raw_query(&Addr::unchecked(PAIR_ADDRESS_MALAGA), b"missing")
+            .unwrap_err();
+        assert!(err.to_string().contains("not cached"));
+    }
 
-        let resp = model
-            .wasm_query(&token_address, query_balance_msg.as_bytes())
+    #[test]
+    fn test_scan_storage() {
+        let model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let token_address = Addr::unchecked(TOKEN_ADDRESS_MALAGA);
+        // cw20 balances are stored in a Map under "balance"
+        let balances = model
+            .scan_map(&token_address, "balance", Order::Ascending, Some(3))
             .unwrap();
-        let resp_json: serde_json::Value = serde_json::from_slice(resp.as_slice()).unwrap();
-        let token_balance_after = u128::from_str(resp_json["balance"].as_str().unwrap()).unwrap();
-        let resp = model
-            .bank_query(to_binary(&bank_query).unwrap().as_slice())
+        assert!(!balances.is_empty() && balances.len() <= 3);
+        for (holder, balance) in balances.iter() {
+            assert!(String::from_utf8(holder.clone())
+                .unwrap()
+                .starts_with("wasm1"));
+            let _: Uint128 = serde_json::from_slice(balance).unwrap();
+        }
+        let descending = model
+            .scan_map(&token_address, "balance", Order::Descending, None)
             .unwrap();
-        let resp_bank: BalanceResponse = serde_json::from_slice(resp.as_slice()).unwrap();
-        let umlg_balance_after: u128 = resp_bank.amount.amount.into();
+        assert_eq!(descending.last(), balances.first());
 
-        // token and umlg balance as expected
-        assert_eq!(token_balance_after - token_balance_before, 9);
-        assert_eq!(umlg_balance_before - umlg_balance_after, 10);
+        let all = model
+            .scan_storage(&token_address, None, None, Order::Ascending, None)
+            .unwrap();
+        assert!(all.len() > descending.len());
     }
 
     #[test]
-    fn test_flashloan() {
-        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
-        let _vault_address = Addr::unchecked(VAULT_ADDRESS);
-        let vault_router_address = Addr::unchecked(VAULT_ROUTER_ADDRESS);
-        let _my_address = model.sender.clone();
+    fn test_lazy_storage() {
+        let eager = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let mut lazy = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        lazy.set_lazy_storage(true);
+        let token_address = Addr::unchecked(TOKEN_ADDRESS_MALAGA);
 
-        let loan_msg_json = json!({
-            "flash_loan": {
-                "assets": [{
-                    "info": { "native_token": { "denom": "umlg" } },
-                    "amount": "10"
-                }],
-                "msgs": [],
-            }
-        });
-        let loan_msg = serde_json::to_string(&loan_msg_json).unwrap();
-        let prev_block_num = model.states.read().unwrap().block_number;
-        // execute the swap transaction
-        let log = model
-            .execute(&vault_router_address, loan_msg.as_bytes(), &[])
-            .unwrap();
+        let key = b"token_info";
+        let value = lazy.wasm_raw_query(&token_address, key).unwrap();
+        assert!(value.is_some());
+        assert_eq!(value, eager.wasm_raw_query(&token_address, key).unwrap());
+        assert_eq!(
+            lazy.wasm_raw_query(&token_address, b"missing").unwrap(),
+            None
+        );
+        // only the keys accessed so far are loaded
+        let states = lazy.states.read().unwrap();
+        let contract_state = states.contract_state_get(&token_address).unwrap();
+        let loaded: Vec<Vec<u8>> = contract_state
+            .storage
+            .read()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(loaded, vec![key.to_vec()]);
+        drop(states);
 
+        // iterating downloads the rest of the storage
+        let all = lazy
+            .scan_storage(&token_address, None, None, Order::Ascending, None)
+            .unwrap();
         assert_eq!(
-            model.states.read().unwrap().block_number,
-            prev_block_num + 1
+            all,
+            eager
+                .scan_storage(&token_address, None, None, Order::Ascending, None)
+                .unwrap()
         );
-        assert_eq!(log.err_msg, None);
     }
 
     #[test]
-    fn test_storage_write() {
-        use test_contract::msg::ExecuteMsg;
-        // test if querier can view writes to the current contract
+    fn test_query_handle() {
+        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
         let wasm_code = include_bytes!(concat!(
             env!("OUT_DIR"),
             "/wasm32-unknown-unknown/release/test_contract.wasm"
@@ -920,47 +6235,143 @@ mod test {
         let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
         let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
         model.cheat_code(&pair_address, wasm_code).unwrap();
-        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
-        let res = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
-        for log in res.logs {
-            for event in log.events {
-                if event.ty == "read_number" {
-                    assert_eq!(event.attributes[0].value.as_str(), "2");
+        let handle = model.query_handle();
+
+        // queries from other threads
+        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                let pair_address = pair_address.clone();
+                let msg = msg.clone();
+                std::thread::spawn(move || {
+                    let res: ReadNumberResponse =
+                        from_binary(&handle.wasm_query(&pair_address, msg.as_slice()).unwrap())
+                            .unwrap();
+                    res.value
+                })
+            })
+            .collect();
+        let values: Vec<u32> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        let expected: ReadNumberResponse =
+            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert!(values.iter().all(|v| *v == expected.value));
+
+        // the handle must still observe the model after a reverted execution
+        let failing_msg = to_binary(&ExecuteMsg::TestAtomic {}).unwrap();
+        let _ = model
+            .execute(&pair_address, failing_msg.as_slice(), &[])
+            .unwrap();
+        let res: ReadNumberResponse =
+            from_binary(&handle.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(res.value, expected.value);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let alice = model
+            .create_account("alice", &[Coin::new(1000, "umlg")])
+            .unwrap();
+        let handle = model.query_handle();
+        let query = to_binary(&BankQuery::Balance {
+            address: alice.to_string(),
+            denom: "umlg".to_string(),
+        })
+        .unwrap();
+        let balance = |res: Binary| from_binary::<BalanceResponse>(&res).unwrap().amount.amount;
+
+        let snapshot = model.clone();
+        let block_number = model.states.read().unwrap().block_number;
+        model.cheat_mint(&alice, &[Coin::new(500, "umlg")]).unwrap();
+        model.produce_block(3);
+        assert_eq!(
+            balance(model.bank_query(query.as_slice()).unwrap()),
+            Uint128::new(1500)
+        );
+
+        model.restore(&snapshot);
+        assert_eq!(
+            balance(model.bank_query(query.as_slice()).unwrap()),
+            Uint128::new(1000)
+        );
+        assert_eq!(model.states.read().unwrap().block_number, block_number);
+        assert_eq!(model.account("alice"), Some(alice.clone()));
+
+        // a snapshot can be restored more than once, and handles observe the restored state
+        model.cheat_mint(&alice, &[Coin::new(1, "umlg")]).unwrap();
+        model.restore(&snapshot);
+        assert_eq!(
+            balance(handle.bank_query(query.as_slice()).unwrap()),
+            Uint128::new(1000)
+        );
+    }
+
+    #[test]
+    fn test_preload_contracts() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let contracts = vec![
+            Addr::unchecked(PAIR_ADDRESS_MALAGA),
+            Addr::unchecked(TOKEN_ADDRESS_MALAGA),
+            Addr::unchecked(FACTORY_ADDRESS_MALAGA),
+        ];
+        model.preload_contracts(&contracts).unwrap();
+        for contract in contracts.iter() {
+            assert!(model
+                .states
+                .read()
+                .unwrap()
+                .contract_state_get(contract)
+                .is_some());
+        }
+        assert!(!model.wasm_cache.is_empty());
+    }
+
+    #[test]
+    fn test_fork_closure() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        let mut loaded = Vec::new();
+        let closure = model
+            .fork_closure_with_progress(&pair_address, 1, |p| {
+                if let ClosureProgress::Contract { address, .. } = p {
+                    loaded.push(address.clone());
                 }
-            }
+            })
+            .unwrap();
+        assert_eq!(closure.contracts[&pair_address], 0);
+        // the pair refers to its factory and the token it trades
+        assert_eq!(
+            closure.contracts[&Addr::unchecked(FACTORY_ADDRESS_MALAGA)],
+            1
+        );
+        assert_eq!(closure.contracts[&Addr::unchecked(TOKEN_ADDRESS_MALAGA)], 1);
+        assert_eq!(loaded.len(), closure.contracts.len());
+        let states = model.states.read().unwrap();
+        for contract in closure.contracts.keys() {
+            assert!(states.contract_state_get(contract).is_some());
         }
     }
 
     #[test]
-    fn test_atomicity() {
-        use test_contract::msg::{ExecuteMsg, QueryMsg, ReadNumberResponse};
-        // test if querier can view writes to the current contract
-        let wasm_code = include_bytes!(concat!(
+    fn test_maybe_unzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let code = include_bytes!(concat!(
             env!("OUT_DIR"),
             "/wasm32-unknown-unknown/release/test_contract.wasm"
         ));
-        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
-        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
-        model.cheat_code(&pair_address, wasm_code).unwrap();
-
-        // set NUMBER to 2
-        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
-        let _ = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
-
-        // query value of NUMBER
-        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
-        let query_res1: ReadNumberResponse =
-            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(maybe_unzip(code.to_vec()).unwrap(), code.to_vec());
 
-        // run failing execute()
-        let msg = to_binary(&ExecuteMsg::TestAtomic {}).unwrap();
-        let _ = model.execute(&pair_address, msg.as_slice(), &[]).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(code).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert_eq!(maybe_unzip(gzipped).unwrap(), code.to_vec());
 
-        // query value of NUMBER again, it should be same as previous value
-        let msg = to_binary(&QueryMsg::ReadNumber {}).unwrap();
-        let query_res2: ReadNumberResponse =
-            from_binary(&model.wasm_query(&pair_address, msg.as_slice()).unwrap()).unwrap();
-        assert_eq!(query_res1.value, query_res2.value);
+        assert!(maybe_unzip(vec![1, 2, 3, 4, 5]).is_err());
+        assert!(maybe_unzip(vec![]).is_err());
     }
 
     #[test]
@@ -989,6 +6400,48 @@ mod test {
         println!("{}", query_result2);
     }
 
+    #[test]
+    fn test_unified_query() {
+        use cosmwasm_std::{AllBalanceResponse, BondedDenomResponse, QueryRequest, StakingQuery};
+
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let vault = model.create_account("vault", &[]).unwrap();
+        model.cheat_bank_balance(&vault, "umlg", 1000).unwrap();
+        let res = model
+            .query(&QueryRequest::Bank(BankQuery::AllBalances {
+                address: vault.to_string(),
+            }))
+            .unwrap();
+        let res: AllBalanceResponse = from_binary(&res).unwrap();
+        assert_eq!(res.amount, vec![Coin::new(1000, "umlg")]);
+
+        let res = model
+            .query(&QueryRequest::Staking(StakingQuery::BondedDenom {}))
+            .unwrap();
+        let res: BondedDenomResponse = from_binary(&res).unwrap();
+        assert_eq!(res.denom, "umlg");
+
+        // distribution queries of cosmwasm 1.4 need the capability, like for contracts
+        let validator = "wasmvaloper1validator";
+        model.cheat_delegation_rewards(&vault, validator, &[Coin::new(300, "umlg")]);
+        let request = serde_json::json!({
+            "distribution": {
+                "delegation_rewards": {
+                    "delegator_address": vault,
+                    "validator_address": validator,
+                }
+            }
+        });
+        let request = serde_json::to_vec(&request).unwrap();
+        assert!(model.query_json(&request).is_err());
+        let mut capabilities: Vec<String> = model.capabilities().into_iter().collect();
+        capabilities.push("cosmwasm_1_4".to_string());
+        let capabilities: Vec<&str> = capabilities.iter().map(|c| c.as_str()).collect();
+        model.set_capabilities(&capabilities);
+        let res: serde_json::Value = from_binary(&model.query_json(&request).unwrap()).unwrap();
+        assert_eq!(res["rewards"][0]["amount"], "300");
+    }
+
     fn get_contract_address_from_log(logs: &[DebugLogEntry]) -> Option<String> {
         for log in logs.iter() {
             for event in log.events.iter() {
@@ -1002,6 +6455,285 @@ mod test {
         None
     }
 
+    #[test]
+    fn test_capabilities() {
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        assert!(model.capabilities().contains("cosmwasm_1_1"));
+
+        // cosmwasm-std exports requires_iterator with its default features
+        model.set_capabilities(&["staking", "stargate"]);
+        let err = model.add_custom_code(1337, code).unwrap_err();
+        assert!(err.to_string().contains("iterator"));
+
+        model.set_capabilities(&["iterator"]);
+        model.add_custom_code(1337, code).unwrap();
+        model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_cosmwasm_version() {
+        use crate::CosmwasmVersion;
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+        assert_eq!(
+            model.cosmwasm_version(&contract).unwrap(),
+            CosmwasmVersion::V1_0
+        );
+    }
+
+    #[test]
+    fn test_call_reply() {
+        use cosmwasm_std::{Reply, SubMsgResponse, SubMsgResult};
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+
+        // the contract returns the data of the submessage as is
+        let reply = Reply {
+            id: 1,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(Binary::from(b"handcrafted")),
+            }),
+        };
+        let debug_log = model.call_reply(&contract, &reply).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(debug_log.data, Some(Binary::from(b"handcrafted")));
+        assert!(debug_log.call_trace.call_graph_labels[&1].contains("reply"));
+
+        // and the error of a gas limited submessage as data
+        let reply = Reply {
+            id: 2,
+            result: SubMsgResult::Err("out of gas".to_string()),
+        };
+        let debug_log = model.call_reply(&contract, &reply).unwrap();
+        assert_eq!(debug_log.data, Some(Binary::from(b"out of gas")));
+    }
+
+    #[test]
+    fn test_sudo() {
+        use test_contract::msg::{InstantiateMsg, QueryMsg, ReadNumberResponse, SudoMsg};
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.enable_code_coverage();
+        model.enable_coverage_instrumentation();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+
+        let msg = to_binary(&SudoMsg::SetNumber { value: 7 }).unwrap();
+        let debug_log = model.sudo(&contract, msg.as_slice()).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert!(debug_log.call_trace.call_graph_labels[&1].contains("sudo"));
+        debug_log
+            .assert_event("sudo", &[("_contract_address", contract.as_str())])
+            .unwrap();
+        assert_eq!(debug_log.wasm_attribute("number"), Some("7"));
+        let response: ReadNumberResponse = model
+            .query_typed(&contract, &QueryMsg::ReadNumber {})
+            .unwrap();
+        assert_eq!(response.value, 7);
+        // coverage is collected after sudo calls like after any other call
+        let report = model.coverage_report().unwrap();
+        assert_eq!(report.contracts[contract.as_str()].runs, 2);
+
+        let debug_log = model.sudo(&contract, b"{\"unknown\":{}}").unwrap();
+        assert!(debug_log.err_msg.is_some());
+        assert!(debug_log.events.is_empty());
+    }
+
+    #[test]
+    fn test_native_contract() {
+        use crate::NativeHandler;
+        use cosmwasm_std::{QueryRequest, Response, StdError, WasmMsg, WasmQuery};
+        use std::sync::Arc;
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+        let alice = model
+            .create_account("alice", &[Coin::new(1000, "umlg")])
+            .unwrap();
+
+        // an oracle with a fixed price, which forwards every update to the test contract
+        let oracle = model.create_account("oracle", &[]).unwrap();
+        let target = contract.to_string();
+        let handler = NativeHandler::new()
+            .with_query(|_, _| to_binary(&json!({ "price": "1.5" })))
+            .with_execute(move |_, info, msg| {
+                if msg != b"{\"update\":{}}" {
+                    return Err(StdError::generic_err("unknown message"));
+                }
+                let set_data = ExecuteMsg::SetData {
+                    data: Binary::from(info.sender.as_bytes()),
+                };
+                Ok(Response::new()
+                    .add_attribute("action", "update")
+                    .add_message(WasmMsg::Execute {
+                        contract_addr: target.clone(),
+                        msg: to_binary(&set_data)?,
+                        funds: vec![],
+                    }))
+            });
+        model.register_native_contract(&oracle, Arc::new(handler));
+
+        let price = model.wasm_query(&oracle, b"{\"price\":{}}").unwrap();
+        assert_eq!(price, to_binary(&json!({ "price": "1.5" })).unwrap());
+        let raw = model
+            .query(&QueryRequest::Wasm(WasmQuery::Raw {
+                contract_addr: oracle.to_string(),
+                key: Binary::from(b"config"),
+            }))
+            .unwrap();
+        assert!(raw.is_empty());
+
+        let debug_log = model
+            .execute_as(
+                &alice,
+                &oracle,
+                b"{\"update\":{}}",
+                &[Coin::new(10, "umlg")],
+            )
+            .unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(
+            debug_log.contract_attribute(&oracle, "action"),
+            Some("update")
+        );
+        debug_log
+            .assert_event("execute", &[("_contract_address", contract.as_str())])
+            .unwrap();
+        let msg = to_binary(&BankQuery::Balance {
+            address: oracle.to_string(),
+            denom: "umlg".to_string(),
+        })
+        .unwrap();
+        let balance: BalanceResponse =
+            from_binary(&model.bank_query(msg.as_slice()).unwrap()).unwrap();
+        assert_eq!(balance.amount.amount.u128(), 10);
+
+        // errors of native contracts revert the transaction like contract errors
+        let debug_log = model.execute_as(&alice, &oracle, b"{}", &[]).unwrap();
+        debug_log.assert_error_contains("unknown message").unwrap();
+
+        model.remove_native_contract(&oracle);
+        assert!(model.wasm_query(&oracle, b"{\"price\":{}}").is_err());
+    }
+
+    #[test]
+    fn test_query_metering() {
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+        let msg = to_binary(&ExecuteMsg::TestQuerySelf {}).unwrap();
+
+        let mut metered = model.clone();
+        metered.enable_query_metering();
+        let free = model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        let charged = metered.execute(&contract, msg.as_slice(), &[]).unwrap();
+        assert!(free.err_msg.is_none() && charged.err_msg.is_none());
+        // the query of the contract to itself runs a whole instance
+        assert!(charged.gas_used > free.gas_used);
+
+        // the query can't use more gas than the querying contract has left
+        let budget = free.gas_used + (charged.gas_used - free.gas_used) / 2;
+        model.gas_left = Some(budget);
+        metered.gas_left = Some(budget);
+        assert!(model.execute(&contract, msg.as_slice(), &[]).is_ok());
+        assert!(matches!(
+            metered.execute(&contract, msg.as_slice(), &[]),
+            Err(Error::OutOfGas(_))
+        ));
+        model.gas_left = None;
+        metered.gas_left = None;
+
+        metered.disable_query_metering();
+        let free = model.execute(&contract, msg.as_slice(), &[]).unwrap();
+        let charged = metered.execute(&contract, msg.as_slice(), &[]).unwrap();
+        assert_eq!(charged.gas_used, free.gas_used);
+    }
+
+    #[test]
+    fn test_wasm_query_at() {
+        use test_contract::msg::{InstantiateMsg, QueryMsg, ReadNumberResponse};
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract = debug_log.contract_address.unwrap();
+        let (height, time) = {
+            let states = model.states.read().unwrap();
+            (states.block_number, states.block_timestamp)
+        };
+
+        // nested queries run with the overridden block as well
+        let msg = to_binary(&QueryMsg::Recurse { depth: 2 }).unwrap();
+        let week = 7 * 24 * 60 * 60;
+        let response = model
+            .wasm_query_at(
+                &contract,
+                msg.as_slice(),
+                height + 100_000,
+                time.plus_seconds(week),
+            )
+            .unwrap();
+        let response: ReadNumberResponse = from_binary(&response).unwrap();
+        assert_eq!(response.value, 2);
+
+        let states = model.states.read().unwrap();
+        assert_eq!(states.block_number, height);
+        assert_eq!(states.block_timestamp, time);
+    }
+
     #[test]
     fn test_add_custom_code() {
         use test_contract::msg::{InstantiateMsg, QueryMsg, ReadNumberResponse};
@@ -1022,6 +6754,285 @@ mod test {
         assert_eq!(query_res.value, 1);
     }
 
+    #[test]
+    fn test_typed_calls() {
+        use test_contract::msg::{InstantiateMsg, QueryMsg, ReadNumberResponse};
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract_address = debug_log.contract_address.clone().unwrap();
+        assert_eq!(
+            contract_address.as_str(),
+            get_contract_address_from_log(&debug_log.logs).unwrap()
+        );
+        assert_eq!(
+            model.custom_code_checksum(1337).unwrap(),
+            code_checksum(code)
+        );
+        assert!(model.custom_code_checksum(1338).is_none());
+        let query_res: ReadNumberResponse = model
+            .query_typed(&contract_address, &QueryMsg::ReadNumber {})
+            .unwrap();
+        assert_eq!(query_res.value, 1);
+        // the error tells which query could not be parsed
+        let err = model
+            .query_typed::<_, Vec<u64>>(&contract_address, &QueryMsg::ReadNumber {})
+            .unwrap_err();
+        assert!(err.to_string().contains("read_number"));
+    }
+
+    #[test]
+    fn test_add_code_from_path() {
+        use test_contract::msg::InstantiateMsg;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let path = std::path::Path::new(env!("OUT_DIR"))
+            .join("wasm32-unknown-unknown/release/test_contract.wasm");
+        model.add_code_from_path(1337, &path).unwrap();
+        assert!(model
+            .add_code_from_path(1338, &path.with_file_name("missing.wasm"))
+            .is_err());
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
+        assert!(get_contract_address_from_log(&debug_log.logs).is_some());
+    }
+
+    #[test]
+    fn test_store_code() {
+        use test_contract::msg::InstantiateMsg;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let code_id = model.store_code(code).unwrap();
+        assert_eq!(code_id, STORED_CODE_ID_START);
+        assert_eq!(model.store_code(code).unwrap(), code_id + 1);
+        let info = model.code_info(code_id).unwrap();
+        assert_eq!(info.creator, BASE_EOA);
+        assert_eq!(info.checksum, code_checksum(code));
+        assert!(model.code_info(code_id + 2).is_err());
+
+        let debug_log = model
+            .instantiate_typed(code_id, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract_address = debug_log.contract_address.unwrap();
+        assert_eq!(
+            model.contract_info(&contract_address).unwrap().code_id,
+            code_id
+        );
+    }
+
+    #[test]
+    fn test_code_info_query() {
+        use crate::CodeInfoQuery;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let code_id = model.store_code(code).unwrap();
+
+        // cosmwasm 1.2 contracts query with WasmQuery::CodeInfo
+        let mut states = model.states.write().unwrap();
+        for (code_id, creator) in [(code_id, BASE_EOA), (1337, "")] {
+            let query: CodeInfoQuery =
+                serde_json::from_value(json!({ "code_info": { "code_id": code_id } })).unwrap();
+            let res: serde_json::Value =
+                from_binary(&states.code_info_query(&query).unwrap()).unwrap();
+            assert_eq!(res["code_id"], code_id);
+            assert_eq!(res["creator"], creator);
+            assert_eq!(res["checksum"], hex::encode(code_checksum(code)));
+        }
+        drop(states);
+
+        let debug_log = model
+            .instantiate_typed(1337, &test_contract::msg::InstantiateMsg {}, &[])
+            .unwrap();
+        let contract_addr = debug_log.contract_address.unwrap();
+        assert_eq!(
+            model.contract_checksum(&contract_addr).unwrap(),
+            code_checksum(code)
+        );
+    }
+
+    #[test]
+    fn test_register_schema() {
+        use cosmwasm_schema::schema_for;
+        use test_contract::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let mut schema = ContractSchema::default();
+        for (kind, msg_schema) in [
+            (MsgKind::Instantiate, schema_for!(InstantiateMsg)),
+            (MsgKind::Execute, schema_for!(ExecuteMsg)),
+            (MsgKind::Query, schema_for!(QueryMsg)),
+        ] {
+            let msg_schema = serde_json::to_value(msg_schema).unwrap();
+            schema.set_msg_schema(kind, &msg_schema).unwrap();
+        }
+        model.register_schema(1337, schema);
+
+        let err = model
+            .instantiate(1337, br#"{"number":1}"#, &[])
+            .unwrap_err();
+        assert!(err.to_string().contains("unexpected fields number"));
+        let debug_log = model
+            .instantiate_typed(1337, &InstantiateMsg {}, &[])
+            .unwrap();
+        let contract_address =
+            Addr::unchecked(get_contract_address_from_log(&debug_log.logs).unwrap());
+        let err = model
+            .execute(&contract_address, br#"{"test_gas_limit":{}}"#, &[])
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("\"gas_limit\" is a required property"));
+        let err = model
+            .wasm_query(&contract_address, br#"{"recurse":{"depth":-1}}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("at /recurse/depth"));
+        model
+            .wasm_query(&contract_address, br#"{"read_number":{}}"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_seed_and_repro_report() {
+        use crate::ReproReport;
+        use rand::Rng;
+        use test_contract::msg::InstantiateMsg;
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let instantiate = |seed: Option<u64>| {
+            let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+            if let Some(seed) = seed {
+                model.set_seed(seed);
+            }
+            model.add_custom_code(1337, code).unwrap();
+            let msg = to_binary(&InstantiateMsg {}).unwrap();
+            let debug_log = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
+            let address = get_contract_address_from_log(&debug_log.logs).unwrap();
+            (model, address)
+        };
+        let (unseeded, unseeded_address) = instantiate(None);
+        let (mut seeded, seeded_address) = instantiate(Some(7));
+        assert_ne!(seeded_address, unseeded_address);
+        let (mut reseeded, reseeded_address) = instantiate(Some(7));
+        assert_eq!(seeded_address, reseeded_address);
+        assert_eq!(seeded.rng().gen::<u64>(), reseeded.rng().gen::<u64>());
+
+        let report = seeded.repro_report();
+        assert_eq!(report.block_number, MALAGA_BLOCK_NUMBER);
+        assert_eq!(report.seed, Some(7));
+        assert!(report.rpc_cache_key.is_some());
+        assert_eq!(report.custom_codes[0].code_id, 1337);
+        let report = ReproReport::from_json(&report.to_json().unwrap()).unwrap();
+        assert!(report.diff(&reseeded.repro_report()).is_empty());
+        assert_eq!(report.diff(&unseeded.repro_report()).len(), 1);
+    }
+
+    #[test]
+    fn test_contract_admin() {
+        use test_contract::msg::InstantiateMsg;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let admin = model.create_account("admin", &[]).unwrap();
+        let other = model.create_account("other", &[]).unwrap();
+        model.switch_account("admin").unwrap();
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model
+            .instantiate_with_admin(1337, msg.as_slice(), &[], Some(&admin), "test")
+            .unwrap();
+        let contract_address =
+            Addr::unchecked(get_contract_address_from_log(&debug_log.logs).unwrap());
+        let info = model.contract_info(&contract_address).unwrap();
+        assert_eq!(info.code_id, 1337);
+        assert_eq!(info.creator, admin.to_string());
+        assert_eq!(info.admin, Some(admin.to_string()));
+        assert_eq!(info.label, "test");
+
+        // only the admin may change the admin
+        model.switch_account("other").unwrap();
+        let debug_log = model.update_admin(&contract_address, &other).unwrap();
+        assert!(debug_log.err_msg.unwrap().starts_with("unauthorized"));
+        let debug_log = model.migrate(&contract_address, 1337, b"{}").unwrap();
+        assert!(debug_log.err_msg.unwrap().starts_with("unauthorized"));
+        model.switch_account("admin").unwrap();
+        let debug_log = model.update_admin(&contract_address, &other).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(
+            model.contract_info(&contract_address).unwrap().admin,
+            Some(other.to_string())
+        );
+
+        // admin checks can be disabled, e.g. to migrate contracts whose admin is unknown
+        let debug_log = model.clear_admin(&contract_address).unwrap();
+        assert!(debug_log.err_msg.unwrap().starts_with("unauthorized"));
+        model.set_admin_checks(false);
+        let debug_log = model.clear_admin(&contract_address).unwrap();
+        assert!(debug_log.err_msg.is_none());
+        assert_eq!(model.contract_info(&contract_address).unwrap().admin, None);
+
+        // contract info of contracts on chain is fetched
+        let pair_address = Addr::unchecked(PAIR_ADDRESS_MALAGA);
+        let info = model.contract_info(&pair_address).unwrap();
+        assert!(!info.creator.is_empty());
+    }
+
+    #[test]
+    fn test_contract_info_query() {
+        use cosmwasm_std::{ContractInfoResponse, QueryRequest, WasmQuery};
+        use test_contract::msg::InstantiateMsg;
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let admin = model.create_account("admin", &[]).unwrap();
+        model.switch_account("admin").unwrap();
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model
+            .instantiate_with_admin(1337, msg.as_slice(), &[], Some(&admin), "test")
+            .unwrap();
+        let contract_address = debug_log.contract_address.unwrap();
+
+        let request = QueryRequest::Wasm(WasmQuery::ContractInfo {
+            contract_addr: contract_address.to_string(),
+        });
+        let response: ContractInfoResponse = from_binary(&model.query(&request).unwrap()).unwrap();
+        assert_eq!(response.code_id, 1337);
+        assert_eq!(response.creator, admin.to_string());
+        assert_eq!(response.admin, Some(admin.to_string()));
+        assert!(!response.pinned);
+        // the test contract has no ibc entry points
+        assert_eq!(response.ibc_port, None);
+
+        model.pin_code(1337);
+        let response: ContractInfoResponse = from_binary(&model.query(&request).unwrap()).unwrap();
+        assert!(response.pinned);
+        model.unpin_code(1337);
+        let response: ContractInfoResponse = from_binary(&model.query(&request).unwrap()).unwrap();
+        assert!(!response.pinned);
+    }
+
     #[test]
     fn test_call_trace() {
         let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
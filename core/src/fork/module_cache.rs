@@ -0,0 +1,303 @@
+use cosmwasm_vm::internals::{compile, make_runtime_store};
+use cosmwasm_vm::Size;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, debug_span, trace, warn};
+use wasmer::Module;
+
+use crate::Error;
+
+const DEFAULT_MODULE_CACHE_SIZE: usize = 128;
+const MODULE_CACHE_DIRNAME: &str = ".cw-sim-module-cache";
+
+/// default location of serialized modules, separated by wasmer version
+/// because serialized modules are not compatible across wasmer versions.
+/// None without a home directory: loading modules runs their native code, so they must not be
+/// read from a directory other users can write to, such as /tmp
+pub fn default_module_cache_dir() -> Option<PathBuf> {
+    let homedir = env::var("HOME").ok().filter(|home| !home.is_empty())?;
+    Some(
+        Path::new(&homedir)
+            .join(MODULE_CACHE_DIRNAME)
+            .join(format!("wasmer-{}", wasmer::VERSION)),
+    )
+}
+
+pub fn code_checksum(code: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(code);
+    hasher.finalize().to_vec()
+}
+
+struct ModuleCacheInner {
+    modules: HashMap<Vec<u8>, Module>,
+    // least recently used checksum comes first
+    lru: VecDeque<Vec<u8>>,
+    capacity: usize,
+    // directory of serialized modules, None if disk caching is disabled
+    disk_cache_dir: Option<PathBuf>,
+    // maximum memory of instances in bytes, modules are compiled for a single limit
+    memory_limit: Option<usize>,
+}
+
+impl ModuleCacheInner {
+    fn touch(&mut self, checksum: &[u8]) {
+        if let Some(pos) = self.lru.iter().position(|c| c == checksum) {
+            let checksum = self.lru.remove(pos).unwrap();
+            self.lru.push_back(checksum);
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.modules.len() > self.capacity {
+            match self.lru.pop_front() {
+                Some(checksum) => {
+                    self.modules.remove(&checksum);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// LRU cache of compiled modules keyed by code checksum.
+/// Clones share the same cache, so a Model and all of its clones never compile the same code twice.
+#[derive(Clone)]
+pub struct ModuleCache {
+    inner: Arc<Mutex<ModuleCacheInner>>,
+}
+
+impl ModuleCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ModuleCacheInner {
+                modules: HashMap::new(),
+                lru: VecDeque::new(),
+                capacity,
+                disk_cache_dir: None,
+                memory_limit: None,
+            })),
+        }
+    }
+
+    pub fn get(&self, checksum: &[u8]) -> Option<Module> {
+        let mut inner = self.inner.lock().unwrap();
+        let module = inner.modules.get(checksum).cloned();
+        if module.is_some() {
+            inner.touch(checksum);
+        }
+        module
+    }
+
+    pub fn insert(&self, checksum: Vec<u8>, module: Module) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.modules.insert(checksum.clone(), module).is_some() {
+            inner.touch(&checksum);
+        } else {
+            inner.lru.push_back(checksum);
+        }
+        inner.evict();
+    }
+
+    /// returns the cached module for code, compiling it on a cache miss
+    pub fn get_or_compile(&self, code: &[u8]) -> Result<Module, Error> {
+        let checksum = code_checksum(code);
+        if let Some(module) = self.get(&checksum) {
+            trace!(checksum = %hex::encode(&checksum), "module cache hit");
+            return Ok(module);
+        }
+        let (disk_cache_dir, memory_limit) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.disk_cache_dir.clone(), inner.memory_limit)
+        };
+        if let Some(module) = disk_cache_dir
+            .as_ref()
+            .and_then(|dir| Self::load_from_disk(dir, &checksum, memory_limit))
+        {
+            debug!(checksum = %hex::encode(&checksum), "module loaded from disk");
+            self.insert(checksum, module.clone());
+            return Ok(module);
+        }
+        let _span = debug_span!("compile", checksum = %hex::encode(&checksum), bytes = code.len())
+            .entered();
+        // compile without holding the lock, compilation may take seconds
+        let module = compile(code, memory_limit.map(Size), &[]).map_err(Error::from)?;
+        debug!("module compiled");
+        if let Some(dir) = disk_cache_dir {
+            // the module is usable without the disk cache, e.g. if the home directory is read-only
+            if let Err(e) = Self::store_to_disk(&dir, &checksum, memory_limit, &module) {
+                warn!(dir = %dir.display(), error = %e, "failed to cache the compiled module");
+            }
+        }
+        self.insert(checksum, module.clone());
+        Ok(module)
+    }
+
+    /// returns None if the module is not cached or cannot be deserialized
+    /// in the latter case, the module is simply recompiled
+    fn load_from_disk(dir: &Path, checksum: &[u8], memory_limit: Option<usize>) -> Option<Module> {
+        let path = dir.join(Self::file_name(checksum, memory_limit));
+        if !path.is_file() {
+            return None;
+        }
+        let store = make_runtime_store(memory_limit.map(Size));
+        // the cache directory is trusted to contain modules serialized by store_to_disk
+        unsafe { Module::deserialize_from_file(&store, path) }.ok()
+    }
+
+    fn store_to_disk(
+        dir: &Path,
+        checksum: &[u8],
+        memory_limit: Option<usize>,
+        module: &Module,
+    ) -> Result<(), Error> {
+        static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(dir).map_err(Error::from)?;
+        let path = dir.join(Self::file_name(checksum, memory_limit));
+        // other processes sharing the directory must never see a partially written module
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(format!(
+            ".{}-{}.tmp",
+            process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp_path = PathBuf::from(tmp_path);
+        let result = module
+            .serialize_to_file(&tmp_path)
+            .map_err(Error::io_error)
+            .and_then(|_| fs::rename(&tmp_path, &path).map_err(Error::from));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    /// the memory limit changes the memory styles of compiled modules
+    fn file_name(checksum: &[u8], memory_limit: Option<usize>) -> String {
+        match memory_limit {
+            Some(limit) => format!("{}-{}", hex::encode(checksum), limit),
+            None => hex::encode(checksum),
+        }
+    }
+
+    /// persist compiled modules in dir, so that later runs can skip compilation
+    /// None disables disk caching
+    pub fn set_disk_cache_dir(&self, dir: Option<PathBuf>) {
+        self.inner.lock().unwrap().disk_cache_dir = dir;
+    }
+
+    /// limit the memory of instances of the modules to memory_limit bytes, None for no limit.
+    /// Modules compiled with a different limit are dropped
+    pub fn set_memory_limit(&self, memory_limit: Option<usize>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.memory_limit != memory_limit {
+            inner.memory_limit = memory_limit;
+            inner.modules.clear();
+            inner.lru.clear();
+        }
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.inner.lock().unwrap().memory_limit
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        inner.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ModuleCache {
+    fn default() -> Self {
+        let cache = Self::new(DEFAULT_MODULE_CACHE_SIZE);
+        cache.set_disk_cache_dir(default_module_cache_dir());
+        cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleCache;
+
+    const WASM_A: &str = "(module)";
+    const WASM_B: &str = "(module (func))";
+    const WASM_C: &str = "(module (func) (func))";
+    const WASM_MEMORY: &str = r#"(module (memory (export "memory") 1))"#;
+
+    #[test]
+    fn test_module_cache_lru() {
+        let cache = ModuleCache::new(2);
+        let a = wat::parse_str(WASM_A).unwrap();
+        let b = wat::parse_str(WASM_B).unwrap();
+        let c = wat::parse_str(WASM_C).unwrap();
+        cache.get_or_compile(&a).unwrap();
+        cache.get_or_compile(&b).unwrap();
+        // a becomes the most recently used, so b is evicted
+        cache.get_or_compile(&a).unwrap();
+        cache.get_or_compile(&c).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&super::code_checksum(&a)).is_some());
+        assert!(cache.get(&super::code_checksum(&b)).is_none());
+
+        // clones share the cache
+        let shared = cache.clone();
+        shared.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_module_cache_disk() {
+        let dir = std::env::temp_dir().join(format!("cw-sim-module-cache-{}", std::process::id()));
+        let code = wat::parse_str(WASM_B).unwrap();
+        let cache = ModuleCache::new(2);
+        cache.set_disk_cache_dir(Some(dir.clone()));
+        cache.get_or_compile(&code).unwrap();
+        let path = dir.join(hex::encode(super::code_checksum(&code)));
+        assert!(path.is_file());
+
+        // a fresh cache loads the serialized module instead of compiling
+        let cache = ModuleCache::new(2);
+        cache.set_disk_cache_dir(Some(dir.clone()));
+        assert!(ModuleCache::load_from_disk(&dir, &super::code_checksum(&code), None).is_some());
+        cache.get_or_compile(&code).unwrap();
+        assert_eq!(cache.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_module_cache_disk_memory_limit() {
+        let dir =
+            std::env::temp_dir().join(format!("cw-sim-module-cache-limit-{}", std::process::id()));
+        let code = wat::parse_str(WASM_MEMORY).unwrap();
+        let checksum = super::code_checksum(&code);
+        let limit = 2 * 64 * 1024;
+        let cache = ModuleCache::new(2);
+        cache.set_disk_cache_dir(Some(dir.clone()));
+        cache.set_memory_limit(Some(limit));
+        cache.get_or_compile(&code).unwrap();
+
+        // modules compiled for a limit are not loaded for another one
+        assert!(ModuleCache::load_from_disk(&dir, &checksum, None).is_none());
+        let module = ModuleCache::load_from_disk(&dir, &checksum, Some(limit)).unwrap();
+        let instance = wasmer::Instance::new(&module, &wasmer::imports! {}).unwrap();
+        let memory = instance.exports.get_memory("memory").unwrap();
+        assert!(memory.grow(1).is_ok());
+        assert!(memory.grow(1).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+use cosmwasm_std::{Binary, Env, MessageInfo, Response, StdError, StdResult};
+use std::sync::Arc;
+
+/// a contract implemented in rust instead of wasm, e.g. to stub an oracle with fixed prices.
+/// Native contracts have no code nor storage, so their calls are neither metered nor covered.
+/// Handlers run while the model is borrowed, they must not call back into it
+pub trait NativeContract: Send + Sync {
+    /// errors fail the call like the errors of wasm contracts
+    fn execute(&self, env: &Env, info: &MessageInfo, msg: &[u8]) -> StdResult<Response>;
+
+    fn query(&self, env: &Env, msg: &[u8]) -> StdResult<Binary>;
+}
+
+type ExecuteFn = dyn Fn(&Env, &MessageInfo, &[u8]) -> StdResult<Response> + Send + Sync;
+type QueryFn = dyn Fn(&Env, &[u8]) -> StdResult<Binary> + Send + Sync;
+
+/// native contract made of closures. Calls without a closure fail
+#[derive(Clone, Default)]
+pub struct NativeHandler {
+    execute: Option<Arc<ExecuteFn>>,
+    query: Option<Arc<QueryFn>>,
+}
+
+impl NativeHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_execute<F>(mut self, execute: F) -> Self
+    where
+        F: Fn(&Env, &MessageInfo, &[u8]) -> StdResult<Response> + Send + Sync + 'static,
+    {
+        self.execute = Some(Arc::new(execute));
+        self
+    }
+
+    pub fn with_query<F>(mut self, query: F) -> Self
+    where
+        F: Fn(&Env, &[u8]) -> StdResult<Binary> + Send + Sync + 'static,
+    {
+        self.query = Some(Arc::new(query));
+        self
+    }
+}
+
+impl NativeContract for NativeHandler {
+    fn execute(&self, env: &Env, info: &MessageInfo, msg: &[u8]) -> StdResult<Response> {
+        match &self.execute {
+            Some(execute) => execute(env, info, msg),
+            None => Err(StdError::generic_err(format!(
+                "native contract {} does not handle execute messages",
+                env.contract.address
+            ))),
+        }
+    }
+
+    fn query(&self, env: &Env, msg: &[u8]) -> StdResult<Binary> {
+        match &self.query {
+            Some(query) => query(env, msg),
+            None => Err(StdError::generic_err(format!(
+                "native contract {} does not handle queries",
+                env.contract.address
+            ))),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use cosmwasm_std::{Coin, Decimal, Uint128, Uint256};
+use prost::Message;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use crate::rpc_items::osmosis::gamm::v1beta1::{Pool, QueryPoolRequest, QueryPoolResponse};
+use crate::{CwClientBackend, Error};
+
+pub const BALANCER_POOL_TYPE_URL: &str = "/osmosis.gamm.v1beta1.Pool";
+// sdk.Dec is encoded as its atomics
+const DEC_PLACES: u32 = 18;
+
+/// balancer pools of the Osmosis gamm module, fetched from chain on first use.
+/// Swaps are emulated against the local copies, other pool types are not supported
+#[derive(Clone, Debug, Default)]
+pub struct Gamm {
+    pools: BTreeMap<u64, Pool>,
+}
+
+impl Gamm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// pool with id, fetching it if it is not known yet
+    pub fn pool(
+        &mut self,
+        client: &mut dyn CwClientBackend,
+        pool_id: u64,
+    ) -> Result<&mut Pool, Error> {
+        let entry = match self.pools.entry(pool_id) {
+            Entry::Occupied(entry) => return Ok(entry.into_mut()),
+            Entry::Vacant(entry) => entry,
+        };
+        let request = QueryPoolRequest { pool_id };
+        let out =
+            client.query_grpc("/osmosis.gamm.v1beta1.Query/Pool", &request.encode_to_vec())?;
+        let resp = QueryPoolResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        let any = resp.pool.unwrap_or_default();
+        if any.type_url != BALANCER_POOL_TYPE_URL {
+            return Err(Error::invalid_argument(format!(
+                "pool {} of type {} is not supported",
+                pool_id, any.type_url
+            )));
+        }
+        let pool = Pool::decode(any.value.as_slice()).map_err(Error::format_error)?;
+        Ok(entry.insert(pool))
+    }
+
+    /// local copy of the pool, None if it was never used by the simulation
+    pub fn local_pool(&self, pool_id: u64) -> Option<&Pool> {
+        self.pools.get(&pool_id)
+    }
+
+    /// replaces the pool with the same id
+    pub fn set_pool(&mut self, pool: Pool) {
+        self.pools.insert(pool.id, pool);
+    }
+}
+
+/// parses a protobuf encoded sdk.Dec
+pub fn parse_dec(atomics: &str) -> Result<Decimal, String> {
+    let atomics = Uint128::from_str(atomics).map_err(|e| e.to_string())?;
+    Decimal::from_atomics(atomics, DEC_PLACES).map_err(|e| e.to_string())
+}
+
+/// formats a decimal the same way as sdk.Dec.String, with all 18 decimal places
+pub fn format_dec(dec: Decimal) -> String {
+    let atomics = dec.atomics().u128();
+    let unit = 10u128.pow(DEC_PLACES);
+    format!("{}.{:018}", atomics / unit, atomics % unit)
+}
+
+/// balance and weight of denom in the pool
+fn asset(pool: &Pool, denom: &str) -> Result<(Uint128, Uint128), String> {
+    let asset = pool
+        .pool_assets
+        .iter()
+        .find(|a| a.token.as_ref().map(|t| t.denom.as_str()) == Some(denom))
+        .ok_or_else(|| format!("{} does not exist in pool {}", denom, pool.id))?;
+    let balance =
+        Uint128::from_str(&asset.token.as_ref().unwrap().amount).map_err(|e| e.to_string())?;
+    let weight = Uint128::from_str(&asset.weight).map_err(|e| e.to_string())?;
+    Ok((balance, weight))
+}
+
+fn add_to_asset(pool: &mut Pool, denom: &str, amount: Uint128, sub: bool) {
+    for token in pool.pool_assets.iter_mut().filter_map(|a| a.token.as_mut()) {
+        if token.denom == denom {
+            let balance = Uint128::from_str(&token.amount).unwrap_or_default();
+            let balance = if sub {
+                balance.saturating_sub(amount)
+            } else {
+                balance + amount
+            };
+            token.amount = balance.to_string();
+        }
+    }
+}
+
+/// amount of token_out_denom received for token_in, following the balancer formula
+/// out = balance_out * (1 - (balance_in / (balance_in + in * (1 - swap_fee))) ^ (weight_in / weight_out)).
+/// The result is exact for pools with equal weights, and approximated with floats otherwise
+pub fn calc_out_given_in(
+    pool: &Pool,
+    token_in: &Coin,
+    token_out_denom: &str,
+) -> Result<Uint128, String> {
+    if token_in.denom == token_out_denom {
+        return Err("cannot trade the same denom".to_string());
+    }
+    let (balance_in, weight_in) = asset(pool, &token_in.denom)?;
+    let (balance_out, weight_out) = asset(pool, token_out_denom)?;
+    let swap_fee = pool
+        .pool_params
+        .as_ref()
+        .map(|p| parse_dec(&p.swap_fee))
+        .transpose()?
+        .unwrap_or_default();
+    let unit = Uint256::from(10u128.pow(DEC_PLACES));
+    // atomics of in * (1 - swap_fee)
+    let adjusted_in = Uint256::from(token_in.amount) * (unit - Uint256::from(swap_fee.atomics()));
+    let out = if weight_in == weight_out {
+        let out = Uint256::from(balance_out) * adjusted_in
+            / (Uint256::from(balance_in) * unit + adjusted_in);
+        Uint128::try_from(out).map_err(|e| e.to_string())?
+    } else {
+        let swap_fee = swap_fee.atomics().u128() as f64 / 10f64.powi(DEC_PLACES as i32);
+        let adjusted_in = token_in.amount.u128() as f64 * (1.0 - swap_fee);
+        let y = balance_in.u128() as f64 / (balance_in.u128() as f64 + adjusted_in);
+        let ratio = weight_in.u128() as f64 / weight_out.u128() as f64;
+        Uint128::new((balance_out.u128() as f64 * (1.0 - y.powf(ratio))).max(0.0) as u128)
+    };
+    if out >= balance_out {
+        return Err(format!("token amount {} exceeds the pool balance", out));
+    }
+    Ok(out)
+}
+
+/// swaps token_in for token_out_denom, updating the pool balances
+pub fn swap_exact_amount_in(
+    pool: &mut Pool,
+    token_in: &Coin,
+    token_out_denom: &str,
+) -> Result<Uint128, String> {
+    let out = calc_out_given_in(pool, token_in, token_out_denom)?;
+    add_to_asset(pool, &token_in.denom, token_in.amount, false);
+    add_to_asset(pool, token_out_denom, out, true);
+    Ok(out)
+}
+
+/// price of base_denom in quote_denom, without the swap fee
+pub fn spot_price(pool: &Pool, base_denom: &str, quote_denom: &str) -> Result<Decimal, String> {
+    let (balance_base, weight_base) = asset(pool, base_denom)?;
+    let (balance_quote, weight_quote) = asset(pool, quote_denom)?;
+    if balance_base.is_zero() || weight_quote.is_zero() {
+        return Err(format!("pool {} is empty", pool.id));
+    }
+    let unit = Uint256::from(10u128.pow(DEC_PLACES));
+    let atomics = Uint256::from(balance_quote) * Uint256::from(weight_base) * unit
+        / (Uint256::from(balance_base) * Uint256::from(weight_quote));
+    let atomics = Uint128::try_from(atomics).map_err(|e| e.to_string())?;
+    Decimal::from_atomics(atomics, DEC_PLACES).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{Coin, Decimal, Uint128};
+
+    use super::{calc_out_given_in, format_dec, parse_dec, spot_price, swap_exact_amount_in};
+    use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+    use crate::rpc_items::osmosis::gamm::v1beta1::{Pool, PoolAsset, PoolParams};
+
+    fn pool(assets: &[(&str, u128, u128)]) -> Pool {
+        Pool {
+            id: 1,
+            pool_params: Some(PoolParams {
+                // 0.2%
+                swap_fee: "2000000000000000".to_string(),
+                exit_fee: "0".to_string(),
+            }),
+            pool_assets: assets
+                .iter()
+                .map(|(denom, amount, weight)| PoolAsset {
+                    token: Some(ProtoCoin {
+                        denom: denom.to_string(),
+                        amount: amount.to_string(),
+                    }),
+                    weight: weight.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_balancer_swap() {
+        assert_eq!(parse_dec("2000000000000000").unwrap(), Decimal::permille(2));
+        assert_eq!(format_dec(Decimal::percent(150)), "1.500000000000000000");
+
+        let mut equal = pool(&[("uatom", 1_000_000, 1), ("uosmo", 2_000_000, 1)]);
+        assert_eq!(
+            spot_price(&equal, "uatom", "uosmo").unwrap(),
+            Decimal::percent(200)
+        );
+        // 2_000_000 * 998 / (1_000_000 + 998)
+        let out = swap_exact_amount_in(&mut equal, &Coin::new(1000, "uatom"), "uosmo").unwrap();
+        assert_eq!(out, Uint128::new(1994));
+        assert_eq!(
+            calc_out_given_in(&equal, &Coin::new(1000, "uatom"), "uosmo").unwrap(),
+            Uint128::new(1990)
+        );
+        assert!(calc_out_given_in(&equal, &Coin::new(1000, "uion"), "uosmo").is_err());
+
+        // a 4:1 weighted pool gives roughly the same price as the equal one
+        let weighted = pool(&[("uatom", 1_000_000, 4), ("uosmo", 500_000, 1)]);
+        assert_eq!(
+            spot_price(&weighted, "uatom", "uosmo").unwrap(),
+            Decimal::percent(200)
+        );
+        let out = calc_out_given_in(&weighted, &Coin::new(1000, "uatom"), "uosmo").unwrap();
+        assert!(out.u128() > 1980 && out.u128() < 1996);
+    }
+}
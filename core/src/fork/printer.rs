@@ -0,0 +1,185 @@
+use crate::{DebugLog, ExecutionHooks};
+use cosmwasm_std::{from_binary, to_binary, Binary, ContractResult};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// smart queries to this address are handled by the simulator instead of a contract,
+/// contracts use it to print debug messages, see PrinterMsg
+pub const PRINTER_ADDR: &str = "supergodprinter";
+
+/// the original print request, recorded in DebugLog::stdout
+#[derive(Serialize, Deserialize)]
+struct PrintRequest {
+    msg: String,
+}
+
+/// every printer request is answered with an ack
+#[derive(Serialize, Deserialize)]
+struct PrintResponse {
+    ack: bool,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PrintLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// structured requests to PRINTER_ADDR, recorded in DebugLog::prints
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrinterMsg {
+    Log {
+        level: PrintLevel,
+        msg: String,
+    },
+    /// recorded at debug level as a hexdump of data
+    Hexdump {
+        label: String,
+        data: Binary,
+    },
+    /// recorded at debug level, and pauses execution in ExecutionHooks::on_breakpoint
+    Breakpoint {
+        label: String,
+    },
+}
+
+/// a message printed by a contract
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Print {
+    pub level: PrintLevel,
+    pub msg: String,
+    /// call in the call trace which printed the message
+    pub call_id: usize,
+}
+
+/// 16 bytes per line with their offset and ascii representation, like hexdump -C
+pub fn hexdump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out += &format!("{:08x}  {:<47}  |{}|\n", i * 16, hex.join(" "), ascii);
+    }
+    out
+}
+
+/// handles a smart query to PRINTER_ADDR
+pub(crate) fn handle_print(
+    msg: &Binary,
+    debug_log: &Arc<Mutex<DebugLog>>,
+    hooks: Option<Arc<dyn ExecutionHooks>>,
+) -> ContractResult<Binary> {
+    let ack = to_binary(&PrintResponse { ack: true }).unwrap();
+    if let Ok(request) = from_binary::<PrintRequest>(msg) {
+        debug_log.lock().unwrap().append_stdout(&request.msg);
+        return ContractResult::Ok(ack);
+    }
+    let request: PrinterMsg = match from_binary(msg) {
+        Ok(request) => request,
+        Err(e) => return ContractResult::Err(format!("invalid printer request: {}", e)),
+    };
+    match request {
+        PrinterMsg::Log { level, msg } => {
+            debug_log.lock().unwrap().append_print(level, &msg);
+        }
+        PrinterMsg::Hexdump { label, data } => {
+            let msg = format!("{}:\n{}", label, hexdump(data.as_slice()));
+            debug_log
+                .lock()
+                .unwrap()
+                .append_print(PrintLevel::Debug, &msg);
+        }
+        PrinterMsg::Breakpoint { label } => {
+            let mut log = debug_log.lock().unwrap();
+            log.append_print(PrintLevel::Debug, &format!("breakpoint {}", label));
+            let call = log
+                .call_trace
+                .call_graph_labels
+                .get(&log.call_trace.current_call_id())
+                .cloned()
+                .unwrap_or_default();
+            // hooks may block until the user resumes, the log must stay usable meanwhile
+            drop(log);
+            if let Some(hooks) = hooks {
+                hooks.on_breakpoint(&label, &call);
+            }
+        }
+    }
+    ContractResult::Ok(ack)
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{to_binary, Binary};
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    use super::{handle_print, hexdump, PrintLevel, PrinterMsg};
+    use crate::{DebugLog, HookEvent, HookRecorder};
+
+    #[test]
+    fn test_handle_print() {
+        let debug_log = Arc::new(Mutex::new(DebugLog::new()));
+        let recorder = Arc::new(HookRecorder::new());
+        let print = |msg: Binary| handle_print(&msg, &debug_log, Some(recorder.clone()));
+
+        assert!(print(to_binary(&json!({ "msg": "hello" })).unwrap()).is_ok());
+        let msg = PrinterMsg::Log {
+            level: PrintLevel::Warn,
+            msg: "low balance".to_string(),
+        };
+        assert!(print(to_binary(&msg).unwrap()).is_ok());
+        let msg = PrinterMsg::Hexdump {
+            label: "key".to_string(),
+            data: Binary::from(b"ab"),
+        };
+        assert!(print(to_binary(&msg).unwrap()).is_ok());
+        let msg = PrinterMsg::Breakpoint {
+            label: "before swap".to_string(),
+        };
+        assert!(print(to_binary(&msg).unwrap()).is_ok());
+        assert!(print(to_binary(&json!({ "unknown": {} })).unwrap()).is_err());
+
+        let log = debug_log.lock().unwrap();
+        assert_eq!(log.get_stdout(), "hello");
+        assert_eq!(log.prints.len(), 3);
+        assert_eq!(log.prints_at(PrintLevel::Warn).len(), 1);
+        assert!(log.prints[1].msg.starts_with("key:\n00000000  61 62"));
+        assert_eq!(
+            recorder.events(),
+            vec![HookEvent::Breakpoint {
+                label: "before swap".to_string(),
+                call: "top".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let data: Vec<u8> = (0x3e..0x50).collect();
+        let expected = format!(
+            "00000000  3e 3f 40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d  |>?@ABCDEFGHIJKLM|\n\
+             00000010  4e 4f{}  |NO|\n",
+            " ".repeat(42)
+        );
+        assert_eq!(hexdump(&data), expected);
+        assert_eq!(
+            hexdump(b"a\x00"),
+            format!("00000000  61 00{}  |a.|\n", " ".repeat(42))
+        );
+    }
+}
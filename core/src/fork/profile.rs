@@ -0,0 +1,226 @@
+use cosmwasm_std::Addr;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::fork::labels::short_address;
+
+/// a single call to a contract, e.g. an execute or a reply, see Model::profile_report
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameProfile {
+    pub contract: Addr,
+    /// instantiate, execute, migrate, reply or the ibc entry point called
+    pub entry_point: String,
+    /// number of calls the call is nested in, 0 for top-level calls
+    pub depth: usize,
+    /// wall-clock time of the call, including the calls it made
+    pub total_time: Duration,
+    /// wall-clock time of the call, excluding the calls it made. Queries of other contracts
+    /// are not calls, their time is counted here
+    pub self_time: Duration,
+    /// gas used by the wasm code of the call
+    pub gas_used: u64,
+}
+
+/// the frames of a contract added up, see Model::profile_report
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractProfile {
+    pub contract: Addr,
+    pub calls: u64,
+    /// reentrant calls are counted once, in the time of the outermost call
+    pub total_time: Duration,
+    pub self_time: Duration,
+    pub gas_used: u64,
+}
+
+/// the key contracts are sorted by in a ProfileReport, in descending order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileSort {
+    TotalTime,
+    SelfTime,
+    Gas,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileReport {
+    /// the hottest contract first
+    pub contracts: Vec<ContractProfile>,
+    /// in the order the calls finished, i.e. nested calls before their callers
+    pub frames: Vec<FrameProfile>,
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>8} {:>12} {:>12} {:>14}",
+            "contract", "calls", "total (ms)", "self (ms)", "gas"
+        )?;
+        for contract in self.contracts.iter() {
+            writeln!(
+                f,
+                "{:<16} {:>8} {:>12.3} {:>12.3} {:>14}",
+                short_address(contract.contract.as_str()),
+                contract.calls,
+                contract.total_time.as_secs_f64() * 1000.0,
+                contract.self_time.as_secs_f64() * 1000.0,
+                contract.gas_used
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// a call which has not finished yet
+#[derive(Clone, Debug)]
+struct OpenFrame {
+    contract: Addr,
+    entry_point: String,
+    start: Instant,
+    // total time of the calls it made so far
+    children_time: Duration,
+    gas_used: u64,
+    // whether the contract is not on the stack below this frame
+    outermost: bool,
+}
+
+/// times the calls made by a model, see Model::enable_profiling
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profiler {
+    stack: Vec<OpenFrame>,
+    frames: Vec<FrameProfile>,
+    // total time of the outermost calls of each contract
+    outermost_time: BTreeMap<Addr, Duration>,
+}
+
+impl Profiler {
+    pub fn enter(&mut self, entry_point: &str, contract: &Addr) {
+        let outermost = self.stack.iter().all(|frame| &frame.contract != contract);
+        self.stack.push(OpenFrame {
+            contract: contract.clone(),
+            entry_point: entry_point.to_string(),
+            start: Instant::now(),
+            children_time: Duration::ZERO,
+            gas_used: 0,
+            outermost,
+        });
+    }
+
+    /// attributes gas to the innermost unfinished call
+    pub fn add_gas(&mut self, gas_used: u64) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.gas_used += gas_used;
+        }
+    }
+
+    pub fn exit(&mut self) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let total_time = frame.start.elapsed();
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children_time += total_time;
+        }
+        if frame.outermost {
+            *self
+                .outermost_time
+                .entry(frame.contract.clone())
+                .or_default() += total_time;
+        }
+        self.frames.push(FrameProfile {
+            depth: self.stack.len(),
+            contract: frame.contract,
+            entry_point: frame.entry_point,
+            total_time,
+            self_time: total_time.saturating_sub(frame.children_time),
+            gas_used: frame.gas_used,
+        });
+    }
+
+    pub fn report(&self, sort: ProfileSort) -> ProfileReport {
+        let mut contracts: BTreeMap<&Addr, ContractProfile> = BTreeMap::new();
+        for frame in self.frames.iter() {
+            let profile = contracts
+                .entry(&frame.contract)
+                .or_insert_with(|| ContractProfile {
+                    contract: frame.contract.clone(),
+                    calls: 0,
+                    total_time: self
+                        .outermost_time
+                        .get(&frame.contract)
+                        .copied()
+                        .unwrap_or_default(),
+                    self_time: Duration::ZERO,
+                    gas_used: 0,
+                });
+            profile.calls += 1;
+            profile.self_time += frame.self_time;
+            profile.gas_used += frame.gas_used;
+        }
+        let mut contracts: Vec<ContractProfile> = contracts.into_values().collect();
+        match sort {
+            ProfileSort::TotalTime => contracts.sort_by(|a, b| b.total_time.cmp(&a.total_time)),
+            ProfileSort::SelfTime => contracts.sort_by(|a, b| b.self_time.cmp(&a.self_time)),
+            ProfileSort::Gas => contracts.sort_by(|a, b| b.gas_used.cmp(&a.gas_used)),
+        }
+        ProfileReport {
+            contracts,
+            frames: self.frames.clone(),
+        }
+    }
+
+    /// forgets finished calls, calls still running are kept
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.outermost_time.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::{ProfileSort, Profiler};
+
+    #[test]
+    fn test_profiler() {
+        let router = Addr::unchecked("router");
+        let pair = Addr::unchecked("pair");
+        let mut profiler = Profiler::default();
+        profiler.enter("execute", &router);
+        profiler.add_gas(100);
+        profiler.enter("execute", &pair);
+        profiler.add_gas(20);
+        sleep(Duration::from_millis(20));
+        profiler.exit();
+        profiler.enter("reply", &router);
+        profiler.add_gas(10);
+        profiler.exit();
+        profiler.exit();
+
+        let report = profiler.report(ProfileSort::SelfTime);
+        assert_eq!(report.frames.len(), 3);
+        assert_eq!(report.frames[0].contract, pair);
+        assert_eq!(report.frames[0].depth, 1);
+        assert_eq!(report.frames[2].depth, 0);
+        assert!(report.frames[2].total_time >= report.frames[0].total_time);
+        assert!(report.frames[2].self_time < report.frames[2].total_time);
+
+        // the reentrant reply is counted once in the total time of the router
+        assert_eq!(report.contracts[0].contract, pair);
+        let router_profile = &report.contracts[1];
+        assert_eq!(router_profile.calls, 2);
+        assert_eq!(router_profile.gas_used, 110);
+        assert_eq!(router_profile.total_time, report.frames[2].total_time);
+
+        let report = profiler.report(ProfileSort::Gas);
+        assert_eq!(report.contracts[0].contract, router);
+
+        profiler.clear();
+        assert!(profiler.report(ProfileSort::TotalTime).contracts.is_empty());
+    }
+}
@@ -1,35 +1,98 @@
 use crate::fork::AllStates;
-use crate::{ContractState, DebugLog, Error, RpcContractInstance, RpcMockApi, RpcMockStorage};
+use crate::{
+    code_checksum, shared_storage, CodeInfoQuery, ContractState, DebugLog, DenomMetadataQuery,
+    DistributionQuery, Error, InstancePool, ModuleCache, NativeContract, RpcContractInstance,
+    RpcInstance, RpcMockStorage, StorageTracer,
+};
 use cosmwasm_std::{
-    from_binary, from_slice, to_binary, Addr, Binary, ContractInfo, ContractResult, Env,
-    QueryRequest, SystemResult, WasmQuery,
+    from_slice, Addr, Binary, BlockInfo, ContractInfo, ContractResult, Env, QueryRequest,
+    SystemError, SystemResult, WasmQuery,
 };
-use cosmwasm_vm::{Backend, BackendError, BackendResult, GasInfo, InstanceOptions, Querier};
-use serde::{Deserialize, Serialize};
+use cosmwasm_vm::internals::instance_from_module;
+use cosmwasm_vm::{Backend, BackendError, BackendResult, GasInfo, Querier};
+use serde::Deserialize;
+use tracing::debug_span;
 
 use std::sync::{Arc, Mutex, RwLock};
 
+use super::capabilities::query_capability;
 use super::model::maybe_unzip;
+use super::printer::{handle_print, PRINTER_ADDR};
 
-#[derive(Clone)]
-pub struct RpcMockQuerier {
-    states: Arc<RwLock<AllStates>>,
-    debug_log: Arc<Mutex<DebugLog>>,
+/// queries which the vendored QueryRequest can't parse
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MetadataQueryRequest {
+    Bank(DenomMetadataQuery),
+    Wasm(CodeInfoQuery),
+    Distribution(DistributionQuery),
 }
 
-const PRINTER_ADDR: &str = "supergodprinter";
+/// how reentrant calls and queries nested deeper than the limit are handled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecursionPolicy {
+    /// record a warning in DebugLog::warnings and continue
+    Warn,
+    /// record a warning and fail the call
+    Abort,
+}
 
-#[derive(Serialize, Deserialize)]
-struct PrintRequest {
-    msg: String,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecursionConfig {
+    pub policy: RecursionPolicy,
+    /// queries nested deeper than this are refused regardless of the policy,
+    /// since every nested query runs a new instance on the native stack
+    pub max_query_depth: usize,
 }
 
-#[derive(Serialize, Deserialize)]
-struct PrintResponse {
-    ack: bool,
+impl Default for RecursionConfig {
+    fn default() -> Self {
+        Self {
+            policy: RecursionPolicy::Warn,
+            // same as the default max query stack size of wasmd
+            max_query_depth: 10,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcMockQuerier {
+    states: Arc<RwLock<AllStates>>,
+    debug_log: Arc<Mutex<DebugLog>>,
+    // trace storage accesses of queried contracts
+    trace_storage: bool,
+    // charge the gas of queried contracts to the querying instance, see Model::enable_query_metering
+    meter_queries: bool,
+    recursion: RecursionConfig,
+    // number of queries the contract using this querier is nested in
+    query_depth: usize,
+    // block seen by queried contracts instead of the current one, see Model::wasm_query_at
+    block: Option<BlockInfo>,
+    // compiled modules and idle instances of queried contracts, shared with the model
+    wasm_cache: ModuleCache,
+    instance_pool: Option<InstancePool>,
 }
 
 impl RpcMockQuerier {
+    // queries of capabilities the chain doesn't advertise are unknown to it
+    fn unsupported(&self, capability: &str) -> Option<SystemResult<ContractResult<Binary>>> {
+        if self
+            .states
+            .read()
+            .unwrap()
+            .capabilities
+            .contains(capability)
+        {
+            return None;
+        }
+        Some(SystemResult::Err(SystemError::UnsupportedRequest {
+            kind: format!(
+                "query requires the {} capability, which the chain does not advertise",
+                capability
+            ),
+        }))
+    }
+
     fn fetch_contract_state(&self, contract_addr: &Addr) -> Result<(), Error> {
         if self
             .states
@@ -40,12 +103,20 @@ impl RpcMockQuerier {
         {
             return Ok(());
         }
+        self.states
+            .read()
+            .unwrap()
+            .check_fetched_address(contract_addr)?;
         let contract_info = self
             .states
             .write()
             .unwrap()
             .client
             .query_wasm_contract_info(contract_addr.as_str())?;
+        self.states
+            .read()
+            .unwrap()
+            .check_fetched_code_id(contract_addr, contract_info.code_id)?;
         let wasm_code = maybe_unzip(
             self.states
                 .write()
@@ -53,15 +124,16 @@ impl RpcMockQuerier {
                 .client
                 .query_wasm_contract_code(contract_info.code_id)?,
         )?;
+        let (storage, lazy) = self
+            .states
+            .write()
+            .unwrap()
+            .fetch_contract_storage(contract_addr)?;
         let contract_state = ContractState {
-            code: wasm_code,
-            storage: Arc::new(RwLock::new(
-                self.states
-                    .write()
-                    .unwrap()
-                    .client
-                    .query_wasm_contract_state_all(contract_addr.as_str())?,
-            )),
+            code: Arc::new(wasm_code),
+            storage: shared_storage(storage),
+            info: contract_info,
+            lazy,
         };
         self.states
             .write()
@@ -72,17 +144,19 @@ impl RpcMockQuerier {
 
     fn env(&self, contract_addr: &Addr) -> Result<Env, Error> {
         let states = self.states.read().unwrap();
-        let block_number = states.block_number;
-        let block_timestamp = states.block_timestamp;
-        let chain_id = states.chain_id.to_string();
-        Ok(Env {
-            block: cosmwasm_std::BlockInfo {
-                height: block_number,
-                time: block_timestamp,
-                chain_id,
+        let block = match &self.block {
+            Some(block) => block.clone(),
+            None => BlockInfo {
+                height: states.block_number,
+                time: states.block_timestamp,
+                chain_id: states.chain_id.to_string(),
             },
-            // assumption: all blocks have only 1 transaction
-            transaction: Some(cosmwasm_std::TransactionInfo { index: 0 }),
+        };
+        Ok(Env {
+            block,
+            transaction: Some(cosmwasm_std::TransactionInfo {
+                index: states.tx_index,
+            }),
             // I don't really know what this is for, so for now, set it to the target contract address
             contract: ContractInfo {
                 address: contract_addr.clone(),
@@ -90,9 +164,106 @@ impl RpcMockQuerier {
         })
     }
 
-    fn mock_storage(&self, contract_state: &ContractState) -> Result<RpcMockStorage, Error> {
-        let storage = RpcMockStorage::new(&contract_state.storage);
-        Ok(storage)
+    // native contracts have no storage, so raw queries find nothing
+    fn native_query(
+        &self,
+        native: &Arc<dyn NativeContract>,
+        contract_addr: &Addr,
+        wasm_query: &WasmQuery,
+    ) -> ContractResult<Binary> {
+        match wasm_query {
+            WasmQuery::Smart { msg, .. } => match self.env(contract_addr) {
+                Ok(env) => native.query(&env, msg.as_slice()).into(),
+                Err(e) => ContractResult::Err(e.to_string()),
+            },
+            WasmQuery::Raw { .. } => ContractResult::Ok(Binary::default()),
+            _ => ContractResult::Err(format!(
+                "native contract {} has no contract info",
+                contract_addr
+            )),
+        }
+    }
+
+    fn mock_storage(
+        &self,
+        contract_addr: &Addr,
+        contract_state: &ContractState,
+    ) -> Result<RpcMockStorage, Error> {
+        let storage =
+            RpcMockStorage::new(&contract_state.storage).with_lazy(contract_state.lazy.clone());
+        if self.trace_storage {
+            Ok(storage.with_tracer(StorageTracer::new(contract_addr, &self.debug_log)))
+        } else {
+            Ok(storage)
+        }
+    }
+
+    /// an instance of the contract with storage, querier and gas_limit for a new query,
+    /// taken from the instance pool if possible
+    fn instance(
+        &self,
+        contract_addr: &Addr,
+        contract_state: &ContractState,
+        checksum: &[u8],
+        gas_limit: u64,
+    ) -> Result<RpcInstance, Error> {
+        let storage = self.mock_storage(contract_addr, contract_state)?;
+        let querier = RpcMockQuerier::new(&self.states, &self.debug_log)
+            .with_storage_trace(self.trace_storage)
+            .with_query_metering(self.meter_queries)
+            .with_recursion(self.recursion, self.query_depth + 1)
+            .with_block(self.block.clone())
+            .with_module_cache(&self.wasm_cache);
+        let querier = match &self.instance_pool {
+            Some(pool) => querier.with_instance_pool(pool),
+            None => querier,
+        };
+        let pooled = self
+            .instance_pool
+            .as_ref()
+            .and_then(|pool| pool.take(contract_addr, checksum));
+        if let Some(mut instance) = pooled {
+            instance
+                .with_storage(|s| {
+                    *s = storage;
+                    Ok(())
+                })
+                .map_err(Error::from)?;
+            instance
+                .with_querier(|q| {
+                    *q = querier;
+                    Ok(())
+                })
+                .map_err(Error::from)?;
+            instance.set_gas_left(gas_limit);
+            return Ok(instance);
+        }
+
+        let states = self.states.read().unwrap();
+        let api = states.api()?;
+        drop(states);
+        let deps = Backend {
+            storage,
+            api,
+            querier,
+        };
+        let module = self.wasm_cache.get_or_compile(&contract_state.code)?;
+        instance_from_module(&module, deps, gas_limit, false, None).map_err(Error::from)
+    }
+
+    /// returns a finished instance to the instance pool
+    fn release(&self, contract_addr: &Addr, checksum: Vec<u8>, mut instance: RpcInstance) {
+        if let Some(pool) = &self.instance_pool {
+            // pooled instances must not hold the pool, or it would never be dropped
+            let detached = RpcMockQuerier::new(&self.states, &self.debug_log);
+            let detached = instance.with_querier(|q| {
+                *q = detached;
+                Ok(())
+            });
+            if detached.is_ok() {
+                pool.put(contract_addr, checksum, instance);
+            }
+        }
     }
 }
 
@@ -100,8 +271,28 @@ impl Querier for RpcMockQuerier {
     fn query_raw(
         &self,
         request: &[u8],
-        _gas_limit: u64,
+        gas_limit: u64,
     ) -> BackendResult<SystemResult<ContractResult<Binary>>> {
+        if let Ok(request) = from_slice::<MetadataQueryRequest>(request) {
+            let capability = match request {
+                MetadataQueryRequest::Bank(_) => "cosmwasm_1_3",
+                MetadataQueryRequest::Wasm(_) => "cosmwasm_1_2",
+                MetadataQueryRequest::Distribution(_) => "cosmwasm_1_4",
+            };
+            if let Some(result) = self.unsupported(capability) {
+                return (Ok(result), GasInfo::free());
+            }
+            let mut states = self.states.write().unwrap();
+            let result = match request {
+                MetadataQueryRequest::Bank(query) => states.denom_metadata_query(&query),
+                MetadataQueryRequest::Wasm(query) => states.code_info_query(&query),
+                MetadataQueryRequest::Distribution(query) => states.distribution_query(&query),
+            };
+            return (
+                Ok(SystemResult::Ok(result.map_err(|e| e.to_string()).into())),
+                GasInfo::free(),
+            );
+        }
         let request: QueryRequest<()> = match from_slice(request) {
             Ok(v) => v,
             Err(e) => {
@@ -111,6 +302,9 @@ impl Querier for RpcMockQuerier {
                 )
             }
         };
+        if let Some(result) = query_capability(&request).and_then(|c| self.unsupported(c)) {
+            return (Ok(result), GasInfo::free());
+        }
 
         match request {
             QueryRequest::Bank(bank_query) => {
@@ -141,25 +335,68 @@ impl Querier for RpcMockQuerier {
                             contract_addr: _,
                             msg,
                         } => {
-                            let msg: PrintRequest = from_binary(&msg).unwrap();
-                            self.debug_log.lock().unwrap().append_stdout(&msg.msg);
-                            let resp = to_binary(&PrintResponse { ack: true }).unwrap();
-                            (
-                                Ok(SystemResult::Ok(ContractResult::Ok(resp))),
-                                GasInfo::free(),
-                            )
+                            let hooks = self.states.read().unwrap().hooks.clone();
+                            let result = handle_print(&msg, &self.debug_log, hooks);
+                            (Ok(SystemResult::Ok(result)), GasInfo::free())
                         }
                         _ => {
                             panic!("invalid query to printer");
                         }
                     }
                 } else {
+                    let _span =
+                        debug_span!("query", contract = %contract_addr, depth = self.query_depth)
+                            .entered();
+                    if self.query_depth >= self.recursion.max_query_depth {
+                        let msg = format!(
+                            "query to {} exceeds the maximum query depth of {}",
+                            contract_addr, self.recursion.max_query_depth
+                        );
+                        let mut debug_log = self.debug_log.lock().unwrap();
+                        debug_log.append_warning(&msg);
+                        if self.recursion.policy == RecursionPolicy::Abort {
+                            debug_log.abort(&msg);
+                        }
+                        return (
+                            Ok(SystemResult::Ok(ContractResult::Err(msg))),
+                            GasInfo::free(),
+                        );
+                    }
+                    let native = self
+                        .states
+                        .read()
+                        .unwrap()
+                        .native_contracts
+                        .get(&contract_addr)
+                        .cloned();
+                    if let Some(native) = native {
+                        return (
+                            Ok(SystemResult::Ok(self.native_query(
+                                &native,
+                                &contract_addr,
+                                &wasm_query,
+                            ))),
+                            GasInfo::free(),
+                        );
+                    }
                     if let Err(e) = self.fetch_contract_state(&contract_addr) {
                         return (
                             Err(BackendError::Unknown { msg: e.to_string() }),
                             GasInfo::free(),
                         );
                     }
+                    // answered by wasmd from the contract metadata, without calling the contract
+                    if let WasmQuery::ContractInfo { .. } = wasm_query {
+                        let result = self
+                            .states
+                            .write()
+                            .unwrap()
+                            .contract_info_query(&contract_addr);
+                        return (
+                            Ok(SystemResult::Ok(result.map_err(|e| e.to_string()).into())),
+                            GasInfo::free(),
+                        );
+                    }
                     let env = match self.env(&contract_addr) {
                         Ok(e) => e,
                         Err(e) => {
@@ -176,53 +413,28 @@ impl Querier for RpcMockQuerier {
                         .contract_state_get(&contract_addr)
                         .unwrap()
                         .clone();
-                    let states = self.states.read().unwrap();
-                    let canonical_address_length = states.canonical_address_length;
-                    let bech32_prefix = states.bech32_prefix.to_string();
-                    drop(states);
-                    let storage = match self.mock_storage(&contract_state) {
-                        Ok(s) => s,
-                        Err(e) => {
-                            return (
-                                Err(BackendError::Unknown { msg: e.to_string() }),
-                                GasInfo::free(),
-                            );
-                        }
-                    };
-                    let api =
-                        match RpcMockApi::new(canonical_address_length, bech32_prefix.as_str()) {
-                            Ok(a) => a,
-                            Err(e) => {
-                                return (
-                                    Err(BackendError::Unknown { msg: e.to_string() }),
-                                    GasInfo::free(),
-                                );
-                            }
-                        };
-                    let deps = Backend {
-                        storage,
-                        api,
-                        querier: RpcMockQuerier::new(&self.states, &self.debug_log),
-                    };
-                    let options = InstanceOptions {
-                        gas_limit: u64::MAX,
-                        print_debug: false,
+                    let checksum = code_checksum(&contract_state.code);
+                    // the querying contract runs out of gas if the query uses more than it has left
+                    let gas_limit = if self.meter_queries {
+                        gas_limit
+                    } else {
+                        u64::MAX
                     };
-                    let wasm_instance = match cosmwasm_vm::Instance::from_code(
-                        contract_state.code.as_slice(),
-                        deps,
-                        options,
-                        None,
+                    let mut instance = match self.instance(
+                        &contract_addr,
+                        &contract_state,
+                        &checksum,
+                        gas_limit,
                     ) {
+                        Ok(i) => RpcContractInstance::new(&contract_addr, i)
+                            .with_code_id(contract_state.info.code_id),
                         Err(e) => {
                             return (
                                 Err(BackendError::Unknown { msg: e.to_string() }),
                                 GasInfo::free(),
                             );
                         }
-                        Ok(i) => i,
                     };
-                    let mut instance = RpcContractInstance::new(&contract_addr, wasm_instance);
                     let call_id = if let WasmQuery::Smart {
                         contract_addr: _,
                         msg,
@@ -238,15 +450,22 @@ impl Querier for RpcMockQuerier {
                         None
                     };
 
-                    let result = match instance.query(&env, &wasm_query) {
-                        Ok(response) => (
-                            Ok(SystemResult::Ok(ContractResult::Ok(response))),
-                            GasInfo::free(),
-                        ),
-                        Err(e) => (
-                            Err(BackendError::Unknown { msg: e.to_string() }),
-                            GasInfo::free(),
-                        ),
+                    let result = instance.query(&env, &wasm_query);
+                    let gas_info = if self.meter_queries {
+                        GasInfo::with_externally_used(
+                            gas_limit.saturating_sub(instance.instance.get_gas_left()),
+                        )
+                    } else {
+                        GasInfo::free()
+                    };
+                    let result = match result {
+                        Ok(response) => {
+                            // instances which trapped are not reused
+                            self.release(&contract_addr, checksum, instance.instance);
+                            (Ok(SystemResult::Ok(ContractResult::Ok(response))), gas_info)
+                        }
+                        Err(Error::OutOfGas(_)) => (Err(BackendError::out_of_gas()), gas_info),
+                        Err(e) => (Err(BackendError::Unknown { msg: e.to_string() }), gas_info),
                     };
 
                     if let Some(call_id) = call_id {
@@ -256,7 +475,33 @@ impl Querier for RpcMockQuerier {
                     result
                 }
             }
-            _ => unimplemented!(),
+            QueryRequest::Staking(staking_query) => {
+                let result = self.states.write().unwrap().staking_query(&staking_query);
+                (
+                    Ok(SystemResult::Ok(result.map_err(|e| e.to_string()).into())),
+                    GasInfo::free(),
+                )
+            }
+            QueryRequest::Stargate { path, data } => {
+                let result = self.states.write().unwrap().stargate_query(&path, &data);
+                (
+                    Ok(SystemResult::Ok(result.map_err(|e| e.to_string()).into())),
+                    GasInfo::free(),
+                )
+            }
+            QueryRequest::Ibc(ibc_query) => {
+                let result = self.states.read().unwrap().ibc_query(&ibc_query);
+                (
+                    Ok(SystemResult::Ok(result.map_err(|e| e.to_string()).into())),
+                    GasInfo::free(),
+                )
+            }
+            _ => (
+                Ok(SystemResult::Err(SystemError::UnsupportedRequest {
+                    kind: "query not implemented by the simulator".to_string(),
+                })),
+                GasInfo::free(),
+            ),
         }
     }
 }
@@ -266,8 +511,48 @@ impl RpcMockQuerier {
         Self {
             states: states.clone(),
             debug_log: debug_log.clone(),
+            trace_storage: false,
+            meter_queries: false,
+            recursion: RecursionConfig::default(),
+            query_depth: 0,
+            block: None,
+            wasm_cache: ModuleCache::default(),
+            instance_pool: None,
         }
     }
+
+    pub fn with_storage_trace(mut self, trace_storage: bool) -> Self {
+        self.trace_storage = trace_storage;
+        self
+    }
+
+    pub fn with_query_metering(mut self, meter_queries: bool) -> Self {
+        self.meter_queries = meter_queries;
+        self
+    }
+
+    pub fn with_recursion(mut self, recursion: RecursionConfig, query_depth: usize) -> Self {
+        self.recursion = recursion;
+        self.query_depth = query_depth;
+        self
+    }
+
+    /// queried contracts see block instead of the current block if set
+    pub fn with_block(mut self, block: Option<BlockInfo>) -> Self {
+        self.block = block;
+        self
+    }
+
+    pub fn with_module_cache(mut self, wasm_cache: &ModuleCache) -> Self {
+        self.wasm_cache = wasm_cache.clone();
+        self
+    }
+
+    /// reuse instances of queried contracts, see InstancePool
+    pub fn with_instance_pool(mut self, instance_pool: &InstancePool) -> Self {
+        self.instance_pool = Some(instance_pool.clone());
+        self
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,124 @@
+use crate::{
+    shared_storage, AllStates, ContractState, ContractStorage, DebugLog, Error, ModuleCache,
+    RpcContractInstance, RpcMockQuerier, RpcMockStorage,
+};
+use cosmwasm_std::{from_binary, Addr, BankQuery, Binary, ContractInfo, Env, WasmQuery};
+use cosmwasm_vm::internals::instance_from_module;
+use cosmwasm_vm::Backend;
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::model::maybe_unzip;
+
+/// Read-only view of a Model's states.
+/// Handles are cheap to clone and can be sent to other threads, so queries can run concurrently
+/// with executions happening on the Model that created them.
+/// A handle shares its states with the Model, so it observes the latest committed state
+/// (and the intermediate state of an in-flight execution).
+#[derive(Clone)]
+pub struct QueryHandle {
+    states: Arc<RwLock<AllStates>>,
+    wasm_cache: ModuleCache,
+}
+
+impl QueryHandle {
+    pub fn new(states: &Arc<RwLock<AllStates>>) -> Self {
+        Self {
+            states: states.clone(),
+            wasm_cache: ModuleCache::default(),
+        }
+    }
+
+    /// compile contracts with the given cache, e.g. the one of the Model
+    pub fn with_module_cache(mut self, wasm_cache: &ModuleCache) -> Self {
+        self.wasm_cache = wasm_cache.clone();
+        self
+    }
+
+    /// Does nothing if the state already exists.
+    /// The state is fetched with a copy of the client, so that other queries are not blocked
+    /// behind the lock while waiting for the node
+    fn fetch_contract_state(&self, contract_addr: &Addr) -> Result<(), Error> {
+        let (mut client, lazy) = {
+            let states = self.states.read().unwrap();
+            if states.contract_state_get(contract_addr).is_some() {
+                return Ok(());
+            }
+            (states.client.clone(), states.lazy_source(contract_addr))
+        };
+        let contract_info = client.query_wasm_contract_info(contract_addr.as_str())?;
+        let wasm_code = maybe_unzip(client.query_wasm_contract_code(contract_info.code_id)?)?;
+        // lazily loaded contracts start out empty
+        let storage = match lazy {
+            Some(_) => ContractStorage::new(),
+            None => client.query_wasm_contract_state_all(contract_addr.as_str())?,
+        };
+        let mut states = self.states.write().unwrap();
+        // another thread might have fetched it in the meantime, and written to it since
+        if states.contract_state_get(contract_addr).is_some() {
+            return Ok(());
+        }
+        let contract_state = ContractState {
+            code: Arc::new(wasm_code),
+            storage: shared_storage(storage),
+            info: contract_info,
+            lazy,
+        };
+        states.contract_state_insert(contract_addr.clone(), contract_state);
+        Ok(())
+    }
+
+    fn env(&self, contract_addr: &Addr) -> Env {
+        let states = self.states.read().unwrap();
+        Env {
+            block: cosmwasm_std::BlockInfo {
+                height: states.block_number,
+                time: states.block_timestamp,
+                chain_id: states.chain_id.to_string(),
+            },
+            transaction: Some(cosmwasm_std::TransactionInfo {
+                index: states.tx_index,
+            }),
+            contract: ContractInfo {
+                address: contract_addr.clone(),
+            },
+        }
+    }
+
+    fn create_instance(&self, contract_addr: &Addr) -> Result<RpcContractInstance, Error> {
+        self.fetch_contract_state(contract_addr)?;
+        let states = self.states.read().unwrap();
+        let contract_state = states.contract_state_get(contract_addr).unwrap();
+        // queries issued from a handle are not recorded in the Model's debug log
+        let debug_log = Arc::new(Mutex::new(DebugLog::new()));
+        let deps = Backend {
+            storage: RpcMockStorage::new(&contract_state.storage)
+                .with_lazy(contract_state.lazy.clone()),
+            api: states.api()?,
+            querier: RpcMockQuerier::new(&self.states, &debug_log)
+                .with_module_cache(&self.wasm_cache),
+        };
+        let code = contract_state.code.clone();
+        let code_id = contract_state.info.code_id;
+        drop(states);
+        let module = self.wasm_cache.get_or_compile(&code)?;
+        let wasm_instance =
+            instance_from_module(&module, deps, u64::MAX, false, None).map_err(Error::from)?;
+        Ok(RpcContractInstance::new(contract_addr, wasm_instance).with_code_id(code_id))
+    }
+
+    pub fn wasm_query(&self, contract_addr: &Addr, msg: &[u8]) -> Result<Binary, Error> {
+        let env = self.env(contract_addr);
+        let mut instance = self.create_instance(contract_addr)?;
+        let wasm_query = WasmQuery::Smart {
+            contract_addr: contract_addr.to_string(),
+            msg: Binary::from(msg),
+        };
+        instance.query(&env, &wasm_query)
+    }
+
+    pub fn bank_query(&self, bank_query_: &[u8]) -> Result<Binary, Error> {
+        let bank_query: BankQuery =
+            from_binary(&Binary::from(bank_query_)).map_err(Error::format_error)?;
+        self.states.write().unwrap().bank_query(&bank_query)
+    }
+}
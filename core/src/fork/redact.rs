@@ -0,0 +1,77 @@
+use cosmwasm_std::{CosmosMsg, WasmMsg};
+
+/// codespace and code of a registered cosmos-sdk error
+pub type AbciCode = (&'static str, u32);
+
+pub const INSTANTIATE_FAILED: AbciCode = ("wasm", 4);
+pub const EXECUTE_FAILED: AbciCode = ("wasm", 5);
+pub const MIGRATION_FAILED: AbciCode = ("wasm", 11);
+pub const UNAUTHORIZED: AbciCode = ("sdk", 4);
+const INSUFFICIENT_FUNDS: AbciCode = ("sdk", 5);
+const OUT_OF_GAS: AbciCode = ("sdk", 11);
+/// errors which are not registered, e.g. of modules the simulation does not know the codes of
+pub const UNDEFINED: AbciCode = ("undefined", 1);
+
+/// the error wasmd fails msg with
+pub fn msg_error_code(msg: &CosmosMsg) -> AbciCode {
+    match msg {
+        CosmosMsg::Wasm(WasmMsg::Instantiate { .. }) => INSTANTIATE_FAILED,
+        CosmosMsg::Wasm(WasmMsg::Execute { .. }) => EXECUTE_FAILED,
+        CosmosMsg::Wasm(WasmMsg::Migrate { .. }) => MIGRATION_FAILED,
+        CosmosMsg::Wasm(WasmMsg::UpdateAdmin { .. })
+        | CosmosMsg::Wasm(WasmMsg::ClearAdmin { .. }) => UNAUTHORIZED,
+        _ => UNDEFINED,
+    }
+}
+
+/// err of a message failing with code as wasmd passes it to reply, which only keeps the codespace
+/// and code of the error since error messages are not deterministic. Running out of gas and
+/// lacking funds fail with the errors of the sdk whatever the message
+pub fn redact_error(code: AbciCode, err: &str) -> String {
+    let (codespace, code) = if err.starts_with("out of gas") {
+        OUT_OF_GAS
+    } else if err.contains("insufficient balance") || err.contains("insufficient funds") {
+        INSUFFICIENT_FUNDS
+    } else {
+        code
+    };
+    format!("codespace: {}, code: {}", codespace, code)
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{to_binary, BankMsg, CosmosMsg, Empty, WasmMsg};
+
+    use super::{msg_error_code, redact_error, EXECUTE_FAILED, UNDEFINED};
+
+    #[test]
+    fn test_redact_error() {
+        let execute: CosmosMsg<Empty> = CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "wasm1contract".to_string(),
+            msg: to_binary(&()).unwrap(),
+            funds: vec![],
+        });
+        assert_eq!(msg_error_code(&execute), EXECUTE_FAILED);
+        let send: CosmosMsg<Empty> = CosmosMsg::Bank(BankMsg::Burn { amount: vec![] });
+        assert_eq!(msg_error_code(&send), UNDEFINED);
+
+        assert_eq!(
+            redact_error(EXECUTE_FAILED, "Generic error: not the owner"),
+            "codespace: wasm, code: 5"
+        );
+        assert_eq!(
+            redact_error(
+                EXECUTE_FAILED,
+                "out of gas: submessage exceeded its gas limit of 1"
+            ),
+            "codespace: sdk, code: 11"
+        );
+        assert_eq!(
+            redact_error(
+                EXECUTE_FAILED,
+                "insufficient balance (owner: wasm1a, balance: 0, amount: 1)"
+            ),
+            "codespace: sdk, code: 5"
+        );
+    }
+}
@@ -0,0 +1,384 @@
+use crate::{Acknowledgement, DebugLog, Error, Model};
+use cosmwasm_std::{
+    Binary, IbcChannel, IbcChannelCloseMsg, IbcChannelConnectMsg, IbcChannelOpenMsg, IbcEndpoint,
+    IbcOrder, IbcPacket,
+};
+
+// connections, like the light clients they stand for, are not simulated
+const CONNECTION_ID: &str = "connection-0";
+
+/// order in which the packets of unordered channels are delivered.
+/// Packets of ordered channels are always delivered in the order they were sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryOrder {
+    Sent,
+    Reversed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RelayConfig {
+    pub delivery_order: DeliveryOrder,
+    /// blocks produced on the receiving chain before packets are delivered, to let them time out
+    pub delay_blocks: u64,
+    /// rounds after which relay gives up on packets which keep triggering new packets
+    pub max_rounds: usize,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            delivery_order: DeliveryOrder::Sent,
+            delay_blocks: 0,
+            max_rounds: 100,
+        }
+    }
+}
+
+/// a packet relayed between linked models
+#[derive(Clone, Debug)]
+pub struct RelayedPacket {
+    pub packet: IbcPacket,
+    /// None if the packet timed out
+    pub acknowledgement: Option<Binary>,
+    /// receipt on the destination, None if the packet timed out
+    pub receive_log: Option<DebugLog>,
+    /// acknowledgement or timeout on the source
+    pub source_log: DebugLog,
+}
+
+/// two models of different chains linked by a relayer, which opens channels between them and
+/// relays the packets they send each other. Relayer transactions are signed by the current
+/// sender of each model
+pub struct LinkedModels {
+    pub a: Model,
+    pub b: Model,
+    config: RelayConfig,
+}
+
+/// the log of a relayer transaction, or why it failed
+fn check_log(log: DebugLog) -> Result<DebugLog, Error> {
+    match &log.err_msg {
+        Some(e) => Err(Error::vm_error(e)),
+        None => Ok(log),
+    }
+}
+
+/// packets in the order they are delivered in
+fn delivery_order<F>(packets: Vec<IbcPacket>, order: DeliveryOrder, ordered: F) -> Vec<IbcPacket>
+where
+    F: Fn(&IbcPacket) -> bool,
+{
+    let mut packets = packets;
+    if order == DeliveryOrder::Reversed {
+        packets.reverse();
+        // packets of ordered channels keep their slots, but are put back in order
+        let slots: Vec<usize> = (0..packets.len())
+            .filter(|i| ordered(&packets[*i]))
+            .collect();
+        let mut in_order: Vec<IbcPacket> = slots.iter().map(|i| packets[*i].clone()).collect();
+        in_order
+            .sort_by(|x, y| (&x.src.channel_id, x.sequence).cmp(&(&y.src.channel_id, y.sequence)));
+        for (slot, packet) in slots.into_iter().zip(in_order) {
+            packets[slot] = packet;
+        }
+    }
+    packets
+}
+
+/// whether channel_id of model is open
+fn is_open(model: &Model, channel_id: &str) -> bool {
+    model.ibc_channel(channel_id).is_some_and(|c| c.open)
+}
+
+/// delivers packets sent by src to dst, and their acknowledgements or timeouts back to src
+fn relay_packets(
+    src: &mut Model,
+    dst: &mut Model,
+    packets: Vec<IbcPacket>,
+    config: &RelayConfig,
+    relayed: &mut Vec<RelayedPacket>,
+) -> Result<(), Error> {
+    if packets.is_empty() {
+        return Ok(());
+    }
+    if config.delay_blocks > 0 {
+        dst.produce_block(config.delay_blocks);
+    }
+    let packets = delivery_order(packets, config.delivery_order, |p| {
+        src.ibc_channel(&p.src.channel_id)
+            .is_some_and(|c| c.channel.order == IbcOrder::Ordered)
+    });
+    for packet in packets {
+        let closed =
+            !is_open(src, &packet.src.channel_id) || !is_open(dst, &packet.dest.channel_id);
+        if closed || dst.is_ibc_timed_out(&packet.timeout) {
+            let source_log = src.ibc_packet_timeout(&packet, &src.sender())?;
+            relayed.push(RelayedPacket {
+                packet,
+                acknowledgement: None,
+                receive_log: None,
+                source_log,
+            });
+            continue;
+        }
+        let receive_log = dst.ibc_packet_receive(&packet, &dst.sender())?;
+        let ack = match (&receive_log.err_msg, &receive_log.data) {
+            (Some(e), _) => Acknowledgement::error(e),
+            (None, data) => data.clone().unwrap_or_default(),
+        };
+        let source_log = src.ibc_packet_ack(&packet, &ack, &src.sender())?;
+        relayed.push(RelayedPacket {
+            packet,
+            acknowledgement: Some(ack),
+            receive_log: Some(receive_log),
+            source_log,
+        });
+    }
+    Ok(())
+}
+
+/// closes the ends of channels of dst whose counterparty on src was closed
+fn close_counterparties(src: &Model, dst: &mut Model) -> Result<(), Error> {
+    for end in src.ibc_channels().into_iter().filter(|c| !c.open) {
+        let counterparty = &end.channel.counterparty_endpoint;
+        if let Some(dst_end) = dst.ibc_channel(&counterparty.channel_id) {
+            if dst_end.open && dst_end.channel.counterparty_endpoint == end.channel.endpoint {
+                let msg = IbcChannelCloseMsg::new_confirm(dst_end.channel);
+                check_log(dst.ibc_channel_close(&msg, &dst.sender())?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl LinkedModels {
+    pub fn new(a: Model, b: Model) -> Self {
+        Self {
+            a,
+            b,
+            config: RelayConfig::default(),
+        }
+    }
+
+    pub fn relay_config(&self) -> RelayConfig {
+        self.config
+    }
+
+    pub fn set_relay_config(&mut self, config: RelayConfig) {
+        self.config = config;
+    }
+
+    /// runs the handshake of a channel between port_a of a and port_b of b, returning the channel
+    /// ids on a and b. The contracts bound to the ports may propose another version
+    pub fn open_channel(
+        &mut self,
+        port_a: &str,
+        port_b: &str,
+        order: IbcOrder,
+        version: &str,
+    ) -> Result<(String, String), Error> {
+        let end_a = IbcEndpoint {
+            port_id: port_a.to_string(),
+            channel_id: self.a.next_ibc_channel_id(),
+        };
+        let end_b = IbcEndpoint {
+            port_id: port_b.to_string(),
+            channel_id: self.b.next_ibc_channel_id(),
+        };
+        let channel =
+            |end: &IbcEndpoint, counterparty: &IbcEndpoint, version: &str| -> IbcChannel {
+                IbcChannel::new(
+                    end.clone(),
+                    counterparty.clone(),
+                    order.clone(),
+                    version,
+                    CONNECTION_ID,
+                )
+            };
+        let version_a = self
+            .a
+            .ibc_channel_open(&IbcChannelOpenMsg::new_init(channel(
+                &end_a, &end_b, version,
+            )))?
+            .unwrap_or_else(|| version.to_string());
+        let version_b = self
+            .b
+            .ibc_channel_open(&IbcChannelOpenMsg::new_try(
+                channel(&end_b, &end_a, &version_a),
+                &version_a,
+            ))?
+            .unwrap_or_else(|| version_a.clone());
+        let msg = IbcChannelConnectMsg::new_ack(channel(&end_a, &end_b, &version_b), &version_b);
+        check_log(self.a.ibc_channel_connect(&msg, &self.a.sender())?)?;
+        let msg = IbcChannelConnectMsg::new_confirm(channel(&end_b, &end_a, &version_b));
+        check_log(self.b.ibc_channel_connect(&msg, &self.b.sender())?)?;
+        Ok((end_a.channel_id, end_b.channel_id))
+    }
+
+    /// relays packets between the models until neither has packets left, acknowledging or timing
+    /// out each packet on its source. Packets sent while relaying are relayed as well, and
+    /// channels closed on one side are closed on the other
+    pub fn relay(&mut self) -> Result<Vec<RelayedPacket>, Error> {
+        let mut relayed = Vec::new();
+        for _ in 0..self.config.max_rounds {
+            close_counterparties(&self.a, &mut self.b)?;
+            close_counterparties(&self.b, &mut self.a)?;
+            let from_a = self.a.take_ibc_packets();
+            let from_b = self.b.take_ibc_packets();
+            if from_a.is_empty() && from_b.is_empty() {
+                return Ok(relayed);
+            }
+            relay_packets(&mut self.a, &mut self.b, from_a, &self.config, &mut relayed)?;
+            relay_packets(&mut self.b, &mut self.a, from_b, &self.config, &mut relayed)?;
+        }
+        Err(Error::invalid_argument(format!(
+            "packets still pending after {} relay rounds",
+            self.config.max_rounds
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{
+        to_binary, Addr, BalanceResponse, BankQuery, Binary, Coin, IbcEndpoint, IbcOrder,
+        IbcPacket, IbcTimeout, IbcTimeoutBlock,
+    };
+
+    use super::{delivery_order, DeliveryOrder, LinkedModels};
+    use crate::{escrow_address, local_denom, Acknowledgement, Model};
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+
+    fn packet(channel_id: &str, sequence: u64) -> IbcPacket {
+        let endpoint = IbcEndpoint {
+            port_id: "transfer".to_string(),
+            channel_id: channel_id.to_string(),
+        };
+        IbcPacket::new(
+            Binary::default(),
+            endpoint.clone(),
+            endpoint,
+            sequence,
+            IbcTimeout::with_block(IbcTimeoutBlock {
+                revision: 0,
+                height: 1,
+            }),
+        )
+    }
+
+    #[test]
+    fn test_delivery_order() {
+        let packets = vec![
+            packet("channel-0", 1),
+            packet("channel-1", 1),
+            packet("channel-0", 2),
+            packet("channel-1", 2),
+        ];
+        let ordered = |p: &IbcPacket| p.src.channel_id == "channel-1";
+        let order = |packets: Vec<IbcPacket>| -> Vec<(String, u64)> {
+            packets
+                .into_iter()
+                .map(|p| (p.src.channel_id, p.sequence))
+                .collect()
+        };
+        assert_eq!(
+            order(delivery_order(
+                packets.clone(),
+                DeliveryOrder::Sent,
+                ordered
+            )),
+            order(packets.clone())
+        );
+        assert_eq!(
+            order(delivery_order(packets, DeliveryOrder::Reversed, ordered)),
+            vec![
+                ("channel-1".to_string(), 1),
+                ("channel-0".to_string(), 2),
+                ("channel-1".to_string(), 2),
+                ("channel-0".to_string(), 1),
+            ]
+        );
+    }
+
+    fn balance(model: &mut Model, address: &Addr, denom: &str) -> u128 {
+        let query = BankQuery::Balance {
+            address: address.to_string(),
+            denom: denom.to_string(),
+        };
+        let resp = model
+            .bank_query(to_binary(&query).unwrap().as_slice())
+            .unwrap();
+        let resp: BalanceResponse = serde_json::from_slice(resp.as_slice()).unwrap();
+        resp.amount.amount.u128()
+    }
+
+    #[test]
+    fn test_ics20_transfer() {
+        let a = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let b = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let mut linked = LinkedModels::new(a, b);
+        assert!(linked
+            .open_channel("transfer", "transfer", IbcOrder::Ordered, "ics20-1")
+            .is_err());
+        let (channel_a, channel_b) = linked
+            .open_channel("transfer", "transfer", IbcOrder::Unordered, "ics20-1")
+            .unwrap();
+        assert_eq!(
+            (channel_a.as_str(), channel_b.as_str()),
+            ("channel-0", "channel-0")
+        );
+
+        let user = linked.a.sender();
+        let before = balance(&mut linked.a, &user, "umlg");
+        let timeout = |model: &Model| {
+            IbcTimeout::with_block(IbcTimeoutBlock {
+                revision: 0,
+                height: model.block_number() + 100,
+            })
+        };
+        let sent = Coin::new(100, "umlg");
+        let log = linked
+            .a
+            .ibc_transfer(&channel_a, user.as_str(), &sent, timeout(&linked.a))
+            .unwrap();
+        assert!(log.err_msg.is_none());
+        let relayed = linked.relay().unwrap();
+        assert_eq!(relayed.len(), 1);
+        assert_eq!(relayed[0].acknowledgement, Some(Acknowledgement::success()));
+
+        let voucher = local_denom(&format!("transfer/{}/umlg", channel_b));
+        assert_eq!(balance(&mut linked.b, &user, &voucher), 100);
+        let escrow = escrow_address("transfer", &channel_a, "wasm");
+        let escrowed = balance(&mut linked.a, &escrow, "umlg");
+        assert_eq!(balance(&mut linked.a, &user, "umlg"), before - 100);
+
+        // vouchers sent back are burned and released from escrow
+        let returned = Coin::new(40, &voucher);
+        let log = linked
+            .b
+            .ibc_transfer(&channel_b, user.as_str(), &returned, timeout(&linked.b))
+            .unwrap();
+        assert!(log.err_msg.is_none());
+        linked.relay().unwrap();
+        assert_eq!(balance(&mut linked.b, &user, &voucher), 60);
+        assert_eq!(balance(&mut linked.a, &user, "umlg"), before - 60);
+        assert_eq!(balance(&mut linked.a, &escrow, "umlg"), escrowed - 40);
+
+        // timed out transfers are refunded
+        let expired = IbcTimeout::with_block(IbcTimeoutBlock {
+            revision: 0,
+            height: linked.b.block_number(),
+        });
+        linked
+            .a
+            .ibc_transfer(&channel_a, user.as_str(), &sent, expired)
+            .unwrap();
+        assert_eq!(balance(&mut linked.a, &user, "umlg"), before - 160);
+        let relayed = linked.relay().unwrap();
+        assert_eq!(relayed[0].acknowledgement, None);
+        assert_eq!(balance(&mut linked.a, &user, "umlg"), before - 60);
+        assert_eq!(balance(&mut linked.b, &user, &voucher), 60);
+    }
+}
@@ -0,0 +1,123 @@
+use cosmwasm_std::Addr;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// code replacing the code of a contract on chain, see Model::cheat_code
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeOverride {
+    pub contract: Addr,
+    /// hex encoded sha256 of the wasm code
+    pub checksum: String,
+}
+
+/// code registered with Model::add_custom_code
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomCode {
+    pub code_id: u64,
+    /// hex encoded sha256 of the wasm code
+    pub checksum: String,
+}
+
+/// everything needed to set up a model which behaves the same as the one the report was taken from,
+/// given the same code and the same sequence of calls
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReproReport {
+    pub url: String,
+    /// block the model was forked from
+    pub block_number: u64,
+    pub chain_id: String,
+    pub bech32_prefix: String,
+    /// file name of the cached rpc responses in ~/.cw-rpc-cache, copy it along to re-run offline
+    pub rpc_cache_key: Option<String>,
+    pub seed: Option<u64>,
+    pub sender: Addr,
+    pub code_overrides: Vec<CodeOverride>,
+    pub custom_codes: Vec<CustomCode>,
+}
+
+impl ReproReport {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::from)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(Error::from)
+    }
+
+    /// differences between this report and other, empty if they describe the same setup
+    pub fn diff(&self, other: &ReproReport) -> Vec<String> {
+        let mut diffs = Vec::new();
+        let mut compare = |name: &str, expected: String, actual: String| {
+            if expected != actual {
+                diffs.push(format!("{}: expected {}, got {}", name, expected, actual));
+            }
+        };
+        compare("url", self.url.clone(), other.url.clone());
+        compare(
+            "block_number",
+            self.block_number.to_string(),
+            other.block_number.to_string(),
+        );
+        compare("chain_id", self.chain_id.clone(), other.chain_id.clone());
+        compare(
+            "bech32_prefix",
+            self.bech32_prefix.clone(),
+            other.bech32_prefix.clone(),
+        );
+        compare(
+            "seed",
+            format!("{:?}", self.seed),
+            format!("{:?}", other.seed),
+        );
+        compare("sender", self.sender.to_string(), other.sender.to_string());
+        compare(
+            "code_overrides",
+            format!("{:?}", self.code_overrides),
+            format!("{:?}", other.code_overrides),
+        );
+        compare(
+            "custom_codes",
+            format!("{:?}", self.custom_codes),
+            format!("{:?}", other.custom_codes),
+        );
+        diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+
+    use super::{CodeOverride, ReproReport};
+
+    #[test]
+    fn test_repro_report() {
+        let report = ReproReport {
+            url: "https://rpc.malaga-420.cosmwasm.com:443".to_string(),
+            block_number: 2246678,
+            chain_id: "malaga-420".to_string(),
+            bech32_prefix: "wasm".to_string(),
+            rpc_cache_key: Some("abcd".to_string()),
+            seed: Some(42),
+            sender: Addr::unchecked("wasm1alice"),
+            code_overrides: vec![CodeOverride {
+                contract: Addr::unchecked("wasm1pair"),
+                checksum: "00".repeat(32),
+            }],
+            custom_codes: vec![],
+        };
+        let json = report.to_json().unwrap();
+        assert_eq!(ReproReport::from_json(&json).unwrap(), report);
+        assert!(report.diff(&report).is_empty());
+
+        // the cache key is not part of the setup
+        let mut other = report.clone();
+        other.rpc_cache_key = None;
+        other.seed = None;
+        assert_eq!(
+            report.diff(&other),
+            vec!["seed: expected Some(42), got None".to_string()]
+        );
+    }
+}
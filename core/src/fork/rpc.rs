@@ -1,6 +1,7 @@
 use bincode;
-use cosmwasm_std::Timestamp;
+use cosmwasm_std::{Event, Timestamp};
 use hex;
+use lazy_static::lazy_static;
 use prost::Message;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -9,32 +10,57 @@ use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::convert::TryInto;
-use std::env;
 use std::fs;
 use std::fs::OpenOptions;
 use std::future::Future;
 use std::hash::Hash;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tendermint::abci;
 use tendermint::block::Height;
 use tendermint::Time;
+use tendermint_rpc::endpoint::abci_query::AbciQuery;
 use tendermint_rpc::{Client, HttpClient};
 use tokio;
+use tokio::runtime::Runtime;
+use tracing::{debug, debug_span, trace};
 
-use super::client_backend::ContractInfo;
-use crate::CwClientBackend;
+use super::client_backend::{
+    is_pruned_message, lowest_height_in, CodeInfo, ContractInfo, DenomMetadata, TxInfo,
+};
 use crate::Error;
+use crate::{CacheConfig, ContractStorage, CwClientBackend};
 
-const RPC_CACHE_DIRNAME: &str = ".cw-rpc-cache";
+// responses of these queries never change, so they can be shared by the caches of all heights
+const IMMUTABLE_QUERIES: &[&str] = &["/cosmwasm.wasm.v1.Query/Code"];
 
-fn rwopen<P: AsRef<Path>>(path: P) -> std::io::Result<fs::File> {
-    OpenOptions::new()
-        .read(true)
-        .write(true)
+/// exclusive lock of the cache file at path among processes, released when the returned file is closed
+fn lock_file(path: &Path) -> Result<fs::File, Error> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = OpenOptions::new()
         .create(true)
-        .open(path)
+        .write(true)
+        .truncate(false)
+        .open(lock_path)
+        .map_err(Error::from)?;
+    file.lock().map_err(Error::from)?;
+    Ok(file)
+}
+
+/// writes contents to path through a temporary file, so that readers never see a partially written file
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(
+        ".{}-{}.tmp",
+        process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp_path, contents).map_err(Error::from)?;
+    fs::rename(&tmp_path, path).map_err(Error::from)
 }
 
 fn sha256hex(input_str: &str) -> String {
@@ -46,7 +72,9 @@ fn sha256hex(input_str: &str) -> String {
 
 #[derive(Clone)]
 pub struct CwRpcClient {
-    _inner: HttpClient,
+    // None for offline clients
+    _inner: Option<HttpClient>,
+    url: String,
     block_number: u64,
 
     cache: RpcCache,
@@ -67,69 +95,73 @@ pub struct RpcCacheInner {
     timestamp: u64,
 }
 
+#[derive(Clone)]
 pub enum RpcCache {
     Empty,
     FileBacked {
         // (path: String, data: Vec<u8>) -> AbciQuery.value
         inner: RpcCacheInner,
+        // responses of immutable queries, stored in files shared with the caches of other heights
+        shared: HashMap<RpcCacheK, RpcCacheV>,
         initialized: bool,
-        file_name: String,
-        file: fs::File,
+        // set when there are responses which were not saved yet
+        dirty: bool,
+        file_name: PathBuf,
+        config: CacheConfig,
     },
 }
 
-impl Clone for RpcCache {
-    fn clone(&self) -> Self {
-        match self {
-            Self::Empty => Self::Empty,
-            Self::FileBacked {
-                inner, file_name, ..
-            } => Self::FileBacked {
-                inner: inner.clone(),
-                file_name: file_name.clone(),
-                file: rwopen(file_name).unwrap(),
-                initialized: true,
-            },
-        }
+impl RpcCache {
+    /// name of the cache file of responses from url at block_number
+    fn key(url: &str, block_number: u64) -> String {
+        sha256hex(&format!("{}||{}", url, block_number))
     }
-}
 
-impl RpcCache {
-    fn file_backed(url: &str, block_number: u64) -> Result<Self, Error> {
-        let filename = sha256hex(&format!("{}||{}", url, block_number));
-        let homedir = match env::var("HOME") {
-            Ok(val) => val,
-            Err(_) => "/tmp/".to_string(),
-        };
-        let cachedir = format!("{}/{}", homedir, RPC_CACHE_DIRNAME);
-        let cachedir_path = Path::new(&cachedir);
-        if !cachedir_path.is_dir() {
-            fs::create_dir(cachedir_path).map_err(Error::io_error)?;
-        }
-        let cachefile = format!("{}/{}", cachedir, filename);
-        let cachefile_path = Path::new(&cachefile);
-        let (file, inner, initialized) = if cachefile_path.is_file() {
-            let mut file = rwopen(cachefile_path).map_err(Error::io_error)?;
-            let mut file_contents = Vec::new();
-            let _ = file
-                .read_to_end(&mut file_contents)
-                .map_err(Error::io_error)?;
+    fn file_backed(url: &str, block_number: u64, config: &CacheConfig) -> Result<Self, Error> {
+        Self::open(config.dir.join(Self::key(url, block_number)), config)
+    }
+
+    fn open(file_name: PathBuf, config: &CacheConfig) -> Result<Self, Error> {
+        let (inner, initialized) = if file_name.is_file() {
+            let file_contents = fs::read(&file_name).map_err(Error::from)?;
             let inner: RpcCacheInner =
                 bincode::deserialize(&file_contents).map_err(Error::format_error)?;
-            (file, inner, true)
+            (inner, true)
         } else {
-            let file = rwopen(cachefile_path).map_err(Error::io_error)?;
-            (file, RpcCacheInner::default(), false)
+            (RpcCacheInner::default(), false)
         };
         Ok(Self::FileBacked {
             inner,
-            file_name: cachefile,
-            file,
+            shared: HashMap::new(),
+            file_name,
             initialized,
+            dirty: false,
+            config: config.clone(),
         })
     }
 
-    fn read(&self, path: &str, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    fn config(&self) -> Option<&CacheConfig> {
+        match self {
+            Self::Empty => None,
+            Self::FileBacked { config, .. } => Some(config),
+        }
+    }
+
+    /// file storing the response to key, if it is shared across heights
+    fn shared_file(config: &CacheConfig, chain_id: &str, key: &RpcCacheK) -> Option<PathBuf> {
+        if !config.share_code || !IMMUTABLE_QUERIES.contains(&key.path.as_str()) {
+            return None;
+        }
+        let name = sha256hex(&format!(
+            "{}||{}||{}",
+            chain_id,
+            key.path,
+            hex::encode(&key.data)
+        ));
+        Some(config.dir.join("shared").join(name))
+    }
+
+    fn read(&mut self, path: &str, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
         let key = RpcCacheK {
             path: path.to_string(),
             data: data.to_vec(),
@@ -137,7 +169,24 @@ impl RpcCache {
         match self {
             // empty always returns None
             Self::Empty => Ok(None),
-            Self::FileBacked { inner, .. } => Ok(inner.db.get(&key).cloned()),
+            Self::FileBacked {
+                inner,
+                shared,
+                config,
+                ..
+            } => {
+                if let Some(value) = inner.db.get(&key).or_else(|| shared.get(&key)) {
+                    return Ok(Some(value.clone()));
+                }
+                match Self::shared_file(config, &inner.chain_id, &key) {
+                    Some(file) if file.is_file() => {
+                        let value = fs::read(file).map_err(Error::from)?;
+                        shared.insert(key, value.clone());
+                        Ok(Some(value))
+                    }
+                    _ => Ok(None),
+                }
+            }
         }
     }
 
@@ -163,25 +212,99 @@ impl RpcCache {
         match self {
             // empty always returns None
             Self::Empty => Ok(()),
-            Self::FileBacked { inner, .. } => {
-                inner.db.insert(key, response.to_owned());
+            Self::FileBacked {
+                inner,
+                shared,
+                config,
+                dirty,
+                ..
+            } => {
+                match Self::shared_file(config, &inner.chain_id, &key) {
+                    Some(file) => {
+                        if !config.read_only {
+                            fs::create_dir_all(file.parent().unwrap()).map_err(Error::from)?;
+                            write_atomic(&file, response)?;
+                        }
+                        shared.insert(key, response.to_owned());
+                    }
+                    None => {
+                        inner.db.insert(key, response.to_owned());
+                        *dirty = true;
+                    }
+                }
                 Ok(())
             }
         }
     }
 
+    /// merges the responses into the cache file, which other processes may have saved to
+    /// since it was loaded
     fn save(&mut self) -> Result<(), Error> {
         match self {
-            Self::Empty => Ok(()),
-            Self::FileBacked { inner, file, .. } => {
+            Self::FileBacked {
+                inner,
+                file_name,
+                config,
+                dirty,
+                ..
+            } if *dirty && !config.read_only => {
+                fs::create_dir_all(&config.dir).map_err(Error::from)?;
+                let _lock = lock_file(file_name)?;
+                if let Ok(contents) = fs::read(&file_name) {
+                    if let Ok(on_disk) = bincode::deserialize::<RpcCacheInner>(&contents) {
+                        for (key, value) in on_disk.db {
+                            inner.db.entry(key).or_insert(value);
+                        }
+                    }
+                }
                 let serialized = bincode::serialize(inner).map_err(Error::format_error)?;
-                file.seek(SeekFrom::Start(0)).map_err(Error::io_error)?;
-                file.write(&serialized).map_err(Error::io_error)?;
+                write_atomic(file_name, &serialized)?;
+                *dirty = false;
                 Ok(())
             }
+            _ => Ok(()),
+        }
+    }
+
+    /// every cached response, including shared ones
+    fn export(&self) -> Result<RpcCacheInner, Error> {
+        match self {
+            Self::Empty => Err(Error::backend_error("responses are not cached")),
+            Self::FileBacked { inner, shared, .. } => {
+                let mut out = inner.clone();
+                out.db.extend(shared.clone());
+                Ok(out)
+            }
         }
     }
 
+    /// adds the responses of other which are not cached yet, returning how many there were
+    fn import(&mut self, other: RpcCacheInner) -> Result<usize, Error> {
+        match self {
+            Self::Empty => return Err(Error::backend_error("responses are not cached")),
+            Self::FileBacked { inner, dirty, .. } => {
+                // the cache of a client which was never connected adopts the block of other
+                if inner.chain_id.is_empty() {
+                    inner.chain_id = other.chain_id.clone();
+                    inner.timestamp = other.timestamp;
+                    *dirty = true;
+                } else if inner.chain_id != other.chain_id || inner.timestamp != other.timestamp {
+                    return Err(Error::invalid_argument(format!(
+                        "responses of {} at {} can't be imported into a cache of {} at {}",
+                        other.chain_id, other.timestamp, inner.chain_id, inner.timestamp
+                    )));
+                }
+            }
+        }
+        let count = other.db.len();
+        for (key, value) in other.db {
+            if self.read(&key.path, &key.data)?.is_none() {
+                self.write(&key.path, &key.data, &value)?;
+            }
+        }
+        Ok(count)
+    }
+
     fn initialized(&self) -> bool {
         match self {
             Self::Empty => true,
@@ -191,14 +314,20 @@ impl RpcCache {
 
     fn set_chain_id(&mut self, chain_id: String) {
         match self {
-            Self::FileBacked { inner, .. } => inner.chain_id = chain_id,
+            Self::FileBacked { inner, dirty, .. } => {
+                inner.chain_id = chain_id;
+                *dirty = true;
+            }
             Self::Empty => {}
         }
     }
 
     fn set_timestamp(&mut self, timestamp: u64) {
         match self {
-            Self::FileBacked { inner, .. } => inner.timestamp = timestamp,
+            Self::FileBacked { inner, dirty, .. } => {
+                inner.timestamp = timestamp;
+                *dirty = true;
+            }
             Self::Empty => {}
         }
     }
@@ -212,19 +341,29 @@ impl Drop for RpcCache {
 
 impl CwRpcClient {
     pub fn new(url: &str, block_number: Option<u64>) -> Result<Self, Error> {
+        Self::with_cache_config(url, block_number, &CacheConfig::default())
+    }
+
+    pub fn with_cache_config(
+        url: &str,
+        block_number: Option<u64>,
+        cache_config: &CacheConfig,
+    ) -> Result<Self, Error> {
         let mut rv = Self {
             _inner: match HttpClient::new(url) {
-                Ok(h) => h,
+                Ok(h) => Some(h),
                 Err(e) => {
-                    return Err(Error::rpc_error(e));
+                    return Err(Error::from(e));
                 }
             },
+            url: url.to_string(),
             block_number: 0,
             cache: RpcCache::Empty,
         };
         if let Some(bn) = block_number {
+            rv.block_number = bn;
             // first check if cache exists
-            rv.cache = RpcCache::file_backed(url, bn)?;
+            rv.cache = RpcCache::file_backed(url, bn, cache_config)?;
             if !rv.cache.initialized() {
                 let timestamp = rv.timestamp()?;
                 let chain_id = rv.chain_id()?;
@@ -238,45 +377,194 @@ impl CwRpcClient {
             rv.block_number = block_height;
             // Don't change this line's order. To fetch the timestamp block_number must be properly initialized
             let timestamp = rv.timestamp()?;
-            rv.cache = RpcCache::file_backed(url, block_height)?;
+            rv.cache = RpcCache::file_backed(url, block_height, cache_config)?;
             rv.cache.set_chain_id(chain_id);
             rv.cache.set_timestamp(timestamp.nanos());
             Ok(rv)
         }
     }
 
+    /// client answering queries only from the cache file at cache_path,
+    /// e.g. one in ~/.cw-rpc-cache or one written by export_cache.
+    /// Cache files don't record their height, so block_number must be the one the cache was filled at.
+    /// Queries which are not cached fail instead of reaching the network
+    pub fn offline(cache_path: &Path, block_number: u64) -> Result<Self, Error> {
+        if !cache_path.is_file() {
+            return Err(Error::io_error(format!(
+                "no rpc cache at {}",
+                cache_path.display()
+            )));
+        }
+        let config = CacheConfig {
+            read_only: true,
+            ..CacheConfig::default()
+        };
+        Ok(Self {
+            _inner: None,
+            url: format!("offline:{}", cache_path.display()),
+            block_number,
+            cache: RpcCache::open(cache_path.to_path_buf(), &config)?,
+        })
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self._inner.is_none()
+    }
+
+    /// the http client, or an error describing the request if the client is offline
+    fn http<F: FnOnce() -> String>(&self, request: F) -> Result<&HttpClient, Error> {
+        self._inner.as_ref().ok_or_else(|| {
+            Error::rpc_error(format!(
+                "offline client can't send {}: the response is not cached",
+                request()
+            ))
+        })
+    }
+
     pub fn abci_query_raw(&mut self, path_: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let _span = debug_span!("rpc_query", path = path_, height = self.block_number).entered();
         if let Some(in_db) = self.cache.read(path_, data)? {
+            trace!("cache hit");
             return Ok(in_db);
         }
+        debug!("cache miss, querying {}", self.url);
         let path = match abci::Path::from_str(path_) {
             Ok(p) => p,
             Err(e) => {
-                return Err(Error::tendermint_error(e));
+                return Err(Error::from(e));
             }
         };
         let height = match Height::try_from(self.block_number) {
             Ok(h) => h,
             Err(e) => {
-                return Err(Error::tendermint_error(e));
+                return Err(Error::from(e));
             }
         };
-        let result = wait_future(
-            self._inner
-                .abci_query(Some(path), data, Some(height), false),
-        )?
-        .map_err(Error::rpc_error)?;
-        match result.code {
-            abci::Code::Ok => {}
-            _ => {
-                return Err(Error::tendermint_error(result.log));
+        let client = self.http(|| format!("query {} with data {}", path_, hex::encode(data)))?;
+        let result = wait_future(client.abci_query(Some(path), data, Some(height), false))?
+            .map_err(|e| self.pruned_error(self.block_number, Error::from(e)))?;
+        let value =
+            abci_query_value(result).map_err(|e| self.pruned_error(self.block_number, e))?;
+        debug!(bytes = value.len(), "response received");
+        self.cache.write(path_, data, &value)?;
+        Ok(value)
+    }
+
+    /// adds the pages of the state of address starting at next_key to storage
+    fn fetch_remaining_state(
+        &mut self,
+        address: &str,
+        storage: &mut ContractStorage,
+        next_key: Option<Vec<u8>>,
+    ) -> Result<(), Error> {
+        let mut next_key = next_key;
+        while let Some(key) = next_key {
+            let (path, data) = contract_state_all_query(address, Some(key));
+            let out = self.abci_query_raw(path, data.as_slice())?;
+            let (records, key) = decode_contract_state_all(&out)?;
+            storage.extend(records);
+            next_key = key;
+        }
+        Ok(())
+    }
+
+    /// answers (path, data) queries in the same order, sending those which are not cached concurrently
+    pub fn abci_query_batch(&mut self, queries: &[(&str, Vec<u8>)]) -> Result<Vec<Vec<u8>>, Error> {
+        let _span = debug_span!("rpc_batch", queries = queries.len()).entered();
+        let height = Height::try_from(self.block_number).map_err(Error::from)?;
+        let mut out = Vec::with_capacity(queries.len());
+        let mut handles = Vec::new();
+        for (i, (path, data)) in queries.iter().enumerate() {
+            let cached = self.cache.read(path, data)?;
+            if cached.is_none() {
+                let client = self
+                    .http(|| format!("query {} with data {}", path, hex::encode(data)))?
+                    .clone();
+                let path = abci::Path::from_str(path).map_err(Error::from)?;
+                let data = data.clone();
+                let handle = runtime()?.spawn(async move {
+                    client
+                        .abci_query(Some(path), data, Some(height), false)
+                        .await
+                });
+                handles.push((i, handle));
             }
+            out.push(cached);
+        }
+        debug!(
+            "{} cache hits, {} queries sent",
+            queries.len() - handles.len(),
+            handles.len()
+        );
+        for (i, handle) in handles {
+            let result = wait_future(handle)?
+                .map_err(Error::from)?
+                .map_err(|e| self.pruned_error(self.block_number, Error::from(e)))?;
+            let value =
+                abci_query_value(result).map_err(|e| self.pruned_error(self.block_number, e))?;
+            let (path, data) = &queries[i];
+            self.cache.write(path, data, &value)?;
+            out[i] = Some(value);
         }
-        self.cache.write(path_, data, &result.value)?;
-        Ok(result.value)
+        Ok(out.into_iter().map(|v| v.unwrap()).collect())
     }
 }
 
+impl CwRpcClient {
+    fn block_timestamp(&self, height: u64) -> Result<Timestamp, Error> {
+        let client = self.http(|| format!("block query for height {}", height))?;
+        let block_info = wait_future(client.block(Height::try_from(height).map_err(Error::from)?))?
+            .map_err(|e| self.pruned_error(height, Error::from(e)))?;
+        time_to_timestamp(block_info.block.header.time)
+    }
+
+    /// lowest height of the blocks kept by the node, which reports it when asked for block 1
+    fn earliest_block(&self) -> Result<u64, Error> {
+        let client = self.http(|| "block query for height 1".to_string())?;
+        let height = Height::try_from(1u64).map_err(Error::from)?;
+        match wait_future(client.block(height))? {
+            Ok(_) => Ok(1),
+            Err(e) => lowest_height_in(&e.to_string()).ok_or_else(|| Error::rpc_error(e)),
+        }
+    }
+
+    /// Error::HeightPruned if e tells that the node pruned height, e otherwise
+    fn pruned_error(&self, height: u64, e: Error) -> Error {
+        match &e {
+            Error::RpcError(msg, _) | Error::TendermintError(msg, _) if is_pruned_message(msg) => {
+                let earliest = lowest_height_in(msg).or_else(|| self.earliest_block().ok());
+                Error::height_pruned(height, earliest)
+            }
+            _ => e,
+        }
+    }
+}
+
+fn time_to_timestamp(time: Time) -> Result<Timestamp, Error> {
+    let duration = time
+        .duration_since(Time::unix_epoch())
+        .map_err(Error::from)?;
+    Ok(Timestamp::from_nanos(
+        duration
+            .as_nanos()
+            .try_into()
+            .map_err(Error::tendermint_error)?,
+    ))
+}
+
+fn abci_events_to_events(events: &[abci::Event]) -> Vec<Event> {
+    events
+        .iter()
+        .map(|e| {
+            Event::new(&e.type_str).add_attributes(
+                e.attributes
+                    .iter()
+                    .map(|t| (t.key.to_string(), t.value.to_string())),
+            )
+        })
+        .collect()
+}
+
 // protobuf serialize
 fn serialize<M: Message>(m: &M) -> Result<Vec<u8>, Error> {
     let mut out = Vec::new();
@@ -286,13 +574,109 @@ fn serialize<M: Message>(m: &M) -> Result<Vec<u8>, Error> {
     }
 }
 
-fn wait_future<F: Future>(f: F) -> Result<F::Output, Error> {
-    match tokio::runtime::Builder::new_current_thread()
+lazy_static! {
+    // shared by all clients, so that connections to the nodes are kept alive between requests
+    static ref RUNTIME: std::io::Result<Runtime> = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
-        .build()
-    {
-        Ok(b) => Ok(b.block_on(f)),
-        Err(e) => Err(Error::tokio_error(e)),
+        .build();
+}
+
+fn runtime() -> Result<&'static Runtime, Error> {
+    RUNTIME.as_ref().map_err(Error::tokio_error)
+}
+
+fn wait_future<F: Future>(f: F) -> Result<F::Output, Error> {
+    Ok(runtime()?.block_on(f))
+}
+
+fn abci_query_value(result: AbciQuery) -> Result<Vec<u8>, Error> {
+    match result.code {
+        abci::Code::Ok => Ok(result.value),
+        _ => Err(Error::tendermint_error(result.log)),
+    }
+}
+
+/// query of the page of the state of address starting at key, the first page if key is None
+fn contract_state_all_query(address: &str, key: Option<Vec<u8>>) -> (&'static str, Vec<u8>) {
+    use crate::rpc_items::cosmos::base::query::v1beta1::PageRequest;
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryAllContractStateRequest;
+    let request = QueryAllContractStateRequest {
+        address: address.to_string(),
+        pagination: key.map(|key| PageRequest {
+            key,
+            ..Default::default()
+        }),
+    };
+    let path = "/cosmwasm.wasm.v1.Query/AllContractState";
+    (path, serialize(&request).unwrap())
+}
+
+/// records of a page of contract state, and the key of the next page if there is one
+fn decode_contract_state_all(out: &[u8]) -> Result<(ContractStorage, Option<Vec<u8>>), Error> {
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryAllContractStateResponse;
+    let resp = QueryAllContractStateResponse::decode(out).map_err(Error::format_error)?;
+    let next_key = resp
+        .pagination
+        .map(|p| p.next_key)
+        .filter(|k| !k.is_empty());
+    let records = resp.models.into_iter().map(|m| (m.key, m.value)).collect();
+    Ok((records, next_key))
+}
+
+fn contract_info_query(address: &str) -> (&'static str, Vec<u8>) {
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryContractInfoRequest;
+    let request = QueryContractInfoRequest {
+        address: address.to_string(),
+    };
+    let path = "/cosmwasm.wasm.v1.Query/ContractInfo";
+    (path, serialize(&request).unwrap())
+}
+
+fn decode_contract_info(address: &str, out: &[u8]) -> Result<ContractInfo, Error> {
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryContractInfoResponse;
+    let resp = QueryContractInfoResponse::decode(out).map_err(Error::format_error)?;
+    if let Some(ci) = resp.contract_info {
+        Ok(ContractInfo {
+            code_id: ci.code_id,
+            creator: ci.creator,
+            // the chain encodes a missing admin as an empty string
+            admin: Some(ci.admin).filter(|a| !a.is_empty()),
+            label: ci.label,
+        })
+    } else {
+        Err(Error::invalid_argument(format!(
+            "address {} is most likely not a contract address",
+            address
+        )))
+    }
+}
+
+fn code_query(code_id: u64) -> (&'static str, Vec<u8>) {
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryCodeRequest;
+    let request = QueryCodeRequest { code_id };
+    let path = "/cosmwasm.wasm.v1.Query/Code";
+    (path, serialize(&request).unwrap())
+}
+
+fn decode_code(out: &[u8]) -> Result<Vec<u8>, Error> {
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryCodeResponse;
+    let resp = QueryCodeResponse::decode(out).map_err(Error::format_error)?;
+    Ok(resp.data)
+}
+
+fn decode_code_info(code_id: u64, out: &[u8]) -> Result<CodeInfo, Error> {
+    use crate::rpc_items::cosmwasm::wasm::v1::QueryCodeResponse;
+    let resp = QueryCodeResponse::decode(out).map_err(Error::format_error)?;
+    match resp.code_info {
+        Some(info) => Ok(CodeInfo {
+            code_id,
+            creator: info.creator,
+            checksum: info.data_hash,
+        }),
+        None => Err(Error::invalid_argument(format!(
+            "code id {} does not exist",
+            code_id
+        ))),
     }
 }
 
@@ -301,11 +685,54 @@ impl CwClientBackend for CwRpcClient {
         self.block_number
     }
 
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        match &self.cache {
+            RpcCache::Empty => None,
+            RpcCache::FileBacked { file_name, .. } => file_name
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string()),
+        }
+    }
+
+    fn cache_config(&self) -> Option<CacheConfig> {
+        self.cache.config().cloned()
+    }
+
+    fn set_cache_config(&mut self, config: CacheConfig) -> Result<(), Error> {
+        if self.is_offline() {
+            return Err(Error::invalid_argument(
+                "the cache of an offline client can't be reconfigured",
+            ));
+        }
+        let mut cache = RpcCache::file_backed(&self.url, self.block_number, &config)?;
+        cache.import(self.cache.export()?)?;
+        // the old cache is saved with its own config when dropped
+        self.cache = cache;
+        Ok(())
+    }
+
+    fn export_cache(&self, path: &Path) -> Result<(), Error> {
+        let serialized = bincode::serialize(&self.cache.export()?).map_err(Error::format_error)?;
+        fs::write(path, serialized).map_err(Error::from)
+    }
+
+    fn import_cache(&mut self, path: &Path) -> Result<usize, Error> {
+        let contents = fs::read(path).map_err(Error::from)?;
+        let imported: RpcCacheInner =
+            bincode::deserialize(&contents).map_err(Error::format_error)?;
+        self.cache.import(imported)
+    }
+
     fn chain_id(&mut self) -> Result<String, Error> {
         if let Some(chain_id) = self.cache.chain_id() {
             Ok(chain_id)
         } else {
-            let status = wait_future(self._inner.status())?.map_err(Error::rpc_error)?;
+            let client = self.http(|| "status query".to_string())?;
+            let status = wait_future(client.status())?.map_err(Error::from)?;
             Ok(status.node_info.network.to_string())
         }
     }
@@ -315,29 +742,20 @@ impl CwClientBackend for CwRpcClient {
         if let Some(timestamp_ns) = self.cache.timestamp() {
             Ok(Timestamp::from_nanos(timestamp_ns))
         } else {
-            let block_info = wait_future(
-                self._inner
-                    .block(Height::try_from(self.block_number).map_err(Error::tendermint_error)?),
-            )?
-            .map_err(Error::rpc_error)?;
-            let time = block_info.block.header.time;
-            let duration = time
-                .duration_since(Time::unix_epoch())
-                .map_err(Error::tendermint_error)?;
-            Ok(Timestamp::from_nanos(
-                duration
-                    .as_nanos()
-                    .try_into()
-                    .map_err(Error::tendermint_error)?,
-            ))
+            self.block_timestamp(self.block_number)
         }
     }
 
     fn block_height(&mut self) -> Result<u64, Error> {
-        let status = wait_future(self._inner.status())?.map_err(Error::rpc_error)?;
+        let client = self.http(|| "status query".to_string())?;
+        let status = wait_future(client.status())?.map_err(Error::from)?;
         Ok(status.sync_info.latest_block_height.value())
     }
 
+    fn earliest_available_block(&mut self) -> Result<u64, Error> {
+        self.earliest_block()
+    }
+
     fn query_bank_all_balances(&mut self, address: &str) -> Result<Vec<(String, u128)>, Error> {
         use crate::rpc_items::cosmos::bank::v1beta1::QueryAllBalancesRequest;
         use crate::rpc_items::cosmos::bank::v1beta1::QueryAllBalancesResponse;
@@ -362,6 +780,122 @@ impl CwClientBackend for CwRpcClient {
         Ok(balances)
     }
 
+    fn query_bank_supply(&mut self, denom: &str) -> Result<u128, Error> {
+        use crate::rpc_items::cosmos::bank::v1beta1::QuerySupplyOfRequest;
+        use crate::rpc_items::cosmos::bank::v1beta1::QuerySupplyOfResponse;
+        let request = QuerySupplyOfRequest {
+            denom: denom.to_string(),
+        };
+        let path = "/cosmos.bank.v1beta1.Query/SupplyOf";
+        let data = serialize(&request).unwrap();
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        let resp = match QuerySupplyOfResponse::decode(out.as_slice()) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::format_error(e));
+            }
+        };
+        match resp.amount {
+            Some(coin) => u128::from_str(&coin.amount).map_err(Error::format_error),
+            None => Ok(0),
+        }
+    }
+
+    fn query_bank_denom_metadata(&mut self, denom: &str) -> Result<DenomMetadata, Error> {
+        use crate::rpc_items::cosmos::bank::v1beta1::QueryDenomMetadataRequest;
+        use crate::rpc_items::cosmos::bank::v1beta1::QueryDenomMetadataResponse;
+        let request = QueryDenomMetadataRequest {
+            denom: denom.to_string(),
+        };
+        let path = "/cosmos.bank.v1beta1.Query/DenomMetadata";
+        let data = serialize(&request).unwrap();
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        let resp =
+            QueryDenomMetadataResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        match resp.metadata {
+            Some(metadata) => Ok(metadata.into()),
+            None => Err(Error::invalid_argument(format!(
+                "no metadata registered for denom {}",
+                denom
+            ))),
+        }
+    }
+
+    fn query_bank_all_denom_metadata(&mut self) -> Result<Vec<DenomMetadata>, Error> {
+        use crate::rpc_items::cosmos::bank::v1beta1::QueryDenomsMetadataRequest;
+        use crate::rpc_items::cosmos::bank::v1beta1::QueryDenomsMetadataResponse;
+        let request = QueryDenomsMetadataRequest { pagination: None };
+        let path = "/cosmos.bank.v1beta1.Query/DenomsMetadata";
+        let data = serialize(&request).unwrap();
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        let resp =
+            QueryDenomsMetadataResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        Ok(resp.metadatas.into_iter().map(|m| m.into()).collect())
+    }
+
+    fn query_distribution_rewards(
+        &mut self,
+        delegator: &str,
+        validator: &str,
+    ) -> Result<Vec<(String, u128)>, Error> {
+        use crate::rpc_items::cosmos::distribution::v1beta1::QueryDelegationRewardsRequest;
+        use crate::rpc_items::cosmos::distribution::v1beta1::QueryDelegationRewardsResponse;
+        let request = QueryDelegationRewardsRequest {
+            delegator_address: delegator.to_string(),
+            validator_address: validator.to_string(),
+        };
+        let path = "/cosmos.distribution.v1beta1.Query/DelegationRewards";
+        let data = serialize(&request).unwrap();
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        let resp =
+            QueryDelegationRewardsResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        resp.rewards
+            .into_iter()
+            .map(|coin| {
+                // sdk.Dec is encoded as its atomics, with 18 decimal places
+                let atomics = u128::from_str(&coin.amount).map_err(Error::format_error)?;
+                Ok((coin.denom, atomics / 10u128.pow(18)))
+            })
+            .collect()
+    }
+
+    fn query_distribution_withdraw_address(&mut self, delegator: &str) -> Result<String, Error> {
+        use crate::rpc_items::cosmos::distribution::v1beta1::QueryDelegatorWithdrawAddressRequest;
+        use crate::rpc_items::cosmos::distribution::v1beta1::QueryDelegatorWithdrawAddressResponse;
+        let request = QueryDelegatorWithdrawAddressRequest {
+            delegator_address: delegator.to_string(),
+        };
+        let path = "/cosmos.distribution.v1beta1.Query/DelegatorWithdrawAddress";
+        let data = serialize(&request).unwrap();
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        let resp = QueryDelegatorWithdrawAddressResponse::decode(out.as_slice())
+            .map_err(Error::format_error)?;
+        Ok(resp.withdraw_address)
+    }
+
+    fn query_gov_latest_proposal_id(&mut self) -> Result<u64, Error> {
+        use crate::rpc_items::cosmos::base::query::v1beta1::PageRequest;
+        use crate::rpc_items::cosmos::gov::v1beta1::QueryProposalsRequest;
+        use crate::rpc_items::cosmos::gov::v1beta1::QueryProposalsResponse;
+        let request = QueryProposalsRequest {
+            pagination: Some(PageRequest {
+                limit: 1,
+                reverse: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let path = "/cosmos.gov.v1beta1.Query/Proposals";
+        let data = serialize(&request).unwrap();
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        let resp = QueryProposalsResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        Ok(resp.proposals.first().map(|p| p.proposal_id).unwrap_or(0))
+    }
+
+    fn query_grpc(&mut self, path: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.abci_query_raw(path, data)
+    }
+
     fn query_wasm_contract_smart(
         &mut self,
         address: &str,
@@ -389,79 +923,152 @@ impl CwClientBackend for CwRpcClient {
         &mut self,
         address: &str,
     ) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, Error> {
-        use crate::rpc_items::cosmwasm::wasm::v1::QueryAllContractStateRequest;
-        use crate::rpc_items::cosmwasm::wasm::v1::QueryAllContractStateResponse;
-        let request = QueryAllContractStateRequest {
-            address: address.to_string(),
-            pagination: None,
-        };
-        let path = "/cosmwasm.wasm.v1.Query/AllContractState";
-        let data = serialize(&request).unwrap();
+        let (path, data) = contract_state_all_query(address, None);
         let out = self.abci_query_raw(path, data.as_slice())?;
-        let resp = match QueryAllContractStateResponse::decode(out.as_slice()) {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::format_error(e));
-            }
-        };
-        let mut out = BTreeMap::new();
-        for model in resp.models {
-            out.insert(model.key, model.value);
+        let (mut storage, next_key) = decode_contract_state_all(&out)?;
+        self.fetch_remaining_state(address, &mut storage, next_key)?;
+        Ok(storage)
+    }
+
+    fn query_wasm_contracts_state_all(
+        &mut self,
+        addresses: &[String],
+    ) -> Result<Vec<ContractStorage>, Error> {
+        let queries: Vec<_> = addresses
+            .iter()
+            .map(|a| contract_state_all_query(a, None))
+            .collect();
+        // only the first pages are fetched concurrently
+        let out = self.abci_query_batch(&queries)?;
+        let mut storages = Vec::with_capacity(addresses.len());
+        for (address, out) in addresses.iter().zip(out.iter()) {
+            let (mut storage, next_key) = decode_contract_state_all(out)?;
+            self.fetch_remaining_state(address, &mut storage, next_key)?;
+            storages.push(storage);
         }
-        Ok(out)
+        Ok(storages)
     }
 
-    fn query_wasm_contract_info(&mut self, address: &str) -> Result<ContractInfo, Error> {
-        use crate::rpc_items::cosmwasm::wasm::v1::QueryContractInfoRequest;
-        use crate::rpc_items::cosmwasm::wasm::v1::QueryContractInfoResponse;
-        let request = QueryContractInfoRequest {
+    fn query_wasm_contract_state_raw(
+        &mut self,
+        address: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        use crate::rpc_items::cosmwasm::wasm::v1::{
+            QueryRawContractStateRequest, QueryRawContractStateResponse,
+        };
+        let request = QueryRawContractStateRequest {
             address: address.to_string(),
+            query_data: key.to_vec(),
         };
-        let path = "/cosmwasm.wasm.v1.Query/ContractInfo";
-        let data = serialize(&request).unwrap();
+        let path = "/cosmwasm.wasm.v1.Query/RawContractState";
+        let out = self.abci_query_raw(path, serialize(&request).unwrap().as_slice())?;
+        let resp =
+            QueryRawContractStateResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        // the chain does not store empty values, so empty data means the key is not set
+        Ok(Some(resp.data).filter(|d| !d.is_empty()))
+    }
+
+    fn query_wasm_contract_info(&mut self, address: &str) -> Result<ContractInfo, Error> {
+        let (path, data) = contract_info_query(address);
         let out = self.abci_query_raw(path, data.as_slice())?;
-        let resp = match QueryContractInfoResponse::decode(out.as_slice()) {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::format_error(e));
-            }
-        };
-        if let Some(ci) = resp.contract_info {
-            Ok(ContractInfo {
-                code_id: ci.code_id,
-            })
-        } else {
-            Err(Error::invalid_argument(format!(
-                "address {} is most likely not a contract address",
-                address
-            )))
-        }
+        decode_contract_info(address, &out)
+    }
+
+    fn query_wasm_contracts_info(
+        &mut self,
+        addresses: &[String],
+    ) -> Result<Vec<ContractInfo>, Error> {
+        let queries: Vec<_> = addresses.iter().map(|a| contract_info_query(a)).collect();
+        let out = self.abci_query_batch(&queries)?;
+        addresses
+            .iter()
+            .zip(out.iter())
+            .map(|(a, o)| decode_contract_info(a, o))
+            .collect()
     }
 
     fn query_wasm_contract_code(&mut self, code_id: u64) -> Result<Vec<u8>, Error> {
-        use crate::rpc_items::cosmwasm::wasm::v1::QueryCodeRequest;
-        use crate::rpc_items::cosmwasm::wasm::v1::QueryCodeResponse;
-        let request = QueryCodeRequest { code_id };
-        let path = "/cosmwasm.wasm.v1.Query/Code";
-        let data = serialize(&request).unwrap();
+        let (path, data) = code_query(code_id);
         let out = self.abci_query_raw(path, data.as_slice())?;
-        let resp = match QueryCodeResponse::decode(out.as_slice()) {
-            Ok(r) => r,
-            Err(e) => {
-                return Err(Error::format_error(e));
-            }
-        };
-        Ok(resp.data)
+        decode_code(&out)
+    }
+
+    fn query_wasm_code_info(&mut self, code_id: u64) -> Result<CodeInfo, Error> {
+        // the same query as for the code, so that the cached response is shared
+        let (path, data) = code_query(code_id);
+        let out = self.abci_query_raw(path, data.as_slice())?;
+        decode_code_info(code_id, &out)
+    }
+
+    fn query_wasm_contract_codes(&mut self, code_ids: &[u64]) -> Result<Vec<Vec<u8>>, Error> {
+        let queries: Vec<_> = code_ids.iter().map(|c| code_query(*c)).collect();
+        let out = self.abci_query_batch(&queries)?;
+        out.iter().map(|o| decode_code(o)).collect()
+    }
+
+    fn query_tx(&mut self, hash: &str) -> Result<TxInfo, Error> {
+        let tx_hash = abci::transaction::Hash::from_str(hash).map_err(Error::invalid_argument)?;
+        let client = self.http(|| format!("tx query for {}", hash))?;
+        let response = wait_future(client.tx(tx_hash, false))?.map_err(Error::from)?;
+        let height = response.height.value();
+        Ok(TxInfo {
+            hash: response.hash.to_string(),
+            height,
+            timestamp: self.block_timestamp(height)?,
+            success: response.tx_result.code.is_ok(),
+            events: abci_events_to_events(&response.tx_result.events),
+            tx: response.tx.into(),
+        })
+    }
+
+    fn query_block_txs(&mut self, height: u64) -> Result<Vec<TxInfo>, Error> {
+        let block_height = Height::try_from(height).map_err(Error::from)?;
+        let client = self.http(|| format!("block query for height {}", height))?;
+        let block_info = wait_future(client.block(block_height))?.map_err(Error::from)?;
+        let block_results =
+            wait_future(client.block_results(block_height))?.map_err(Error::from)?;
+        let timestamp = time_to_timestamp(block_info.block.header.time)?;
+        let txs_results = block_results.txs_results.unwrap_or_default();
+        let mut out = Vec::new();
+        for (tx, tx_result) in block_info.block.data.iter().zip(txs_results.iter()) {
+            let mut hasher = Sha256::new();
+            hasher.update(tx.as_bytes());
+            out.push(TxInfo {
+                hash: hex::encode_upper(hasher.finalize()),
+                height,
+                timestamp,
+                success: tx_result.code.is_ok(),
+                events: abci_events_to_events(&tx_result.events),
+                tx: tx.as_bytes().to_vec(),
+            });
+        }
+        Ok(out)
+    }
+
+    fn at_height(&self, height: u64) -> Result<Box<dyn CwClientBackend>, Error> {
+        if self.is_offline() {
+            return Err(Error::invalid_argument(
+                "offline clients are pinned to the height of their cache",
+            ));
+        }
+        let cache_config = self.cache.config().cloned().unwrap_or_default();
+        Ok(Box::new(CwRpcClient::with_cache_config(
+            &self.url,
+            Some(height),
+            &cache_config,
+        )?))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{CwClientBackend, CwRpcClient};
+    use crate::{CacheConfig, CwClientBackend, CwRpcClient};
     use cosmwasm_std::{Addr, Uint128};
     use serde::{Deserialize, Serialize};
 
-    use super::RpcCache;
+    use super::{is_pruned_message, lowest_height_in, RpcCache};
+    use crate::Error;
 
     const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
     const MALAGA_CHAIN_ID: &str = "malaga-420";
@@ -577,6 +1184,25 @@ mod tests {
         assert_eq!(pair_info.asset_decimals[0], token_info.decimals);
     }
 
+    #[test]
+    fn test_rpc_batch() {
+        let mut client = CwRpcClient::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER)).unwrap();
+        let addresses = vec![PAIR_ADDRESS.to_string(), TOKEN_ADDRESS.to_string()];
+        let states = client.query_wasm_contracts_state_all(&addresses).unwrap();
+        assert_eq!(
+            states[0],
+            client.query_wasm_contract_state_all(PAIR_ADDRESS).unwrap()
+        );
+        assert_eq!(
+            states[1],
+            client.query_wasm_contract_state_all(TOKEN_ADDRESS).unwrap()
+        );
+        let infos = client.query_wasm_contracts_info(&addresses).unwrap();
+        assert_eq!(infos[0].code_id, 1786);
+        // the token is not a pair
+        assert_ne!(infos[1].code_id, 1786);
+    }
+
     #[test]
     fn test_rpc_get_code() {
         let mut client = CwRpcClient::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER)).unwrap();
@@ -591,15 +1217,163 @@ mod tests {
 
     #[test]
     fn test_cache() {
-        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 100000).unwrap();
+        let config = CacheConfig::default();
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 100000, &config).unwrap();
         let path = "aaaaaaaa";
         let data = "bbbbbbbb".as_bytes();
         let response = "cccccccc".as_bytes();
         cache.write(path, data, response).unwrap();
         drop(cache);
 
-        let cache = RpcCache::file_backed(MALAGA_RPC_URL, 100000).unwrap();
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 100000, &config).unwrap();
         let data = cache.read(path, data).unwrap();
         println!("{:?}", &data);
     }
+
+    #[test]
+    fn test_offline() {
+        let dir = std::env::temp_dir().join(format!("cw-rpc-cache-offline-{}", std::process::id()));
+        let config = CacheConfig {
+            dir: dir.clone(),
+            ..CacheConfig::default()
+        };
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 1, &config).unwrap();
+        cache.set_chain_id(MALAGA_CHAIN_ID.to_string());
+        cache.set_timestamp(42);
+        drop(cache);
+        let path = dir.join(RpcCache::key(MALAGA_RPC_URL, 1));
+
+        let mut client = CwRpcClient::offline(&path, 1).unwrap();
+        assert!(client.is_offline());
+        assert_eq!(client.chain_id().unwrap(), MALAGA_CHAIN_ID);
+        assert_eq!(client.timestamp().unwrap().nanos(), 42);
+        assert_eq!(client.cache_key(), Some(RpcCache::key(MALAGA_RPC_URL, 1)));
+        let err = client.query_bank_supply("umlg").unwrap_err().to_string();
+        assert!(
+            err.contains("/cosmos.bank.v1beta1.Query/SupplyOf"),
+            "{}",
+            err
+        );
+        assert!(client.block_height().is_err());
+        assert!(CwRpcClient::offline(&dir.join("missing"), 1).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pruned_errors() {
+        let abci_log = "failed to load state at height 100; version does not exist (latest height: 2346678): invalid request";
+        let block_err =
+            "Internal error: height 1 is not available, lowest height is 2000000 (code: -32603)";
+        assert!(is_pruned_message(abci_log));
+        assert!(is_pruned_message(block_err));
+        assert!(!is_pruned_message("out of gas in location: ReadFlat"));
+        assert_eq!(lowest_height_in(abci_log), None);
+        assert_eq!(lowest_height_in(block_err), Some(2000000));
+
+        let dir = std::env::temp_dir().join(format!("cw-rpc-cache-pruned-{}", std::process::id()));
+        let config = CacheConfig {
+            dir: dir.clone(),
+            ..CacheConfig::default()
+        };
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 100, &config).unwrap();
+        cache.set_chain_id(MALAGA_CHAIN_ID.to_string());
+        cache.set_timestamp(42);
+        drop(cache);
+        let client =
+            CwRpcClient::offline(&dir.join(RpcCache::key(MALAGA_RPC_URL, 100)), 100).unwrap();
+        // the offline client can't look the earliest height up
+        match client.pruned_error(100, Error::tendermint_error(abci_log)) {
+            Error::HeightPruned { height, earliest } => assert_eq!((height, earliest), (100, None)),
+            e => panic!("{}", e),
+        }
+        match client.pruned_error(1, Error::rpc_error(block_err)) {
+            Error::HeightPruned { height, earliest } => {
+                assert_eq!((height, earliest), (1, Some(2000000)))
+            }
+            e => panic!("{}", e),
+        }
+        assert!(matches!(
+            client.pruned_error(100, Error::rpc_error("connection refused")),
+            Error::RpcError(..)
+        ));
+        assert!(Error::height_pruned(1, Some(2000000))
+            .to_string()
+            .contains("earliest available height is 2000000"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_concurrent_saves() {
+        let dir = std::env::temp_dir().join(format!("cw-rpc-cache-locks-{}", std::process::id()));
+        let config = CacheConfig {
+            dir: dir.clone(),
+            ..CacheConfig::default()
+        };
+        // every cache is loaded before any of them is saved, yet no response is lost
+        let caches: Vec<RpcCache> = (0..8)
+            .map(|_| RpcCache::file_backed(MALAGA_RPC_URL, 1, &config).unwrap())
+            .collect();
+        let handles: Vec<_> = caches
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut cache)| {
+                std::thread::spawn(move || {
+                    cache.write("/path", &[i as u8], b"value").unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 1, &config).unwrap();
+        for i in 0..8u8 {
+            assert_eq!(cache.read("/path", &[i]).unwrap(), Some(b"value".to_vec()));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_config() {
+        let dir = std::env::temp_dir().join(format!("cw-rpc-cache-test-{}", std::process::id()));
+        let config = CacheConfig {
+            dir: dir.clone(),
+            read_only: false,
+            share_code: true,
+        };
+        let code_path = "/cosmwasm.wasm.v1.Query/Code";
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 1, &config).unwrap();
+        cache.set_chain_id(MALAGA_CHAIN_ID.to_string());
+        cache.set_timestamp(1);
+        cache.write(code_path, b"1", b"wasm").unwrap();
+        cache.write("/other", b"1", b"value").unwrap();
+
+        // code is shared with other heights, other responses are not
+        let mut other = RpcCache::file_backed(MALAGA_RPC_URL, 2, &config).unwrap();
+        other.set_chain_id(MALAGA_CHAIN_ID.to_string());
+        other.set_timestamp(2);
+        assert_eq!(other.read(code_path, b"1").unwrap(), Some(b"wasm".to_vec()));
+        assert_eq!(other.read("/other", b"1").unwrap(), None);
+
+        // exported responses can only be imported at the same height
+        let exported = cache.export().unwrap();
+        assert!(other.import(exported.clone()).is_err());
+        let mut fresh = RpcCache::file_backed(MALAGA_RPC_URL, 1, &config).unwrap();
+        assert_eq!(fresh.import(exported).unwrap(), 2);
+        assert_eq!(fresh.read("/other", b"1").unwrap(), Some(b"value".to_vec()));
+        drop(fresh);
+        drop(other);
+        drop(cache);
+
+        // read-only caches are never written
+        let read_only = CacheConfig {
+            read_only: true,
+            ..config.clone()
+        };
+        let mut cache = RpcCache::file_backed(MALAGA_RPC_URL, 3, &read_only).unwrap();
+        cache.write("/other", b"1", b"value").unwrap();
+        drop(cache);
+        assert!(!dir.join(RpcCache::key(MALAGA_RPC_URL, 3)).exists());
+        assert!(dir.join(RpcCache::key(MALAGA_RPC_URL, 1)).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
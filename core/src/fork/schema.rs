@@ -0,0 +1,248 @@
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::Error;
+
+/// kind of the messages a schema describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MsgKind {
+    Instantiate,
+    Execute,
+    Query,
+    Migrate,
+}
+
+impl MsgKind {
+    const ALL: [MsgKind; 4] = [
+        MsgKind::Instantiate,
+        MsgKind::Execute,
+        MsgKind::Query,
+        MsgKind::Migrate,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            MsgKind::Instantiate => "instantiate",
+            MsgKind::Execute => "execute",
+            MsgKind::Query => "query",
+            MsgKind::Migrate => "migrate",
+        }
+    }
+}
+
+impl FromStr for MsgKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        MsgKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| Error::invalid_argument(format!("unknown message kind {}", s)))
+    }
+}
+
+impl fmt::Display for MsgKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+fn compile(schema: &Value) -> Result<JSONSchema, Error> {
+    JSONSchema::compile(schema).map_err(|e| Error::format_error(format!("invalid schema: {}", e)))
+}
+
+fn errors(schema: &JSONSchema, msg: &Value) -> Vec<String> {
+    match schema.validate(msg) {
+        Ok(()) => vec![],
+        Err(errors) => errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                let error = match (&e.kind, msg.pointer(&path).and_then(|v| v.as_object())) {
+                    // additionalProperties of a struct without fields only tells the first value
+                    (ValidationErrorKind::FalseSchema, Some(object)) => {
+                        let fields: Vec<&str> = object.keys().map(|k| k.as_str()).collect();
+                        format!("unexpected fields {}", fields.join(", "))
+                    }
+                    _ => e.to_string(),
+                };
+                if path.is_empty() {
+                    error
+                } else {
+                    format!("at {}: {}", path, error)
+                }
+            })
+            .collect(),
+    }
+}
+
+/// a message schema generated by cosmwasm-schema
+pub struct MsgSchema {
+    root: JSONSchema,
+    // enum messages are a oneOf of objects with a single key, one schema per key
+    // so that errors point inside the variant instead of at the whole oneOf
+    variants: BTreeMap<String, JSONSchema>,
+}
+
+impl MsgSchema {
+    pub fn new(schema: &Value) -> Result<Self, Error> {
+        let root = compile(schema)?;
+        let mut variants = BTreeMap::new();
+        let one_of = schema.get("oneOf").and_then(|v| v.as_array());
+        for variant in one_of.into_iter().flatten() {
+            let name = match variant.get("required").and_then(|r| r.as_array()) {
+                Some(required) if required.len() == 1 => required[0].as_str(),
+                _ => None,
+            };
+            if let Some(name) = name {
+                let mut variant = variant.clone();
+                variant["definitions"] = schema.get("definitions").cloned().unwrap_or(json!({}));
+                variants.insert(name.to_string(), compile(&variant)?);
+            }
+        }
+        Ok(MsgSchema { root, variants })
+    }
+
+    /// names of the variants of an enum message
+    pub fn variants(&self) -> Vec<String> {
+        self.variants.keys().cloned().collect()
+    }
+
+    /// every way msg does not match the schema, empty if it does
+    pub fn errors(&self, msg: &Value) -> Vec<String> {
+        if let Some(object) = msg.as_object().filter(|o| o.len() == 1) {
+            let name = object.keys().next().unwrap();
+            match self.variants.get(name) {
+                Some(variant) => return errors(variant, msg),
+                None if !self.variants.is_empty() && !self.root.is_valid(msg) => {
+                    return vec![format!(
+                        "unknown variant {}, expected one of {}",
+                        name,
+                        self.variants().join(", ")
+                    )]
+                }
+                None => {}
+            }
+        }
+        errors(&self.root, msg)
+    }
+}
+
+/// the message schemas of a contract, checked before messages reach the vm
+#[derive(Default)]
+pub struct ContractSchema {
+    msgs: HashMap<MsgKind, MsgSchema>,
+}
+
+impl ContractSchema {
+    /// parses the schema written by cosmwasm_schema::write_api, e.g. schema/contract.json
+    pub fn from_api_json(json: &[u8]) -> Result<Self, Error> {
+        let api: Value = serde_json::from_slice(json).map_err(Error::from)?;
+        let mut schema = ContractSchema::default();
+        for kind in MsgKind::ALL {
+            match api.get(kind.as_str()) {
+                None | Some(Value::Null) => {}
+                Some(msg_schema) => schema.set_msg_schema(kind, msg_schema)?,
+            }
+        }
+        Ok(schema)
+    }
+
+    /// reads the schema written by cosmwasm_schema::write_api
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let json =
+            fs::read(path).map_err(|e| Error::io_error(format!("{}: {}", path.display(), e)))?;
+        Self::from_api_json(&json)
+    }
+
+    /// sets the schema of one kind of messages, e.g. the output of schema_for!(ExecuteMsg)
+    pub fn set_msg_schema(&mut self, kind: MsgKind, schema: &Value) -> Result<(), Error> {
+        self.msgs.insert(kind, MsgSchema::new(schema)?);
+        Ok(())
+    }
+
+    pub fn msg_schema(&self, kind: MsgKind) -> Option<&MsgSchema> {
+        self.msgs.get(&kind)
+    }
+
+    /// checks msg against the schema of kind, any msg is valid if there is none
+    pub fn validate(&self, kind: MsgKind, msg: &[u8]) -> Result<(), Error> {
+        let schema = match self.msgs.get(&kind) {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let msg: Value = serde_json::from_slice(msg)
+            .map_err(|e| Error::invalid_argument(format!("{} message is not json: {}", kind, e)))?;
+        let errors = schema.errors(&msg);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::invalid_argument(format!(
+                "{} message does not match the schema: {}",
+                kind,
+                errors.join("; ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_schema::schema_for;
+    use test_contract::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+
+    #[test]
+    fn test_contract_schema() {
+        // the layout written by write_api
+        let api = json!({
+            "contract_name": "test-contract",
+            "instantiate": schema_for!(InstantiateMsg),
+            "execute": schema_for!(ExecuteMsg),
+            "query": schema_for!(QueryMsg),
+            "migrate": null,
+        });
+        let json = serde_json::to_vec(&api).unwrap();
+        let schema = ContractSchema::from_api_json(&json).unwrap();
+        assert!(schema.msg_schema(MsgKind::Migrate).is_none());
+        assert!(schema
+            .msg_schema(MsgKind::Query)
+            .unwrap()
+            .variants()
+            .contains(&"recurse".to_string()));
+
+        schema
+            .validate(MsgKind::Execute, br#"{"test_gas_limit":{"gas_limit":10}}"#)
+            .unwrap();
+        schema.validate(MsgKind::Migrate, b"anything").unwrap();
+        assert_eq!("query".parse::<MsgKind>().unwrap(), MsgKind::Query);
+        assert!("sudo".parse::<MsgKind>().is_err());
+        let err = |kind, msg: &[u8]| schema.validate(kind, msg).unwrap_err().to_string();
+        assert!(err(MsgKind::Execute, br#"{"test_gas_limit":{}}"#)
+            .contains("at /test_gas_limit: \"gas_limit\" is a required property"));
+        assert!(err(
+            MsgKind::Execute,
+            br#"{"test_gas_limit":{"gas_limit":"10"}}"#
+        )
+        .contains("at /test_gas_limit/gas_limit: \"10\" is not of type \"integer\""));
+        assert!(err(MsgKind::Query, br#"{"read_numbers":{}}"#)
+            .contains("unknown variant read_numbers, expected one of read_number, recurse"));
+        assert!(err(MsgKind::Instantiate, br#"{"extra":1}"#).contains("unexpected fields extra"));
+        assert!(err(MsgKind::Execute, br#"{"test_atomic":{"extra":1}}"#)
+            .contains("at /test_atomic: unexpected fields extra"));
+        assert!(err(MsgKind::Query, b"{").contains("not json"));
+
+        let mut schema = ContractSchema::default();
+        let query = serde_json::to_value(schema_for!(QueryMsg)).unwrap();
+        schema.set_msg_schema(MsgKind::Query, &query).unwrap();
+        schema
+            .validate(MsgKind::Query, br#"{"recurse":{"depth":3}}"#)
+            .unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+use cosmwasm_std::{Addr, Coin, Decimal, Delegation, Uint128, Validator};
+use prost::Message;
+
+use crate::rpc_items::cosmos::base::query::v1beta1::PageRequest;
+use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+use crate::{CwClientBackend, Error};
+
+// more than the active set of any chain, so that a single page holds every bonded validator
+const VALIDATORS_PAGE_LIMIT: u64 = 1000;
+
+// the messages of cosmos.staking.v1beta1 used here, which are not compiled from the protos
+// since they pull in most of tendermint
+#[derive(Clone, PartialEq, Message)]
+struct QueryParamsRequest {}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryParamsResponse {
+    #[prost(message, optional, tag = "1")]
+    params: Option<Params>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Params {
+    #[prost(string, tag = "5")]
+    bond_denom: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryValidatorsRequest {
+    #[prost(string, tag = "1")]
+    status: String,
+    #[prost(message, optional, tag = "2")]
+    pagination: Option<PageRequest>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryValidatorsResponse {
+    #[prost(message, repeated, tag = "1")]
+    validators: Vec<ProtoValidator>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryValidatorRequest {
+    #[prost(string, tag = "1")]
+    validator_addr: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryValidatorResponse {
+    #[prost(message, optional, tag = "1")]
+    validator: Option<ProtoValidator>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoValidator {
+    #[prost(string, tag = "1")]
+    operator_address: String,
+    #[prost(message, optional, tag = "10")]
+    commission: Option<Commission>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Commission {
+    #[prost(message, optional, tag = "1")]
+    commission_rates: Option<CommissionRates>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct CommissionRates {
+    #[prost(string, tag = "1")]
+    rate: String,
+    #[prost(string, tag = "2")]
+    max_rate: String,
+    #[prost(string, tag = "3")]
+    max_change_rate: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryDelegatorDelegationsRequest {
+    #[prost(string, tag = "1")]
+    delegator_addr: String,
+    #[prost(message, optional, tag = "2")]
+    pagination: Option<PageRequest>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryDelegatorDelegationsResponse {
+    #[prost(message, repeated, tag = "1")]
+    delegation_responses: Vec<DelegationResponse>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryDelegationRequest {
+    #[prost(string, tag = "1")]
+    delegator_addr: String,
+    #[prost(string, tag = "2")]
+    validator_addr: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryDelegationResponse {
+    #[prost(message, optional, tag = "1")]
+    delegation_response: Option<DelegationResponse>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct DelegationResponse {
+    #[prost(message, optional, tag = "1")]
+    delegation: Option<ProtoDelegation>,
+    #[prost(message, optional, tag = "2")]
+    balance: Option<ProtoCoin>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDelegation {
+    #[prost(string, tag = "1")]
+    delegator_address: String,
+    #[prost(string, tag = "2")]
+    validator_address: String,
+}
+
+// sdk.Dec is encoded as its atomics, with 18 decimal places
+fn parse_dec(dec: &str) -> Result<Decimal, Error> {
+    let atomics: Uint128 = dec.parse().map_err(Error::format_error)?;
+    Decimal::from_atomics(atomics, 18).map_err(Error::format_error)
+}
+
+fn parse_coin(coin: Option<ProtoCoin>) -> Result<Coin, Error> {
+    let coin = coin.unwrap_or_default();
+    let amount: u128 = if coin.amount.is_empty() {
+        0
+    } else {
+        coin.amount.parse().map_err(Error::format_error)?
+    };
+    Ok(Coin::new(amount, coin.denom))
+}
+
+fn decode_validator(validator: ProtoValidator) -> Result<Validator, Error> {
+    let rates = validator
+        .commission
+        .and_then(|c| c.commission_rates)
+        .unwrap_or_default();
+    Ok(Validator {
+        address: validator.operator_address,
+        commission: parse_dec(&rates.rate)?,
+        max_commission: parse_dec(&rates.max_rate)?,
+        max_change_rate: parse_dec(&rates.max_change_rate)?,
+    })
+}
+
+fn decode_delegation(response: DelegationResponse) -> Result<Delegation, Error> {
+    let delegation = response.delegation.unwrap_or_default();
+    Ok(Delegation {
+        delegator: Addr::unchecked(delegation.delegator_address),
+        validator: delegation.validator_address,
+        amount: parse_coin(response.balance)?,
+    })
+}
+
+fn page(limit: u64) -> Option<PageRequest> {
+    Some(PageRequest {
+        limit,
+        ..Default::default()
+    })
+}
+
+/// denom of the staking token
+pub fn bonded_denom(client: &mut dyn CwClientBackend) -> Result<String, Error> {
+    let out = client.query_grpc(
+        "/cosmos.staking.v1beta1.Query/Params",
+        &QueryParamsRequest {}.encode_to_vec(),
+    )?;
+    let response = QueryParamsResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+    Ok(response.params.unwrap_or_default().bond_denom)
+}
+
+/// validators of the active set, the same ones as wasmd returns to contracts
+pub fn bonded_validators(client: &mut dyn CwClientBackend) -> Result<Vec<Validator>, Error> {
+    let request = QueryValidatorsRequest {
+        status: "BOND_STATUS_BONDED".to_string(),
+        pagination: page(VALIDATORS_PAGE_LIMIT),
+    };
+    let out = client.query_grpc(
+        "/cosmos.staking.v1beta1.Query/Validators",
+        &request.encode_to_vec(),
+    )?;
+    let response = QueryValidatorsResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+    response
+        .validators
+        .into_iter()
+        .map(decode_validator)
+        .collect()
+}
+
+/// None if there is no such validator
+pub fn validator(
+    client: &mut dyn CwClientBackend,
+    address: &str,
+) -> Result<Option<Validator>, Error> {
+    let request = QueryValidatorRequest {
+        validator_addr: address.to_string(),
+    };
+    // the chain fails the query if there is no such validator
+    let out = match client.query_grpc(
+        "/cosmos.staking.v1beta1.Query/Validator",
+        &request.encode_to_vec(),
+    ) {
+        Ok(out) => out,
+        Err(Error::TendermintError(_, _)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let response = QueryValidatorResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+    response.validator.map(decode_validator).transpose()
+}
+
+/// every delegation of delegator
+pub fn delegations(
+    client: &mut dyn CwClientBackend,
+    delegator: &str,
+) -> Result<Vec<Delegation>, Error> {
+    let request = QueryDelegatorDelegationsRequest {
+        delegator_addr: delegator.to_string(),
+        pagination: page(VALIDATORS_PAGE_LIMIT),
+    };
+    // accounts unknown to the chain have no delegations
+    let out = match client.query_grpc(
+        "/cosmos.staking.v1beta1.Query/DelegatorDelegations",
+        &request.encode_to_vec(),
+    ) {
+        Ok(out) => out,
+        Err(Error::TendermintError(_, _)) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let response =
+        QueryDelegatorDelegationsResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+    response
+        .delegation_responses
+        .into_iter()
+        .map(decode_delegation)
+        .collect()
+}
+
+/// None if delegator does not delegate to validator
+pub fn delegation(
+    client: &mut dyn CwClientBackend,
+    delegator: &str,
+    validator: &str,
+) -> Result<Option<Delegation>, Error> {
+    let request = QueryDelegationRequest {
+        delegator_addr: delegator.to_string(),
+        validator_addr: validator.to_string(),
+    };
+    let out = match client.query_grpc(
+        "/cosmos.staking.v1beta1.Query/Delegation",
+        &request.encode_to_vec(),
+    ) {
+        Ok(out) => out,
+        Err(Error::TendermintError(_, _)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let response = QueryDelegationResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+    response
+        .delegation_response
+        .map(decode_delegation)
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coin, Decimal};
+    use prost::Message;
+
+    use super::{
+        decode_delegation, decode_validator, parse_dec, Commission, CommissionRates,
+        DelegationResponse, ProtoDelegation, ProtoValidator, QueryValidatorsResponse,
+    };
+    use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+
+    #[test]
+    fn test_decode_staking() {
+        assert_eq!(parse_dec("50000000000000000").unwrap(), Decimal::percent(5));
+        assert!(parse_dec("0.05").is_err());
+
+        let response = QueryValidatorsResponse {
+            validators: vec![ProtoValidator {
+                operator_address: "wasmvaloper1abc".to_string(),
+                commission: Some(Commission {
+                    commission_rates: Some(CommissionRates {
+                        rate: "100000000000000000".to_string(),
+                        max_rate: "200000000000000000".to_string(),
+                        max_change_rate: "10000000000000000".to_string(),
+                    }),
+                }),
+            }],
+        };
+        let response =
+            QueryValidatorsResponse::decode(response.encode_to_vec().as_slice()).unwrap();
+        let validator = decode_validator(response.validators[0].clone()).unwrap();
+        assert_eq!(validator.address, "wasmvaloper1abc");
+        assert_eq!(validator.commission, Decimal::percent(10));
+        assert_eq!(validator.max_commission, Decimal::percent(20));
+        assert_eq!(validator.max_change_rate, Decimal::percent(1));
+
+        let delegation = decode_delegation(DelegationResponse {
+            delegation: Some(ProtoDelegation {
+                delegator_address: "wasm1delegator".to_string(),
+                validator_address: "wasmvaloper1abc".to_string(),
+            }),
+            balance: Some(ProtoCoin {
+                denom: "umlg".to_string(),
+                amount: "1000".to_string(),
+            }),
+        })
+        .unwrap();
+        assert_eq!(delegation.delegator.as_str(), "wasm1delegator");
+        assert_eq!(delegation.amount, coin(1000, "umlg"));
+    }
+}
@@ -1,43 +1,320 @@
+use crate::address_prefix;
+use crate::fork::capabilities::default_capabilities;
+use crate::fork::client_backend::{CodeInfo, ContractInfo, DenomMetadata, DenomUnit};
+use crate::fork::distribution::Distribution;
+use crate::fork::events::{
+    burn_events, coin_received_event, coin_spent_event, coins_string, message_event,
+    transfer_events,
+};
+use crate::fork::gov::{Gov, Proposal};
+use crate::fork::ibc::{
+    bound_port, contract_port, escrow_address, local_denom, Acknowledgement,
+    FungibleTokenPacketData, Ibc, TRANSFER_PORT,
+};
+use crate::fork::journal::{Journal, StorageJournal, Undo};
+use crate::fork::staking;
+use crate::fork::tokenfactory::TokenFactory;
+use crate::module_address;
 use crate::CwClientBackend;
 use crate::Error;
+use crate::ExecutionHooks;
+use crate::LazyStorage;
+use crate::NativeContract;
+use crate::RpcMockApi;
+use crate::SharedStorage;
+use crate::StorageChange;
 use cosmwasm_std::{
-    to_binary, Addr, AllBalanceResponse, BalanceResponse, BankMsg, BankQuery, Binary, Coin,
-    ContractResult, Event, Response, Timestamp, Uint128,
+    to_binary, Addr, AllBalanceResponse, AllDelegationsResponse, AllValidatorsResponse,
+    BalanceResponse, BankMsg, BankQuery, Binary, BondedDenomResponse, ChannelResponse, Coin,
+    ContractInfoResponse, ContractResult, DelegationResponse, DistributionMsg, Event,
+    FullDelegation, GovMsg, HexBinary, IbcMsg, IbcPacket, IbcQuery, IbcTimeout,
+    ListChannelsResponse, Response, StakingQuery, Timestamp, Uint128, ValidatorResponse,
+    VoteOption,
 };
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::collections::hash_map::Entry;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::{Arc, RwLock};
 
 pub type ContractStorage = BTreeMap<Vec<u8>, Vec<u8>>;
 
-const BLOCK_EPOCH: u64 = 1_000_000_000;
+// same as the default page size of cosmos-sdk
+const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// bank queries added in cosmwasm 1.3, which the vendored cosmwasm-std can't parse as BankQuery
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DenomMetadataQuery {
+    DenomMetadata {
+        denom: String,
+    },
+    AllDenomMetadata {
+        #[serde(default)]
+        pagination: Option<PageRequest>,
+    },
+}
+
+/// the code info query added in cosmwasm 1.2, which the vendored cosmwasm-std can't parse as WasmQuery
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeInfoQuery {
+    CodeInfo { code_id: u64 },
+}
+
+/// the distribution queries added in cosmwasm 1.4, which the vendored cosmwasm-std can't parse as QueryRequest
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistributionQuery {
+    DelegatorWithdrawAddress {
+        delegator_address: String,
+    },
+    DelegationRewards {
+        delegator_address: String,
+        validator_address: String,
+    },
+}
+
+/// pagination of AllDenomMetadata, the key is the base denom to start from
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct PageRequest {
+    pub key: Option<Binary>,
+    pub limit: u32,
+    pub reverse: bool,
+}
+
+/// lists the keys created, updated and deleted between old and new
+pub fn diff_storage(old: &ContractStorage, new: &ContractStorage) -> Vec<StorageChange> {
+    let mut changes = Vec::new();
+    for (key, old_value) in old.iter() {
+        match new.get(key) {
+            Some(new_value) if new_value == old_value => {}
+            new_value => changes.push(StorageChange {
+                key: Binary::from(key.as_slice()),
+                old: Some(Binary::from(old_value.as_slice())),
+                new: new_value.map(|v| Binary::from(v.as_slice())),
+            }),
+        }
+    }
+    for (key, new_value) in new.iter() {
+        if !old.contains_key(key) {
+            changes.push(StorageChange {
+                key: Binary::from(key.as_slice()),
+                old: None,
+                new: Some(Binary::from(new_value.as_slice())),
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.key.as_slice().cmp(b.key.as_slice()));
+    changes
+}
+
+const DEFAULT_SECONDS_PER_BLOCK: u64 = 1;
+
+/// Controls how blocks are produced by the simulator.
+/// After every successful execute or instantiate, `blocks_per_execute` blocks are produced,
+/// each advancing the timestamp by `seconds_per_block`.
+/// If `blocks_per_execute` is 0, blocks are only produced by Model::produce_block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockConfig {
+    pub seconds_per_block: u64,
+    pub blocks_per_execute: u64,
+}
+
+impl BlockConfig {
+    /// blocks are only advanced explicitly
+    pub fn manual(seconds_per_block: u64) -> Self {
+        Self {
+            seconds_per_block,
+            blocks_per_execute: 0,
+        }
+    }
+
+    pub fn is_manual(&self) -> bool {
+        self.blocks_per_execute == 0
+    }
+}
+
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self {
+            seconds_per_block: DEFAULT_SECONDS_PER_BLOCK,
+            blocks_per_execute: 1,
+        }
+    }
+}
+
+/// memory held by a model, see Model::memory_stats.
+/// Storages and code shared with clones, e.g. with snapshots, are counted in full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// number of loaded contracts
+    pub contracts: usize,
+    pub storage_records: usize,
+    /// size of the keys and values of all loaded storages
+    pub storage_bytes: usize,
+    /// storages not copied yet since the model was cloned
+    pub shared_storages: usize,
+    /// size of the distinct code of loaded contracts
+    pub code_bytes: usize,
+    pub compiled_modules: usize,
+    /// instances kept for queries, each holding its own wasm memory
+    pub idle_instances: usize,
+}
+
+/// resources used by a contract, see Model::contract_metrics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ContractMetrics {
+    /// size of the wasm code of the contract
+    pub code_size: usize,
+    /// size of the module compiled from the code, serialized
+    pub module_size: usize,
+    /// wasm memory pages of 64 KiB the instance had at the end of the last call, 0 if never called
+    pub memory_pages: usize,
+    /// gas used by all calls to the contract so far, in CosmWasm gas
+    pub gas_used: u64,
+    pub calls: u64,
+}
 
 /// techically contract code is not part of contract state, but we just name it as 'state' for simplicity
+/// Clones share the code, and the storage until it is written to
 pub struct ContractState {
-    pub code: Vec<u8>,
-    pub storage: Arc<RwLock<ContractStorage>>,
+    pub code: Arc<Vec<u8>>,
+    pub storage: SharedStorage,
+    pub info: ContractInfo,
+    /// set if storage is fetched from chain on demand
+    pub lazy: Option<Arc<LazyStorage>>,
 }
 
 impl Clone for ContractState {
     fn clone(&self) -> Self {
         Self {
             code: self.code.clone(),
-            storage: Arc::new(RwLock::new(self.storage.read().unwrap().clone())),
+            storage: Arc::new(RwLock::new(Arc::clone(&*self.storage.read().unwrap()))),
+            info: self.info.clone(),
+            lazy: self
+                .lazy
+                .as_ref()
+                .map(|lazy| Arc::new(LazyStorage::clone(lazy))),
+        }
+    }
+}
+
+impl ContractState {
+    /// makes sure the value of key is in storage, fetching it if storage is lazy
+    pub fn fetch_key(&self, key: &[u8]) -> Result<(), Error> {
+        match &self.lazy {
+            Some(lazy) => lazy.fetch_key(&self.storage, key),
+            None => Ok(()),
+        }
+    }
+
+    /// makes sure the whole storage is loaded, downloading it if storage is lazy
+    pub fn fetch_all(&self) -> Result<(), Error> {
+        match &self.lazy {
+            Some(lazy) => lazy.sync(&self.storage),
+            None => Ok(()),
         }
     }
 }
 
+/// states of the modules other than wasm, small enough to be copied at checkpoints
+#[derive(Clone)]
+struct ModuleStates {
+    bank_states: HashMap<Addr, HashMap<String, Uint128>>,
+    supplies: HashMap<String, Uint128>,
+    denom_metadata: BTreeMap<String, DenomMetadata>,
+    all_denom_metadata_fetched: bool,
+    tokenfactory: TokenFactory,
+    distribution: Distribution,
+    gov: Gov,
+    ibc: Ibc,
+    #[cfg(feature = "osmosis")]
+    gamm: crate::fork::osmosis::Gamm,
+}
+
+/// see AllStates::checkpoint
+pub(crate) struct StatesCheckpoint {
+    // length of the journal
+    undo: usize,
+    modules: ModuleStates,
+}
+
 #[derive(Clone)]
 pub struct AllStates {
     contract_states: HashMap<Addr, ContractState>,
+    // metadata of the code stored during the simulation, see Model::store_code
+    stored_codes: BTreeMap<u64, CodeInfo>,
+    // metadata of the code added with Model::add_custom_code
+    custom_codes: BTreeMap<u64, CodeInfo>,
     bank_states: HashMap<Addr, HashMap<String, Uint128>>,
+    // total supply per denom, fetched lazily
+    supplies: HashMap<String, Uint128>,
+    // denom metadata by base denom, fetched lazily or set by cheats
+    denom_metadata: BTreeMap<String, DenomMetadata>,
+    all_denom_metadata_fetched: bool,
+    tokenfactory: TokenFactory,
+    distribution: Distribution,
+    gov: Gov,
+    ibc: Ibc,
+    #[cfg(feature = "osmosis")]
+    gamm: crate::fork::osmosis::Gamm,
+    // changes to contracts since the oldest open checkpoint
+    journal: Journal,
     pub client: Box<dyn CwClientBackend>,
+    /// see Model::set_hooks
+    pub hooks: Option<Arc<dyn ExecutionHooks>>,
+    /// see Model::register_native_contract
+    pub native_contracts: BTreeMap<Addr, Arc<dyn NativeContract>>,
+    /// fetch the storage of contracts key by key instead of all at once when they are loaded
+    pub lazy_storage: bool,
     // fields related to blockchain environment
     pub block_number: u64,
     pub block_timestamp: Timestamp,
+    pub block_config: BlockConfig,
     pub chain_id: String,
+    /// index of transactions in their block, see Model::cheat_transaction_info
+    pub tx_index: u32,
+    /// synthetic hash of the running transaction, None between transactions
+    pub tx_hash: Option<String>,
     pub canonical_address_length: usize,
     pub bech32_prefix: String,
+    /// prefixes accepted besides bech32_prefix with their canonical lengths, see Model::add_bech32_prefix
+    pub extra_bech32_prefixes: Vec<(String, usize)>,
+    /// see Model::set_capabilities
+    pub capabilities: BTreeSet<String>,
+    // code ids pinned to the wasm cache of the chain, fetched on first use, see Model::pin_code
+    pinned_codes: Option<BTreeSet<u64>>,
+}
+
+// code ids pinned on chain, which are few enough to be fetched page by page
+fn fetch_pinned_codes(client: &mut dyn CwClientBackend) -> Result<BTreeSet<u64>, Error> {
+    use crate::rpc_items::cosmos::base::query::v1beta1::PageRequest;
+    use crate::rpc_items::cosmwasm::wasm::v1::{QueryPinnedCodesRequest, QueryPinnedCodesResponse};
+    use prost::Message;
+
+    let mut code_ids = BTreeSet::new();
+    let mut key = Vec::new();
+    loop {
+        let request = QueryPinnedCodesRequest {
+            pagination: Some(PageRequest {
+                key,
+                limit: DEFAULT_PAGE_LIMIT as u64,
+                ..Default::default()
+            }),
+        };
+        let out = client.query_grpc(
+            "/cosmwasm.wasm.v1.Query/PinnedCodes",
+            &request.encode_to_vec(),
+        )?;
+        let response =
+            QueryPinnedCodesResponse::decode(out.as_slice()).map_err(Error::format_error)?;
+        code_ids.extend(response.code_ids);
+        key = response.pagination.map(|p| p.next_key).unwrap_or_default();
+        if key.is_empty() {
+            return Ok(code_ids);
+        }
+    }
 }
 
 impl AllStates {
@@ -46,26 +323,198 @@ impl AllStates {
         canonical_address_length: usize,
         bech32_prefix: &str,
     ) -> Result<Self, Error> {
+        // fail early on prefixes contracts could not use
+        RpcMockApi::new(canonical_address_length, bech32_prefix)?;
         let mut client = client;
         let block_number = client.block_number();
         let block_timestamp = client.timestamp()?;
         let chain_id = client.chain_id()?;
         Ok(Self {
             contract_states: HashMap::new(),
+            stored_codes: BTreeMap::new(),
+            custom_codes: BTreeMap::new(),
             bank_states: HashMap::new(),
+            supplies: HashMap::new(),
+            denom_metadata: BTreeMap::new(),
+            all_denom_metadata_fetched: false,
+            tokenfactory: TokenFactory::new(),
+            distribution: Distribution::new(),
+            gov: Gov::new(),
+            ibc: Ibc::new(),
+            #[cfg(feature = "osmosis")]
+            gamm: crate::fork::osmosis::Gamm::new(),
+            journal: Journal::default(),
             client,
+            hooks: None,
+            native_contracts: BTreeMap::new(),
+            lazy_storage: false,
             block_number,
             block_timestamp,
+            block_config: BlockConfig::default(),
             chain_id,
+            tx_index: 0,
+            tx_hash: None,
             canonical_address_length,
             bech32_prefix: bech32_prefix.to_string(),
+            extra_bech32_prefixes: Vec::new(),
+            capabilities: default_capabilities(),
+            pinned_codes: None,
         })
     }
 
+    /// the api of contracts, with the address format of the chain
+    pub fn api(&self) -> Result<RpcMockApi, Error> {
+        let mut api = RpcMockApi::new(self.canonical_address_length, &self.bech32_prefix)?;
+        for (prefix, canonical_length) in self.extra_bech32_prefixes.iter() {
+            api = api.with_prefix(prefix, *canonical_length)?;
+        }
+        Ok(api)
+    }
+
     pub fn contract_state_insert(&mut self, contract_addr: Addr, contract_state: ContractState) {
         self.contract_states.insert(contract_addr, contract_state);
     }
 
+    /// inserts the state of an instantiated contract, it is removed if the transaction is rolled back
+    pub fn contract_state_create(&mut self, contract_addr: Addr, contract_state: ContractState) {
+        self.journal.record(Undo::Created {
+            contract: contract_addr.clone(),
+        });
+        self.contract_states.insert(contract_addr, contract_state);
+    }
+
+    /// modifies the code and info of a loaded contract, they are restored if the transaction is rolled back
+    pub fn update_contract_metadata<F>(&mut self, contract_addr: &Addr, f: F)
+    where
+        F: FnOnce(&mut Arc<Vec<u8>>, &mut ContractInfo),
+    {
+        if let Some(contract_state) = self.contract_states.get_mut(contract_addr) {
+            self.journal.record(Undo::Metadata {
+                contract: contract_addr.clone(),
+                code: contract_state.code.clone(),
+                info: contract_state.info.clone(),
+            });
+            f(&mut contract_state.code, &mut contract_state.info);
+        }
+    }
+
+    /// where the storage of contract_addr records its writes, so that they can be rolled back
+    pub(crate) fn storage_journal(&self, contract_addr: &Addr) -> StorageJournal {
+        self.journal.storage_journal(contract_addr)
+    }
+
+    /// the states to roll back to if a transaction or submessage fails.
+    /// Changes to contracts are journaled as they are made, the other modules are copied
+    pub(crate) fn checkpoint(&self) -> StatesCheckpoint {
+        StatesCheckpoint {
+            undo: self.journal.len(),
+            modules: ModuleStates {
+                bank_states: self.bank_states.clone(),
+                supplies: self.supplies.clone(),
+                denom_metadata: self.denom_metadata.clone(),
+                all_denom_metadata_fetched: self.all_denom_metadata_fetched,
+                tokenfactory: self.tokenfactory.clone(),
+                distribution: self.distribution.clone(),
+                gov: self.gov.clone(),
+                ibc: self.ibc.clone(),
+                #[cfg(feature = "osmosis")]
+                gamm: self.gamm.clone(),
+            },
+        }
+    }
+
+    /// undoes the changes journaled since checkpoint, newest first, and restores the other modules.
+    /// Contracts loaded from chain since are kept, they hold the state on chain
+    pub(crate) fn rollback(&mut self, checkpoint: StatesCheckpoint) {
+        for undo in self.journal.split_off(checkpoint.undo).into_iter().rev() {
+            match undo {
+                Undo::Storage { contract, key, old } => {
+                    if let Some(contract_state) = self.contract_states.get(&contract) {
+                        let mut storage = contract_state.storage.write().unwrap();
+                        let storage = Arc::make_mut(&mut *storage);
+                        match old {
+                            Some(value) => storage.insert(key, value),
+                            None => storage.remove(&key),
+                        };
+                    }
+                }
+                Undo::Created { contract } => {
+                    self.contract_states.remove(&contract);
+                }
+                Undo::Metadata {
+                    contract,
+                    code,
+                    info,
+                } => {
+                    if let Some(contract_state) = self.contract_states.get_mut(&contract) {
+                        contract_state.code = code;
+                        contract_state.info = info;
+                    }
+                }
+            }
+        }
+        let modules = checkpoint.modules;
+        self.bank_states = modules.bank_states;
+        self.supplies = modules.supplies;
+        self.denom_metadata = modules.denom_metadata;
+        self.all_denom_metadata_fetched = modules.all_denom_metadata_fetched;
+        self.tokenfactory = modules.tokenfactory;
+        self.distribution = modules.distribution;
+        self.gov = modules.gov;
+        self.ibc = modules.ibc;
+        #[cfg(feature = "osmosis")]
+        {
+            self.gamm = modules.gamm;
+        }
+    }
+
+    /// keeps the changes made since checkpoint, they can no longer be rolled back
+    pub(crate) fn commit(&mut self, checkpoint: StatesCheckpoint) {
+        self.journal.truncate(checkpoint.undo);
+    }
+
+    /// storage changes of every contract written to since checkpoint, from the journal
+    pub(crate) fn storage_changes(
+        &self,
+        checkpoint: &StatesCheckpoint,
+    ) -> BTreeMap<Addr, Vec<StorageChange>> {
+        // the value of each key before its first write
+        let mut old_values: BTreeMap<Addr, BTreeMap<Vec<u8>, Option<Vec<u8>>>> = BTreeMap::new();
+        for undo in self.journal.since(checkpoint.undo) {
+            if let Undo::Storage { contract, key, old } = undo {
+                old_values
+                    .entry(contract)
+                    .or_default()
+                    .entry(key)
+                    .or_insert(old);
+            }
+        }
+        let mut out = BTreeMap::new();
+        for (contract_addr, values) in old_values {
+            let contract_state = match self.contract_states.get(&contract_addr) {
+                Some(contract_state) => contract_state,
+                None => continue,
+            };
+            let storage = contract_state.storage.read().unwrap();
+            let changes: Vec<StorageChange> = values
+                .into_iter()
+                .filter_map(|(key, old)| {
+                    let new = storage.get(&key);
+                    (old.as_ref() != new).then(|| StorageChange {
+                        key: Binary::from(key.as_slice()),
+                        old: old.map(Binary::from),
+                        new: new.map(|v| Binary::from(v.as_slice())),
+                    })
+                })
+                .collect();
+            drop(storage);
+            if !changes.is_empty() {
+                out.insert(contract_addr, changes);
+            }
+        }
+        out
+    }
+
     pub fn contract_state_remove(&mut self, contract_addr: &Addr) {
         self.contract_states.remove(contract_addr);
     }
@@ -77,17 +526,296 @@ impl AllStates {
             .unwrap()
             .storage
             .write()
-            .unwrap() = new_storage;
+            .unwrap() = Arc::new(new_storage);
     }
 
     pub fn contract_state_get(&self, contract_addr: &Addr) -> Option<&ContractState> {
         self.contract_states.get(contract_addr)
     }
 
+    // the pinned code ids, fetched from the chain the first time
+    fn pinned_codes(&mut self) -> &mut BTreeSet<u64> {
+        if self.pinned_codes.is_none() {
+            // clients without protobuf queries, like the LCD client, know no pinned code
+            let pinned = fetch_pinned_codes(self.client.as_mut()).unwrap_or_default();
+            self.pinned_codes = Some(pinned);
+        }
+        self.pinned_codes.as_mut().unwrap()
+    }
+
+    pub fn is_pinned(&mut self, code_id: u64) -> bool {
+        self.pinned_codes().contains(&code_id)
+    }
+
+    /// see Model::pin_code
+    pub fn set_pinned(&mut self, code_id: u64, pinned: bool) {
+        if pinned {
+            self.pinned_codes().insert(code_id);
+        } else {
+            self.pinned_codes().remove(&code_id);
+        }
+    }
+
+    /// the response of wasmd to a ContractInfo query of contract_addr, which must be loaded
+    pub fn contract_info_query(&mut self, contract_addr: &Addr) -> Result<Binary, Error> {
+        let contract_state = self.contract_state_get(contract_addr).ok_or_else(|| {
+            Error::invalid_argument(format!("no such contract: {}", contract_addr))
+        })?;
+        let info = contract_state.info.clone();
+        let ibc_port = bound_port(contract_addr, &contract_state.code)?;
+        let mut response = ContractInfoResponse::new(info.code_id, info.creator);
+        response.admin = info.admin;
+        response.pinned = self.is_pinned(info.code_id);
+        response.ibc_port = ibc_port;
+        Ok(to_binary(&response)?)
+    }
+
     pub fn contract_state_get_mut(&mut self, contract_addr: &Addr) -> Option<&mut ContractState> {
         self.contract_states.get_mut(contract_addr)
     }
 
+    /// registers the metadata of code stored during the simulation
+    pub fn insert_stored_code(&mut self, info: CodeInfo) {
+        self.stored_codes.insert(info.code_id, info);
+    }
+
+    /// metadata of code stored during the simulation
+    pub fn stored_code(&self, code_id: u64) -> Option<&CodeInfo> {
+        self.stored_codes.get(&code_id)
+    }
+
+    /// registers the metadata of code added by the user, replacing the code of the chain
+    pub fn insert_custom_code(&mut self, info: CodeInfo) {
+        self.custom_codes.insert(info.code_id, info);
+    }
+
+    /// creator and checksum of code_id, code stored in the simulation or added by the user
+    /// takes precedence over the code of the chain
+    pub fn code_info(&mut self, code_id: u64) -> Result<CodeInfo, Error> {
+        if let Some(info) = self
+            .stored_codes
+            .get(&code_id)
+            .or_else(|| self.custom_codes.get(&code_id))
+        {
+            return Ok(info.clone());
+        }
+        self.client.query_wasm_code_info(code_id)
+    }
+
+    /// whether a loaded contract runs code_id
+    pub fn code_id_in_use(&self, code_id: u64) -> bool {
+        self.contract_states
+            .values()
+            .any(|state| state.info.code_id == code_id)
+    }
+
+    /// fails if a contract fetched from chain runs a code id allocated to code stored during the simulation
+    pub fn check_fetched_code_id(&self, contract_addr: &Addr, code_id: u64) -> Result<(), Error> {
+        if self.stored_codes.contains_key(&code_id) {
+            return Err(Error::invalid_argument(format!(
+                "code id {} of {} on chain collides with code stored in the simulation",
+                code_id, contract_addr
+            )));
+        }
+        Ok(())
+    }
+
+    /// fails if the contract at contract_addr can't be addressed with the bech32 prefixes of the
+    /// simulation, instead of it failing later on its own address. Addresses which are not bech32
+    /// are left to the chain
+    pub fn check_fetched_address(&self, contract_addr: &Addr) -> Result<(), Error> {
+        let (prefix, length) = match address_prefix(contract_addr.as_str()) {
+            Ok(prefix) => prefix,
+            Err(_) => return Ok(()),
+        };
+        let canonical_length = if prefix == self.bech32_prefix {
+            Some(self.canonical_address_length)
+        } else {
+            self.extra_bech32_prefixes
+                .iter()
+                .find(|(extra, _)| *extra == prefix)
+                .map(|(_, canonical_length)| *canonical_length)
+        };
+        match canonical_length {
+            None => Err(Error::invalid_argument(format!(
+                "contract {} has bech32 prefix {}, but the model was created with prefix {}, \
+                 create it with prefix {} or with Model::new_auto",
+                contract_addr, prefix, self.bech32_prefix, prefix
+            ))),
+            Some(canonical_length) if length > canonical_length => {
+                Err(Error::invalid_argument(format!(
+                    "contract {} has a {} byte canonical address, but addresses with prefix {} \
+                     are at most {} bytes long, see Model::set_canonical_address_length",
+                    contract_addr, length, prefix, canonical_length
+                )))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// the code of a loaded contract with the sha256 checksum, if any
+    pub fn loaded_code(&self, checksum: &[u8]) -> Option<Arc<Vec<u8>>> {
+        self.contract_states
+            .values()
+            .map(|state| &state.code)
+            .find(|code| Sha256::digest(code.as_slice()).as_slice() == checksum)
+            .cloned()
+    }
+
+    /// the loaded contract instantiated with label, the lowest address if there are several
+    pub fn contract_by_label(&self, label: &str) -> Option<Addr> {
+        self.contract_states
+            .iter()
+            .filter(|(_, state)| state.info.label == label)
+            .map(|(addr, _)| addr)
+            .min()
+            .cloned()
+    }
+
+    /// sha256 over the block, the code, info and storage of every loaded contract and the nonzero
+    /// balances of every loaded account, in the order of addresses, keys and denoms.
+    /// Lazily loaded storages are downloaded whole first, so that the keys read so far don't matter
+    pub fn state_hash(&self) -> Result<Vec<u8>, Error> {
+        let mut hasher = Sha256::new();
+        // every item is prefixed with its length, so that no two states encode the same
+        let mut update = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_be_bytes());
+            hasher.update(bytes);
+        };
+        update(&self.block_number.to_be_bytes());
+        update(&self.block_timestamp.nanos().to_be_bytes());
+        update(self.chain_id.as_bytes());
+        update(&(self.contract_states.len() as u64).to_be_bytes());
+        let mut contract_addrs: Vec<_> = self.contract_states.keys().collect();
+        contract_addrs.sort();
+        for contract_addr in contract_addrs {
+            let contract_state = &self.contract_states[contract_addr];
+            contract_state.fetch_all()?;
+            let info = &contract_state.info;
+            update(contract_addr.as_bytes());
+            update(&Sha256::digest(contract_state.code.as_slice()));
+            update(&info.code_id.to_be_bytes());
+            update(info.creator.as_bytes());
+            // contracts without admin differ from contracts with an empty admin
+            update(&[info.admin.is_some() as u8]);
+            update(info.admin.as_deref().unwrap_or_default().as_bytes());
+            update(info.label.as_bytes());
+            let storage = contract_state.storage.read().unwrap();
+            update(&(storage.len() as u64).to_be_bytes());
+            for (key, value) in storage.iter() {
+                update(key);
+                update(value);
+            }
+        }
+        let mut addrs: Vec<_> = self.bank_states.keys().collect();
+        addrs.sort();
+        for addr in addrs {
+            let mut nonzero: Vec<_> = self.bank_states[addr]
+                .iter()
+                .filter(|(_, a)| !a.is_zero())
+                .collect();
+            nonzero.sort();
+            // accounts loaded without balances are the same as accounts never loaded
+            if nonzero.is_empty() {
+                continue;
+            }
+            update(addr.as_bytes());
+            update(&(nonzero.len() as u64).to_be_bytes());
+            for (denom, amount) in nonzero {
+                update(denom.as_bytes());
+                update(&amount.u128().to_be_bytes());
+            }
+        }
+        Ok(hasher.finalize().to_vec())
+    }
+
+    /// memory held by loaded contracts, the fields not about contracts are left 0
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats {
+            contracts: self.contract_states.len(),
+            ..MemoryStats::default()
+        };
+        let mut codes = HashSet::new();
+        for contract_state in self.contract_states.values() {
+            let storage = contract_state.storage.read().unwrap();
+            stats.storage_records += storage.len();
+            stats.storage_bytes += storage
+                .iter()
+                .map(|(k, v)| k.len() + v.len())
+                .sum::<usize>();
+            if Arc::strong_count(&*storage) > 1 {
+                stats.shared_storages += 1;
+            }
+            if codes.insert(Arc::as_ptr(&contract_state.code)) {
+                stats.code_bytes += contract_state.code.len();
+            }
+        }
+        stats
+    }
+
+    /// source of the storage of a contract about to be loaded, if lazy storage is enabled
+    pub fn lazy_source(&self, contract_addr: &Addr) -> Option<Arc<LazyStorage>> {
+        if self.lazy_storage {
+            Some(Arc::new(LazyStorage::new(
+                contract_addr,
+                self.client.clone(),
+            )))
+        } else {
+            None
+        }
+    }
+
+    /// initial storage of a contract about to be loaded, empty if storage is lazy
+    pub fn fetch_contract_storage(
+        &mut self,
+        contract_addr: &Addr,
+    ) -> Result<(ContractStorage, Option<Arc<LazyStorage>>), Error> {
+        match self.lazy_source(contract_addr) {
+            Some(lazy) => Ok((ContractStorage::new(), Some(lazy))),
+            None => Ok((
+                self.client
+                    .query_wasm_contract_state_all(contract_addr.as_str())?,
+                None,
+            )),
+        }
+    }
+
+    /// storage changes of every loaded contract, relative to prev
+    /// contracts which were not loaded in prev are compared against their storage on chain,
+    /// or against empty storage if they were instantiated
+    pub fn storage_diff(&mut self, prev: &AllStates) -> BTreeMap<Addr, Vec<StorageChange>> {
+        let mut out = BTreeMap::new();
+        let contract_addrs: Vec<Addr> = self.contract_states.keys().cloned().collect();
+        for contract_addr in contract_addrs {
+            let prev_state = prev.contract_state_get(&contract_addr);
+            let lazy = self.contract_states[&contract_addr].lazy.clone();
+            let mut old = match prev_state {
+                Some(contract_state) => contract_state.storage.read().unwrap().as_ref().clone(),
+                None if lazy.is_some() => ContractStorage::new(),
+                None => self
+                    .client
+                    .query_wasm_contract_state_all(contract_addr.as_str())
+                    .unwrap_or_default(),
+            };
+            if let Some(lazy) = lazy {
+                // keys fetched since prev had the same value on chain back then
+                let prev_lazy = prev_state.and_then(|s| s.lazy.as_ref());
+                for (key, value) in lazy.chain_values() {
+                    if !prev_lazy.is_some_and(|l| l.is_fetched(&key)) {
+                        old.entry(key).or_insert(value);
+                    }
+                }
+            }
+            let new = self.contract_states[&contract_addr].storage.read().unwrap();
+            let changes = diff_storage(&old, &new);
+            drop(new);
+            if !changes.is_empty() {
+                out.insert(contract_addr, changes);
+            }
+        }
+        out
+    }
+
     pub fn insert_bank_state(&mut self, addr: Addr, balances: HashMap<String, Uint128>) {
         self.bank_states.insert(addr, balances);
     }
@@ -100,24 +828,30 @@ impl AllStates {
         self.bank_states.entry(addr)
     }
 
-    /// emulate blockchain block creation
-    /// increment block number by 1
-    /// increment timestamp by a constant
-    pub fn update_block(&mut self) {
-        self.block_number += 1;
-        self.block_timestamp.plus_nanos(BLOCK_EPOCH);
+    /// hash of the sequence-th transaction of the simulation, hex encoded like the hashes of the
+    /// chain. It is derived from the chain id, the block and the index of the transaction, so that
+    /// simulations running the same transactions see the same hashes
+    pub fn synthetic_tx_hash(&self, sequence: u64) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.chain_id.as_bytes());
+        hasher.update(self.block_number.to_be_bytes());
+        hasher.update(self.tx_index.to_be_bytes());
+        hasher.update(sequence.to_be_bytes());
+        hex::encode_upper(hasher.finalize())
     }
 
-    fn coin_spent_event(sender: &Addr, amount: Uint128, denom: &str) -> Event {
-        Event::new("coin_spent")
-            .add_attribute("spender", sender)
-            .add_attribute("amount", format!("{}{}", amount, denom))
+    /// emulate blockchain block creation after a transaction, according to block_config
+    pub fn update_block(&mut self) {
+        self.produce_blocks(self.block_config.blocks_per_execute);
     }
 
-    fn coin_received_event(receiver: &Addr, amount: Uint128, denom: &str) -> Event {
-        Event::new("coin_received")
-            .add_attribute("receiver", receiver)
-            .add_attribute("amount", format!("{}{}", amount, denom))
+    /// increment block number by n
+    /// increment timestamp by n * seconds_per_block
+    pub fn produce_blocks(&mut self, n: u64) {
+        self.block_number += n;
+        self.block_timestamp = self
+            .block_timestamp
+            .plus_seconds(n * self.block_config.seconds_per_block);
     }
 
     pub fn get_balance(&mut self, owner: &Addr, denom: &str) -> Result<Uint128, Error> {
@@ -151,13 +885,15 @@ impl AllStates {
         }
 
         let balances = self.get_bank_state(owner).unwrap();
-        let coins: Vec<Coin> = balances
+        let mut coins: Vec<Coin> = balances
             .iter()
             .map(|(d, v)| Coin {
                 denom: d.to_string(),
                 amount: *v,
             })
             .collect();
+        // sorted by denom like the sdk, contracts may hash or store the response
+        coins.sort_by(|a, b| a.denom.cmp(&b.denom));
         Ok(coins)
     }
 
@@ -173,21 +909,387 @@ impl AllStates {
         Ok(())
     }
 
+    /// total supply of denom
+    pub fn get_supply(&mut self, denom: &str) -> Result<Uint128, Error> {
+        if let Some(supply) = self.supplies.get(denom) {
+            return Ok(*supply);
+        }
+        let supply = Uint128::new(self.client.query_bank_supply(denom)?);
+        self.supplies.insert(denom.to_string(), supply);
+        Ok(supply)
+    }
+
+    pub fn set_supply(&mut self, denom: &str, supply: Uint128) {
+        self.supplies.insert(denom.to_string(), supply);
+    }
+
+    /// metadata of denom, fails if none is registered
+    pub fn get_denom_metadata(&mut self, denom: &str) -> Result<DenomMetadata, Error> {
+        if let Some(metadata) = self.denom_metadata.get(denom) {
+            return Ok(metadata.clone());
+        }
+        let metadata = self.client.query_bank_denom_metadata(denom)?;
+        self.denom_metadata
+            .insert(denom.to_string(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// metadata of all registered denoms, ordered by base denom
+    pub fn get_all_denom_metadata(&mut self) -> Result<Vec<DenomMetadata>, Error> {
+        if !self.all_denom_metadata_fetched {
+            for metadata in self.client.query_bank_all_denom_metadata()? {
+                // metadata set by cheats takes precedence
+                self.denom_metadata
+                    .entry(metadata.base.clone())
+                    .or_insert(metadata);
+            }
+            self.all_denom_metadata_fetched = true;
+        }
+        Ok(self.denom_metadata.values().cloned().collect())
+    }
+
+    /// registers metadata under its base denom, replacing the existing one
+    pub fn set_denom_metadata(&mut self, metadata: DenomMetadata) {
+        self.denom_metadata.insert(metadata.base.clone(), metadata);
+    }
+
+    pub fn ibc(&self) -> &Ibc {
+        &self.ibc
+    }
+
+    pub fn ibc_mut(&mut self) -> &mut Ibc {
+        &mut self.ibc
+    }
+
+    pub fn tokenfactory(&self) -> &TokenFactory {
+        &self.tokenfactory
+    }
+
+    pub fn tokenfactory_mut(&mut self) -> &mut TokenFactory {
+        &mut self.tokenfactory
+    }
+
+    /// balancer pool with id, fetched from chain on first use
+    #[cfg(feature = "osmosis")]
+    pub fn gamm_pool(
+        &mut self,
+        pool_id: u64,
+    ) -> Result<&mut crate::rpc_items::osmosis::gamm::v1beta1::Pool, Error> {
+        self.gamm.pool(self.client.as_mut(), pool_id)
+    }
+
+    #[cfg(feature = "osmosis")]
+    pub fn gamm_mut(&mut self) -> &mut crate::fork::osmosis::Gamm {
+        &mut self.gamm
+    }
+
+    /// swaps token_in along routes of (pool id, token out denom), failing if less than token_out_min is received
+    #[cfg(feature = "osmosis")]
+    pub fn gamm_swap_exact_amount_in(
+        &mut self,
+        sender: &Addr,
+        routes: &[(u64, String)],
+        token_in: Coin,
+        token_out_min: Uint128,
+    ) -> Result<ContractResult<Response>, Error> {
+        use crate::fork::osmosis::swap_exact_amount_in;
+        use crate::rpc_items::osmosis::gamm::v1beta1::{MsgSwapExactAmountInResponse, Pool};
+        use prost::Message;
+
+        // swap against copies of the pools first, so that nothing changes if the swap fails
+        let mut swaps: Vec<(Pool, Coin, Coin)> = Vec::new();
+        let mut token = token_in;
+        for (pool_id, token_out_denom) in routes {
+            // routes may pass the same pool twice
+            let mut pool = match swaps.iter().rev().find(|(p, _, _)| p.id == *pool_id) {
+                Some((pool, _, _)) => pool.clone(),
+                None => self.gamm_pool(*pool_id)?.clone(),
+            };
+            let token_out = match swap_exact_amount_in(&mut pool, &token, token_out_denom) {
+                Ok(amount) => Coin {
+                    denom: token_out_denom.to_string(),
+                    amount,
+                },
+                Err(e) => return Ok(ContractResult::Err(e)),
+            };
+            swaps.push((pool, token, token_out.clone()));
+            token = token_out;
+        }
+        if token.amount < token_out_min {
+            return Ok(ContractResult::Err(format!(
+                "token amount calculated ({}) is lesser than min amount ({})",
+                token.amount, token_out_min
+            )));
+        }
+
+        let mut events = Vec::new();
+        for (pool, token_in, token_out) in swaps {
+            let pool_address = Addr::unchecked(&pool.address);
+            let pool_id = pool.id;
+            self.gamm.set_pool(pool);
+            for (src, dst, coin) in [
+                (sender, &pool_address, &token_in),
+                (&pool_address, sender, &token_out),
+            ] {
+                match self.bank_send(src, dst, std::slice::from_ref(coin))? {
+                    ContractResult::Ok(r) => events.extend(r.events),
+                    err => return Ok(err),
+                }
+            }
+            events.push(
+                Event::new("token_swapped")
+                    .add_attribute("module", "gamm")
+                    .add_attribute("sender", sender.to_string())
+                    .add_attribute("pool_id", pool_id.to_string())
+                    .add_attribute("tokens_in", token_in.to_string())
+                    .add_attribute("tokens_out", token_out.to_string()),
+            );
+        }
+        let data = MsgSwapExactAmountInResponse {
+            token_out_amount: token.amount.to_string(),
+        };
+        Ok(ContractResult::Ok(
+            Response::new()
+                .add_events(events)
+                .set_data(data.encode_to_vec()),
+        ))
+    }
+
+    /// address receiving the staking rewards of delegator
+    pub fn get_withdraw_address(&mut self, delegator: &Addr) -> Result<Addr, Error> {
+        if let Some(address) = self.distribution.withdraw_address(delegator) {
+            return Ok(address.clone());
+        }
+        // accounts unknown to the chain withdraw to themselves
+        let address = self
+            .client
+            .query_distribution_withdraw_address(delegator.as_str())
+            .unwrap_or_else(|_| delegator.to_string());
+        let address = Addr::unchecked(address);
+        self.distribution.set_withdraw_address(delegator, &address);
+        Ok(address)
+    }
+
+    pub fn set_withdraw_address(&mut self, delegator: &Addr, withdraw_address: &Addr) {
+        self.distribution
+            .set_withdraw_address(delegator, withdraw_address);
+    }
+
+    /// pending staking rewards of the delegation to validator
+    pub fn get_delegation_rewards(
+        &mut self,
+        delegator: &Addr,
+        validator: &str,
+    ) -> Result<Vec<Coin>, Error> {
+        if let Some(rewards) = self.distribution.rewards(delegator, validator) {
+            return Ok(rewards.clone());
+        }
+        // the chain fails the query if there is no such delegation
+        let rewards: Vec<Coin> = self
+            .client
+            .query_distribution_rewards(delegator.as_str(), validator)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, amount)| *amount > 0)
+            .map(|(denom, amount)| Coin::new(amount, denom))
+            .collect();
+        self.distribution
+            .set_rewards(delegator, validator, rewards.clone());
+        Ok(rewards)
+    }
+
+    pub fn set_delegation_rewards(
+        &mut self,
+        delegator: &Addr,
+        validator: &str,
+        rewards: Vec<Coin>,
+    ) {
+        self.distribution.set_rewards(delegator, validator, rewards);
+    }
+
+    /// rewards are paid from the account of the distribution module
+    pub fn distribution_execute(
+        &mut self,
+        sender: &Addr,
+        distribution_msg: &DistributionMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        match distribution_msg {
+            DistributionMsg::SetWithdrawAddress { address } => {
+                let address = Addr::unchecked(address);
+                self.set_withdraw_address(sender, &address);
+                let event = Event::new("set_withdraw_address")
+                    .add_attribute("withdraw_address", address.to_string());
+                Ok(ContractResult::Ok(Response::new().add_event(event)))
+            }
+            DistributionMsg::WithdrawDelegatorReward { validator } => {
+                let rewards = self.get_delegation_rewards(sender, validator)?;
+                let withdraw_address = self.get_withdraw_address(sender)?;
+                let module = module_address("distribution", &self.bech32_prefix);
+                let mut response = match self.bank_send(&module, &withdraw_address, &rewards)? {
+                    ContractResult::Ok(r) => r,
+                    err => return Ok(err),
+                };
+                self.set_delegation_rewards(sender, validator, Vec::new());
+                let amount: Vec<String> = rewards.iter().map(|c| c.to_string()).collect();
+                let event = Event::new("withdraw_rewards")
+                    .add_attribute("amount", amount.join(","))
+                    .add_attribute("validator", validator.to_string());
+                response.events.push(event);
+                Ok(ContractResult::Ok(response))
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    /// id the next submitted proposal will get, following the last proposal on chain
+    fn next_proposal_id(&mut self) -> u64 {
+        if let Some(proposal_id) = self.gov.next_proposal_id() {
+            return proposal_id;
+        }
+        let proposal_id = self.client.query_gov_latest_proposal_id().unwrap_or(0) + 1;
+        self.gov.set_next_proposal_id(proposal_id);
+        proposal_id
+    }
+
+    /// proposal submitted during the simulation
+    pub fn proposal(&self, proposal_id: u64) -> Option<&Proposal> {
+        self.gov.proposal(proposal_id)
+    }
+
+    pub fn proposal_mut(&mut self, proposal_id: u64) -> Option<&mut Proposal> {
+        self.gov.proposal_mut(proposal_id)
+    }
+
+    /// votes cast during the simulation
+    pub fn proposal_votes(&self, proposal_id: u64) -> Vec<(Addr, VoteOption)> {
+        self.gov.votes(proposal_id)
+    }
+
+    /// deposits are held by the account of the gov module
+    fn gov_submit_proposal(
+        &mut self,
+        proposer: &Addr,
+        messages: Vec<(String, Binary)>,
+        initial_deposit: Vec<Coin>,
+    ) -> Result<ContractResult<Response>, Error> {
+        use crate::rpc_items::cosmos::gov::v1::MsgSubmitProposalResponse;
+        use prost::Message;
+
+        let proposal_id = self.next_proposal_id();
+        let module = module_address("gov", &self.bech32_prefix);
+        let mut response = match self.bank_send(proposer, &module, &initial_deposit)? {
+            ContractResult::Ok(r) => r,
+            err => return Ok(err),
+        };
+        let type_urls: Vec<&str> = messages.iter().map(|(t, _)| t.as_str()).collect();
+        let event = Event::new("submit_proposal")
+            .add_attribute("proposal_id", proposal_id.to_string())
+            .add_attribute("proposal_messages", type_urls.join(","));
+        response.events.push(event);
+        if !initial_deposit.is_empty() {
+            response
+                .events
+                .push(Self::proposal_deposit_event(proposal_id, &initial_deposit));
+        }
+        self.gov
+            .submit_proposal(proposer, messages, initial_deposit);
+        let data = MsgSubmitProposalResponse { proposal_id };
+        Ok(ContractResult::Ok(response.set_data(data.encode_to_vec())))
+    }
+
+    fn gov_vote(
+        &mut self,
+        voter: &Addr,
+        proposal_id: u64,
+        option: VoteOption,
+    ) -> Result<ContractResult<Response>, Error> {
+        self.next_proposal_id();
+        let event = Event::new("proposal_vote")
+            .add_attribute("option", Self::vote_option_name(&option))
+            .add_attribute("proposal_id", proposal_id.to_string());
+        Ok(match self.gov.vote(proposal_id, voter, option) {
+            Ok(()) => ContractResult::Ok(Response::new().add_event(event)),
+            Err(e) => ContractResult::Err(e),
+        })
+    }
+
+    fn gov_deposit(
+        &mut self,
+        depositor: &Addr,
+        proposal_id: u64,
+        amount: Vec<Coin>,
+    ) -> Result<ContractResult<Response>, Error> {
+        self.next_proposal_id();
+        if let Err(e) = self.gov.deposit(proposal_id, depositor, amount.clone()) {
+            return Ok(ContractResult::Err(e));
+        }
+        let module = module_address("gov", &self.bech32_prefix);
+        Ok(match self.bank_send(depositor, &module, &amount)? {
+            ContractResult::Ok(response) => ContractResult::Ok(
+                response.add_event(Self::proposal_deposit_event(proposal_id, &amount)),
+            ),
+            err => err,
+        })
+    }
+
+    fn proposal_deposit_event(proposal_id: u64, amount: &[Coin]) -> Event {
+        let amount: Vec<String> = amount.iter().map(|c| c.to_string()).collect();
+        Event::new("proposal_deposit")
+            .add_attribute("amount", amount.join(","))
+            .add_attribute("proposal_id", proposal_id.to_string())
+    }
+
+    fn vote_option_name(option: &VoteOption) -> &'static str {
+        match option {
+            VoteOption::Yes => "VOTE_OPTION_YES",
+            VoteOption::No => "VOTE_OPTION_NO",
+            VoteOption::Abstain => "VOTE_OPTION_ABSTAIN",
+            VoteOption::NoWithVeto => "VOTE_OPTION_NO_WITH_VETO",
+        }
+    }
+
+    pub fn gov_execute(
+        &mut self,
+        sender: &Addr,
+        gov_msg: &GovMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        match gov_msg {
+            GovMsg::Vote { proposal_id, vote } => self.gov_vote(sender, *proposal_id, vote.clone()),
+        }
+    }
+
+    /// returns the deposits of a proposal to their depositors
+    pub fn refund_proposal_deposits(
+        &mut self,
+        proposal_id: u64,
+    ) -> Result<ContractResult<Response>, Error> {
+        let deposits = match self.gov.proposal_mut(proposal_id) {
+            Some(proposal) => std::mem::take(&mut proposal.deposits),
+            None => return Ok(ContractResult::Ok(Response::new())),
+        };
+        let module = module_address("gov", &self.bech32_prefix);
+        let mut events = Vec::new();
+        for (depositor, amount) in deposits.iter() {
+            match self.bank_send(&module, depositor, amount)? {
+                ContractResult::Ok(r) => events.extend(r.events),
+                err => return Ok(err),
+            }
+        }
+        Ok(ContractResult::Ok(Response::new().add_events(events)))
+    }
+
     fn bank_send(
         &mut self,
         src: &Addr,
         dst: &Addr,
         amount: &[Coin],
     ) -> Result<ContractResult<Response>, Error> {
-        let mut events = Vec::new();
         for coin in amount.iter() {
             let src_amount = self.get_balance(src, &coin.denom)?;
             let dst_amount = self.get_balance(dst, &coin.denom)?;
             if src_amount >= coin.amount {
                 self.set_balance(src, &coin.denom, src_amount - coin.amount)?;
                 self.set_balance(dst, &coin.denom, dst_amount + coin.amount)?;
-                events.push(Self::coin_spent_event(src, coin.amount, &coin.denom));
-                events.push(Self::coin_received_event(dst, coin.amount, &coin.denom));
             } else {
                 return Ok(ContractResult::Err(format!(
                     "insufficient balance (owner: {}, balance: {}, amount: {})",
@@ -195,8 +1297,10 @@ impl AllStates {
                 )));
             }
         }
-        // TODO: make this more verbose
-        let response = Response::new().add_events(events);
+        if let Some(hooks) = &self.hooks {
+            hooks.on_bank_transfer(src, dst, amount);
+        }
+        let response = Response::new().add_events(transfer_events(src, dst, amount));
         Ok(ContractResult::Ok(response))
     }
 
@@ -209,6 +1313,8 @@ impl AllStates {
             let src_amount = self.get_balance(src, &coin.denom)?;
             if src_amount >= coin.amount {
                 self.set_balance(src, &coin.denom, src_amount - coin.amount)?;
+                let supply = self.get_supply(&coin.denom)?;
+                self.set_supply(&coin.denom, supply.saturating_sub(coin.amount));
             } else {
                 return Ok(ContractResult::Err(format!(
                     "insufficient balance (owner: {}, balance: {}, amount: {})",
@@ -216,11 +1322,880 @@ impl AllStates {
                 )));
             }
         }
-        // TODO: make this more verbose
-        let response = Response::new();
+        let response = Response::new().add_events(burn_events(src, amount));
         Ok(ContractResult::Ok(response))
     }
 
+    /// credit newly minted coins to receiver, increasing the supply
+    pub fn bank_mint(&mut self, receiver: &Addr, amount: &[Coin]) -> Result<Response, Error> {
+        for coin in amount.iter() {
+            let balance = self.get_balance(receiver, &coin.denom)?;
+            self.set_balance(receiver, &coin.denom, balance + coin.amount)?;
+            let supply = self.get_supply(&coin.denom)?;
+            self.set_supply(&coin.denom, supply + coin.amount);
+        }
+        Ok(Response::new().add_event(coin_received_event(receiver, amount)))
+    }
+
+    /// moves coins from every input to every output at once.
+    /// Inputs must be sent by sender, and the inputs and outputs must add up to the same coins
+    pub fn bank_multi_send(
+        &mut self,
+        sender: &Addr,
+        inputs: &[(Addr, Vec<Coin>)],
+        outputs: &[(Addr, Vec<Coin>)],
+    ) -> Result<ContractResult<Response>, Error> {
+        let mut total_in: BTreeMap<&str, Uint128> = BTreeMap::new();
+        let mut total_out: BTreeMap<&str, Uint128> = BTreeMap::new();
+        for (addr, coins) in inputs.iter() {
+            if addr != sender {
+                return Ok(ContractResult::Err(format!(
+                    "unauthorized: input {} is not signed by {}",
+                    addr, sender
+                )));
+            }
+            for coin in coins.iter() {
+                *total_in.entry(&coin.denom).or_default() += coin.amount;
+            }
+        }
+        for (_, coins) in outputs.iter() {
+            for coin in coins.iter() {
+                *total_out.entry(&coin.denom).or_default() += coin.amount;
+            }
+        }
+        total_in.retain(|_, a| !a.is_zero());
+        total_out.retain(|_, a| !a.is_zero());
+        if total_in != total_out {
+            return Ok(ContractResult::Err("sum inputs != sum outputs".to_string()));
+        }
+        // check balances first, so that a failed transfer leaves no partial changes
+        let mut debits: HashMap<(&Addr, &str), Uint128> = HashMap::new();
+        for (addr, coins) in inputs.iter() {
+            for coin in coins.iter() {
+                *debits.entry((addr, &coin.denom)).or_default() += coin.amount;
+            }
+        }
+        // in a fixed order, so that the same balance is reported as insufficient every run
+        let mut debits: Vec<_> = debits.into_iter().collect();
+        debits.sort();
+        for ((addr, denom), amount) in debits.iter() {
+            let balance = self.get_balance(addr, denom)?;
+            if balance < *amount {
+                return Ok(ContractResult::Err(format!(
+                    "insufficient balance (owner: {}, balance: {}, amount: {})",
+                    addr, balance, amount
+                )));
+            }
+        }
+        // like the sdk, a coin_spent and message event per input,
+        // and a coin_received and transfer event per output
+        let mut events = Vec::new();
+        for (addr, coins) in inputs.iter() {
+            for coin in coins.iter() {
+                let balance = self.get_balance(addr, &coin.denom)?;
+                self.set_balance(addr, &coin.denom, balance - coin.amount)?;
+            }
+            events.push(coin_spent_event(addr, coins));
+            events.push(message_event(addr));
+        }
+        for (addr, coins) in outputs.iter() {
+            for coin in coins.iter() {
+                let balance = self.get_balance(addr, &coin.denom)?;
+                self.set_balance(addr, &coin.denom, balance + coin.amount)?;
+            }
+            events.push(coin_received_event(addr, coins));
+            events.push(
+                Event::new("transfer")
+                    .add_attribute("recipient", addr)
+                    .add_attribute("amount", coins_string(coins)),
+            );
+        }
+        // all inputs are of sender, so every output is a transfer from sender
+        if let Some(hooks) = &self.hooks {
+            for (addr, coins) in outputs.iter() {
+                hooks.on_bank_transfer(sender, addr, coins);
+            }
+        }
+        Ok(ContractResult::Ok(Response::new().add_events(events)))
+    }
+
+    /// error message if sender is not the tokenfactory admin of denom
+    fn check_denom_admin(&self, sender: &Addr, denom: &str) -> Option<String> {
+        match self.tokenfactory.admin(denom) {
+            Some(admin) if &admin == sender => None,
+            _ => Some(format!(
+                "unauthorized: {} is not the admin of {}",
+                sender, denom
+            )),
+        }
+    }
+
+    /// executes the protobuf encoded messages of the bank, gov and tokenfactory modules
+    pub fn stargate_execute(
+        &mut self,
+        sender: &Addr,
+        type_url: &str,
+        value: &Binary,
+    ) -> Result<ContractResult<Response>, Error> {
+        use crate::rpc_items::cosmos::bank::v1beta1::{MsgMultiSend, MsgSend};
+        use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+        use crate::rpc_items::osmosis::tokenfactory::v1beta1::{
+            MsgBurn, MsgChangeAdmin, MsgCreateDenom, MsgCreateDenomResponse, MsgMint,
+            MsgSetDenomMetadata,
+        };
+        use prost::Message;
+
+        fn to_coin(coin: ProtoCoin) -> Result<Coin, Error> {
+            let amount = coin.amount.parse().map_err(Error::format_error)?;
+            Ok(Coin {
+                denom: coin.denom,
+                amount: Uint128::new(amount),
+            })
+        }
+        fn to_coins(coins: Vec<ProtoCoin>) -> Result<Vec<Coin>, Error> {
+            coins.into_iter().map(to_coin).collect()
+        }
+        fn to_vote_option(option: i32) -> Result<VoteOption, Error> {
+            match option {
+                1 => Ok(VoteOption::Yes),
+                2 => Ok(VoteOption::Abstain),
+                3 => Ok(VoteOption::No),
+                4 => Ok(VoteOption::NoWithVeto),
+                _ => Err(Error::invalid_argument(format!(
+                    "invalid vote option: {}",
+                    option
+                ))),
+            }
+        }
+        fn check_signer(signer: &str, sender: &Addr) -> Option<String> {
+            if signer != sender.as_str() {
+                Some(format!(
+                    "unauthorized: {} is not signed by {}",
+                    signer, sender
+                ))
+            } else {
+                None
+            }
+        }
+        match type_url {
+            "/cosmos.bank.v1beta1.MsgSend" => {
+                let msg = MsgSend::decode(value.as_slice()).map_err(Error::format_error)?;
+                if msg.from_address != sender.as_str() {
+                    return Ok(ContractResult::Err(format!(
+                        "unauthorized: {} is not signed by {}",
+                        msg.from_address, sender
+                    )));
+                }
+                let dst = Addr::unchecked(msg.to_address);
+                self.bank_send(sender, &dst, &to_coins(msg.amount)?)
+            }
+            "/cosmos.bank.v1beta1.MsgMultiSend" => {
+                let msg = MsgMultiSend::decode(value.as_slice()).map_err(Error::format_error)?;
+                let inputs = msg
+                    .inputs
+                    .into_iter()
+                    .map(|i| Ok((Addr::unchecked(i.address), to_coins(i.coins)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let outputs = msg
+                    .outputs
+                    .into_iter()
+                    .map(|o| Ok((Addr::unchecked(o.address), to_coins(o.coins)?)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                self.bank_multi_send(sender, &inputs, &outputs)
+            }
+            // legacy proposals carry a content instead of messages, so passing them has no effect
+            "/cosmos.gov.v1beta1.MsgSubmitProposal" => {
+                use crate::rpc_items::cosmos::gov::v1beta1::MsgSubmitProposal;
+                let msg =
+                    MsgSubmitProposal::decode(value.as_slice()).map_err(Error::format_error)?;
+                if let Some(e) = check_signer(&msg.proposer, sender) {
+                    return Ok(ContractResult::Err(e));
+                }
+                self.gov_submit_proposal(sender, Vec::new(), to_coins(msg.initial_deposit)?)
+            }
+            "/cosmos.gov.v1.MsgSubmitProposal" => {
+                use crate::rpc_items::cosmos::gov::v1::MsgSubmitProposal;
+                let msg =
+                    MsgSubmitProposal::decode(value.as_slice()).map_err(Error::format_error)?;
+                if let Some(e) = check_signer(&msg.proposer, sender) {
+                    return Ok(ContractResult::Err(e));
+                }
+                let messages = msg
+                    .messages
+                    .into_iter()
+                    .map(|any| (any.type_url, Binary::from(any.value)))
+                    .collect();
+                self.gov_submit_proposal(sender, messages, to_coins(msg.initial_deposit)?)
+            }
+            // v1 messages share the layout of v1beta1, apart from the ignored metadata
+            "/cosmos.gov.v1beta1.MsgVote" | "/cosmos.gov.v1.MsgVote" => {
+                use crate::rpc_items::cosmos::gov::v1beta1::MsgVote;
+                let msg = MsgVote::decode(value.as_slice()).map_err(Error::format_error)?;
+                if let Some(e) = check_signer(&msg.voter, sender) {
+                    return Ok(ContractResult::Err(e));
+                }
+                self.gov_vote(sender, msg.proposal_id, to_vote_option(msg.option)?)
+            }
+            "/cosmos.gov.v1beta1.MsgDeposit" | "/cosmos.gov.v1.MsgDeposit" => {
+                use crate::rpc_items::cosmos::gov::v1beta1::MsgDeposit;
+                let msg = MsgDeposit::decode(value.as_slice()).map_err(Error::format_error)?;
+                if let Some(e) = check_signer(&msg.depositor, sender) {
+                    return Ok(ContractResult::Err(e));
+                }
+                self.gov_deposit(sender, msg.proposal_id, to_coins(msg.amount)?)
+            }
+            "/osmosis.tokenfactory.v1beta1.MsgCreateDenom" => {
+                let msg = MsgCreateDenom::decode(value.as_slice()).map_err(Error::format_error)?;
+                let denom = match TokenFactory::denom(sender, &msg.subdenom) {
+                    Ok(denom) => denom,
+                    Err(e) => return Ok(ContractResult::Err(e)),
+                };
+                // denoms created on chain are recognized by their bank metadata
+                if self.get_denom_metadata(&denom).is_ok() {
+                    return Ok(ContractResult::Err(format!(
+                        "attempting to create a denom that already exists: {}",
+                        denom
+                    )));
+                }
+                if let Err(e) = self.tokenfactory.create_denom(sender, &msg.subdenom) {
+                    return Ok(ContractResult::Err(e));
+                }
+                self.set_denom_metadata(DenomMetadata {
+                    denom_units: vec![DenomUnit {
+                        denom: denom.clone(),
+                        exponent: 0,
+                        aliases: vec![],
+                    }],
+                    base: denom.clone(),
+                    ..Default::default()
+                });
+                let event = Event::new("create_denom")
+                    .add_attribute("creator", sender.to_string())
+                    .add_attribute("new_token_denom", denom.clone());
+                let data = MsgCreateDenomResponse {
+                    new_token_denom: denom,
+                };
+                Ok(ContractResult::Ok(
+                    Response::new()
+                        .add_event(event)
+                        .set_data(data.encode_to_vec()),
+                ))
+            }
+            "/osmosis.tokenfactory.v1beta1.MsgMint" => {
+                let msg = MsgMint::decode(value.as_slice()).map_err(Error::format_error)?;
+                let coin = to_coin(msg.amount.unwrap_or_default())?;
+                if let Some(e) = self.check_denom_admin(sender, &coin.denom) {
+                    return Ok(ContractResult::Err(e));
+                }
+                let receiver = if msg.mint_to_address.is_empty() {
+                    sender.clone()
+                } else {
+                    Addr::unchecked(msg.mint_to_address)
+                };
+                let event = Event::new("tf_mint")
+                    .add_attribute("mint_to_address", receiver.to_string())
+                    .add_attribute("amount", coin.to_string());
+                let response = self.bank_mint(&receiver, &[coin])?;
+                Ok(ContractResult::Ok(response.add_event(event)))
+            }
+            "/osmosis.tokenfactory.v1beta1.MsgBurn" => {
+                let msg = MsgBurn::decode(value.as_slice()).map_err(Error::format_error)?;
+                let coin = to_coin(msg.amount.unwrap_or_default())?;
+                if let Some(e) = self.check_denom_admin(sender, &coin.denom) {
+                    return Ok(ContractResult::Err(e));
+                }
+                let owner = if msg.burn_from_address.is_empty() {
+                    sender.clone()
+                } else {
+                    Addr::unchecked(msg.burn_from_address)
+                };
+                let event = Event::new("tf_burn")
+                    .add_attribute("burn_from_address", owner.to_string())
+                    .add_attribute("amount", coin.to_string());
+                Ok(match self.bank_burn(&owner, &[coin])? {
+                    ContractResult::Ok(response) => ContractResult::Ok(response.add_event(event)),
+                    err => err,
+                })
+            }
+            "/osmosis.tokenfactory.v1beta1.MsgChangeAdmin" => {
+                let msg = MsgChangeAdmin::decode(value.as_slice()).map_err(Error::format_error)?;
+                if let Some(e) = self.check_denom_admin(sender, &msg.denom) {
+                    return Ok(ContractResult::Err(e));
+                }
+                let new_admin =
+                    Some(Addr::unchecked(&msg.new_admin)).filter(|a| !a.as_str().is_empty());
+                self.tokenfactory.set_admin(&msg.denom, new_admin.as_ref());
+                let event = Event::new("change_admin")
+                    .add_attribute("denom", msg.denom)
+                    .add_attribute("new_admin", msg.new_admin);
+                Ok(ContractResult::Ok(Response::new().add_event(event)))
+            }
+            "/osmosis.tokenfactory.v1beta1.MsgSetDenomMetadata" => {
+                let msg =
+                    MsgSetDenomMetadata::decode(value.as_slice()).map_err(Error::format_error)?;
+                let metadata: DenomMetadata = msg.metadata.unwrap_or_default().into();
+                if let Some(e) = self.check_denom_admin(sender, &metadata.base) {
+                    return Ok(ContractResult::Err(e));
+                }
+                let event = Event::new("set_denom_metadata")
+                    .add_attribute("denom", metadata.base.clone())
+                    .add_attribute(
+                        "denom_metadata",
+                        serde_json::to_string(&metadata).map_err(Error::from)?,
+                    );
+                self.set_denom_metadata(metadata);
+                Ok(ContractResult::Ok(Response::new().add_event(event)))
+            }
+            #[cfg(feature = "osmosis")]
+            "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn"
+            | "/osmosis.poolmanager.v1beta1.MsgSwapExactAmountIn" => {
+                use crate::rpc_items::osmosis::gamm::v1beta1::MsgSwapExactAmountIn;
+                let msg =
+                    MsgSwapExactAmountIn::decode(value.as_slice()).map_err(Error::format_error)?;
+                if msg.sender != sender.as_str() {
+                    return Ok(ContractResult::Err(format!(
+                        "unauthorized: {} is not signed by {}",
+                        msg.sender, sender
+                    )));
+                }
+                let routes: Vec<(u64, String)> = msg
+                    .routes
+                    .into_iter()
+                    .map(|r| (r.pool_id, r.token_out_denom))
+                    .collect();
+                let token_out_min = msg
+                    .token_out_min_amount
+                    .parse()
+                    .map_err(Error::format_error)?;
+                self.gamm_swap_exact_amount_in(
+                    sender,
+                    &routes,
+                    to_coin(msg.token_in.unwrap_or_default())?,
+                    Uint128::new(token_out_min),
+                )
+            }
+            _ => Err(Error::invalid_argument(format!(
+                "unsupported stargate message: {}",
+                type_url
+            ))),
+        }
+    }
+
+    /// answers the protobuf encoded queries of the bank module which are not covered by BankQuery,
+    /// and those of the distribution and tokenfactory modules
+    pub fn stargate_query(&mut self, path: &str, data: &Binary) -> Result<Binary, Error> {
+        use crate::rpc_items::cosmos::bank::v1beta1::{
+            QueryDenomMetadataRequest, QueryDenomMetadataResponse, QueryDenomsMetadataResponse,
+        };
+        use crate::rpc_items::cosmos::base::v1beta1::DecCoin;
+        use crate::rpc_items::cosmos::distribution::v1beta1::{
+            QueryDelegationRewardsRequest, QueryDelegationRewardsResponse,
+            QueryDelegatorWithdrawAddressRequest, QueryDelegatorWithdrawAddressResponse,
+        };
+        use crate::rpc_items::osmosis::tokenfactory::v1beta1::{
+            DenomAuthorityMetadata, QueryDenomAuthorityMetadataRequest,
+            QueryDenomAuthorityMetadataResponse, QueryDenomsFromCreatorRequest,
+            QueryDenomsFromCreatorResponse,
+        };
+        use prost::Message;
+
+        match path {
+            "/cosmos.bank.v1beta1.Query/DenomMetadata" => {
+                let request = QueryDenomMetadataRequest::decode(data.as_slice())
+                    .map_err(Error::format_error)?;
+                let response = QueryDenomMetadataResponse {
+                    metadata: Some(self.get_denom_metadata(&request.denom)?.into()),
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            // pagination is ignored, all metadata is returned at once
+            "/cosmos.bank.v1beta1.Query/DenomsMetadata" => {
+                let response = QueryDenomsMetadataResponse {
+                    metadatas: self
+                        .get_all_denom_metadata()?
+                        .into_iter()
+                        .map(|m| m.into())
+                        .collect(),
+                    pagination: None,
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            "/osmosis.tokenfactory.v1beta1.Query/DenomAuthorityMetadata" => {
+                let request = QueryDenomAuthorityMetadataRequest::decode(data.as_slice())
+                    .map_err(Error::format_error)?;
+                if TokenFactory::parse_denom(&request.denom).is_none() {
+                    return Err(Error::invalid_argument(format!(
+                        "not a tokenfactory denom: {}",
+                        request.denom
+                    )));
+                }
+                let admin = self.tokenfactory.admin(&request.denom);
+                let response = QueryDenomAuthorityMetadataResponse {
+                    authority_metadata: Some(DenomAuthorityMetadata {
+                        admin: admin.map(|a| a.to_string()).unwrap_or_default(),
+                    }),
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            // only denoms created during the simulation are returned
+            "/osmosis.tokenfactory.v1beta1.Query/DenomsFromCreator" => {
+                let request = QueryDenomsFromCreatorRequest::decode(data.as_slice())
+                    .map_err(Error::format_error)?;
+                let response = QueryDenomsFromCreatorResponse {
+                    denoms: self.tokenfactory.denoms_from_creator(&request.creator),
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            // pools swapped against in the simulation are answered locally
+            #[cfg(feature = "osmosis")]
+            "/osmosis.gamm.v1beta1.Query/Pool" => {
+                use crate::fork::osmosis::BALANCER_POOL_TYPE_URL;
+                use crate::rpc_items::osmosis::gamm::v1beta1::{
+                    QueryPoolRequest, QueryPoolResponse,
+                };
+                let request =
+                    QueryPoolRequest::decode(data.as_slice()).map_err(Error::format_error)?;
+                match self.gamm.local_pool(request.pool_id) {
+                    Some(pool) => {
+                        let response = QueryPoolResponse {
+                            pool: Some(prost_types::Any {
+                                type_url: BALANCER_POOL_TYPE_URL.to_string(),
+                                value: pool.encode_to_vec(),
+                            }),
+                        };
+                        Ok(Binary::from(response.encode_to_vec()))
+                    }
+                    None => Ok(Binary::from(self.client.query_grpc(path, data.as_slice())?)),
+                }
+            }
+            #[cfg(feature = "osmosis")]
+            "/osmosis.gamm.v1beta1.Query/SpotPrice"
+            | "/osmosis.gamm.v2.Query/SpotPrice"
+            | "/osmosis.poolmanager.v1beta1.Query/SpotPrice" => {
+                use crate::fork::osmosis::{format_dec, spot_price};
+                use crate::rpc_items::osmosis::gamm::v1beta1::{
+                    QuerySpotPriceRequest, QuerySpotPriceResponse,
+                };
+                let request =
+                    QuerySpotPriceRequest::decode(data.as_slice()).map_err(Error::format_error)?;
+                let pool = match self.gamm.local_pool(request.pool_id) {
+                    Some(pool) => pool,
+                    None => {
+                        return Ok(Binary::from(self.client.query_grpc(path, data.as_slice())?))
+                    }
+                };
+                // the deprecated gamm v1beta1 query swaps base and quote
+                let (base, quote) = if path == "/osmosis.gamm.v1beta1.Query/SpotPrice" {
+                    (&request.quote_asset_denom, &request.base_asset_denom)
+                } else {
+                    (&request.base_asset_denom, &request.quote_asset_denom)
+                };
+                let price = spot_price(pool, base, quote).map_err(Error::invalid_argument)?;
+                let response = QuerySpotPriceResponse {
+                    spot_price: format_dec(price),
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            // other queries such as twap are read only, and answered by the chain
+            #[cfg(feature = "osmosis")]
+            path if path.starts_with("/osmosis.") => {
+                Ok(Binary::from(self.client.query_grpc(path, data.as_slice())?))
+            }
+            "/cosmos.distribution.v1beta1.Query/DelegationRewards" => {
+                let request = QueryDelegationRewardsRequest::decode(data.as_slice())
+                    .map_err(Error::format_error)?;
+                let rewards = self.get_delegation_rewards(
+                    &Addr::unchecked(request.delegator_address),
+                    &request.validator_address,
+                )?;
+                let response = QueryDelegationRewardsResponse {
+                    rewards: rewards
+                        .into_iter()
+                        .map(|coin| DecCoin {
+                            denom: coin.denom,
+                            // sdk.Dec is encoded as its atomics, with 18 decimal places
+                            amount: (coin.amount.full_mul(10u128.pow(18))).to_string(),
+                        })
+                        .collect(),
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            "/cosmos.distribution.v1beta1.Query/DelegatorWithdrawAddress" => {
+                let request = QueryDelegatorWithdrawAddressRequest::decode(data.as_slice())
+                    .map_err(Error::format_error)?;
+                let response = QueryDelegatorWithdrawAddressResponse {
+                    withdraw_address: self
+                        .get_withdraw_address(&Addr::unchecked(request.delegator_address))?
+                        .to_string(),
+                };
+                Ok(Binary::from(response.encode_to_vec()))
+            }
+            // staking is not simulated, its queries are answered by the chain
+            path if path.starts_with("/cosmos.staking.") => {
+                Ok(Binary::from(self.client.query_grpc(path, data.as_slice())?))
+            }
+            _ => Err(Error::invalid_argument(format!(
+                "unsupported stargate query: {}",
+                path
+            ))),
+        }
+    }
+
+    /// answers the code info query of WasmQuery, see CodeInfoQuery
+    pub fn code_info_query(&mut self, query: &CodeInfoQuery) -> Result<Binary, Error> {
+        match query {
+            CodeInfoQuery::CodeInfo { code_id } => {
+                let info = self.code_info(*code_id)?;
+                // CodeInfoResponse is missing from the vendored cosmwasm-std
+                let response = serde_json::json!({
+                    "code_id": info.code_id,
+                    "creator": info.creator,
+                    "checksum": HexBinary::from(info.checksum),
+                });
+                Ok(to_binary(&response).map_err(Error::from)?)
+            }
+        }
+    }
+
+    /// answers the distribution queries of cosmwasm 1.4, see DistributionQuery
+    pub fn distribution_query(&mut self, query: &DistributionQuery) -> Result<Binary, Error> {
+        match query {
+            DistributionQuery::DelegatorWithdrawAddress { delegator_address } => {
+                let withdraw_address =
+                    self.get_withdraw_address(&Addr::unchecked(delegator_address))?;
+                let response = serde_json::json!({
+                    "withdraw_address": withdraw_address,
+                });
+                Ok(to_binary(&response).map_err(Error::from)?)
+            }
+            DistributionQuery::DelegationRewards {
+                delegator_address,
+                validator_address,
+            } => {
+                let rewards = self.get_delegation_rewards(
+                    &Addr::unchecked(delegator_address),
+                    validator_address,
+                )?;
+                // DecCoin amounts are Decimal256, which serializes whole numbers the same as Uint128
+                let response = serde_json::json!({
+                    "rewards": rewards,
+                });
+                Ok(to_binary(&response).map_err(Error::from)?)
+            }
+        }
+    }
+
+    /// answers StakingQuery from the staking module of the chain at the forked height, with the
+    /// rewards of delegations as simulated. Delegations can always be redelegated in full
+    pub fn staking_query(&mut self, staking_query: &StakingQuery) -> Result<Binary, Error> {
+        let client = self.client.as_mut();
+        match staking_query {
+            StakingQuery::BondedDenom {} => {
+                let response = BondedDenomResponse {
+                    denom: staking::bonded_denom(client)?,
+                };
+                to_binary(&response).map_err(Error::from)
+            }
+            StakingQuery::AllValidators {} => {
+                let response = AllValidatorsResponse {
+                    validators: staking::bonded_validators(client)?,
+                };
+                to_binary(&response).map_err(Error::from)
+            }
+            StakingQuery::Validator { address } => {
+                let response = ValidatorResponse {
+                    validator: staking::validator(client, address)?,
+                };
+                to_binary(&response).map_err(Error::from)
+            }
+            StakingQuery::AllDelegations { delegator } => {
+                let delegations = staking::delegations(client, delegator)?;
+                to_binary(&AllDelegationsResponse { delegations }).map_err(Error::from)
+            }
+            StakingQuery::Delegation {
+                delegator,
+                validator,
+            } => {
+                let delegation = match staking::delegation(client, delegator, validator)? {
+                    Some(d) => Some(FullDelegation {
+                        accumulated_rewards: self
+                            .get_delegation_rewards(&d.delegator, &d.validator)?,
+                        can_redelegate: d.amount.clone(),
+                        delegator: d.delegator,
+                        validator: d.validator,
+                        amount: d.amount,
+                    }),
+                    None => None,
+                };
+                to_binary(&DelegationResponse { delegation }).map_err(Error::from)
+            }
+            _ => Err(Error::invalid_argument(format!(
+                "unsupported staking query {:?}",
+                staking_query
+            ))),
+        }
+    }
+
+    /// answers the denom metadata queries of BankQuery, see DenomMetadataQuery
+    pub fn denom_metadata_query(&mut self, query: &DenomMetadataQuery) -> Result<Binary, Error> {
+        match query {
+            DenomMetadataQuery::DenomMetadata { denom } => {
+                let response = serde_json::json!({
+                    "metadata": self.get_denom_metadata(denom)?,
+                });
+                Ok(to_binary(&response).map_err(Error::from)?)
+            }
+            DenomMetadataQuery::AllDenomMetadata { pagination } => {
+                let mut all = self.get_all_denom_metadata()?;
+                let (limit, next_key) = match pagination {
+                    Some(page) => {
+                        let key = page
+                            .key
+                            .as_ref()
+                            .map(|k| String::from_utf8_lossy(k.as_slice()).to_string());
+                        if page.reverse {
+                            all.reverse();
+                        }
+                        if let Some(key) = key {
+                            all.retain(|m| {
+                                if page.reverse {
+                                    m.base <= key
+                                } else {
+                                    m.base >= key
+                                }
+                            });
+                        }
+                        let limit = if page.limit == 0 {
+                            DEFAULT_PAGE_LIMIT
+                        } else {
+                            page.limit as usize
+                        };
+                        (
+                            limit,
+                            all.get(limit).map(|m| Binary::from(m.base.as_bytes())),
+                        )
+                    }
+                    None => (all.len(), None),
+                };
+                all.truncate(limit);
+                let response = serde_json::json!({
+                    "metadata": all,
+                    "next_key": next_key,
+                });
+                Ok(to_binary(&response).map_err(Error::from)?)
+            }
+        }
+    }
+
+    fn send_packet_event(packet: &IbcPacket) -> Event {
+        Event::new("send_packet")
+            .add_attribute("packet_src_port", &packet.src.port_id)
+            .add_attribute("packet_src_channel", &packet.src.channel_id)
+            .add_attribute("packet_dst_port", &packet.dest.port_id)
+            .add_attribute("packet_dst_channel", &packet.dest.channel_id)
+            .add_attribute("packet_sequence", packet.sequence.to_string())
+    }
+
+    /// executes the ibc messages of contracts, queueing the packets they send to be relayed
+    pub fn ibc_execute(
+        &mut self,
+        sender: &Addr,
+        ibc_msg: &IbcMsg,
+    ) -> Result<ContractResult<Response>, Error> {
+        match ibc_msg {
+            IbcMsg::Transfer {
+                channel_id,
+                to_address,
+                amount,
+                timeout,
+            } => self.ibc_transfer(sender, channel_id, to_address, amount, timeout),
+            IbcMsg::SendPacket {
+                channel_id,
+                data,
+                timeout,
+            } => {
+                let port_id = contract_port(sender);
+                match self
+                    .ibc
+                    .send_packet(&port_id, channel_id, data.clone(), timeout.clone())
+                {
+                    Ok(packet) => Ok(ContractResult::Ok(
+                        Response::new().add_event(Self::send_packet_event(&packet)),
+                    )),
+                    Err(e) => Ok(ContractResult::Err(e)),
+                }
+            }
+            IbcMsg::CloseChannel { channel_id } => {
+                let port_id = contract_port(sender);
+                match self.ibc.close_channel(&port_id, channel_id) {
+                    Ok(_) => Ok(ContractResult::Ok(
+                        Response::new().add_event(
+                            Event::new("channel_close_init")
+                                .add_attribute("port_id", port_id)
+                                .add_attribute("channel_id", channel_id),
+                        ),
+                    )),
+                    Err(e) => Ok(ContractResult::Err(e)),
+                }
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    /// sends amount to to_address on the other end of a transfer channel.
+    /// Coins native to this chain are escrowed, vouchers of coins coming back over the channel are burned
+    fn ibc_transfer(
+        &mut self,
+        sender: &Addr,
+        channel_id: &str,
+        to_address: &str,
+        amount: &Coin,
+        timeout: &IbcTimeout,
+    ) -> Result<ContractResult<Response>, Error> {
+        let denom_path = match self.ibc.denom_trace(&amount.denom) {
+            Some(path) => path.to_string(),
+            None if amount.denom.starts_with("ibc/") => {
+                return Ok(ContractResult::Err(format!(
+                    "denom trace of {} is unknown, only vouchers received during the simulation can be sent",
+                    amount.denom
+                )))
+            }
+            None => amount.denom.clone(),
+        };
+        let own_prefix = format!("{}/{}/", TRANSFER_PORT, channel_id);
+        let moved = if denom_path.starts_with(&own_prefix) {
+            self.bank_burn(sender, std::slice::from_ref(amount))?
+        } else {
+            let escrow = escrow_address(TRANSFER_PORT, channel_id, &self.bech32_prefix);
+            self.bank_send(sender, &escrow, std::slice::from_ref(amount))?
+        };
+        let mut response = match moved {
+            ContractResult::Ok(r) => r,
+            err => return Ok(err),
+        };
+        let data = FungibleTokenPacketData {
+            denom: denom_path,
+            amount: amount.amount,
+            sender: sender.to_string(),
+            receiver: to_address.to_string(),
+            memo: String::new(),
+        };
+        let data = to_binary(&data).map_err(Error::from)?;
+        match self
+            .ibc
+            .send_packet(TRANSFER_PORT, channel_id, data, timeout.clone())
+        {
+            Ok(packet) => {
+                response.events.push(Self::send_packet_event(&packet));
+                Ok(ContractResult::Ok(response))
+            }
+            Err(e) => Ok(ContractResult::Err(e)),
+        }
+    }
+
+    /// credits the coins of an ICS20 packet to its receiver, returning the acknowledgement as data.
+    /// Coins returning over the channel they were sent over are released from escrow,
+    /// others are minted as vouchers
+    pub fn ibc_transfer_receive(
+        &mut self,
+        packet: &IbcPacket,
+    ) -> Result<ContractResult<Response>, Error> {
+        let data: FungibleTokenPacketData = match cosmwasm_std::from_binary(&packet.data) {
+            Ok(data) => data,
+            Err(e) => return Ok(ContractResult::Err(e.to_string())),
+        };
+        let receiver = Addr::unchecked(&data.receiver);
+        let source_prefix = format!("{}/{}/", packet.src.port_id, packet.src.channel_id);
+        let received = match data.denom.strip_prefix(&source_prefix) {
+            Some(unwound) => {
+                let escrow = escrow_address(
+                    &packet.dest.port_id,
+                    &packet.dest.channel_id,
+                    &self.bech32_prefix,
+                );
+                let coin = Coin::new(data.amount.u128(), local_denom(unwound));
+                self.bank_send(&escrow, &receiver, &[coin])?
+            }
+            None => {
+                let path = format!(
+                    "{}/{}/{}",
+                    packet.dest.port_id, packet.dest.channel_id, data.denom
+                );
+                let denom = self.ibc.add_denom_trace(&path);
+                ContractResult::Ok(
+                    self.bank_mint(&receiver, &[Coin::new(data.amount.u128(), denom)])?,
+                )
+            }
+        };
+        Ok(match received {
+            ContractResult::Ok(r) => {
+                let mut response = r.add_event(
+                    Event::new("fungible_token_packet")
+                        .add_attribute("receiver", &data.receiver)
+                        .add_attribute("denom", &data.denom)
+                        .add_attribute("amount", data.amount),
+                );
+                response.data = Some(Acknowledgement::success());
+                ContractResult::Ok(response)
+            }
+            err => err,
+        })
+    }
+
+    /// gives the coins of an ICS20 packet which failed or timed out back to their sender
+    pub fn ibc_transfer_refund(
+        &mut self,
+        packet: &IbcPacket,
+    ) -> Result<ContractResult<Response>, Error> {
+        let data: FungibleTokenPacketData = match cosmwasm_std::from_binary(&packet.data) {
+            Ok(data) => data,
+            Err(e) => return Ok(ContractResult::Err(e.to_string())),
+        };
+        let sender = Addr::unchecked(&data.sender);
+        let coin = Coin::new(data.amount.u128(), local_denom(&data.denom));
+        let own_prefix = format!("{}/{}/", packet.src.port_id, packet.src.channel_id);
+        if data.denom.starts_with(&own_prefix) {
+            Ok(ContractResult::Ok(self.bank_mint(&sender, &[coin])?))
+        } else {
+            let escrow = escrow_address(
+                &packet.src.port_id,
+                &packet.src.channel_id,
+                &self.bech32_prefix,
+            );
+            self.bank_send(&escrow, &sender, &[coin])
+        }
+    }
+
+    /// answers the channel queries of contracts from the channels opened during the simulation.
+    /// The port of the querying contract is not known, so it must be given
+    pub fn ibc_query(&self, ibc_query: &IbcQuery) -> Result<Binary, Error> {
+        match ibc_query {
+            IbcQuery::ListChannels {
+                port_id: Some(port_id),
+            } => {
+                let response = ListChannelsResponse {
+                    channels: self.ibc.channels(port_id),
+                };
+                to_binary(&response).map_err(Error::from)
+            }
+            IbcQuery::Channel {
+                channel_id,
+                port_id: Some(port_id),
+            } => {
+                let channel = self
+                    .ibc
+                    .channel(channel_id)
+                    .filter(|c| c.open && &c.channel.endpoint.port_id == port_id)
+                    .map(|c| c.channel.clone());
+                to_binary(&ChannelResponse { channel }).map_err(Error::from)
+            }
+            _ => Err(Error::invalid_argument(format!(
+                "unsupported ibc query {:?}, the port id must be given",
+                ibc_query
+            ))),
+        }
+    }
+
     pub fn bank_execute(
         &mut self,
         sender: &Addr,
@@ -248,14 +2223,57 @@ impl AllStates {
                         amount: balance,
                     },
                 };
-                Ok(to_binary(&response).map_err(Error::std_error)?)
+                Ok(to_binary(&response).map_err(Error::from)?)
             }
             BankQuery::AllBalances { address } => {
                 let balances = self.get_balances(&Addr::unchecked(address))?;
                 let response = AllBalanceResponse { amount: balances };
-                Ok(to_binary(&response).map_err(Error::std_error)?)
+                Ok(to_binary(&response).map_err(Error::from)?)
+            }
+            BankQuery::Supply { denom } => {
+                let supply = self.get_supply(denom)?;
+                // SupplyResponse is non_exhaustive, so it can't be constructed here
+                let response = serde_json::json!({
+                    "amount": Coin {
+                        denom: denom.to_string(),
+                        amount: supply,
+                    }
+                });
+                Ok(to_binary(&response).map_err(Error::from)?)
             }
             _ => unimplemented!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_storage, ContractStorage};
+
+    #[test]
+    fn test_diff_storage() {
+        let mut old = ContractStorage::new();
+        old.insert(b"deleted".to_vec(), b"1".to_vec());
+        old.insert(b"updated".to_vec(), b"1".to_vec());
+        old.insert(b"untouched".to_vec(), b"1".to_vec());
+        let mut new = old.clone();
+        new.remove(b"deleted".as_slice());
+        new.insert(b"updated".to_vec(), b"2".to_vec());
+        new.insert(b"created".to_vec(), b"3".to_vec());
+
+        let changes = diff_storage(&old, &new);
+        let keys: Vec<&[u8]> = changes.iter().map(|c| c.key.as_slice()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                b"created".as_slice(),
+                b"deleted".as_slice(),
+                b"updated".as_slice()
+            ]
+        );
+        assert_eq!(changes[0].old, None);
+        assert_eq!(changes[1].new, None);
+        assert_eq!(changes[2].old.as_ref().unwrap().as_slice(), b"1");
+        assert_eq!(changes[2].new.as_ref().unwrap().as_slice(), b"2");
+    }
+}
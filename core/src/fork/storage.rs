@@ -1,15 +1,230 @@
-use crate::ContractStorage;
-use cosmwasm_std::{Order, Record};
+use crate::fork::journal::StorageJournal;
+use crate::{ContractStorage, CwClientBackend, DebugLog, Error, ExecutionHooks};
+use cosmwasm_std::{Addr, Order, Record};
 use cosmwasm_vm::{BackendError, BackendResult, GasInfo, Storage};
 
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+#[cfg(feature = "iterator")]
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// records of storage with keys in [start, end), in the given order
+/// an empty range is returned if start > end
+pub fn scan_range(
+    storage: &ContractStorage,
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+    order: Order,
+) -> Vec<Record> {
+    let range = match (start, end) {
+        (Some(s), Some(e)) => {
+            // BTreeMap.range panics if start > end
+            if s > e {
+                return Vec::new();
+            }
+            storage.range(s.to_vec()..e.to_vec())
+        }
+        (Some(s), None) => storage.range(s.to_vec()..),
+        (None, Some(e)) => storage.range(..e.to_vec()),
+        (None, None) => storage.range(Vec::new()..),
+    };
+    let records = range.map(|(k, v)| (k.clone(), v.clone()));
+    match order {
+        Order::Ascending => records.collect(),
+        Order::Descending => records.rev().collect(),
+    }
+}
+
+/// length prefixed namespaces, the prefix of all keys of a cw-storage-plus Map
+/// (or of a sub-map of a Map with tuple keys, e.g. [b"allowances", owner])
+pub fn namespace_prefix(namespaces: &[&[u8]]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    for namespace in namespaces.iter() {
+        prefix.extend_from_slice(&(namespace.len() as u16).to_be_bytes());
+        prefix.extend_from_slice(namespace);
+    }
+    prefix
+}
+
+/// smallest key greater than all keys starting with prefix, None if there is none
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// splits a cw-storage-plus key into its first `prefixes` length prefixed parts and the rest,
+/// e.g. 1 for the namespace and key of a Map, 2 for the namespace, first and second key of a Map with pair keys
+pub fn split_namespaced_key(key: &[u8], prefixes: usize) -> Option<(Vec<Vec<u8>>, Vec<u8>)> {
+    let mut parts = Vec::new();
+    let mut rest = key;
+    for _ in 0..prefixes {
+        let len = <[u8; 2]>::try_from(rest.get(0..2)?).ok()?;
+        let len = u16::from_be_bytes(len) as usize;
+        parts.push(rest.get(2..2 + len)?.to_vec());
+        rest = &rest[2 + len..];
+    }
+    Some((parts, rest.to_vec()))
+}
+
+/// records of a contract, shared by the clones of its ContractState, e.g. the copies of a model kept
+/// to revert failed calls, until one of them writes to them. Writes go through Arc::make_mut,
+/// so that only the storages which change are copied
+pub type SharedStorage = Arc<RwLock<Arc<ContractStorage>>>;
+
+pub fn shared_storage(storage: ContractStorage) -> SharedStorage {
+    Arc::new(RwLock::new(Arc::new(storage)))
+}
+
+/// records storage accesses of a contract into a debug log
+#[derive(Clone)]
+pub struct StorageTracer {
+    contract_addr: Addr,
+    debug_log: Arc<Mutex<DebugLog>>,
+}
+
+impl StorageTracer {
+    pub fn new(contract_addr: &Addr, debug_log: &Arc<Mutex<DebugLog>>) -> Self {
+        Self {
+            contract_addr: contract_addr.clone(),
+            debug_log: Arc::clone(debug_log),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct LazyStatus {
+    /// value on chain of every key fetched so far, None if it is not set
+    fetched: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    /// set once the whole storage was downloaded
+    synced: bool,
+}
+
+impl LazyStatus {
+    fn is_fetched(&self, key: &[u8]) -> bool {
+        self.synced || self.fetched.contains_key(key)
+    }
+}
+
+/// storage of a contract which is fetched from chain one key at a time, as it is accessed.
+/// Iterators can't know which keys exist, so the first scan downloads the whole storage.
+/// Keys are only fetched once, later accesses see the local storage including any writes
+pub struct LazyStorage {
+    contract_addr: String,
+    client: Arc<Mutex<Box<dyn CwClientBackend>>>,
+    status: RwLock<LazyStatus>,
+}
+
+impl Clone for LazyStorage {
+    fn clone(&self) -> Self {
+        Self {
+            contract_addr: self.contract_addr.clone(),
+            client: Arc::clone(&self.client),
+            status: RwLock::new(self.status.read().unwrap().clone()),
+        }
+    }
+}
+
+impl LazyStorage {
+    pub fn new(contract_addr: &Addr, client: Box<dyn CwClientBackend>) -> Self {
+        Self {
+            contract_addr: contract_addr.to_string(),
+            client: Arc::new(Mutex::new(client)),
+            status: RwLock::new(LazyStatus::default()),
+        }
+    }
+
+    /// whether the value on chain of key is already part of the local storage
+    pub fn is_fetched(&self, key: &[u8]) -> bool {
+        self.status.read().unwrap().is_fetched(key)
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.status.read().unwrap().synced
+    }
+
+    /// values on chain of the keys fetched so far which are set
+    pub fn chain_values(&self) -> ContractStorage {
+        self.status
+            .read()
+            .unwrap()
+            .fetched
+            .iter()
+            .filter_map(|(k, v)| v.as_ref().map(|v| (k.clone(), v.clone())))
+            .collect()
+    }
+
+    /// copies the value on chain of key into storage, unless it was fetched before
+    pub fn fetch_key(
+        &self,
+        storage: &RwLock<Arc<ContractStorage>>,
+        key: &[u8],
+    ) -> Result<(), Error> {
+        if self.is_fetched(key) {
+            return Ok(());
+        }
+        let value = self
+            .client
+            .lock()
+            .unwrap()
+            .query_wasm_contract_state_raw(&self.contract_addr, key)?;
+        let mut status = self.status.write().unwrap();
+        // another thread might have fetched it in the meantime, and written to it since
+        if status.is_fetched(key) {
+            return Ok(());
+        }
+        if let Some(value) = &value {
+            Arc::make_mut(&mut *storage.write().unwrap()).insert(key.to_vec(), value.clone());
+        }
+        status.fetched.insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    /// copies every key on chain which was not fetched before into storage
+    pub fn sync(&self, storage: &RwLock<Arc<ContractStorage>>) -> Result<(), Error> {
+        if self.is_synced() {
+            return Ok(());
+        }
+        let remote = self
+            .client
+            .lock()
+            .unwrap()
+            .query_wasm_contract_state_all(&self.contract_addr)?;
+        let mut status = self.status.write().unwrap();
+        if status.synced {
+            return Ok(());
+        }
+        let mut storage = storage.write().unwrap();
+        let storage = Arc::make_mut(&mut *storage);
+        for (key, value) in remote {
+            if let Entry::Vacant(entry) = status.fetched.entry(key.clone()) {
+                storage.insert(key, value.clone());
+                entry.insert(Some(value));
+            }
+        }
+        status.synced = true;
+        Ok(())
+    }
+}
 
 ///mock storage
 #[derive(Clone)]
 pub struct RpcMockStorage {
-    inner: Arc<RwLock<ContractStorage>>,
+    inner: SharedStorage,
+    lazy: Option<Arc<LazyStorage>>,
+    tracer: Option<StorageTracer>,
+    // contract of this storage and the hooks observing its writes
+    hooks: Option<(Addr, Arc<dyn ExecutionHooks>)>,
+    // where writes are recorded so that they can be rolled back
+    journal: Option<StorageJournal>,
     #[cfg(feature = "iterator")]
     iterators: HashMap<u32, (Vec<Record>, usize)>,
     #[cfg(feature = "iterator")]
@@ -24,14 +239,84 @@ impl fmt::Debug for RpcMockStorage {
 }
 
 impl RpcMockStorage {
-    pub fn new(inner: &Arc<RwLock<ContractStorage>>) -> Self {
+    pub fn new(inner: &SharedStorage) -> Self {
         Self {
             inner: Arc::clone(inner),
+            lazy: None,
+            tracer: None,
+            hooks: None,
+            journal: None,
+            #[cfg(feature = "iterator")]
             iterators: HashMap::new(),
+            #[cfg(feature = "iterator")]
             iterator_id_ctr: 0,
         }
     }
 
+    /// record every access to this storage
+    pub fn with_tracer(mut self, tracer: StorageTracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// report writes to the storage of contract_addr to hooks, see Model::set_hooks
+    pub fn with_hooks(
+        mut self,
+        contract_addr: &Addr,
+        hooks: Option<Arc<dyn ExecutionHooks>>,
+    ) -> Self {
+        self.hooks = hooks.map(|hooks| (contract_addr.clone(), hooks));
+        self
+    }
+
+    /// record writes to journal, see AllStates::checkpoint
+    pub(crate) fn with_journal(mut self, journal: StorageJournal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// fetch keys missing from the storage from chain
+    pub fn with_lazy(mut self, lazy: Option<Arc<LazyStorage>>) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    fn fetch_key(&self, key: &[u8]) -> Result<(), BackendError> {
+        match &self.lazy {
+            Some(lazy) => lazy
+                .fetch_key(&self.inner, key)
+                .map_err(|e| BackendError::unknown(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn trace_read(&self, key: &[u8]) {
+        if let Some(tracer) = &self.tracer {
+            tracer
+                .debug_log
+                .lock()
+                .unwrap()
+                .append_storage_read(&tracer.contract_addr, key);
+        }
+    }
+
+    fn trace_write(&self, key: &[u8], old: Option<Vec<u8>>, new: Option<&[u8]>) {
+        if let Some(journal) = &self.journal {
+            journal.record_write(key, old.clone());
+        }
+        if let Some((contract_addr, hooks)) = &self.hooks {
+            hooks.on_storage_write(contract_addr, key, new);
+        }
+        if let Some(tracer) = &self.tracer {
+            tracer.debug_log.lock().unwrap().append_storage_write(
+                &tracer.contract_addr,
+                key,
+                old,
+                new,
+            );
+        }
+    }
+
     #[cfg(feature = "iterator")]
     pub fn new_iterator(&mut self, records: Vec<Record>) -> u32 {
         self.iterator_id_ctr += 1;
@@ -43,10 +328,12 @@ impl RpcMockStorage {
 
 impl Storage for RpcMockStorage {
     fn get(&self, key: &[u8]) -> BackendResult<Option<Vec<u8>>> {
-        (
-            Ok(self.inner.read().unwrap().get(key).cloned()),
-            GasInfo::free(),
-        )
+        if let Err(e) = self.fetch_key(key) {
+            return (Err(e), GasInfo::free());
+        }
+        let value = self.inner.read().unwrap().get(key).cloned();
+        self.trace_read(key);
+        (Ok(value), GasInfo::free())
     }
 
     #[cfg(feature = "iterator")]
@@ -56,33 +343,13 @@ impl Storage for RpcMockStorage {
         end: Option<&[u8]>,
         order: Order,
     ) -> BackendResult<u32> {
-        // BTreeMap.range panics if range is start > end.
-        // However, this cases represent just empty range and we treat it as such.
-
-        let inner = self.inner.read().unwrap();
-        let range = match (start, end) {
-            (Some(s), Some(e)) => {
-                if start > end {
-                    drop(inner);
-                    return (Ok(self.new_iterator(vec![])), GasInfo::free());
-                } else {
-                    inner.range(s.to_vec()..e.to_vec())
-                }
-            }
-            (Some(s), None) => inner.range(s.to_vec()..),
-            (None, Some(e)) => inner.range(..e.to_vec()),
-            (None, None) => inner.range(vec![]..),
-        };
-        let mut records: Vec<Record> = range.map(|(x, y)| (x.clone(), y.clone())).collect();
-        drop(inner);
-
-        match order {
-            Order::Ascending => (Ok(self.new_iterator(records)), GasInfo::free()),
-            Order::Descending => {
-                records.reverse();
-                (Ok(self.new_iterator(records)), GasInfo::free())
+        if let Some(lazy) = &self.lazy {
+            if let Err(e) = lazy.sync(&self.inner) {
+                return (Err(BackendError::unknown(e.to_string())), GasInfo::free());
             }
         }
+        let records = scan_range(&self.inner.read().unwrap(), start, end, order);
+        (Ok(self.new_iterator(records)), GasInfo::free())
     }
 
     #[cfg(feature = "iterator")]
@@ -92,7 +359,10 @@ impl Storage for RpcMockStorage {
                 (Ok(None), GasInfo::free())
             } else {
                 *index += 1;
-                (Ok(Some(records[*index - 1].clone())), GasInfo::free())
+                let record = records[*index - 1].clone();
+                // keys visited by iterators are part of the read set
+                self.trace_read(&record.0);
+                (Ok(Some(record)), GasInfo::free())
             }
         } else {
             (
@@ -103,15 +373,87 @@ impl Storage for RpcMockStorage {
     }
 
     fn set(&mut self, key: &[u8], value: &[u8]) -> BackendResult<()> {
-        self.inner
-            .write()
-            .unwrap()
-            .insert(key.to_vec(), value.to_vec());
+        // the old value is needed for the trace, and must not be fetched over the new one later
+        if let Err(e) = self.fetch_key(key) {
+            return (Err(e), GasInfo::free());
+        }
+        let old =
+            Arc::make_mut(&mut *self.inner.write().unwrap()).insert(key.to_vec(), value.to_vec());
+        self.trace_write(key, old, Some(value));
         (Ok(()), GasInfo::free())
     }
 
     fn remove(&mut self, key: &[u8]) -> BackendResult<()> {
-        self.inner.write().unwrap().remove(key);
+        if let Err(e) = self.fetch_key(key) {
+            return (Err(e), GasInfo::free());
+        }
+        let old = Arc::make_mut(&mut *self.inner.write().unwrap()).remove(key);
+        self.trace_write(key, old, None);
         (Ok(()), GasInfo::free())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Order;
+
+    use super::{
+        namespace_prefix, prefix_end, shared_storage, split_namespaced_key, RpcMockStorage,
+    };
+    use crate::ContractStorage;
+
+    #[cfg(feature = "iterator")]
+    #[test]
+    fn test_iterator() {
+        use cosmwasm_vm::Storage;
+
+        let mut storage = ContractStorage::new();
+        for key in [b"a", b"b", b"c", b"d"] {
+            storage.insert(key.to_vec(), key.to_vec());
+        }
+        let mut storage = RpcMockStorage::new(&shared_storage(storage));
+        let mut collect = |start: Option<&[u8]>, end: Option<&[u8]>, order| {
+            let id = storage.scan(start, end, order).0.unwrap();
+            let mut keys = Vec::new();
+            while let Some((key, _)) = storage.next(id).0.unwrap() {
+                keys.push(key);
+            }
+            keys
+        };
+        assert_eq!(
+            collect(None, None, Order::Ascending),
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]
+        );
+        assert_eq!(
+            collect(Some(b"b"), Some(b"d"), Order::Descending),
+            vec![b"c".to_vec(), b"b".to_vec()]
+        );
+        assert_eq!(collect(Some(b"bb"), None, Order::Ascending).len(), 2);
+        assert!(collect(Some(b"d"), Some(b"a"), Order::Ascending).is_empty());
+        // iterators are independent
+        let first = storage.scan(None, None, Order::Ascending).0.unwrap();
+        let second = storage.scan(None, None, Order::Descending).0.unwrap();
+        assert_eq!(storage.next(first).0.unwrap().unwrap().0, b"a");
+        assert_eq!(storage.next(second).0.unwrap().unwrap().0, b"d");
+        assert!(storage.next(second + 1).0.is_err());
+    }
+
+    #[test]
+    fn test_namespaced_keys() {
+        let prefix = namespace_prefix(&[b"balances"]);
+        assert_eq!(prefix, b"\x00\x08balances");
+        assert_eq!(prefix_end(&prefix).unwrap(), b"\x00\x08balancet");
+        assert_eq!(prefix_end(&[1, 255]).unwrap(), vec![2]);
+        assert_eq!(prefix_end(&[255, 255]), None);
+
+        let mut key = namespace_prefix(&[b"allowances", b"owner"]);
+        key.extend_from_slice(b"spender");
+        let (parts, rest) = split_namespaced_key(&key, 2).unwrap();
+        assert_eq!(parts, vec![b"allowances".to_vec(), b"owner".to_vec()]);
+        assert_eq!(rest, b"spender");
+        let (parts, rest) = split_namespaced_key(&key, 1).unwrap();
+        assert_eq!(parts, vec![b"allowances".to_vec()]);
+        assert_eq!(&rest[2..7], b"owner");
+        assert!(split_namespaced_key(b"\x00\x10short", 1).is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use cosmwasm_std::Addr;
+use std::collections::BTreeMap;
+
+const DENOM_PREFIX: &str = "factory";
+// limits of osmosis and the bank module
+const MAX_SUBDENOM_LENGTH: usize = 44;
+const MAX_DENOM_LENGTH: usize = 128;
+
+/// keeper of the tokenfactory module, holds the admins of factory/{creator}/{subdenom} denoms.
+/// Only denoms created or modified during the simulation are tracked,
+/// denoms created on chain are assumed to still be administered by their creator
+#[derive(Clone, Debug, Default)]
+pub struct TokenFactory {
+    // empty if the admin was renounced
+    admins: BTreeMap<String, String>,
+}
+
+impl TokenFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// splits a tokenfactory denom into its creator and subdenom
+    pub fn parse_denom(denom: &str) -> Option<(&str, &str)> {
+        let mut parts = denom.splitn(3, '/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(DENOM_PREFIX), Some(creator), Some(subdenom)) if !creator.is_empty() => {
+                Some((creator, subdenom))
+            }
+            _ => None,
+        }
+    }
+
+    /// the denom created by creator for subdenom, fails if it is not a valid denom
+    pub fn denom(creator: &Addr, subdenom: &str) -> Result<String, String> {
+        if subdenom.len() > MAX_SUBDENOM_LENGTH {
+            return Err(format!(
+                "subdenom too long, max length is {} bytes",
+                MAX_SUBDENOM_LENGTH
+            ));
+        }
+        if creator.as_str().contains('/') {
+            return Err(format!("invalid creator: {}", creator));
+        }
+        let denom = format!("{}/{}/{}", DENOM_PREFIX, creator, subdenom);
+        let valid_chars = denom
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c));
+        if denom.len() > MAX_DENOM_LENGTH || !valid_chars {
+            return Err(format!("invalid denom: {}", denom));
+        }
+        Ok(denom)
+    }
+
+    /// registers a new denom administered by creator
+    pub fn create_denom(&mut self, creator: &Addr, subdenom: &str) -> Result<String, String> {
+        let denom = Self::denom(creator, subdenom)?;
+        if self.admins.contains_key(&denom) {
+            return Err(format!(
+                "attempting to create a denom that already exists: {}",
+                denom
+            ));
+        }
+        self.admins.insert(denom.clone(), creator.to_string());
+        Ok(denom)
+    }
+
+    /// current admin of denom, None if it is not a tokenfactory denom or the admin was renounced
+    pub fn admin(&self, denom: &str) -> Option<Addr> {
+        match self.admins.get(denom) {
+            Some(admin) if admin.is_empty() => None,
+            Some(admin) => Some(Addr::unchecked(admin)),
+            None => Self::parse_denom(denom).map(|(creator, _)| Addr::unchecked(creator)),
+        }
+    }
+
+    /// sets the admin of denom, None renounces it
+    pub fn set_admin(&mut self, denom: &str, admin: Option<&Addr>) {
+        self.admins.insert(
+            denom.to_string(),
+            admin.map(|a| a.to_string()).unwrap_or_default(),
+        );
+    }
+
+    /// denoms created by creator during the simulation
+    pub fn denoms_from_creator(&self, creator: &str) -> Vec<String> {
+        self.admins
+            .keys()
+            .filter(|denom| matches!(Self::parse_denom(denom), Some((c, _)) if c == creator))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+
+    use super::TokenFactory;
+
+    #[test]
+    fn test_denom_admins() {
+        let alice = Addr::unchecked("wasm1alice");
+        let bob = Addr::unchecked("wasm1bob");
+        let mut tokenfactory = TokenFactory::new();
+
+        let denom = tokenfactory.create_denom(&alice, "token").unwrap();
+        assert_eq!(denom, "factory/wasm1alice/token");
+        assert!(tokenfactory.create_denom(&alice, "token").is_err());
+        assert!(tokenfactory.create_denom(&alice, &"a".repeat(45)).is_err());
+        assert!(tokenfactory.create_denom(&alice, "to ken").is_err());
+        assert_eq!(tokenfactory.admin(&denom), Some(alice.clone()));
+
+        tokenfactory.set_admin(&denom, Some(&bob));
+        assert_eq!(tokenfactory.admin(&denom), Some(bob));
+        tokenfactory.set_admin(&denom, None);
+        assert_eq!(tokenfactory.admin(&denom), None);
+
+        // denoms created before the fork keep their creator as admin
+        assert_eq!(
+            tokenfactory.admin("factory/wasm1carol/old"),
+            Some(Addr::unchecked("wasm1carol"))
+        );
+        assert_eq!(tokenfactory.admin("uosmo"), None);
+        assert_eq!(
+            tokenfactory.denoms_from_creator(alice.as_str()),
+            vec![denom]
+        );
+    }
+}
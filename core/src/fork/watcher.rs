@@ -0,0 +1,123 @@
+use cosmwasm_std::Addr;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::module_cache::code_checksum;
+use crate::Error;
+
+/// a local wasm artifact the code of a contract is reloaded from
+#[derive(Clone, Debug)]
+struct WatchedFile {
+    path: PathBuf,
+    // modification time and length seen last, the file is only read when they change
+    stamp: Option<(SystemTime, u64)>,
+    // checksum of the code loaded last, rewriting the same code does not reload it
+    checksum: Vec<u8>,
+}
+
+/// the wasm files contracts are reloaded from when they change, see Model::watch_and_reload.
+/// Files are polled, so that a model keeps being driven by a single thread
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Watcher {
+    files: BTreeMap<Addr, WatchedFile>,
+}
+
+impl Watcher {
+    /// watches path for the code of contract, the code is loaded on the next check
+    pub fn watch(&mut self, contract: &Addr, path: &Path) {
+        self.files.insert(
+            contract.clone(),
+            WatchedFile {
+                path: path.to_path_buf(),
+                stamp: None,
+                checksum: Vec::new(),
+            },
+        );
+    }
+
+    pub fn unwatch(&mut self, contract: &Addr) -> bool {
+        self.files.remove(contract).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn watched(&self) -> Vec<(Addr, PathBuf)> {
+        self.files
+            .iter()
+            .map(|(contract, file)| (contract.clone(), file.path.clone()))
+            .collect()
+    }
+
+    /// the contracts whose file changed since the last check, with their new code.
+    /// Files which are missing or empty, e.g. while the compiler writes them, are checked again next time
+    pub fn changed(&mut self) -> Result<Vec<(Addr, Vec<u8>)>, Error> {
+        let mut changed = Vec::new();
+        for (contract, file) in self.files.iter_mut() {
+            let metadata = match fs::metadata(&file.path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::io_error(format!("{}: {}", file.path.display(), e))),
+            };
+            let stamp = (metadata.modified().map_err(Error::from)?, metadata.len());
+            if metadata.len() == 0 || file.stamp == Some(stamp) {
+                continue;
+            }
+            let code = fs::read(&file.path)
+                .map_err(|e| Error::io_error(format!("{}: {}", file.path.display(), e)))?;
+            file.stamp = Some(stamp);
+            let checksum = code_checksum(&code);
+            if checksum != file.checksum {
+                file.checksum = checksum;
+                changed.push((contract.clone(), code));
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Addr;
+    use std::fs;
+
+    use super::Watcher;
+
+    #[test]
+    fn test_watcher() {
+        let dir = std::env::temp_dir().join(format!("cw-watcher-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("contract.wasm");
+        let contract = Addr::unchecked("contract");
+        let mut watcher = Watcher::default();
+        watcher.watch(&contract, &path);
+        assert!(watcher.changed().unwrap().is_empty());
+
+        fs::write(&path, b"v1").unwrap();
+        assert_eq!(
+            watcher.changed().unwrap(),
+            vec![(contract.clone(), b"v1".to_vec())]
+        );
+        assert!(watcher.changed().unwrap().is_empty());
+
+        // the same code written again is not reloaded
+        fs::write(&path, b"").unwrap();
+        assert!(watcher.changed().unwrap().is_empty());
+        fs::write(&path, b"v1").unwrap();
+        assert!(watcher.changed().unwrap().is_empty());
+
+        fs::write(&path, b"v22").unwrap();
+        assert_eq!(
+            watcher.changed().unwrap(),
+            vec![(contract.clone(), b"v22".to_vec())]
+        );
+        assert_eq!(watcher.watched(), vec![(contract.clone(), path.clone())]);
+        assert!(watcher.unwatch(&contract));
+        assert!(watcher.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+mod schema;
+
+pub use schema::MsgGenerator;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::coverage::CoverageFeedback;
+use crate::{Addr, Coin, DebugLog, Error, Model};
+
+/// checked after every successful execution, returns a description of the violation on failure
+pub type Invariant = Box<dyn Fn(&mut Model, &DebugLog) -> Result<(), String>>;
+
+#[derive(Clone, Debug)]
+pub struct FuzzConfig {
+    pub seed: u64,
+    /// execute every input on a fresh copy of the model instead of the state left by previous inputs
+    pub reset_state: bool,
+    /// senders to pick from, the model's sender if empty
+    pub senders: Vec<Addr>,
+    /// funds to pick from for every execution, no funds if empty
+    pub funds: Vec<Vec<Coin>>,
+    /// instrument the contract if it does not export dump_coverage
+    pub instrument: bool,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            reset_state: true,
+            senders: Vec::new(),
+            funds: Vec::new(),
+            instrument: true,
+        }
+    }
+}
+
+/// a single execution
+#[derive(Clone, Debug, Serialize)]
+pub struct FuzzInput {
+    pub msg: Value,
+    pub sender: Addr,
+    pub funds: Vec<Coin>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum FindingKind {
+    /// the contract trapped or the vm failed, e.g. because of a panic
+    Crash,
+    InvariantViolation(String),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub input: FuzzInput,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FuzzReport {
+    pub executions: usize,
+    pub successes: usize,
+    /// number of executions per contract error message
+    pub contract_errors: BTreeMap<String, usize>,
+    /// one finding per distinct error
+    pub findings: Vec<Finding>,
+    /// inputs which reached new coverage
+    pub corpus: Vec<FuzzInput>,
+    /// number of coverage map entries reached by all executions
+    pub coverage: usize,
+}
+
+impl fmt::Display for FuzzReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} executions, {} succeeded, {} contract errors, corpus of {}, coverage {}",
+            self.executions,
+            self.successes,
+            self.contract_errors.values().sum::<usize>(),
+            self.corpus.len(),
+            self.coverage
+        )?;
+        for finding in self.findings.iter() {
+            writeln!(
+                f,
+                "{:?}: {} (msg: {}, sender: {})",
+                finding.kind, finding.error, finding.input.msg, finding.input.sender
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// coverage guided fuzzer of a single contract's execute entry point
+pub struct Fuzzer {
+    model: Model,
+    contract_addr: Addr,
+    generator: MsgGenerator,
+    config: FuzzConfig,
+    invariants: Vec<(String, Invariant)>,
+    rng: StdRng,
+    feedback: CoverageFeedback,
+    report: FuzzReport,
+}
+
+impl Fuzzer {
+    pub fn new(
+        mut model: Model,
+        contract_addr: &Addr,
+        mut generator: MsgGenerator,
+        config: FuzzConfig,
+    ) -> Result<Self, Error> {
+        model.enable_code_coverage();
+        if config.instrument {
+            model.enable_coverage_instrumentation();
+            model.instrument_contract(contract_addr)?;
+        }
+        if generator.addresses.is_empty() {
+            generator.addresses.push(contract_addr.to_string());
+            generator.addresses.push(model.sender().to_string());
+            generator
+                .addresses
+                .extend(config.senders.iter().map(|s| s.to_string()));
+        }
+        Ok(Self {
+            model,
+            contract_addr: contract_addr.clone(),
+            generator,
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            invariants: Vec::new(),
+            feedback: CoverageFeedback::new(),
+            report: FuzzReport::default(),
+        })
+    }
+
+    pub fn add_invariant(&mut self, name: &str, invariant: Invariant) {
+        self.invariants.push((name.to_string(), invariant));
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    fn next_input(&mut self) -> FuzzInput {
+        let msg = match self.report.corpus.choose(&mut self.rng) {
+            Some(seed) if self.rng.gen_bool(0.7) => {
+                let msg = seed.msg.clone();
+                self.generator.mutate(&msg, &mut self.rng)
+            }
+            _ => self.generator.generate(&mut self.rng),
+        };
+        let sender = self
+            .config
+            .senders
+            .choose(&mut self.rng)
+            .cloned()
+            .unwrap_or_else(|| self.model.sender());
+        let funds = self
+            .config
+            .funds
+            .choose(&mut self.rng)
+            .cloned()
+            .unwrap_or_default();
+        FuzzInput { msg, sender, funds }
+    }
+
+    fn add_finding(&mut self, kind: FindingKind, input: &FuzzInput, error: String) {
+        if !self
+            .report
+            .findings
+            .iter()
+            .any(|f| f.kind == kind && f.error == error)
+        {
+            self.report.findings.push(Finding {
+                kind,
+                input: input.clone(),
+                error,
+            });
+        }
+    }
+
+    /// execute a single input and record its outcome
+    pub fn run_input(&mut self, input: &FuzzInput) -> Result<(), Error> {
+        let msg = serde_json::to_vec(&input.msg).map_err(Error::from)?;
+        // executions which crash are not reverted, so always run on a copy
+        let mut model = self.model.clone();
+        self.report.executions += 1;
+        let result = model.execute_as(&input.sender, &self.contract_addr, &msg, &input.funds);
+        let map = model.last_coverage_map()?;
+        let mut violations = Vec::new();
+        match result {
+            Ok(debug_log) => {
+                match &debug_log.err_msg {
+                    Some(err_msg) => {
+                        *self
+                            .report
+                            .contract_errors
+                            .entry(err_msg.clone())
+                            .or_insert(0) += 1;
+                    }
+                    None => {
+                        self.report.successes += 1;
+                        for (name, invariant) in self.invariants.iter() {
+                            if let Err(e) = invariant(&mut model, &debug_log) {
+                                violations.push((name.clone(), e));
+                            }
+                        }
+                    }
+                }
+                if !self.config.reset_state {
+                    self.model = model;
+                }
+            }
+            Err(e) => self.add_finding(FindingKind::Crash, input, e.to_string()),
+        }
+        for (name, e) in violations {
+            self.add_finding(FindingKind::InvariantViolation(name), input, e);
+        }
+        if self.feedback.observe(&map) {
+            self.report.corpus.push(input.clone());
+        }
+        self.report.coverage = self.feedback.seen().count();
+        Ok(())
+    }
+
+    pub fn run(&mut self, iterations: usize) -> Result<&FuzzReport, Error> {
+        for _ in 0..iterations {
+            let input = self.next_input();
+            self.run_input(&input)?;
+        }
+        Ok(&self.report)
+    }
+
+    pub fn report(&self) -> &FuzzReport {
+        &self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_schema::schema_for;
+    use cosmwasm_std::to_binary;
+    use test_contract::msg::{ExecuteMsg, InstantiateMsg};
+
+    use super::{FuzzConfig, Fuzzer, MsgGenerator};
+    use crate::{Addr, Model};
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+
+    #[test]
+    fn test_fuzz_contract() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
+        let contract_addr = Addr::unchecked(
+            debug_log.logs[0].events[0]
+                .attributes
+                .iter()
+                .find(|a| a.key == "_contract_address")
+                .unwrap()
+                .value
+                .clone(),
+        );
+
+        let schema = serde_json::to_value(schema_for!(ExecuteMsg)).unwrap();
+        let generator = MsgGenerator::new(schema).unwrap();
+        let mut fuzzer =
+            Fuzzer::new(model, &contract_addr, generator, FuzzConfig::default()).unwrap();
+        fuzzer.add_invariant(
+            "no_events",
+            Box::new(
+                |_, debug_log| match debug_log.logs.iter().all(|l| l.events.is_empty()) {
+                    true => Ok(()),
+                    false => Err("events emitted".to_string()),
+                },
+            ),
+        );
+        let report = fuzzer.run(20).unwrap();
+        assert_eq!(report.executions, 20);
+        // TestAtomic always fails, TestQuerySelf emits an event
+        assert!(!report.contract_errors.is_empty());
+        assert_eq!(report.findings.len(), 1);
+        assert!(!report.corpus.is_empty());
+    }
+}
@@ -0,0 +1,431 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::{json, Map, Value};
+
+use crate::analyzer::WasmAnalysis;
+use crate::{Error, MsgKind};
+
+const DEFAULT_MAX_DEPTH: usize = 4;
+const MAX_ARRAY_LEN: usize = 3;
+
+// values which commonly trigger edge cases
+const INTERESTING_INTEGERS: [u64; 8] = [0, 1, 2, 7, 255, 65535, u32::MAX as u64, u64::MAX];
+const INTERESTING_UINT128: [&str; 6] = [
+    "0",
+    "1",
+    "1000000",
+    "18446744073709551615",
+    "18446744073709551616",
+    "340282366920938463463374607431768211455",
+];
+const INTERESTING_DECIMALS: [&str; 5] = ["0", "0.000000000000000001", "0.5", "1", "1000000"];
+const INTERESTING_STRINGS: [&str; 6] = ["", "a", "uatom", "0", "-1", "\u{0}"];
+
+/// generates and mutates messages conforming to a cosmwasm json schema
+#[derive(Clone, Debug)]
+pub struct MsgGenerator {
+    schema: Value,
+    definitions: Map<String, Value>,
+    /// addresses used for Addr fields and address-like strings
+    pub addresses: Vec<String>,
+    /// objects nested deeper than this only get their required fields, arrays are left empty
+    pub max_depth: usize,
+}
+
+impl MsgGenerator {
+    /// schema is either the schema of a single message, or the api json of a contract
+    /// generated by cosmwasm-schema, in which case the execute message is used
+    pub fn new(schema: Value) -> Result<Self, Error> {
+        let schema = match schema.get("execute") {
+            Some(execute) if schema.get("contract_name").is_some() => execute.clone(),
+            _ => schema,
+        };
+        if !schema.is_object() {
+            return Err(Error::invalid_argument("schema must be a json object"));
+        }
+        let definitions = schema
+            .get("definitions")
+            .and_then(|d| d.as_object())
+            .cloned()
+            .unwrap_or_default();
+        Ok(Self {
+            schema,
+            definitions,
+            addresses: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        })
+    }
+
+    /// generates execute messages from the schema embedded in the code, or from the variants guessed from it
+    pub fn from_analysis(analysis: &WasmAnalysis) -> Result<Self, Error> {
+        match analysis.msg_schema(MsgKind::Execute) {
+            Some(schema) => Self::new(schema),
+            None => Err(Error::invalid_argument(
+                "the code has neither a schema nor candidate execute variants",
+            )),
+        }
+    }
+
+    pub fn from_slice(schema: &[u8]) -> Result<Self, Error> {
+        let schema: Value = serde_json::from_slice(schema).map_err(Error::format_error)?;
+        Self::new(schema)
+    }
+
+    pub fn generate(&self, rng: &mut StdRng) -> Value {
+        self.generate_node(&self.schema, 0, rng)
+    }
+
+    fn resolve<'a>(&self, reference: &'a str) -> Option<(&'a str, &Value)> {
+        let name = reference.strip_prefix("#/definitions/")?;
+        self.definitions.get(name).map(|d| (name, d))
+    }
+
+    fn generate_node(&self, schema: &Value, depth: usize, rng: &mut StdRng) -> Value {
+        let schema = match schema.as_object() {
+            Some(schema) => schema,
+            None => return Value::Null,
+        };
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            return match self.resolve(reference) {
+                Some((name, definition)) => match self.generate_well_known(name, rng) {
+                    Some(value) => value,
+                    None => self.generate_node(definition, depth, rng),
+                },
+                None => Value::Null,
+            };
+        }
+        if let Some(value) = schema.get("const") {
+            return value.clone();
+        }
+        if let Some(values) = schema.get("enum").and_then(|e| e.as_array()) {
+            return values.choose(rng).cloned().unwrap_or(Value::Null);
+        }
+        for key in ["oneOf", "anyOf"] {
+            if let Some(branches) = schema.get(key).and_then(|b| b.as_array()) {
+                // stop recursing through optional values
+                if depth >= self.max_depth {
+                    if let Some(null) = branches
+                        .iter()
+                        .find(|b| b.get("type") == Some(&json!("null")))
+                    {
+                        return self.generate_node(null, depth, rng);
+                    }
+                }
+                return match branches.choose(rng) {
+                    Some(branch) => self.generate_node(branch, depth, rng),
+                    None => Value::Null,
+                };
+            }
+        }
+        if let Some(first) = schema.get("allOf").and_then(|a| a.get(0)) {
+            return self.generate_node(first, depth, rng);
+        }
+        let ty = match schema.get("type") {
+            Some(Value::String(ty)) => ty.as_str(),
+            Some(Value::Array(types)) => {
+                // nullable fields are set most of the time
+                let non_null: Vec<&str> = types
+                    .iter()
+                    .filter_map(|t| t.as_str())
+                    .filter(|t| *t != "null")
+                    .collect();
+                match non_null.choose(rng) {
+                    Some(ty) if depth < self.max_depth && !rng.gen_bool(0.2) => ty,
+                    _ => "null",
+                }
+            }
+            _ => return Value::Null,
+        };
+        match ty {
+            "boolean" => Value::Bool(rng.gen()),
+            "integer" => self.generate_integer(schema, rng),
+            "number" => json!(rng.gen_range(-1e6..1e6)),
+            "string" => self.generate_string(rng),
+            "array" => self.generate_array(schema, depth, rng),
+            "object" => self.generate_object(schema, depth, rng),
+            _ => Value::Null,
+        }
+    }
+
+    /// types from cosmwasm-std which are represented as strings with a fixed format
+    fn generate_well_known(&self, name: &str, rng: &mut StdRng) -> Option<Value> {
+        let value = match name {
+            "Uint64" | "Timestamp" => {
+                json!(INTERESTING_INTEGERS.choose(rng).unwrap().to_string())
+            }
+            "Uint128" | "Uint256" | "Uint512" => {
+                if rng.gen_bool(0.5) {
+                    json!(INTERESTING_UINT128.choose(rng).unwrap())
+                } else {
+                    json!(rng.gen_range(0..1_000_000_000u64).to_string())
+                }
+            }
+            "Decimal" | "Decimal256" => json!(INTERESTING_DECIMALS.choose(rng).unwrap()),
+            "Addr" => json!(self.generate_address(rng)),
+            "Binary" => {
+                let len = rng.gen_range(0..16);
+                let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+                json!(base64::encode(bytes))
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn generate_address(&self, rng: &mut StdRng) -> String {
+        match self.addresses.choose(rng) {
+            Some(address) => address.clone(),
+            None => random_string(rng),
+        }
+    }
+
+    fn generate_integer(&self, schema: &Map<String, Value>, rng: &mut StdRng) -> Value {
+        let max = match schema.get("format").and_then(|f| f.as_str()) {
+            Some("uint8") => u8::MAX as u64,
+            Some("uint16") => u16::MAX as u64,
+            Some("uint32") => u32::MAX as u64,
+            Some("int8") => i8::MAX as u64,
+            Some("int16") => i16::MAX as u64,
+            Some("int32") => i32::MAX as u64,
+            Some("int64") => i64::MAX as u64,
+            _ => u64::MAX,
+        };
+        let signed = schema
+            .get("format")
+            .and_then(|f| f.as_str())
+            .is_some_and(|f| f.starts_with("int"));
+        let value = if rng.gen_bool(0.5) {
+            *INTERESTING_INTEGERS.choose(rng).unwrap()
+        } else {
+            rng.gen_range(0..=max.min(1_000_000))
+        }
+        .min(max);
+        if signed && rng.gen_bool(0.2) {
+            json!(-(value as i64))
+        } else {
+            json!(value)
+        }
+    }
+
+    fn generate_string(&self, rng: &mut StdRng) -> Value {
+        match rng.gen_range(0..3) {
+            0 => json!(INTERESTING_STRINGS.choose(rng).unwrap()),
+            1 if !self.addresses.is_empty() => json!(self.generate_address(rng)),
+            _ => json!(random_string(rng)),
+        }
+    }
+
+    fn generate_array(&self, schema: &Map<String, Value>, depth: usize, rng: &mut StdRng) -> Value {
+        match schema.get("items") {
+            // tuples
+            Some(Value::Array(items)) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.generate_node(item, depth + 1, rng))
+                    .collect(),
+            ),
+            Some(item) => {
+                let len = if depth >= self.max_depth {
+                    0
+                } else {
+                    rng.gen_range(0..=MAX_ARRAY_LEN)
+                };
+                Value::Array(
+                    (0..len)
+                        .map(|_| self.generate_node(item, depth + 1, rng))
+                        .collect(),
+                )
+            }
+            None => Value::Array(Vec::new()),
+        }
+    }
+
+    fn generate_object(
+        &self,
+        schema: &Map<String, Value>,
+        depth: usize,
+        rng: &mut StdRng,
+    ) -> Value {
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|r| r.iter().filter_map(|k| k.as_str()).collect())
+            .unwrap_or_default();
+        let mut object = Map::new();
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (key, property) in properties.iter() {
+                let is_required = required.contains(&key.as_str());
+                if is_required || (depth < self.max_depth && rng.gen_bool(0.5)) {
+                    object.insert(key.clone(), self.generate_node(property, depth + 1, rng));
+                }
+            }
+        }
+        Value::Object(object)
+    }
+
+    /// returns a copy of msg with a random part changed
+    /// mutated messages do not necessarily conform to the schema anymore, on purpose
+    pub fn mutate(&self, msg: &Value, rng: &mut StdRng) -> Value {
+        if rng.gen_bool(0.2) {
+            return self.generate(rng);
+        }
+        let mut mutated = msg.clone();
+        let mut paths = Vec::new();
+        collect_paths(&mutated, String::new(), &mut paths);
+        let path = match paths.choose(rng) {
+            Some(path) => path.clone(),
+            None => return self.generate(rng),
+        };
+        let target = mutated.pointer_mut(&path).unwrap();
+        let replacement = match &*target {
+            Value::Bool(b) => Value::Bool(!*b),
+            Value::Number(n) => mutate_number(n.as_u64().unwrap_or(0), rng),
+            Value::String(s) => match s.parse::<u128>() {
+                Ok(n) => json!(match rng.gen_range(0..4) {
+                    0 => n.wrapping_add(1),
+                    1 => n.wrapping_sub(1),
+                    2 => n.wrapping_mul(2),
+                    _ => u128::MAX,
+                }
+                .to_string()),
+                Err(_) => self.generate_string(rng),
+            },
+            Value::Array(items) => {
+                let mut items = items.clone();
+                if !items.is_empty() && rng.gen_bool(0.5) {
+                    let i = rng.gen_range(0..items.len());
+                    items.remove(i);
+                } else if let Some(item) = items.choose(rng).cloned() {
+                    items.push(item);
+                }
+                Value::Array(items)
+            }
+            Value::Null => json!(random_string(rng)),
+            Value::Object(_) => Value::Object(Map::new()),
+        };
+        *target = replacement;
+        mutated
+    }
+}
+
+/// json pointers of every value inside msg, except the root
+fn collect_paths(value: &Value, path: String, paths: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, child) in object.iter() {
+                let child_path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                collect_paths(child, child_path.clone(), paths);
+                paths.push(child_path);
+            }
+        }
+        Value::Array(items) => {
+            for (i, child) in items.iter().enumerate() {
+                let child_path = format!("{}/{}", path, i);
+                collect_paths(child, child_path.clone(), paths);
+                paths.push(child_path);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn mutate_number(n: u64, rng: &mut StdRng) -> Value {
+    match rng.gen_range(0..4) {
+        0 => json!(n.wrapping_add(1)),
+        1 => json!(n.wrapping_sub(1)),
+        2 => json!(-1),
+        _ => json!(*INTERESTING_INTEGERS.choose(rng).unwrap()),
+    }
+}
+
+fn random_string(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(1..12);
+    (0..len)
+        .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_schema::schema_for;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::{json, Value};
+    use test_contract::msg::ExecuteMsg;
+
+    use super::MsgGenerator;
+    use crate::analyzer::WasmAnalysis;
+
+    #[test]
+    fn test_generate_from_schema() {
+        let schema = serde_json::to_value(schema_for!(ExecuteMsg)).unwrap();
+        let generator = MsgGenerator::new(schema).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let msg = generator.generate(&mut rng);
+            let bytes = serde_json::to_vec(&msg).unwrap();
+            assert!(
+                serde_json::from_slice::<ExecuteMsg>(&bytes).is_ok(),
+                "{}",
+                msg
+            );
+        }
+
+        let schema = json!({
+            "oneOf": [{
+                "type": "object",
+                "required": ["send"],
+                "properties": {
+                    "send": {
+                        "type": "object",
+                        "required": ["amount", "recipient", "memo"],
+                        "properties": {
+                            "amount": { "$ref": "#/definitions/Uint128" },
+                            "recipient": { "$ref": "#/definitions/Addr" },
+                            "memo": { "type": ["string", "null"] },
+                            "count": { "type": "integer", "format": "uint8" }
+                        }
+                    }
+                }
+            }],
+            "definitions": {
+                "Addr": { "type": "string" },
+                "Uint128": { "type": "string" }
+            }
+        });
+        let mut generator = MsgGenerator::new(schema).unwrap();
+        generator.addresses = vec!["wasm1recipient".to_string()];
+        for _ in 0..20 {
+            let msg = generator.generate(&mut rng);
+            let send = &msg["send"];
+            assert!(send["amount"].as_str().unwrap().parse::<u128>().is_ok());
+            assert_eq!(send["recipient"], json!("wasm1recipient"));
+            assert!(send["memo"].is_string() || send["memo"].is_null());
+            if let Some(count) = send.get("count") {
+                assert!(count.as_u64().unwrap() <= 255);
+            }
+            assert_ne!(generator.mutate(&msg, &mut rng), Value::Null);
+        }
+        assert!(MsgGenerator::from_slice(b"[]").is_err());
+    }
+
+    #[test]
+    fn test_generate_from_analysis() {
+        assert!(MsgGenerator::from_analysis(&WasmAnalysis::default()).is_err());
+        let analysis = WasmAnalysis {
+            name_lists: vec![vec!["pause".to_string(), "resume".to_string()]],
+            ..WasmAnalysis::default()
+        };
+        let generator = MsgGenerator::from_analysis(&analysis).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..5 {
+            let msg = generator.generate(&mut rng);
+            assert!(
+                msg == json!({"pause": {}}) || msg == json!({"resume": {}}),
+                "{}",
+                msg
+            );
+        }
+    }
+}
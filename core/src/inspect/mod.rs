@@ -0,0 +1,250 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{namespace_prefix, Addr, Error, Model, Order};
+
+/// a key of a cw-storage-plus Map, encoded into parts the same way as PrimaryKey
+pub trait StorageKey {
+    fn key_parts(&self) -> Vec<Vec<u8>>;
+}
+
+impl StorageKey for &str {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        vec![self.as_bytes().to_vec()]
+    }
+}
+
+impl StorageKey for String {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        vec![self.as_bytes().to_vec()]
+    }
+}
+
+impl StorageKey for &Addr {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        vec![self.as_bytes().to_vec()]
+    }
+}
+
+impl StorageKey for Addr {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        vec![self.as_bytes().to_vec()]
+    }
+}
+
+impl StorageKey for &[u8] {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        vec![self.to_vec()]
+    }
+}
+
+impl StorageKey for Vec<u8> {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        vec![self.clone()]
+    }
+}
+
+// unsigned integers are stored big endian so that they sort numerically
+macro_rules! unsigned_key {
+    ($($t:ty),*) => {
+        $(impl StorageKey for $t {
+            fn key_parts(&self) -> Vec<Vec<u8>> {
+                vec![self.to_be_bytes().to_vec()]
+            }
+        })*
+    };
+}
+
+// signed integers additionally have the sign bit flipped
+macro_rules! signed_key {
+    ($($t:ty),*) => {
+        $(impl StorageKey for $t {
+            fn key_parts(&self) -> Vec<Vec<u8>> {
+                vec![(*self ^ <$t>::MIN).to_be_bytes().to_vec()]
+            }
+        })*
+    };
+}
+
+unsigned_key!(u8, u16, u32, u64, u128);
+signed_key!(i8, i16, i32, i64, i128);
+
+impl<A: StorageKey, B: StorageKey> StorageKey for (A, B) {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        let mut parts = self.0.key_parts();
+        parts.extend(self.1.key_parts());
+        parts
+    }
+}
+
+impl<A: StorageKey, B: StorageKey, C: StorageKey> StorageKey for (A, B, C) {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        let mut parts = self.0.key_parts();
+        parts.extend(self.1.key_parts());
+        parts.extend(self.2.key_parts());
+        parts
+    }
+}
+
+/// already encoded key parts, e.g. for keys built outside of rust
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawKey(pub Vec<Vec<u8>>);
+
+impl StorageKey for RawKey {
+    fn key_parts(&self) -> Vec<Vec<u8>> {
+        self.0.clone()
+    }
+}
+
+/// raw key of a cw-storage-plus Item
+pub fn item_key(namespace: &str) -> Vec<u8> {
+    namespace.as_bytes().to_vec()
+}
+
+/// raw key of an entry of a cw-storage-plus Map,
+/// the namespace and all key parts but the last are length prefixed
+pub fn map_key<K: StorageKey>(namespace: &str, key: K) -> Vec<u8> {
+    let mut parts = key.key_parts();
+    let last = parts.pop().unwrap_or_default();
+    let mut prefixes: Vec<&[u8]> = vec![namespace.as_bytes()];
+    prefixes.extend(parts.iter().map(|p| p.as_slice()));
+    let mut key = namespace_prefix(&prefixes);
+    key.extend_from_slice(&last);
+    key
+}
+
+impl Model {
+    fn read_value<T: DeserializeOwned>(
+        &self,
+        contract_addr: &Addr,
+        key: &[u8],
+    ) -> Result<Option<T>, Error> {
+        match self.wasm_raw_query(contract_addr, key)? {
+            Some(value) => Ok(Some(
+                serde_json::from_slice(&value).map_err(Error::format_error)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// value of the Item stored under namespace, None if it is not set
+    pub fn read_item<T: DeserializeOwned>(
+        &self,
+        contract_addr: &Addr,
+        namespace: &str,
+    ) -> Result<Option<T>, Error> {
+        self.read_value(contract_addr, &item_key(namespace))
+    }
+
+    /// value of key in the Map stored under namespace, None if it is not set
+    pub fn read_map<K: StorageKey, T: DeserializeOwned>(
+        &self,
+        contract_addr: &Addr,
+        namespace: &str,
+        key: K,
+    ) -> Result<Option<T>, Error> {
+        self.read_value(contract_addr, &map_key(namespace, key))
+    }
+
+    /// all entries of the Map stored under namespace, keys are raw and without the namespace
+    pub fn read_map_entries<T: DeserializeOwned>(
+        &self,
+        contract_addr: &Addr,
+        namespace: &str,
+    ) -> Result<Vec<(Vec<u8>, T)>, Error> {
+        self.scan_map(contract_addr, namespace, Order::Ascending, None)?
+            .into_iter()
+            .map(|(k, v)| {
+                let value = serde_json::from_slice(&v).map_err(Error::format_error)?;
+                Ok((k, value))
+            })
+            .collect()
+    }
+
+    pub fn write_item<T: Serialize>(
+        &mut self,
+        contract_addr: &Addr,
+        namespace: &str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let value = serde_json::to_vec(value).map_err(Error::format_error)?;
+        self.cheat_storage(contract_addr, &item_key(namespace), &value)
+    }
+
+    pub fn write_map<K: StorageKey, T: Serialize>(
+        &mut self,
+        contract_addr: &Addr,
+        namespace: &str,
+        key: K,
+        value: &T,
+    ) -> Result<(), Error> {
+        let value = serde_json::to_vec(value).map_err(Error::format_error)?;
+        self.cheat_storage(contract_addr, &map_key(namespace, key), &value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::Uint128;
+
+    use super::{item_key, map_key, RawKey};
+    use crate::{Addr, Model, Order};
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+    const TOKEN_ADDRESS_MALAGA: &str =
+        "wasm124v54ngky9wxhx87t252x4xfgujmdsu7uhjdugtkkqt39nld0e6st7e64h";
+
+    #[test]
+    fn test_storage_keys() {
+        assert_eq!(item_key("config"), b"config");
+        assert_eq!(map_key("balance", "wasm1abc"), b"\x00\x07balancewasm1abc");
+        assert_eq!(
+            map_key(
+                "allowance",
+                (&Addr::unchecked("owner"), &Addr::unchecked("spender"))
+            ),
+            b"\x00\x09allowance\x00\x05ownerspender"
+        );
+        assert_eq!(map_key("ids", 1u32), b"\x00\x03ids\x00\x00\x00\x01");
+        assert_eq!(map_key("ids", -1i8), b"\x00\x03ids\x7f");
+        assert_eq!(map_key("ids", 1i8), b"\x00\x03ids\x81");
+        assert_eq!(
+            map_key("nested", ("a", 2u8, "b")),
+            map_key(
+                "nested",
+                RawKey(vec![b"a".to_vec(), vec![2], b"b".to_vec()])
+            )
+        );
+    }
+
+    #[test]
+    fn test_read_write_map() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let token_address = Addr::unchecked(TOKEN_ADDRESS_MALAGA);
+        let balances: Vec<(Vec<u8>, Uint128)> =
+            model.read_map_entries(&token_address, "balance").unwrap();
+        let (holder, balance) = balances.first().unwrap();
+        let holder = Addr::unchecked(String::from_utf8(holder.clone()).unwrap());
+        let read: Option<Uint128> = model.read_map(&token_address, "balance", &holder).unwrap();
+        assert_eq!(read, Some(*balance));
+
+        let new_balance = Uint128::new(1337);
+        model
+            .write_map(&token_address, "balance", &holder, &new_balance)
+            .unwrap();
+        let read: Option<Uint128> = model.read_map(&token_address, "balance", &holder).unwrap();
+        assert_eq!(read, Some(new_balance));
+        let unknown: Option<Uint128> = model
+            .read_map(&token_address, "balance", "wasm1nobody")
+            .unwrap();
+        assert_eq!(unknown, None);
+        assert_eq!(
+            model
+                .scan_map(&token_address, "balance", Order::Ascending, None)
+                .unwrap()
+                .len(),
+            balances.len()
+        );
+    }
+}
@@ -2,8 +2,19 @@ pub mod analyzer;
 pub mod coverage;
 pub mod error;
 pub mod fork;
+pub mod fuzz;
+pub mod inspect;
+pub mod multitest;
+pub mod presets;
+pub mod repl;
+pub mod replay;
+pub mod scenario;
+pub mod script;
 
 pub use error::Error;
 pub use fork::*;
 
-pub use cosmwasm_std::{Addr, Coin, Timestamp, Uint128};
+pub use cosmwasm_std::{
+    Addr, Attribute, Binary, Coin, ContractResult, CosmosMsg, Order, Reply, Response, StdError,
+    SubMsgResponse, SubMsgResult, Timestamp, Uint128,
+};
@@ -0,0 +1,347 @@
+use cosmwasm_std::{
+    Attribute, BankMsg, Binary, BlockInfo, CosmosMsg, Empty, Event, Querier, QuerierResult,
+    QuerierWrapper, SystemError, SystemResult,
+};
+use cosmwasm_vm::Querier as _;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::{read_wasm, Addr, Coin, DebugLog, Error, Model, RpcMockQuerier};
+
+/// code ids given by App::store_code start here, above the codes of live chains
+const FIRST_CODE_ID: u64 = 1_000_000;
+
+/// what a successful message returned, as in cw-multi-test
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppResponse {
+    /// the events of the message in wasmd's format and order
+    pub events: Vec<Event>,
+    pub data: Option<Binary>,
+}
+
+impl AppResponse {
+    /// the attributes of the event at idx, without its leading _contract_address
+    pub fn custom_attrs(&self, idx: usize) -> &[Attribute] {
+        let attributes = &self.events[idx].attributes;
+        match attributes.first() {
+            Some(attribute) if attribute.key == "_contract_address" => &attributes[1..],
+            _ => attributes,
+        }
+    }
+
+    /// whether an event of the type of expected has all the attributes of expected
+    pub fn has_event(&self, expected: &Event) -> bool {
+        self.events.iter().any(|event| {
+            event.ty == expected.ty
+                && expected
+                    .attributes
+                    .iter()
+                    .all(|attribute| event.attributes.contains(attribute))
+        })
+    }
+
+    /// panics listing the events if there is no event like expected, see has_event
+    pub fn assert_event(&self, expected: &Event) {
+        assert!(
+            self.has_event(expected),
+            "expected event {:?} not found in {:?}",
+            expected,
+            self.events
+        );
+    }
+}
+
+// the std Querier of a QuerierWrapper, over the vm querier contracts use
+struct AppQuerier(RpcMockQuerier);
+
+impl Querier for AppQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        match self.0.query_raw(bin_request, u64::MAX).0 {
+            Ok(result) => result,
+            Err(e) => SystemResult::Err(SystemError::InvalidRequest {
+                error: e.to_string(),
+                request: Binary::from(bin_request),
+            }),
+        }
+    }
+}
+
+/// the interface of cw-multi-test's App over a forked Model, so that multitest suites can run
+/// against mainnet state with few changes. Contracts are wasm code rather than Rust closures,
+/// and failed messages return the crate Error instead of anyhow::Error
+pub struct App {
+    model: Model,
+    querier: AppQuerier,
+    next_code_id: u64,
+}
+
+impl App {
+    pub fn new(model: Model) -> Self {
+        let querier = AppQuerier(model.querier());
+        App {
+            model,
+            querier,
+            next_code_id: FIRST_CODE_ID,
+        }
+    }
+
+    /// forks the chain at url, see Model::new
+    pub fn fork(url: &str, block_number: Option<u64>, bech32_prefix: &str) -> Result<Self, Error> {
+        Ok(Self::new(Model::new(url, block_number, bech32_prefix)?))
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// the model, e.g. for cheats. wrap keeps querying the states of the model the app was
+    /// created with, so replace the app rather than the model
+    pub fn model_mut(&mut self) -> &mut Model {
+        &mut self.model
+    }
+
+    pub fn into_model(self) -> Model {
+        self.model
+    }
+
+    /// a QuerierWrapper over the forked states, e.g. for wrap().query_wasm_smart
+    pub fn wrap(&self) -> QuerierWrapper<'_, Empty> {
+        QuerierWrapper::new(&self.querier)
+    }
+
+    pub fn block_info(&self) -> BlockInfo {
+        self.model.block_info()
+    }
+
+    /// sets the height and time of the block to the ones of block, the chain id is kept
+    pub fn set_block(&mut self, block: BlockInfo) {
+        // cheating the block can't fail
+        self.model.cheat_block_number(block.height).unwrap();
+        self.model.cheat_block_timestamp(block.time).unwrap();
+    }
+
+    /// changes the block with action, e.g. app.update_block(|b| b.height += 1)
+    pub fn update_block<F: Fn(&mut BlockInfo)>(&mut self, action: F) {
+        let mut block = self.block_info();
+        action(&mut block);
+        self.set_block(block);
+    }
+
+    /// adds wasm code under a new code id, in place of multitest's store_code of a ContractWrapper
+    pub fn store_code(&mut self, code: &[u8]) -> Result<u64, Error> {
+        while self.model.custom_code_checksum(self.next_code_id).is_some() {
+            self.next_code_id += 1;
+        }
+        let code_id = self.next_code_id;
+        self.model.add_custom_code(code_id, code)?;
+        self.next_code_id += 1;
+        Ok(code_id)
+    }
+
+    /// store_code with the wasm file at path
+    pub fn store_code_from_path(&mut self, path: &Path) -> Result<u64, Error> {
+        let code = read_wasm(path)?;
+        self.store_code(&code)
+    }
+
+    /// mints amount to addr, in place of init_modules with router.bank.init_balance
+    pub fn init_balance(&mut self, addr: &Addr, amount: Vec<Coin>) -> Result<(), Error> {
+        self.model.cheat_mint(addr, &amount)
+    }
+
+    // runs f with sender as the sender of the model, restoring the previous sender afterwards
+    fn as_sender<T, F>(&mut self, sender: &Addr, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Model) -> Result<T, Error>,
+    {
+        let previous = self.model.sender();
+        self.model.cheat_message_sender(sender)?;
+        let result = f(&mut self.model);
+        self.model.cheat_message_sender(&previous)?;
+        result
+    }
+
+    pub fn instantiate_contract<T: Serialize, U: Into<String>>(
+        &mut self,
+        code_id: u64,
+        sender: Addr,
+        init_msg: &T,
+        send_funds: &[Coin],
+        label: U,
+        admin: Option<String>,
+    ) -> Result<Addr, Error> {
+        let msg = serde_json::to_vec(init_msg).map_err(Error::from)?;
+        let label = label.into();
+        let admin = admin.map(Addr::unchecked);
+        let log = self.as_sender(&sender, |model| {
+            model.instantiate_with_admin(code_id, &msg, send_funds, admin.as_ref(), &label)
+        })?;
+        match (log.contract_address, log.err_msg) {
+            (Some(contract_addr), None) => Ok(contract_addr),
+            (_, err_msg) => Err(Error::msg_failed(format!(
+                "instantiating code {} failed: {}",
+                code_id,
+                err_msg.unwrap_or_default()
+            ))),
+        }
+    }
+
+    pub fn execute_contract<T: Serialize>(
+        &mut self,
+        sender: Addr,
+        contract_addr: Addr,
+        msg: &T,
+        send_funds: &[Coin],
+    ) -> Result<AppResponse, Error> {
+        let msg = serde_json::to_vec(msg).map_err(Error::from)?;
+        let log = self
+            .model
+            .execute_as(&sender, &contract_addr, &msg, send_funds)?;
+        contract_response(&contract_addr, log)
+    }
+
+    pub fn migrate_contract<T: Serialize>(
+        &mut self,
+        sender: Addr,
+        contract_addr: Addr,
+        msg: &T,
+        new_code_id: u64,
+    ) -> Result<AppResponse, Error> {
+        let msg = serde_json::to_vec(msg).map_err(Error::from)?;
+        let log = self.as_sender(&sender, |model| {
+            model.migrate(&contract_addr, new_code_id, &msg)
+        })?;
+        contract_response(&contract_addr, log)
+    }
+
+    /// runs msg as a top-level message of sender
+    pub fn execute(&mut self, sender: Addr, msg: CosmosMsg) -> Result<AppResponse, Error> {
+        let log = self.model.execute_msg_as(&sender, &msg)?;
+        match log.err_msg {
+            Some(err_msg) => Err(Error::msg_failed(err_msg)),
+            None => Ok(response(log)),
+        }
+    }
+
+    pub fn send_tokens(
+        &mut self,
+        owner: Addr,
+        recipient: Addr,
+        amount: &[Coin],
+    ) -> Result<AppResponse, Error> {
+        let msg = BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: amount.to_vec(),
+        };
+        self.execute(owner, msg.into())
+    }
+}
+
+fn response(log: DebugLog) -> AppResponse {
+    AppResponse {
+        events: log.events.into_iter().map(|e| e.event).collect(),
+        data: log.data,
+    }
+}
+
+// a failed execution or migration is reported as an error of the contract it was sent to
+fn contract_response(contract_addr: &Addr, log: DebugLog) -> Result<AppResponse, Error> {
+    match &log.err_msg {
+        Some(err_msg) => Err(Error::contract_error(contract_addr, None, err_msg)),
+        None => Ok(response(log)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coins, Addr, Event};
+    use test_contract::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ReadNumberResponse};
+
+    use super::{App, AppResponse, FIRST_CODE_ID};
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+
+    #[test]
+    fn test_app_response() {
+        let response = AppResponse {
+            events: vec![Event::new("wasm")
+                .add_attribute("_contract_address", "contract")
+                .add_attribute("action", "swap")
+                .add_attribute("amount", "5")],
+            data: None,
+        };
+        assert_eq!(response.custom_attrs(0).len(), 2);
+        response.assert_event(&Event::new("wasm").add_attribute("action", "swap"));
+        assert!(!response.has_event(&Event::new("wasm").add_attribute("action", "burn")));
+        assert!(!response.has_event(&Event::new("transfer")));
+    }
+
+    #[test]
+    fn test_multitest_app() {
+        let wasm_code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        let mut app = App::fork(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code_id = app.store_code(wasm_code).unwrap();
+        assert_eq!(code_id, FIRST_CODE_ID);
+        assert_eq!(app.store_code(wasm_code).unwrap(), FIRST_CODE_ID + 1);
+
+        let owner = app.model_mut().create_account("owner", &[]).unwrap();
+        app.init_balance(&owner, coins(100, "umlg")).unwrap();
+        let contract = app
+            .instantiate_contract(
+                code_id,
+                owner.clone(),
+                &InstantiateMsg {},
+                &coins(10, "umlg"),
+                "test",
+                Some(owner.to_string()),
+            )
+            .unwrap();
+
+        let response = app
+            .execute_contract(
+                owner.clone(),
+                contract.clone(),
+                &ExecuteMsg::TestQuerySelf {},
+                &[],
+            )
+            .unwrap();
+        assert!(response.has_event(
+            &Event::new("execute").add_attribute("_contract_address", contract.as_str())
+        ));
+        let number: ReadNumberResponse = app
+            .wrap()
+            .query_wasm_smart(contract.as_str(), &QueryMsg::ReadNumber {})
+            .unwrap();
+        assert_eq!(number.value, 1);
+
+        let err = app
+            .execute_contract(
+                owner.clone(),
+                contract.clone(),
+                &ExecuteMsg::TestAtomic {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(err.contract(), Some(&contract));
+        assert!(err.is_contract_error());
+
+        let recipient = Addr::unchecked("wasm1recipient");
+        app.send_tokens(owner.clone(), recipient.clone(), &coins(40, "umlg"))
+            .unwrap();
+        let balance = app.wrap().query_balance(&recipient, "umlg").unwrap();
+        assert_eq!(balance.amount.u128(), 40);
+        assert!(app
+            .send_tokens(owner.clone(), recipient, &coins(1000, "umlg"))
+            .is_err());
+        // the sender of the model is left as it was
+        assert_ne!(app.model().sender(), owner);
+
+        let height = app.block_info().height;
+        app.update_block(|b| b.height += 5);
+        assert_eq!(app.block_info().height, height + 5);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{Error, FailoverClient, Model};
+
+/// endpoints and parameters of a chain, see Model::for_chain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainPreset {
+    /// the chain id, by which the preset is selected
+    pub name: &'static str,
+    /// public rpc endpoints, tried in order
+    pub rpc_urls: &'static [&'static str],
+    /// public LCD endpoints, for Model::new_lcd
+    pub lcd_urls: &'static [&'static str],
+    pub bech32_prefix: &'static str,
+    /// length of the canonical addresses of contracts, accounts have 20 byte addresses on every chain
+    pub canonical_address_length: usize,
+    /// denom of the staking and fee token
+    pub native_denom: &'static str,
+    /// minimum gas price of the validators, in native_denom per gas
+    pub gas_price: f64,
+}
+
+pub const PRESETS: &[ChainPreset] = &[
+    ChainPreset {
+        name: "malaga-420",
+        rpc_urls: &["https://rpc.malaga-420.cosmwasm.com:443"],
+        lcd_urls: &["https://lcd.malaga-420.cosmwasm.com"],
+        bech32_prefix: "wasm",
+        canonical_address_length: 32,
+        native_denom: "umlg",
+        gas_price: 0.05,
+    },
+    ChainPreset {
+        name: "osmosis-1",
+        rpc_urls: &[
+            "https://rpc.osmosis.zone",
+            "https://osmosis-rpc.polkachu.com",
+        ],
+        lcd_urls: &[
+            "https://lcd.osmosis.zone",
+            "https://osmosis-api.polkachu.com",
+        ],
+        bech32_prefix: "osmo",
+        canonical_address_length: 32,
+        native_denom: "uosmo",
+        gas_price: 0.0025,
+    },
+    ChainPreset {
+        name: "juno-1",
+        rpc_urls: &["https://juno-rpc.polkachu.com"],
+        lcd_urls: &["https://juno-api.polkachu.com"],
+        bech32_prefix: "juno",
+        canonical_address_length: 32,
+        native_denom: "ujuno",
+        gas_price: 0.075,
+    },
+    ChainPreset {
+        name: "neutron-1",
+        rpc_urls: &["https://neutron-rpc.polkachu.com"],
+        lcd_urls: &["https://neutron-api.polkachu.com"],
+        bech32_prefix: "neutron",
+        canonical_address_length: 32,
+        native_denom: "untrn",
+        gas_price: 0.0053,
+    },
+    ChainPreset {
+        name: "phoenix-1",
+        rpc_urls: &["https://terra-rpc.polkachu.com"],
+        lcd_urls: &["https://terra-api.polkachu.com"],
+        bech32_prefix: "terra",
+        canonical_address_length: 32,
+        native_denom: "uluna",
+        gas_price: 0.015,
+    },
+    ChainPreset {
+        name: "stargaze-1",
+        rpc_urls: &["https://stargaze-rpc.polkachu.com"],
+        lcd_urls: &["https://stargaze-api.polkachu.com"],
+        bech32_prefix: "stars",
+        canonical_address_length: 32,
+        native_denom: "ustars",
+        gas_price: 1.0,
+    },
+    ChainPreset {
+        name: "archway-1",
+        rpc_urls: &["https://archway-rpc.polkachu.com"],
+        lcd_urls: &["https://archway-api.polkachu.com"],
+        bech32_prefix: "archway",
+        canonical_address_length: 32,
+        native_denom: "aarch",
+        gas_price: 140_000_000_000.0,
+    },
+    ChainPreset {
+        name: "pacific-1",
+        rpc_urls: &["https://sei-rpc.polkachu.com"],
+        lcd_urls: &["https://sei-api.polkachu.com"],
+        bech32_prefix: "sei",
+        canonical_address_length: 32,
+        native_denom: "usei",
+        gas_price: 0.02,
+    },
+];
+
+/// the preset of the chain with id name
+pub fn chain_preset(name: &str) -> Result<&'static ChainPreset, Error> {
+    PRESETS.iter().find(|p| p.name == name).ok_or_else(|| {
+        let known: Vec<&str> = PRESETS.iter().map(|p| p.name).collect();
+        Error::invalid_argument(format!(
+            "unknown chain {}, known chains are {}",
+            name,
+            known.join(", ")
+        ))
+    })
+}
+
+impl Model {
+    /// forks the chain with id name from its public rpc endpoints, failing over between them,
+    /// at block_number or at the latest block. See PRESETS for the known chains
+    pub fn for_chain(name: &str, block_number: Option<u64>) -> Result<Self, Error> {
+        let preset = chain_preset(name)?;
+        let client = FailoverClient::rpc(preset.rpc_urls, block_number)?;
+        let mut model = Self::from_client(Box::new(client), preset.bech32_prefix)?;
+        model.set_canonical_address_length(preset.canonical_address_length)?;
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{chain_preset, PRESETS};
+
+    #[test]
+    fn test_chain_preset() {
+        let preset = chain_preset("osmosis-1").unwrap();
+        assert_eq!(preset.bech32_prefix, "osmo");
+        assert_eq!(preset.native_denom, "uosmo");
+        let err = chain_preset("osmosis").unwrap_err();
+        assert!(err.to_string().contains("osmosis-1"));
+
+        let names: HashSet<&str> = PRESETS.iter().map(|p| p.name).collect();
+        assert_eq!(names.len(), PRESETS.len());
+        assert!(PRESETS.iter().all(|p| !p.rpc_urls.is_empty()));
+    }
+}
@@ -0,0 +1,447 @@
+use cosmwasm_std::{from_binary, to_binary, BalanceResponse, BankQuery, Uint128};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{Addr, Coin, DebugLog, Error, Model, MsgKind};
+
+const HELP: &str = "\
+commands:
+  alias <name> <address>            name an address, usable as <name> in place of addresses
+                                    and as \"@<name>\" in json messages
+  aliases                           list the aliases
+  account <name> [funds]            create an account named name, e.g. account alice 100uatom
+  sender <address>                  send the following messages as address
+  autofund on|off                   mint the funds attached to messages to the sender when short
+  code <code id> <path>             register the wasm file at path as code id
+  watch <contract> <path>           set the code of contract to the wasm file at path, and again
+                                    whenever the file changes
+  instantiate <code id> [funds] <json>
+  execute <contract> [funds] <json>
+  query <contract> <json>
+  messages <contract> [kind]        suggest messages of kind (execute by default, or instantiate,
+                                    query, migrate) from the schema embedded in the code, or
+                                    from the variant names found in it
+  balance <address> [denom]
+  block [n]                         produce n blocks, 1 by default
+  history                           list the commands of the session
+  save <path>                       save the session, load it with load <path> or cw-repl --load <path>
+  load <path>                       replace the session with the one saved at path
+  help";
+
+/// a fork and the commands run on it, saved by the repl to resume exploring later
+/// loading forks the same height again and replays the commands
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub url: String,
+    pub block_number: u64,
+    pub bech32_prefix: String,
+    pub seed: Option<u64>,
+    /// commands which succeeded, in order
+    pub history: Vec<String>,
+}
+
+/// interactive shell over a model, one command per line
+pub struct Repl {
+    model: Model,
+    session: Session,
+    aliases: BTreeMap<String, Addr>,
+    autofund: bool,
+}
+
+/// parses funds like 100uatom,5ujuno
+pub fn parse_funds(funds: &str) -> Result<Vec<Coin>, Error> {
+    funds
+        .split(',')
+        .filter(|coin| !coin.is_empty())
+        .map(|coin| {
+            let split = coin
+                .find(|c: char| !c.is_ascii_digit())
+                .filter(|split| *split > 0)
+                .ok_or_else(|| Error::invalid_argument(format!("invalid coin: {}", coin)))?;
+            let amount: u128 = coin[..split]
+                .parse()
+                .map_err(|e| Error::invalid_argument(format!("invalid coin {}: {}", coin, e)))?;
+            Ok(Coin::new(amount, &coin[split..]))
+        })
+        .collect()
+}
+
+/// splits off the first whitespace separated word of line
+fn next_word(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+fn summarize(log: &DebugLog) -> String {
+    if let Some(err_msg) = &log.err_msg {
+        return format!("failed: {}", err_msg);
+    }
+    let mut lines = vec!["ok".to_string()];
+    for entry in log.logs.iter() {
+        for event in entry.events.iter() {
+            let attributes: Vec<String> = event
+                .attributes
+                .iter()
+                .map(|a| format!("{}={}", a.key, a.value))
+                .collect();
+            lines.push(format!("  {} {}", event.ty, attributes.join(" ")));
+        }
+    }
+    if let Some(data) = &log.data {
+        lines.push(format!("  data {}", data));
+    }
+    lines.join("\n")
+}
+
+impl Repl {
+    pub fn new(model: Model) -> Self {
+        let report = model.repro_report();
+        let session = Session {
+            url: report.url,
+            block_number: report.block_number,
+            bech32_prefix: report.bech32_prefix,
+            seed: report.seed,
+            history: Vec::new(),
+        };
+        Repl {
+            model,
+            session,
+            aliases: BTreeMap::new(),
+            autofund: false,
+        }
+    }
+
+    /// forks the height of session again and replays its commands
+    pub fn from_session(session: &Session) -> Result<Self, Error> {
+        let mut model = Model::new(
+            &session.url,
+            Some(session.block_number),
+            &session.bech32_prefix,
+        )?;
+        if let Some(seed) = session.seed {
+            model.set_seed(seed);
+        }
+        let mut repl = Repl::new(model);
+        for line in session.history.iter() {
+            repl.run_line(line).map_err(|e| {
+                Error::invalid_argument(format!("failed to replay {}: {}", line, e))
+            })?;
+        }
+        Ok(repl)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let json =
+            fs::read(path).map_err(|e| Error::io_error(format!("{}: {}", path.display(), e)))?;
+        let session: Session = serde_json::from_slice(&json).map_err(Error::from)?;
+        Self::from_session(&session)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(&self.session).map_err(Error::from)?;
+        fs::write(path, json).map_err(|e| Error::io_error(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn model(&mut self) -> &mut Model {
+        &mut self.model
+    }
+
+    pub fn alias(&self, name: &str) -> Option<&Addr> {
+        self.aliases.get(name)
+    }
+
+    /// runs one command, returns what it prints
+    /// commands which succeed are added to the history of the session, except for
+    /// history, messages, save, load and help
+    pub fn run_line(&mut self, line: &str) -> Result<String, Error> {
+        let line = line.trim();
+        let (command, args) = next_word(line);
+        let output = match command {
+            "" => return Ok(String::new()),
+            "help" => return Ok(HELP.to_string()),
+            "history" => return Ok(self.session.history.join("\n")),
+            "messages" => {
+                let (contract, kind) = next_word(args);
+                let kind: MsgKind = match kind {
+                    "" => MsgKind::Execute,
+                    kind => kind.parse()?,
+                };
+                let analysis = self.model.analyze_contract(&self.address(contract))?;
+                let templates: Vec<String> = analysis
+                    .msg_templates(kind)
+                    .iter()
+                    .map(|template| template.to_string())
+                    .collect();
+                return Ok(templates.join("\n"));
+            }
+            "save" => {
+                self.save(Path::new(args))?;
+                return Ok(format!("saved to {}", args));
+            }
+            "load" => {
+                *self = Self::load(Path::new(args))?;
+                return Ok(format!("replayed {} commands", self.session.history.len()));
+            }
+            "alias" => {
+                let (name, address) = next_word(args);
+                if name.is_empty() || address.is_empty() {
+                    return Err(Error::invalid_argument("usage: alias <name> <address>"));
+                }
+                let address = self.address(address);
+                // named in call traces too
+                self.model.set_alias(name, &address);
+                self.aliases.insert(name.to_string(), address);
+                String::new()
+            }
+            "aliases" => self
+                .aliases
+                .iter()
+                .map(|(name, address)| format!("{} {}", name, address))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            "account" => {
+                let (name, funds) = next_word(args);
+                let address = self.model.create_account(name, &parse_funds(funds)?)?;
+                self.aliases.insert(name.to_string(), address.clone());
+                address.to_string()
+            }
+            "sender" => {
+                let address = self.address(args);
+                self.model.cheat_message_sender(&address)?;
+                String::new()
+            }
+            "autofund" => {
+                self.autofund = match args {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(Error::invalid_argument("usage: autofund on|off")),
+                };
+                String::new()
+            }
+            "code" => {
+                let (code_id, path) = next_word(args);
+                let code_id = self.code_id(code_id)?;
+                self.model.add_code_from_path(code_id, Path::new(path))?;
+                String::new()
+            }
+            "watch" => {
+                let (contract, path) = next_word(args);
+                let contract = self.address(contract);
+                self.model.watch_and_reload(&contract, Path::new(path))?;
+                String::new()
+            }
+            "instantiate" => {
+                let (code_id, rest) = next_word(args);
+                let code_id = self.code_id(code_id)?;
+                let (funds, msg) = self.funds_and_msg(rest)?;
+                self.fund_sender(&funds)?;
+                let log = self.model.instantiate(code_id, &msg, &funds)?;
+                match &log.contract_address {
+                    Some(address) => format!("{}\n{}", address, summarize(&log)),
+                    None => summarize(&log),
+                }
+            }
+            "execute" => {
+                let (contract, rest) = next_word(args);
+                let contract = self.address(contract);
+                let (funds, msg) = self.funds_and_msg(rest)?;
+                self.fund_sender(&funds)?;
+                summarize(&self.model.execute(&contract, &msg, &funds)?)
+            }
+            "query" => {
+                let (contract, msg) = next_word(args);
+                let contract = self.address(contract);
+                let msg = self.msg(msg)?;
+                let response = self.model.wasm_query(&contract, &msg)?;
+                let response: Value = from_binary(&response).map_err(Error::format_error)?;
+                serde_json::to_string_pretty(&response).map_err(Error::from)?
+            }
+            "balance" => {
+                let (address, denom) = next_word(args);
+                let address = self.address(address);
+                if denom.is_empty() {
+                    let query = BankQuery::AllBalances {
+                        address: address.to_string(),
+                    };
+                    let response = self
+                        .model
+                        .bank_query(&to_binary(&query).map_err(Error::from)?)?;
+                    let response: Value = from_binary(&response).map_err(Error::from)?;
+                    serde_json::to_string_pretty(&response).map_err(Error::from)?
+                } else {
+                    self.balance(&address, denom)?.to_string()
+                }
+            }
+            "block" => {
+                let n = match args {
+                    "" => 1,
+                    n => n
+                        .parse()
+                        .map_err(|e| Error::invalid_argument(format!("invalid count: {}", e)))?,
+                };
+                self.model.produce_block(n);
+                String::new()
+            }
+            _ => {
+                return Err(Error::invalid_argument(format!(
+                    "unknown command {}, see help",
+                    command
+                )))
+            }
+        };
+        self.session.history.push(line.to_string());
+        Ok(output)
+    }
+
+    /// an alias or the address itself
+    fn address(&self, address: &str) -> Addr {
+        match self.aliases.get(address) {
+            Some(address) => address.clone(),
+            None => Addr::unchecked(address),
+        }
+    }
+
+    fn code_id(&self, code_id: &str) -> Result<u64, Error> {
+        code_id
+            .parse()
+            .map_err(|e| Error::invalid_argument(format!("invalid code id {}: {}", code_id, e)))
+    }
+
+    /// the json message, with strings "@name" replaced by the address of the alias name
+    fn msg(&self, msg: &str) -> Result<Vec<u8>, Error> {
+        let mut msg: Value = serde_json::from_str(msg)
+            .map_err(|e| Error::invalid_argument(format!("invalid json message: {}", e)))?;
+        self.resolve_aliases(&mut msg);
+        serde_json::to_vec(&msg).map_err(Error::from)
+    }
+
+    fn resolve_aliases(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(address) = s.strip_prefix('@').and_then(|name| self.aliases.get(name)) {
+                    *s = address.to_string();
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.resolve_aliases(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.resolve_aliases(v)),
+            _ => {}
+        }
+    }
+
+    /// optional funds followed by the json message
+    fn funds_and_msg(&self, args: &str) -> Result<(Vec<Coin>, Vec<u8>), Error> {
+        let (first, rest) = next_word(args);
+        if first.starts_with(|c: char| c.is_ascii_digit()) {
+            Ok((parse_funds(first)?, self.msg(rest)?))
+        } else {
+            Ok((Vec::new(), self.msg(args)?))
+        }
+    }
+
+    fn balance(&mut self, address: &Addr, denom: &str) -> Result<Uint128, Error> {
+        let query = BankQuery::Balance {
+            address: address.to_string(),
+            denom: denom.to_string(),
+        };
+        let response = self
+            .model
+            .bank_query(&to_binary(&query).map_err(Error::from)?)?;
+        let response: BalanceResponse = from_binary(&response).map_err(Error::from)?;
+        Ok(response.amount.amount)
+    }
+
+    /// mints what the sender lacks of funds if autofund is on
+    fn fund_sender(&mut self, funds: &[Coin]) -> Result<(), Error> {
+        if !self.autofund {
+            return Ok(());
+        }
+        let sender = self.model.sender();
+        for coin in funds.iter() {
+            let balance = self.balance(&sender, &coin.denom)?;
+            if balance < coin.amount {
+                let missing = Coin {
+                    denom: coin.denom.clone(),
+                    amount: coin.amount - balance,
+                };
+                self.model.cheat_mint(&sender, &[missing])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+
+    #[test]
+    fn test_parse_funds() {
+        assert_eq!(
+            parse_funds("100uatom,5ibc/27394FB0").unwrap(),
+            vec![Coin::new(100, "uatom"), Coin::new(5, "ibc/27394FB0")]
+        );
+        assert!(parse_funds("").unwrap().is_empty());
+        assert!(parse_funds("uatom").is_err());
+        assert!(parse_funds("100").is_err());
+        assert_eq!(
+            next_word("  execute  contract {\"a\": 1}"),
+            ("execute", "contract {\"a\": 1}")
+        );
+        assert_eq!(next_word("help"), ("help", ""));
+    }
+
+    #[test]
+    fn test_repl_session() {
+        let model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let mut repl = Repl::new(model);
+        let code = concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        );
+        let alice = repl.run_line("account alice").unwrap();
+        repl.run_line("sender alice").unwrap();
+        repl.run_line("autofund on").unwrap();
+        repl.run_line(&format!("code 1337 {}", code)).unwrap();
+        let output = repl.run_line("instantiate 1337 100umlg {}").unwrap();
+        let contract = output.lines().next().unwrap().to_string();
+        repl.run_line(&format!("alias test {}", contract)).unwrap();
+        assert!(repl
+            .run_line("execute test {\"test_query_self\":{}}")
+            .unwrap()
+            .starts_with("ok"));
+        assert_eq!(repl.run_line("balance test umlg").unwrap(), "100");
+        assert_eq!(repl.run_line("balance alice umlg").unwrap(), "0");
+        assert!(repl.run_line("query test {\"read_number\":{}}").is_ok());
+        assert!(repl
+            .run_line("messages test")
+            .unwrap()
+            .lines()
+            .any(|line| line == "{\"test_atomic\":{}}"));
+        assert!(repl.run_line("messages test sudo").is_err());
+        assert!(repl.run_line("frobnicate").is_err());
+        assert_eq!(repl.session().history.len(), 10);
+
+        let path =
+            std::env::temp_dir().join(format!("cw-repl-session-{}.json", std::process::id()));
+        repl.save(&path).unwrap();
+        let mut loaded = Repl::load(&path).unwrap();
+        assert_eq!(loaded.session(), repl.session());
+        assert_eq!(loaded.alias("alice").unwrap().as_str(), alice);
+        assert_eq!(loaded.alias("test").unwrap().as_str(), contract);
+        assert_eq!(loaded.run_line("balance test umlg").unwrap(), "100");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,442 @@
+use cosmwasm_std::{Addr, Binary, Coin, Event};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::{event_matches, MSG_EXECUTE_CONTRACT_TYPE_URL};
+use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+use crate::rpc_items::cosmos::tx::signing::v1beta1::SignMode;
+use crate::rpc_items::cosmos::tx::v1beta1::{
+    mode_info, AuthInfo, Fee, ModeInfo, SignerInfo, TxBody, TxRaw,
+};
+use crate::rpc_items::cosmwasm::wasm::v1::MsgExecuteContract;
+use crate::{Error, Model};
+
+const BASE_ACCOUNT_TYPE_URL: &str = "/cosmos.auth.v1beta1.BaseAccount";
+// high enough for any message, simulations are not charged
+const SIMULATION_GAS_LIMIT: u64 = 100_000_000;
+
+// the messages of cosmos.auth.v1beta1 and cosmos.tx.v1beta1.Service used here, which are not
+// compiled from the protos since they pull in most of tendermint
+#[derive(Clone, PartialEq, Message)]
+struct QueryAccountRequest {
+    #[prost(string, tag = "1")]
+    address: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryAccountResponse {
+    #[prost(message, optional, tag = "1")]
+    account: Option<prost_types::Any>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct BaseAccount {
+    #[prost(uint64, tag = "4")]
+    sequence: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct SimulateRequest {
+    #[prost(bytes = "vec", tag = "2")]
+    tx_bytes: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct SimulateResponse {
+    #[prost(message, optional, tag = "1")]
+    gas_info: Option<GasInfo>,
+    #[prost(message, optional, tag = "2")]
+    result: Option<AbciResult>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct GasInfo {
+    #[prost(uint64, tag = "2")]
+    gas_used: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AbciResult {
+    #[prost(message, repeated, tag = "3")]
+    events: Vec<AbciEvent>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AbciEvent {
+    #[prost(string, tag = "1")]
+    ty: String,
+    #[prost(message, repeated, tag = "2")]
+    attributes: Vec<AbciEventAttribute>,
+}
+
+// bytes before tendermint 0.37 and strings since, which are the same on the wire
+#[derive(Clone, PartialEq, Message)]
+struct AbciEventAttribute {
+    #[prost(bytes = "vec", tag = "1")]
+    key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    value: Vec<u8>,
+}
+
+/// events emitted on chain and in the simulation for the same message, see Model::diff_execute
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ExecutionDiff {
+    pub contract: Addr,
+    pub msg: Binary,
+    /// error of the message on chain, None if it succeeded
+    pub chain_error: Option<String>,
+    /// error of the message in the simulation, None if it succeeded
+    pub simulated_error: Option<String>,
+    /// in sdk gas, including the gas of the ante handlers
+    pub chain_gas_used: u64,
+    /// in CosmWasm gas, only the gas of contract calls
+    pub simulated_gas_used: u64,
+    pub events_matched: usize,
+    /// events emitted on chain which the simulation did not emit
+    pub missing_events: Vec<Event>,
+    /// events emitted by the simulation which the chain did not emit
+    pub extra_events: Vec<Event>,
+}
+
+impl ExecutionDiff {
+    /// whether the message had the same outcome and emitted the same events on both sides
+    pub fn is_consistent(&self) -> bool {
+        self.chain_error.is_none() == self.simulated_error.is_none()
+            && self.missing_events.is_empty()
+            && self.extra_events.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::format_error)
+    }
+}
+
+impl fmt::Display for ExecutionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "execution of {}", self.contract)?;
+        writeln!(
+            f,
+            "  chain: {}, simulation: {}",
+            self.chain_error.as_deref().unwrap_or("ok"),
+            self.simulated_error.as_deref().unwrap_or("ok")
+        )?;
+        writeln!(f, "  events matched: {}", self.events_matched)?;
+        for event in self.missing_events.iter() {
+            writeln!(f, "  missing: {} {:?}", event.ty, event.attributes)?;
+        }
+        for event in self.extra_events.iter() {
+            writeln!(f, "  extra: {} {:?}", event.ty, event.attributes)?;
+        }
+        write!(
+            f,
+            "{}",
+            if self.is_consistent() {
+                "consistent"
+            } else {
+                "DIVERGED"
+            }
+        )
+    }
+}
+
+/// responses of the chain and of the simulation to the same smart query, see Model::diff_query
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueryDiff {
+    pub contract: Addr,
+    pub msg: Binary,
+    /// the response, or the error the query failed with
+    pub chain: Result<Binary, String>,
+    pub simulated: Result<Binary, String>,
+}
+
+impl QueryDiff {
+    /// whether both sides succeeded with the same json, or both failed
+    pub fn is_consistent(&self) -> bool {
+        match (&self.chain, &self.simulated) {
+            (Ok(chain), Ok(simulated)) => same_json(chain, simulated),
+            (Err(_), Err(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+// compares json values, so that the order of fields and whitespace don't matter
+fn same_json(a: &[u8], b: &[u8]) -> bool {
+    match (
+        serde_json::from_slice::<serde_json::Value>(a),
+        serde_json::from_slice::<serde_json::Value>(b),
+    ) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// encoded SimulateRequest of an unsigned tx executing contract. Simulations don't check
+/// signatures, but the sequence must be the one of the sender
+fn simulate_execute_request(
+    sender: &Addr,
+    contract: &Addr,
+    msg: &[u8],
+    funds: &[Coin],
+    sequence: u64,
+) -> Vec<u8> {
+    let msg = MsgExecuteContract {
+        sender: sender.to_string(),
+        contract: contract.to_string(),
+        msg: msg.to_vec(),
+        funds: funds
+            .iter()
+            .map(|c| ProtoCoin {
+                denom: c.denom.clone(),
+                amount: c.amount.to_string(),
+            })
+            .collect(),
+    };
+    let body = TxBody {
+        messages: vec![prost_types::Any {
+            type_url: MSG_EXECUTE_CONTRACT_TYPE_URL.to_string(),
+            value: msg.encode_to_vec(),
+        }],
+        ..Default::default()
+    };
+    let auth_info = AuthInfo {
+        signer_infos: vec![SignerInfo {
+            // the chain substitutes a placeholder key when simulating
+            public_key: None,
+            mode_info: Some(ModeInfo {
+                sum: Some(mode_info::Sum::Single(mode_info::Single {
+                    mode: SignMode::Direct as i32,
+                })),
+            }),
+            sequence,
+        }],
+        fee: Some(Fee {
+            gas_limit: SIMULATION_GAS_LIMIT,
+            ..Default::default()
+        }),
+    };
+    let tx = TxRaw {
+        body_bytes: body.encode_to_vec(),
+        auth_info_bytes: auth_info.encode_to_vec(),
+        signatures: vec![Vec::new()],
+    };
+    SimulateRequest {
+        tx_bytes: tx.encode_to_vec(),
+    }
+    .encode_to_vec()
+}
+
+/// gas used and events of a SimulateResponse
+fn decode_simulate_response(out: &[u8]) -> Result<(u64, Vec<Event>), Error> {
+    let resp = SimulateResponse::decode(out).map_err(Error::format_error)?;
+    let gas_used = resp.gas_info.map(|g| g.gas_used).unwrap_or(0);
+    let events = resp
+        .result
+        .map(|r| r.events)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|e| {
+            Event::new(e.ty).add_attributes(
+                e.attributes
+                    .into_iter()
+                    // index of the message in the tx, annotated by cosmos-sdk 0.50
+                    .filter(|a| a.key != b"msg_index")
+                    .map(|a| {
+                        (
+                            String::from_utf8_lossy(&a.key).to_string(),
+                            String::from_utf8_lossy(&a.value).to_string(),
+                        )
+                    }),
+            )
+        })
+        .collect();
+    Ok((gas_used, events))
+}
+
+fn decode_sequence(address: &Addr, out: &[u8]) -> Result<u64, Error> {
+    let resp = QueryAccountResponse::decode(out).map_err(Error::format_error)?;
+    let account = resp
+        .account
+        .ok_or_else(|| Error::invalid_argument(format!("account {} does not exist", address)))?;
+    if account.type_url != BASE_ACCOUNT_TYPE_URL {
+        return Err(Error::invalid_argument(format!(
+            "account {} is a {}, only base accounts can be simulated",
+            address, account.type_url
+        )));
+    }
+    let account = BaseAccount::decode(account.value.as_slice()).map_err(Error::format_error)?;
+    Ok(account.sequence)
+}
+
+/// pairs every chain event with a matching simulated event, returning the number of pairs
+/// and the events of either side left without one
+fn diff_events(
+    chain: &[Event],
+    simulated: &[Event],
+    ignored_event_types: &[&str],
+) -> (usize, Vec<Event>, Vec<Event>) {
+    let considered = |e: &&Event| !ignored_event_types.contains(&e.ty.as_str());
+    let mut unmatched: Vec<&Event> = simulated.iter().filter(considered).collect();
+    let mut matched = 0;
+    let mut missing = Vec::new();
+    for expected in chain.iter().filter(considered) {
+        match unmatched.iter().position(|s| event_matches(expected, s)) {
+            Some(i) => {
+                unmatched.remove(i);
+                matched += 1;
+            }
+            None => missing.push(expected.clone()),
+        }
+    }
+    (matched, missing, unmatched.into_iter().cloned().collect())
+}
+
+impl Model {
+    /// executes msg on contract as the current sender both in the simulation and with the
+    /// Simulate endpoint of the chain, and diffs their outcome and events. The chain simulates
+    /// on the state of the forked height, so the diff is meaningful while the simulation has
+    /// not diverged from it, e.g. on a fresh fork. The model is left unchanged. Event types
+    /// in ignored_event_types, e.g. tx, are not compared
+    pub fn diff_execute(
+        &self,
+        contract: &Addr,
+        msg: &[u8],
+        funds: &[Coin],
+        ignored_event_types: &[&str],
+    ) -> Result<ExecutionDiff, Error> {
+        let sender = self.sender();
+        let (chain_error, chain_gas_used, chain_events) = self.with_client(|client| {
+            let request = QueryAccountRequest {
+                address: sender.to_string(),
+            };
+            let out = client.query_grpc(
+                "/cosmos.auth.v1beta1.Query/Account",
+                &request.encode_to_vec(),
+            )?;
+            let sequence = decode_sequence(&sender, &out)?;
+            let request = simulate_execute_request(&sender, contract, msg, funds, sequence);
+            // failing messages fail the query, with the error of the message
+            match client.query_grpc("/cosmos.tx.v1beta1.Service/Simulate", &request) {
+                Ok(out) => {
+                    let (gas_used, events) = decode_simulate_response(&out)?;
+                    Ok((None, gas_used, events))
+                }
+                Err(Error::TendermintError(e, _)) => Ok((Some(e), 0, Vec::new())),
+                Err(e) => Err(e),
+            }
+        })?;
+
+        let debug_log = self.clone().execute(contract, msg, funds)?;
+        let simulated_events = debug_log.tx_events();
+        let (events_matched, missing_events, extra_events) =
+            diff_events(&chain_events, &simulated_events, ignored_event_types);
+        Ok(ExecutionDiff {
+            contract: contract.clone(),
+            msg: Binary::from(msg),
+            chain_error,
+            simulated_error: debug_log.err_msg,
+            chain_gas_used,
+            simulated_gas_used: debug_log.gas_used,
+            events_matched,
+            missing_events,
+            extra_events,
+        })
+    }
+
+    /// runs a smart query on contract both in the simulation and on chain at the forked height
+    pub fn diff_query(&mut self, contract: &Addr, msg: &[u8]) -> Result<QueryDiff, Error> {
+        let chain = match self
+            .with_client(|client| client.query_wasm_contract_smart(contract.as_str(), msg))
+        {
+            Ok(out) => Ok(Binary::from(out)),
+            Err(Error::TendermintError(e, _)) => Err(e),
+            Err(e) => return Err(e),
+        };
+        let simulated = self.wasm_query(contract, msg).map_err(|e| e.to_string());
+        Ok(QueryDiff {
+            contract: contract.clone(),
+            msg: Binary::from(msg),
+            chain,
+            simulated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{coins, Addr, Binary, Event};
+    use prost::Message;
+
+    use super::{
+        decode_simulate_response, diff_events, simulate_execute_request, AbciEvent,
+        AbciEventAttribute, AbciResult, GasInfo, QueryDiff, SimulateRequest, SimulateResponse,
+    };
+    use crate::rpc_items::cosmos::tx::v1beta1::{AuthInfo, TxBody, TxRaw};
+    use crate::rpc_items::cosmwasm::wasm::v1::MsgExecuteContract;
+
+    #[test]
+    fn test_simulate_request() {
+        let request = simulate_execute_request(
+            &Addr::unchecked("wasm1sender"),
+            &Addr::unchecked("wasm1contract"),
+            br#"{"swap":{}}"#,
+            &coins(10, "umlg"),
+            7,
+        );
+        let request = SimulateRequest::decode(request.as_slice()).unwrap();
+        let tx = TxRaw::decode(request.tx_bytes.as_slice()).unwrap();
+        let auth_info = AuthInfo::decode(tx.auth_info_bytes.as_slice()).unwrap();
+        assert_eq!(auth_info.signer_infos[0].sequence, 7);
+        let body = TxBody::decode(tx.body_bytes.as_slice()).unwrap();
+        let msg = MsgExecuteContract::decode(body.messages[0].value.as_slice()).unwrap();
+        assert_eq!(msg.contract, "wasm1contract");
+        assert_eq!(msg.funds[0].amount, "10");
+
+        let attribute = |key: &str, value: &str| AbciEventAttribute {
+            key: key.as_bytes().to_vec(),
+            value: value.as_bytes().to_vec(),
+        };
+        let response = SimulateResponse {
+            gas_info: Some(GasInfo { gas_used: 42 }),
+            result: Some(AbciResult {
+                events: vec![AbciEvent {
+                    ty: "wasm".to_string(),
+                    attributes: vec![attribute("action", "swap"), attribute("msg_index", "0")],
+                }],
+            }),
+        };
+        let (gas_used, events) = decode_simulate_response(&response.encode_to_vec()).unwrap();
+        assert_eq!(gas_used, 42);
+        assert_eq!(
+            events,
+            vec![Event::new("wasm").add_attribute("action", "swap")]
+        );
+    }
+
+    #[test]
+    fn test_diff_events() {
+        let chain = vec![
+            Event::new("tx").add_attribute("fee", ""),
+            Event::new("wasm").add_attribute("action", "swap"),
+            Event::new("wasm").add_attribute("action", "swap"),
+        ];
+        let simulated = vec![
+            Event::new("wasm").add_attribute("action", "swap"),
+            Event::new("wasm").add_attribute("action", "withdraw"),
+        ];
+        let (matched, missing, extra) = diff_events(&chain, &simulated, &["tx"]);
+        // every simulated event matches a single chain event
+        assert_eq!(matched, 1);
+        assert_eq!(missing, vec![chain[2].clone()]);
+        assert_eq!(extra, vec![simulated[1].clone()]);
+
+        let diff = QueryDiff {
+            contract: Addr::unchecked("wasm1contract"),
+            msg: Binary::from(b"{}"),
+            chain: Ok(Binary::from(br#"{"a":1,"b":2}"#)),
+            simulated: Ok(Binary::from(br#"{ "b": 2, "a": 1 }"#)),
+        };
+        assert!(diff.is_consistent());
+    }
+}
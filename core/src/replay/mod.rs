@@ -0,0 +1,676 @@
+use cosmwasm_std::{Addr, Binary, Coin, Event, Timestamp, Uint128};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+use crate::rpc_items::cosmos::tx::v1beta1::Tx;
+use crate::rpc_items::cosmwasm::wasm::v1::{MsgExecuteContract, MsgInstantiateContract};
+use crate::{CwClientBackend, CwRpcClient, DebugLog, Error, Model, TxInfo};
+
+mod differential;
+
+pub use differential::{ExecutionDiff, QueryDiff};
+
+const MSG_EXECUTE_CONTRACT_TYPE_URL: &str = "/cosmwasm.wasm.v1.MsgExecuteContract";
+const MSG_INSTANTIATE_CONTRACT_TYPE_URL: &str = "/cosmwasm.wasm.v1.MsgInstantiateContract";
+
+/// A block recorded from the chain, along with the results the chain produced for it.
+/// Used to measure how faithfully the simulator reproduces on-chain semantics.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedBlock {
+    pub height: u64,
+    pub time: Timestamp,
+    pub txs: Vec<RecordedTx>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedTx {
+    pub hash: String,
+    pub sender: Addr,
+    pub msgs: Vec<RecordedMsg>,
+    /// whether the transaction succeeded on chain
+    pub success: bool,
+    /// events emitted by the chain for this transaction
+    #[serde(default)]
+    pub events: Vec<Event>,
+    /// storage of touched contracts after the transaction
+    #[serde(default)]
+    pub state: Vec<RecordedStorage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedMsg {
+    Execute {
+        contract: Addr,
+        msg: Binary,
+        funds: Vec<Coin>,
+    },
+    Instantiate {
+        code_id: u64,
+        msg: Binary,
+        funds: Vec<Coin>,
+    },
+}
+
+/// value of a raw storage key, None if the key was deleted
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedStorage {
+    pub contract: Addr,
+    pub key: Binary,
+    pub value: Option<Binary>,
+}
+
+fn decode_coins(coins: &[ProtoCoin]) -> Result<Vec<Coin>, Error> {
+    coins
+        .iter()
+        .map(|c| {
+            Ok(Coin {
+                denom: c.denom.clone(),
+                amount: Uint128::from_str(&c.amount).map_err(Error::format_error)?,
+            })
+        })
+        .collect()
+}
+
+impl RecordedTx {
+    /// reconstructs the wasm messages of a transaction fetched from the chain
+    /// transactions containing other messages (e.g. bank sends) cannot be replayed and are rejected
+    /// the recorded state is left empty, since the chain does not report the touched storage
+    pub fn from_tx_info(tx_info: &TxInfo) -> Result<Self, Error> {
+        let tx = Tx::decode(tx_info.tx.as_slice()).map_err(Error::format_error)?;
+        let body = tx
+            .body
+            .ok_or_else(|| Error::format_error("transaction has no body"))?;
+        let mut sender = None;
+        let mut msgs = Vec::new();
+        for any in body.messages.iter() {
+            match any.type_url.as_str() {
+                MSG_EXECUTE_CONTRACT_TYPE_URL => {
+                    let msg = MsgExecuteContract::decode(any.value.as_slice())
+                        .map_err(Error::format_error)?;
+                    sender.get_or_insert(msg.sender);
+                    msgs.push(RecordedMsg::Execute {
+                        contract: Addr::unchecked(msg.contract),
+                        msg: Binary::from(msg.msg),
+                        funds: decode_coins(&msg.funds)?,
+                    });
+                }
+                MSG_INSTANTIATE_CONTRACT_TYPE_URL => {
+                    let msg = MsgInstantiateContract::decode(any.value.as_slice())
+                        .map_err(Error::format_error)?;
+                    sender.get_or_insert(msg.sender);
+                    msgs.push(RecordedMsg::Instantiate {
+                        code_id: msg.code_id,
+                        msg: Binary::from(msg.msg),
+                        funds: decode_coins(&msg.funds)?,
+                    });
+                }
+                type_url => {
+                    return Err(Error::invalid_argument(format!(
+                        "unsupported message type {} in tx {}",
+                        type_url, tx_info.hash
+                    )));
+                }
+            }
+        }
+        let sender =
+            sender.ok_or_else(|| Error::invalid_argument("transaction has no messages"))?;
+        Ok(Self {
+            hash: tx_info.hash.clone(),
+            sender: Addr::unchecked(sender),
+            msgs,
+            success: tx_info.success,
+            events: tx_info.events.clone(),
+            state: Vec::new(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TxFidelity {
+    pub hash: String,
+    pub success_matched: bool,
+    pub events_expected: usize,
+    pub events_matched: usize,
+    pub keys_expected: usize,
+    pub keys_matched: usize,
+    pub mismatched_keys: Vec<RecordedStorage>,
+    pub err_msg: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FidelityReport {
+    pub height: u64,
+    pub txs: Vec<TxFidelity>,
+}
+
+fn rate(matched: usize, expected: usize) -> f64 {
+    if expected == 0 {
+        1.0
+    } else {
+        matched as f64 / expected as f64
+    }
+}
+
+impl FidelityReport {
+    pub fn event_match_rate(&self) -> f64 {
+        let expected = self.txs.iter().map(|t| t.events_expected).sum();
+        let matched = self.txs.iter().map(|t| t.events_matched).sum();
+        rate(matched, expected)
+    }
+
+    pub fn state_match_rate(&self) -> f64 {
+        let expected = self.txs.iter().map(|t| t.keys_expected).sum();
+        let matched = self.txs.iter().map(|t| t.keys_matched).sum();
+        rate(matched, expected)
+    }
+
+    pub fn outcome_match_rate(&self) -> f64 {
+        let matched = self.txs.iter().filter(|t| t.success_matched).count();
+        rate(matched, self.txs.len())
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::format_error)
+    }
+}
+
+impl fmt::Display for FidelityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "fidelity report for block {}", self.height)?;
+        for tx in self.txs.iter() {
+            writeln!(
+                f,
+                "  {}: outcome {}, events {}/{}, keys {}/{}",
+                tx.hash,
+                if tx.success_matched { "ok" } else { "MISMATCH" },
+                tx.events_matched,
+                tx.events_expected,
+                tx.keys_matched,
+                tx.keys_expected
+            )?;
+        }
+        writeln!(f, "outcome match rate: {:.3}", self.outcome_match_rate())?;
+        writeln!(f, "event match rate: {:.3}", self.event_match_rate())?;
+        write!(f, "state match rate: {:.3}", self.state_match_rate())
+    }
+}
+
+/// A storage key whose simulated value differs from the value on chain
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateDivergence {
+    pub contract: Addr,
+    pub key: Binary,
+    pub expected: Option<Binary>,
+    pub simulated: Option<Binary>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BlockReplay {
+    pub height: u64,
+    pub replayed_txs: Vec<String>,
+    /// transactions containing messages other than wasm executes and instantiates, which cannot
+    /// be replayed. They may or may not touch the filtered contracts
+    pub skipped_txs: Vec<String>,
+    /// transactions which failed to decode, so it is unknown what they contain
+    #[serde(default)]
+    pub undecodable_txs: Vec<String>,
+    /// transactions which succeeded on chain but failed in the simulation, or vice versa
+    pub outcome_mismatches: Vec<String>,
+    pub divergences: Vec<StateDivergence>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub blocks: Vec<BlockReplay>,
+}
+
+impl ReplayReport {
+    /// true if every replayed transaction reproduced the outcome and post-state of the chain
+    pub fn is_faithful(&self) -> bool {
+        self.blocks
+            .iter()
+            .all(|b| b.outcome_mismatches.is_empty() && b.divergences.is_empty())
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::format_error)
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for block in self.blocks.iter() {
+            writeln!(
+                f,
+                "block {}: {} replayed, {} skipped, {} undecodable, {} outcome mismatches, {} diverging keys",
+                block.height,
+                block.replayed_txs.len(),
+                block.skipped_txs.len(),
+                block.undecodable_txs.len(),
+                block.outcome_mismatches.len(),
+                block.divergences.len()
+            )?;
+            for d in block.divergences.iter() {
+                writeln!(
+                    f,
+                    "  {} {}: expected {:?}, simulated {:?}",
+                    d.contract,
+                    hex::encode(d.key.as_slice()),
+                    d.expected.as_ref().map(|v| hex::encode(v.as_slice())),
+                    d.simulated.as_ref().map(|v| hex::encode(v.as_slice()))
+                )?;
+            }
+        }
+        write!(
+            f,
+            "{}",
+            if self.is_faithful() {
+                "no divergence"
+            } else {
+                "DIVERGED"
+            }
+        )
+    }
+}
+
+/// contracts directly targeted by the messages of a transaction
+fn touched_contracts(tx: &RecordedTx) -> Vec<&Addr> {
+    tx.msgs
+        .iter()
+        .filter_map(|m| match m {
+            RecordedMsg::Execute { contract, .. } => Some(contract),
+            RecordedMsg::Instantiate { .. } => None,
+        })
+        .collect()
+}
+
+/// An expected event matches a simulated event if they have the same type and
+/// every expected attribute is present in the simulated event.
+/// Attributes prefixed with '_' (e.g. _contract_address) are injected by the chain and ignored.
+fn event_matches(expected: &Event, simulated: &Event) -> bool {
+    expected.ty == simulated.ty
+        && expected
+            .attributes
+            .iter()
+            .filter(|a| !a.key.starts_with('_'))
+            .all(|a| simulated.attributes.contains(a))
+}
+
+impl Model {
+    /// replays a recorded block on the fork and scores how closely the results match the chain
+    /// event types listed in `ignored_event_types` are excluded from the event match rate
+    pub fn verify_block(
+        &mut self,
+        block: &RecordedBlock,
+        ignored_event_types: &[&str],
+    ) -> Result<FidelityReport, Error> {
+        let orig_sender = self.sender();
+        let mut report = FidelityReport {
+            height: block.height,
+            txs: Vec::new(),
+        };
+        for tx in block.txs.iter() {
+            let result = self.verify_tx(block, tx, ignored_event_types);
+            self.cheat_message_sender(&orig_sender)?;
+            report.txs.push(result?);
+        }
+        Ok(report)
+    }
+
+    /// fetches a transaction from the chain, as a block containing only that transaction
+    pub fn fetch_tx(&mut self, hash: &str) -> Result<RecordedBlock, Error> {
+        let tx_info = self.with_client(|client| client.query_tx(hash))?;
+        Ok(RecordedBlock {
+            height: tx_info.height,
+            time: tx_info.timestamp,
+            txs: vec![RecordedTx::from_tx_info(&tx_info)?],
+        })
+    }
+
+    /// forks the chain at the parent block of a transaction, so that it can be replayed with replay_block
+    /// transactions preceding it in the same block are not applied
+    pub fn fork_at_tx(
+        url: &str,
+        hash: &str,
+        bech32_prefix: &str,
+    ) -> Result<(Self, RecordedBlock), Error> {
+        let tx_info = CwRpcClient::new(url, None)?.query_tx(hash)?;
+        let block = RecordedBlock {
+            height: tx_info.height,
+            time: tx_info.timestamp,
+            txs: vec![RecordedTx::from_tx_info(&tx_info)?],
+        };
+        let model = Self::new(url, Some(tx_info.height - 1), bech32_prefix)?;
+        Ok((model, block))
+    }
+
+    /// replays the transactions of a recorded block and returns the debug log of every executed message
+    /// a failing message reverts its transaction, and its debug log is the last one of that transaction
+    pub fn replay_block(&mut self, block: &RecordedBlock) -> Result<Vec<DebugLog>, Error> {
+        let orig_sender = self.sender();
+        let mut debug_logs = Vec::new();
+        for tx in block.txs.iter() {
+            let result = self.run_tx(block, tx);
+            self.cheat_message_sender(&orig_sender)?;
+            debug_logs.extend(result?);
+        }
+        Ok(debug_logs)
+    }
+
+    /// replays on-chain blocks from..=to on the fork, which should be forked at block from - 1
+    /// only wasm transactions executing a contract in `contracts` are replayed,
+    /// and after every block the storage of those contracts is compared against the chain
+    /// transactions which affect the contracts only indirectly are not replayed,
+    /// so they show up as divergences
+    pub fn replay_blocks(
+        &mut self,
+        from: u64,
+        to: u64,
+        contracts: &[Addr],
+    ) -> Result<ReplayReport, Error> {
+        let mut report = ReplayReport::default();
+        for height in from..=to {
+            report
+                .blocks
+                .push(self.replay_chain_block(height, contracts)?);
+        }
+        Ok(report)
+    }
+
+    fn replay_chain_block(
+        &mut self,
+        height: u64,
+        contracts: &[Addr],
+    ) -> Result<BlockReplay, Error> {
+        let tx_infos = self.with_client(|client| client.query_block_txs(height))?;
+        // client pinned to the block, for fetching its timestamp and the actual post-state
+        let mut actual_client = self.with_client(|client| client.at_height(height))?;
+        let mut result = BlockReplay {
+            height,
+            ..Default::default()
+        };
+        let mut block = RecordedBlock {
+            height,
+            time: actual_client.timestamp()?,
+            txs: Vec::new(),
+        };
+        for tx_info in tx_infos.iter() {
+            match RecordedTx::from_tx_info(tx_info) {
+                Ok(tx) => {
+                    if touched_contracts(&tx).iter().any(|c| contracts.contains(c)) {
+                        block.txs.push(tx);
+                    }
+                }
+                // there is no way to tell whether these transactions touch the contracts
+                // without replaying them, so report them all
+                Err(Error::InvalidArg(_)) => result.skipped_txs.push(tx_info.hash.clone()),
+                Err(_) => result.undecodable_txs.push(tx_info.hash.clone()),
+            }
+        }
+
+        let orig_sender = self.sender();
+        for tx in block.txs.iter() {
+            let debug_logs = self.run_tx(&block, tx);
+            self.cheat_message_sender(&orig_sender)?;
+            let failed = debug_logs?.last().is_some_and(|log| log.err_msg.is_some());
+            if failed == tx.success {
+                result.outcome_mismatches.push(tx.hash.clone());
+            }
+            result.replayed_txs.push(tx.hash.clone());
+        }
+
+        // a block without transactions still advances the chain
+        self.cheat_block_number(height)?;
+        self.cheat_block_timestamp(block.time)?;
+
+        for contract in contracts.iter() {
+            let simulated = self.contract_storage(contract)?;
+            let actual = actual_client.query_wasm_contract_state_all(contract.as_str())?;
+            let keys: BTreeSet<&Vec<u8>> = simulated.keys().chain(actual.keys()).collect();
+            for key in keys {
+                let expected = actual.get(key);
+                let value = simulated.get(key);
+                if expected != value {
+                    result.divergences.push(StateDivergence {
+                        contract: contract.clone(),
+                        key: Binary::from(key.as_slice()),
+                        expected: expected.map(|v| Binary::from(v.as_slice())),
+                        simulated: value.map(|v| Binary::from(v.as_slice())),
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn run_tx(&mut self, block: &RecordedBlock, tx: &RecordedTx) -> Result<Vec<DebugLog>, Error> {
+        // transactions are atomic, so failing messages revert the entire transaction
+        let state_copy = self.clone();
+        self.cheat_message_sender(&tx.sender)?;
+        let mut debug_logs = Vec::new();
+        let mut failed = false;
+        for msg in tx.msgs.iter() {
+            // all transactions of a block share the same block info
+            self.cheat_block_number(block.height)?;
+            self.cheat_block_timestamp(block.time)?;
+            let debug_log = match msg {
+                RecordedMsg::Execute {
+                    contract,
+                    msg,
+                    funds,
+                } => self.execute(contract, msg.as_slice(), funds)?,
+                RecordedMsg::Instantiate {
+                    code_id,
+                    msg,
+                    funds,
+                } => self.instantiate(*code_id, msg.as_slice(), funds)?,
+            };
+            failed = debug_log.err_msg.is_some();
+            debug_logs.push(debug_log);
+            if failed {
+                break;
+            }
+        }
+        if failed {
+            self.revert(state_copy);
+        }
+        Ok(debug_logs)
+    }
+
+    fn verify_tx(
+        &mut self,
+        block: &RecordedBlock,
+        tx: &RecordedTx,
+        ignored_event_types: &[&str],
+    ) -> Result<TxFidelity, Error> {
+        let debug_logs = self.run_tx(block, tx)?;
+        let events: Vec<Event> = debug_logs.iter().flat_map(|log| log.tx_events()).collect();
+        let err_msg = debug_logs.last().and_then(|log| log.err_msg.clone());
+
+        let expected_events: Vec<&Event> = tx
+            .events
+            .iter()
+            .filter(|e| !ignored_event_types.contains(&e.ty.as_str()))
+            .collect();
+        let events_matched = expected_events
+            .iter()
+            .filter(|e| events.iter().any(|s| event_matches(e, s)))
+            .count();
+
+        let mut mismatched_keys = Vec::new();
+        for expected in tx.state.iter() {
+            let value = self.wasm_raw_query(&expected.contract, expected.key.as_slice())?;
+            if value.as_deref() != expected.value.as_ref().map(|v| v.as_slice()) {
+                mismatched_keys.push(expected.clone());
+            }
+        }
+
+        Ok(TxFidelity {
+            hash: tx.hash.clone(),
+            success_matched: err_msg.is_none() == tx.success,
+            events_expected: expected_events.len(),
+            events_matched,
+            keys_expected: tx.state.len(),
+            keys_matched: tx.state.len() - mismatched_keys.len(),
+            mismatched_keys,
+            err_msg,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{Addr, Binary, Event, Timestamp};
+    use prost::Message;
+
+    use super::{
+        event_matches, touched_contracts, BlockReplay, FidelityReport, RecordedMsg, RecordedTx,
+        ReplayReport, StateDivergence, TxFidelity,
+    };
+    use crate::rpc_items::cosmos::base::v1beta1::Coin as ProtoCoin;
+    use crate::rpc_items::cosmos::tx::v1beta1::{Tx, TxBody};
+    use crate::rpc_items::cosmwasm::wasm::v1::MsgExecuteContract;
+    use crate::TxInfo;
+
+    #[test]
+    fn test_tx_from_tx_info() {
+        let msg = MsgExecuteContract {
+            sender: "wasm1sender".to_string(),
+            contract: "wasm1contract".to_string(),
+            msg: br#"{"swap":{}}"#.to_vec(),
+            funds: vec![ProtoCoin {
+                denom: "umlg".to_string(),
+                amount: "10".to_string(),
+            }],
+        };
+        let mut tx = Tx {
+            body: Some(TxBody {
+                messages: vec![prost_types::Any {
+                    type_url: "/cosmwasm.wasm.v1.MsgExecuteContract".to_string(),
+                    value: msg.encode_to_vec(),
+                }],
+                ..Default::default()
+            }),
+            auth_info: None,
+            signatures: Vec::new(),
+        };
+        let mut tx_info = TxInfo {
+            hash: "ABCD".to_string(),
+            height: 10,
+            timestamp: Timestamp::from_seconds(1),
+            success: true,
+            events: vec![Event::new("wasm").add_attribute("action", "swap")],
+            tx: tx.encode_to_vec(),
+        };
+        let recorded = RecordedTx::from_tx_info(&tx_info).unwrap();
+        assert_eq!(recorded.sender, Addr::unchecked("wasm1sender"));
+        assert_eq!(recorded.events.len(), 1);
+        match &recorded.msgs[..] {
+            [RecordedMsg::Execute {
+                contract,
+                msg,
+                funds,
+            }] => {
+                assert_eq!(contract, &Addr::unchecked("wasm1contract"));
+                assert_eq!(msg, &Binary::from(br#"{"swap":{}}"#));
+                assert_eq!(funds[0].amount.u128(), 10);
+            }
+            _ => panic!("unexpected messages"),
+        }
+
+        // non-wasm messages cannot be replayed
+        tx.body.as_mut().unwrap().messages[0].type_url = "/cosmos.bank.v1beta1.MsgSend".to_string();
+        tx_info.tx = tx.encode_to_vec();
+        assert!(RecordedTx::from_tx_info(&tx_info).is_err());
+    }
+
+    #[test]
+    fn test_event_matches() {
+        let expected = Event::new("wasm")
+            .add_attribute("_contract_address", "wasm1abc")
+            .add_attribute("action", "swap");
+        let simulated = Event::new("wasm")
+            .add_attribute("action", "swap")
+            .add_attribute("offer_amount", "10");
+        assert!(event_matches(&expected, &simulated));
+        let simulated = Event::new("wasm").add_attribute("action", "withdraw");
+        assert!(!event_matches(&expected, &simulated));
+    }
+
+    #[test]
+    fn test_report_rates() {
+        let report = FidelityReport {
+            height: 1,
+            txs: vec![
+                TxFidelity {
+                    success_matched: true,
+                    events_expected: 4,
+                    events_matched: 3,
+                    keys_expected: 2,
+                    keys_matched: 2,
+                    ..Default::default()
+                },
+                TxFidelity {
+                    success_matched: false,
+                    events_expected: 0,
+                    events_matched: 0,
+                    keys_expected: 2,
+                    keys_matched: 0,
+                    ..Default::default()
+                },
+            ],
+        };
+        assert_eq!(report.event_match_rate(), 0.75);
+        assert_eq!(report.state_match_rate(), 0.5);
+        assert_eq!(report.outcome_match_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_replay_report() {
+        let tx = RecordedTx {
+            hash: "ABCD".to_string(),
+            sender: Addr::unchecked("wasm1sender"),
+            msgs: vec![
+                RecordedMsg::Instantiate {
+                    code_id: 1,
+                    msg: Binary::from(b"{}"),
+                    funds: Vec::new(),
+                },
+                RecordedMsg::Execute {
+                    contract: Addr::unchecked("wasm1contract"),
+                    msg: Binary::from(b"{}"),
+                    funds: Vec::new(),
+                },
+            ],
+            success: true,
+            events: Vec::new(),
+            state: Vec::new(),
+        };
+        assert_eq!(
+            touched_contracts(&tx),
+            vec![&Addr::unchecked("wasm1contract")]
+        );
+
+        let mut report = ReplayReport {
+            blocks: vec![BlockReplay {
+                height: 1,
+                replayed_txs: vec![tx.hash],
+                ..Default::default()
+            }],
+        };
+        assert!(report.is_faithful());
+        report.blocks[0].divergences.push(StateDivergence {
+            contract: Addr::unchecked("wasm1contract"),
+            key: Binary::from(b"config"),
+            expected: Some(Binary::from(b"1")),
+            simulated: None,
+        });
+        assert!(!report.is_faithful());
+        assert!(report.to_string().ends_with("DIVERGED"));
+    }
+}
@@ -0,0 +1,540 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{Addr, Coin, DebugLog, Error, Model, Uint128};
+
+/// upper bound of executed sequences while shrinking a failure
+const MAX_SHRINK_RUNS: usize = 1000;
+
+/// checked after every call of a sequence, gets the logs of all calls so far
+pub type Property = Box<dyn Fn(&mut Model, &[DebugLog]) -> Result<(), String>>;
+
+/// a symbolic value, drawn anew for every call of every iteration
+#[derive(Clone, Debug)]
+pub enum Sym {
+    Const(Value),
+    /// one of the values, earlier values are considered simpler when shrinking
+    OneOf(Vec<Value>),
+    /// an integer in [lo, hi], rendered as a string like Uint128
+    Range(u128, u128),
+}
+
+impl Sym {
+    pub fn one_of_addrs(addrs: &[Addr]) -> Self {
+        Sym::OneOf(addrs.iter().map(|a| Value::String(a.to_string())).collect())
+    }
+
+    fn draw(&self, rng: &mut StdRng) -> Value {
+        match self {
+            Sym::Const(value) => value.clone(),
+            Sym::OneOf(values) => values.choose(rng).cloned().unwrap_or(Value::Null),
+            Sym::Range(lo, hi) => Value::String(rng.gen_range(*lo..=*hi).to_string()),
+        }
+    }
+
+    /// simpler values than current, simplest first
+    fn shrink(&self, current: &Value) -> Vec<Value> {
+        match self {
+            Sym::Const(_) => Vec::new(),
+            Sym::OneOf(values) => match values.iter().position(|v| v == current) {
+                Some(index) => values[..index].to_vec(),
+                None => Vec::new(),
+            },
+            Sym::Range(lo, _) => {
+                let current = match current.as_str().and_then(|s| s.parse::<u128>().ok()) {
+                    Some(current) => current,
+                    None => return Vec::new(),
+                };
+                // lo, then halve the distance to current
+                let mut candidates = Vec::new();
+                let mut distance = current.saturating_sub(*lo);
+                while distance > 0 {
+                    candidates.push(Value::String((current - distance).to_string()));
+                    distance /= 2;
+                }
+                candidates
+            }
+        }
+    }
+}
+
+/// a contract execution with symbolic placeholders
+/// strings of the form "$name" in msg are replaced by the scenario variable name
+#[derive(Clone, Debug)]
+pub struct Call {
+    contract: Addr,
+    msg: Value,
+    sender: Option<Sym>,
+    funds: Vec<(String, Sym)>,
+    expect_success: bool,
+}
+
+impl Call {
+    pub fn execute(contract: &Addr, msg: Value) -> Self {
+        Self {
+            contract: contract.clone(),
+            msg,
+            sender: None,
+            funds: Vec::new(),
+            expect_success: false,
+        }
+    }
+
+    /// the model's sender is used if not set
+    pub fn sender(mut self, sender: Sym) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn funds(mut self, denom: &str, amount: Sym) -> Self {
+        self.funds.push((denom.to_string(), amount));
+        self
+    }
+
+    /// a contract error fails the scenario instead of being ignored
+    pub fn expect_success(mut self) -> Self {
+        self.expect_success = true;
+        self
+    }
+}
+
+/// drawn values of a single call
+#[derive(Clone, Debug)]
+struct Draw {
+    call: usize,
+    sender: Option<Value>,
+    funds: Vec<Value>,
+    vars: BTreeMap<String, Value>,
+}
+
+/// concrete call, as executed
+#[derive(Clone, Debug, Serialize)]
+pub struct CallInstance {
+    pub contract: Addr,
+    pub msg: Value,
+    pub sender: Addr,
+    pub funds: Vec<Coin>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ScenarioFailure {
+    pub iteration: usize,
+    /// length of the sequence before shrinking
+    pub original_len: usize,
+    /// shrunk sequence, the last call fails
+    pub calls: Vec<CallInstance>,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScenarioReport {
+    pub iterations: usize,
+    pub failure: Option<ScenarioFailure>,
+}
+
+impl fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            None => writeln!(f, "{} iterations passed", self.iterations),
+            Some(failure) => {
+                writeln!(
+                    f,
+                    "iteration {} failed: {} (shrunk from {} to {} calls)",
+                    failure.iteration,
+                    failure.reason,
+                    failure.original_len,
+                    failure.calls.len()
+                )?;
+                for call in failure.calls.iter() {
+                    writeln!(
+                        f,
+                        "{} -> {}: {} {:?}",
+                        call.sender, call.contract, call.msg, call.funds
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// sequence of calls run many times with randomized placeholders, failing sequences are shrunk
+#[derive(Default)]
+pub struct Scenario {
+    vars: BTreeMap<String, Sym>,
+    calls: Vec<Call>,
+    properties: Vec<(String, Property)>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn var(mut self, name: &str, sym: Sym) -> Self {
+        self.vars.insert(name.to_string(), sym);
+        self
+    }
+
+    pub fn call(mut self, call: Call) -> Self {
+        self.calls.push(call);
+        self
+    }
+
+    pub fn property(mut self, name: &str, property: Property) -> Self {
+        self.properties.push((name.to_string(), property));
+        self
+    }
+
+    /// run the sequence iterations times on copies of model, stops at the first failure
+    pub fn run(
+        &self,
+        model: &Model,
+        iterations: usize,
+        seed: u64,
+    ) -> Result<ScenarioReport, Error> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut report = ScenarioReport::default();
+        for iteration in 0..iterations {
+            report.iterations += 1;
+            let draws: Vec<Draw> = (0..self.calls.len())
+                .map(|call| self.draw(call, &mut rng))
+                .collect();
+            if let Some((len, reason)) = self.run_sequence(model, &draws)? {
+                let original_len = draws.len();
+                let draws = draws[..len].to_vec();
+                let (draws, reason) = self.shrink(model, draws, reason)?;
+                report.failure = Some(ScenarioFailure {
+                    iteration,
+                    original_len,
+                    calls: draws
+                        .iter()
+                        .map(|d| self.instantiate(model, d))
+                        .collect::<Result<_, _>>()?,
+                    reason,
+                });
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    fn draw(&self, call: usize, rng: &mut StdRng) -> Draw {
+        let template = &self.calls[call];
+        let mut vars = BTreeMap::new();
+        collect_placeholders(&template.msg, &mut |name| {
+            if let Some(sym) = self.vars.get(name) {
+                if !vars.contains_key(name) {
+                    vars.insert(name.to_string(), sym.draw(rng));
+                }
+            }
+        });
+        Draw {
+            call,
+            sender: template.sender.as_ref().map(|s| s.draw(rng)),
+            funds: template.funds.iter().map(|(_, s)| s.draw(rng)).collect(),
+            vars,
+        }
+    }
+
+    fn instantiate(&self, model: &Model, draw: &Draw) -> Result<CallInstance, Error> {
+        let template = &self.calls[draw.call];
+        let sender = match &draw.sender {
+            Some(Value::String(sender)) => Addr::unchecked(sender),
+            Some(sender) => {
+                return Err(Error::invalid_argument(format!(
+                    "sender must be a string, got {}",
+                    sender
+                )))
+            }
+            None => model.sender(),
+        };
+        let funds = template
+            .funds
+            .iter()
+            .zip(draw.funds.iter())
+            .map(|((denom, _), amount)| {
+                let amount = match amount {
+                    Value::String(s) => s.parse::<u128>().map_err(Error::invalid_argument)?,
+                    Value::Number(n) => n
+                        .as_u64()
+                        .ok_or_else(|| Error::invalid_argument(format!("invalid amount {}", n)))?
+                        as u128,
+                    _ => return Err(Error::invalid_argument("amount must be an integer")),
+                };
+                Ok(Coin {
+                    denom: denom.clone(),
+                    amount: Uint128::new(amount),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(CallInstance {
+            contract: template.contract.clone(),
+            msg: substitute(&template.msg, &draw.vars),
+            sender,
+            funds,
+        })
+    }
+
+    /// returns the number of calls up to and including the failing one, and the reason
+    fn run_sequence(
+        &self,
+        model: &Model,
+        draws: &[Draw],
+    ) -> Result<Option<(usize, String)>, Error> {
+        let mut model = model.clone();
+        let mut logs = Vec::new();
+        for (i, draw) in draws.iter().enumerate() {
+            let call = self.instantiate(&model, draw)?;
+            let msg = serde_json::to_vec(&call.msg).map_err(Error::format_error)?;
+            let debug_log = match model.execute_as(&call.sender, &call.contract, &msg, &call.funds)
+            {
+                Ok(debug_log) => debug_log,
+                Err(e) => return Ok(Some((i + 1, format!("crash: {}", e)))),
+            };
+            if let Some(err_msg) = &debug_log.err_msg {
+                if self.calls[draw.call].expect_success {
+                    return Ok(Some((i + 1, format!("call failed: {}", err_msg))));
+                }
+            }
+            logs.push(debug_log);
+            for (name, property) in self.properties.iter() {
+                if let Err(e) = property(&mut model, &logs) {
+                    return Ok(Some((i + 1, format!("{}: {}", name, e))));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// remove calls and simplify drawn values while the sequence still fails
+    fn shrink(
+        &self,
+        model: &Model,
+        mut draws: Vec<Draw>,
+        mut reason: String,
+    ) -> Result<(Vec<Draw>, String), Error> {
+        let mut runs = 0;
+        let mut try_candidate = |draws: &mut Vec<Draw>,
+                                 candidate: Vec<Draw>,
+                                 reason: &mut String|
+         -> Result<bool, Error> {
+            if runs >= MAX_SHRINK_RUNS {
+                return Ok(false);
+            }
+            runs += 1;
+            match self.run_sequence(model, &candidate)? {
+                Some((len, new_reason)) => {
+                    *draws = candidate[..len].to_vec();
+                    *reason = new_reason;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        };
+
+        // drop calls
+        let mut i = 0;
+        while i < draws.len() {
+            let mut candidate = draws.clone();
+            candidate.remove(i);
+            if !try_candidate(&mut draws, candidate, &mut reason)? {
+                i += 1;
+            }
+        }
+
+        // simplify values
+        for i in 0..draws.len() {
+            let template = &self.calls[draws[i].call];
+            if let Some(sym) = &template.sender {
+                while let Some(sender) = draws.get(i).and_then(|d| d.sender.clone()) {
+                    let mut shrunk = false;
+                    for value in sym.shrink(&sender) {
+                        let mut candidate = draws.clone();
+                        candidate[i].sender = Some(value);
+                        if try_candidate(&mut draws, candidate, &mut reason)? {
+                            shrunk = true;
+                            break;
+                        }
+                    }
+                    if !shrunk {
+                        break;
+                    }
+                }
+            }
+            for (j, (_, sym)) in template.funds.iter().enumerate() {
+                while let Some(amount) = draws.get(i).map(|d| d.funds[j].clone()) {
+                    let mut shrunk = false;
+                    for value in sym.shrink(&amount) {
+                        let mut candidate = draws.clone();
+                        candidate[i].funds[j] = value;
+                        if try_candidate(&mut draws, candidate, &mut reason)? {
+                            shrunk = true;
+                            break;
+                        }
+                    }
+                    if !shrunk {
+                        break;
+                    }
+                }
+            }
+            let names: Vec<String> = draws
+                .get(i)
+                .map(|d| d.vars.keys().cloned().collect())
+                .unwrap_or_default();
+            for name in names.iter() {
+                let sym = &self.vars[name];
+                while let Some(current) = draws.get(i).and_then(|d| d.vars.get(name).cloned()) {
+                    let mut shrunk = false;
+                    for value in sym.shrink(&current) {
+                        let mut candidate = draws.clone();
+                        candidate[i].vars.insert(name.clone(), value);
+                        if try_candidate(&mut draws, candidate, &mut reason)? {
+                            shrunk = true;
+                            break;
+                        }
+                    }
+                    if !shrunk {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok((draws, reason))
+    }
+}
+
+fn placeholder(value: &Value) -> Option<&str> {
+    value.as_str().and_then(|s| s.strip_prefix('$'))
+}
+
+fn collect_placeholders<F: FnMut(&str)>(template: &Value, f: &mut F) {
+    match template {
+        Value::Array(values) => values.iter().for_each(|v| collect_placeholders(v, f)),
+        Value::Object(map) => map.values().for_each(|v| collect_placeholders(v, f)),
+        value => {
+            if let Some(name) = placeholder(value) {
+                f(name)
+            }
+        }
+    }
+}
+
+/// replace placeholders in template, unknown placeholders are kept as is
+fn substitute(template: &Value, vars: &BTreeMap<String, Value>) -> Value {
+    match template {
+        Value::Array(values) => Value::Array(values.iter().map(|v| substitute(v, vars)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), substitute(v, vars)))
+                .collect(),
+        ),
+        value => match placeholder(value).and_then(|name| vars.get(name)) {
+            Some(var) => var.clone(),
+            None => value.clone(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{to_binary, BalanceResponse, BankQuery};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use test_contract::msg::InstantiateMsg;
+
+    use super::{substitute, Call, Scenario, Sym};
+    use crate::{Addr, Coin, Model};
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+
+    #[test]
+    fn test_substitute_and_shrink() {
+        let mut vars = BTreeMap::new();
+        vars.insert("amount".to_string(), json!("10"));
+        let msg = substitute(
+            &json!({"transfer": {"amount": "$amount", "memo": "$unknown", "list": ["$amount"]}}),
+            &vars,
+        );
+        assert_eq!(
+            msg,
+            json!({"transfer": {"amount": "10", "memo": "$unknown", "list": ["10"]}})
+        );
+
+        assert_eq!(
+            Sym::Range(0, 100).shrink(&json!("10")),
+            vec![json!("0"), json!("5"), json!("8"), json!("9")]
+        );
+        assert!(Sym::Range(10, 100).shrink(&json!("10")).is_empty());
+        assert_eq!(
+            Sym::OneOf(vec![json!("a"), json!("b"), json!("c")]).shrink(&json!("c")),
+            vec![json!("a"), json!("b")]
+        );
+    }
+
+    #[test]
+    fn test_scenario_shrink() {
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let code = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        ));
+        model.add_custom_code(1337, code).unwrap();
+        let msg = to_binary(&InstantiateMsg {}).unwrap();
+        let debug_log = model.instantiate(1337, msg.as_slice(), &[]).unwrap();
+        let contract_addr = Addr::unchecked(
+            debug_log.logs[0].events[0]
+                .attributes
+                .iter()
+                .find(|a| a.key == "_contract_address")
+                .unwrap()
+                .value
+                .clone(),
+        );
+        let senders: Vec<Addr> = ["alice", "bob"]
+            .iter()
+            .map(|label| {
+                model
+                    .create_account(label, &[Coin::new(1_000_000, "umlg")])
+                    .unwrap()
+            })
+            .collect();
+
+        let call = Call::execute(&contract_addr, json!({"test_query_self": {}}))
+            .sender(Sym::one_of_addrs(&senders))
+            .funds("umlg", Sym::Range(0, 1000))
+            .expect_success();
+        let balance_query = serde_json::to_vec(&BankQuery::Balance {
+            address: contract_addr.to_string(),
+            denom: "umlg".to_string(),
+        })
+        .unwrap();
+        let scenario = Scenario::new()
+            .call(call.clone())
+            .call(call.clone())
+            .call(call)
+            .property(
+                "balance_below_500",
+                Box::new(move |model, _| {
+                    let response = model.bank_query(&balance_query).unwrap();
+                    let balance: BalanceResponse = serde_json::from_slice(&response).unwrap();
+                    match balance.amount.amount.u128() < 500 {
+                        true => Ok(()),
+                        false => Err(format!("balance is {}", balance.amount.amount)),
+                    }
+                }),
+            );
+        let report = scenario.run(&model, 20, 0).unwrap();
+        let failure = report.failure.unwrap();
+        // a single call with the smallest failing amount from the first sender
+        assert_eq!(failure.calls.len(), 1);
+        assert_eq!(failure.calls[0].sender, senders[0]);
+        assert_eq!(failure.calls[0].funds, vec![Coin::new(500, "umlg")]);
+    }
+}
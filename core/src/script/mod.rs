@@ -0,0 +1,555 @@
+use cosmwasm_std::{from_binary, to_binary, BalanceResponse, BankQuery, Timestamp, Uint128};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{Addr, Coin, DebugLog, Error, Model};
+
+/// chain forked by Script::fork
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fork {
+    pub url: String,
+    /// the latest block if not set
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    pub bech32_prefix: String,
+}
+
+/// expected outcome of an instantiation or execution, success if nothing is set
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    /// the call fails with an error containing this
+    #[serde(default)]
+    pub error_contains: Option<String>,
+    /// events the call emits, with at least the given attributes
+    #[serde(default)]
+    pub events: Vec<ExpectedEvent>,
+    /// data returned by the call, as json
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedEvent {
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// a step of a script. Addresses may be given as names, see Model::lookup,
+/// and strings "@name" in messages are replaced by the address of name
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// create an account named name, see Model::create_account
+    Account {
+        name: String,
+        #[serde(default)]
+        funds: Vec<Coin>,
+    },
+    /// register a wasm file as code_id, the path is relative to the script
+    Code {
+        code_id: u64,
+        path: PathBuf,
+    },
+    /// the contract is named name with Model::set_alias if the instantiation succeeds
+    Instantiate {
+        code_id: u64,
+        msg: Value,
+        #[serde(default)]
+        funds: Vec<Coin>,
+        #[serde(default)]
+        sender: Option<String>,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        expect: Expect,
+    },
+    Execute {
+        contract: String,
+        msg: Value,
+        #[serde(default)]
+        funds: Vec<Coin>,
+        #[serde(default)]
+        sender: Option<String>,
+        #[serde(default)]
+        expect: Expect,
+    },
+    /// the response must equal response if set
+    Query {
+        contract: String,
+        msg: Value,
+        #[serde(default)]
+        response: Option<Value>,
+        #[serde(default)]
+        error_contains: Option<String>,
+    },
+    /// the balance of address must equal amount
+    Balance {
+        address: String,
+        denom: String,
+        amount: Uint128,
+    },
+    Mint {
+        address: String,
+        funds: Vec<Coin>,
+    },
+    /// use address as the sender of the following steps
+    Sender {
+        address: String,
+    },
+    /// produce blocks
+    Blocks {
+        count: u64,
+    },
+    /// set the block height and time
+    Warp {
+        #[serde(default)]
+        height: Option<u64>,
+        #[serde(default)]
+        time: Option<Timestamp>,
+    },
+}
+
+/// outcome of a step, failure is None if it passed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StepResult {
+    pub index: usize,
+    pub step: String,
+    pub failure: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ScriptReport {
+    pub steps: Vec<StepResult>,
+}
+
+impl ScriptReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|s| s.failure.is_none())
+    }
+
+    pub fn failures(&self) -> Vec<&StepResult> {
+        self.steps.iter().filter(|s| s.failure.is_some()).collect()
+    }
+}
+
+impl fmt::Display for ScriptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in self.steps.iter() {
+            match &result.failure {
+                None => writeln!(f, "ok   {} {}", result.index, result.step)?,
+                Some(failure) => writeln!(f, "FAIL {} {}: {}", result.index, result.step, failure)?,
+            }
+        }
+        let failed = self.failures().len();
+        writeln!(f, "{} passed, {} failed", self.steps.len() - failed, failed)
+    }
+}
+
+/// steps with expected results, written in yaml or json, see README.
+/// Every step runs as its own transaction, all steps run even if some fail
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    #[serde(default)]
+    pub fork: Option<Fork>,
+    pub steps: Vec<Step>,
+    // code paths are relative to this
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+impl Script {
+    pub fn from_yaml(yaml: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(yaml)
+            .map_err(|e| Error::format_error(format!("invalid script: {}", e)))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json)
+            .map_err(|e| Error::format_error(format!("invalid script: {}", e)))
+    }
+
+    /// reads a json file if path ends with .json, and a yaml file otherwise
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::io_error(format!("{}: {}", path.display(), e)))?;
+        let mut script = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&text)?,
+            _ => Self::from_yaml(&text)?,
+        };
+        script.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(script)
+    }
+
+    /// forks the chain of the fork section
+    pub fn fork(&self) -> Result<Model, Error> {
+        let fork = self
+            .fork
+            .as_ref()
+            .ok_or_else(|| Error::invalid_argument("the script has no fork section"))?;
+        Model::new(&fork.url, fork.block_number, &fork.bech32_prefix)
+    }
+
+    /// runs the steps on model. Errors of the simulator fail the step they happen in,
+    /// unless the step expects them
+    pub fn run(&self, model: &mut Model) -> Result<ScriptReport, Error> {
+        let mut runner = Runner {
+            model,
+            base_dir: &self.base_dir,
+        };
+        let steps = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| StepResult {
+                index,
+                step: describe(step),
+                failure: runner.run_step(step).err(),
+            })
+            .collect();
+        Ok(ScriptReport { steps })
+    }
+}
+
+fn describe(step: &Step) -> String {
+    match step {
+        Step::Account { name, .. } => format!("account {}", name),
+        Step::Code { code_id, .. } => format!("code {}", code_id),
+        Step::Instantiate { code_id, name, .. } => match name {
+            Some(name) => format!("instantiate {} as {}", code_id, name),
+            None => format!("instantiate {}", code_id),
+        },
+        Step::Execute { contract, msg, .. } => format!("execute {} {}", contract, msg),
+        Step::Query { contract, msg, .. } => format!("query {} {}", contract, msg),
+        Step::Balance { address, denom, .. } => format!("balance {} {}", address, denom),
+        Step::Mint { address, .. } => format!("mint {}", address),
+        Step::Sender { address } => format!("sender {}", address),
+        Step::Blocks { count } => format!("blocks {}", count),
+        Step::Warp { .. } => "warp".to_string(),
+    }
+}
+
+struct Runner<'a> {
+    model: &'a mut Model,
+    base_dir: &'a Path,
+}
+
+impl<'a> Runner<'a> {
+    /// returns why the step failed
+    fn run_step(&mut self, step: &Step) -> Result<(), String> {
+        match step {
+            Step::Account { name, funds } => {
+                self.model
+                    .create_account(name, funds)
+                    .map_err(|e| e.to_string())?;
+            }
+            Step::Code { code_id, path } => {
+                self.model
+                    .add_code_from_path(*code_id, &self.base_dir.join(path))
+                    .map_err(|e| e.to_string())?;
+            }
+            Step::Instantiate {
+                code_id,
+                msg,
+                funds,
+                sender,
+                name,
+                expect,
+            } => {
+                let msg = self.msg(msg)?;
+                let result =
+                    self.as_sender(sender, |model| model.instantiate(*code_id, &msg, funds));
+                let debug_log = check_call(result, expect)?;
+                if let (Some(name), Some(log)) = (name, debug_log) {
+                    let address = log
+                        .contract_address
+                        .ok_or_else(|| "no contract was instantiated".to_string())?;
+                    self.model.set_alias(name, &address);
+                }
+            }
+            Step::Execute {
+                contract,
+                msg,
+                funds,
+                sender,
+                expect,
+            } => {
+                let contract = self.address(contract);
+                let msg = self.msg(msg)?;
+                let sender = match sender {
+                    Some(sender) => self.address(sender),
+                    None => self.model.sender(),
+                };
+                let result = self.model.execute_as(&sender, &contract, &msg, funds);
+                check_call(result, expect)?;
+            }
+            Step::Query {
+                contract,
+                msg,
+                response,
+                error_contains,
+            } => {
+                let contract = self.address(contract);
+                let msg = self.msg(msg)?;
+                let result = self
+                    .model
+                    .wasm_query(&contract, &msg)
+                    .and_then(|r| from_binary::<Value>(&r).map_err(Error::format_error));
+                match (result, error_contains) {
+                    (Err(e), Some(expected)) if e.to_string().contains(expected.as_str()) => {}
+                    (Err(e), _) => return Err(e.to_string()),
+                    (Ok(actual), Some(expected)) => {
+                        return Err(format!(
+                            "expected an error containing {:?}, got {}",
+                            expected, actual
+                        ))
+                    }
+                    (Ok(actual), None) => match response {
+                        Some(expected) if *expected != actual => {
+                            return Err(format!("expected {}, got {}", expected, actual))
+                        }
+                        _ => {}
+                    },
+                }
+            }
+            Step::Balance {
+                address,
+                denom,
+                amount,
+            } => {
+                let query = BankQuery::Balance {
+                    address: self.address(address).to_string(),
+                    denom: denom.clone(),
+                };
+                let response = to_binary(&query)
+                    .map_err(Error::from)
+                    .and_then(|query| self.model.bank_query(&query))
+                    .and_then(|r| from_binary::<BalanceResponse>(&r).map_err(Error::from))
+                    .map_err(|e| e.to_string())?;
+                if response.amount.amount != *amount {
+                    return Err(format!(
+                        "expected {}{}, got {}",
+                        amount, denom, response.amount
+                    ));
+                }
+            }
+            Step::Mint { address, funds } => {
+                let address = self.address(address);
+                self.model
+                    .cheat_mint(&address, funds)
+                    .map_err(|e| e.to_string())?;
+            }
+            Step::Sender { address } => {
+                let address = self.address(address);
+                self.model
+                    .cheat_message_sender(&address)
+                    .map_err(|e| e.to_string())?;
+            }
+            Step::Blocks { count } => self.model.produce_block(*count),
+            Step::Warp { height, time } => {
+                if let Some(height) = height {
+                    self.model
+                        .cheat_block_number(*height)
+                        .map_err(|e| e.to_string())?;
+                }
+                if let Some(time) = time {
+                    self.model
+                        .cheat_block_timestamp(*time)
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// a name known to the model, e.g. of a contract named by instantiate, or the address itself
+    fn address(&self, address: &str) -> Addr {
+        self.model
+            .lookup(address)
+            .unwrap_or_else(|| Addr::unchecked(address))
+    }
+
+    fn msg(&self, msg: &Value) -> Result<Vec<u8>, String> {
+        let mut msg = msg.clone();
+        self.resolve_names(&mut msg);
+        serde_json::to_vec(&msg).map_err(|e| e.to_string())
+    }
+
+    fn resolve_names(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(name) = s.strip_prefix('@') {
+                    *s = self.address(name).to_string();
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.resolve_names(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.resolve_names(v)),
+            _ => {}
+        }
+    }
+
+    /// runs f with sender as the sender of the model if set
+    fn as_sender<F>(&mut self, sender: &Option<String>, f: F) -> Result<DebugLog, Error>
+    where
+        F: FnOnce(&mut Model) -> Result<DebugLog, Error>,
+    {
+        let sender = match sender {
+            Some(sender) => self.address(sender),
+            None => return f(self.model),
+        };
+        let previous = self.model.sender();
+        self.model.cheat_message_sender(&sender)?;
+        let result = f(self.model);
+        self.model.cheat_message_sender(&previous)?;
+        result
+    }
+}
+
+/// the log of a call which succeeded as expected
+fn check_call(
+    result: Result<DebugLog, Error>,
+    expect: &Expect,
+) -> Result<Option<DebugLog>, String> {
+    let debug_log = match (result, &expect.error_contains) {
+        (Err(e), Some(expected)) if e.to_string().contains(expected.as_str()) => return Ok(None),
+        (Err(e), _) => return Err(e.to_string()),
+        (Ok(debug_log), expected) => match (debug_log.err_msg.clone(), expected) {
+            (Some(err), Some(expected)) if err.contains(expected.as_str()) => return Ok(None),
+            (Some(err), Some(expected)) => {
+                return Err(format!(
+                    "expected an error containing {:?}, got {:?}",
+                    expected, err
+                ))
+            }
+            (Some(err), None) => return Err(format!("failed: {}", err)),
+            (None, Some(expected)) => {
+                return Err(format!(
+                    "expected an error containing {:?}, but the call succeeded",
+                    expected
+                ))
+            }
+            (None, None) => debug_log,
+        },
+    };
+    for expected in expect.events.iter() {
+        let found = debug_log
+            .logs
+            .iter()
+            .flat_map(|entry| entry.events.iter())
+            .any(|event| {
+                event.ty == expected.ty
+                    && expected.attributes.iter().all(|(key, value)| {
+                        event
+                            .attributes
+                            .iter()
+                            .any(|a| &a.key == key && &a.value == value)
+                    })
+            });
+        if !found {
+            return Err(format!(
+                "no {} event with attributes {:?}",
+                expected.ty, expected.attributes
+            ));
+        }
+    }
+    if let Some(expected) = &expect.data {
+        let actual = match &debug_log.data {
+            Some(data) => from_binary::<Value>(data).map_err(|e| e.to_string())?,
+            None => Value::Null,
+        };
+        if *expected != actual {
+            return Err(format!("expected data {}, got {}", expected, actual));
+        }
+    }
+    Ok(Some(debug_log))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Script, Step};
+    use crate::Model;
+
+    const MALAGA_RPC_URL: &str = "https://rpc.malaga-420.cosmwasm.com:443";
+    const MALAGA_BLOCK_NUMBER: u64 = 2326474;
+
+    #[test]
+    fn test_parse_script() {
+        let yaml = r#"
+fork:
+  url: https://rpc.malaga-420.cosmwasm.com:443
+  block_number: 2326474
+  bech32_prefix: wasm
+steps:
+  - account:
+      name: alice
+      funds: [{ denom: umlg, amount: "100" }]
+  - execute:
+      contract: counter
+      msg: { increment: {} }
+      expect:
+        error_contains: Unauthorized
+  - blocks: { count: 2 }
+"#;
+        let script = Script::from_yaml(yaml).unwrap();
+        assert_eq!(script.fork.as_ref().unwrap().bech32_prefix, "wasm");
+        assert_eq!(script.steps.len(), 3);
+        assert_eq!(script.steps[2], Step::Blocks { count: 2 });
+        let json = serde_json::to_string(&script).unwrap();
+        assert_eq!(Script::from_json(&json).unwrap(), script);
+
+        let err = Script::from_yaml("steps:\n  - frobnicate: {}\n").unwrap_err();
+        assert!(err.to_string().contains("invalid script"));
+    }
+
+    #[test]
+    fn test_run_script() {
+        let code = concat!(
+            env!("OUT_DIR"),
+            "/wasm32-unknown-unknown/release/test_contract.wasm"
+        );
+        let yaml = format!(
+            r#"
+steps:
+  - account: {{ name: alice, funds: [{{ denom: umlg, amount: "1000" }}] }}
+  - sender: {{ address: alice }}
+  - code: {{ code_id: 1337, path: "{}" }}
+  - instantiate: {{ code_id: 1337, msg: {{}}, name: test }}
+  - execute:
+      contract: test
+      msg: {{ test_query_self: {{}} }}
+      funds: [{{ denom: umlg, amount: "100" }}]
+      expect:
+        events: [{{ type: read_number, attributes: {{ value: "2" }} }}]
+  - query: {{ contract: test, msg: {{ read_number: {{}} }}, response: {{ value: 1 }} }}
+  - balance: {{ address: test, denom: umlg, amount: "100" }}
+  - execute:
+      contract: test
+      msg: {{ test_atomic: {{}} }}
+      expect: {{ error_contains: Unauthorized }}
+  - execute:
+      contract: test
+      msg: {{ test_query_self: {{}} }}
+      expect: {{ error_contains: Unauthorized }}
+  - balance: {{ address: alice, denom: umlg, amount: "1000" }}
+"#,
+            code
+        );
+        let script = Script::from_yaml(&yaml).unwrap();
+        let mut model = Model::new(MALAGA_RPC_URL, Some(MALAGA_BLOCK_NUMBER), "wasm").unwrap();
+        let report = script.run(&mut model).unwrap();
+        let failures: Vec<usize> = report.failures().iter().map(|f| f.index).collect();
+        // test_query_self succeeds, and alice has sent 100umlg
+        assert_eq!(failures, vec![8, 9]);
+        assert!(!report.passed());
+        assert!(report.to_string().ends_with("8 passed, 2 failed\n"));
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
-use crate::static_analysis::ExportInfo;
+use crate::errors::VmResult;
+use crate::static_analysis::{deserialize_wasm, ExportInfo};
 
 const REQUIRES_PREFIX: &str = "requires_";
 
@@ -30,10 +31,15 @@ pub fn required_capabilities_from_module(module: &impl ExportInfo) -> HashSet<St
         .collect()
 }
 
+/// Returns the capabilities required by the given Wasm bytecode, without the other checks of check_wasm.
+pub fn required_capabilities(wasm_code: &[u8]) -> VmResult<HashSet<String>> {
+    let module = deserialize_wasm(wasm_code)?;
+    Ok(required_capabilities_from_module(&module))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::static_analysis::deserialize_wasm;
 
     #[test]
     fn capabilities_from_csv_works() {
@@ -89,4 +95,14 @@ mod tests {
         let required_capabilities = required_capabilities_from_module(&module);
         assert_eq!(required_capabilities.len(), 0);
     }
+
+    #[test]
+    fn required_capabilities_works() {
+        let wasm = wat::parse_str(r#"(module (func) (export "requires_water" (func 0)))"#).unwrap();
+        let required = required_capabilities(&wasm).unwrap();
+        assert_eq!(required.len(), 1);
+        assert!(required.contains("water"));
+
+        assert!(required_capabilities(b"not wasm").is_err());
+    }
 }
@@ -8,6 +8,7 @@ use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, M
 
 use crate::backend::{BackendApi, GasInfo, Querier, Storage};
 use crate::errors::{VmError, VmResult};
+use crate::wasm_backend::record_wasm_trace;
 
 /// Never can never be instantiated.
 /// Replace this with the [never primitive type](https://doc.rust-lang.org/std/primitive.never.html) when stable.
@@ -166,6 +167,7 @@ impl<A: BackendApi, S: Storage, Q: Querier> Environment<A, S, Q> {
             Ok(func.clone())
         })?;
         func.call(args).map_err(|runtime_err| -> VmError {
+            record_wasm_trace(runtime_err.trace());
             self.with_wasmer_instance::<_, Never>(|instance| {
                 let err: VmError = match get_remaining_points(instance) {
                     MeteringPoints::Remaining(_) => VmError::from(runtime_err),
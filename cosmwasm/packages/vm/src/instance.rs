@@ -18,6 +18,7 @@ use crate::imports::{
 use crate::imports::{do_db_next, do_db_scan};
 use crate::memory::{read_region, write_region};
 use crate::size::Size;
+use crate::static_analysis::ExportInfo;
 use crate::wasm_backend::compile;
 
 #[derive(Copy, Clone, Debug)]
@@ -278,6 +279,12 @@ where
         required_capabilities_from_module(self._inner.module())
     }
 
+    /// Returns the names of the exported functions starting with prefix, e.g. the
+    /// interface_version_* marker export.
+    pub fn exported_function_names(&self, prefix: Option<&str>) -> HashSet<String> {
+        self._inner.module().exported_function_names(prefix)
+    }
+
     /// Returns the size of the default memory in pages.
     /// This provides a rough idea of the peak memory consumption. Note that
     /// Wasm memory always grows in 64 KiB steps (pages) and can never shrink
@@ -291,6 +298,11 @@ where
         self.env.get_gas_left()
     }
 
+    /// Sets the remaining gas, e.g. to give a reused instance the gas limit of its next call.
+    pub fn set_gas_left(&mut self, gas_left: u64) {
+        self.env.set_gas_left(gas_left)
+    }
+
     /// Creates and returns a gas report.
     /// This is a snapshot and multiple reports can be created during the lifetime of
     /// an instance.
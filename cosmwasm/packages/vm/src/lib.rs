@@ -54,7 +54,11 @@ pub mod internals {
     //! Please don't use any of these types directly, as
     //! they might change frequently or be removed in the future.
 
+    pub use crate::capabilities::required_capabilities;
     pub use crate::compatibility::check_wasm;
     pub use crate::instance::instance_from_module;
-    pub use crate::wasm_backend::{compile, make_runtime_store};
+    pub use crate::static_analysis::{deserialize_wasm, has_ibc_entry_points};
+    pub use crate::wasm_backend::{
+        compile, make_runtime_store, take_failed_grow, take_wasm_trace, WasmFrame,
+    };
 }
@@ -1,12 +1,66 @@
+use std::cell::Cell;
 use std::ptr::NonNull;
 use std::sync::Arc;
 
-use loupe::MemoryUsage;
+use loupe::{MemoryUsage, MemoryUsageTracker};
 use wasmer::{
     vm::{self, MemoryError, MemoryStyle, TableStyle, VMMemoryDefinition, VMTableDefinition},
     MemoryType, Pages, TableType, Tunables,
 };
 
+thread_local! {
+    static FAILED_GROW: Cell<bool> = Cell::new(false);
+}
+
+/// Tells whether a memory.grow failed on this thread since the last call, e.g. because
+/// the contract tried to grow its memory beyond the limit. Contracts usually trap right after,
+/// so this tells running out of memory apart from other traps.
+pub fn take_failed_grow() -> bool {
+    FAILED_GROW.with(|failed| failed.replace(false))
+}
+
+/// A memory recording failed grows, see take_failed_grow
+#[derive(Debug)]
+struct GrowRecordingMemory {
+    inner: Arc<dyn vm::Memory>,
+}
+
+impl MemoryUsage for GrowRecordingMemory {
+    fn size_of_val(&self, tracker: &mut dyn MemoryUsageTracker) -> usize {
+        std::mem::size_of_val(self) + self.inner.size_of_val(tracker)
+    }
+}
+
+impl vm::Memory for GrowRecordingMemory {
+    fn ty(&self) -> MemoryType {
+        self.inner.ty()
+    }
+
+    fn style(&self) -> &MemoryStyle {
+        self.inner.style()
+    }
+
+    fn size(&self) -> Pages {
+        self.inner.size()
+    }
+
+    fn grow(&self, delta: Pages) -> Result<Pages, MemoryError> {
+        let result = self.inner.grow(delta);
+        if result.is_err() {
+            FAILED_GROW.with(|failed| failed.set(true));
+        }
+        result
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        self.inner.vmmemory()
+    }
+}
+
+fn record_grows(memory: Arc<dyn vm::Memory>) -> Arc<dyn vm::Memory> {
+    Arc::new(GrowRecordingMemory { inner: memory })
+}
+
 /// A custom tunables that allows you to set a memory limit.
 ///
 /// After adjusting the memory limits, it delegates all other logic
@@ -87,7 +141,9 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
     ) -> Result<Arc<dyn vm::Memory>, MemoryError> {
         let adjusted = self.adjust_memory(ty);
         self.validate_memory(&adjusted)?;
-        self.base.create_host_memory(&adjusted, style)
+        self.base
+            .create_host_memory(&adjusted, style)
+            .map(record_grows)
     }
 
     /// Create a memory owned by the VM given a [`MemoryType`] and a [`MemoryStyle`].
@@ -103,6 +159,7 @@ impl<T: Tunables> Tunables for LimitingTunables<T> {
         self.validate_memory(&adjusted)?;
         self.base
             .create_vm_memory(&adjusted, style, vm_definition_location)
+            .map(record_grows)
     }
 
     /// Create a table owned by the host given a [`TableType`] and a [`TableStyle`].
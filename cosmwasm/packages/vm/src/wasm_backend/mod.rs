@@ -2,7 +2,10 @@ mod compile;
 mod gatekeeper;
 mod limiting_tunables;
 mod store;
+mod trace;
 
 pub use compile::compile;
-pub use limiting_tunables::LimitingTunables;
+pub use limiting_tunables::{take_failed_grow, LimitingTunables};
 pub use store::make_runtime_store;
+pub(crate) use trace::record_wasm_trace;
+pub use trace::{take_wasm_trace, WasmFrame};
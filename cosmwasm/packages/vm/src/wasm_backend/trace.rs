@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+
+use wasmer::FrameInfo;
+
+/// A frame of the wasm backtrace of a runtime error
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmFrame {
+    pub func_index: u32,
+    /// The function name from the name section of the module, if present
+    pub function_name: Option<String>,
+    /// Offset of the instruction in the module
+    pub module_offset: usize,
+}
+
+thread_local! {
+    static LAST_WASM_TRACE: RefCell<Vec<WasmFrame>> = RefCell::new(Vec::new());
+}
+
+/// Keeps the trace of a runtime error. The trace is not part of VmError
+/// because it depends on how the module was compiled, which would make errors
+/// non-deterministic.
+pub(crate) fn record_wasm_trace(trace: &[FrameInfo]) {
+    let frames = trace
+        .iter()
+        .map(|frame| WasmFrame {
+            func_index: frame.func_index(),
+            function_name: frame.function_name().map(|name| name.to_string()),
+            module_offset: frame.module_offset(),
+        })
+        .collect();
+    LAST_WASM_TRACE.with(|last| *last.borrow_mut() = frames);
+}
+
+/// Takes the wasm backtrace of the last runtime error on this thread, innermost frame first.
+/// Returns an empty trace if no runtime error occurred since the last call.
+pub fn take_wasm_trace() -> Vec<WasmFrame> {
+    LAST_WASM_TRACE.with(|last| std::mem::take(&mut *last.borrow_mut()))
+}
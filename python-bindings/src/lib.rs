@@ -1,12 +1,43 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use cosmwasm_simulate::{Addr, Coin, Timestamp, Uint128};
+use cosmwasm_simulate::coverage::CoverageSnapshot;
+use cosmwasm_simulate::fuzz::{FuzzConfig, Fuzzer, MsgGenerator};
+use cosmwasm_simulate::inspect::RawKey;
+use cosmwasm_simulate::scenario::{Call, Sym};
+use cosmwasm_simulate::script::Script;
+use cosmwasm_simulate::{
+    code_checksum, Addr, Attribute, Binary, BlockConfig, CheatKey, ClosureProgress, Coin,
+    ContractResult, ContractSchema, CosmosMsg, Error, KeyAlgo, MsgAction, MsgKind, NativeHandler,
+    Order, ProfileSort, RecursionConfig, RecursionPolicy, Reply, Response, SandboxLimits, StdError,
+    Timestamp, Uint128, WasmBuild,
+};
 // we don't import Model and DebugLog in order to use their names for Python classes
-use pyo3::{exceptions::PyRuntimeError, prelude::*};
+use pyo3::{
+    exceptions::{PyAssertionError, PyRuntimeError},
+    prelude::*,
+    types::{PyBytes, PyDict, PyList},
+};
 
 #[pyclass]
 struct Model {
     inner: cosmwasm_simulate::Model,
+    // states saved by snapshot, the index is the snapshot id
+    snapshots: Vec<cosmwasm_simulate::Model>,
+    // snapshots taken by __enter__, innermost last
+    entered: Vec<usize>,
+}
+
+impl From<cosmwasm_simulate::Model> for Model {
+    fn from(inner: cosmwasm_simulate::Model) -> Self {
+        Model {
+            inner,
+            snapshots: Vec::new(),
+            entered: Vec::new(),
+        }
+    }
 }
 
 #[pyclass]
@@ -16,6 +47,16 @@ struct DebugLog {
 
 #[pymethods]
 impl DebugLog {
+    /// the whole log with the call tree, stable across runs for snapshot tests
+    fn __str__(self_: PyRefMut<Self>) -> PyResult<String> {
+        Ok(self_.inner.to_string())
+    }
+
+    /// the whole log serialized to json
+    fn to_json(self_: PyRefMut<Self>) -> PyResult<String> {
+        serde_json::to_string(&self_.inner).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
     fn get_log(self_: PyRefMut<Self>) -> PyResult<Vec<String>> {
         let debug_log = &self_.inner;
         let mut out = Vec::new();
@@ -25,6 +66,161 @@ impl DebugLog {
         Ok(out)
     }
 
+    /// logs as dicts with attributes, events and data, in the order they were emitted
+    /// attributes are (key, value) tuples, events are dicts with type and attributes
+    fn get_logs(self_: PyRefMut<Self>) -> PyResult<Vec<PyObject>> {
+        let py = self_.py();
+        let attributes = |attributes: &[Attribute]| -> Vec<(String, String)> {
+            attributes
+                .iter()
+                .map(|a| (a.key.clone(), a.value.clone()))
+                .collect()
+        };
+        let mut out = Vec::new();
+        for entry in self_.inner.logs.iter() {
+            let events = PyList::empty(py);
+            for event in entry.events.iter() {
+                let dict = PyDict::new(py);
+                dict.set_item("type", &event.ty)?;
+                dict.set_item("attributes", attributes(&event.attributes))?;
+                events.append(dict)?;
+            }
+            let dict = PyDict::new(py);
+            dict.set_item("attributes", attributes(&entry.attributes))?;
+            dict.set_item("events", events)?;
+            dict.set_item(
+                "data",
+                entry.data.as_ref().map(|d| PyBytes::new(py, d.as_slice())),
+            )?;
+            out.push(dict.to_object(py));
+        }
+        Ok(out)
+    }
+
+    /// events the chain emits for the call as dicts with call_id, type and attributes,
+    /// in the format and order of wasmd. Empty if the call failed
+    fn get_events(self_: PyRefMut<Self>) -> PyResult<Vec<PyObject>> {
+        let py = self_.py();
+        let mut out = Vec::new();
+        for e in self_.inner.events.iter() {
+            let attributes: Vec<(String, String)> = e
+                .event
+                .attributes
+                .iter()
+                .map(|a| (a.key.clone(), a.value.clone()))
+                .collect();
+            let dict = PyDict::new(py);
+            dict.set_item("call_id", e.call_id)?;
+            dict.set_item("type", &e.event.ty)?;
+            dict.set_item("attributes", attributes)?;
+            out.push(dict.to_object(py));
+        }
+        Ok(out)
+    }
+
+    /// attributes of the first event of type ty having every given attribute, raises
+    /// AssertionError describing how the closest events differ if there is none
+    fn assert_event(
+        self_: PyRefMut<Self>,
+        ty: &str,
+        attributes: Vec<(String, String)>,
+    ) -> PyResult<Vec<(String, String)>> {
+        let attributes: Vec<(&str, &str)> = attributes
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        match self_.inner.assert_event(ty, &attributes) {
+            Ok(event) => Ok(event
+                .attributes
+                .iter()
+                .map(|a| (a.key.clone(), a.value.clone()))
+                .collect()),
+            Err(e) => Err(PyAssertionError::new_err(e.to_string())),
+        }
+    }
+
+    fn assert_transfer(
+        self_: PyRefMut<Self>,
+        sender: &str,
+        recipient: &str,
+        amount: u128,
+        denom: &str,
+    ) -> PyResult<()> {
+        match self_.inner.assert_transfer(
+            &Addr::unchecked(sender),
+            &Addr::unchecked(recipient),
+            amount,
+            denom,
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PyAssertionError::new_err(e.to_string())),
+        }
+    }
+
+    fn assert_error_contains(self_: PyRefMut<Self>, msg: &str) -> PyResult<()> {
+        self_
+            .inner
+            .assert_error_contains(msg)
+            .map_err(|e| PyAssertionError::new_err(e.to_string()))
+    }
+
+    /// values of key among the attributes the contracts returned, of contract only if given
+    fn get_wasm_attributes(
+        self_: PyRefMut<Self>,
+        key: &str,
+        contract: Option<&str>,
+    ) -> PyResult<Vec<String>> {
+        let values = match contract {
+            Some(contract) => self_
+                .inner
+                .contract_attributes(&Addr::unchecked(contract), key),
+            None => self_.inner.wasm_attributes(key),
+        };
+        Ok(values.into_iter().map(|v| v.to_string()).collect())
+    }
+
+    /// calls of the call trace as dicts with call_id, parent, label, err_msg and gas_used,
+    /// ordered by call_id. gas_used excludes the gas used by the calls made by the call
+    fn get_call_frames(self_: PyRefMut<Self>) -> PyResult<Vec<PyObject>> {
+        let py = self_.py();
+        let mut out = Vec::new();
+        for frame in self_.inner.call_frames() {
+            let dict = PyDict::new(py);
+            dict.set_item("call_id", frame.call_id)?;
+            dict.set_item("parent", frame.parent)?;
+            dict.set_item("label", frame.label)?;
+            dict.set_item("err_msg", frame.err_msg)?;
+            dict.set_item("gas_used", frame.gas_used)?;
+            out.push(dict.to_object(py));
+        }
+        Ok(out)
+    }
+
+    /// CosmWasm gas used by all contract calls
+    fn get_gas_used(self_: PyRefMut<Self>) -> PyResult<u64> {
+        Ok(self_.inner.gas_used)
+    }
+
+    /// (height, time in nanoseconds, chain id) of the block every call and query of the
+    /// transaction saw, fixed when it started
+    fn get_block(self_: PyRefMut<Self>) -> PyResult<Option<(u64, u64, String)>> {
+        Ok(self_
+            .inner
+            .block
+            .as_ref()
+            .map(|b| (b.height, b.time.nanos(), b.chain_id.clone())))
+    }
+
+    /// index of the transaction in its block, as contracts saw it in env.transaction
+    fn get_tx_index(self_: PyRefMut<Self>) -> PyResult<Option<u32>> {
+        Ok(self_.inner.transaction.as_ref().map(|t| t.index))
+    }
+
+    /// synthetic hash of the transaction, hex encoded
+    fn get_tx_hash(self_: PyRefMut<Self>) -> PyResult<Option<String>> {
+        Ok(self_.inner.tx_hash.clone())
+    }
+
     fn get_err_msg(self_: PyRefMut<Self>) -> PyResult<String> {
         let debug_log = &self_.inner;
         if let Some(err_msg) = &debug_log.err_msg {
@@ -39,21 +235,222 @@ impl DebugLog {
         Ok(debug_log.get_stdout())
     }
 
+    fn get_data(self_: PyRefMut<Self>) -> PyResult<Option<Vec<u8>>> {
+        Ok(self_.inner.data.as_ref().map(|d| d.to_vec()))
+    }
+
+    /// address of the contract created by instantiate, None if instantiation failed
+    fn get_contract_address(self_: PyRefMut<Self>) -> PyResult<Option<String>> {
+        Ok(self_.inner.contract_address.as_ref().map(|a| a.to_string()))
+    }
+
+    /// returns (level, msg, call id) of every message sent to the printer with a level
+    fn get_prints(self_: PyRefMut<Self>) -> PyResult<Vec<(String, String, usize)>> {
+        Ok(self_
+            .inner
+            .prints
+            .iter()
+            .map(|p| {
+                let level = format!("{:?}", p.level).to_lowercase();
+                (level, p.msg.clone(), p.call_id)
+            })
+            .collect())
+    }
+
+    fn get_warnings(self_: PyRefMut<Self>) -> PyResult<Vec<String>> {
+        Ok(self_.inner.warnings.clone())
+    }
+
     fn get_call_trace(
         self_: PyRefMut<Self>,
     ) -> PyResult<(HashMap<usize, Vec<usize>>, HashMap<usize, String>)> {
         let debug_log = &self_.inner;
         Ok(debug_log.get_call_trace())
     }
+
+    /// the call trace in the Graphviz dot language
+    fn get_call_trace_dot(self_: PyRefMut<Self>) -> PyResult<String> {
+        Ok(self_.inner.call_trace.to_dot())
+    }
+
+    /// the call trace as nested json objects
+    fn get_call_trace_json(self_: PyRefMut<Self>) -> PyResult<String> {
+        Ok(self_.inner.call_trace.to_json().to_string())
+    }
+
+    /// the call trace as folded stacks weighted by gas, for flamegraph tools
+    fn get_call_trace_folded(self_: PyRefMut<Self>) -> PyResult<String> {
+        Ok(self_.inner.call_trace.to_folded())
+    }
+
+    /// returns (key, old value, new value) of every storage key changed by the call, per contract
+    #[allow(clippy::type_complexity)]
+    fn get_storage_diff(
+        self_: PyRefMut<Self>,
+    ) -> PyResult<HashMap<String, Vec<(Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>)>>> {
+        let debug_log = &self_.inner;
+        let mut out = HashMap::new();
+        for (contract_addr, changes) in debug_log.storage_diff.iter() {
+            let changes = changes
+                .iter()
+                .map(|c| {
+                    (
+                        c.key.to_vec(),
+                        c.old.as_ref().map(|v| v.to_vec()),
+                        c.new.as_ref().map(|v| v.to_vec()),
+                    )
+                })
+                .collect();
+            out.insert(contract_addr.to_string(), changes);
+        }
+        Ok(out)
+    }
+
+    /// returns (contract, key, old value, new value, call id) of every storage write, in order
+    #[allow(clippy::type_complexity)]
+    fn get_storage_trace(
+        self_: PyRefMut<Self>,
+    ) -> PyResult<Vec<(String, Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>, usize)>> {
+        let debug_log = &self_.inner;
+        Ok(debug_log
+            .storage_trace()
+            .iter()
+            .map(|w| {
+                (
+                    w.contract.to_string(),
+                    w.key.to_vec(),
+                    w.old.as_ref().map(|v| v.to_vec()),
+                    w.new.as_ref().map(|v| v.to_vec()),
+                    w.call_id,
+                )
+            })
+            .collect())
+    }
+
+    /// returns (contract, key) pairs read during the call
+    fn get_read_set(self_: PyRefMut<Self>) -> PyResult<Vec<(String, Vec<u8>)>> {
+        let debug_log = &self_.inner;
+        Ok(debug_log
+            .read_set()
+            .into_iter()
+            .map(|(c, k)| (c.to_string(), k.to_vec()))
+            .collect())
+    }
+
+    /// returns (contract, key) pairs written during the call
+    fn get_write_set(self_: PyRefMut<Self>) -> PyResult<Vec<(String, Vec<u8>)>> {
+        let debug_log = &self_.inner;
+        Ok(debug_log
+            .write_set()
+            .into_iter()
+            .map(|(c, k)| (c.to_string(), k.to_vec()))
+            .collect())
+    }
+
+    /// returns the sender of the top-level call and its account label
+    fn get_sender(self_: PyRefMut<Self>) -> PyResult<(String, Option<String>)> {
+        let debug_log = &self_.inner;
+        let sender = match &debug_log.sender {
+            Some(sender) => sender.to_string(),
+            None => "".to_string(),
+        };
+        Ok((sender, debug_log.sender_label.clone()))
+    }
 }
 
 #[pymethods]
 impl Model {
+    /// faucet is a list of (denom, amount) the sender is topped up to, see Model::with_faucet
     #[new]
-    fn new(url: String, block_number: Option<u64>, bech32_prefix: String) -> PyResult<Model> {
-        let model = cosmwasm_simulate::Model::new(&url, block_number, &bech32_prefix)
+    #[args(sender = "None", faucet = "None")]
+    fn new(
+        url: String,
+        block_number: Option<u64>,
+        bech32_prefix: String,
+        sender: Option<String>,
+        faucet: Option<Vec<(String, u128)>>,
+    ) -> PyResult<Model> {
+        let mut model = cosmwasm_simulate::Model::new(&url, block_number, &bech32_prefix)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if let Some(sender) = sender {
+            model = model
+                .with_sender(&Addr::unchecked(sender))
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        }
+        if let Some(faucet) = faucet {
+            let funds: Vec<Coin> = faucet
+                .iter()
+                .map(|(d, a)| Coin {
+                    denom: d.to_string(),
+                    amount: Uint128::new(*a),
+                })
+                .collect();
+            model = model
+                .with_faucet(&funds)
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        }
+        Ok(model.into())
+    }
+
+    /// forks a known chain by its chain id, e.g. "osmosis-1", see cosmwasm_simulate::presets
+    #[staticmethod]
+    #[args(block_number = "None")]
+    fn for_chain(name: String, block_number: Option<u64>) -> PyResult<Model> {
+        let model = cosmwasm_simulate::Model::for_chain(&name, block_number)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(model.into())
+    }
+
+    /// forks like Model(url, block_number, bech32_prefix), detecting the prefix from the chain
+    #[staticmethod]
+    #[args(block_number = "None")]
+    fn new_auto(url: String, block_number: Option<u64>) -> PyResult<Model> {
+        let model = cosmwasm_simulate::Model::new_auto(&url, block_number)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(model.into())
+    }
+
+    #[staticmethod]
+    #[args(block_number = "None")]
+    fn new_lcd(url: String, bech32_prefix: String, block_number: Option<u64>) -> PyResult<Model> {
+        let model = cosmwasm_simulate::Model::new_lcd(&url, block_number, &bech32_prefix)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(model.into())
+    }
+
+    #[staticmethod]
+    fn new_offline(
+        cache_path: String,
+        block_number: u64,
+        bech32_prefix: String,
+    ) -> PyResult<Model> {
+        let model = cosmwasm_simulate::Model::new_offline(
+            Path::new(&cache_path),
+            block_number,
+            &bech32_prefix,
+        )
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(model.into())
+    }
+
+    #[staticmethod]
+    fn new_failover(
+        urls: Vec<String>,
+        block_number: Option<u64>,
+        bech32_prefix: String,
+    ) -> PyResult<Model> {
+        let urls: Vec<&str> = urls.iter().map(|u| u.as_str()).collect();
+        let model = cosmwasm_simulate::Model::new_failover(&urls, block_number, &bech32_prefix)
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-        Ok(Model { inner: model })
+        Ok(model.into())
+    }
+
+    /// prints the simulator's logs at level, e.g. "debug", to stderr, see Model::enable_tracing
+    #[staticmethod]
+    #[args(level = "\"info\"")]
+    fn enable_tracing(level: &str) -> PyResult<()> {
+        cosmwasm_simulate::Model::enable_tracing(level)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 
     pub fn block_number(mut self_: PyRefMut<Self>) -> PyResult<u64> {
@@ -61,20 +458,143 @@ impl Model {
         Ok(model.block_number())
     }
 
-    pub fn add_custom_code(mut self_: PyRefMut<Self>, code_id: u64, code: &[u8]) -> PyResult<()> {
+    /// saves the state of the model, returns the id to revert to it
+    pub fn snapshot(mut self_: PyRefMut<Self>) -> PyResult<usize> {
+        let snapshot = self_.inner.clone();
+        self_.snapshots.push(snapshot);
+        Ok(self_.snapshots.len() - 1)
+    }
+
+    /// restores the state saved by snapshot, dropping the snapshots taken after it.
+    /// The snapshot itself can be reverted to again
+    pub fn revert(mut self_: PyRefMut<Self>, snapshot_id: usize) -> PyResult<()> {
+        let this = &mut *self_;
+        let snapshot = this.snapshots.get(snapshot_id).ok_or_else(|| {
+            PyRuntimeError::new_err(format!("no snapshot with id {}", snapshot_id))
+        })?;
+        this.inner.restore(snapshot);
+        this.snapshots.truncate(snapshot_id + 1);
+        this.entered.retain(|id| *id <= snapshot_id);
+        Ok(())
+    }
+
+    /// `with model:` reverts every change made in the block when leaving it
+    pub fn __enter__(mut self_: PyRefMut<Self>) -> PyResult<PyRefMut<Self>> {
+        let snapshot = self_.inner.clone();
+        self_.snapshots.push(snapshot);
+        let snapshot_id = self_.snapshots.len() - 1;
+        self_.entered.push(snapshot_id);
+        Ok(self_)
+    }
+
+    pub fn __exit__(
+        mut self_: PyRefMut<Self>,
+        _exc_type: &PyAny,
+        _exc_value: &PyAny,
+        _traceback: &PyAny,
+    ) -> PyResult<bool> {
+        let this = &mut *self_;
+        if let Some(snapshot_id) = this.entered.pop() {
+            this.inner.restore(&this.snapshots[snapshot_id]);
+            this.snapshots.truncate(snapshot_id);
+        }
+        Ok(false)
+    }
+
+    /// returns the hex encoded sha256 checksum of code
+    pub fn add_custom_code(
+        mut self_: PyRefMut<Self>,
+        code_id: u64,
+        code: &[u8],
+    ) -> PyResult<String> {
         let model = &mut self_.inner;
         model
             .add_custom_code(code_id, code)
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(hex::encode(code_checksum(code)))
+    }
+
+    /// returns the hex encoded sha256 checksum of the code
+    pub fn add_code_from_path(
+        mut self_: PyRefMut<Self>,
+        code_id: u64,
+        path: &str,
+    ) -> PyResult<String> {
+        let model = &mut self_.inner;
+        model
+            .add_code_from_path(code_id, Path::new(path))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(hex::encode(model.custom_code_checksum(code_id).unwrap()))
+    }
+
+    /// uploads code as the current sender, returns the code id allocated to it
+    pub fn store_code(mut self_: PyRefMut<Self>, code: &[u8]) -> PyResult<u64> {
+        self_
+            .inner
+            .store_code(code)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// (creator, hex encoded sha256 checksum) of code_id
+    pub fn code_info(self_: PyRef<Self>, code_id: u64) -> PyResult<(String, String)> {
+        let info = self_
+            .inner
+            .code_info(code_id)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok((info.creator, hex::encode(info.checksum)))
+    }
+
+    /// hex encoded sha256 checksum of the code contract_addr runs
+    pub fn contract_checksum(self_: PyRef<Self>, contract_addr_: &str) -> PyResult<String> {
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let checksum = self_
+            .inner
+            .contract_checksum(&contract_addr)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(hex::encode(checksum))
+    }
+
+    /// hex encoded sha256 checksum of the code added as code_id, None if there is none
+    pub fn custom_code_checksum(self_: PyRef<Self>, code_id: u64) -> PyResult<Option<String>> {
+        Ok(self_.inner.custom_code_checksum(code_id).map(hex::encode))
+    }
+
+    /// path is the schema written by cosmwasm_schema::write_api
+    pub fn register_schema(mut self_: PyRefMut<Self>, code_id: u64, path: &str) -> PyResult<()> {
+        let schema = ContractSchema::from_path(Path::new(path))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_.inner.register_schema(code_id, schema);
         Ok(())
     }
 
+    #[args(features = "vec![]")]
+    pub fn add_code_from_project(
+        mut self_: PyRefMut<Self>,
+        code_id: u64,
+        project_dir: &str,
+        features: Vec<String>,
+    ) -> PyResult<String> {
+        let config = WasmBuild {
+            features,
+            ..Default::default()
+        };
+        let wasm_path = self_
+            .inner
+            .add_code_from_project(code_id, Path::new(project_dir), &config)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(wasm_path.display().to_string())
+    }
+
+    #[args(admin_ = "None", label = "\"\"")]
     pub fn instantiate(
         mut self_: PyRefMut<Self>,
         code_id: u64,
         msg: &[u8],
         funds_: Vec<(String, u128)>,
+        admin_: Option<&str>,
+        label: &str,
     ) -> PyResult<DebugLog> {
+        let py = self_.py();
         let model = &mut self_.inner;
         let funds: Vec<Coin> = funds_
             .iter()
@@ -83,8 +603,11 @@ impl Model {
                 amount: Uint128::new(*a),
             })
             .collect();
-        let debug_log = model
-            .instantiate(code_id, msg, &funds)
+        let admin = admin_.map(Addr::unchecked);
+        let debug_log = py
+            .allow_threads(|| {
+                model.instantiate_with_admin(code_id, msg, &funds, admin.as_ref(), label)
+            })
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         Ok(DebugLog { inner: debug_log })
     }
@@ -95,6 +618,7 @@ impl Model {
         msg: &[u8],
         funds_: Vec<(String, u128)>,
     ) -> PyResult<DebugLog> {
+        let py = self_.py();
         let model = &mut self_.inner;
         let funds: Vec<Coin> = funds_
             .iter()
@@ -104,8 +628,69 @@ impl Model {
             })
             .collect();
         let contract_addr = Addr::unchecked(contract_addr_);
-        let debug_log = model
-            .execute(&contract_addr, msg, &funds)
+        let debug_log = py
+            .allow_threads(|| model.execute(&contract_addr, msg, &funds))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(DebugLog { inner: debug_log })
+    }
+
+    /// if transfer_funds is false, funds are credited to the contract without debiting the sender
+    #[args(transfer_funds = "true")]
+    pub fn execute_as(
+        mut self_: PyRefMut<Self>,
+        sender_: &str,
+        contract_addr_: &str,
+        msg: &[u8],
+        funds_: Vec<(String, u128)>,
+        transfer_funds: bool,
+    ) -> PyResult<DebugLog> {
+        let py = self_.py();
+        let model = &mut self_.inner;
+        let funds: Vec<Coin> = funds_
+            .iter()
+            .map(|(d, a)| Coin {
+                denom: d.to_string(),
+                amount: Uint128::new(*a),
+            })
+            .collect();
+        let sender = Addr::unchecked(sender_);
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let debug_log = py
+            .allow_threads(|| {
+                if transfer_funds {
+                    model.execute_as(&sender, &contract_addr, msg, &funds)
+                } else {
+                    model.execute_as_without_transfer(&sender, &contract_addr, msg, &funds)
+                }
+            })
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(DebugLog { inner: debug_log })
+    }
+
+    /// reply is the json encoded Reply, e.g. {"id": 1, "result": {"error": "out of gas"}}
+    pub fn call_reply(
+        mut self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        reply: &[u8],
+    ) -> PyResult<DebugLog> {
+        let py = self_.py();
+        let model = &mut self_.inner;
+        let reply: Reply =
+            serde_json::from_slice(reply).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let debug_log = py
+            .allow_threads(|| model.call_reply(&contract_addr, &reply))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(DebugLog { inner: debug_log })
+    }
+
+    /// calls the sudo entry point of the contract, as a chain module would
+    pub fn sudo(mut self_: PyRefMut<Self>, contract_addr_: &str, msg: &[u8]) -> PyResult<DebugLog> {
+        let py = self_.py();
+        let model = &mut self_.inner;
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let debug_log = py
+            .allow_threads(|| model.sudo(&contract_addr, msg))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         Ok(DebugLog { inner: debug_log })
     }
@@ -115,105 +700,1338 @@ impl Model {
         contract_addr_: &str,
         msg: &[u8],
     ) -> PyResult<Vec<u8>> {
+        let py = self_.py();
         let model = &mut self_.inner;
         let contract_addr = Addr::unchecked(contract_addr_);
-        let out = model
-            .wasm_query(&contract_addr, msg)
+        let out = py
+            .allow_threads(|| model.wasm_query(&contract_addr, msg))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         Ok(out.to_vec())
     }
 
-    pub fn bank_query(mut self_: PyRefMut<Self>, msg: &[u8]) -> PyResult<Vec<u8>> {
+    /// any json encoded QueryRequest, e.g. {"staking":{"bonded_denom":{}}}, answered the same way
+    /// as the queries of contracts
+    pub fn query(mut self_: PyRefMut<Self>, request: &[u8]) -> PyResult<Vec<u8>> {
+        let py = self_.py();
         let model = &mut self_.inner;
-        let out = model
-            .bank_query(msg)
+        let out = py
+            .allow_threads(|| model.query_json(request))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
         Ok(out.to_vec())
     }
 
-    pub fn cheat_block_number(mut self_: PyRefMut<Self>, block_number: u64) -> PyResult<()> {
+    /// wasm_query with the block height and timestamp (in nanoseconds) seen by the contract
+    /// overridden, the block of the model is kept
+    pub fn wasm_query_at(
+        mut self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        msg: &[u8],
+        height: u64,
+        timestamp_: u64,
+    ) -> PyResult<Vec<u8>> {
+        let py = self_.py();
         let model = &mut self_.inner;
-        model
-            .cheat_block_number(block_number)
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let timestamp = Timestamp::from_nanos(timestamp_);
+        let out = py
+            .allow_threads(|| model.wasm_query_at(&contract_addr, msg, height, timestamp))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-        Ok(())
+        Ok(out.to_vec())
     }
 
-    /// set latest block timestamp, units in nanoseconds
-    pub fn cheat_block_timestamp(mut self_: PyRefMut<Self>, timestamp_: u64) -> PyResult<()> {
+    /// runs the steps of the yaml or json script at path on this model, the fork section is ignored.
+    /// returns the json encoded report
+    pub fn run_script(mut self_: PyRefMut<Self>, path: &str) -> PyResult<String> {
+        let py = self_.py();
         let model = &mut self_.inner;
-        let timestamp = Timestamp::from_nanos(timestamp_);
-        model
-            .cheat_block_timestamp(timestamp)
+        let script =
+            Script::load(Path::new(path)).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let report = py
+            .allow_threads(|| script.run(model))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-        Ok(())
+        serde_json::to_string(&report).map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 
-    pub fn cheat_bank_balance(
+    pub fn migrate(
         mut self_: PyRefMut<Self>,
-        addr_: &str,
-        amount: (String, u128),
-    ) -> PyResult<()> {
+        contract_addr_: &str,
+        new_code_id: u64,
+        msg: &[u8],
+    ) -> PyResult<DebugLog> {
+        let py = self_.py();
         let model = &mut self_.inner;
-        let addr = Addr::unchecked(addr_);
-        let (denom, new_balance) = amount;
-        model
-            .cheat_bank_balance(&addr, &denom, new_balance)
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let debug_log = py
+            .allow_threads(|| model.migrate(&contract_addr, new_code_id, msg))
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-        Ok(())
+        Ok(DebugLog { inner: debug_log })
     }
 
-    pub fn cheat_code(
+    pub fn update_admin(
         mut self_: PyRefMut<Self>,
         contract_addr_: &str,
-        code: &[u8],
-    ) -> PyResult<()> {
+        admin_: &str,
+    ) -> PyResult<DebugLog> {
         let model = &mut self_.inner;
         let contract_addr = Addr::unchecked(contract_addr_);
-        model
-            .cheat_code(&contract_addr, code)
+        let admin = Addr::unchecked(admin_);
+        let debug_log = model
+            .update_admin(&contract_addr, &admin)
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-        Ok(())
+        Ok(DebugLog { inner: debug_log })
     }
 
-    pub fn cheat_message_sender(mut self_: PyRefMut<Self>, sender: &str) -> PyResult<()> {
+    pub fn clear_admin(mut self_: PyRefMut<Self>, contract_addr_: &str) -> PyResult<DebugLog> {
         let model = &mut self_.inner;
-        let sender_addr = Addr::unchecked(sender);
-        model
-            .cheat_message_sender(&sender_addr)
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let debug_log = model
+            .clear_admin(&contract_addr)
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
-        Ok(())
+        Ok(DebugLog { inner: debug_log })
     }
 
-    pub fn cheat_storage(
-        mut self_: PyRefMut<Self>,
-        contract_addr: &str,
-        key: &[u8],
-        value: &[u8],
-    ) -> PyResult<()> {
-        let model = &mut self_.inner;
-        let contract_addr = Addr::unchecked(contract_addr);
-        model
-            .cheat_storage(&contract_addr, key, value)
+    /// (code_id, creator, admin, label) of the contract
+    pub fn contract_info(
+        self_: PyRefMut<Self>,
+        contract_addr_: &str,
+    ) -> PyResult<(u64, String, Option<String>, String)> {
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let info = self_
+            .inner
+            .contract_info(&contract_addr)
             .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok((info.code_id, info.creator, info.admin, info.label))
+    }
+
+    /// reported as pinned by ContractInfo queries to contracts of code_id
+    pub fn pin_code(mut self_: PyRefMut<Self>, code_id: u64) -> PyResult<()> {
+        self_.inner.pin_code(code_id);
         Ok(())
     }
 
-    pub fn enable_code_coverage(mut self_: PyRefMut<Self>) -> PyResult<()> {
-        let model = &mut self_.inner;
-        model.enable_code_coverage();
+    pub fn unpin_code(mut self_: PyRefMut<Self>, code_id: u64) -> PyResult<()> {
+        self_.inner.unpin_code(code_id);
         Ok(())
     }
 
-    pub fn disable_code_coverage(mut self_: PyRefMut<Self>) -> PyResult<()> {
-        let model = &mut self_.inner;
-        model.disable_code_coverage();
+    pub fn set_admin_checks(mut self_: PyRefMut<Self>, enabled: bool) -> PyResult<()> {
+        self_.inner.set_admin_checks(enabled);
         Ok(())
     }
 
-    pub fn get_code_coverage(mut self_: PyRefMut<Self>) -> PyResult<HashMap<String, Vec<Vec<u8>>>> {
-        let model = &mut self_.inner;
-        Ok(model.get_coverage())
+    pub fn set_cache_dir(mut self_: PyRefMut<Self>, dir: String) -> PyResult<()> {
+        self_
+            .inner
+            .set_cache_dir(PathBuf::from(dir))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn set_cache_read_only(mut self_: PyRefMut<Self>, read_only: bool) -> PyResult<()> {
+        self_
+            .inner
+            .set_cache_read_only(read_only)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn set_cache_share_code(mut self_: PyRefMut<Self>, enabled: bool) -> PyResult<()> {
+        self_
+            .inner
+            .set_cache_share_code(enabled)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn export_cache(self_: PyRef<Self>, path: String) -> PyResult<()> {
+        self_
+            .inner
+            .export_cache(Path::new(&path))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn import_cache(mut self_: PyRefMut<Self>, path: String) -> PyResult<usize> {
+        self_
+            .inner
+            .import_cache(Path::new(&path))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn earliest_available_block(self_: PyRef<Self>) -> PyResult<u64> {
+        self_
+            .inner
+            .earliest_available_block()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn set_lazy_storage(mut self_: PyRefMut<Self>, enabled: bool) -> PyResult<()> {
+        self_.inner.set_lazy_storage(enabled);
+        Ok(())
+    }
+
+    pub fn set_seed(mut self_: PyRefMut<Self>, seed: u64) -> PyResult<()> {
+        self_.inner.set_seed(seed);
+        Ok(())
+    }
+
+    pub fn seed(self_: PyRefMut<Self>) -> PyResult<Option<u64>> {
+        Ok(self_.inner.seed())
+    }
+
+    /// json encoded report of the model setup, to re-run a simulation elsewhere
+    pub fn repro_report(self_: PyRefMut<Self>) -> PyResult<String> {
+        self_
+            .inner
+            .repro_report()
+            .to_json()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// json encoded fixture of the code checksum, info, storage and balances of contract_addr
+    pub fn export_contract(self_: PyRef<Self>, contract_addr: &str) -> PyResult<String> {
+        self_
+            .inner
+            .export_contract(&Addr::unchecked(contract_addr))
+            .and_then(|fixture| fixture.to_json())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// loads contract_addr from a fixture returned by export_contract, without network access
+    pub fn import_contract(
+        mut self_: PyRefMut<Self>,
+        fixture: &str,
+        contract_addr: &str,
+    ) -> PyResult<()> {
+        let fixture = cosmwasm_simulate::ContractFixture::from_json(fixture)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_
+            .inner
+            .import_contract(&fixture, &Addr::unchecked(contract_addr))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// hex encoded canonical hash of the block, the loaded contracts and the loaded balances
+    pub fn state_hash(self_: PyRef<Self>) -> PyResult<String> {
+        let hash = self_
+            .inner
+            .state_hash()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(hex::encode(hash))
+    }
+
+    /// memory held by loaded contracts, compiled modules and idle instances as a dict
+    pub fn memory_stats(self_: PyRef<Self>) -> PyResult<HashMap<&'static str, usize>> {
+        let stats = self_.inner.memory_stats();
+        Ok(HashMap::from([
+            ("contracts", stats.contracts),
+            ("storage_records", stats.storage_records),
+            ("storage_bytes", stats.storage_bytes),
+            ("shared_storages", stats.shared_storages),
+            ("code_bytes", stats.code_bytes),
+            ("compiled_modules", stats.compiled_modules),
+            ("idle_instances", stats.idle_instances),
+        ]))
+    }
+
+    /// code size, compiled module size, memory pages and gas used by the calls of a contract
+    pub fn contract_metrics(
+        self_: PyRef<Self>,
+        contract_addr: &str,
+    ) -> PyResult<HashMap<&'static str, u64>> {
+        let metrics = self_
+            .inner
+            .contract_metrics(&Addr::unchecked(contract_addr))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(HashMap::from([
+            ("code_size", metrics.code_size as u64),
+            ("module_size", metrics.module_size as u64),
+            ("memory_pages", metrics.memory_pages as u64),
+            ("gas_used", metrics.gas_used),
+            ("calls", metrics.calls),
+        ]))
+    }
+
+    pub fn clear_contract_metrics(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.clear_contract_metrics();
+        Ok(())
+    }
+
+    pub fn enable_profiling(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.enable_profiling();
+        Ok(())
+    }
+
+    pub fn disable_profiling(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.disable_profiling();
+        Ok(())
+    }
+
+    /// json of the time and gas of the calls profiled so far, contracts sorted by sort_by:
+    /// "total", "self" or "gas"
+    pub fn profile_report(self_: PyRef<Self>, sort_by: &str) -> PyResult<String> {
+        let sort = match sort_by {
+            "total" => ProfileSort::TotalTime,
+            "self" => ProfileSort::SelfTime,
+            "gas" => ProfileSort::Gas,
+            _ => {
+                return Err(PyRuntimeError::new_err(format!(
+                    "unknown sort key {}, expected total, self or gas",
+                    sort_by
+                )))
+            }
+        };
+        serde_json::to_string(&self_.inner.profile_report(sort))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn clear_profile(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.clear_profile();
+        Ok(())
+    }
+
+    /// json of the exports, embedded schema and candidate message variants of the code of a contract
+    pub fn analyze_contract(self_: PyRef<Self>, contract_addr: &str) -> PyResult<String> {
+        let analysis = self_
+            .inner
+            .analyze_contract(&Addr::unchecked(contract_addr))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        serde_json::to_string(&analysis).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// json messages of kind the contract likely takes, with placeholders for the required fields
+    #[args(kind = "\"execute\"")]
+    pub fn msg_templates(
+        self_: PyRef<Self>,
+        contract_addr: &str,
+        kind: &str,
+    ) -> PyResult<Vec<String>> {
+        let kind: MsgKind = kind
+            .parse()
+            .map_err(|e: Error| PyRuntimeError::new_err(e.to_string()))?;
+        let analysis = self_
+            .inner
+            .analyze_contract(&Addr::unchecked(contract_addr))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(analysis
+            .msg_templates(kind)
+            .iter()
+            .map(|template| template.to_string())
+            .collect())
+    }
+
+    pub fn clear_instance_pool(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.clear_instance_pool();
+        Ok(())
+    }
+
+    /// callback(sender, msg) sees every message before it is dispatched, with msg encoded as json.
+    /// It returns None to pass the message on, a json encoded message to replace it,
+    /// or an empty string to skip it as if it succeeded. Exceptions fail the message
+    pub fn add_msg_middleware(mut self_: PyRefMut<Self>, callback: PyObject) -> PyResult<()> {
+        let middleware = move |sender: &Addr, msg: &CosmosMsg| -> Result<MsgAction, Error> {
+            let msg = serde_json::to_string(msg).map_err(Error::format_error)?;
+            Python::with_gil(|py| {
+                let ret = match callback.call1(py, (sender.to_string(), msg)) {
+                    Ok(ret) => ret,
+                    Err(e) => return Ok(MsgAction::Respond(ContractResult::Err(e.to_string()))),
+                };
+                match ret
+                    .extract::<Option<String>>(py)
+                    .map_err(Error::invalid_argument)?
+                {
+                    None => Ok(MsgAction::Continue),
+                    Some(msg) if msg.is_empty() => {
+                        Ok(MsgAction::Respond(ContractResult::Ok(Response::new())))
+                    }
+                    Some(msg) => Ok(MsgAction::Replace(
+                        serde_json::from_str(&msg).map_err(Error::format_error)?,
+                    )),
+                }
+            })
+        };
+        self_.inner.add_middleware(Arc::new(middleware));
+        Ok(())
+    }
+
+    pub fn clear_msg_middlewares(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.clear_middlewares();
+        Ok(())
+    }
+
+    /// answers the messages and queries sent to address with python instead of wasm.
+    /// execute(sender, msg, funds) returns the json encoded Response, or None for an empty one,
+    /// and query(msg) returns the response as bytes. Exceptions fail the call
+    #[args(execute = "None", query = "None")]
+    pub fn register_native_contract(
+        mut self_: PyRefMut<Self>,
+        address: &str,
+        execute: Option<PyObject>,
+        query: Option<PyObject>,
+    ) -> PyResult<()> {
+        let mut handler = NativeHandler::new();
+        if let Some(execute) = execute {
+            handler = handler.with_execute(move |_, info, msg| {
+                let funds: Vec<(String, u128)> = info
+                    .funds
+                    .iter()
+                    .map(|c| (c.denom.clone(), c.amount.u128()))
+                    .collect();
+                Python::with_gil(|py| {
+                    let ret = execute
+                        .call1(py, (info.sender.to_string(), PyBytes::new(py, msg), funds))
+                        .and_then(|ret| ret.extract::<Option<String>>(py))
+                        .map_err(|e| StdError::generic_err(e.to_string()))?;
+                    match ret {
+                        Some(response) => serde_json::from_str(&response)
+                            .map_err(|e| StdError::parse_err("Response", e)),
+                        None => Ok(Response::new()),
+                    }
+                })
+            });
+        }
+        if let Some(query) = query {
+            handler = handler.with_query(move |_, msg| {
+                Python::with_gil(|py| {
+                    query
+                        .call1(py, (PyBytes::new(py, msg),))
+                        .and_then(|ret| ret.extract::<Vec<u8>>(py))
+                        .map(Binary::from)
+                        .map_err(|e| StdError::generic_err(e.to_string()))
+                })
+            });
+        }
+        self_
+            .inner
+            .register_native_contract(&Addr::unchecked(address), Arc::new(handler));
+        Ok(())
+    }
+
+    pub fn remove_native_contract(mut self_: PyRefMut<Self>, address: &str) -> PyResult<()> {
+        self_
+            .inner
+            .remove_native_contract(&Addr::unchecked(address));
+        Ok(())
+    }
+
+    pub fn bank_query(mut self_: PyRefMut<Self>, msg: &[u8]) -> PyResult<Vec<u8>> {
+        let model = &mut self_.inner;
+        let out = model
+            .bank_query(msg)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(out.to_vec())
+    }
+
+    pub fn cheat_block_number(mut self_: PyRefMut<Self>, block_number: u64) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model
+            .cheat_block_number(block_number)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    /// set blocks_per_execute to 0 to only produce blocks via produce_block
+    pub fn set_block_config(
+        mut self_: PyRefMut<Self>,
+        seconds_per_block: u64,
+        blocks_per_execute: u64,
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.set_block_config(BlockConfig {
+            seconds_per_block,
+            blocks_per_execute,
+        });
+        Ok(())
+    }
+
+    pub fn produce_block(mut self_: PyRefMut<Self>, n: u64) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.produce_block(n);
+        Ok(())
+    }
+
+    /// every transaction until end_block goes into the same block
+    pub fn begin_block(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_
+            .inner
+            .begin_block()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// msgs is the json encoded list of CosmosMsgs sender sends in a single transaction,
+    /// e.g. [{"wasm": {"execute": {"contract_addr": ..., "msg": ..., "funds": []}}}]
+    pub fn deliver(mut self_: PyRefMut<Self>, sender: &str, msgs: &[u8]) -> PyResult<DebugLog> {
+        let py = self_.py();
+        let model = &mut self_.inner;
+        let msgs: Vec<CosmosMsg> =
+            serde_json::from_slice(msgs).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let sender = Addr::unchecked(sender);
+        let debug_log = py
+            .allow_threads(|| model.deliver(&sender, &msgs))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(DebugLog { inner: debug_log })
+    }
+
+    pub fn end_block(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_
+            .inner
+            .end_block()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// moves the block time forward by seconds, leaving the height as is
+    pub fn cheat_advance_time(mut self_: PyRefMut<Self>, seconds: u64) -> PyResult<()> {
+        self_
+            .inner
+            .cheat_advance_time(Duration::from_secs(seconds))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// moves the chain forward by n blocks, like produce_block
+    pub fn cheat_advance_blocks(mut self_: PyRefMut<Self>, n: u64) -> PyResult<()> {
+        self_
+            .inner
+            .cheat_advance_blocks(n)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// sets the index of every transaction from now on in its block, see env.transaction
+    pub fn cheat_transaction_info(mut self_: PyRefMut<Self>, index: u32) -> PyResult<()> {
+        self_
+            .inner
+            .cheat_transaction_info(index)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// set latest block timestamp, units in nanoseconds
+    pub fn cheat_block_timestamp(mut self_: PyRefMut<Self>, timestamp_: u64) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let timestamp = Timestamp::from_nanos(timestamp_);
+        model
+            .cheat_block_timestamp(timestamp)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn cheat_bank_balance(
+        mut self_: PyRefMut<Self>,
+        addr_: &str,
+        amount: (String, u128),
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let addr = Addr::unchecked(addr_);
+        let (denom, new_balance) = amount;
+        model
+            .cheat_bank_balance(&addr, &denom, new_balance)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn cheat_mint(
+        mut self_: PyRefMut<Self>,
+        addr_: &str,
+        amount_: Vec<(String, u128)>,
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let addr = Addr::unchecked(addr_);
+        let amount: Vec<Coin> = amount_
+            .iter()
+            .map(|(d, a)| Coin {
+                denom: d.to_string(),
+                amount: Uint128::new(*a),
+            })
+            .collect();
+        model
+            .cheat_mint(&addr, &amount)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn supply(self_: PyRefMut<Self>, denom: &str) -> PyResult<u128> {
+        let supply = self_
+            .inner
+            .supply(denom)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(supply.u128())
+    }
+
+    pub fn delegation_rewards(
+        self_: PyRefMut<Self>,
+        delegator_: &str,
+        validator: &str,
+    ) -> PyResult<Vec<(String, u128)>> {
+        let rewards = self_
+            .inner
+            .delegation_rewards(&Addr::unchecked(delegator_), validator)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(rewards
+            .into_iter()
+            .map(|c| (c.denom, c.amount.u128()))
+            .collect())
+    }
+
+    pub fn withdraw_address(self_: PyRefMut<Self>, delegator_: &str) -> PyResult<String> {
+        let address = self_
+            .inner
+            .withdraw_address(&Addr::unchecked(delegator_))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(address.to_string())
+    }
+
+    pub fn cheat_delegation_rewards(
+        mut self_: PyRefMut<Self>,
+        delegator_: &str,
+        validator: &str,
+        rewards_: Vec<(String, u128)>,
+    ) -> PyResult<()> {
+        let rewards: Vec<Coin> = rewards_
+            .iter()
+            .map(|(d, a)| Coin {
+                denom: d.to_string(),
+                amount: Uint128::new(*a),
+            })
+            .collect();
+        self_
+            .inner
+            .cheat_delegation_rewards(&Addr::unchecked(delegator_), validator, &rewards);
+        Ok(())
+    }
+
+    /// status of a proposal submitted during the simulation, e.g. "VotingPeriod" or "Passed"
+    pub fn proposal_status(self_: PyRefMut<Self>, proposal_id: u64) -> PyResult<Option<String>> {
+        Ok(self_
+            .inner
+            .proposal(proposal_id)
+            .map(|p| format!("{:?}", p.status)))
+    }
+
+    pub fn cheat_pass_proposal(mut self_: PyRefMut<Self>, proposal_id: u64) -> PyResult<DebugLog> {
+        let debug_log = self_
+            .inner
+            .cheat_pass_proposal(proposal_id)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(DebugLog { inner: debug_log })
+    }
+
+    pub fn cheat_reject_proposal(mut self_: PyRefMut<Self>, proposal_id: u64) -> PyResult<()> {
+        self_
+            .inner
+            .cheat_reject_proposal(proposal_id)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn denom_admin(self_: PyRefMut<Self>, denom: &str) -> PyResult<Option<String>> {
+        Ok(self_.inner.denom_admin(denom).map(|a| a.to_string()))
+    }
+
+    pub fn tokenfactory_denoms(self_: PyRefMut<Self>, creator_: &str) -> PyResult<Vec<String>> {
+        Ok(self_.inner.tokenfactory_denoms(&Addr::unchecked(creator_)))
+    }
+
+    pub fn cheat_denom_admin(
+        mut self_: PyRefMut<Self>,
+        denom: &str,
+        admin_: Option<&str>,
+    ) -> PyResult<()> {
+        let admin = admin_.map(Addr::unchecked);
+        self_.inner.cheat_denom_admin(denom, admin.as_ref());
+        Ok(())
+    }
+
+    /// json encoded metadata of denom, in the format of cosmwasm_std::DenomMetadata
+    pub fn denom_metadata(self_: PyRefMut<Self>, denom: &str) -> PyResult<String> {
+        let metadata = self_
+            .inner
+            .denom_metadata(denom)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        serde_json::to_string(&metadata).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn all_denom_metadata(self_: PyRefMut<Self>) -> PyResult<String> {
+        let metadata = self_
+            .inner
+            .all_denom_metadata()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        serde_json::to_string(&metadata).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn cheat_denom_metadata(mut self_: PyRefMut<Self>, metadata_: &str) -> PyResult<()> {
+        let metadata =
+            serde_json::from_str(metadata_).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_.inner.cheat_denom_metadata(metadata);
+        Ok(())
+    }
+
+    pub fn cheat_code(
+        mut self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        code: &[u8],
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let contract_addr = Addr::unchecked(contract_addr_);
+        model
+            .cheat_code(&contract_addr, code)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    /// cheat_code with the wasm file at path, again whenever the file changes
+    pub fn watch_and_reload(
+        mut self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        path: &str,
+    ) -> PyResult<()> {
+        self_
+            .inner
+            .watch_and_reload(&Addr::unchecked(contract_addr_), Path::new(path))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn unwatch(mut self_: PyRefMut<Self>, contract_addr_: &str) -> PyResult<bool> {
+        Ok(self_.inner.unwatch(&Addr::unchecked(contract_addr_)))
+    }
+
+    /// reloads the watched files which changed, returns the contracts reloaded
+    pub fn reload_watched(mut self_: PyRefMut<Self>) -> PyResult<Vec<String>> {
+        let reloaded = self_
+            .inner
+            .reload_watched()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(reloaded.into_iter().map(|a| a.to_string()).collect())
+    }
+
+    pub fn cheat_admin(
+        mut self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        admin_: Option<&str>,
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let admin = admin_.map(Addr::unchecked);
+        model
+            .cheat_admin(&contract_addr, admin.as_ref())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn add_bech32_prefix(
+        mut self_: PyRefMut<Self>,
+        prefix: &str,
+        canonical_length: usize,
+    ) -> PyResult<()> {
+        self_
+            .inner
+            .add_bech32_prefix(prefix, canonical_length)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn set_canonical_address_length(
+        mut self_: PyRefMut<Self>,
+        canonical_length: usize,
+    ) -> PyResult<()> {
+        self_
+            .inner
+            .set_canonical_address_length(canonical_length)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn human_to_canonical(self_: PyRef<Self>, human: &str) -> PyResult<Vec<u8>> {
+        self_
+            .inner
+            .human_to_canonical(human)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn canonical_to_human(self_: PyRef<Self>, canonical: &[u8]) -> PyResult<String> {
+        let human = self_
+            .inner
+            .canonical_to_human(canonical)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(human.to_string())
+    }
+
+    pub fn cheat_message_sender(mut self_: PyRefMut<Self>, sender: &str) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let sender_addr = Addr::unchecked(sender);
+        model
+            .cheat_message_sender(&sender_addr)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn register_account(
+        mut self_: PyRefMut<Self>,
+        label: &str,
+        addr_: &str,
+        funds_: Vec<(String, u128)>,
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let funds: Vec<Coin> = funds_
+            .iter()
+            .map(|(d, a)| Coin {
+                denom: d.to_string(),
+                amount: Uint128::new(*a),
+            })
+            .collect();
+        let addr = Addr::unchecked(addr_);
+        model
+            .register_account(label, &addr, &funds)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn create_account(
+        mut self_: PyRefMut<Self>,
+        label: &str,
+        funds_: Vec<(String, u128)>,
+    ) -> PyResult<String> {
+        let model = &mut self_.inner;
+        let funds: Vec<Coin> = funds_
+            .iter()
+            .map(|(d, a)| Coin {
+                denom: d.to_string(),
+                amount: Uint128::new(*a),
+            })
+            .collect();
+        let addr = model
+            .create_account(label, &funds)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(addr.to_string())
+    }
+
+    /// registers an account holding the key of algo derived from label, returns its address and public key
+    #[args(algo = "\"secp256k1\"")]
+    pub fn create_key_account(
+        mut self_: PyRefMut<Self>,
+        label: &str,
+        funds_: Vec<(String, u128)>,
+        algo: &str,
+    ) -> PyResult<(String, Vec<u8>)> {
+        let algo: KeyAlgo = algo
+            .parse()
+            .map_err(|e: Error| PyRuntimeError::new_err(e.to_string()))?;
+        let funds: Vec<Coin> = funds_
+            .iter()
+            .map(|(d, a)| Coin {
+                denom: d.to_string(),
+                amount: Uint128::new(*a),
+            })
+            .collect();
+        let key = self_
+            .inner
+            .create_key_account(label, algo, &funds)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let addr = self_.inner.account(label).unwrap();
+        Ok((addr.to_string(), key.public_key()))
+    }
+
+    /// signs msg with the key of algo derived from label, see create_key_account
+    #[staticmethod]
+    #[args(algo = "\"secp256k1\"")]
+    fn cheat_sign(label: &str, msg: Vec<u8>, algo: &str) -> PyResult<Vec<u8>> {
+        let algo: KeyAlgo = algo
+            .parse()
+            .map_err(|e: Error| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(CheatKey::from_label(algo, label).sign(&msg))
+    }
+
+    pub fn switch_account(mut self_: PyRefMut<Self>, label: &str) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model
+            .switch_account(label)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    /// loads root_ and the contracts found in storage up to depth levels away, returns the depth of every
+    /// loaded contract and the code ids referenced by their storage.
+    /// progress(address, depth) is called as contracts are loaded
+    pub fn fork_closure(
+        mut self_: PyRefMut<Self>,
+        root_: &str,
+        depth: usize,
+        progress: Option<PyObject>,
+    ) -> PyResult<(HashMap<String, usize>, Vec<u64>)> {
+        let py = self_.py();
+        let model = &mut self_.inner;
+        let root = Addr::unchecked(root_);
+        let closure = model
+            .fork_closure_with_progress(&root, depth, |p| {
+                if let (Some(progress), ClosureProgress::Contract { address, depth }) =
+                    (&progress, p)
+                {
+                    // progress is informational, exceptions don't abort loading
+                    let _ = progress.call1(py, (address.to_string(), *depth));
+                }
+            })
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let contracts = closure
+            .contracts
+            .into_iter()
+            .map(|(address, depth)| (address.to_string(), depth))
+            .collect();
+        Ok((contracts, closure.code_ids.into_iter().collect()))
+    }
+
+    pub fn set_alias(mut self_: PyRefMut<Self>, name: &str, addr_: &str) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.set_alias(name, &Addr::unchecked(addr_));
+        Ok(())
+    }
+
+    pub fn remove_alias(mut self_: PyRefMut<Self>, name: &str) -> PyResult<Option<String>> {
+        let model = &mut self_.inner;
+        Ok(model.remove_alias(name).map(|a| a.to_string()))
+    }
+
+    /// the address of an alias, account label or contract label
+    pub fn lookup(self_: PyRef<Self>, label: &str) -> PyResult<Option<String>> {
+        Ok(self_.inner.lookup(label).map(|a| a.to_string()))
+    }
+
+    /// the name of addr_ in call traces
+    pub fn name_of(self_: PyRef<Self>, addr_: &str) -> PyResult<Option<String>> {
+        Ok(self_.inner.name_of(&Addr::unchecked(addr_)))
+    }
+
+    pub fn cheat_storage(
+        mut self_: PyRefMut<Self>,
+        contract_addr: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let contract_addr = Addr::unchecked(contract_addr);
+        model
+            .cheat_storage(&contract_addr, key, value)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(())
+    }
+
+    /// records with keys in [start, end), in descending order if descending is set
+    pub fn scan_storage(
+        self_: PyRefMut<Self>,
+        contract_addr: &str,
+        start: Option<Vec<u8>>,
+        end: Option<Vec<u8>>,
+        descending: bool,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let order = if descending {
+            Order::Descending
+        } else {
+            Order::Ascending
+        };
+        self_
+            .inner
+            .scan_storage(
+                &Addr::unchecked(contract_addr),
+                start.as_deref(),
+                end.as_deref(),
+                order,
+                limit,
+            )
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// entries of a cw-storage-plus Map, keys without the namespace
+    pub fn scan_map(
+        self_: PyRefMut<Self>,
+        contract_addr: &str,
+        namespace: &str,
+        descending: bool,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let order = if descending {
+            Order::Descending
+        } else {
+            Order::Ascending
+        };
+        self_
+            .inner
+            .scan_map(&Addr::unchecked(contract_addr), namespace, order, limit)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// json encoded value of a cw-storage-plus Item
+    pub fn read_item(
+        self_: PyRefMut<Self>,
+        contract_addr: &str,
+        namespace: &str,
+    ) -> PyResult<Option<String>> {
+        let value: Option<serde_json::Value> = self_
+            .inner
+            .read_item(&Addr::unchecked(contract_addr), namespace)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(value.map(|v| v.to_string()))
+    }
+
+    /// json encoded value of a cw-storage-plus Map entry, key_parts are encoded as by cw-storage-plus
+    pub fn read_map(
+        self_: PyRefMut<Self>,
+        contract_addr: &str,
+        namespace: &str,
+        key_parts: Vec<Vec<u8>>,
+    ) -> PyResult<Option<String>> {
+        let value: Option<serde_json::Value> = self_
+            .inner
+            .read_map(
+                &Addr::unchecked(contract_addr),
+                namespace,
+                RawKey(key_parts),
+            )
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(value.map(|v| v.to_string()))
+    }
+
+    pub fn write_item(
+        mut self_: PyRefMut<Self>,
+        contract_addr: &str,
+        namespace: &str,
+        value: &str,
+    ) -> PyResult<()> {
+        let value: serde_json::Value =
+            serde_json::from_str(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_
+            .inner
+            .write_item(&Addr::unchecked(contract_addr), namespace, &value)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn write_map(
+        mut self_: PyRefMut<Self>,
+        contract_addr: &str,
+        namespace: &str,
+        key_parts: Vec<Vec<u8>>,
+        value: &str,
+    ) -> PyResult<()> {
+        let value: serde_json::Value =
+            serde_json::from_str(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_
+            .inner
+            .write_map(
+                &Addr::unchecked(contract_addr),
+                namespace,
+                RawKey(key_parts),
+                &value,
+            )
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn enable_code_coverage(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.enable_code_coverage();
+        Ok(())
+    }
+
+    pub fn disable_code_coverage(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.disable_code_coverage();
+        Ok(())
+    }
+
+    pub fn enable_coverage_instrumentation(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.enable_coverage_instrumentation();
+        Ok(())
+    }
+
+    pub fn disable_coverage_instrumentation(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.disable_coverage_instrumentation();
+        Ok(())
+    }
+
+    pub fn instrument_contract(mut self_: PyRefMut<Self>, contract_addr_: &str) -> PyResult<()> {
+        let model = &mut self_.inner;
+        let contract_addr = Addr::unchecked(contract_addr_);
+        model
+            .instrument_contract(&contract_addr)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// abort fails reentrant calls and calls exceeding the query depth instead of only warning
+    pub fn set_recursion_policy(
+        mut self_: PyRefMut<Self>,
+        abort: bool,
+        max_query_depth: usize,
+    ) -> PyResult<()> {
+        let policy = if abort {
+            RecursionPolicy::Abort
+        } else {
+            RecursionPolicy::Warn
+        };
+        self_.inner.set_recursion_config(RecursionConfig {
+            policy,
+            max_query_depth,
+        });
+        Ok(())
+    }
+
+    pub fn get_capabilities(self_: PyRefMut<Self>) -> PyResult<Vec<String>> {
+        Ok(self_.inner.capabilities().into_iter().collect())
+    }
+
+    /// capabilities advertised by the chain, e.g. ["iterator", "staking", "cosmwasm_1_2"]
+    pub fn set_capabilities(mut self_: PyRefMut<Self>, capabilities: Vec<&str>) -> PyResult<()> {
+        self_.inner.set_capabilities(&capabilities);
+        Ok(())
+    }
+
+    /// memory limit of contract instances in bytes and maximum depth of nested contract calls,
+    /// None removes the limit. Exceeding them raises an error
+    pub fn set_limits(
+        mut self_: PyRefMut<Self>,
+        memory_limit: Option<usize>,
+        max_call_depth: Option<usize>,
+    ) -> PyResult<()> {
+        self_.inner.set_limits(SandboxLimits {
+            memory_limit,
+            max_call_depth,
+        });
+        Ok(())
+    }
+
+    pub fn enable_backtraces(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.enable_backtraces();
+        Ok(())
+    }
+
+    pub fn disable_backtraces(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.disable_backtraces();
+        Ok(())
+    }
+
+    pub fn enable_funds_merging(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.enable_funds_merging();
+        Ok(())
+    }
+
+    pub fn disable_funds_merging(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.disable_funds_merging();
+        Ok(())
+    }
+
+    pub fn enable_reply_error_redaction(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.enable_reply_error_redaction();
+        Ok(())
+    }
+
+    pub fn disable_reply_error_redaction(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.disable_reply_error_redaction();
+        Ok(())
+    }
+
+    pub fn enable_storage_trace(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.enable_storage_trace();
+        Ok(())
+    }
+
+    pub fn disable_storage_trace(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        let model = &mut self_.inner;
+        model.disable_storage_trace();
+        Ok(())
+    }
+
+    /// charge the gas of queried contracts to the contracts querying them
+    pub fn enable_query_metering(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.enable_query_metering();
+        Ok(())
+    }
+
+    pub fn disable_query_metering(mut self_: PyRefMut<Self>) -> PyResult<()> {
+        self_.inner.disable_query_metering();
+        Ok(())
+    }
+
+    pub fn get_code_coverage(mut self_: PyRefMut<Self>) -> PyResult<HashMap<String, Vec<Vec<u8>>>> {
+        let model = &mut self_.inner;
+        Ok(model.get_coverage())
+    }
+
+    /// hash of the coverage map of the last instantiate or execute
+    pub fn get_coverage_hash(self_: PyRefMut<Self>) -> PyResult<u64> {
+        self_
+            .inner
+            .coverage_hash()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// AFL style coverage map of the last instantiate or execute
+    pub fn get_last_coverage_map(self_: PyRefMut<Self>) -> PyResult<Vec<u8>> {
+        let map = self_
+            .inner
+            .last_coverage_map()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(map.as_bytes().to_vec())
+    }
+
+    pub fn has_new_coverage(mut self_: PyRefMut<Self>) -> PyResult<bool> {
+        let model = &mut self_.inner;
+        model
+            .has_new_coverage()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn get_coverage_lcov(self_: PyRefMut<Self>) -> PyResult<String> {
+        self_
+            .inner
+            .coverage_lcov()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    pub fn get_coverage_html(self_: PyRefMut<Self>) -> PyResult<String> {
+        self_
+            .inner
+            .coverage_html()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// merged coverage of every contract in a compact binary format, see merge_coverage
+    pub fn get_coverage_snapshot(self_: PyRefMut<Self>) -> PyResult<Vec<u8>> {
+        self_
+            .inner
+            .coverage_snapshot()
+            .and_then(|snapshot| snapshot.to_bytes())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// add a snapshot of get_coverage_snapshot, e.g. of another fuzzing shard, to the coverage
+    pub fn merge_coverage(mut self_: PyRefMut<Self>, snapshot: &[u8]) -> PyResult<()> {
+        let snapshot = CoverageSnapshot::from_bytes(snapshot)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_.inner.merge_coverage(&snapshot);
+        Ok(())
+    }
+
+    /// functions and number of blocks covered by this model which the baseline snapshot does not
+    /// cover, by contract address
+    pub fn get_new_coverage(
+        self_: PyRefMut<Self>,
+        baseline: &[u8],
+    ) -> PyResult<HashMap<String, (Vec<String>, usize)>> {
+        let baseline = CoverageSnapshot::from_bytes(baseline)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let snapshot = self_
+            .inner
+            .coverage_snapshot()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(snapshot
+            .diff(&baseline)
+            .contracts
+            .into_iter()
+            .map(|(address, diff)| (address, (diff.new_functions, diff.new_blocks)))
+            .collect())
+    }
+
+    pub fn fuzz(
+        self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        schema: &[u8],
+        iterations: usize,
+        seed: u64,
+    ) -> PyResult<String> {
+        let contract_addr = Addr::unchecked(contract_addr_);
+        let generator =
+            MsgGenerator::from_slice(schema).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let config = FuzzConfig {
+            seed,
+            ..FuzzConfig::default()
+        };
+        let mut fuzzer = Fuzzer::new(self_.inner.clone(), &contract_addr, generator, config)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let report = self_
+            .py()
+            .allow_threads(|| fuzzer.run(iterations).cloned())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        serde_json::to_string(&report).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+}
+
+/// scenario without properties, contract errors of calls with expect_success and crashes fail it
+#[pyclass]
+#[derive(Default)]
+struct Scenario {
+    vars: Vec<(String, Sym)>,
+    calls: Vec<Call>,
+}
+
+#[pymethods]
+impl Scenario {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_range_var(
+        mut self_: PyRefMut<Self>,
+        name: &str,
+        lo: u128,
+        hi: u128,
+    ) -> PyResult<()> {
+        self_.vars.push((name.to_string(), Sym::Range(lo, hi)));
+        Ok(())
+    }
+
+    /// values are json encoded
+    pub fn add_choice_var(
+        mut self_: PyRefMut<Self>,
+        name: &str,
+        values: Vec<String>,
+    ) -> PyResult<()> {
+        let values = values
+            .iter()
+            .map(|v| serde_json::from_str(v))
+            .collect::<Result<_, _>>()
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        self_.vars.push((name.to_string(), Sym::OneOf(values)));
+        Ok(())
+    }
+
+    /// senders are picked at random, the model's sender is used if empty
+    /// funds are (denom, min, max) tuples
+    pub fn add_call(
+        mut self_: PyRefMut<Self>,
+        contract_addr_: &str,
+        msg: &[u8],
+        senders: Vec<String>,
+        funds: Vec<(String, u128, u128)>,
+        expect_success: bool,
+    ) -> PyResult<()> {
+        let msg =
+            serde_json::from_slice(msg).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let mut call = Call::execute(&Addr::unchecked(contract_addr_), msg);
+        if !senders.is_empty() {
+            let senders: Vec<Addr> = senders.iter().map(Addr::unchecked).collect();
+            call = call.sender(Sym::one_of_addrs(&senders));
+        }
+        for (denom, lo, hi) in funds.iter() {
+            call = call.funds(denom, Sym::Range(*lo, *hi));
+        }
+        if expect_success {
+            call = call.expect_success();
+        }
+        self_.calls.push(call);
+        Ok(())
+    }
+
+    /// returns the json encoded report
+    pub fn run(
+        self_: PyRefMut<Self>,
+        model: PyRef<Model>,
+        iterations: usize,
+        seed: u64,
+    ) -> PyResult<String> {
+        let mut scenario = cosmwasm_simulate::scenario::Scenario::new();
+        for (name, sym) in self_.vars.iter() {
+            scenario = scenario.var(name, sym.clone());
+        }
+        for call in self_.calls.iter() {
+            scenario = scenario.call(call.clone());
+        }
+        let model = model.inner.clone();
+        let report = self_
+            .py()
+            .allow_threads(move || scenario.run(&model, iterations, seed))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        serde_json::to_string(&report).map_err(|e| PyRuntimeError::new_err(e.to_string()))
     }
 }
 
@@ -222,5 +2040,6 @@ impl Model {
 fn cwsimpy(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Model>()?;
     m.add_class::<DebugLog>()?;
+    m.add_class::<Scenario>()?;
     Ok(())
 }
@@ -1,12 +1,13 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, Binary, Deps, DepsMut, Env, Event, MessageInfo, Response, StdResult,
+    to_binary, Binary, Deps, DepsMut, Env, Event, MessageInfo, Reply, Response, StdResult, SubMsg,
+    WasmMsg,
 };
 // use cw2::set_contract_version;
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ReadNumberResponse};
+use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ReadNumberResponse, SudoMsg};
 use crate::state::NUMBER;
 
 /*
@@ -36,6 +37,11 @@ pub fn execute(
     match msg {
         ExecuteMsg::TestQuerySelf {} => execute_write_and_query_self(deps, env),
         ExecuteMsg::TestAtomic {} => execute_write_and_panic(deps),
+        ExecuteMsg::TestReenter {} => execute_reenter(env),
+        ExecuteMsg::SetData { data } => Ok(Response::new().set_data(data)),
+        ExecuteMsg::TestReplyData { data } => execute_reply_data(env, data),
+        ExecuteMsg::TestGasLimit { gas_limit } => execute_gas_limit(env, gas_limit),
+        ExecuteMsg::TestReplyOnError {} => execute_reply_on_error(env),
     }
 }
 
@@ -56,13 +62,94 @@ fn execute_write_and_panic(deps: DepsMut) -> Result<Response, ContractError> {
     Err(ContractError::Unauthorized {})
 }
 
+fn execute_reenter(env: Env) -> Result<Response, ContractError> {
+    let msg = WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&ExecuteMsg::TestQuerySelf {})?,
+        funds: vec![],
+    };
+    Ok(Response::new().add_message(msg))
+}
+
+const REPLY_DATA_ID: u64 = 1;
+const REPLY_GAS_LIMIT_ID: u64 = 2;
+const REPLY_ON_ERROR_ID: u64 = 3;
+
+fn execute_reply_data(env: Env, data: Binary) -> Result<Response, ContractError> {
+    let msg = WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&ExecuteMsg::SetData { data })?,
+        funds: vec![],
+    };
+    Ok(Response::new().add_submessage(SubMsg::reply_on_success(msg, REPLY_DATA_ID)))
+}
+
+fn execute_gas_limit(env: Env, gas_limit: u64) -> Result<Response, ContractError> {
+    let msg = WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&ExecuteMsg::TestQuerySelf {})?,
+        funds: vec![],
+    };
+    let msg = SubMsg::reply_always(msg, REPLY_GAS_LIMIT_ID).with_gas_limit(gas_limit);
+    Ok(Response::new().add_submessage(msg))
+}
+
+fn execute_reply_on_error(env: Env) -> Result<Response, ContractError> {
+    let msg = WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        msg: to_binary(&ExecuteMsg::TestAtomic {})?,
+        funds: vec![],
+    };
+    Ok(Response::new().add_submessage(SubMsg::reply_on_error(msg, REPLY_ON_ERROR_ID)))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        // the write of the failed submessage must have been rolled back
+        REPLY_ON_ERROR_ID => {
+            let number = NUMBER.load(deps.storage)?;
+            Ok(Response::new().set_data(to_binary(&number)?))
+        }
+        REPLY_GAS_LIMIT_ID => {
+            let err = msg.result.into_result().err().unwrap_or_default();
+            Ok(Response::new().set_data(err.into_bytes()))
+        }
+        _ => {
+            let data = msg.result.unwrap().data.unwrap_or_default();
+            Ok(Response::new().set_data(data))
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, _env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::SetNumber { value } => {
+            NUMBER.save(deps.storage, &value)?;
+            Ok(Response::new().add_attribute("number", value.to_string()))
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::ReadNumber {} => {
             let number = NUMBER.load(deps.storage).unwrap();
             Ok(to_binary(&ReadNumberResponse { value: number }).unwrap())
         }
+        QueryMsg::Recurse { depth } => {
+            if depth == 0 {
+                return to_binary(&ReadNumberResponse { value: 0 });
+            }
+            let msg = QueryMsg::Recurse { depth: depth - 1 };
+            let res: ReadNumberResponse =
+                deps.querier.query_wasm_smart(env.contract.address, &msg)?;
+            to_binary(&ReadNumberResponse {
+                value: res.value + 1,
+            })
+        }
     }
 }
 
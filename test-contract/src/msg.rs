@@ -1,4 +1,5 @@
 use cosmwasm_schema::cw_serde;
+use cosmwasm_std::Binary;
 
 #[cw_serde]
 pub struct InstantiateMsg {}
@@ -7,11 +8,29 @@ pub struct InstantiateMsg {}
 pub enum ExecuteMsg {
     TestQuerySelf {},
     TestAtomic {},
+    // calls itself with TestQuerySelf, a reentrant submessage
+    TestReenter {},
+    // returns data as the data of the response
+    SetData { data: Binary },
+    // calls itself with SetData, and returns the data of the reply as is
+    TestReplyData { data: Binary },
+    // calls itself with TestQuerySelf within gas_limit, and returns the error of the reply as data
+    TestGasLimit { gas_limit: u64 },
+    // calls itself with TestAtomic, and returns the number seen by the reply on error as data
+    TestReplyOnError {},
+}
+
+#[cw_serde]
+pub enum SudoMsg {
+    // saves value as the number, as a chain module would
+    SetNumber { value: u32 },
 }
 
 #[cw_serde]
 pub enum QueryMsg {
     ReadNumber {},
+    // queries itself depth times
+    Recurse { depth: u32 },
 }
 
 #[cw_serde]